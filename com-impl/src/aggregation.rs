@@ -0,0 +1,150 @@
+//! COM aggregation, for objects that can be the inner object of an outer `IUnknown`.
+//!
+//! An [`Aggregation`] member makes `#[derive(ComImpl)]` give the object two `IUnknown`s. The
+//! one on its interfaces delegates to the controlling unknown: the outer object when
+//! aggregated, otherwise the object itself. The other, non-delegating one is handed to the
+//! outer object, which uses it to query the inner object's interfaces and to release it.
+//!
+//! The member is not a parameter of `create_raw`, which creates a standalone object. An
+//! additional `create_raw_aggregated` takes the outer `IUnknown` first and returns the
+//! non-delegating `IUnknown`.
+//!
+//! For the outer side, a member marked `#[aggregate]` holds an inner object's non-delegating
+//! `IUnknown`, usually in an [`Inner`]. `QueryInterface` forwards every IID the outer object
+//! doesn't answer itself to it, so the outer object exposes all of the inner object's
+//! interfaces without listing them.
+//!
+//! ```no_run
+//! use com_impl::aggregation::Aggregation;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::shared::guiddef::{IsEqualIID, IID};
+//! use winapi::shared::winerror::{CLASS_E_NOAGGREGATION, HRESULT};
+//! use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+//! use winapi::Interface;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Inner {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     aggregation: Aggregation,
+//!     value: u32,
+//! }
+//!
+//! /// What a class factory's `CreateInstance` does.
+//! unsafe fn create(outer: *mut IUnknown, riid: &IID) -> Result<*mut IUnknown, HRESULT> {
+//!     if outer.is_null() {
+//!         return Ok(Inner::create_raw(42) as *mut IUnknown);
+//!     }
+//!     // An aggregating outer object must ask for the non-delegating IUnknown
+//!     if !IsEqualIID(riid, &IUnknown::uuidof()) {
+//!         return Err(CLASS_E_NOAGGREGATION);
+//!     }
+//!     Ok(Inner::create_raw_aggregated(outer, 42))
+//! }
+//! ```
+
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+use crate::VTable;
+
+#[repr(C)]
+/// The non-delegating `IUnknown` of an aggregable object, and its outer object if it has one.
+pub struct Aggregation {
+    vtbl: VTable<IUnknownVtbl>,
+    outer: *mut IUnknown,
+}
+
+impl Aggregation {
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]`, which provides the non-delegating vtable.
+    pub fn new(vtbl: VTable<IUnknownVtbl>, outer: *mut IUnknown) -> Aggregation {
+        Aggregation { vtbl, outer }
+    }
+
+    /// The controlling outer object, if aggregated.
+    ///
+    /// The inner object holds no reference to it; it lives at least as long as the inner
+    /// object.
+    pub fn outer(&self) -> Option<NonNull<IUnknown>> {
+        NonNull::new(self.outer)
+    }
+
+    pub fn is_aggregated(&self) -> bool {
+        !self.outer.is_null()
+    }
+
+    /// The non-delegating `IUnknown`.
+    pub fn as_ptr(&self) -> *mut IUnknown {
+        &self.vtbl as *const _ as *mut IUnknown
+    }
+}
+
+/// What an `#[aggregate]` member holds: the non-delegating `IUnknown` of an inner object.
+pub trait InnerUnknown {
+    /// The inner object, or null while there is none.
+    fn inner_unknown(&self) -> *mut IUnknown;
+}
+
+#[derive(Debug)]
+/// The inner object of an outer object, set once the outer object exists so the inner one
+/// can be created with it.
+pub struct Inner {
+    unknown: AtomicPtr<IUnknown>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Inner {
+            unknown: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl Inner {
+    /// Takes over the reference to `unknown`, the inner object's non-delegating `IUnknown`,
+    /// releasing the previous inner object if there was one.
+    ///
+    /// # Safety
+    ///
+    /// `unknown` must be null or a live object's non-delegating `IUnknown`, aggregated by the
+    /// object holding this `Inner`, and the caller must own the reference handed over.
+    pub unsafe fn set(&self, unknown: *mut IUnknown) {
+        let old = self.unknown.swap(unknown, Ordering::AcqRel);
+        if !old.is_null() {
+            (*old).Release();
+        }
+    }
+
+    pub fn get(&self) -> *mut IUnknown {
+        self.unknown.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        unsafe { self.set(ptr::null_mut()) }
+    }
+}
+
+impl InnerUnknown for Inner {
+    fn inner_unknown(&self) -> *mut IUnknown {
+        self.get()
+    }
+}
+
+impl InnerUnknown for ComPtr<IUnknown> {
+    fn inner_unknown(&self) -> *mut IUnknown {
+        self.as_raw()
+    }
+}
+
+impl InnerUnknown for Option<ComPtr<IUnknown>> {
+    fn inner_unknown(&self) -> *mut IUnknown {
+        self.as_ref().map_or(ptr::null_mut(), ComPtr::as_raw)
+    }
+}
@@ -0,0 +1,108 @@
+//! Allocators for the memory of COM objects.
+//!
+//! `#[com_impl(alloc = ...)]` on a `#[derive(ComImpl)]` struct, before the derive, makes
+//! `create_raw` place the object in memory from a [`ComAlloc`] instead of a `Box`, and the
+//! final Release give it back there. `alloc = "cotaskmem"` picks [`CoTaskMem`], for hosts that
+//! expect objects on the COM task heap; any other type implementing the trait may be given by
+//! its path, e.g. an arena for objects that are created at a high rate.
+//!
+//! ```no_run
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! #[repr(C)]
+//! #[com_impl::com_impl(alloc = "cotaskmem")]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Callback {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     cookie: u32,
+//! }
+//! ```
+
+use std::alloc::{self, handle_alloc_error, Layout};
+use std::mem;
+use std::ptr::{self, NonNull};
+
+use winapi::um::combaseapi::{CoTaskMemAlloc, CoTaskMemFree};
+
+/// Where `#[derive(ComImpl)]` objects live. The allocator is a type rather than a value, so an
+/// allocator with state keeps it in a static.
+///
+/// # Safety
+///
+/// `alloc_uninit` must return memory valid and aligned for a `T`, or diverge, which stays
+/// valid until `free` is called with it.
+pub unsafe trait ComAlloc {
+    /// Uninitialized memory for a `T`, aligned for it. The object is written to it in place by
+    /// `create_raw_with`, and by `alloc`.
+    fn alloc_uninit<T>() -> *mut T;
+
+    /// Moves `object` into memory of this allocator.
+    fn alloc<T>(object: T) -> *mut T {
+        let ptr = Self::alloc_uninit::<T>();
+        unsafe { ptr::write(ptr, object) };
+        ptr
+    }
+
+    /// Drops the object `alloc` returned and frees its memory.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `alloc` or `alloc_uninit` of this allocator, hold an initialized
+    /// `T`, and not be used again.
+    unsafe fn free<T>(ptr: *mut T);
+}
+
+#[derive(Debug)]
+/// The global allocator, through `Box`, as for objects without an allocator.
+pub enum BoxAlloc {}
+
+unsafe impl ComAlloc for BoxAlloc {
+    fn alloc_uninit<T>() -> *mut T {
+        // The memory of a Box, which frees it again
+        let layout = Layout::new::<T>();
+        if layout.size() == 0 {
+            return NonNull::dangling().as_ptr();
+        }
+        let ptr = unsafe { alloc::alloc(layout) } as *mut T;
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        ptr
+    }
+
+    fn alloc<T>(object: T) -> *mut T {
+        Box::into_raw(Box::new(object))
+    }
+
+    unsafe fn free<T>(ptr: *mut T) {
+        mem::drop(Box::from_raw(ptr));
+    }
+}
+
+#[derive(Debug)]
+/// The COM task heap, through `CoTaskMemAlloc` and `CoTaskMemFree`. It aligns to
+/// `MEMORY_ALLOCATION_ALIGNMENT`, twice the size of a pointer, which is enough for all but
+/// over-aligned types.
+pub enum CoTaskMem {}
+
+unsafe impl ComAlloc for CoTaskMem {
+    fn alloc_uninit<T>() -> *mut T {
+        let layout = Layout::new::<T>();
+        assert!(
+            layout.align() <= 2 * mem::size_of::<usize>(),
+            "CoTaskMemAlloc can't align the object",
+        );
+        let ptr = unsafe { CoTaskMemAlloc(layout.size()) } as *mut T;
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+        ptr
+    }
+
+    unsafe fn free<T>(ptr: *mut T) {
+        ptr::drop_in_place(ptr);
+        CoTaskMemFree(ptr as *mut _);
+    }
+}
@@ -0,0 +1,269 @@
+//! Running the methods of apartment-affine objects on their owning thread.
+//!
+//! Objects implemented with `#[com_impl(apartment)]` provide a [`Dispatcher`] through
+//! [`Affine`]. When one of their methods is called on any other thread, the generated stub
+//! sends the call to the dispatcher's thread through its message queue and blocks until it has
+//! run there, so the method bodies only ever see the thread that created the dispatcher.
+//!
+//! ```no_run
+//! use com_impl::apartment::{Affine, Dispatcher};
+//! use com_impl::{Refcount, VTable};
+//! use std::rc::Rc;
+//! use winapi::ctypes::c_void;
+//! use winapi::shared::winerror::{E_NOTIMPL, HRESULT, S_OK};
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct FileStream {
+//!     vtbl: VTable<IDWriteFontFileStreamVtbl>,
+//!     refcount: Refcount,
+//!     dispatcher: Dispatcher,
+//!     // Shared with the rest of the UI thread, so it must only be touched there
+//!     data: Rc<Vec<u8>>,
+//! }
+//!
+//! impl Affine for FileStream {
+//!     fn dispatcher(&self) -> &Dispatcher {
+//!         &self.dispatcher
+//!     }
+//! }
+//!
+//! #[com_impl::com_impl(apartment)]
+//! unsafe impl IDWriteFontFileStream for FileStream {
+//!     unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+//!         *size = self.data.len() as u64;
+//!         S_OK
+//!     }
+//!
+//!     unsafe fn get_last_write_time(&self, _time: *mut u64) -> HRESULT {
+//!         E_NOTIMPL
+//!     }
+//!
+//!     unsafe fn read_file_fragment(
+//!         &self,
+//!         _start: *mut *const c_void,
+//!         _offset: u64,
+//!         _size: u64,
+//!         _ctx: *mut *mut c_void,
+//!     ) -> HRESULT {
+//!         E_NOTIMPL
+//!     }
+//!
+//!     unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+//! }
+//! ```
+//!
+//! The owning thread has to pump messages for calls from other threads to make progress. While
+//! the calling thread waits it still handles messages sent to its own windows, so two threads
+//! calling into each other's objects don't deadlock.
+
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Once;
+
+use winapi::shared::minwindef::{HINSTANCE, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::{HRESULT, HRESULT_FROM_WIN32, RPC_E_DISCONNECTED};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, PostMessageW, RegisterClassExW, SendMessageW,
+    HWND_MESSAGE, WM_APP, WM_CLOSE, WNDCLASSEXW,
+};
+
+const WM_DISPATCH: UINT = WM_APP + 0x3c0;
+
+/// Implemented by objects whose `#[com_impl(apartment)]` methods must run on one thread.
+pub trait Affine {
+    fn dispatcher(&self) -> &Dispatcher;
+}
+
+/// What a stub returns when its call could not be delivered to the owning thread.
+pub trait Undelivered {
+    fn undelivered() -> Self;
+}
+
+/// `RPC_E_DISCONNECTED`, as COM's own proxies return for a torn down apartment.
+impl Undelivered for HRESULT {
+    fn undelivered() -> Self {
+        RPC_E_DISCONNECTED
+    }
+}
+
+/// For `AddRef`-style counts.
+impl Undelivered for u32 {
+    fn undelivered() -> Self {
+        0
+    }
+}
+
+impl Undelivered for () {
+    fn undelivered() {}
+}
+
+/// A message-only window on the thread that created it, receiving calls from other threads.
+pub struct Dispatcher {
+    thread: u32,
+    hwnd: HWND,
+}
+
+// The window handle is only used with SendMessage/PostMessage off the owning thread
+unsafe impl Send for Dispatcher {}
+unsafe impl Sync for Dispatcher {}
+
+/// A call waiting on the stack of the sending thread.
+struct Call<'a> {
+    f: &'a mut dyn FnMut(),
+    panic: Option<Box<dyn Any + Send>>,
+    done: bool,
+}
+
+impl Dispatcher {
+    /// A dispatcher owned by the calling thread, which must run a message loop.
+    pub fn new() -> Result<Dispatcher, HRESULT> {
+        let class = class_name();
+        unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            register_class(instance);
+            let hwnd = CreateWindowExW(
+                0,
+                class.as_ptr(),
+                ptr::null(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                ptr::null_mut(),
+                instance,
+                ptr::null_mut(),
+            );
+            if hwnd.is_null() {
+                return Err(HRESULT_FROM_WIN32(GetLastError()));
+            }
+            Ok(Dispatcher {
+                thread: GetCurrentThreadId(),
+                hwnd,
+            })
+        }
+    }
+
+    /// Whether the calling thread is the one the dispatcher belongs to.
+    pub fn is_current(&self) -> bool {
+        unsafe { GetCurrentThreadId() == self.thread }
+    }
+
+    /// Runs `f` on the owning thread and returns its result, or `None` if the owning thread
+    /// has destroyed the dispatcher's window. A panic in `f` is resumed on the calling thread.
+    pub fn run<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        unsafe { self.run_unchecked(f) }
+    }
+
+    /// `run` for closures and results that aren't `Send`.
+    ///
+    /// # Safety
+    ///
+    /// `f` runs on the owning thread while the calling thread waits, and its result is
+    /// returned to the calling thread. Whatever `f` captures and returns must be sound to use
+    /// across that hand-off, as for the raw pointers and apartment-bound object a stub passes.
+    unsafe fn run_unchecked<R>(&self, f: impl FnOnce() -> R) -> Option<R> {
+        if self.is_current() {
+            return Some(f());
+        }
+
+        let mut f = Some(f);
+        let mut result = None;
+        let mut call_once = || {
+            if let Some(f) = f.take() {
+                result = Some(f());
+            }
+        };
+        let mut call = Call {
+            f: &mut call_once,
+            panic: None,
+            done: false,
+        };
+        // Blocks until the owning thread has handled the message
+        SendMessageW(self.hwnd, WM_DISPATCH, 0, &mut call as *mut Call as LPARAM);
+
+        if let Some(panic) = call.panic {
+            panic::resume_unwind(panic);
+        }
+        if !call.done {
+            return None;
+        }
+        drop(call);
+        result
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        unsafe {
+            if self.is_current() {
+                DestroyWindow(self.hwnd);
+            } else {
+                // DestroyWindow only works on the owning thread
+                PostMessageW(self.hwnd, WM_CLOSE, 0, 0);
+            }
+        }
+    }
+}
+
+/// Used by the stubs of `#[com_impl(apartment)]`.
+///
+/// # Safety
+///
+/// As for `Dispatcher::run_unchecked`. The stubs pass the object, whose methods only touch its
+/// state on the owning thread, and the call's arguments, which COM callers may send to any
+/// thread.
+#[doc(hidden)]
+pub unsafe fn dispatch<R: Undelivered>(dispatcher: &Dispatcher, f: impl FnOnce() -> R) -> R {
+    dispatcher.run_unchecked(f).unwrap_or_else(R::undelivered)
+}
+
+fn class_name() -> Vec<u16> {
+    "com_impl::apartment::Dispatcher\0".encode_utf16().collect()
+}
+
+unsafe fn register_class(instance: HINSTANCE) {
+    static REGISTER: Once = Once::new();
+    REGISTER.call_once(|| {
+        let class = class_name();
+        let mut wc: WNDCLASSEXW = mem::zeroed();
+        wc.cbSize = mem::size_of::<WNDCLASSEXW>() as UINT;
+        wc.lpfnWndProc = Some(window_proc);
+        wc.hInstance = instance;
+        wc.lpszClassName = class.as_ptr();
+        // Failure shows up as CreateWindowExW failing
+        RegisterClassExW(&wc);
+    });
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: UINT,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg != WM_DISPATCH {
+        return DefWindowProcW(hwnd, msg, wparam, lparam);
+    }
+
+    let call = &mut *(lparam as *mut Call);
+    // Unwinding out of a window procedure is undefined, so the panic travels back instead
+    if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| (call.f)())) {
+        call.panic = Some(panic);
+    }
+    call.done = true;
+    0
+}
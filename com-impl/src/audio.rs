@@ -0,0 +1,407 @@
+//! Closure-based sinks for the WASAPI notification interfaces.
+//!
+//! Only the events given a handler do anything; the rest return `S_OK` without calling back
+//! into Rust.
+//!
+//! ```no_run
+//! use com_impl::audio::DeviceNotificationBuilder;
+//!
+//! let client = DeviceNotificationBuilder::new()
+//!     .on_default_device_changed(|flow, role, id| {
+//!         println!("default device for {}/{} is now {:?}", flow, role, id);
+//!     })
+//!     .build();
+//!
+//! // Pass client.as_raw() to IMMDeviceEnumerator::RegisterEndpointNotificationCallback
+//! ```
+//!
+//! Both sinks are called from threads owned by the audio service, so every handler has to be
+//! `Send + Sync`. Handlers must not register or unregister sinks themselves.
+
+use winapi::shared::guiddef::{GUID, LPCGUID};
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+use winapi::shared::wtypes::PROPERTYKEY;
+use winapi::um::audiosessiontypes::AudioSessionState;
+use winapi::um::mmdeviceapi::{EDataFlow, ERole, IMMNotificationClient, IMMNotificationClientVtbl};
+use winapi::um::winnt::LPCWSTR;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub use self::ffi::{IAudioSessionEvents, IAudioSessionEventsVtbl};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::guiddef::LPCGUID;
+    use winapi::shared::minwindef::{BOOL, DWORD};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::audiosessiontypes::AudioSessionState;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::LPCWSTR;
+    use winapi::RIDL;
+
+    // audiopolicy.h is not covered by winapi.
+    RIDL! {#[uuid(0x24918acc, 0x64b3, 0x37c1, 0x8c, 0xa9, 0x74, 0xa6, 0x6e, 0x99, 0x57, 0xa8)]
+    interface IAudioSessionEvents(IAudioSessionEventsVtbl): IUnknown(IUnknownVtbl) {
+        fn OnDisplayNameChanged(
+            NewDisplayName: LPCWSTR,
+            EventContext: LPCGUID,
+        ) -> HRESULT,
+        fn OnIconPathChanged(
+            NewIconPath: LPCWSTR,
+            EventContext: LPCGUID,
+        ) -> HRESULT,
+        fn OnSimpleVolumeChanged(
+            NewVolume: f32,
+            NewMute: BOOL,
+            EventContext: LPCGUID,
+        ) -> HRESULT,
+        fn OnChannelVolumeChanged(
+            ChannelCount: DWORD,
+            NewChannelVolumeArray: *const f32,
+            ChangedChannel: DWORD,
+            EventContext: LPCGUID,
+        ) -> HRESULT,
+        fn OnGroupingParamChanged(
+            NewGroupingParam: LPCGUID,
+            EventContext: LPCGUID,
+        ) -> HRESULT,
+        fn OnStateChanged(
+            NewState: AudioSessionState,
+        ) -> HRESULT,
+        fn OnSessionDisconnected(
+            DisconnectReason: DWORD,
+        ) -> HRESULT,
+    }}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Why an audio session was disconnected, from `AudioSessionDisconnectReason`.
+pub enum DisconnectReason {
+    DeviceRemoval,
+    ServerShutdown,
+    FormatChanged,
+    SessionLogoff,
+    SessionDisconnected,
+    ExclusiveModeOverride,
+    Unknown(u32),
+}
+
+impl From<u32> for DisconnectReason {
+    fn from(reason: u32) -> DisconnectReason {
+        match reason {
+            0 => DisconnectReason::DeviceRemoval,
+            1 => DisconnectReason::ServerShutdown,
+            2 => DisconnectReason::FormatChanged,
+            3 => DisconnectReason::SessionLogoff,
+            4 => DisconnectReason::SessionDisconnected,
+            5 => DisconnectReason::ExclusiveModeOverride,
+            other => DisconnectReason::Unknown(other),
+        }
+    }
+}
+
+unsafe fn from_wide(s: LPCWSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *s.offset(i) != 0).count();
+    let s = std::slice::from_raw_parts(s, len);
+    Some(String::from_utf16_lossy(s))
+}
+
+type DeviceFn = Box<dyn Fn(&str) + Send + Sync>;
+type DeviceStateFn = Box<dyn Fn(&str, DWORD) + Send + Sync>;
+type DefaultDeviceFn = Box<dyn Fn(EDataFlow, ERole, Option<&str>) + Send + Sync>;
+type PropertyFn = Box<dyn Fn(&str, PROPERTYKEY) + Send + Sync>;
+
+#[derive(Default)]
+/// Builds an `IMMNotificationClient` for device arrival, removal and default changes.
+pub struct DeviceNotificationBuilder {
+    state_changed: Option<DeviceStateFn>,
+    added: Option<DeviceFn>,
+    removed: Option<DeviceFn>,
+    default_changed: Option<DefaultDeviceFn>,
+    property_changed: Option<PropertyFn>,
+}
+
+impl DeviceNotificationBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Called with the device ID and its new `DEVICE_STATE_*` value.
+    pub fn on_device_state_changed(
+        mut self,
+        f: impl Fn(&str, DWORD) + Send + Sync + 'static,
+    ) -> Self {
+        self.state_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_device_added(mut self, f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.added = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_device_removed(mut self, f: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.removed = Some(Box::new(f));
+        self
+    }
+
+    /// The device ID is `None` when the last device for that flow and role went away.
+    pub fn on_default_device_changed(
+        mut self,
+        f: impl Fn(EDataFlow, ERole, Option<&str>) + Send + Sync + 'static,
+    ) -> Self {
+        self.default_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_property_value_changed(
+        mut self,
+        f: impl Fn(&str, PROPERTYKEY) + Send + Sync + 'static,
+    ) -> Self {
+        self.property_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn build(self) -> ComPtr<IMMNotificationClient> {
+        let ptr = DeviceNotificationClient::create_raw(self);
+        let ptr = ptr as *mut IMMNotificationClient;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct DeviceNotificationClient {
+    vtbl: VTable<IMMNotificationClientVtbl>,
+    refcount: Refcount,
+    handlers: DeviceNotificationBuilder,
+}
+
+#[com_impl::com_impl]
+unsafe impl IMMNotificationClient for DeviceNotificationClient {
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_device_state_changed(&self, id: LPCWSTR, state: DWORD) -> HRESULT {
+        if let (Some(f), Some(id)) = (&self.handlers.state_changed, from_wide(id)) {
+            f(&id, state);
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_device_added(&self, id: LPCWSTR) -> HRESULT {
+        if let (Some(f), Some(id)) = (&self.handlers.added, from_wide(id)) {
+            f(&id);
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_device_removed(&self, id: LPCWSTR) -> HRESULT {
+        if let (Some(f), Some(id)) = (&self.handlers.removed, from_wide(id)) {
+            f(&id);
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_default_device_changed(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        id: LPCWSTR,
+    ) -> HRESULT {
+        if let Some(f) = &self.handlers.default_changed {
+            f(flow, role, from_wide(id).as_deref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_property_value_changed(&self, id: LPCWSTR, key: PROPERTYKEY) -> HRESULT {
+        if let (Some(f), Some(id)) = (&self.handlers.property_changed, from_wide(id)) {
+            f(&id, key);
+        }
+        S_OK
+    }
+}
+
+type TextFn = Box<dyn Fn(&str, Option<&GUID>) + Send + Sync>;
+type VolumeFn = Box<dyn Fn(f32, bool, Option<&GUID>) + Send + Sync>;
+type ChannelVolumeFn = Box<dyn Fn(&[f32], u32, Option<&GUID>) + Send + Sync>;
+type GroupingFn = Box<dyn Fn(&GUID, Option<&GUID>) + Send + Sync>;
+type StateFn = Box<dyn Fn(AudioSessionState) + Send + Sync>;
+type DisconnectFn = Box<dyn Fn(DisconnectReason) + Send + Sync>;
+
+#[derive(Default)]
+/// Builds an `IAudioSessionEvents` for `IAudioSessionControl::RegisterAudioSessionNotification`.
+///
+/// The `Option<&GUID>` passed to most handlers is the event context given to the call that
+/// caused the change, which lets an application ignore changes it made itself.
+pub struct SessionEventsBuilder {
+    display_name_changed: Option<TextFn>,
+    icon_path_changed: Option<TextFn>,
+    simple_volume_changed: Option<VolumeFn>,
+    channel_volume_changed: Option<ChannelVolumeFn>,
+    grouping_param_changed: Option<GroupingFn>,
+    state_changed: Option<StateFn>,
+    session_disconnected: Option<DisconnectFn>,
+}
+
+impl SessionEventsBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn on_display_name_changed(
+        mut self,
+        f: impl Fn(&str, Option<&GUID>) + Send + Sync + 'static,
+    ) -> Self {
+        self.display_name_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_icon_path_changed(
+        mut self,
+        f: impl Fn(&str, Option<&GUID>) + Send + Sync + 'static,
+    ) -> Self {
+        self.icon_path_changed = Some(Box::new(f));
+        self
+    }
+
+    /// Called with the new master volume, from 0.0 to 1.0, and mute state.
+    pub fn on_simple_volume_changed(
+        mut self,
+        f: impl Fn(f32, bool, Option<&GUID>) + Send + Sync + 'static,
+    ) -> Self {
+        self.simple_volume_changed = Some(Box::new(f));
+        self
+    }
+
+    /// Called with every channel's volume and the index of the one that changed, which is
+    /// `u32::MAX` when more than one did.
+    pub fn on_channel_volume_changed(
+        mut self,
+        f: impl Fn(&[f32], u32, Option<&GUID>) + Send + Sync + 'static,
+    ) -> Self {
+        self.channel_volume_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_grouping_param_changed(
+        mut self,
+        f: impl Fn(&GUID, Option<&GUID>) + Send + Sync + 'static,
+    ) -> Self {
+        self.grouping_param_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_state_changed(
+        mut self,
+        f: impl Fn(AudioSessionState) + Send + Sync + 'static,
+    ) -> Self {
+        self.state_changed = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_session_disconnected(
+        mut self,
+        f: impl Fn(DisconnectReason) + Send + Sync + 'static,
+    ) -> Self {
+        self.session_disconnected = Some(Box::new(f));
+        self
+    }
+
+    pub fn build(self) -> ComPtr<IAudioSessionEvents> {
+        let ptr = SessionEvents::create_raw(self);
+        let ptr = ptr as *mut IAudioSessionEvents;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct SessionEvents {
+    vtbl: VTable<IAudioSessionEventsVtbl>,
+    refcount: Refcount,
+    handlers: SessionEventsBuilder,
+}
+
+#[com_impl::com_impl]
+unsafe impl IAudioSessionEvents for SessionEvents {
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_display_name_changed(&self, name: LPCWSTR, context: LPCGUID) -> HRESULT {
+        if let Some(f) = &self.handlers.display_name_changed {
+            f(&from_wide(name).unwrap_or_default(), context.as_ref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_icon_path_changed(&self, path: LPCWSTR, context: LPCGUID) -> HRESULT {
+        if let Some(f) = &self.handlers.icon_path_changed {
+            f(&from_wide(path).unwrap_or_default(), context.as_ref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_simple_volume_changed(
+        &self,
+        volume: f32,
+        mute: BOOL,
+        context: LPCGUID,
+    ) -> HRESULT {
+        if let Some(f) = &self.handlers.simple_volume_changed {
+            f(volume, mute != 0, context.as_ref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_channel_volume_changed(
+        &self,
+        count: DWORD,
+        volumes: *const f32,
+        changed: DWORD,
+        context: LPCGUID,
+    ) -> HRESULT {
+        if let Some(f) = &self.handlers.channel_volume_changed {
+            let volumes = if volumes.is_null() {
+                &[]
+            } else {
+                std::slice::from_raw_parts(volumes, count as usize)
+            };
+            f(volumes, changed, context.as_ref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_grouping_param_changed(&self, param: LPCGUID, context: LPCGUID) -> HRESULT {
+        if let (Some(f), Some(param)) = (&self.handlers.grouping_param_changed, param.as_ref()) {
+            f(param, context.as_ref());
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn on_state_changed(&self, state: AudioSessionState) -> HRESULT {
+        if let Some(f) = &self.handlers.state_changed {
+            f(state);
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn on_session_disconnected(&self, reason: DWORD) -> HRESULT {
+        if let Some(f) = &self.handlers.session_disconnected {
+            f(DisconnectReason::from(reason));
+        }
+        S_OK
+    }
+}
@@ -0,0 +1,227 @@
+//! `IBindStatusCallback` driven by a Rust trait, for URL moniker downloads such as
+//! `URLDownloadToFileW`.
+//!
+//! ```no_run
+//! use com_impl::bind_status::{BindStatusCallback, Progress};
+//!
+//! let callback = BindStatusCallback::new(|progress: &Progress| {
+//!     println!("{} / {}", progress.current, progress.max);
+//!     true
+//! });
+//!
+//! // Pass callback.as_raw() as the lpfnCB parameter of URLDownloadToFileW
+//! ```
+//!
+//! The URL moniker interfaces are not covered by winapi, so the ones used here are defined in
+//! this module.
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::{DWORD, ULONG};
+use winapi::shared::winerror::{E_ABORT, E_FAIL, E_INVALIDARG, E_NOTIMPL, HRESULT, S_OK};
+use winapi::um::objidl::{FORMATETC, STGMEDIUM};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::{LONG, LPCWSTR};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub use self::ffi::{IBindStatusCallback, IBindStatusCallbackVtbl, IBinding, IBindingVtbl};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::ctypes::c_void;
+    use winapi::shared::guiddef::{CLSID, REFIID};
+    use winapi::shared::minwindef::{DWORD, ULONG};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::objidl::{FORMATETC, STGMEDIUM};
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::{LONG, LPCWSTR, LPWSTR};
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0x79eac9c0, 0xbaf9, 0x11ce, 0x8c, 0x82, 0x00, 0xaa, 0x00, 0x4b, 0xa9, 0x0b)]
+    interface IBinding(IBindingVtbl): IUnknown(IUnknownVtbl) {
+        fn Abort() -> HRESULT,
+        fn Suspend() -> HRESULT,
+        fn Resume() -> HRESULT,
+        fn SetPriority(
+            nPriority: LONG,
+        ) -> HRESULT,
+        fn GetPriority(
+            pnPriority: *mut LONG,
+        ) -> HRESULT,
+        fn GetBindResult(
+            pclsidProtocol: *mut CLSID,
+            pdwResult: *mut DWORD,
+            pszResult: *mut LPWSTR,
+            pdwReserved: *mut DWORD,
+        ) -> HRESULT,
+    }}
+
+    // BINDINFO is left opaque since nothing here fills it in.
+    RIDL! {#[uuid(0x79eac9c1, 0xbaf9, 0x11ce, 0x8c, 0x82, 0x00, 0xaa, 0x00, 0x4b, 0xa9, 0x0b)]
+    interface IBindStatusCallback(IBindStatusCallbackVtbl): IUnknown(IUnknownVtbl) {
+        fn OnStartBinding(
+            dwReserved: DWORD,
+            pib: *mut IBinding,
+        ) -> HRESULT,
+        fn GetPriority(
+            pnPriority: *mut LONG,
+        ) -> HRESULT,
+        fn OnLowResource(
+            reserved: DWORD,
+        ) -> HRESULT,
+        fn OnProgress(
+            ulProgress: ULONG,
+            ulProgressMax: ULONG,
+            ulStatusCode: ULONG,
+            szStatusText: LPCWSTR,
+        ) -> HRESULT,
+        fn OnStopBinding(
+            hresult: HRESULT,
+            szError: LPCWSTR,
+        ) -> HRESULT,
+        fn GetBindInfo(
+            grfBINDF: *mut DWORD,
+            pbindinfo: *mut c_void,
+        ) -> HRESULT,
+        fn OnDataAvailable(
+            grfBSCF: DWORD,
+            dwSize: DWORD,
+            pformatetc: *mut FORMATETC,
+            pstgmed: *mut STGMEDIUM,
+        ) -> HRESULT,
+        fn OnObjectAvailable(
+            riid: REFIID,
+            punk: *mut IUnknown,
+        ) -> HRESULT,
+    }}
+}
+
+#[derive(Clone, Debug)]
+/// A progress notification from the download.
+pub struct Progress {
+    pub current: u32,
+    /// Total size, or 0 if it is not known yet.
+    pub max: u32,
+    /// One of the `BINDSTATUS_*` values from urlmon.h.
+    pub status: u32,
+    pub text: Option<String>,
+}
+
+/// Receives notifications from a URL moniker bind operation.
+///
+/// Any `Fn(&Progress) -> bool` closure implements this trait.
+pub trait BindStatus {
+    /// Return `false` to cancel the operation.
+    fn on_progress(&self, progress: &Progress) -> bool;
+
+    /// Called as data arrives when binding asynchronously. `flags` is a combination of the
+    /// `BSCF_*` values from urlmon.h.
+    fn on_data_available(&self, _flags: u32, _size: u32, _format: &FORMATETC, _medium: &STGMEDIUM) {
+    }
+
+    /// Called once the operation has finished, successfully or otherwise.
+    fn on_stop_binding(&self, _result: HRESULT, _error: Option<String>) {}
+}
+
+impl<F> BindStatus for F
+where
+    F: Fn(&Progress) -> bool,
+{
+    fn on_progress(&self, progress: &Progress) -> bool {
+        self(progress)
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IBindStatusCallback` by forwarding to a [`BindStatus`].
+pub struct BindStatusCallback<S: BindStatus> {
+    vtbl: VTable<IBindStatusCallbackVtbl>,
+    refcount: Refcount,
+    status: S,
+}
+
+impl<S: BindStatus> BindStatusCallback<S> {
+    pub fn new(status: S) -> ComPtr<IBindStatusCallback> {
+        let ptr = BindStatusCallback::create_raw(status);
+        let ptr = ptr as *mut IBindStatusCallback;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+unsafe fn from_wide(s: LPCWSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *s.offset(i) != 0).count();
+    let s = std::slice::from_raw_parts(s, len);
+    Some(String::from_utf16_lossy(s))
+}
+
+#[com_impl::com_impl]
+unsafe impl<S: BindStatus> IBindStatusCallback for BindStatusCallback<S> {
+    fn on_start_binding(&self, _reserved: DWORD, _binding: *mut IBinding) -> HRESULT {
+        S_OK
+    }
+
+    fn get_priority(&self, _priority: *mut LONG) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn on_low_resource(&self, _reserved: DWORD) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_progress(
+        &self,
+        current: ULONG,
+        max: ULONG,
+        status: ULONG,
+        text: LPCWSTR,
+    ) -> HRESULT {
+        let progress = Progress {
+            current,
+            max,
+            status,
+            text: from_wide(text),
+        };
+        if self.status.on_progress(&progress) {
+            S_OK
+        } else {
+            E_ABORT
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_stop_binding(&self, result: HRESULT, error: LPCWSTR) -> HRESULT {
+        self.status.on_stop_binding(result, from_wide(error));
+        S_OK
+    }
+
+    fn get_bind_info(&self, _flags: *mut DWORD, _info: *mut c_void) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_data_available(
+        &self,
+        flags: DWORD,
+        size: DWORD,
+        format: *mut FORMATETC,
+        medium: *mut STGMEDIUM,
+    ) -> HRESULT {
+        if format.is_null() || medium.is_null() {
+            return E_INVALIDARG;
+        }
+        self.status
+            .on_data_available(flags, size, &*format, &*medium);
+        S_OK
+    }
+
+    fn on_object_available(&self, _riid: REFIID, _object: *mut IUnknown) -> HRESULT {
+        E_NOTIMPL
+    }
+}
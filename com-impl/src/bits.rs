@@ -0,0 +1,213 @@
+//! `IBackgroundCopyCallback` driven by a Rust trait or delivering to a channel, for
+//! Background Intelligent Transfer Service jobs.
+//!
+//! ```no_run
+//! use com_impl::bits::{JobCallback, JobEvent};
+//!
+//! let (callback, events) = JobCallback::channel();
+//!
+//! // job.SetNotifyInterface(callback.as_raw() as *mut IUnknown);
+//! // job.SetNotifyFlags(BG_NOTIFY_JOB_TRANSFERRED | BG_NOTIFY_JOB_ERROR);
+//! for event in events {
+//!     if let JobEvent::Transferred { job } = event {
+//!         // manager.GetJob(&job, ...) and Complete() it
+//!         break;
+//!     }
+//! }
+//! ```
+//!
+//! BITS calls the callback from its own RPC threads. The trait-based callback therefore needs
+//! a `Send + Sync` implementation, and the channel events carry the job's ID rather than the
+//! job itself, to be looked up again with `IBackgroundCopyManager::GetJob`.
+
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_FAIL, E_POINTER, HRESULT, S_OK};
+use winapi::um::bits::{
+    IBackgroundCopyCallback, IBackgroundCopyCallbackVtbl, IBackgroundCopyError, IBackgroundCopyJob,
+};
+use winapi::um::combaseapi::CoTaskMemFree;
+use winapi::um::winnt::{LANG_NEUTRAL, LPWSTR, MAKELANGID, SUBLANG_DEFAULT};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+/// Receives notifications for the jobs the callback is registered with.
+pub trait JobEvents: Send + Sync {
+    /// All files have been transferred. This is usually where the job is `Complete`d.
+    ///
+    /// Returning an error makes BITS call again later.
+    fn job_transferred(&self, job: &IBackgroundCopyJob) -> Result<(), HRESULT>;
+
+    /// The job is in the error state. Returning `Ok` after dealing with the error, e.g. by
+    /// cancelling or resuming the job, stops further notifications for it.
+    fn job_error(
+        &self,
+        job: &IBackgroundCopyJob,
+        error: &IBackgroundCopyError,
+    ) -> Result<(), HRESULT>;
+
+    /// The job's state or progress changed. Only called with `BG_NOTIFY_JOB_MODIFICATION`.
+    fn job_modification(&self, _job: &IBackgroundCopyJob) {}
+}
+
+#[derive(Clone)]
+/// A notification as delivered by [`JobCallback::channel`].
+pub enum JobEvent {
+    Transferred {
+        job: GUID,
+    },
+    Error {
+        job: GUID,
+        /// One of the `BG_ERROR_CONTEXT_*` values.
+        context: u32,
+        code: HRESULT,
+        description: Option<String>,
+    },
+    Modified {
+        job: GUID,
+    },
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IBackgroundCopyCallback` by forwarding to a [`JobEvents`].
+pub struct JobCallback<E: JobEvents> {
+    vtbl: VTable<IBackgroundCopyCallbackVtbl>,
+    refcount: Refcount,
+    events: E,
+}
+
+impl<E: JobEvents> JobCallback<E> {
+    pub fn new(events: E) -> ComPtr<IBackgroundCopyCallback> {
+        let ptr = JobCallback::create_raw(events);
+        let ptr = ptr as *mut IBackgroundCopyCallback;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+impl JobCallback<ChannelEvents> {
+    /// A callback sending every notification to the returned receiver.
+    ///
+    /// Transferred jobs are left for the receiver to complete, so the callback reports
+    /// success straight away.
+    pub fn channel() -> (ComPtr<IBackgroundCopyCallback>, Receiver<JobEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let callback = JobCallback::new(ChannelEvents(Mutex::new(tx)));
+        (callback, rx)
+    }
+}
+
+#[doc(hidden)]
+pub struct ChannelEvents(Mutex<Sender<JobEvent>>);
+
+impl ChannelEvents {
+    fn send(&self, event: JobEvent) {
+        if let Ok(tx) = self.0.lock() {
+            // The receiver going away just means nobody is listening any more
+            let _ = tx.send(event);
+        }
+    }
+}
+
+unsafe fn job_id(job: &IBackgroundCopyJob) -> Result<GUID, HRESULT> {
+    let mut id = std::mem::zeroed();
+    let hr = job.GetId(&mut id);
+    if hr < 0 {
+        return Err(hr);
+    }
+    Ok(id)
+}
+
+unsafe fn take_co_task_string(s: LPWSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *s.offset(i) != 0).count();
+    let text = String::from_utf16_lossy(std::slice::from_raw_parts(s, len));
+    CoTaskMemFree(s as _);
+    Some(text)
+}
+
+impl JobEvents for ChannelEvents {
+    fn job_transferred(&self, job: &IBackgroundCopyJob) -> Result<(), HRESULT> {
+        let job = unsafe { job_id(job)? };
+        self.send(JobEvent::Transferred { job });
+        Ok(())
+    }
+
+    fn job_error(
+        &self,
+        job: &IBackgroundCopyJob,
+        error: &IBackgroundCopyError,
+    ) -> Result<(), HRESULT> {
+        unsafe {
+            let job = job_id(job)?;
+            let mut context = 0;
+            let mut code = S_OK;
+            let hr = error.GetError(&mut context, &mut code);
+            if hr < 0 {
+                return Err(hr);
+            }
+
+            let mut description = ptr::null_mut();
+            let language = MAKELANGID(LANG_NEUTRAL, SUBLANG_DEFAULT) as DWORD;
+            error.GetErrorDescription(language, &mut description);
+
+            self.send(JobEvent::Error {
+                job,
+                context,
+                code,
+                description: take_co_task_string(description),
+            });
+        }
+        Ok(())
+    }
+
+    fn job_modification(&self, job: &IBackgroundCopyJob) {
+        if let Ok(job) = unsafe { job_id(job) } {
+            self.send(JobEvent::Modified { job });
+        }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<E: JobEvents> IBackgroundCopyCallback for JobCallback<E> {
+    #[panic(result = "E_FAIL")]
+    unsafe fn job_transferred(&self, job: *mut IBackgroundCopyJob) -> HRESULT {
+        if job.is_null() {
+            return E_POINTER;
+        }
+        match self.events.job_transferred(&*job) {
+            Ok(()) => S_OK,
+            Err(hr) => hr,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn job_error(
+        &self,
+        job: *mut IBackgroundCopyJob,
+        error: *mut IBackgroundCopyError,
+    ) -> HRESULT {
+        if job.is_null() || error.is_null() {
+            return E_POINTER;
+        }
+        match self.events.job_error(&*job, &*error) {
+            Ok(()) => S_OK,
+            Err(hr) => hr,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn job_modification(&self, job: *mut IBackgroundCopyJob, _reserved: DWORD) -> HRESULT {
+        if !job.is_null() {
+            self.events.job_modification(&*job);
+        }
+        S_OK
+    }
+}
@@ -0,0 +1,122 @@
+//! Class factories, creating the objects of a COM class for `CoCreateInstance`.
+//!
+//! `#[class_factory]` on a `#[derive(ComImpl)]` struct implements [`CoClass`] for it, creating
+//! objects through the generated constructor with every member given by `Default::default()`.
+//! A class with an `com_impl::aggregation::Aggregation` member can be aggregated, and is
+//! created with `create_raw_aggregated` when `CreateInstance` is given an outer object; other
+//! classes refuse with `CLASS_E_NOAGGREGATION`. Classes whose members need other values
+//! implement [`CoClass`] by hand instead.
+//!
+//! [`ClassFactory<T>`](ClassFactory) is the `IClassFactory` handed out for the class. Its
+//! `LockServer` calls count on the module lock of `com_impl::server_lock`; give the class a
+//! `com_impl::server_lock::ServerLock` member for its objects to count there as well.
+//!
+//! ```no_run
+//! use com_impl::class_factory::ClassFactory;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::unknwnbase::{IClassFactory, IUnknownVtbl};
+//! use wio::com::ComPtr;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[class_factory]
+//! pub struct Counter {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     count: std::sync::atomic::AtomicU32,
+//! }
+//!
+//! let factory: ComPtr<IClassFactory> = ClassFactory::<Counter>::new();
+//! ```
+
+use std::marker::PhantomData;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, REFIID};
+use winapi::shared::minwindef::{BOOL, FALSE};
+use winapi::shared::winerror::{CLASS_E_NOAGGREGATION, E_POINTER, E_UNEXPECTED, HRESULT, S_OK};
+use winapi::um::unknwnbase::{IClassFactory, IClassFactoryVtbl, IUnknown};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::server_lock;
+use crate::{Refcount, VTable};
+
+/// A COM class whose objects a [`ClassFactory`] can create. Implemented by
+/// `#[class_factory]`.
+///
+/// # Safety
+///
+/// `create_instance` must return a live object's `IUnknown`, with one reference owned by the
+/// caller, and only aggregate objects when asked to.
+pub unsafe trait CoClass {
+    /// Creates an object and returns one reference to its `IUnknown`.
+    ///
+    /// When `outer` isn't null, the object is aggregated by it and the non-delegating
+    /// `IUnknown` is returned, or the class refuses with `CLASS_E_NOAGGREGATION`.
+    ///
+    /// # Safety
+    ///
+    /// `outer` must be null or a live controlling `IUnknown`, which the new object forwards
+    /// its `IUnknown` calls to.
+    unsafe fn create_instance(outer: *mut IUnknown) -> Result<*mut IUnknown, HRESULT>;
+}
+
+/// Creates the class factory of a class, e.g. `ClassFactory::<T>::new`.
+pub type NewFactory = fn() -> ComPtr<IClassFactory>;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IClassFactory)]
+/// The `IClassFactory` of the class `T`.
+pub struct ClassFactory<T: CoClass + 'static> {
+    vtbl: VTable<IClassFactoryVtbl>,
+    refcount: Refcount,
+    class: PhantomData<fn() -> T>,
+}
+
+impl<T: CoClass + 'static> ClassFactory<T> {
+    pub fn new() -> ComPtr<IClassFactory> {
+        let ptr = ClassFactory::<T>::create_raw(PhantomData);
+        unsafe { ComPtr::from_raw(ptr as *mut IClassFactory) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: CoClass + 'static> IClassFactory for ClassFactory<T> {
+    #[panic(result = E_UNEXPECTED)]
+    unsafe fn create_instance(
+        &self,
+        outer: *mut IUnknown,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        *ppv = ptr::null_mut();
+
+        // An aggregating outer object must ask for the non-delegating IUnknown
+        if !outer.is_null() && !IsEqualIID(&*riid, &IUnknown::uuidof()) {
+            return CLASS_E_NOAGGREGATION;
+        }
+
+        let unknown = match T::create_instance(outer) {
+            Ok(unknown) => unknown,
+            Err(hr) => return hr,
+        };
+        let hr = (*unknown).QueryInterface(riid, ppv);
+        (*unknown).Release();
+        hr
+    }
+
+    fn lock_server(&self, lock: BOOL) -> HRESULT {
+        if lock != FALSE {
+            server_lock::lock();
+        } else {
+            server_lock::unlock();
+        }
+        S_OK
+    }
+}
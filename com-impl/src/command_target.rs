@@ -0,0 +1,359 @@
+//! A table-driven `IOleCommandTarget`, for browser helper objects, editors and ribbon hosts.
+//!
+//! Each command group is a Rust type implementing [`CommandGroup`], usually a fieldless enum,
+//! and is handled by a [`CommandHandler`] for that type. Groups that aren't in the table are
+//! answered with `OLECMDERR_E_UNKNOWNGROUP`, and IDs that don't map to a command with
+//! `OLECMDERR_E_NOTSUPPORTED`, without calling into Rust.
+//!
+//! ```no_run
+//! use com_impl::command_target::{
+//!     CommandGroup, CommandHandler, CommandState, CommandTableBuilder, ExecOption,
+//! };
+//! use com_impl::variant::Variant;
+//! use winapi::shared::guiddef::GUID;
+//! use winapi::shared::winerror::HRESULT;
+//!
+//! #[derive(Copy, Clone)]
+//! enum Format {
+//!     Bold,
+//!     Italic,
+//! }
+//!
+//! impl CommandGroup for Format {
+//!     const GROUP: Option<GUID> = Some(GUID {
+//!         Data1: 0x6c4a_c5d2,
+//!         Data2: 0x8e3f,
+//!         Data3: 0x4a1b,
+//!         Data4: [0x9d, 0x27, 0x31, 0x5e, 0x0b, 0x84, 0xc6, 0x13],
+//!     });
+//!
+//!     fn from_id(id: u32) -> Option<Format> {
+//!         match id {
+//!             1 => Some(Format::Bold),
+//!             2 => Some(Format::Italic),
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! struct Editor;
+//!
+//! impl CommandHandler<Format> for Editor {
+//!     fn status(&self, _command: Format) -> CommandState {
+//!         CommandState::enabled()
+//!     }
+//!
+//!     fn exec(
+//!         &self,
+//!         command: Format,
+//!         _option: ExecOption,
+//!         _input: Option<&Variant>,
+//!     ) -> Result<Option<Variant>, HRESULT> {
+//!         match command {
+//!             Format::Bold => println!("bold"),
+//!             Format::Italic => println!("italic"),
+//!         }
+//!         Ok(None)
+//!     }
+//! }
+//!
+//! let target = CommandTableBuilder::new().group(Editor).build();
+//! ```
+
+use winapi::shared::guiddef::{IsEqualGUID, GUID};
+use winapi::shared::minwindef::{DWORD, LOWORD, ULONG};
+use winapi::shared::winerror::{E_FAIL, E_POINTER, HRESULT, S_OK};
+use winapi::um::docobj::{
+    IOleCommandTarget, IOleCommandTargetVtbl, OLECMD, OLECMDEXECOPT_DODEFAULT,
+    OLECMDEXECOPT_DONTPROMPTUSER, OLECMDEXECOPT_PROMPTUSER, OLECMDEXECOPT_SHOWHELP,
+    OLECMDF_ENABLED, OLECMDF_INVISIBLE, OLECMDF_LATCHED, OLECMDF_SUPPORTED, OLECMDTEXT,
+};
+use winapi::um::oaidl::VARIANT;
+use winapi::um::oleauto::VariantClear;
+use wio::com::ComPtr;
+
+use crate::variant::Variant;
+use crate::{Refcount, VTable};
+
+pub const OLECMDERR_E_NOTSUPPORTED: HRESULT = 0x8004_0100u32 as HRESULT;
+pub const OLECMDERR_E_DISABLED: HRESULT = 0x8004_0101u32 as HRESULT;
+pub const OLECMDERR_E_UNKNOWNGROUP: HRESULT = 0x8004_0104u32 as HRESULT;
+
+/// A set of commands sharing a command group GUID.
+pub trait CommandGroup: Copy + 'static {
+    /// `None` for the standard `OLECMDID_*` commands, which are sent without a group.
+    const GROUP: Option<GUID>;
+
+    /// `None` for IDs that aren't part of the group.
+    fn from_id(id: u32) -> Option<Self>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The standard `OLECMDID_*` commands, for handlers that match on the raw IDs.
+pub struct StandardCommand(pub u32);
+
+impl CommandGroup for StandardCommand {
+    const GROUP: Option<GUID> = None;
+
+    fn from_id(id: u32) -> Option<StandardCommand> {
+        Some(StandardCommand(id))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// What `QueryStatus` reports for a command.
+pub struct CommandState {
+    pub supported: bool,
+    pub enabled: bool,
+    /// Shown as checked or pressed.
+    pub latched: bool,
+    pub invisible: bool,
+}
+
+impl CommandState {
+    pub fn enabled() -> CommandState {
+        CommandState {
+            supported: true,
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn disabled() -> CommandState {
+        CommandState {
+            supported: true,
+            ..Default::default()
+        }
+    }
+
+    /// Commands the handler doesn't implement after all.
+    pub fn unsupported() -> CommandState {
+        Default::default()
+    }
+
+    pub fn latched(mut self, latched: bool) -> CommandState {
+        self.latched = latched;
+        self
+    }
+
+    pub fn invisible(mut self, invisible: bool) -> CommandState {
+        self.invisible = invisible;
+        self
+    }
+
+    fn flags(self) -> DWORD {
+        if !self.supported {
+            return 0;
+        }
+        let mut flags = OLECMDF_SUPPORTED;
+        if self.enabled {
+            flags |= OLECMDF_ENABLED;
+        }
+        if self.latched {
+            flags |= OLECMDF_LATCHED;
+        }
+        if self.invisible {
+            flags |= OLECMDF_INVISIBLE;
+        }
+        flags
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// How the caller would like a command to interact with the user.
+pub enum ExecOption {
+    Default,
+    PromptUser,
+    DontPromptUser,
+    ShowHelp,
+    Unknown(u32),
+}
+
+impl From<u32> for ExecOption {
+    fn from(option: u32) -> ExecOption {
+        match option {
+            OLECMDEXECOPT_DODEFAULT => ExecOption::Default,
+            OLECMDEXECOPT_PROMPTUSER => ExecOption::PromptUser,
+            OLECMDEXECOPT_DONTPROMPTUSER => ExecOption::DontPromptUser,
+            OLECMDEXECOPT_SHOWHELP => ExecOption::ShowHelp,
+            other => ExecOption::Unknown(other),
+        }
+    }
+}
+
+/// Handles the commands of one group.
+pub trait CommandHandler<C: CommandGroup> {
+    fn status(&self, command: C) -> CommandState;
+
+    /// Only called for commands whose status is supported and enabled. The returned value
+    /// is handed back to callers that asked for output.
+    fn exec(
+        &self,
+        command: C,
+        option: ExecOption,
+        input: Option<&Variant>,
+    ) -> Result<Option<Variant>, HRESULT>;
+}
+
+/// A group's handler with the group type erased.
+trait Group {
+    fn guid(&self) -> Option<GUID>;
+    fn status(&self, id: u32) -> CommandState;
+    fn exec(
+        &self,
+        id: u32,
+        option: ExecOption,
+        input: Option<&Variant>,
+    ) -> Result<Option<Variant>, HRESULT>;
+}
+
+struct Entry<C, H> {
+    handler: H,
+    _group: std::marker::PhantomData<C>,
+}
+
+impl<C: CommandGroup, H: CommandHandler<C>> Group for Entry<C, H> {
+    fn guid(&self) -> Option<GUID> {
+        C::GROUP
+    }
+
+    fn status(&self, id: u32) -> CommandState {
+        match C::from_id(id) {
+            Some(command) => self.handler.status(command),
+            None => CommandState::unsupported(),
+        }
+    }
+
+    fn exec(
+        &self,
+        id: u32,
+        option: ExecOption,
+        input: Option<&Variant>,
+    ) -> Result<Option<Variant>, HRESULT> {
+        let command = C::from_id(id).ok_or(OLECMDERR_E_NOTSUPPORTED)?;
+        let state = self.handler.status(command);
+        if !state.supported {
+            return Err(OLECMDERR_E_NOTSUPPORTED);
+        }
+        if !state.enabled {
+            return Err(OLECMDERR_E_DISABLED);
+        }
+        self.handler.exec(command, option, input)
+    }
+}
+
+#[derive(Default)]
+pub struct CommandTableBuilder {
+    groups: Vec<Box<dyn Group>>,
+}
+
+impl CommandTableBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a group. If the same group is added twice, the first handler wins.
+    pub fn group<C: CommandGroup>(mut self, handler: impl CommandHandler<C> + 'static) -> Self {
+        self.groups.push(Box::new(Entry {
+            handler,
+            _group: std::marker::PhantomData::<C>,
+        }));
+        self
+    }
+
+    pub fn build(self) -> ComPtr<IOleCommandTarget> {
+        let ptr = CommandTable::create_raw(self.groups);
+        let ptr = ptr as *mut IOleCommandTarget;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct CommandTable {
+    vtbl: VTable<IOleCommandTargetVtbl>,
+    refcount: Refcount,
+    groups: Vec<Box<dyn Group>>,
+}
+
+impl CommandTable {
+    unsafe fn find(&self, group: *const GUID) -> Option<&dyn Group> {
+        let group = if group.is_null() { None } else { Some(&*group) };
+        self.groups
+            .iter()
+            .find(|g| match (g.guid(), group) {
+                (Some(a), Some(b)) => IsEqualGUID(&a, b),
+                (None, None) => true,
+                _ => false,
+            })
+            .map(|g| &**g)
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IOleCommandTarget for CommandTable {
+    #[panic(result = "E_FAIL")]
+    unsafe fn query_status(
+        &self,
+        group: *const GUID,
+        count: ULONG,
+        commands: *mut OLECMD,
+        text: *mut OLECMDTEXT,
+    ) -> HRESULT {
+        if commands.is_null() && count > 0 {
+            return E_POINTER;
+        }
+        let group = match self.find(group) {
+            Some(group) => group,
+            None => return OLECMDERR_E_UNKNOWNGROUP,
+        };
+
+        let commands = std::slice::from_raw_parts_mut(commands, count as usize);
+        for command in commands {
+            command.cmdf = group.status(command.cmdID).flags();
+        }
+
+        // No command names or status text are provided
+        if !text.is_null() {
+            (*text).cwActual = 0;
+            if (*text).cwBuf > 0 {
+                (*text).rgwz[0] = 0;
+            }
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn exec(
+        &self,
+        group: *const GUID,
+        id: DWORD,
+        option: DWORD,
+        input: *mut VARIANT,
+        output: *mut VARIANT,
+    ) -> HRESULT {
+        let group = match self.find(group) {
+            Some(group) => group,
+            None => return OLECMDERR_E_UNKNOWNGROUP,
+        };
+
+        // The high word is used by some commands to carry extra information
+        let option = ExecOption::from(LOWORD(option) as u32);
+        let input = if input.is_null() {
+            None
+        } else {
+            Some(Variant::from_raw_ref(&*input))
+        };
+
+        match group.exec(id, option, input) {
+            Ok(value) => {
+                if let (Some(value), false) = (value, output.is_null()) {
+                    VariantClear(output);
+                    *output = value.into_raw();
+                }
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Conversion between Direct2D geometry sinks and plain Rust path data.
+//!
+//! [`GeometryCollector`] is an `ID2D1SimplifiedGeometrySink` that records everything written
+//! to it as a [`Geometry`], which is handy for pulling glyph outlines out of
+//! `IDWriteFontFace::GetGlyphRunOutline` or flattening an `ID2D1Geometry` with `Simplify`.
+//! [`Geometry::replay`] goes the other way and writes recorded data into any sink.
+
+use std::sync::{Arc, Mutex};
+
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::d2d1::{
+    ID2D1SimplifiedGeometrySink, ID2D1SimplifiedGeometrySinkVtbl, D2D1_BEZIER_SEGMENT,
+    D2D1_FIGURE_BEGIN, D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_BEGIN_HOLLOW, D2D1_FIGURE_END,
+    D2D1_FIGURE_END_CLOSED, D2D1_FIGURE_END_OPEN, D2D1_FILL_MODE, D2D1_FILL_MODE_ALTERNATE,
+    D2D1_FILL_MODE_WINDING, D2D1_PATH_SEGMENT, D2D1_POINT_2F,
+};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl From<D2D1_POINT_2F> for Point {
+    fn from(p: D2D1_POINT_2F) -> Point {
+        Point { x: p.x, y: p.y }
+    }
+}
+
+impl From<Point> for D2D1_POINT_2F {
+    fn from(p: Point) -> D2D1_POINT_2F {
+        D2D1_POINT_2F { x: p.x, y: p.y }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Segment {
+    Line(Point),
+    /// Cubic bezier with two control points and an end point.
+    Bezier(Point, Point, Point),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Figure {
+    pub start: Point,
+    pub filled: bool,
+    pub closed: bool,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum FillMode {
+    #[default]
+    Alternate,
+    Winding,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// A sequence of figures as written to an `ID2D1SimplifiedGeometrySink`. Segment flags are not
+/// recorded.
+pub struct Geometry {
+    pub fill_mode: FillMode,
+    pub figures: Vec<Figure>,
+}
+
+impl Geometry {
+    /// Writes every figure to `sink`. `Close` is not called, so more data can be added to the
+    /// sink afterwards.
+    ///
+    /// # Safety
+    ///
+    /// `sink` must be open and usable from the current thread.
+    pub unsafe fn replay(&self, sink: &ID2D1SimplifiedGeometrySink) {
+        sink.SetFillMode(match self.fill_mode {
+            FillMode::Alternate => D2D1_FILL_MODE_ALTERNATE,
+            FillMode::Winding => D2D1_FILL_MODE_WINDING,
+        });
+
+        for figure in &self.figures {
+            let begin = if figure.filled {
+                D2D1_FIGURE_BEGIN_FILLED
+            } else {
+                D2D1_FIGURE_BEGIN_HOLLOW
+            };
+            sink.BeginFigure(figure.start.into(), begin);
+
+            // Batch runs of the same segment type into a single call.
+            let mut lines: Vec<D2D1_POINT_2F> = Vec::new();
+            let mut beziers: Vec<D2D1_BEZIER_SEGMENT> = Vec::new();
+            for segment in &figure.segments {
+                match *segment {
+                    Segment::Line(to) => {
+                        flush_beziers(sink, &mut beziers);
+                        lines.push(to.into());
+                    }
+                    Segment::Bezier(c1, c2, to) => {
+                        flush_lines(sink, &mut lines);
+                        beziers.push(D2D1_BEZIER_SEGMENT {
+                            point1: c1.into(),
+                            point2: c2.into(),
+                            point3: to.into(),
+                        });
+                    }
+                }
+            }
+            flush_lines(sink, &mut lines);
+            flush_beziers(sink, &mut beziers);
+
+            sink.EndFigure(if figure.closed {
+                D2D1_FIGURE_END_CLOSED
+            } else {
+                D2D1_FIGURE_END_OPEN
+            });
+        }
+    }
+}
+
+unsafe fn flush_lines(sink: &ID2D1SimplifiedGeometrySink, lines: &mut Vec<D2D1_POINT_2F>) {
+    if !lines.is_empty() {
+        sink.AddLines(lines.as_ptr(), lines.len() as u32);
+        lines.clear();
+    }
+}
+
+unsafe fn flush_beziers(
+    sink: &ID2D1SimplifiedGeometrySink,
+    beziers: &mut Vec<D2D1_BEZIER_SEGMENT>,
+) {
+    if !beziers.is_empty() {
+        sink.AddBeziers(beziers.as_ptr(), beziers.len() as u32);
+        beziers.clear();
+    }
+}
+
+/// Records the data written to an `ID2D1SimplifiedGeometrySink` as a [`Geometry`].
+pub struct GeometryCollector {
+    sink: ComPtr<ID2D1SimplifiedGeometrySink>,
+    geometry: Arc<Mutex<Geometry>>,
+}
+
+impl GeometryCollector {
+    pub fn new() -> GeometryCollector {
+        let geometry = Arc::new(Mutex::new(Geometry::default()));
+        let ptr = CollectorSink::create_raw(geometry.clone());
+        let ptr = ptr as *mut ID2D1SimplifiedGeometrySink;
+        let sink = unsafe { ComPtr::from_raw(ptr) };
+
+        GeometryCollector { sink, geometry }
+    }
+
+    /// The sink to hand to Direct2D or DirectWrite.
+    pub fn sink(&self) -> &ComPtr<ID2D1SimplifiedGeometrySink> {
+        &self.sink
+    }
+
+    /// Everything recorded so far.
+    pub fn geometry(&self) -> Geometry {
+        self.geometry.lock().unwrap().clone()
+    }
+}
+
+impl Default for GeometryCollector {
+    fn default() -> Self {
+        GeometryCollector::new()
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct CollectorSink {
+    vtbl: VTable<ID2D1SimplifiedGeometrySinkVtbl>,
+    refcount: Refcount,
+    geometry: Arc<Mutex<Geometry>>,
+}
+
+impl CollectorSink {
+    fn with_figure(&self, f: impl FnOnce(&mut Figure)) {
+        let mut geometry = self.geometry.lock().unwrap();
+        if let Some(figure) = geometry.figures.last_mut() {
+            f(figure);
+        }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl ID2D1SimplifiedGeometrySink for CollectorSink {
+    #[panic(abort)]
+    fn set_fill_mode(&self, mode: D2D1_FILL_MODE) {
+        self.geometry.lock().unwrap().fill_mode = match mode {
+            D2D1_FILL_MODE_WINDING => FillMode::Winding,
+            _ => FillMode::Alternate,
+        };
+    }
+
+    fn set_segment_flags(&self, _flags: D2D1_PATH_SEGMENT) {
+        // Not recorded
+    }
+
+    #[panic(abort)]
+    fn begin_figure(&self, start: D2D1_POINT_2F, begin: D2D1_FIGURE_BEGIN) {
+        self.geometry.lock().unwrap().figures.push(Figure {
+            start: start.into(),
+            filled: begin == D2D1_FIGURE_BEGIN_FILLED,
+            closed: false,
+            segments: Vec::new(),
+        });
+    }
+
+    #[panic(abort)]
+    unsafe fn add_lines(&self, points: *const D2D1_POINT_2F, count: u32) {
+        if points.is_null() {
+            return;
+        }
+        let points = std::slice::from_raw_parts(points, count as usize);
+        self.with_figure(|figure| {
+            let lines = points.iter().map(|&p| Segment::Line(p.into()));
+            figure.segments.extend(lines);
+        });
+    }
+
+    #[panic(abort)]
+    unsafe fn add_beziers(&self, beziers: *const D2D1_BEZIER_SEGMENT, count: u32) {
+        if beziers.is_null() {
+            return;
+        }
+        let beziers = std::slice::from_raw_parts(beziers, count as usize);
+        self.with_figure(|figure| {
+            let beziers = beziers
+                .iter()
+                .map(|b| Segment::Bezier(b.point1.into(), b.point2.into(), b.point3.into()));
+            figure.segments.extend(beziers);
+        });
+    }
+
+    #[panic(abort)]
+    fn end_figure(&self, end: D2D1_FIGURE_END) {
+        self.with_figure(|figure| figure.closed = end == D2D1_FIGURE_END_CLOSED);
+    }
+
+    fn close(&self) -> HRESULT {
+        S_OK
+    }
+}
@@ -0,0 +1,395 @@
+//! `INotifyPropertyChanged` for Rust view-models bound from XAML, e.g. in a XAML island.
+//!
+//! ```no_run
+//! use com_impl::data_binding::{PropertyNotifier, XamlTypes};
+//!
+//! # let types: XamlTypes = unimplemented!();
+//! let notifier = PropertyNotifier::new(types);
+//!
+//! // Hand notifier.as_inspectable() to XAML as the DataContext, or answer QueryInterface
+//! // for INotifyPropertyChanged with it.
+//!
+//! // Later, whenever a bound value changes:
+//! notifier.raise_property_changed("Title").unwrap();
+//! ```
+//!
+//! Both system XAML (`Windows.UI.Xaml.Data`) and WinUI 3 (`Microsoft.UI.Xaml.Data`) define
+//! `INotifyPropertyChanged` and `PropertyChangedEventArgs`, with different IIDs. winapi covers
+//! neither, so the IIDs are passed in through [`XamlTypes`]. Take them from the
+//! `windows.ui.xaml.data.h` or `microsoft.ui.xaml.data.h` header of the XAML in use.
+//!
+//! XAML only adds and removes handlers on its UI thread, and the notifier is not `Send`, so
+//! properties have to be raised from that thread as well.
+
+#![allow(non_snake_case)]
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID};
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_NOINTERFACE, E_OUTOFMEMORY, E_POINTER, HRESULT, S_OK};
+use winapi::um::combaseapi::CoTaskMemAlloc;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::winrt::hstring::HSTRING;
+use winapi::winrt::inspectable::{IInspectable, IInspectableVtbl, TrustLevel};
+use winapi::winrt::roapi::RoGetActivationFactory;
+use winapi::winrt::winstring::{WindowsCreateString, WindowsDeleteString};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+#[derive(Copy, Clone)]
+/// Identifies the XAML flavour whose data binding interfaces are implemented.
+pub struct XamlTypes {
+    /// `IID_INotifyPropertyChanged`
+    pub notify_property_changed: IID,
+    /// `IID_IPropertyChangedEventArgsFactory`
+    pub event_args_factory: IID,
+    /// The runtime class name of `PropertyChangedEventArgs`, e.g.
+    /// `"Windows.UI.Xaml.Data.PropertyChangedEventArgs"`.
+    pub event_args_class: &'static str,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventRegistrationToken {
+    pub value: i64,
+}
+
+#[repr(C)]
+pub struct INotifyPropertyChangedVtbl {
+    pub parent: IInspectableVtbl,
+    pub add_PropertyChanged: unsafe extern "system" fn(
+        this: *mut IInspectable,
+        handler: *mut IUnknown,
+        token: *mut EventRegistrationToken,
+    ) -> HRESULT,
+    pub remove_PropertyChanged: unsafe extern "system" fn(
+        this: *mut IInspectable,
+        token: EventRegistrationToken,
+    ) -> HRESULT,
+}
+
+#[repr(C)]
+struct PropertyChangedEventHandlerVtbl {
+    parent: IUnknownVtbl,
+    Invoke: unsafe extern "system" fn(
+        this: *mut IUnknown,
+        sender: *mut IInspectable,
+        args: *mut IInspectable,
+    ) -> HRESULT,
+}
+
+#[repr(C)]
+struct IPropertyChangedEventArgsFactoryVtbl {
+    parent: IInspectableVtbl,
+    CreateInstance: unsafe extern "system" fn(
+        this: *mut IInspectable,
+        name: HSTRING,
+        outer: *mut IInspectable,
+        inner: *mut *mut IInspectable,
+        value: *mut *mut IInspectable,
+    ) -> HRESULT,
+}
+
+/// An owned `HSTRING`.
+struct HString(HSTRING);
+
+impl HString {
+    fn new(s: &str) -> Result<HString, HRESULT> {
+        let wide: Vec<u16> = s.encode_utf16().collect();
+        let mut hstring = ptr::null_mut();
+        let hr = unsafe { WindowsCreateString(wide.as_ptr(), wide.len() as u32, &mut hstring) };
+        if hr < 0 {
+            return Err(hr);
+        }
+        Ok(HString(hstring))
+    }
+}
+
+impl Drop for HString {
+    fn drop(&mut self) {
+        unsafe {
+            WindowsDeleteString(self.0);
+        }
+    }
+}
+
+/// COM object implementing `INotifyPropertyChanged` for the IID given in [`XamlTypes`].
+///
+/// Not derived, because the interface is only known at runtime.
+#[repr(C)]
+struct Notifier {
+    vtbl: VTable<INotifyPropertyChangedVtbl>,
+    refcount: Refcount,
+    types: XamlTypes,
+    handlers: RefCell<Vec<(EventRegistrationToken, ComPtr<IUnknown>)>>,
+    next_token: Cell<i64>,
+    factory: RefCell<Option<ComPtr<IInspectable>>>,
+}
+
+impl Notifier {
+    const VTBL: INotifyPropertyChangedVtbl = INotifyPropertyChangedVtbl {
+        parent: IInspectableVtbl {
+            parent: IUnknownVtbl {
+                QueryInterface: Self::query_interface,
+                AddRef: Self::add_ref,
+                Release: Self::release,
+            },
+            GetIids: Self::get_iids,
+            GetRuntimeClassName: Self::get_runtime_class_name,
+            GetTrustLevel: Self::get_trust_level,
+        },
+        add_PropertyChanged: Self::add_property_changed,
+        remove_PropertyChanged: Self::remove_property_changed,
+    };
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof())
+            || IsEqualIID(riid, &IInspectable::uuidof())
+            || IsEqualIID(riid, &that.types.notify_property_changed)
+        {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            S_OK
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn get_iids(
+        this: *mut IInspectable,
+        count: *mut ULONG,
+        iids: *mut *mut IID,
+    ) -> HRESULT {
+        if count.is_null() || iids.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let array = CoTaskMemAlloc(mem::size_of::<IID>()) as *mut IID;
+        if array.is_null() {
+            return E_OUTOFMEMORY;
+        }
+        *array = that.types.notify_property_changed;
+        *count = 1;
+        *iids = array;
+        S_OK
+    }
+
+    unsafe extern "system" fn get_runtime_class_name(
+        _this: *mut IInspectable,
+        name: *mut HSTRING,
+    ) -> HRESULT {
+        if name.is_null() {
+            return E_POINTER;
+        }
+        // Not a projected runtime class
+        *name = ptr::null_mut();
+        S_OK
+    }
+
+    unsafe extern "system" fn get_trust_level(
+        _this: *mut IInspectable,
+        level: *mut TrustLevel,
+    ) -> HRESULT {
+        if level.is_null() {
+            return E_POINTER;
+        }
+        *level = winapi::winrt::inspectable::BaseTrust;
+        S_OK
+    }
+
+    unsafe extern "system" fn add_property_changed(
+        this: *mut IInspectable,
+        handler: *mut IUnknown,
+        token: *mut EventRegistrationToken,
+    ) -> HRESULT {
+        if handler.is_null() || token.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let value = that.next_token.get() + 1;
+        that.next_token.set(value);
+
+        (*handler).AddRef();
+        let handler = ComPtr::from_raw(handler);
+        *token = EventRegistrationToken { value };
+        that.handlers.borrow_mut().push((*token, handler));
+        S_OK
+    }
+
+    unsafe extern "system" fn remove_property_changed(
+        this: *mut IInspectable,
+        token: EventRegistrationToken,
+    ) -> HRESULT {
+        let that = &*(this as *const Self);
+        // Removing an unknown token is not an error
+        let removed = {
+            let mut handlers = that.handlers.borrow_mut();
+            let index = handlers.iter().position(|(t, _)| *t == token);
+            index.map(|i| handlers.remove(i))
+        };
+        drop(removed);
+        S_OK
+    }
+
+    fn event_args(&self, name: &str) -> Result<ComPtr<IInspectable>, HRESULT> {
+        let factory = self.factory()?;
+        let name = HString::new(name)?;
+        unsafe {
+            let vtbl = (*factory.as_raw()).lpVtbl as *const IPropertyChangedEventArgsFactoryVtbl;
+            let mut inner = ptr::null_mut();
+            let mut args = ptr::null_mut();
+            let hr = ((*vtbl).CreateInstance)(
+                factory.as_raw(),
+                name.0,
+                ptr::null_mut(),
+                &mut inner,
+                &mut args,
+            );
+            if !inner.is_null() {
+                (*inner).Release();
+            }
+            if hr < 0 {
+                return Err(hr);
+            }
+            Ok(ComPtr::from_raw(args))
+        }
+    }
+
+    fn factory(&self) -> Result<ComPtr<IInspectable>, HRESULT> {
+        if let Some(factory) = &*self.factory.borrow() {
+            return Ok(factory.clone());
+        }
+
+        let class = HString::new(self.types.event_args_class)?;
+        let mut factory = ptr::null_mut();
+        let hr = unsafe {
+            RoGetActivationFactory(class.0, &self.types.event_args_factory, &mut factory)
+        };
+        if hr < 0 {
+            return Err(hr);
+        }
+        let factory = unsafe { ComPtr::from_raw(factory as *mut IInspectable) };
+        *self.factory.borrow_mut() = Some(factory.clone());
+        Ok(factory)
+    }
+}
+
+/// The Rust side of an `INotifyPropertyChanged` object.
+pub struct PropertyNotifier {
+    object: *mut Notifier,
+}
+
+impl PropertyNotifier {
+    pub fn new(types: XamlTypes) -> PropertyNotifier {
+        let object = Box::into_raw(Box::new(Notifier {
+            vtbl: VTable::new(&Notifier::VTBL),
+            refcount: Default::default(),
+            types,
+            handlers: RefCell::new(Vec::new()),
+            next_token: Cell::new(0),
+            factory: RefCell::new(None),
+        }));
+        PropertyNotifier { object }
+    }
+
+    fn notifier(&self) -> &Notifier {
+        unsafe { &*self.object }
+    }
+
+    /// A new reference to the COM object, which answers `QueryInterface` for
+    /// `INotifyPropertyChanged`.
+    pub fn as_inspectable(&self) -> ComPtr<IInspectable> {
+        unsafe {
+            let ptr = self.object as *mut IInspectable;
+            (*ptr).AddRef();
+            ComPtr::from_raw(ptr)
+        }
+    }
+
+    /// Whether anything is listening, to skip work for unbound properties.
+    pub fn has_handlers(&self) -> bool {
+        !self.notifier().handlers.borrow().is_empty()
+    }
+
+    /// Tells every registered handler that `name` changed. An empty name means all
+    /// properties changed.
+    ///
+    /// Every handler is called even if some fail; the first failure is returned.
+    pub fn raise_property_changed(&self, name: &str) -> Result<(), HRESULT> {
+        let notifier = self.notifier();
+        // Handlers may add or remove handlers while being called
+        let handlers: Vec<_> = notifier
+            .handlers
+            .borrow()
+            .iter()
+            .map(|(_, h)| h.clone())
+            .collect();
+        if handlers.is_empty() {
+            return Ok(());
+        }
+
+        let args = notifier.event_args(name)?;
+        let sender = self.object as *mut IInspectable;
+        let mut result = Ok(());
+        for handler in handlers {
+            let hr = unsafe {
+                let vtbl = (*handler.as_raw()).lpVtbl as *const PropertyChangedEventHandlerVtbl;
+                ((*vtbl).Invoke)(handler.as_raw(), sender, args.as_raw())
+            };
+            if hr < 0 && result.is_ok() {
+                result = Err(hr);
+            }
+        }
+        result
+    }
+}
+
+impl Clone for PropertyNotifier {
+    fn clone(&self) -> Self {
+        unsafe {
+            self.notifier().refcount.add_ref();
+        }
+        PropertyNotifier {
+            object: self.object,
+        }
+    }
+}
+
+impl Drop for PropertyNotifier {
+    fn drop(&mut self) {
+        unsafe {
+            Notifier::release(self.object as *mut IUnknown);
+        }
+    }
+}
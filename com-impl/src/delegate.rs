@@ -0,0 +1,73 @@
+//! Interfaces answered by another object, held in a member.
+//!
+//! `#[delegate(IDWriteFontFileStream = stream)]` on a `#[derive(ComImpl)]` struct makes
+//! `QueryInterface` answer `IDWriteFontFileStream` with the object in the member `stream`,
+//! adding a reference to it. The member implements [`Delegate`] for the interface, as
+//! `ComPtr<I>` and `Option<ComPtr<I>>` do; while it holds nothing, the IID isn't answered.
+//!
+//! The delegated object keeps its own identity, so querying it doesn't lead back to the object
+//! delegating to it. Use aggregation instead where clients rely on that.
+//!
+//! ```no_run
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::dwrite::IDWriteFontFileStream;
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//! use wio::com::ComPtr;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[delegate(IDWriteFontFileStream = stream)]
+//! pub struct Font {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     stream: ComPtr<IDWriteFontFileStream>,
+//! }
+//! ```
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+/// Implemented by a member holding the object an interface `I` is delegated to.
+///
+/// # Safety
+///
+/// `delegate` must return null or a live `I`, which QueryInterface hands out in place of the
+/// object's own pointer.
+pub unsafe trait Delegate<I> {
+    /// The object answering `I`, or null while there is none. It must stay alive while the
+    /// member does.
+    fn delegate(&self) -> *mut I;
+}
+
+unsafe impl<I: Interface> Delegate<I> for ComPtr<I> {
+    fn delegate(&self) -> *mut I {
+        self.as_raw()
+    }
+}
+
+unsafe impl<I: Interface> Delegate<I> for Option<ComPtr<I>> {
+    fn delegate(&self) -> *mut I {
+        match self {
+            Some(ptr) => ptr.as_raw(),
+            None => std::ptr::null_mut(),
+        }
+    }
+}
+
+/// The object `member` answers `I` with, with a reference added, if `riid` is `I`'s IID and
+/// there is one. Called by the derived `QueryInterface`.
+#[doc(hidden)]
+pub fn query<I: Interface, D: Delegate<I>>(member: &D, riid: &IID) -> Option<*mut c_void> {
+    if !IsEqualIID(riid, &I::uuidof()) {
+        return None;
+    }
+    let ptr = member.delegate();
+    if ptr.is_null() {
+        return None;
+    }
+    unsafe { (*(ptr as *mut IUnknown)).AddRef() };
+    Some(ptr as *mut c_void)
+}
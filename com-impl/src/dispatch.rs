@@ -0,0 +1,706 @@
+//! Late-bound `IDispatch`, for scripting hosts and other automation clients.
+//!
+//! `#[com_impl(dispatch)]` on an inherent `impl` block implements [`Dispatch`] for the type
+//! from the methods marked `#[dispid(n)]`, along with the `IDispatch` vtable. VBA, JScript and
+//! PowerShell can then call them by name on an object whose primary vtable is a
+//! `VTable<IDispatchVtbl>`. Names are the methods' names in PascalCase, or their
+//! `#[com_name = ...]`, and are matched without regard to case.
+//!
+//! Arguments are converted with [`FromVariant`], which coerces the caller's values like
+//! `VariantChangeType` does, so e.g. a script's string `"3"` is accepted for an `i32`.
+//! Parameters of type `Option<T>` may be left out. Results are converted with
+//! [`IntoVariant`]; methods may return a `Result<T, HRESULT>` to fail the call.
+//!
+//! A method without parameters also answers property reads. `#[dispid(n, propput)]` marks
+//! the method assigning the property `n`, which takes the new value as its last parameter.
+//! Its name defaults to the method's without a `set_` prefix.
+//!
+//! For a dual interface, deriving from `IDispatch`, `#[com_impl(dispatch)]` goes on its
+//! `unsafe impl` instead, with `#[dispid(n)]` on the methods scripts may call. `Invoke` calls
+//! the same vtable entries early-bound callers do, converting arguments to the
+//! [`AutomationType`]s they take. A trailing `*mut T` parameter, other than an interface
+//! pointer, is the `[out, retval]`. Names are those of the vtable entries without the `get_`,
+//! `put_` or `putref_` of property accessors, so `get_Name` and `put_Name` are `Name`.
+//!
+//! `GetTypeInfo` describes the members with [`type_info`], for clients that list them, such
+//! as editors completing member names. Each parameter and result is given the `VARTYPE` its
+//! Rust type converts through, or `VT_VARIANT` for a [`Variant`].
+//!
+//! ```no_run
+//! use com_impl::{Refcount, VTable};
+//! use std::sync::atomic::{AtomicI32, Ordering};
+//! use winapi::shared::winerror::{E_INVALIDARG, HRESULT};
+//! use winapi::um::oaidl::{IDispatch, IDispatchVtbl};
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[interfaces(IDispatch)]
+//! pub struct Calculator {
+//!     vtbl: VTable<IDispatchVtbl>,
+//!     refcount: Refcount,
+//!     memory: AtomicI32,
+//! }
+//!
+//! #[com_impl::com_impl(dispatch)]
+//! impl Calculator {
+//!     #[dispid(1)]
+//!     fn add(&self, a: i32, b: Option<i32>) -> i32 {
+//!         a + b.unwrap_or(1)
+//!     }
+//!
+//!     #[dispid(2)]
+//!     fn divide(&self, a: f64, b: f64) -> Result<f64, HRESULT> {
+//!         if b == 0.0 {
+//!             return Err(E_INVALIDARG);
+//!         }
+//!         Ok(a / b)
+//!     }
+//!
+//!     #[dispid(3)]
+//!     fn memory(&self) -> i32 {
+//!         self.memory.load(Ordering::Relaxed)
+//!     }
+//!
+//!     #[dispid(3, propput)]
+//!     fn set_memory(&self, value: i32) {
+//!         self.memory.store(value, Ordering::Relaxed)
+//!     }
+//! }
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use winapi::shared::guiddef::{IsEqualIID, IID_NULL, REFIID};
+use winapi::shared::minwindef::{UINT, WORD};
+use winapi::shared::winerror::{
+    DISP_E_BADINDEX, DISP_E_BADPARAMCOUNT, DISP_E_MEMBERNOTFOUND, DISP_E_PARAMNOTFOUND,
+    DISP_E_TYPEMISMATCH, DISP_E_UNKNOWNINTERFACE, DISP_E_UNKNOWNNAME, E_INVALIDARG, E_POINTER,
+    FAILED, HRESULT, SUCCEEDED, S_OK,
+};
+use winapi::shared::wtypes::{
+    BSTR, VARTYPE, VT_BOOL, VT_BSTR, VT_DISPATCH, VT_ERROR, VT_I2, VT_I4, VT_I8, VT_R4, VT_R8,
+    VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UNKNOWN, VT_VARIANT, VT_VOID,
+};
+use winapi::shared::wtypesbase::LPOLESTR;
+use winapi::um::oaidl::{
+    IDispatch, ITypeInfo, CC_STDCALL, DISPID, DISPID_PROPERTYPUT, DISPID_UNKNOWN, DISPPARAMS,
+    EXCEPINFO, VARIANT,
+};
+use winapi::um::oleauto::{
+    VariantChangeType, DISPATCH_METHOD, DISPATCH_PROPERTYPUT, DISPATCH_PROPERTYPUTREF,
+};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::LCID;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::variant::Variant;
+
+/// Implemented by `#[com_impl(dispatch)]`, describing the members `IDispatch` exposes.
+///
+/// # Safety
+///
+/// `MEMBERS` must describe the parameters `invoke` reads for each member, as `IDispatch`
+/// hands them out through `GetIDsOfNames` and `GetTypeInfo`.
+pub unsafe trait Dispatch {
+    const MEMBERS: &'static [Member];
+
+    /// Calls `MEMBERS[index]` with `args`.
+    ///
+    /// # Safety
+    ///
+    /// `index` must be below `MEMBERS.len()`, and `args` the arguments of an `Invoke` call
+    /// for that member, whose raw pointers the member's method may dereference.
+    unsafe fn invoke(&self, index: usize, args: &Args) -> Result<Variant, HRESULT>;
+}
+
+#[derive(Copy, Clone, Debug)]
+/// A method or property of a [`Dispatch`] type.
+pub struct Member {
+    pub name: &'static str,
+    pub dispid: DISPID,
+    pub kind: MemberKind,
+    pub params: &'static [Param],
+    /// The type of the result, `VT_VOID` if there is none.
+    pub ret: VARTYPE,
+}
+
+#[derive(Copy, Clone, Debug)]
+/// A parameter of a [`Member`].
+pub struct Param {
+    /// The name callers may pass the argument by.
+    pub name: &'static str,
+    pub vt: VARTYPE,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemberKind {
+    /// Called for `DISPATCH_METHOD` and `DISPATCH_PROPERTYGET`.
+    Method,
+    /// Called for `DISPATCH_PROPERTYPUT` and `DISPATCH_PROPERTYPUTREF`.
+    PropertyPut,
+}
+
+/// The arguments of an `Invoke` call, by parameter.
+pub struct Args<'a> {
+    params: &'a DISPPARAMS,
+    /// The index into `rgvarg` of each parameter that was passed.
+    slots: Vec<Option<usize>>,
+    arg_err: Cell<Option<usize>>,
+    /// Arguments converted for `get_raw`, kept until the call returns.
+    converted: RefCell<Vec<Variant>>,
+}
+
+impl<'a> Args<'a> {
+    unsafe fn new(params: &'a DISPPARAMS, member: &Member) -> Result<Args<'a>, HRESULT> {
+        let count = params.cArgs as usize;
+        let named = params.cNamedArgs as usize;
+        if named > count {
+            return Err(E_INVALIDARG);
+        }
+
+        let mut slots = vec![None; member.params.len()];
+        for i in 0..named {
+            let dispid = *params.rgdispidNamedArgs.add(i);
+            let param = if dispid == DISPID_PROPERTYPUT && member.kind == MemberKind::PropertyPut {
+                slots.len().checked_sub(1)
+            } else if dispid >= 0 && (dispid as usize) < slots.len() {
+                Some(dispid as usize)
+            } else {
+                None
+            };
+            match param {
+                Some(param) if slots[param].is_none() => slots[param] = Some(i),
+                _ => return Err(DISP_E_PARAMNOTFOUND),
+            }
+        }
+        // Positional arguments are stored last to first, after the named ones
+        for param in 0..count - named {
+            match slots.get_mut(param) {
+                Some(slot @ None) => *slot = Some(count - 1 - param),
+                Some(Some(_)) => return Err(DISP_E_PARAMNOTFOUND),
+                None => return Err(DISP_E_BADPARAMCOUNT),
+            }
+        }
+
+        Ok(Args {
+            params,
+            slots,
+            arg_err: Cell::new(None),
+            converted: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// The argument for parameter `index`, or `None` if the caller left it out.
+    pub fn raw(&self, index: usize) -> Option<&Variant> {
+        let slot = self.slots.get(index).cloned().unwrap_or(None)?;
+        let value = Variant::from_raw_ref(unsafe { &*self.params.rgvarg.add(slot) });
+        // How VB passes an optional argument it was not given
+        let omitted = value.vt() == VT_ERROR as VARTYPE
+            && unsafe { *value.as_raw().n1.n2().n3.scode() } == DISP_E_PARAMNOTFOUND;
+        if omitted {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Converts the argument for parameter `index`.
+    pub fn get<T: FromVariant>(&self, index: usize) -> Result<T, HRESULT> {
+        match self.raw(index) {
+            Some(value) => T::from_variant(value).ok_or_else(|| {
+                self.arg_err.set(self.slots[index]);
+                DISP_E_TYPEMISMATCH
+            }),
+            None => T::missing().ok_or(DISP_E_PARAMNOTFOUND),
+        }
+    }
+
+    /// Converts the argument for parameter `index` to the type a dual interface's method
+    /// takes it as. Values such as a `BSTR` stay valid until the call returns.
+    pub fn get_raw<T: AutomationType>(&self, index: usize) -> Result<T, HRESULT> {
+        let value = self.raw(index).ok_or(DISP_E_PARAMNOTFOUND)?;
+        if T::VT == VT_VARIANT as VARTYPE || T::VT == value.vt() {
+            return Ok(unsafe { T::read(value.as_raw()) });
+        }
+        match coerce(value, T::VT as u32) {
+            Some(converted) => {
+                let mut kept = self.converted.borrow_mut();
+                kept.push(converted);
+                Ok(unsafe { T::read(kept[kept.len() - 1].as_raw()) })
+            }
+            None => {
+                self.arg_err.set(self.slots[index]);
+                Err(DISP_E_TYPEMISMATCH)
+            }
+        }
+    }
+}
+
+/// A parameter type of a dispatch method.
+pub trait FromVariant: Sized {
+    /// The type the parameter is described with in the type information.
+    const VT: VARTYPE = VT_VARIANT as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<Self>;
+
+    /// The value of the parameter when the caller leaves it out, if it may.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+fn coerce(value: &Variant, vt: u32) -> Option<Variant> {
+    let mut result = Variant::new();
+    let hr = unsafe { VariantChangeType(result.as_mut_ptr(), value.as_raw(), 0, vt as VARTYPE) };
+    if SUCCEEDED(hr) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+impl FromVariant for bool {
+    const VT: VARTYPE = VT_BOOL as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<bool> {
+        coerce(value, VT_BOOL)?.to_bool()
+    }
+}
+
+impl FromVariant for i32 {
+    const VT: VARTYPE = VT_I4 as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<i32> {
+        coerce(value, VT_I4)?.to_i32()
+    }
+}
+
+impl FromVariant for f64 {
+    const VT: VARTYPE = VT_R8 as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<f64> {
+        coerce(value, VT_R8)?.to_f64()
+    }
+}
+
+impl FromVariant for String {
+    const VT: VARTYPE = VT_BSTR as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<String> {
+        coerce(value, VT_BSTR)?.to_string()
+    }
+}
+
+/// Objects, including `IDispatch` ones.
+impl FromVariant for ComPtr<IUnknown> {
+    const VT: VARTYPE = VT_UNKNOWN as VARTYPE;
+
+    fn from_variant(value: &Variant) -> Option<ComPtr<IUnknown>> {
+        coerce(value, VT_UNKNOWN)?.to_unknown()
+    }
+}
+
+/// The argument as passed.
+impl FromVariant for Variant {
+    fn from_variant(value: &Variant) -> Option<Variant> {
+        Some(value.clone())
+    }
+}
+
+impl<T: FromVariant> FromVariant for Option<T> {
+    const VT: VARTYPE = T::VT;
+
+    fn from_variant(value: &Variant) -> Option<Option<T>> {
+        T::from_variant(value).map(Some)
+    }
+
+    fn missing() -> Option<Option<T>> {
+        Some(None)
+    }
+}
+
+/// A return type of a dispatch method.
+pub trait IntoVariant {
+    /// The type the result is described with in the type information.
+    const VT: VARTYPE = VT_VARIANT as VARTYPE;
+
+    fn into_variant(self) -> Result<Variant, HRESULT>;
+}
+
+macro_rules! into_variant {
+    ($($ty:ty => $vt:ident),*) => {$(
+        impl IntoVariant for $ty {
+            const VT: VARTYPE = $vt as VARTYPE;
+
+            fn into_variant(self) -> Result<Variant, HRESULT> {
+                Ok(Variant::from(self))
+            }
+        }
+    )*};
+}
+
+into_variant!(bool => VT_BOOL, i32 => VT_I4, f64 => VT_R8, String => VT_BSTR, &'static str => VT_BSTR);
+
+/// `VT_EMPTY`.
+impl IntoVariant for () {
+    const VT: VARTYPE = VT_VOID as VARTYPE;
+
+    fn into_variant(self) -> Result<Variant, HRESULT> {
+        Ok(Variant::new())
+    }
+}
+
+impl IntoVariant for Variant {
+    fn into_variant(self) -> Result<Variant, HRESULT> {
+        Ok(self)
+    }
+}
+
+impl<I: Interface> IntoVariant for ComPtr<I> {
+    const VT: VARTYPE = VT_UNKNOWN as VARTYPE;
+
+    fn into_variant(self) -> Result<Variant, HRESULT> {
+        Ok(Variant::from(self))
+    }
+}
+
+impl<T: IntoVariant> IntoVariant for Result<T, HRESULT> {
+    const VT: VARTYPE = T::VT;
+
+    fn into_variant(self) -> Result<Variant, HRESULT> {
+        self.and_then(T::into_variant)
+    }
+}
+
+/// A parameter or `[out, retval]` type of a dual interface's methods, as stored in a
+/// `VARIANT` of type `VT`.
+///
+/// `VARIANT_BOOL` is `i16`, so it is passed as `VT_I2`, with -1 for true.
+///
+/// # Safety
+///
+/// `read` and `into_variant` must use the member of the `VARIANT` union that `VT` selects.
+pub unsafe trait AutomationType: Sized {
+    const VT: VARTYPE;
+
+    /// Reads the value of a `VARIANT` of type `VT`, which keeps ownership of it.
+    ///
+    /// # Safety
+    ///
+    /// `value` must hold a `VT`, e.g. after `VariantChangeType` to it.
+    unsafe fn read(value: &VARIANT) -> Self;
+
+    /// Takes ownership of a value, such as an `[out, retval]`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must own the value, e.g. a `BSTR` or a reference to an interface, which the
+    /// `Variant` frees when dropped.
+    unsafe fn into_variant(self) -> Variant;
+}
+
+macro_rules! automation_type {
+    ($($ty:ty => $vt:ident, $get:ident, $set:ident;)*) => {$(
+        unsafe impl AutomationType for $ty {
+            const VT: VARTYPE = $vt as VARTYPE;
+
+            unsafe fn read(value: &VARIANT) -> $ty {
+                *value.n1.n2().n3.$get()
+            }
+
+            unsafe fn into_variant(self) -> Variant {
+                let mut raw: VARIANT = mem::zeroed();
+                raw.n1.n2_mut().vt = $vt as VARTYPE;
+                *raw.n1.n2_mut().n3.$set() = self;
+                Variant::from_raw(raw)
+            }
+        }
+    )*};
+}
+
+automation_type! {
+    u8 => VT_UI1, bVal, bVal_mut;
+    i16 => VT_I2, iVal, iVal_mut;
+    u16 => VT_UI2, uiVal, uiVal_mut;
+    i32 => VT_I4, lVal, lVal_mut;
+    u32 => VT_UI4, ulVal, ulVal_mut;
+    i64 => VT_I8, llVal, llVal_mut;
+    u64 => VT_UI8, ullVal, ullVal_mut;
+    f32 => VT_R4, fltVal, fltVal_mut;
+    f64 => VT_R8, dblVal, dblVal_mut;
+    BSTR => VT_BSTR, bstrVal, bstrVal_mut;
+    *mut IUnknown => VT_UNKNOWN, punkVal, punkVal_mut;
+    *mut IDispatch => VT_DISPATCH, pdispVal, pdispVal_mut;
+}
+
+/// Passed as given, without conversion.
+unsafe impl AutomationType for VARIANT {
+    const VT: VARTYPE = VT_VARIANT as VARTYPE;
+
+    unsafe fn read(value: &VARIANT) -> VARIANT {
+        *value
+    }
+
+    unsafe fn into_variant(self) -> Variant {
+        Variant::from_raw(self)
+    }
+}
+
+/// Describes the members of `T` and their parameters in a new `ITypeInfo`, which is what
+/// `IDispatch::GetTypeInfo` returns. It is created with `CreateDispTypeInfo`, so it only
+/// carries names, DISPIDs and types.
+pub fn type_info<T: Dispatch>(lcid: LCID) -> Result<ComPtr<ITypeInfo>, HRESULT> {
+    // Kept until the type information, which copies them, is created
+    let mut strings = Vec::new();
+    let mut wide = |name: &str| {
+        let mut wide: Vec<u16> = name.encode_utf16().chain(Some(0)).collect();
+        let ptr = wide.as_mut_ptr();
+        strings.push(wide);
+        ptr
+    };
+
+    let mut params = Vec::with_capacity(T::MEMBERS.len());
+    for member in T::MEMBERS {
+        let member_params = member.params.iter().map(|param| ffi::PARAMDATA {
+            szName: wide(param.name),
+            vt: param.vt,
+        });
+        params.push(member_params.collect::<Vec<_>>());
+    }
+
+    let mut methods = Vec::with_capacity(T::MEMBERS.len());
+    for (i, (member, params)) in T::MEMBERS.iter().zip(&mut params).enumerate() {
+        methods.push(ffi::METHODDATA {
+            szName: wide(member.name),
+            ppdata: params.as_mut_ptr(),
+            dispid: member.dispid,
+            // Calls go through `Invoke`, so these only need to follow IDispatch's entries
+            iMeth: (7 + i) as UINT,
+            cc: CC_STDCALL,
+            cArgs: params.len() as UINT,
+            wFlags: match member.kind {
+                MemberKind::Method => DISPATCH_METHOD,
+                MemberKind::PropertyPut => DISPATCH_PROPERTYPUT,
+            },
+            vtReturn: member.ret,
+        });
+    }
+
+    let mut data = ffi::INTERFACEDATA {
+        pmethdata: methods.as_mut_ptr(),
+        cMembers: methods.len() as UINT,
+    };
+    let mut info = ptr::null_mut();
+    unsafe {
+        let hr = ffi::CreateDispTypeInfo(&mut data, lcid, &mut info);
+        if FAILED(hr) {
+            return Err(hr);
+        }
+        Ok(ComPtr::from_raw(info))
+    }
+}
+
+/// Compares a member or parameter name the way automation clients expect.
+unsafe fn name_matches(name: &str, wide: LPOLESTR) -> bool {
+    if wide.is_null() {
+        return false;
+    }
+    let len = (0..).take_while(|&i| *wide.add(i) != 0).count();
+    let wide = String::from_utf16_lossy(slice::from_raw_parts(wide, len));
+    wide.to_lowercase() == name.to_lowercase()
+}
+
+#[doc(hidden)]
+/// `IDispatch::GetTypeInfoCount` of a [`Dispatch`] type.
+pub unsafe extern "system" fn get_type_info_count<T: Dispatch>(
+    _this: *mut IDispatch,
+    pctinfo: *mut UINT,
+) -> HRESULT {
+    if pctinfo.is_null() {
+        return E_POINTER;
+    }
+    *pctinfo = 1;
+    S_OK
+}
+
+#[doc(hidden)]
+/// `IDispatch::GetTypeInfo` of a [`Dispatch`] type.
+pub unsafe extern "system" fn get_type_info<T: Dispatch>(
+    _this: *mut IDispatch,
+    index: UINT,
+    lcid: LCID,
+    info: *mut *mut ITypeInfo,
+) -> HRESULT {
+    if info.is_null() {
+        return E_POINTER;
+    }
+    *info = ptr::null_mut();
+    if index != 0 {
+        return DISP_E_BADINDEX;
+    }
+    match type_info::<T>(lcid) {
+        Ok(type_info) => {
+            *info = type_info.into_raw();
+            S_OK
+        }
+        Err(hr) => hr,
+    }
+}
+
+#[doc(hidden)]
+/// `IDispatch::GetIDsOfNames` of a [`Dispatch`] type. The first name is the member's and the
+/// rest are its parameters', whose DISPIDs are their positions.
+pub unsafe extern "system" fn get_ids_of_names<T: Dispatch>(
+    _this: *mut IDispatch,
+    riid: REFIID,
+    names: *mut LPOLESTR,
+    count: UINT,
+    _lcid: LCID,
+    ids: *mut DISPID,
+) -> HRESULT {
+    if !IsEqualIID(&*riid, &IID_NULL) {
+        return DISP_E_UNKNOWNINTERFACE;
+    }
+    if count == 0 {
+        return S_OK;
+    }
+    if names.is_null() || ids.is_null() {
+        return E_POINTER;
+    }
+    let names = slice::from_raw_parts(names, count as usize);
+    let ids = slice::from_raw_parts_mut(ids, count as usize);
+
+    // A property's setter may be the only member to carry its name
+    let matching = |kind| {
+        T::MEMBERS
+            .iter()
+            .find(|m| m.kind == kind && name_matches(m.name, names[0]))
+    };
+    let member = match matching(MemberKind::Method).or_else(|| matching(MemberKind::PropertyPut)) {
+        Some(member) => member,
+        None => {
+            for id in ids.iter_mut() {
+                *id = DISPID_UNKNOWN;
+            }
+            return DISP_E_UNKNOWNNAME;
+        }
+    };
+
+    ids[0] = member.dispid;
+    let mut hr = S_OK;
+    for (id, &name) in ids[1..].iter_mut().zip(&names[1..]) {
+        *id = match member
+            .params
+            .iter()
+            .position(|p| name_matches(p.name, name))
+        {
+            Some(position) => position as DISPID,
+            None => {
+                hr = DISP_E_UNKNOWNNAME;
+                DISPID_UNKNOWN
+            }
+        };
+    }
+    hr
+}
+
+#[doc(hidden)]
+/// `IDispatch::Invoke` of a [`Dispatch`] type whose primary vtable is the `IDispatch` one.
+pub unsafe extern "system" fn invoke<T: Dispatch>(
+    this: *mut IDispatch,
+    dispid: DISPID,
+    riid: REFIID,
+    _lcid: LCID,
+    flags: WORD,
+    params: *mut DISPPARAMS,
+    result: *mut VARIANT,
+    _excep_info: *mut EXCEPINFO,
+    arg_err: *mut UINT,
+) -> HRESULT {
+    if !IsEqualIID(&*riid, &IID_NULL) {
+        return DISP_E_UNKNOWNINTERFACE;
+    }
+    if params.is_null() {
+        return E_POINTER;
+    }
+
+    let kind = if flags & (DISPATCH_PROPERTYPUT | DISPATCH_PROPERTYPUTREF) != 0 {
+        MemberKind::PropertyPut
+    } else {
+        MemberKind::Method
+    };
+    let index = match T::MEMBERS
+        .iter()
+        .position(|m| m.dispid == dispid && m.kind == kind)
+    {
+        Some(index) => index,
+        None => return DISP_E_MEMBERNOTFOUND,
+    };
+
+    let args = match Args::new(&*params, &T::MEMBERS[index]) {
+        Ok(args) => args,
+        Err(hr) => return hr,
+    };
+    let this = &*(this as *const T);
+    match this.invoke(index, &args) {
+        Ok(value) => {
+            if !result.is_null() {
+                *result = value.into_raw();
+            }
+            S_OK
+        }
+        Err(hr) => {
+            if let (Some(slot), false) = (args.arg_err.get(), arg_err.is_null()) {
+                *arg_err = slot as UINT;
+            }
+            hr
+        }
+    }
+}
+
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+mod ffi {
+    use winapi::shared::minwindef::{UINT, WORD};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::shared::wtypes::VARTYPE;
+    use winapi::shared::wtypesbase::OLECHAR;
+    use winapi::um::oaidl::{ITypeInfo, CALLCONV, DISPID};
+    use winapi::um::winnt::LCID;
+
+    #[repr(C)]
+    pub struct PARAMDATA {
+        pub szName: *mut OLECHAR,
+        pub vt: VARTYPE,
+    }
+
+    #[repr(C)]
+    pub struct METHODDATA {
+        pub szName: *mut OLECHAR,
+        pub ppdata: *mut PARAMDATA,
+        pub dispid: DISPID,
+        pub iMeth: UINT,
+        pub cc: CALLCONV,
+        pub cArgs: UINT,
+        pub wFlags: WORD,
+        pub vtReturn: VARTYPE,
+    }
+
+    #[repr(C)]
+    pub struct INTERFACEDATA {
+        pub pmethdata: *mut METHODDATA,
+        pub cMembers: UINT,
+    }
+
+    #[link(name = "oleaut32")]
+    extern "system" {
+        pub fn CreateDispTypeInfo(
+            pidata: *mut INTERFACEDATA,
+            lcid: LCID,
+            pptinfo: *mut *mut ITypeInfo,
+        ) -> HRESULT;
+    }
+}
@@ -0,0 +1,355 @@
+//! OLE drag and drop through safe Rust traits.
+//!
+//! Implement [`DropTarget`] and [`register`] it on a window to accept drops, or implement
+//! [`DropSource`] and call [`do_drag_drop`] to start a drag. OLE must be initialized on the
+//! calling thread with `OleInitialize` first.
+
+use std::ops::{BitAnd, BitOr};
+
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE};
+use winapi::shared::windef::{HWND, POINTL};
+use winapi::shared::winerror::{
+    DRAGDROP_S_CANCEL, DRAGDROP_S_DROP, DRAGDROP_S_USEDEFAULTCURSORS, E_FAIL, E_POINTER, HRESULT,
+    SUCCEEDED, S_OK,
+};
+use winapi::um::objidl::IDataObject;
+use winapi::um::ole2::{RegisterDragDrop, RevokeDragDrop};
+use winapi::um::oleidl::{
+    IDropTarget, IDropTargetVtbl, DROPEFFECT_COPY, DROPEFFECT_LINK, DROPEFFECT_MOVE,
+    DROPEFFECT_NONE, DROPEFFECT_SCROLL,
+};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub use self::ffi::{IDropSource, IDropSourceVtbl};
+
+// Not provided by winapi.
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::minwindef::{BOOL, DWORD};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::objidl::IDataObject;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0x00000121, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IDropSource(IDropSourceVtbl): IUnknown(IUnknownVtbl) {
+        fn QueryContinueDrag(
+            fEscapePressed: BOOL,
+            grfKeyState: DWORD,
+        ) -> HRESULT,
+        fn GiveFeedback(
+            dwEffect: DWORD,
+        ) -> HRESULT,
+    }}
+
+    #[link(name = "ole32")]
+    extern "system" {
+        pub fn DoDragDrop(
+            pDataObj: *mut IDataObject,
+            pDropSource: *mut IDropSource,
+            dwOKEffects: DWORD,
+            pdwEffect: *mut DWORD,
+        ) -> HRESULT;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The mouse buttons and modifier keys held during a drag.
+pub struct KeyState(pub u32);
+
+impl KeyState {
+    pub fn left_button(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    pub fn right_button(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    pub fn shift(self) -> bool {
+        self.0 & 0x04 != 0
+    }
+
+    pub fn control(self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    pub fn middle_button(self) -> bool {
+        self.0 & 0x10 != 0
+    }
+
+    pub fn alt(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// A set of `DROPEFFECT_*` flags.
+pub struct DropEffect(pub u32);
+
+impl DropEffect {
+    pub const NONE: DropEffect = DropEffect(DROPEFFECT_NONE);
+    pub const COPY: DropEffect = DropEffect(DROPEFFECT_COPY);
+    pub const MOVE: DropEffect = DropEffect(DROPEFFECT_MOVE);
+    pub const LINK: DropEffect = DropEffect(DROPEFFECT_LINK);
+    pub const SCROLL: DropEffect = DropEffect(DROPEFFECT_SCROLL);
+
+    pub fn contains(self, other: DropEffect) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for DropEffect {
+    type Output = DropEffect;
+    fn bitor(self, rhs: DropEffect) -> DropEffect {
+        DropEffect(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for DropEffect {
+    type Output = DropEffect;
+    fn bitand(self, rhs: DropEffect) -> DropEffect {
+        DropEffect(self.0 & rhs.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+/// Cursor position in screen coordinates.
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl From<POINTL> for Point {
+    fn from(p: POINTL) -> Point {
+        Point { x: p.x, y: p.y }
+    }
+}
+
+/// Receives drops on a window registered with [`register`].
+///
+/// `allowed` is the set of effects the source permits. Each method returns the effect that
+/// would happen if the data were dropped at that point, which should be a subset of `allowed`.
+pub trait DropTarget {
+    fn drag_enter(
+        &self,
+        data: &IDataObject,
+        keys: KeyState,
+        point: Point,
+        allowed: DropEffect,
+    ) -> DropEffect;
+
+    fn drag_over(&self, keys: KeyState, point: Point, allowed: DropEffect) -> DropEffect;
+
+    fn drag_leave(&self) {}
+
+    fn drop(
+        &self,
+        data: &IDataObject,
+        keys: KeyState,
+        point: Point,
+        allowed: DropEffect,
+    ) -> DropEffect;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DragAction {
+    Continue,
+    Drop,
+    Cancel,
+}
+
+/// Controls a drag started with [`do_drag_drop`].
+pub trait DropSource {
+    /// The default cancels on escape and drops once neither mouse button is held.
+    fn query_continue_drag(&self, escape_pressed: bool, keys: KeyState) -> DragAction {
+        if escape_pressed {
+            DragAction::Cancel
+        } else if !keys.left_button() && !keys.right_button() {
+            DragAction::Drop
+        } else {
+            DragAction::Continue
+        }
+    }
+
+    /// Return `false` if you have set the cursor yourself, `true` to use the OLE defaults.
+    fn give_feedback(&self, _effect: DropEffect) -> bool {
+        true
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IDropTarget` by forwarding to a [`DropTarget`].
+pub struct DropTargetAdapter<T: DropTarget> {
+    vtbl: VTable<IDropTargetVtbl>,
+    refcount: Refcount,
+    target: T,
+}
+
+impl<T: DropTarget> DropTargetAdapter<T> {
+    pub fn new(target: T) -> ComPtr<IDropTarget> {
+        let ptr = DropTargetAdapter::create_raw(target);
+        let ptr = ptr as *mut IDropTarget;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: DropTarget> IDropTarget for DropTargetAdapter<T> {
+    #[panic(result = "E_FAIL")]
+    unsafe fn drag_enter(
+        &self,
+        data: *const IDataObject,
+        keys: DWORD,
+        point: *const POINTL,
+        effect: *mut DWORD,
+    ) -> HRESULT {
+        if data.is_null() || point.is_null() || effect.is_null() {
+            return E_POINTER;
+        }
+        let allowed = DropEffect(*effect);
+        let result = self
+            .target
+            .drag_enter(&*data, KeyState(keys), (*point).into(), allowed);
+        *effect = result.0;
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn drag_over(&self, keys: DWORD, point: *const POINTL, effect: *mut DWORD) -> HRESULT {
+        if point.is_null() || effect.is_null() {
+            return E_POINTER;
+        }
+        let allowed = DropEffect(*effect);
+        let result = self
+            .target
+            .drag_over(KeyState(keys), (*point).into(), allowed);
+        *effect = result.0;
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn drag_leave(&self) -> HRESULT {
+        self.target.drag_leave();
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn drop(
+        &self,
+        data: *const IDataObject,
+        keys: DWORD,
+        point: *const POINTL,
+        effect: *mut DWORD,
+    ) -> HRESULT {
+        if data.is_null() || point.is_null() || effect.is_null() {
+            return E_POINTER;
+        }
+        let allowed = DropEffect(*effect);
+        let result = self
+            .target
+            .drop(&*data, KeyState(keys), (*point).into(), allowed);
+        *effect = result.0;
+        S_OK
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IDropSource` by forwarding to a [`DropSource`].
+pub struct DropSourceAdapter<S: DropSource> {
+    vtbl: VTable<IDropSourceVtbl>,
+    refcount: Refcount,
+    source: S,
+}
+
+impl<S: DropSource> DropSourceAdapter<S> {
+    pub fn new(source: S) -> ComPtr<IDropSource> {
+        let ptr = DropSourceAdapter::create_raw(source);
+        let ptr = ptr as *mut IDropSource;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<S: DropSource> IDropSource for DropSourceAdapter<S> {
+    #[panic(result = "E_FAIL")]
+    fn query_continue_drag(&self, escape_pressed: BOOL, keys: DWORD) -> HRESULT {
+        match self
+            .source
+            .query_continue_drag(escape_pressed != FALSE, KeyState(keys))
+        {
+            DragAction::Continue => S_OK,
+            DragAction::Drop => DRAGDROP_S_DROP,
+            DragAction::Cancel => DRAGDROP_S_CANCEL,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn give_feedback(&self, effect: DWORD) -> HRESULT {
+        if self.source.give_feedback(DropEffect(effect)) {
+            DRAGDROP_S_USEDEFAULTCURSORS
+        } else {
+            S_OK
+        }
+    }
+}
+
+/// Keeps a window registered as a drop target. The registration is revoked on drop.
+#[derive(Debug)]
+pub struct Registration {
+    hwnd: HWND,
+}
+
+/// Registers `target` to receive drops on `hwnd`.
+///
+/// # Safety
+///
+/// `hwnd` must be a valid window owned by the current thread, which must have initialized OLE.
+pub unsafe fn register<T>(hwnd: HWND, target: T) -> Result<Registration, HRESULT>
+where
+    T: DropTarget + 'static,
+{
+    let target = DropTargetAdapter::new(target);
+    let hr = RegisterDragDrop(hwnd, target.as_raw());
+    if SUCCEEDED(hr) {
+        Ok(Registration { hwnd })
+    } else {
+        Err(hr)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        unsafe {
+            RevokeDragDrop(self.hwnd);
+        }
+    }
+}
+
+/// Runs a modal drag of `data` controlled by `source`, returning the effect the target
+/// performed, or `None` if the drag was cancelled.
+///
+/// # Safety
+///
+/// The current thread must have initialized OLE.
+pub unsafe fn do_drag_drop<S>(
+    data: &ComPtr<IDataObject>,
+    source: S,
+    allowed: DropEffect,
+) -> Result<Option<DropEffect>, HRESULT>
+where
+    S: DropSource + 'static,
+{
+    let source = DropSourceAdapter::new(source);
+    let mut effect = DROPEFFECT_NONE;
+    let hr = ffi::DoDragDrop(data.as_raw(), source.as_raw(), allowed.0, &mut effect);
+    match hr {
+        DRAGDROP_S_DROP => Ok(Some(DropEffect(effect))),
+        DRAGDROP_S_CANCEL => Ok(None),
+        hr => Err(hr),
+    }
+}
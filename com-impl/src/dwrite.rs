@@ -0,0 +1,565 @@
+//! Ready-made DirectWrite loaders for using in-memory fonts as a custom font collection.
+//!
+//! DirectWrite needs four cooperating COM objects to load fonts from anywhere other than the
+//! system font directory: a collection loader, a file enumerator, a file loader and a stream
+//! per font file. [`CustomFontCollection`] builds and registers all of them for you.
+//!
+//! Custom text rendering only needs [`TextRenderer::draw_glyph_run`]; wrap your renderer in
+//! a [`TextRendererAdapter`] to get an `IDWriteTextRenderer` with sensible defaults for
+//! everything else.
+//!
+//! ```no_run
+//! use com_impl::dwrite::CustomFontCollection;
+//! use winapi::um::dwrite::{DWriteCreateFactory, IDWriteFactory, DWRITE_FACTORY_TYPE_SHARED};
+//! use winapi::Interface;
+//! use wio::com::ComPtr;
+//!
+//! let factory = unsafe {
+//!     let mut ptr = std::ptr::null_mut();
+//!     DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED, &IDWriteFactory::uuidof(), &mut ptr);
+//!     ComPtr::from_raw(ptr as *mut IDWriteFactory)
+//! };
+//!
+//! let fonts = vec![std::fs::read("MyFont.ttf").unwrap()];
+//! let collection = CustomFontCollection::new(&factory, fonts).unwrap();
+//!
+//! // Pass collection.collection() to CreateTextFormat
+//! ```
+
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::Arc;
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
+use winapi::shared::winerror::{
+    ERROR_INVALID_INDEX, E_BOUNDS, E_FAIL, E_INVALIDARG, E_POINTER, HRESULT, HRESULT_FROM_WIN32,
+    SUCCEEDED, S_OK,
+};
+use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::um::dwrite::{
+    IDWriteFactory, IDWriteFontCollection, IDWriteFontCollectionLoader,
+    IDWriteFontCollectionLoaderVtbl, IDWriteFontFile, IDWriteFontFileEnumerator,
+    IDWriteFontFileEnumeratorVtbl, IDWriteFontFileLoader, IDWriteFontFileLoaderVtbl,
+    IDWriteFontFileStream, IDWriteFontFileStreamVtbl, IDWriteInlineObject, IDWritePixelSnapping,
+    IDWritePixelSnappingVtbl, IDWriteTextRenderer, IDWriteTextRendererVtbl, DWRITE_GLYPH_RUN,
+    DWRITE_GLYPH_RUN_DESCRIPTION, DWRITE_MATRIX, DWRITE_STRIKETHROUGH, DWRITE_UNDERLINE,
+};
+use winapi::um::unknwnbase::IUnknown;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+type FontData = Arc<Vec<Vec<u8>>>;
+
+/// A font collection built from font files held in memory.
+///
+/// The loaders backing the collection stay registered with the factory for as long as this
+/// value is alive, and are unregistered when it is dropped.
+pub struct CustomFontCollection {
+    factory: ComPtr<IDWriteFactory>,
+    collection_loader: ComPtr<IDWriteFontCollectionLoader>,
+    file_loader: ComPtr<IDWriteFontFileLoader>,
+    collection: ComPtr<IDWriteFontCollection>,
+}
+
+impl CustomFontCollection {
+    /// Registers loaders for `fonts` with `factory` and creates a collection containing every
+    /// font in every file.
+    pub fn new(
+        factory: &ComPtr<IDWriteFactory>,
+        fonts: Vec<Vec<u8>>,
+    ) -> Result<CustomFontCollection, HRESULT> {
+        let fonts = Arc::new(fonts);
+        let file_loader = FontFileLoader::new(fonts.clone());
+        let collection_loader = FontCollectionLoader::new(fonts.len(), file_loader.clone());
+
+        unsafe {
+            let hr = factory.RegisterFontFileLoader(file_loader.as_raw());
+            if !SUCCEEDED(hr) {
+                return Err(hr);
+            }
+
+            let hr = factory.RegisterFontCollectionLoader(collection_loader.as_raw());
+            if !SUCCEEDED(hr) {
+                factory.UnregisterFontFileLoader(file_loader.as_raw());
+                return Err(hr);
+            }
+
+            let mut collection = std::ptr::null_mut();
+            let hr = factory.CreateCustomFontCollection(
+                collection_loader.as_raw(),
+                COLLECTION_KEY.as_ptr() as *const c_void,
+                COLLECTION_KEY.len() as u32,
+                &mut collection,
+            );
+            if !SUCCEEDED(hr) {
+                factory.UnregisterFontCollectionLoader(collection_loader.as_raw());
+                factory.UnregisterFontFileLoader(file_loader.as_raw());
+                return Err(hr);
+            }
+
+            Ok(CustomFontCollection {
+                factory: factory.clone(),
+                collection_loader,
+                file_loader,
+                collection: ComPtr::from_raw(collection),
+            })
+        }
+    }
+
+    /// Reads every file in `paths` into memory and creates a collection from them. I/O errors
+    /// are reported as the equivalent `HRESULT`.
+    pub fn from_paths<P: AsRef<Path>>(
+        factory: &ComPtr<IDWriteFactory>,
+        paths: &[P],
+    ) -> Result<CustomFontCollection, HRESULT> {
+        let fonts = paths
+            .iter()
+            .map(std::fs::read)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| match e.raw_os_error() {
+                Some(code) => HRESULT_FROM_WIN32(code as u32),
+                None => E_FAIL,
+            })?;
+
+        Self::new(factory, fonts)
+    }
+
+    /// The collection, suitable for passing to `IDWriteFactory::CreateTextFormat`.
+    pub fn collection(&self) -> &ComPtr<IDWriteFontCollection> {
+        &self.collection
+    }
+}
+
+impl Drop for CustomFontCollection {
+    fn drop(&mut self) {
+        unsafe {
+            self.factory
+                .UnregisterFontCollectionLoader(self.collection_loader.as_raw());
+            self.factory
+                .UnregisterFontFileLoader(self.file_loader.as_raw());
+        }
+    }
+}
+
+/// Every `CustomFontCollection` has its own loader, so the key only needs to be non-empty.
+const COLLECTION_KEY: [u8; 1] = [0];
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct FontCollectionLoader {
+    vtbl: VTable<IDWriteFontCollectionLoaderVtbl>,
+    refcount: Refcount,
+    font_count: usize,
+    file_loader: ComPtr<IDWriteFontFileLoader>,
+}
+
+impl FontCollectionLoader {
+    fn new(
+        font_count: usize,
+        file_loader: ComPtr<IDWriteFontFileLoader>,
+    ) -> ComPtr<IDWriteFontCollectionLoader> {
+        let ptr = FontCollectionLoader::create_raw(font_count, file_loader);
+        let ptr = ptr as *mut IDWriteFontCollectionLoader;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontCollectionLoader for FontCollectionLoader {
+    unsafe fn create_enumerator_from_key(
+        &self,
+        factory: *mut IDWriteFactory,
+        _key: *const c_void,
+        _key_size: u32,
+        enumerator: *mut *mut IDWriteFontFileEnumerator,
+    ) -> HRESULT {
+        if factory.is_null() || enumerator.is_null() {
+            return E_INVALIDARG;
+        }
+
+        (*factory).AddRef();
+        let factory = ComPtr::from_raw(factory);
+        *enumerator = FontFileEnumerator::new(factory, self.font_count, self.file_loader.clone());
+        S_OK
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct FontFileEnumerator {
+    vtbl: VTable<IDWriteFontFileEnumeratorVtbl>,
+    refcount: Refcount,
+    factory: ComPtr<IDWriteFactory>,
+    font_count: usize,
+    file_loader: ComPtr<IDWriteFontFileLoader>,
+    // One past the current file, so that 0 means MoveNext has not been called yet.
+    position: Cell<usize>,
+}
+
+impl FontFileEnumerator {
+    fn new(
+        factory: ComPtr<IDWriteFactory>,
+        font_count: usize,
+        file_loader: ComPtr<IDWriteFontFileLoader>,
+    ) -> *mut IDWriteFontFileEnumerator {
+        let ptr = FontFileEnumerator::create_raw(factory, font_count, file_loader, Cell::new(0));
+        ptr as *mut IDWriteFontFileEnumerator
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileEnumerator for FontFileEnumerator {
+    unsafe fn move_next(&self, has_current_file: *mut BOOL) -> HRESULT {
+        let position = (self.position.get() + 1).min(self.font_count + 1);
+        self.position.set(position);
+        *has_current_file = if position <= self.font_count {
+            TRUE
+        } else {
+            FALSE
+        };
+        S_OK
+    }
+
+    unsafe fn get_current_font_file(&self, font_file: *mut *mut IDWriteFontFile) -> HRESULT {
+        let position = self.position.get();
+        if position == 0 || position > self.font_count {
+            *font_file = std::ptr::null_mut();
+            return E_BOUNDS;
+        }
+
+        let key = position - 1;
+        self.factory.CreateCustomFontFileReference(
+            &key as *const usize as *const c_void,
+            std::mem::size_of::<usize>() as u32,
+            self.file_loader.as_raw(),
+            font_file,
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct FontFileLoader {
+    vtbl: VTable<IDWriteFontFileLoaderVtbl>,
+    refcount: Refcount,
+    fonts: FontData,
+}
+
+impl FontFileLoader {
+    fn new(fonts: FontData) -> ComPtr<IDWriteFontFileLoader> {
+        let ptr = FontFileLoader::create_raw(fonts);
+        let ptr = ptr as *mut IDWriteFontFileLoader;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileLoader for FontFileLoader {
+    unsafe fn create_stream_from_key(
+        &self,
+        key: *const c_void,
+        key_size: u32,
+        stream: *mut *mut IDWriteFontFileStream,
+    ) -> HRESULT {
+        if key.is_null() || key_size as usize != std::mem::size_of::<usize>() {
+            return E_INVALIDARG;
+        }
+
+        let index = std::ptr::read_unaligned(key as *const usize);
+        if index >= self.fonts.len() {
+            return E_INVALIDARG;
+        }
+
+        *stream = FontFileStream::create_raw(self.fonts.clone(), index) as *mut _;
+        S_OK
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct FontFileStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    fonts: FontData,
+    index: usize,
+}
+
+impl FontFileStream {
+    fn data(&self) -> &[u8] {
+        &self.fonts[self.index]
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileStream for FontFileStream {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.data().len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        // In-memory fonts never change.
+        *write_time = 0;
+        S_OK
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        let data = self.data();
+        let end = offset.checked_add(size);
+        match end {
+            Some(end) if end <= data.len() as u64 => {
+                *start = data[offset as usize..].as_ptr() as *const c_void;
+                *ctx = std::ptr::null_mut();
+                S_OK
+            }
+            _ => HRESULT_FROM_WIN32(ERROR_INVALID_INDEX),
+        }
+    }
+
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {
+        // Nothing to do, the data lives as long as the stream
+    }
+}
+
+/// A custom renderer for `IDWriteTextLayout::Draw`, implemented as an `IDWriteTextRenderer` by
+/// [`TextRendererAdapter`].
+///
+/// Only `draw_glyph_run` is required. Underlines and strikethroughs are skipped, inline objects
+/// draw themselves through this renderer, and pixel snapping uses an identity transform at 96
+/// DPI unless overridden.
+#[allow(clippy::too_many_arguments)]
+pub trait TextRenderer {
+    fn draw_glyph_run(
+        &self,
+        context: *mut c_void,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: &DWRITE_GLYPH_RUN,
+        description: Option<&DWRITE_GLYPH_RUN_DESCRIPTION>,
+        effect: *mut IUnknown,
+    ) -> HRESULT;
+
+    fn draw_underline(
+        &self,
+        _context: *mut c_void,
+        _baseline_origin_x: f32,
+        _baseline_origin_y: f32,
+        _underline: &DWRITE_UNDERLINE,
+        _effect: *mut IUnknown,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    fn draw_strikethrough(
+        &self,
+        _context: *mut c_void,
+        _baseline_origin_x: f32,
+        _baseline_origin_y: f32,
+        _strikethrough: &DWRITE_STRIKETHROUGH,
+        _effect: *mut IUnknown,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    /// `renderer` is the `IDWriteTextRenderer` this call arrived through, for passing on to
+    /// `IDWriteInlineObject::Draw`.
+    ///
+    /// # Safety
+    ///
+    /// The pointers must be the ones DirectWrite passed to the renderer.
+    unsafe fn draw_inline_object(
+        &self,
+        context: *mut c_void,
+        origin_x: f32,
+        origin_y: f32,
+        inline_object: &IDWriteInlineObject,
+        is_sideways: bool,
+        is_right_to_left: bool,
+        effect: *mut IUnknown,
+        renderer: *mut IDWriteTextRenderer,
+    ) -> HRESULT {
+        inline_object.Draw(
+            context,
+            renderer,
+            origin_x,
+            origin_y,
+            is_sideways as BOOL,
+            is_right_to_left as BOOL,
+            effect,
+        )
+    }
+
+    fn is_pixel_snapping_disabled(&self, _context: *mut c_void) -> bool {
+        false
+    }
+
+    fn current_transform(&self, _context: *mut c_void) -> DWRITE_MATRIX {
+        DWRITE_MATRIX {
+            m11: 1.0,
+            m12: 0.0,
+            m21: 0.0,
+            m22: 1.0,
+            dx: 0.0,
+            dy: 0.0,
+        }
+    }
+
+    fn pixels_per_dip(&self, _context: *mut c_void) -> f32 {
+        1.0
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IDWritePixelSnapping, IDWriteTextRenderer)]
+/// COM object implementing `IDWriteTextRenderer` by forwarding to a [`TextRenderer`].
+pub struct TextRendererAdapter<R: TextRenderer> {
+    vtbl: VTable<IDWriteTextRendererVtbl>,
+    refcount: Refcount,
+    renderer: R,
+}
+
+impl<R: TextRenderer> TextRendererAdapter<R> {
+    pub fn new(renderer: R) -> ComPtr<IDWriteTextRenderer> {
+        let ptr = TextRendererAdapter::create_raw(renderer);
+        let ptr = ptr as *mut IDWriteTextRenderer;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<R: TextRenderer> IDWriteTextRenderer for TextRendererAdapter<R> {
+    #[interface(IDWritePixelSnapping)]
+    unsafe fn is_pixel_snapping_disabled(
+        &self,
+        context: *mut c_void,
+        is_disabled: *mut BOOL,
+    ) -> HRESULT {
+        if is_disabled.is_null() {
+            return E_POINTER;
+        }
+        *is_disabled = self.renderer.is_pixel_snapping_disabled(context) as BOOL;
+        S_OK
+    }
+
+    #[interface(IDWritePixelSnapping)]
+    unsafe fn get_current_transform(
+        &self,
+        context: *mut c_void,
+        transform: *mut DWRITE_MATRIX,
+    ) -> HRESULT {
+        if transform.is_null() {
+            return E_POINTER;
+        }
+        *transform = self.renderer.current_transform(context);
+        S_OK
+    }
+
+    #[interface(IDWritePixelSnapping)]
+    unsafe fn get_pixels_per_dip(&self, context: *mut c_void, pixels_per_dip: *mut f32) -> HRESULT {
+        if pixels_per_dip.is_null() {
+            return E_POINTER;
+        }
+        *pixels_per_dip = self.renderer.pixels_per_dip(context);
+        S_OK
+    }
+
+    unsafe fn draw_glyph_run(
+        &self,
+        context: *mut c_void,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: *const DWRITE_GLYPH_RUN,
+        description: *const DWRITE_GLYPH_RUN_DESCRIPTION,
+        effect: *mut IUnknown,
+    ) -> HRESULT {
+        if glyph_run.is_null() {
+            return E_POINTER;
+        }
+        self.renderer.draw_glyph_run(
+            context,
+            baseline_origin_x,
+            baseline_origin_y,
+            measuring_mode,
+            &*glyph_run,
+            description.as_ref(),
+            effect,
+        )
+    }
+
+    unsafe fn draw_underline(
+        &self,
+        context: *mut c_void,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        underline: *const DWRITE_UNDERLINE,
+        effect: *mut IUnknown,
+    ) -> HRESULT {
+        if underline.is_null() {
+            return E_POINTER;
+        }
+        self.renderer.draw_underline(
+            context,
+            baseline_origin_x,
+            baseline_origin_y,
+            &*underline,
+            effect,
+        )
+    }
+
+    unsafe fn draw_strikethrough(
+        &self,
+        context: *mut c_void,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        strikethrough: *const DWRITE_STRIKETHROUGH,
+        effect: *mut IUnknown,
+    ) -> HRESULT {
+        if strikethrough.is_null() {
+            return E_POINTER;
+        }
+        self.renderer.draw_strikethrough(
+            context,
+            baseline_origin_x,
+            baseline_origin_y,
+            &*strikethrough,
+            effect,
+        )
+    }
+
+    unsafe fn draw_inline_object(
+        &self,
+        context: *mut c_void,
+        origin_x: f32,
+        origin_y: f32,
+        inline_object: *mut IDWriteInlineObject,
+        is_sideways: BOOL,
+        is_right_to_left: BOOL,
+        effect: *mut IUnknown,
+    ) -> HRESULT {
+        if inline_object.is_null() {
+            return E_POINTER;
+        }
+        let renderer = self as *const Self as *mut IDWriteTextRenderer;
+        self.renderer.draw_inline_object(
+            context,
+            origin_x,
+            origin_y,
+            &*inline_object,
+            is_sideways != FALSE,
+            is_right_to_left != FALSE,
+            effect,
+            renderer,
+        )
+    }
+}
@@ -0,0 +1,158 @@
+//! COM objects backed by trait objects, for plugin systems.
+//!
+//! [`dyn_object!`] declares one COM class wrapping a `Box<dyn Trait>`, together with the trait,
+//! which mirrors the interface's methods. Its vtable and the stubs forwarding each method to
+//! the boxed implementation are generated once, for the trait object, so every implementation
+//! of the trait, including ones loaded from other modules at runtime, shares them instead of
+//! each needing its own derive.
+//!
+//! ```no_run
+//! use winapi::ctypes::c_void;
+//! use winapi::shared::winerror::{
+//!     ERROR_INVALID_INDEX, E_NOTIMPL, HRESULT, HRESULT_FROM_WIN32, S_OK,
+//! };
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//!
+//! com_impl::dyn_object! {
+//!     pub struct PluginStream: IDWriteFontFileStream(IDWriteFontFileStreamVtbl) => dyn FontStream {
+//!         unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT;
+//!         unsafe fn get_last_write_time(&self, time: *mut u64) -> HRESULT;
+//!         unsafe fn read_file_fragment(
+//!             &self,
+//!             start: *mut *const c_void,
+//!             offset: u64,
+//!             size: u64,
+//!             ctx: *mut *mut c_void,
+//!         ) -> HRESULT;
+//!         unsafe fn release_file_fragment(&self, ctx: *mut c_void);
+//!     }
+//! }
+//!
+//! /// What a plugin provides.
+//! struct FontData(Vec<u8>);
+//!
+//! impl FontStream for FontData {
+//!     unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+//!         *size = self.0.len() as u64;
+//!         S_OK
+//!     }
+//!
+//!     unsafe fn get_last_write_time(&self, _time: *mut u64) -> HRESULT {
+//!         E_NOTIMPL
+//!     }
+//!
+//!     unsafe fn read_file_fragment(
+//!         &self,
+//!         start: *mut *const c_void,
+//!         offset: u64,
+//!         size: u64,
+//!         ctx: *mut *mut c_void,
+//!     ) -> HRESULT {
+//!         match offset.checked_add(size) {
+//!             Some(end) if end <= self.0.len() as u64 => {}
+//!             _ => return HRESULT_FROM_WIN32(ERROR_INVALID_INDEX),
+//!         }
+//!         *start = self.0[offset as usize..].as_ptr() as *const c_void;
+//!         *ctx = std::ptr::null_mut();
+//!         S_OK
+//!     }
+//!
+//!     unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+//! }
+//!
+//! fn load(data: Vec<u8>) {
+//!     let stream = PluginStream::new(Box::new(FontData(data)));
+//!     // Hand stream to DirectWrite
+//! }
+//! ```
+
+#[doc(hidden)]
+pub use wio::com::ComPtr;
+
+/// Declares a COM class holding a `Box<dyn Trait>` in a private `imp` member.
+///
+/// `$name: $interface($vtbl) => dyn $trait { ... }` declares `$name`, implementing `$interface`
+/// through `$vtbl`, with a `new` taking the boxed implementation and returning a
+/// `ComPtr<$interface>`. The braces list the methods of the interface as `#[com_impl]` takes
+/// them, `unsafe` or not, ending in `;`. They declare `$trait` with those methods, and
+/// `$name` implements the interface by calling them on `imp`. The arguments must be plain
+/// names. `$interface` must be in scope.
+///
+/// `=> $imp;`, with any type and no braces, only declares `$name`, leaving the trait and the
+/// `#[com_impl]` block forwarding to `imp` to you.
+#[macro_export]
+macro_rules! dyn_object {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident : $interface:ident($vtbl:ty) => dyn $trait:ident {
+            $($methods:tt)*
+        }
+    ) => {
+        $crate::dyn_object!(@trait $vis $trait [] $($methods)*);
+        $crate::dyn_object! {
+            $(#[$attr])*
+            $vis struct $name: $interface($vtbl) => dyn $trait;
+        }
+        $crate::dyn_object!(@forward $name $interface [] $($methods)*);
+    };
+    ($(#[$attr:meta])* $vis:vis struct $name:ident : $interface:ident($vtbl:ty) => $imp:ty;) => {
+        $(#[$attr])*
+        #[repr(C)]
+        #[derive($crate::ComImpl)]
+        #[com_crate($crate)]
+        #[interfaces($interface)]
+        $vis struct $name {
+            vtbl: $crate::VTable<$vtbl>,
+            refcount: $crate::Refcount,
+            imp: Box<$imp>,
+        }
+
+        impl $name {
+            #[allow(dead_code)]
+            $vis fn new(imp: Box<$imp>) -> $crate::dynamic::ComPtr<$interface> {
+                let ptr = $name::create_raw(imp) as *mut $interface;
+                unsafe { $crate::dynamic::ComPtr::from_raw(ptr) }
+            }
+        }
+    };
+
+    // The trait, with the methods as they are given
+    (@trait $vis:vis $trait:ident [$($done:tt)*]) => {
+        $vis trait $trait {
+            $($done)*
+        }
+    };
+    (@trait $vis:vis $trait:ident [$($done:tt)*] $(#[$attr:meta])* $($sig:ident)+ ($($args:tt)*)
+        $(-> $ret:ty)?; $($rest:tt)*) => {
+        $crate::dyn_object!(@trait $vis $trait [
+            $($done)*
+            $(#[$attr])* $($sig)+ ($($args)*) $(-> $ret)?;
+        ] $($rest)*);
+    };
+
+    // The #[com_impl] block, each method calling the trait's
+    (@forward $name:ident $interface:ident [$($done:tt)*]) => {
+        #[$crate::com_impl(crate = $crate)]
+        unsafe impl $interface for $name {
+            $($done)*
+        }
+    };
+    (@forward $name:ident $interface:ident [$($done:tt)*] $(#[$attr:meta])* unsafe fn
+        $method:ident(&self $(, $arg:ident: $ty:ty)* $(,)?) $(-> $ret:ty)?; $($rest:tt)*) => {
+        $crate::dyn_object!(@forward $name $interface [
+            $($done)*
+            unsafe fn $method(&self $(, $arg: $ty)*) $(-> $ret)? {
+                self.imp.$method($($arg),*)
+            }
+        ] $($rest)*);
+    };
+    (@forward $name:ident $interface:ident [$($done:tt)*] $(#[$attr:meta])* fn
+        $method:ident(&self $(, $arg:ident: $ty:ty)* $(,)?) $(-> $ret:ty)?; $($rest:tt)*) => {
+        $crate::dyn_object!(@forward $name $interface [
+            $($done)*
+            fn $method(&self $(, $arg: $ty)*) $(-> $ret)? {
+                self.imp.$method($($arg),*)
+            }
+        ] $($rest)*);
+    };
+}
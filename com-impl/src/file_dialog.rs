@@ -0,0 +1,372 @@
+//! Closure-driven event handlers for the common item dialog.
+//!
+//! ```no_run
+//! use com_impl::file_dialog::FileDialogEventsBuilder;
+//! use winapi::shared::winerror::{S_FALSE, S_OK};
+//!
+//! let events = FileDialogEventsBuilder::new()
+//!     .on_file_ok(|_dialog| S_OK)
+//!     .on_button_clicked(|_customize, id| if id == 100 { S_OK } else { S_FALSE })
+//!     .build();
+//!
+//! // Pass events.as_raw() to IFileDialog::Advise
+//! ```
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID};
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE};
+use winapi::shared::winerror::{E_FAIL, E_NOINTERFACE, E_NOTIMPL, E_POINTER, HRESULT, S_OK};
+use winapi::um::shobjidl::{
+    IFileDialog, IFileDialogControlEvents, IFileDialogControlEventsVtbl, IFileDialogCustomize,
+    IFileDialogEvents, IFileDialogEventsVtbl, FDE_OVERWRITE_RESPONSE, FDE_SHAREVIOLATION_RESPONSE,
+};
+use winapi::um::shobjidl_core::IShellItem;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{BuildVTable, Refcount, VTable};
+
+type DialogFn = Box<dyn Fn(&IFileDialog) -> HRESULT>;
+type ItemFn = Box<dyn Fn(&IFileDialog, &IShellItem) -> HRESULT>;
+type ShareViolationFn = Box<dyn Fn(&IFileDialog, &IShellItem) -> FDE_SHAREVIOLATION_RESPONSE>;
+type OverwriteFn = Box<dyn Fn(&IFileDialog, &IShellItem) -> FDE_OVERWRITE_RESPONSE>;
+type ItemSelectedFn = Box<dyn Fn(&IFileDialogCustomize, DWORD, DWORD) -> HRESULT>;
+type ControlFn = Box<dyn Fn(&IFileDialogCustomize, DWORD) -> HRESULT>;
+type CheckToggledFn = Box<dyn Fn(&IFileDialogCustomize, DWORD, bool) -> HRESULT>;
+
+#[derive(Default)]
+/// Builds an `IFileDialogEvents` sink out of optional closures.
+///
+/// Events without a handler return `S_OK`, except for share violations and overwrites which
+/// return `E_NOTIMPL` so the dialog falls back to its default behavior. If any of the
+/// control event handlers are set, the sink also answers `IFileDialogControlEvents`, so
+/// custom controls added through `IFileDialogCustomize` report to the same object.
+pub struct FileDialogEventsBuilder {
+    on_file_ok: Option<DialogFn>,
+    on_folder_changing: Option<ItemFn>,
+    on_folder_change: Option<DialogFn>,
+    on_selection_change: Option<DialogFn>,
+    on_share_violation: Option<ShareViolationFn>,
+    on_type_change: Option<DialogFn>,
+    on_overwrite: Option<OverwriteFn>,
+    on_item_selected: Option<ItemSelectedFn>,
+    on_button_clicked: Option<ControlFn>,
+    on_check_button_toggled: Option<CheckToggledFn>,
+    on_control_activating: Option<ControlFn>,
+}
+
+impl FileDialogEventsBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Return `S_FALSE` to keep the dialog open.
+    pub fn on_file_ok(mut self, f: impl Fn(&IFileDialog) -> HRESULT + 'static) -> Self {
+        self.on_file_ok = Some(Box::new(f));
+        self
+    }
+
+    /// Return a failure code to prevent navigating to the folder.
+    pub fn on_folder_changing(
+        mut self,
+        f: impl Fn(&IFileDialog, &IShellItem) -> HRESULT + 'static,
+    ) -> Self {
+        self.on_folder_changing = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_folder_change(mut self, f: impl Fn(&IFileDialog) -> HRESULT + 'static) -> Self {
+        self.on_folder_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_selection_change(mut self, f: impl Fn(&IFileDialog) -> HRESULT + 'static) -> Self {
+        self.on_selection_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_share_violation(
+        mut self,
+        f: impl Fn(&IFileDialog, &IShellItem) -> FDE_SHAREVIOLATION_RESPONSE + 'static,
+    ) -> Self {
+        self.on_share_violation = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_type_change(mut self, f: impl Fn(&IFileDialog) -> HRESULT + 'static) -> Self {
+        self.on_type_change = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_overwrite(
+        mut self,
+        f: impl Fn(&IFileDialog, &IShellItem) -> FDE_OVERWRITE_RESPONSE + 'static,
+    ) -> Self {
+        self.on_overwrite = Some(Box::new(f));
+        self
+    }
+
+    /// Called with the control and item ids.
+    pub fn on_item_selected(
+        mut self,
+        f: impl Fn(&IFileDialogCustomize, DWORD, DWORD) -> HRESULT + 'static,
+    ) -> Self {
+        self.on_item_selected = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_button_clicked(
+        mut self,
+        f: impl Fn(&IFileDialogCustomize, DWORD) -> HRESULT + 'static,
+    ) -> Self {
+        self.on_button_clicked = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_check_button_toggled(
+        mut self,
+        f: impl Fn(&IFileDialogCustomize, DWORD, bool) -> HRESULT + 'static,
+    ) -> Self {
+        self.on_check_button_toggled = Some(Box::new(f));
+        self
+    }
+
+    pub fn on_control_activating(
+        mut self,
+        f: impl Fn(&IFileDialogCustomize, DWORD) -> HRESULT + 'static,
+    ) -> Self {
+        self.on_control_activating = Some(Box::new(f));
+        self
+    }
+
+    pub fn build(self) -> ComPtr<IFileDialogEvents> {
+        let has_controls = self.on_item_selected.is_some()
+            || self.on_button_clicked.is_some()
+            || self.on_check_button_toggled.is_some()
+            || self.on_control_activating.is_some();
+
+        let control_events = if has_controls {
+            let ptr = ControlEvents::create_raw(
+                self.on_item_selected,
+                self.on_button_clicked,
+                self.on_check_button_toggled,
+                self.on_control_activating,
+            );
+            Some(unsafe { ComPtr::from_raw(ptr as *mut IFileDialogControlEvents) })
+        } else {
+            None
+        };
+
+        let ptr = Box::into_raw(Box::new(DialogEvents {
+            vtbl: <DialogEvents as BuildVTable<_>>::STATIC_VTABLE,
+            refcount: Default::default(),
+            on_file_ok: self.on_file_ok,
+            on_folder_changing: self.on_folder_changing,
+            on_folder_change: self.on_folder_change,
+            on_selection_change: self.on_selection_change,
+            on_share_violation: self.on_share_violation,
+            on_type_change: self.on_type_change,
+            on_overwrite: self.on_overwrite,
+            control_events,
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IFileDialogEvents) }
+    }
+}
+
+/// Not derived, because QueryInterface has to hand out `control_events` for
+/// `IFileDialogControlEvents`.
+#[repr(C)]
+struct DialogEvents {
+    vtbl: VTable<IFileDialogEventsVtbl>,
+    refcount: Refcount,
+    on_file_ok: Option<DialogFn>,
+    on_folder_changing: Option<ItemFn>,
+    on_folder_change: Option<DialogFn>,
+    on_selection_change: Option<DialogFn>,
+    on_share_violation: Option<ShareViolationFn>,
+    on_type_change: Option<DialogFn>,
+    on_overwrite: Option<OverwriteFn>,
+    control_events: Option<ComPtr<IFileDialogControlEvents>>,
+}
+
+unsafe impl BuildVTable<IUnknownVtbl> for DialogEvents {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: Self::query_interface,
+        AddRef: Self::add_ref,
+        Release: Self::release,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+impl DialogEvents {
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof()) || IsEqualIID(riid, &IFileDialogEvents::uuidof()) {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            return S_OK;
+        }
+
+        match &that.control_events {
+            Some(events) if IsEqualIID(riid, &IFileDialogControlEvents::uuidof()) => {
+                events.AddRef();
+                *ppv = events.as_raw() as *mut c_void;
+                S_OK
+            }
+            _ => {
+                *ppv = std::ptr::null_mut();
+                E_NOINTERFACE
+            }
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+}
+
+fn dispatch<F: ?Sized>(handler: &Option<Box<F>>, call: impl FnOnce(&F) -> HRESULT) -> HRESULT {
+    match handler {
+        Some(handler) => call(handler),
+        None => S_OK,
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IFileDialogEvents for DialogEvents {
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_file_ok(&self, dialog: *mut IFileDialog) -> HRESULT {
+        dispatch(&self.on_file_ok, |f| f(&*dialog))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_folder_changing(
+        &self,
+        dialog: *mut IFileDialog,
+        folder: *mut IShellItem,
+    ) -> HRESULT {
+        dispatch(&self.on_folder_changing, |f| f(&*dialog, &*folder))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_folder_change(&self, dialog: *mut IFileDialog) -> HRESULT {
+        dispatch(&self.on_folder_change, |f| f(&*dialog))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_selection_change(&self, dialog: *mut IFileDialog) -> HRESULT {
+        dispatch(&self.on_selection_change, |f| f(&*dialog))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_share_violation(
+        &self,
+        dialog: *mut IFileDialog,
+        item: *mut IShellItem,
+        response: *mut FDE_SHAREVIOLATION_RESPONSE,
+    ) -> HRESULT {
+        match &self.on_share_violation {
+            Some(f) => {
+                *response = f(&*dialog, &*item);
+                S_OK
+            }
+            None => E_NOTIMPL,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_type_change(&self, dialog: *mut IFileDialog) -> HRESULT {
+        dispatch(&self.on_type_change, |f| f(&*dialog))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_overwrite(
+        &self,
+        dialog: *mut IFileDialog,
+        item: *mut IShellItem,
+        response: *mut FDE_OVERWRITE_RESPONSE,
+    ) -> HRESULT {
+        match &self.on_overwrite {
+            Some(f) => {
+                *response = f(&*dialog, &*item);
+                S_OK
+            }
+            None => E_NOTIMPL,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct ControlEvents {
+    vtbl: VTable<IFileDialogControlEventsVtbl>,
+    refcount: Refcount,
+    on_item_selected: Option<ItemSelectedFn>,
+    on_button_clicked: Option<ControlFn>,
+    on_check_button_toggled: Option<CheckToggledFn>,
+    on_control_activating: Option<ControlFn>,
+}
+
+#[com_impl::com_impl]
+unsafe impl IFileDialogControlEvents for ControlEvents {
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_item_selected(
+        &self,
+        customize: *mut IFileDialogCustomize,
+        control: DWORD,
+        item: DWORD,
+    ) -> HRESULT {
+        dispatch(&self.on_item_selected, |f| f(&*customize, control, item))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_button_clicked(
+        &self,
+        customize: *mut IFileDialogCustomize,
+        control: DWORD,
+    ) -> HRESULT {
+        dispatch(&self.on_button_clicked, |f| f(&*customize, control))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_check_button_toggled(
+        &self,
+        customize: *mut IFileDialogCustomize,
+        control: DWORD,
+        checked: BOOL,
+    ) -> HRESULT {
+        dispatch(&self.on_check_button_toggled, |f| {
+            f(&*customize, control, checked != FALSE)
+        })
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn on_control_activating(
+        &self,
+        customize: *mut IFileDialogCustomize,
+        control: DWORD,
+    ) -> HRESULT {
+        dispatch(&self.on_control_activating, |f| f(&*customize, control))
+    }
+}
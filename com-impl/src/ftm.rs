@@ -0,0 +1,109 @@
+//! Agile objects, callable from any apartment through the free-threaded marshaler.
+//!
+//! A member marked `#[ftm]` on a `#[derive(ComImpl)]` struct makes `QueryInterface` answer
+//! `IMarshal` with the system's free-threaded marshaler, created by
+//! `CoCreateFreeThreadedMarshaler` on first use and aggregated by the object. Marshaling the
+//! object to another apartment then hands out the object itself rather than a proxy, so its
+//! methods must be safe to call from any thread.
+//!
+//! `#[com_impl(agile)]` on the struct, before the derive, adds such a member.
+//!
+//! ```no_run
+//! use com_impl::ftm::FreeThreadedMarshaler;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Counter {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     #[ftm]
+//!     marshaler: FreeThreadedMarshaler,
+//!     count: std::sync::atomic::AtomicU32,
+//! }
+//!
+//! #[repr(C)]
+//! #[com_impl::com_impl(agile)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Gauge {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     level: std::sync::atomic::AtomicU32,
+//! }
+//! ```
+
+use std::fmt;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, REFIID};
+use winapi::shared::winerror::{E_NOINTERFACE, HRESULT, SUCCEEDED};
+use winapi::um::combaseapi::CoCreateFreeThreadedMarshaler;
+use winapi::um::objidlbase::IMarshal;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+#[derive(Default)]
+/// The free-threaded marshaler aggregated by an agile object, once it has been asked for.
+pub struct FreeThreadedMarshaler {
+    inner: AtomicPtr<IUnknown>,
+}
+
+impl FreeThreadedMarshaler {
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]` to answer `IMarshal` for the object `outer`. `None` for
+    /// any other IID.
+    pub unsafe fn query(
+        &self,
+        outer: *mut IUnknown,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> Option<HRESULT> {
+        if !IsEqualIID(&*riid, &IMarshal::uuidof()) {
+            return None;
+        }
+        let mut inner = self.inner.load(Ordering::Acquire);
+        if inner.is_null() {
+            let mut created = ptr::null_mut();
+            let hr = CoCreateFreeThreadedMarshaler(outer, &mut created);
+            if !SUCCEEDED(hr) || created.is_null() {
+                *ppv = ptr::null_mut();
+                return Some(if SUCCEEDED(hr) { E_NOINTERFACE } else { hr });
+            }
+            // Another thread may have got there first
+            inner = match self.inner.compare_exchange(
+                ptr::null_mut(),
+                created,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => created,
+                Err(existing) => {
+                    (*created).Release();
+                    existing
+                }
+            };
+        }
+        Some((*inner).QueryInterface(riid, ppv))
+    }
+}
+
+impl Drop for FreeThreadedMarshaler {
+    fn drop(&mut self) {
+        let inner = *self.inner.get_mut();
+        if !inner.is_null() {
+            unsafe { (*inner).Release() };
+        }
+    }
+}
+
+impl fmt::Debug for FreeThreadedMarshaler {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let created = !self.inner.load(Ordering::Acquire).is_null();
+        fmt.debug_struct("FreeThreadedMarshaler")
+            .field("created", &created)
+            .finish()
+    }
+}
@@ -0,0 +1,225 @@
+//! Generating a C/C++ header for the classes and interfaces a crate implements.
+//!
+//! `#[com_impl(describe)]` records each method's name and C signature, which
+//! [`HeaderBuilder::interface`] turns into a declaration. Interfaces the Windows SDK already
+//! declares don't need to be described; list the CLSIDs and IIDs native callers need and
+//! leave the declarations to the SDK headers.
+//!
+//! The header is usually written by a small binary or test in the implementing crate, since
+//! the descriptions are only available once the crate is compiled.
+//!
+//! ```no_run
+//! use com_impl::header::HeaderBuilder;
+//! use winapi::shared::guiddef::GUID;
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//! # #[repr(C)]
+//! # #[derive(com_impl::ComImpl)]
+//! # pub struct FileStream {
+//! #     vtbl: com_impl::VTable<IDWriteFontFileStreamVtbl>,
+//! #     refcount: com_impl::Refcount,
+//! # }
+//! # #[com_impl::com_impl(describe)]
+//! # unsafe impl IDWriteFontFileStream for FileStream {
+//! #     fn get_file_size(&self, _: *mut u64) -> i32 { 0 }
+//! #     fn get_last_write_time(&self, _: *mut u64) -> i32 { 0 }
+//! #     fn read_file_fragment(
+//! #         &self, _: *mut *const winapi::ctypes::c_void, _: u64, _: u64,
+//! #         _: *mut *mut winapi::ctypes::c_void,
+//! #     ) -> i32 { 0 }
+//! #     fn release_file_fragment(&self, _: *mut winapi::ctypes::c_void) {}
+//! # }
+//!
+//! const CLSID_FILE_STREAM: GUID = GUID {
+//!     Data1: 0x3b1d_53a4,
+//!     Data2: 0x6f0e,
+//!     Data3: 0x4c5b,
+//!     Data4: [0x92, 0x1f, 0x7a, 0x10, 0xd2, 0x4e, 0x8c, 0x31],
+//! };
+//!
+//! HeaderBuilder::new()
+//!     .class("FileStream", &CLSID_FILE_STREAM)
+//!     .interface::<IDWriteFontFileStream, FileStream>()
+//!     .write("include/file_stream.h")
+//!     .unwrap();
+//! ```
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use winapi::shared::guiddef::GUID;
+use winapi::Interface;
+
+#[derive(Copy, Clone)]
+/// An interface as described by `#[com_impl(describe)]`.
+pub struct InterfaceInfo {
+    pub name: &'static str,
+    /// `None` for interfaces implemented with `no_parent`.
+    pub parent: Option<&'static str>,
+    /// The interface's own methods, in vtable order.
+    pub methods: &'static [MethodInfo],
+}
+
+#[derive(Copy, Clone)]
+pub struct MethodInfo {
+    pub name: &'static str,
+    /// The C return type.
+    pub ret: &'static str,
+    pub params: &'static [ParamInfo],
+}
+
+#[derive(Copy, Clone)]
+pub struct ParamInfo {
+    pub name: &'static str,
+    /// The C type.
+    pub ty: &'static str,
+}
+
+/// Implemented by `#[com_impl(describe)]` for the interface `I`.
+pub trait Describe<I: Interface> {
+    const INTERFACE: InterfaceInfo;
+}
+
+enum Item {
+    Guid { name: String, guid: GUID },
+    Interface { info: InterfaceInfo, iid: GUID },
+}
+
+#[derive(Default)]
+pub struct HeaderBuilder {
+    includes: Vec<String>,
+    items: Vec<Item>,
+}
+
+impl HeaderBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds `#include <header>`. `<unknwn.h>` is always included.
+    pub fn include(mut self, header: &str) -> Self {
+        self.includes.push(header.into());
+        self
+    }
+
+    /// Declares `CLSID_<name>`.
+    pub fn class(mut self, name: &str, clsid: &GUID) -> Self {
+        self.items.push(Item::Guid {
+            name: format!("CLSID_{}", name),
+            guid: *clsid,
+        });
+        self
+    }
+
+    /// Declares `IID_<I>` for an interface declared elsewhere, e.g. by the SDK.
+    pub fn iid<I: Interface>(mut self, name: &str) -> Self {
+        self.items.push(Item::Guid {
+            name: format!("IID_{}", name),
+            guid: I::uuidof(),
+        });
+        self
+    }
+
+    /// Declares `IID_<I>` and the interface itself, with the methods `T` implements.
+    pub fn interface<I: Interface, T: Describe<I>>(mut self) -> Self {
+        self.items.push(Item::Interface {
+            info: T::INTERFACE,
+            iid: I::uuidof(),
+        });
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let mut out = String::new();
+        out.push_str("// Generated by com-impl. Do not edit.\n\n");
+        out.push_str("#pragma once\n\n");
+        out.push_str("#include <unknwn.h>\n");
+        for include in &self.includes {
+            let _ = writeln!(out, "#include <{}>", include);
+        }
+        out.push('\n');
+
+        for item in &self.items {
+            match item {
+                Item::Guid { name, guid } => define_guid(&mut out, name, guid),
+                Item::Interface { info, iid } => {
+                    define_guid(&mut out, &format!("IID_{}", info.name), iid)
+                }
+            }
+        }
+
+        let interfaces = self.items.iter().filter_map(|item| match item {
+            Item::Interface { info, iid } => Some((info, iid)),
+            _ => None,
+        });
+        let mut first = true;
+        for (info, iid) in interfaces {
+            if first {
+                out.push_str("\n#ifdef __cplusplus\n");
+                first = false;
+            }
+            declare_interface(&mut out, info, iid);
+        }
+        if !first {
+            out.push_str("\n#endif\n");
+        }
+        out
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.build())
+    }
+}
+
+fn define_guid(out: &mut String, name: &str, guid: &GUID) {
+    let _ = writeln!(out, "// {{{}}}", uuid(guid));
+    let _ = write!(
+        out,
+        "DEFINE_GUID({}, 0x{:08x}, 0x{:04x}, 0x{:04x}",
+        name, guid.Data1, guid.Data2, guid.Data3
+    );
+    for byte in &guid.Data4 {
+        let _ = write!(out, ", 0x{:02x}", byte);
+    }
+    out.push_str(");\n");
+}
+
+fn declare_interface(out: &mut String, info: &InterfaceInfo, iid: &GUID) {
+    let _ = write!(out, "\nMIDL_INTERFACE(\"{}\") {}", uuid(iid), info.name);
+    if let Some(parent) = info.parent {
+        let _ = write!(out, " : public {}", parent);
+    }
+    out.push_str("\n{\npublic:\n");
+    for method in info.methods {
+        let params = method
+            .params
+            .iter()
+            .map(|p| format!("{} {}", p.ty, p.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            out,
+            "    virtual {} STDMETHODCALLTYPE {}({}) = 0;",
+            method.ret, method.name, params
+        );
+    }
+    out.push_str("};\n");
+}
+
+fn uuid(guid: &GUID) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        guid.Data1,
+        guid.Data2,
+        guid.Data3,
+        guid.Data4[0],
+        guid.Data4[1],
+        guid.Data4[2],
+        guid.Data4[3],
+        guid.Data4[4],
+        guid.Data4[5],
+        guid.Data4[6],
+        guid.Data4[7],
+    )
+}
@@ -0,0 +1,162 @@
+//! Repointing live objects at a reloaded implementation, for development loops.
+//!
+//! Objects created with `#[com_impl(hot_reload = SLOT)]` don't point at their type's vtable
+//! but at the [`VTableSlot`] `SLOT`, a static copy of it. After a new build of the
+//! implementation is loaded, repointing the slot at the new build's vtable sends every call,
+//! on objects old and new, to the new code. The slot has to stay where it is, so it belongs
+//! in the host or in the first version of the module, which is then kept loaded.
+//!
+//! The new code runs on objects created by the old. Members may only be added where the new
+//! build can still read objects laid out the old way, e.g. behind a lock holding an enum of
+//! state versions; [`Generation`] tells methods when that state needs migrating.
+//!
+//! ```no_run
+//! use com_impl::hot_reload::{Generation, VTableSlot};
+//! use com_impl::{BuildVTable, Refcount, VTable};
+//! use std::sync::Mutex;
+//! use winapi::shared::winerror::{HRESULT, S_OK};
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//! # use winapi::ctypes::c_void;
+//!
+//! pub static SLOT: VTableSlot<IDWriteFontFileStreamVtbl> =
+//!     VTableSlot::new(<FileStream as BuildVTable<_>>::VTBL);
+//!
+//! enum State {
+//!     V1(Vec<u8>),
+//!     V2 { data: Vec<u8>, reads: u32 },
+//! }
+//!
+//! #[com_impl::com_impl(hot_reload = SLOT)]
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[interfaces(IDWriteFontFileStream)]
+//! pub struct FileStream {
+//!     vtbl: VTable<IDWriteFontFileStreamVtbl>,
+//!     refcount: Refcount,
+//!     generation: Generation,
+//!     state: Mutex<State>,
+//! }
+//!
+//! impl FileStream {
+//!     fn state(&self) -> std::sync::MutexGuard<State> {
+//!         let mut state = self.state.lock().unwrap();
+//!         self.generation.migrate(&SLOT, |_| {
+//!             if let State::V1(data) = &mut *state {
+//!                 let data = std::mem::replace(data, Vec::new());
+//!                 *state = State::V2 { data, reads: 0 };
+//!             }
+//!         });
+//!         state
+//!     }
+//! }
+//!
+//! #[com_impl::com_impl]
+//! unsafe impl IDWriteFontFileStream for FileStream {
+//!     unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+//!         if let State::V2 { data, .. } = &*self.state() {
+//!             *size = data.len() as u64;
+//!         }
+//!         S_OK
+//!     }
+//!     // ...
+//! #   unsafe fn get_last_write_time(&self, _: *mut u64) -> HRESULT { S_OK }
+//! #   unsafe fn read_file_fragment(
+//! #       &self, _: *mut *const c_void, _: u64, _: u64, _: *mut *mut c_void,
+//! #   ) -> HRESULT { S_OK }
+//! #   unsafe fn release_file_fragment(&self, _: *mut c_void) {}
+//! }
+//!
+//! /// Exported by every build, so the host can hand the new vtable to the old slot.
+//! #[no_mangle]
+//! pub extern "C" fn file_stream_vtbl() -> &'static IDWriteFontFileStreamVtbl {
+//!     &<FileStream as BuildVTable<IDWriteFontFileStreamVtbl>>::VTBL
+//! }
+//! ```
+
+use std::cell::UnsafeCell;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::VTable;
+
+/// A vtable objects point at instead of their type's own, which can be repointed while they
+/// are alive.
+pub struct VTableSlot<T: 'static> {
+    table: UnsafeCell<T>,
+    generation: AtomicUsize,
+}
+
+unsafe impl<T: 'static> Sync for VTableSlot<T> {}
+
+impl<T: 'static> VTableSlot<T> {
+    /// A slot initially holding `vtbl`, usually `<Type as BuildVTable<_>>::VTBL`.
+    pub const fn new(vtbl: T) -> Self {
+        VTableSlot {
+            table: UnsafeCell::new(vtbl),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// A `VTable` for new objects, pointing at the slot.
+    pub fn vtable(&'static self) -> VTable<T> {
+        VTable {
+            ptr: self.table.get(),
+        }
+    }
+
+    /// How many times the slot has been repointed.
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Replaces the slot's entries with those of `vtbl` and returns the new generation.
+    ///
+    /// Entries are replaced one at a time, so a call made during the switch may still reach
+    /// the old version of some methods. Repointing the same slot from two threads at once
+    /// can leave it with a mix of both vtables.
+    ///
+    /// # Safety
+    ///
+    /// `vtbl` must be a vtable for the same interface whose functions accept every object
+    /// already pointing at the slot. They must stay loaded for as long as the slot points at
+    /// them.
+    pub unsafe fn repoint(&self, vtbl: &T) -> usize {
+        // Vtables are nothing but function pointers
+        assert_eq!(mem::size_of::<T>() % mem::size_of::<usize>(), 0);
+        let words = mem::size_of::<T>() / mem::size_of::<usize>();
+        let dst = self.table.get() as *const AtomicUsize;
+        let src = vtbl as *const T as *const usize;
+        for i in 0..words {
+            (*dst.add(i)).store(*src.add(i), Ordering::Release);
+        }
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+}
+
+#[derive(Debug)]
+/// The generation of a slot an object's state was last migrated to.
+pub struct Generation {
+    seen: AtomicUsize,
+}
+
+impl Generation {
+    /// Starts at the slot's current generation, for a new object.
+    pub fn current<T: 'static>(slot: &VTableSlot<T>) -> Self {
+        Generation {
+            seen: AtomicUsize::new(slot.generation()),
+        }
+    }
+
+    /// Calls `migrate` with the generation the object was last migrated to, if the slot
+    /// has been repointed since.
+    ///
+    /// Only the first caller after a repoint migrates. Call it while holding the lock
+    /// guarding the state, so other threads don't see the state before it is migrated.
+    pub fn migrate<T: 'static>(&self, slot: &VTableSlot<T>, migrate: impl FnOnce(usize)) {
+        let current = slot.generation();
+        let seen = self.seen.swap(current, Ordering::AcqRel);
+        if seen != current {
+            migrate(seen);
+        }
+    }
+}
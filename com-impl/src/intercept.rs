@@ -0,0 +1,161 @@
+//! Hooks run around every method generated by `#[com_impl(intercept)]`.
+//!
+//! The implementing type's own [`Interceptor`] and the one installed with [`set_global`] see
+//! each call before the method body runs and may answer it with an HRESULT instead, which makes
+//! them a single place for access checks, tracing or fault injection.
+//!
+//! ```no_run
+//! use com_impl::intercept::{Call, Interceptor};
+//! use com_impl::{Refcount, VTable};
+//! use winapi::shared::winerror::{E_ACCESSDENIED, HRESULT, S_OK};
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//! # use winapi::ctypes::c_void;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct FileStream {
+//!     vtbl: VTable<IDWriteFontFileStreamVtbl>,
+//!     refcount: Refcount,
+//!     locked: bool,
+//!     file_data: Vec<u8>,
+//! }
+//!
+//! impl Interceptor for FileStream {
+//!     fn before(&self, _call: &Call) -> Option<HRESULT> {
+//!         if self.locked {
+//!             Some(E_ACCESSDENIED)
+//!         } else {
+//!             None
+//!         }
+//!     }
+//! }
+//!
+//! #[com_impl::com_impl(intercept)]
+//! unsafe impl IDWriteFontFileStream for FileStream {
+//!     unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+//!         *size = self.file_data.len() as u64;
+//!         S_OK
+//!     }
+//!     // ...
+//! #   unsafe fn get_last_write_time(&self, _t: *mut u64) -> HRESULT { S_OK }
+//! #   unsafe fn read_file_fragment(
+//! #       &self, _s: *mut *const c_void, _o: u64, _z: u64, _c: *mut *mut c_void,
+//! #   ) -> HRESULT { S_OK }
+//! #   unsafe fn release_file_fragment(&self, _c: *mut c_void) {}
+//! }
+//! ```
+
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::HRESULT;
+
+#[derive(Copy, Clone, Debug)]
+/// The method being called.
+pub struct Call {
+    /// The interface name, e.g. `"IDWriteFontFileStream"`.
+    pub interface: &'static str,
+    /// The vtable member, e.g. `"GetFileSize"`.
+    pub method: &'static str,
+    /// The interface pointer the caller used.
+    pub this: *const c_void,
+}
+
+/// Runs around intercepted calls.
+pub trait Interceptor {
+    /// Called before the method body. Returning `Some` skips the body and returns the HRESULT
+    /// to the caller instead.
+    fn before(&self, call: &Call) -> Option<HRESULT> {
+        let _ = call;
+        None
+    }
+
+    /// Called after the method body, or after a `before` that answered the call. `result` is
+    /// `None` for methods that don't return an HRESULT.
+    fn after(&self, call: &Call, result: Option<HRESULT>) {
+        let _ = (call, result);
+    }
+}
+
+/// Return types of intercepted methods.
+pub trait CallResult {
+    /// The value returned when an interceptor answers the call with `hr`.
+    fn from_hresult(hr: HRESULT) -> Self;
+
+    fn hresult(&self) -> Option<HRESULT>;
+}
+
+impl CallResult for HRESULT {
+    fn from_hresult(hr: HRESULT) -> Self {
+        hr
+    }
+
+    fn hresult(&self) -> Option<HRESULT> {
+        Some(*self)
+    }
+}
+
+/// `AddRef`-style counts can't carry an error, so an answered call returns 0.
+impl CallResult for u32 {
+    fn from_hresult(_hr: HRESULT) -> Self {
+        0
+    }
+
+    fn hresult(&self) -> Option<HRESULT> {
+        None
+    }
+}
+
+impl CallResult for () {
+    fn from_hresult(_hr: HRESULT) {}
+
+    fn hresult(&self) -> Option<HRESULT> {
+        None
+    }
+}
+
+static GLOBAL: AtomicPtr<&'static (dyn Interceptor + Sync)> = AtomicPtr::new(ptr::null_mut());
+
+/// Installs an interceptor that runs for every intercepted call of every type, before the
+/// type's own. Replaces any previously installed one.
+pub fn set_global(interceptor: &'static (dyn Interceptor + Sync)) {
+    // The previous slot is leaked, as other threads may still be reading it
+    let slot = Box::into_raw(Box::new(interceptor));
+    GLOBAL.store(slot, Ordering::Release);
+}
+
+fn global() -> Option<&'static (dyn Interceptor + Sync)> {
+    let slot = GLOBAL.load(Ordering::Acquire);
+    if slot.is_null() {
+        None
+    } else {
+        Some(unsafe { *slot })
+    }
+}
+
+/// Used by the stubs of `#[com_impl(intercept)]`.
+#[doc(hidden)]
+pub fn invoke<T, R>(this: &T, call: Call, f: impl FnOnce() -> R) -> R
+where
+    T: Interceptor,
+    R: CallResult,
+{
+    let global = global();
+
+    let answered = match global.and_then(|g| g.before(&call)) {
+        Some(hr) => Some(hr),
+        None => this.before(&call),
+    };
+    let result = match answered {
+        Some(hr) => R::from_hresult(hr),
+        None => f(),
+    };
+
+    let hr = result.hresult();
+    this.after(&call, hr);
+    if let Some(global) = global {
+        global.after(&call, hr);
+    }
+    result
+}
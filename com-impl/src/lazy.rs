@@ -0,0 +1,76 @@
+//! Members created on first use instead of in `create_raw`.
+//!
+//! A [`Lazy`] member of a `#[derive(ComImpl)]` struct marked `#[lazy(EXPR)]` starts out empty
+//! and is not a parameter of `create_raw`. The derive adds a private inherent method of the
+//! same name returning a reference to the value, which evaluates `EXPR` on the first call,
+//! e.g. to open a file or create a D3D resource only once a COM method needs it. `EXPR` may
+//! use `self`. When calls race, one of them initializes the value and the others wait for it.
+//!
+//! ```no_run
+//! use com_impl::lazy::Lazy;
+//! use com_impl::{Refcount, VTable};
+//! use std::fs::File;
+//! use std::path::PathBuf;
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Log {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     path: PathBuf,
+//!     #[lazy(File::create(&self.path).expect("the log can't be created"))]
+//!     file: Lazy<File>,
+//! }
+//!
+//! impl Log {
+//!     fn write(&self, line: &str) {
+//!         use std::io::Write;
+//!         writeln!(&*self.file(), "{}", line).ok();
+//!     }
+//! }
+//!
+//! let log = Log::create_raw("app.log".into());
+//! unsafe { (*log).write("the file is created here") };
+//! ```
+
+use std::fmt;
+use std::sync::OnceLock;
+
+/// A value created by the first call to [`get_or_init`](Lazy::get_or_init).
+pub struct Lazy<T> {
+    cell: OnceLock<T>,
+}
+
+impl<T> Lazy<T> {
+    pub const fn new() -> Lazy<T> {
+        Lazy {
+            cell: OnceLock::new(),
+        }
+    }
+
+    /// The value, created with `init` if this is the first call.
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        self.cell.get_or_init(init)
+    }
+
+    /// The value, if it has been created.
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
+
+impl<T> Default for Lazy<T> {
+    fn default() -> Lazy<T> {
+        Lazy::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.get() {
+            Some(value) => fmt.debug_tuple("Lazy").field(value).finish(),
+            None => fmt.write_str("Lazy(<uninit>)"),
+        }
+    }
+}
@@ -79,9 +79,123 @@
 extern crate derive_com_impl;
 extern crate winapi;
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+// Lets the derive output, which names `::com_impl::...`, resolve inside this crate too.
+extern crate self as com_impl;
 
-pub use derive_com_impl::{com_impl, ComImpl};
+use std::any::{Any, TypeId};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+use std::sync::atomic::{self, AtomicPtr, AtomicUsize, Ordering};
+
+use winapi::shared::guiddef::IID;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+
+pub use derive_com_impl::{com_impl, ComImpl, ComVtbl};
+/// The interface pointer com-impl hands out, re-exported for the `query_interface` method the
+/// derive adds.
+pub use wio::com::ComPtr;
+
+#[cfg(feature = "aggregation")]
+pub mod aggregation;
+#[cfg(feature = "alloc")]
+pub mod alloc;
+#[cfg(feature = "apartment")]
+pub mod apartment;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "bind_status")]
+pub mod bind_status;
+#[cfg(feature = "bits")]
+pub mod bits;
+#[cfg(feature = "class_factory")]
+pub mod class_factory;
+#[cfg(feature = "command_target")]
+pub mod command_target;
+#[cfg(feature = "d2d1")]
+pub mod d2d1;
+#[cfg(feature = "data_binding")]
+pub mod data_binding;
+#[cfg(feature = "delegate")]
+pub mod delegate;
+#[cfg(feature = "dispatch")]
+pub mod dispatch;
+#[cfg(feature = "drag_drop")]
+pub mod drag_drop;
+#[cfg(feature = "dwrite")]
+pub mod dwrite;
+#[cfg(feature = "dynamic")]
+pub mod dynamic;
+#[cfg(feature = "file_dialog")]
+pub mod file_dialog;
+#[cfg(feature = "ftm")]
+pub mod ftm;
+#[cfg(feature = "header")]
+pub mod header;
+#[cfg(feature = "hot_reload")]
+pub mod hot_reload;
+#[cfg(feature = "intercept")]
+pub mod intercept;
+#[cfg(feature = "lazy")]
+pub mod lazy;
+#[cfg(feature = "local_server")]
+pub mod local_server;
+#[cfg(feature = "marshal")]
+pub mod marshal;
+#[cfg(feature = "media_foundation")]
+pub mod media_foundation;
+#[cfg(feature = "prelude")]
+pub mod prelude;
+#[cfg(feature = "propsys")]
+pub mod propsys;
+#[cfg(feature = "registration")]
+pub mod registration;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "server_lock")]
+pub mod server_lock;
+#[cfg(feature = "shell")]
+pub mod shell;
+#[cfg(feature = "site")]
+pub mod site;
+#[cfg(feature = "tear_off")]
+pub mod tear_off;
+#[cfg(feature = "token")]
+pub mod token;
+#[cfg(feature = "uia")]
+pub mod uia;
+#[cfg(feature = "variant")]
+pub mod variant;
+#[cfg(feature = "weak")]
+pub mod weak;
+#[cfg(feature = "webview2")]
+pub mod webview2;
+#[cfg(feature = "wic")]
+pub mod wic;
+#[cfg(feature = "windows_sys")]
+pub mod windows_sys;
+#[cfg(feature = "winrt_async")]
+pub mod winrt_async;
+#[cfg(feature = "wmi")]
+pub mod wmi;
+
+/// The winapi items the derive output names, so crates using the macros needn't depend on
+/// winapi, nor enable its features, themselves. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use winapi::ctypes::c_void;
+    pub use winapi::shared::guiddef::{IsEqualIID, IID};
+    pub use winapi::shared::winerror::{
+        CLASS_E_NOAGGREGATION, DISP_E_MEMBERNOTFOUND, E_NOINTERFACE, E_NOTIMPL, E_POINTER, HRESULT,
+        S_OK,
+    };
+    #[cfg(feature = "dispatch")]
+    pub use winapi::um::oaidl::IDispatchVtbl;
+    pub use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    pub use winapi::Interface;
+}
 
 #[repr(transparent)]
 /// Wrapper for the C++ VTable member of a COM object.
@@ -92,7 +206,7 @@ pub struct VTable<T> {
 }
 
 impl<T> VTable<T> {
-    pub fn new(ptr: &'static T) -> Self {
+    pub const fn new(ptr: &'static T) -> Self {
         VTable { ptr }
     }
 }
@@ -107,7 +221,393 @@ impl<T> std::fmt::Debug for VTable<T> {
 /// implements.
 pub unsafe trait BuildVTable<T: 'static> {
     const VTBL: T;
-    fn static_vtable() -> VTable<T>;
+
+    /// A `VTable` pointing at a promoted copy of `VTBL`, so the pointer is a link-time
+    /// constant even for generic implementors.
+    ///
+    /// This is a breaking change for implementations written by hand, which only had to give
+    /// `VTBL` before. They add `const STATIC_VTABLE: VTable<T> = VTable::new(&Self::VTBL);`.
+    /// The trait can't default it, since promoting `VTBL` to a static fails for a `T` that
+    /// might need dropping.
+    const STATIC_VTABLE: VTable<T>;
+
+    #[inline(always)]
+    fn static_vtable() -> VTable<T> {
+        Self::STATIC_VTABLE
+    }
+}
+
+/// Implemented by `#[derive(ComImpl)]` for each interface `QueryInterface` answers with the
+/// object's primary vtable, IUnknown included. Lets generic code require an interface at
+/// compile time, e.g. `fn register<T: ImplementsInterface<I>, I: Interface>(...)`.
+///
+/// # Safety
+///
+/// A pointer to the type must be usable as an `I`: its first member is a vtable laid out as,
+/// or starting with, `I`'s, whose `QueryInterface`, `AddRef` and `Release` count the object.
+pub unsafe trait ImplementsInterface<I: Interface> {
+    /// Takes ownership of one reference to `raw`, e.g. from `create_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must point to a live object, and the caller must own the reference handed over.
+    #[inline(always)]
+    unsafe fn into_com_ptr(raw: *mut Self) -> ComPtr<I>
+    where
+        Self: Sized,
+    {
+        ComPtr::from_raw(raw as *mut I)
+    }
+}
+
+/// Implemented by `#[derive(ComImpl)]` for each `family(I..=IN)` in `#[interfaces]`, where the
+/// type answers every version of an interface extended by derivation, e.g. `IDWriteFactory`
+/// through `IDWriteFactory3`.
+///
+/// # Safety
+///
+/// For every version up to `highest_supported`, `iid` must give an interface that
+/// `QueryInterface` answers with a vtable laid out as that version's.
+pub unsafe trait InterfaceFamily<I: Interface> {
+    /// The newest version implemented, e.g. 3 for `IDWriteFactory3`.
+    fn highest_supported() -> u32;
+
+    /// The IID of `version`, with 0 being `I` itself.
+    fn iid(version: u32) -> Option<IID>;
+}
+
+/// One reference to a derived COM object, as its concrete type, as returned by its `create`.
+/// It derefs to the struct, so Rust code keeps access to the object's members.
+///
+/// `adopt` and `leak` mark the points where a reference is handed to or taken back from code
+/// that stores raw pointers, e.g. a C callback registration that releases its pointer later.
+///
+/// ```
+/// use com_impl::{ComBox, Refcount, VTable};
+/// use winapi::um::unknwnbase::IUnknownVtbl;
+///
+/// #[repr(C)]
+/// #[derive(com_impl::ComImpl)]
+/// struct Cookie {
+///     vtbl: VTable<IUnknownVtbl>,
+///     refcount: Refcount,
+///     id: u32,
+/// }
+///
+/// let cookie = Cookie::create(7);
+///
+/// // Stored by a C API, which owns the reference from here on
+/// let raw = ComBox::leak_unknown(cookie.clone());
+///
+/// // Handed back when the registration ends
+/// let returned = unsafe { ComBox::adopt(raw as *mut Cookie) };
+/// assert_eq!(returned.id, cookie.id);
+/// ```
+pub struct ComBox<T: ImplementsInterface<IUnknown>> {
+    ptr: NonNull<T>,
+}
+
+impl<T: ImplementsInterface<IUnknown>> ComBox<T> {
+    /// Takes ownership of one reference to `ptr`, such as the one returned by `create_raw` or
+    /// one previously given away with [`leak`](ComBox::leak).
+    ///
+    /// Panics if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a live `T`, and the caller must own the reference handed over.
+    pub unsafe fn adopt(ptr: *mut T) -> ComBox<T> {
+        ComBox {
+            ptr: NonNull::new(ptr).expect("ComBox::adopt called with a null pointer"),
+        }
+    }
+
+    /// Gives up the reference without releasing it. Whoever receives the pointer is
+    /// responsible for calling `Release`, or for adopting it again.
+    pub fn leak(this: ComBox<T>) -> *mut T {
+        let ptr = this.ptr.as_ptr();
+        mem::forget(this);
+        ptr
+    }
+
+    /// [`leak`](ComBox::leak), as the `IUnknown` C APIs usually expect.
+    pub fn leak_unknown(this: ComBox<T>) -> *mut IUnknown {
+        ComBox::leak(this) as *mut IUnknown
+    }
+
+    /// Transfers the reference to a `ComPtr` for one of the object's interfaces.
+    pub fn into_com_ptr<I: Interface>(this: ComBox<T>) -> ComPtr<I>
+    where
+        T: ImplementsInterface<I>,
+    {
+        unsafe { T::into_com_ptr(ComBox::leak(this)) }
+    }
+
+    pub fn as_ptr(this: &ComBox<T>) -> *mut T {
+        this.ptr.as_ptr()
+    }
+
+    fn as_unknown(&self) -> &IUnknown {
+        unsafe { &*(self.ptr.as_ptr() as *const IUnknown) }
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> std::ops::Deref for ComBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> Clone for ComBox<T> {
+    fn clone(&self) -> Self {
+        unsafe {
+            self.as_unknown().AddRef();
+        }
+        ComBox { ptr: self.ptr }
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> Drop for ComBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.as_unknown().Release();
+        }
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> std::fmt::Debug for ComBox<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_tuple("ComBox").field(&self.ptr).finish()
+    }
+}
+
+/// A Rust-side weak handle to an `#[arc]` object, from its `create_raw_weak`. It observes the
+/// object without holding a COM reference, so caches of live objects don't keep them alive.
+///
+/// ```
+/// use com_impl::VTable;
+/// use winapi::um::unknwnbase::IUnknownVtbl;
+///
+/// #[repr(C)]
+/// #[derive(com_impl::ComImpl)]
+/// #[arc]
+/// struct Cookie {
+///     vtbl: VTable<IUnknownVtbl>,
+///     id: u32,
+/// }
+///
+/// let (cookie, weak) = Cookie::create_raw_weak(7);
+/// let cookie = unsafe { com_impl::ComBox::adopt(cookie) };
+/// assert_eq!(weak.upgrade().map(|cookie| cookie.id), Some(7));
+///
+/// drop(cookie);
+/// assert!(weak.upgrade().is_none());
+/// ```
+pub struct WeakCom<T> {
+    weak: std::sync::Weak<T>,
+}
+
+impl<T> WeakCom<T> {
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]`, whose `#[arc]` objects live in an `Arc`.
+    pub fn from_weak(weak: std::sync::Weak<T>) -> WeakCom<T> {
+        WeakCom { weak }
+    }
+
+    /// A reference to the object, if it is still alive.
+    pub fn upgrade(&self) -> Option<ComBox<T>>
+    where
+        T: ImplementsInterface<IUnknown>,
+    {
+        let object = self.weak.upgrade()?;
+        Some(unsafe { ComBox::adopt(std::sync::Arc::into_raw(object) as *mut T) })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.weak.strong_count() > 0
+    }
+}
+
+impl<T> Clone for WeakCom<T> {
+    fn clone(&self) -> Self {
+        WeakCom {
+            weak: self.weak.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for WeakCom<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct("WeakCom")
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+/// A `#[stack]` object, from its `create_stack`, living in the guard itself instead of on the
+/// heap. It is for callbacks that are only used during one call, e.g. enumeration sinks, and
+/// may borrow the caller's data. The guard holds the object's first reference; when it goes
+/// out of scope, the callee must have released all the references it added, or the process
+/// aborts, as the object would otherwise be used after its stack frame is gone.
+///
+/// ```
+/// use std::cell::Cell;
+///
+/// use com_impl::{LocalRefcount, StackCom, VTable};
+/// use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+///
+/// #[repr(C)]
+/// #[derive(com_impl::ComImpl)]
+/// #[stack]
+/// struct Visitor<'a> {
+///     vtbl: VTable<IUnknownVtbl>,
+///     refcount: LocalRefcount,
+///     visited: &'a Cell<u32>,
+/// }
+///
+/// // Stands in for an API that calls back into the sink during the call
+/// unsafe fn for_each_item(sink: *mut IUnknown) {
+///     (*sink).AddRef();
+///     (*sink).Release();
+/// }
+///
+/// let visited = Cell::new(0);
+/// let visitor = Visitor::create_stack(&visited);
+/// unsafe { for_each_item(StackCom::as_interface::<IUnknown>(&visitor)) };
+/// visitor.visited.set(visitor.visited.get() + 1);
+/// drop(visitor);
+/// assert_eq!(visited.get(), 1);
+/// ```
+pub struct StackCom<T: ImplementsInterface<IUnknown>> {
+    object: T,
+}
+
+impl<T: ImplementsInterface<IUnknown>> StackCom<T> {
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]`, whose `#[stack]` objects never free themselves.
+    pub unsafe fn new(object: T) -> StackCom<T> {
+        StackCom { object }
+    }
+
+    /// The object, valid while the guard is borrowed.
+    pub fn as_ptr(this: &StackCom<T>) -> *mut T {
+        &this.object as *const T as *mut T
+    }
+
+    /// The object as one of its interfaces, to pass to the call. The pointer carries no
+    /// reference of its own: a callee that keeps it calls `AddRef`, and has to `Release` it
+    /// again before the guard goes out of scope.
+    pub fn as_interface<I: Interface>(this: &StackCom<T>) -> *mut I
+    where
+        T: ImplementsInterface<I>,
+    {
+        StackCom::as_ptr(this) as *mut I
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> std::ops::Deref for StackCom<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.object
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> Drop for StackCom<T> {
+    fn drop(&mut self) {
+        // AddRef and Release read the count, which is back to the guard's own reference
+        let unknown = StackCom::as_interface::<IUnknown>(self);
+        let count = unsafe {
+            (*unknown).AddRef();
+            (*unknown).Release()
+        };
+        if count != 1 {
+            abort_on_panic(
+                b"A #[stack] COM object was still referenced at the end of its scope. Aborting!",
+            );
+        }
+    }
+}
+
+impl<T: ImplementsInterface<IUnknown>> std::fmt::Debug for StackCom<T> {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_tuple("StackCom")
+            .field(&StackCom::as_ptr(self))
+            .finish()
+    }
+}
+
+/// The reference count the IUnknown of a `#[derive(ComImpl)]` object keeps. `Refcount`,
+/// `LocalRefcount` and `weak::WeakRefcount` are found by their type; mark a member of any other
+/// implementing type `#[refcount]`. It is initialized with `Default`, which must hold the one
+/// reference `create_raw` returns.
+///
+/// ```
+/// use std::sync::atomic::{AtomicU32, Ordering};
+///
+/// use com_impl::{ComRefcount, Refcount, VTable};
+/// use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+///
+/// static ADD_REFS: AtomicU32 = AtomicU32::new(0);
+///
+/// /// Counts every AddRef made on any object, to find leaks.
+/// #[derive(Default)]
+/// struct Traced(Refcount);
+///
+/// unsafe impl ComRefcount for Traced {
+///     unsafe fn add_ref(&self) -> u32 {
+///         ADD_REFS.fetch_add(1, Ordering::Relaxed);
+///         self.0.add_ref()
+///     }
+///
+///     unsafe fn release(&self) -> u32 {
+///         self.0.release()
+///     }
+/// }
+///
+/// #[repr(C)]
+/// #[derive(com_impl::ComImpl)]
+/// struct Cookie {
+///     vtbl: VTable<IUnknownVtbl>,
+///     #[refcount]
+///     refcount: Traced,
+/// }
+///
+/// let cookie = Cookie::create_raw() as *mut IUnknown;
+/// unsafe {
+///     (*cookie).AddRef();
+///     (*cookie).Release();
+///     (*cookie).Release();
+/// }
+/// assert_eq!(ADD_REFS.load(Ordering::Relaxed), 1);
+/// ```
+///
+/// # Safety
+///
+/// The derived `IUnknown` frees the object when `release` returns 0, so the counts returned
+/// must follow the references handed out, as the methods describe.
+pub unsafe trait ComRefcount: Default {
+    /// Adds a reference, returning the new count.
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `AddRef` may call it, for a reference it hands out.
+    unsafe fn add_ref(&self) -> u32;
+
+    /// Releases a reference, returning the new count. The object is dropped when this returns
+    /// 0, so it must do so exactly once, and only after the other threads' uses of the object
+    /// happen before it, e.g. through an `Acquire` fence like `Refcount` has.
+    ///
+    /// Both methods wrap around rather than panic, so that the derived `#[refcount_check]` sees
+    /// an AddRef past `u32::MAX` as 0 and a Release past zero as `u32::MAX`.
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `Release` may call it, once for each reference, and drop the object
+    /// when it returns 0.
+    unsafe fn release(&self) -> u32;
 }
 
 #[derive(Debug)]
@@ -130,12 +630,353 @@ impl Refcount {
     #[inline]
     /// `fetch_add(1, Acquire) + 1`
     pub unsafe fn add_ref(&self) -> u32 {
-        self.count.fetch_add(1, Ordering::Acquire) as u32 + 1
+        (self.count.fetch_add(1, Ordering::Acquire) as u32).wrapping_add(1)
+    }
+
+    #[inline]
+    /// `fetch_sub(1, Release) - 1`, followed by an `Acquire` fence when the count reaches 0.
+    /// Like in `std::sync::Arc`, the fence makes everything other threads did with the object
+    /// before their final `Release` happen before the object is dropped.
+    pub unsafe fn release(&self) -> u32 {
+        let count = (self.count.fetch_sub(1, Ordering::Release) as u32).wrapping_sub(1);
+        if count == 0 {
+            atomic::fence(Ordering::Acquire);
+        }
+        count
+    }
+}
+
+unsafe impl ComRefcount for Refcount {
+    #[inline]
+    unsafe fn add_ref(&self) -> u32 {
+        Refcount::add_ref(self)
+    }
+
+    #[inline]
+    unsafe fn release(&self) -> u32 {
+        Refcount::release(self)
+    }
+}
+
+#[derive(Debug)]
+/// Non-atomic refcounter for objects that only ever live on one thread, such as those of a
+/// single-threaded apartment or DirectWrite callbacks made during one call. Used in place of
+/// `Refcount`, it saves the atomic operations of every `AddRef` and `Release`.
+///
+/// It is neither `Send` nor `Sync`, and so neither is the object containing it. Such an object
+/// can't have an `#[ftm]` member.
+pub struct LocalRefcount {
+    count: Cell<u32>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Default for LocalRefcount {
+    fn default() -> Self {
+        LocalRefcount {
+            count: Cell::new(1),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl LocalRefcount {
+    #[inline]
+    /// `count += 1`
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `AddRef` may call it, for a reference it hands out.
+    pub unsafe fn add_ref(&self) -> u32 {
+        let count = self.count.get().wrapping_add(1);
+        self.count.set(count);
+        count
     }
 
     #[inline]
-    /// `fetch_sub(1, Release) - 1`
+    /// `count -= 1`
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `Release` may call it, once for each reference, and drop the object
+    /// when it returns 0.
     pub unsafe fn release(&self) -> u32 {
-        self.count.fetch_sub(1, Ordering::Release) as u32 - 1
+        let count = self.count.get().wrapping_sub(1);
+        self.count.set(count);
+        count
+    }
+}
+
+unsafe impl ComRefcount for LocalRefcount {
+    #[inline]
+    unsafe fn add_ref(&self) -> u32 {
+        LocalRefcount::add_ref(self)
+    }
+
+    #[inline]
+    unsafe fn release(&self) -> u32 {
+        LocalRefcount::release(self)
+    }
+}
+
+#[doc(hidden)]
+/// Used by the `query_interface` method `#[derive(ComImpl)]` adds, which asks the object's own
+/// QueryInterface, so aggregated objects answer through their outer object.
+pub unsafe fn query_interface<I: Interface>(unknown: *mut IUnknown) -> Option<ComPtr<I>> {
+    let mut ptr = std::ptr::null_mut();
+    let hr = (*unknown).QueryInterface(&I::uuidof(), &mut ptr);
+    if hr < 0 || ptr.is_null() {
+        return None;
+    }
+    Some(ComPtr::from_raw(ptr as *mut I))
+}
+
+/// The IID `from_interface` asks for, as the start of a `TypeQuery`. Private to com-impl, so
+/// QueryInterface only meets it there.
+const TYPE_QUERY_IID: IID = IID {
+    Data1: 0x5275_7374,
+    Data2: 0x2a4c,
+    Data3: 0x4f6b,
+    Data4: [0x9d, 0x21, 0x63, 0x0e, 0x8b, 0x5f, 0xc4, 0x17],
+};
+
+#[repr(C)]
+/// What `from_interface` passes QueryInterface as the IID, followed by the type it looks for.
+struct TypeQuery {
+    iid: IID,
+    type_id: TypeId,
+}
+
+#[doc(hidden)]
+/// Used by the derived QueryInterface, to answer `from_interface` with an object of type `T`.
+/// `riid` is only read past the IID when it is `TYPE_QUERY_IID`, i.e. part of a `TypeQuery`.
+pub unsafe fn is_type_query<T: 'static>(riid: *const IID) -> bool {
+    winapi::shared::guiddef::IsEqualGUID(&*riid, &TYPE_QUERY_IID)
+        && (*(riid as *const TypeQuery)).type_id == TypeId::of::<T>()
+}
+
+#[doc(hidden)]
+/// Used by the `from_interface` method `#[derive(ComImpl)]` adds. The object stays alive
+/// through `ptr`, so the reference QueryInterface added is released right away.
+pub unsafe fn from_interface<T: 'static, I: Interface>(ptr: &ComPtr<I>) -> Option<&T> {
+    let query = TypeQuery {
+        iid: TYPE_QUERY_IID,
+        type_id: TypeId::of::<T>(),
+    };
+    let unknown = ptr.as_raw() as *mut IUnknown;
+    let mut object = std::ptr::null_mut();
+    let riid = &query as *const TypeQuery as *const IID;
+    let hr = (*unknown).QueryInterface(riid, &mut object);
+    if hr < 0 || object.is_null() {
+        return None;
+    }
+    (*(object as *mut IUnknown)).Release();
+    Some(&*(object as *const T))
+}
+
+#[derive(Copy, Clone, Debug)]
+/// A panic a `#[panic(result = ...)]` method caught, answering the call with its fallback.
+pub struct CaughtPanic<'a> {
+    /// The interface name, e.g. `"IDWriteFontFileStream"`.
+    pub interface: &'static str,
+    /// The vtable member, e.g. `"ReadFileFragment"`.
+    pub method: &'static str,
+    /// The message of a `panic!`, or `None` for other payloads.
+    pub message: Option<&'a str>,
+}
+
+/// The installed reporter, a `fn(&CaughtPanic)`, or null.
+static PANIC_REPORTER: AtomicPtr<()> = AtomicPtr::new(std::ptr::null_mut());
+
+/// Installs a function called with every panic a `#[panic(result = ...)]` method catches, just
+/// before the method returns its fallback, e.g. to log it. Replaces any previously installed
+/// one. The location of the panic is left to the panic hook, which runs before.
+///
+/// ```no_run
+/// com_impl::set_panic_reporter(|panic| {
+///     let message = panic.message.unwrap_or("Box<dyn Any>");
+///     eprintln!("{}::{} failed: {}", panic.interface, panic.method, message);
+/// });
+/// ```
+pub fn set_panic_reporter(reporter: fn(&CaughtPanic)) {
+    PANIC_REPORTER.store(reporter as *mut (), Ordering::Release);
+}
+
+#[cold]
+#[inline(never)]
+#[doc(hidden)]
+/// Used by the stubs of `#[panic(result = ...)]` methods, for panics answered with the fallback.
+pub fn report_panic(interface: &'static str, method: &'static str, payload: &(dyn Any + Send)) {
+    let reporter = PANIC_REPORTER.load(Ordering::Acquire);
+    if reporter.is_null() {
+        return;
     }
+    let message = match payload.downcast_ref::<&str>() {
+        Some(message) => Some(*message),
+        None => payload.downcast_ref::<String>().map(|message| &message[..]),
+    };
+    // Only set_panic_reporter stores to it, from a `fn(&CaughtPanic)`
+    let reporter = unsafe { mem::transmute::<*mut (), fn(&CaughtPanic)>(reporter) };
+    reporter(&CaughtPanic {
+        interface,
+        method,
+        message,
+    });
+}
+
+#[cold]
+#[inline(never)]
+#[doc(hidden)]
+/// Shared panic handler for `#[panic(abort)]` methods, `#[com_panic(abort)]` types and
+/// `#[refcount_check]`, so each generated stub only carries a call instead of its own copy of
+/// the reporting code.
+pub fn abort_on_panic(message: &[u8]) -> ! {
+    let stderr = std::io::stderr();
+    let _ = std::io::Write::write_all(&mut stderr.lock(), message);
+    std::process::abort();
+}
+
+#[doc(hidden)]
+/// The panic policy `#[com_panic(abort)]` sets for a type. The derive shadows the constant with
+/// an inherent one, which the stubs of `#[com_impl]` blocks see as `Self::__COM_IMPL_PANIC_ABORT`
+/// with this trait in scope, so types without the attribute keep the `false` of the blanket impl.
+pub trait PanicPolicy {
+    const __COM_IMPL_PANIC_ABORT: bool = false;
+}
+
+impl<T: ?Sized> PanicPolicy for T {}
+
+#[inline(always)]
+#[doc(hidden)]
+/// Calls `f` from a stub, catching its panic and aborting with `message` if `abort` is set.
+pub fn call_with_panic_policy<R>(abort: bool, message: &[u8], f: impl FnOnce() -> R) -> R {
+    if !abort {
+        return f();
+    }
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(_) => abort_on_panic(message),
+    }
+}
+
+#[doc(hidden)]
+/// Held by a stub that is the method itself, aborting with `message` if `abort` is set and the
+/// stub unwinds, which it can't catch without a closure around the body.
+pub struct AbortGuard {
+    abort: bool,
+    // A method called while another panic unwinds returns normally as well
+    unwinding: bool,
+    message: &'static [u8],
+}
+
+impl AbortGuard {
+    #[inline(always)]
+    pub fn new(abort: bool, message: &'static [u8]) -> Self {
+        let unwinding = abort && std::thread::panicking();
+        AbortGuard {
+            abort,
+            unwinding,
+            message,
+        }
+    }
+}
+
+impl Drop for AbortGuard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if self.abort && !self.unwinding && std::thread::panicking() {
+            abort_on_panic(self.message);
+        }
+    }
+}
+
+#[doc(hidden)]
+/// Fails to compile unless `T` fills in `V`, the vtable of `I`, for the interfaces listed in
+/// `#[interfaces]`: QueryInterface hands them out as pointers to the primary vtable.
+pub fn check_vtable<T: BuildVTable<V>, I, V: 'static>(_vtable_of: fn(&I) -> *const V) {}
+
+#[doc(hidden)]
+/// How many entries the vtable `V` has after those of its parent, the member `parent_of`
+/// returns, for `#[com_impl]` to check that its block fills in every one of them.
+pub const fn own_methods<V, P>(_parent_of: fn(&V) -> &P) -> usize {
+    (mem::size_of::<V>() - mem::size_of::<P>()) / mem::size_of::<VtblEntry>()
+}
+
+#[doc(hidden)]
+/// A vtable entry, by whose size `#[com_impl]` counts the methods of a vtable.
+pub type VtblEntry = unsafe extern "system" fn(*mut IUnknown) -> winapi::um::winnt::HRESULT;
+
+#[doc(hidden)]
+/// A vtable entry of the type `Self`, for the methods `#[com_impl]` stubs without a signature,
+/// which returns `NotImplementedResult::VALUE` without reading its arguments.
+pub trait Stub: Sized {
+    const STUB: Self;
+}
+
+#[doc(hidden)]
+/// The stub of the vtable entry it fills in, whose type it takes from the entry.
+pub const fn stub<F: Stub>() -> F {
+    F::STUB
 }
+
+#[doc(hidden)]
+/// What a stubbed method returns: `E_NOTIMPL` for an HRESULT, nothing for nothing, and zero or
+/// null otherwise.
+pub trait NotImplementedResult {
+    const VALUE: Self;
+}
+
+impl NotImplementedResult for () {
+    const VALUE: () = ();
+}
+
+impl NotImplementedResult for winapi::um::winnt::HRESULT {
+    // E_NOTIMPL
+    const VALUE: Self = 0x8000_4001_u32 as i32;
+}
+
+impl NotImplementedResult for u32 {
+    const VALUE: Self = 0;
+}
+
+impl NotImplementedResult for f32 {
+    const VALUE: Self = 0.0;
+}
+
+impl<T> NotImplementedResult for *mut T {
+    const VALUE: Self = std::ptr::null_mut();
+}
+
+impl<T> NotImplementedResult for *const T {
+    const VALUE: Self = std::ptr::null();
+}
+
+macro_rules! stub {
+    ($($arg:ident)*) => {
+        impl<This, $($arg,)* Ret: NotImplementedResult> Stub
+            for unsafe extern "system" fn(This, $($arg),*) -> Ret
+        {
+            const STUB: Self = {
+                unsafe extern "system" fn stub<This, $($arg,)* Ret: NotImplementedResult>(
+                    _: This,
+                    $(_: $arg,)*
+                ) -> Ret {
+                    Ret::VALUE
+                }
+                stub::<This, $($arg,)* Ret>
+            };
+        }
+    };
+}
+
+macro_rules! stubs {
+    () => {
+        stub!();
+    };
+    ($first:ident $($rest:ident)*) => {
+        stub!($first $($rest)*);
+        stubs!($($rest)*);
+    };
+}
+
+// Up to the 21 of IDWriteTextAnalyzer::GetGdiCompatibleGlyphPlacements, the most in winapi
+stubs!(A B C D E F G H I J K L M N O P Q R S T U V W X);
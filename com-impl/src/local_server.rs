@@ -0,0 +1,148 @@
+//! Running an executable as an out-of-process COM server.
+//!
+//! [`run_com_server`] registers the class factory of each class with COM, serves requests
+//! until the module lock of `com_impl::server_lock` has been taken and dropped back to zero,
+//! then revokes the factories and returns. Objects count on the lock through a
+//! `com_impl::server_lock::ServerLock` member, and clients through `LockServer`, so the
+//! server exits once the last object is released and the last lock is dropped.
+//!
+//! COM must be initialized on the calling thread. The thread pumps messages while serving,
+//! which a single-threaded apartment needs for its calls to arrive.
+//!
+//! ```no_run
+//! use com_impl::class_factory::ClassFactory;
+//! use com_impl::local_server::run_com_server;
+//! use com_impl::server_lock::ServerLock;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::shared::guiddef::GUID;
+//! use winapi::um::combaseapi::CoInitializeEx;
+//! use winapi::um::objbase::COINIT_APARTMENTTHREADED;
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! pub const CLSID_COUNTER: GUID = GUID {
+//!     Data1: 0x6c4f_0b2e,
+//!     Data2: 0x52a1,
+//!     Data3: 0x4d8e,
+//!     Data4: [0xb3, 0x07, 0x1e, 0x9a, 0x40, 0x5c, 0x77, 0xd2],
+//! };
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[class_factory]
+//! pub struct Counter {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     lock: ServerLock,
+//!     count: std::sync::atomic::AtomicU32,
+//! }
+//!
+//! fn main() {
+//!     unsafe { CoInitializeEx(std::ptr::null_mut(), COINIT_APARTMENTTHREADED) };
+//!     run_com_server(&[(&CLSID_COUNTER, ClassFactory::<Counter>::new)]).unwrap();
+//! }
+//! ```
+
+use std::mem;
+use std::ptr;
+use std::thread;
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::HRESULT;
+use winapi::shared::wtypesbase::CLSCTX_LOCAL_SERVER;
+use winapi::um::combaseapi::{
+    CoRegisterClassObject, CoResumeClassObjects, CoRevokeClassObject, REGCLS_MULTIPLEUSE,
+    REGCLS_SUSPENDED,
+};
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winuser::{
+    DispatchMessageW, GetMessageW, PeekMessageW, PostThreadMessageW, TranslateMessage, PM_NOREMOVE,
+    WM_QUIT,
+};
+
+use crate::class_factory::NewFactory;
+use crate::server_lock;
+
+/// Class factories registered with `CoRegisterClassObject`, revoked when dropped.
+///
+/// The factories are registered suspended, so no object is created before
+/// `CoResumeClassObjects` is called, usually by [`run_com_server`].
+#[derive(Debug)]
+pub struct LocalServer {
+    cookies: Vec<DWORD>,
+}
+
+impl LocalServer {
+    /// Registers the class factory of each class, for any number of clients.
+    ///
+    /// If a class can't be registered, the ones registered before it are revoked again.
+    pub fn register(classes: &[(&GUID, NewFactory)]) -> Result<LocalServer, HRESULT> {
+        let mut server = LocalServer {
+            cookies: Vec::with_capacity(classes.len()),
+        };
+        for (clsid, factory) in classes {
+            let factory = factory();
+            let mut cookie = 0;
+            let hr = unsafe {
+                CoRegisterClassObject(
+                    *clsid,
+                    factory.as_raw() as *mut IUnknown,
+                    CLSCTX_LOCAL_SERVER,
+                    REGCLS_MULTIPLEUSE | REGCLS_SUSPENDED,
+                    &mut cookie,
+                )
+            };
+            if hr < 0 {
+                return Err(hr);
+            }
+            server.cookies.push(cookie);
+        }
+        Ok(server)
+    }
+}
+
+impl Drop for LocalServer {
+    fn drop(&mut self) {
+        for &cookie in &self.cookies {
+            unsafe { CoRevokeClassObject(cookie) };
+        }
+    }
+}
+
+/// Serves `classes` until their objects and locks are gone, or the thread receives `WM_QUIT`.
+///
+/// A server nobody connects to keeps running, as the module lock is never taken.
+pub fn run_com_server(classes: &[(&GUID, NewFactory)]) -> Result<(), HRESULT> {
+    let server = LocalServer::register(classes)?;
+
+    unsafe {
+        // The thread needs its message queue before anything can be posted to it
+        let mut msg = mem::zeroed();
+        PeekMessageW(&mut msg, ptr::null_mut(), 0, 0, PM_NOREMOVE);
+    }
+    let thread = unsafe { GetCurrentThreadId() };
+    let seen = server_lock::releases();
+
+    let hr = unsafe { CoResumeClassObjects() };
+    if hr < 0 {
+        return Err(hr);
+    }
+
+    // Left blocked if the loop ends for another reason; it holds nothing
+    thread::spawn(move || {
+        server_lock::wait_for_release(seen);
+        unsafe { PostThreadMessageW(thread, WM_QUIT, 0, 0) };
+    });
+
+    unsafe {
+        let mut msg = mem::zeroed();
+        while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    drop(server);
+    Ok(())
+}
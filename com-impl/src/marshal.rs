@@ -0,0 +1,303 @@
+//! Marshal-by-value, for objects that are copied to other apartments and processes rather than
+//! called through a proxy.
+//!
+//! `#[marshal_by_value]` on a `#[derive(ComImpl)]` struct makes `QueryInterface` answer
+//! `IMarshal` for a type implementing [`MarshalByValue`]. Marshaling the object writes the
+//! state [`MarshalByValue::marshal`] gives into the stream. The receiving side creates an
+//! instance of [`MarshalByValue::CLSID`], usually the type's own class registered with a
+//! `com_impl::class_factory::ClassFactory`, and asks it to unmarshal the stream, which
+//! creates a new object with [`MarshalByValue::unmarshal`].
+//!
+//! This suits small, immutable objects such as error information or data carriers, whose
+//! copies can't be told apart from the original. The state is copied for every destination,
+//! including other apartments of the same process.
+//!
+//! ```no_run
+//! use com_impl::marshal::MarshalByValue;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::shared::guiddef::{CLSID, GUID};
+//! use winapi::shared::winerror::{E_INVALIDARG, HRESULT};
+//! use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+//! use wio::com::ComPtr;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[class_factory]
+//! #[marshal_by_value]
+//! pub struct Point {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     x: i32,
+//!     y: i32,
+//! }
+//!
+//! impl MarshalByValue for Point {
+//!     const CLSID: CLSID = GUID {
+//!         Data1: 0x2f7c_5a1e,
+//!         Data2: 0x4b1d,
+//!         Data3: 0x4c3a,
+//!         Data4: [0x9e, 0x61, 0x0d, 0x52, 0x7a, 0x13, 0xc4, 0x88],
+//!     };
+//!
+//!     fn marshal(&self, data: &mut Vec<u8>) -> Result<(), HRESULT> {
+//!         data.extend_from_slice(&self.x.to_le_bytes());
+//!         data.extend_from_slice(&self.y.to_le_bytes());
+//!         Ok(())
+//!     }
+//!
+//!     fn unmarshal(data: &[u8]) -> Result<ComPtr<IUnknown>, HRESULT> {
+//!         if data.len() != 8 {
+//!             return Err(E_INVALIDARG);
+//!         }
+//!         let x = i32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+//!         let y = i32::from_le_bytes([data[4], data[5], data[6], data[7]]);
+//!         let ptr = Point::create_raw(x, y);
+//!         Ok(unsafe { ComPtr::from_raw(ptr as *mut IUnknown) })
+//!     }
+//! }
+//! ```
+
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, CLSID, REFIID};
+use winapi::shared::minwindef::{DWORD, ULONG};
+use winapi::shared::winerror::{
+    E_FAIL, E_POINTER, FAILED, HRESULT, STG_E_MEDIUMFULL, STG_E_READFAULT, S_OK,
+};
+use winapi::um::objidlbase::{IMarshal, IMarshalVtbl, IStream, STREAM_SEEK_CUR};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::LARGE_INTEGER;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+/// The state of a `#[marshal_by_value]` object, and how to recreate the object from it.
+pub trait MarshalByValue: Sized + 'static {
+    /// The class the receiving side creates to unmarshal the object. Its objects must answer
+    /// `IMarshal` like this type's do, which is simplest when it is this type's own class.
+    const CLSID: CLSID;
+
+    /// Appends the state of the object to `data`.
+    fn marshal(&self, data: &mut Vec<u8>) -> Result<(), HRESULT>;
+
+    /// Creates a copy of the object from the state `marshal` wrote.
+    fn unmarshal(data: &[u8]) -> Result<ComPtr<IUnknown>, HRESULT>;
+}
+
+#[doc(hidden)]
+/// Used by `#[derive(ComImpl)]` to answer `IMarshal` for the object `this`. `None` for any
+/// other IID.
+pub unsafe fn query<T: MarshalByValue>(
+    this: *mut IUnknown,
+    riid: REFIID,
+    ppv: *mut *mut c_void,
+) -> Option<HRESULT> {
+    if !IsEqualIID(&*riid, &IMarshal::uuidof()) {
+        return None;
+    }
+    (*this).AddRef();
+    *ppv = Marshaler::<T>::create_raw(this as *const T) as *mut c_void;
+    Some(S_OK)
+}
+
+#[com_impl::com_impl(iunknown = manual)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IMarshal)]
+/// The `IMarshal` of an object, created for each query. Its IUnknown is the object's, and it
+/// holds a reference to the object.
+struct Marshaler<T: MarshalByValue> {
+    vtbl: VTable<IMarshalVtbl>,
+    refcount: Refcount,
+    owner: *const T,
+}
+
+impl<T: MarshalByValue> Marshaler<T> {
+    fn owner(&self) -> *mut IUnknown {
+        self.owner as *mut IUnknown
+    }
+
+    unsafe fn state(&self) -> Result<Vec<u8>, HRESULT> {
+        let mut data = Vec::new();
+        (*self.owner).marshal(&mut data)?;
+        if data.len() > ULONG::MAX as usize - mem::size_of::<ULONG>() {
+            return Err(STG_E_MEDIUMFULL);
+        }
+        Ok(data)
+    }
+}
+
+#[com_impl::com_impl(no_parent)]
+unsafe impl<T: MarshalByValue> IUnknown for Marshaler<T> {
+    unsafe fn query_interface(&self, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+        (*self.owner()).QueryInterface(riid, ppv)
+    }
+
+    unsafe fn add_ref(&self) -> ULONG {
+        self.refcount.add_ref()
+    }
+
+    unsafe fn release(&self) -> ULONG {
+        let count = self.refcount.release();
+        if count == 0 {
+            (*self.owner()).Release();
+            mem::drop(Box::from_raw(self as *const Self as *mut Self));
+        }
+        count
+    }
+}
+
+unsafe fn write(stream: *mut IStream, data: &[u8]) -> HRESULT {
+    let mut written = 0;
+    let hr = (*stream).Write(
+        data.as_ptr() as *const c_void,
+        data.len() as ULONG,
+        &mut written,
+    );
+    if FAILED(hr) {
+        hr
+    } else if written as usize != data.len() {
+        STG_E_MEDIUMFULL
+    } else {
+        S_OK
+    }
+}
+
+unsafe fn read(stream: *mut IStream, data: &mut [u8]) -> HRESULT {
+    let mut read = 0;
+    let hr = (*stream).Read(
+        data.as_mut_ptr() as *mut c_void,
+        data.len() as ULONG,
+        &mut read,
+    );
+    if FAILED(hr) {
+        hr
+    } else if read as usize != data.len() {
+        STG_E_READFAULT
+    } else {
+        S_OK
+    }
+}
+
+/// Reads the length written before the state.
+unsafe fn read_len(stream: *mut IStream) -> Result<ULONG, HRESULT> {
+    let mut len = [0; 4];
+    match read(stream, &mut len) {
+        S_OK => Ok(ULONG::from_le_bytes(len)),
+        hr => Err(hr),
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: MarshalByValue> IMarshal for Marshaler<T> {
+    unsafe fn get_unmarshal_class(
+        &self,
+        _riid: REFIID,
+        _pv: *mut c_void,
+        _dest_context: DWORD,
+        _pv_dest_context: *mut c_void,
+        _flags: DWORD,
+        clsid: *mut CLSID,
+    ) -> HRESULT {
+        if clsid.is_null() {
+            return E_POINTER;
+        }
+        *clsid = T::CLSID;
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_marshal_size_max(
+        &self,
+        _riid: REFIID,
+        _pv: *mut c_void,
+        _dest_context: DWORD,
+        _pv_dest_context: *mut c_void,
+        _flags: DWORD,
+        size: *mut DWORD,
+    ) -> HRESULT {
+        if size.is_null() {
+            return E_POINTER;
+        }
+        match self.state() {
+            Ok(data) => {
+                *size = (mem::size_of::<ULONG>() + data.len()) as DWORD;
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn marshal_interface(
+        &self,
+        stream: *mut IStream,
+        _riid: REFIID,
+        _pv: *mut c_void,
+        _dest_context: DWORD,
+        _pv_dest_context: *mut c_void,
+        _flags: DWORD,
+    ) -> HRESULT {
+        if stream.is_null() {
+            return E_POINTER;
+        }
+        let data = match self.state() {
+            Ok(data) => data,
+            Err(hr) => return hr,
+        };
+        match write(stream, &(data.len() as ULONG).to_le_bytes()) {
+            S_OK => write(stream, &data),
+            hr => hr,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn unmarshal_interface(
+        &self,
+        stream: *mut IStream,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        *ppv = ptr::null_mut();
+        if stream.is_null() {
+            return E_POINTER;
+        }
+        let len = match read_len(stream) {
+            Ok(len) => len,
+            Err(hr) => return hr,
+        };
+        let mut data = vec![0; len as usize];
+        match read(stream, &mut data) {
+            S_OK => (),
+            hr => return hr,
+        }
+        match T::unmarshal(&data) {
+            Ok(object) => object.QueryInterface(riid, ppv),
+            Err(hr) => hr,
+        }
+    }
+
+    unsafe fn release_marshal_data(&self, stream: *mut IStream) -> HRESULT {
+        if stream.is_null() {
+            return E_POINTER;
+        }
+        // Nothing was kept for the state, which only needs skipping
+        let len = match read_len(stream) {
+            Ok(len) => len,
+            Err(hr) => return hr,
+        };
+        let mut offset: LARGE_INTEGER = mem::zeroed();
+        *offset.QuadPart_mut() = i64::from(len);
+        (*stream).Seek(offset, STREAM_SEEK_CUR, ptr::null_mut())
+    }
+
+    fn disconnect_object(&self, _reserved: DWORD) -> HRESULT {
+        S_OK
+    }
+}
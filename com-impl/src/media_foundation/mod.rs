@@ -0,0 +1,376 @@
+//! Closure-based `IMFAsyncCallback` for Media Foundation async operations. See [`transform`]
+//! for writing Media Foundation transforms.
+//!
+//! ```no_run
+//! use com_impl::media_foundation::{AsyncCallbackBuilder, MFASYNC_CALLBACK_QUEUE_MULTITHREADED};
+//!
+//! let callback = AsyncCallbackBuilder::new()
+//!     .queue(MFASYNC_CALLBACK_QUEUE_MULTITHREADED)
+//!     .build(|result| {
+//!         let status = unsafe { result.GetStatus() };
+//!         println!("finished with {:#x}", status);
+//!     });
+//!
+//! // Pass callback.as_raw() to BeginGetEvent, BeginReadSample, MFPutWorkItem, ...
+//! ```
+//!
+//! Media Foundation is not covered by winapi, so the interfaces used here are defined in this
+//! module.
+
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{E_FAIL, E_NOTIMPL, E_POINTER, HRESULT, S_OK};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub use self::ffi::{
+    IMFAsyncCallback, IMFAsyncCallbackVtbl, IMFAsyncResult, IMFAsyncResultVtbl, IMFAttributes,
+    IMFAttributesVtbl, IMFMediaBuffer, IMFMediaBufferVtbl, IMFMediaType, IMFMediaTypeVtbl,
+    IMFSample, IMFSampleVtbl,
+};
+pub use self::ffi::{MFCreateMediaType, MFCreateMemoryBuffer, MFCreateSample};
+
+pub mod transform;
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::ctypes::c_void;
+    use winapi::shared::guiddef::{GUID, REFGUID};
+    use winapi::shared::minwindef::{BOOL, BYTE, DWORD};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::propidl::{PROPVARIANT, REFPROPVARIANT};
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::{LONGLONG, LPCWSTR, LPWSTR};
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0xac6b7889, 0x0740, 0x4d51, 0x86, 0x19, 0x90, 0x59, 0x94, 0xa5, 0x5c, 0xc6)]
+    interface IMFAsyncResult(IMFAsyncResultVtbl): IUnknown(IUnknownVtbl) {
+        fn GetState(
+            ppunkState: *mut *mut IUnknown,
+        ) -> HRESULT,
+        fn GetStatus() -> HRESULT,
+        fn SetStatus(
+            hrStatus: HRESULT,
+        ) -> HRESULT,
+        fn GetObject(
+            ppObject: *mut *mut IUnknown,
+        ) -> HRESULT,
+        fn GetStateNoAddRef() -> *mut IUnknown,
+    }}
+
+    RIDL! {#[uuid(0xa27003cf, 0x2354, 0x4f2a, 0x8d, 0x6a, 0xab, 0x7c, 0xff, 0x15, 0x43, 0x7e)]
+    interface IMFAsyncCallback(IMFAsyncCallbackVtbl): IUnknown(IUnknownVtbl) {
+        fn GetParameters(
+            pdwFlags: *mut DWORD,
+            pdwQueue: *mut DWORD,
+        ) -> HRESULT,
+        fn Invoke(
+            pAsyncResult: *mut IMFAsyncResult,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x2cd2d921, 0xc447, 0x44a7, 0xa1, 0x3c, 0x4a, 0xda, 0xbf, 0xc2, 0x47, 0xe3)]
+    interface IMFAttributes(IMFAttributesVtbl): IUnknown(IUnknownVtbl) {
+        fn GetItem(
+            guidKey: REFGUID,
+            pValue: *mut PROPVARIANT,
+        ) -> HRESULT,
+        fn GetItemType(
+            guidKey: REFGUID,
+            pType: *mut DWORD,
+        ) -> HRESULT,
+        fn CompareItem(
+            guidKey: REFGUID,
+            Value: REFPROPVARIANT,
+            pbResult: *mut BOOL,
+        ) -> HRESULT,
+        fn Compare(
+            pTheirs: *mut IMFAttributes,
+            MatchType: DWORD,
+            pbResult: *mut BOOL,
+        ) -> HRESULT,
+        fn GetUINT32(
+            guidKey: REFGUID,
+            punValue: *mut u32,
+        ) -> HRESULT,
+        fn GetUINT64(
+            guidKey: REFGUID,
+            punValue: *mut u64,
+        ) -> HRESULT,
+        fn GetDouble(
+            guidKey: REFGUID,
+            pfValue: *mut f64,
+        ) -> HRESULT,
+        fn GetGUID(
+            guidKey: REFGUID,
+            pguidValue: *mut GUID,
+        ) -> HRESULT,
+        fn GetStringLength(
+            guidKey: REFGUID,
+            pcchLength: *mut u32,
+        ) -> HRESULT,
+        fn GetString(
+            guidKey: REFGUID,
+            pwszValue: LPWSTR,
+            cchBufSize: u32,
+            pcchLength: *mut u32,
+        ) -> HRESULT,
+        fn GetAllocatedString(
+            guidKey: REFGUID,
+            ppwszValue: *mut LPWSTR,
+            pcchLength: *mut u32,
+        ) -> HRESULT,
+        fn GetBlobSize(
+            guidKey: REFGUID,
+            pcbBlobSize: *mut u32,
+        ) -> HRESULT,
+        fn GetBlob(
+            guidKey: REFGUID,
+            pBuf: *mut u8,
+            cbBufSize: u32,
+            pcbBlobSize: *mut u32,
+        ) -> HRESULT,
+        fn GetAllocatedBlob(
+            guidKey: REFGUID,
+            ppBuf: *mut *mut u8,
+            pcbSize: *mut u32,
+        ) -> HRESULT,
+        fn GetUnknown(
+            guidKey: REFGUID,
+            riid: REFGUID,
+            ppv: *mut *mut c_void,
+        ) -> HRESULT,
+        fn SetItem(
+            guidKey: REFGUID,
+            Value: REFPROPVARIANT,
+        ) -> HRESULT,
+        fn DeleteItem(
+            guidKey: REFGUID,
+        ) -> HRESULT,
+        fn DeleteAllItems() -> HRESULT,
+        fn SetUINT32(
+            guidKey: REFGUID,
+            unValue: u32,
+        ) -> HRESULT,
+        fn SetUINT64(
+            guidKey: REFGUID,
+            unValue: u64,
+        ) -> HRESULT,
+        fn SetDouble(
+            guidKey: REFGUID,
+            fValue: f64,
+        ) -> HRESULT,
+        fn SetGUID(
+            guidKey: REFGUID,
+            guidValue: REFGUID,
+        ) -> HRESULT,
+        fn SetString(
+            guidKey: REFGUID,
+            wszValue: LPCWSTR,
+        ) -> HRESULT,
+        fn SetBlob(
+            guidKey: REFGUID,
+            pBuf: *const u8,
+            cbBufSize: u32,
+        ) -> HRESULT,
+        fn SetUnknown(
+            guidKey: REFGUID,
+            pUnknown: *mut IUnknown,
+        ) -> HRESULT,
+        fn LockStore() -> HRESULT,
+        fn UnlockStore() -> HRESULT,
+        fn GetCount(
+            pcItems: *mut u32,
+        ) -> HRESULT,
+        fn GetItemByIndex(
+            unIndex: u32,
+            pguidKey: *mut GUID,
+            pValue: *mut PROPVARIANT,
+        ) -> HRESULT,
+        fn CopyAllItems(
+            pDest: *mut IMFAttributes,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x44ae0fa8, 0xea31, 0x4109, 0x8d, 0x2e, 0x4c, 0xae, 0x49, 0x97, 0xc5, 0x55)]
+    interface IMFMediaType(IMFMediaTypeVtbl): IMFAttributes(IMFAttributesVtbl) {
+        fn GetMajorType(
+            pguidMajorType: *mut GUID,
+        ) -> HRESULT,
+        fn IsCompressedFormat(
+            pfCompressed: *mut BOOL,
+        ) -> HRESULT,
+        fn IsEqual(
+            pIMediaType: *mut IMFMediaType,
+            pdwFlags: *mut DWORD,
+        ) -> HRESULT,
+        fn GetRepresentation(
+            guidRepresentation: GUID,
+            ppvRepresentation: *mut *mut c_void,
+        ) -> HRESULT,
+        fn FreeRepresentation(
+            guidRepresentation: GUID,
+            pvRepresentation: *mut c_void,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x045fa593, 0x8799, 0x42b8, 0xbc, 0x8d, 0x89, 0x68, 0xc6, 0x45, 0x35, 0x07)]
+    interface IMFMediaBuffer(IMFMediaBufferVtbl): IUnknown(IUnknownVtbl) {
+        fn Lock(
+            ppbBuffer: *mut *mut BYTE,
+            pcbMaxLength: *mut DWORD,
+            pcbCurrentLength: *mut DWORD,
+        ) -> HRESULT,
+        fn Unlock() -> HRESULT,
+        fn GetCurrentLength(
+            pcbCurrentLength: *mut DWORD,
+        ) -> HRESULT,
+        fn SetCurrentLength(
+            cbCurrentLength: DWORD,
+        ) -> HRESULT,
+        fn GetMaxLength(
+            pcbMaxLength: *mut DWORD,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xc40a00f2, 0xb93a, 0x4d80, 0xae, 0x8c, 0x5a, 0x1c, 0x63, 0x4f, 0x58, 0xe4)]
+    interface IMFSample(IMFSampleVtbl): IMFAttributes(IMFAttributesVtbl) {
+        fn GetSampleFlags(
+            pdwSampleFlags: *mut DWORD,
+        ) -> HRESULT,
+        fn SetSampleFlags(
+            dwSampleFlags: DWORD,
+        ) -> HRESULT,
+        fn GetSampleTime(
+            phnsSampleTime: *mut LONGLONG,
+        ) -> HRESULT,
+        fn SetSampleTime(
+            hnsSampleTime: LONGLONG,
+        ) -> HRESULT,
+        fn GetSampleDuration(
+            phnsSampleDuration: *mut LONGLONG,
+        ) -> HRESULT,
+        fn SetSampleDuration(
+            hnsSampleDuration: LONGLONG,
+        ) -> HRESULT,
+        fn GetBufferCount(
+            pdwBufferCount: *mut DWORD,
+        ) -> HRESULT,
+        fn GetBufferByIndex(
+            dwIndex: DWORD,
+            ppBuffer: *mut *mut IMFMediaBuffer,
+        ) -> HRESULT,
+        fn ConvertToContiguousBuffer(
+            ppBuffer: *mut *mut IMFMediaBuffer,
+        ) -> HRESULT,
+        fn AddBuffer(
+            pBuffer: *mut IMFMediaBuffer,
+        ) -> HRESULT,
+        fn RemoveBufferByIndex(
+            dwIndex: DWORD,
+        ) -> HRESULT,
+        fn RemoveAllBuffers() -> HRESULT,
+        fn GetTotalLength(
+            pcbTotalLength: *mut DWORD,
+        ) -> HRESULT,
+        fn CopyToBuffer(
+            pBuffer: *mut IMFMediaBuffer,
+        ) -> HRESULT,
+    }}
+
+    #[link(name = "mfplat")]
+    extern "system" {
+        pub fn MFCreateSample(ppIMFSample: *mut *mut IMFSample) -> HRESULT;
+        pub fn MFCreateMemoryBuffer(
+            cbMaxLength: DWORD,
+            ppBuffer: *mut *mut IMFMediaBuffer,
+        ) -> HRESULT;
+        pub fn MFCreateMediaType(ppMFType: *mut *mut IMFMediaType) -> HRESULT;
+    }
+}
+
+pub const MFASYNC_CALLBACK_QUEUE_STANDARD: DWORD = 0x0000_0001;
+pub const MFASYNC_CALLBACK_QUEUE_RT: DWORD = 0x0000_0002;
+pub const MFASYNC_CALLBACK_QUEUE_IO: DWORD = 0x0000_0003;
+pub const MFASYNC_CALLBACK_QUEUE_TIMER: DWORD = 0x0000_0004;
+pub const MFASYNC_CALLBACK_QUEUE_MULTITHREADED: DWORD = 0x0000_0005;
+pub const MFASYNC_CALLBACK_QUEUE_LONG_FUNCTION: DWORD = 0x0000_0007;
+
+pub const MFASYNC_FAST_IO_PROCESSING_CALLBACK: DWORD = 0x0000_0001;
+pub const MFASYNC_SIGNAL_CALLBACK: DWORD = 0x0000_0002;
+pub const MFASYNC_BLOCKING_CALLBACK: DWORD = 0x0000_0004;
+pub const MFASYNC_REPLY_CALLBACK: DWORD = 0x0000_0008;
+
+type InvokeFn = Box<dyn Fn(&IMFAsyncResult) + Send + Sync>;
+
+#[derive(Copy, Clone, Debug, Default)]
+/// Configures the work queue and flags reported by `GetParameters`.
+///
+/// If neither is set, `GetParameters` returns `E_NOTIMPL` and Media Foundation uses the
+/// standard queue with no flags.
+pub struct AsyncCallbackBuilder {
+    queue: Option<DWORD>,
+    flags: Option<DWORD>,
+}
+
+impl AsyncCallbackBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// One of the `MFASYNC_CALLBACK_QUEUE_*` values, or a queue from `MFAllocateWorkQueue`.
+    pub fn queue(mut self, queue: DWORD) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// A combination of the `MFASYNC_*_CALLBACK` flags.
+    pub fn flags(mut self, flags: DWORD) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    /// Wraps `f`, which is called from a Media Foundation work queue thread when the operation
+    /// completes.
+    pub fn build(
+        self,
+        f: impl Fn(&IMFAsyncResult) + Send + Sync + 'static,
+    ) -> ComPtr<IMFAsyncCallback> {
+        let ptr = AsyncCallback::create_raw(self, Box::new(f));
+        let ptr = ptr as *mut IMFAsyncCallback;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct AsyncCallback {
+    vtbl: VTable<IMFAsyncCallbackVtbl>,
+    refcount: Refcount,
+    params: AsyncCallbackBuilder,
+    invoke: InvokeFn,
+}
+
+#[com_impl::com_impl]
+unsafe impl IMFAsyncCallback for AsyncCallback {
+    unsafe fn get_parameters(&self, flags: *mut DWORD, queue: *mut DWORD) -> HRESULT {
+        if self.params.queue.is_none() && self.params.flags.is_none() {
+            return E_NOTIMPL;
+        }
+        if flags.is_null() || queue.is_null() {
+            return E_POINTER;
+        }
+        *flags = self.params.flags.unwrap_or(0);
+        *queue = self.params.queue.unwrap_or(MFASYNC_CALLBACK_QUEUE_STANDARD);
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn invoke(&self, result: *mut IMFAsyncResult) -> HRESULT {
+        if result.is_null() {
+            return E_POINTER;
+        }
+        (self.invoke)(&*result);
+        S_OK
+    }
+}
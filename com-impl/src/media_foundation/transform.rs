@@ -0,0 +1,781 @@
+//! An `IMFTransform` base handling stream and media type negotiation, with the data path
+//! supplied by a [`Transform`].
+//!
+//! The transform has one input and one output stream, both with ID 0, and is synchronous:
+//! each input sample produces exactly one output sample. Output samples are allocated by the
+//! transform (`MFT_OUTPUT_STREAM_PROVIDES_SAMPLES`) and carry the input's time and duration.
+//!
+//! ```no_run
+//! use com_impl::media_foundation::transform::{Frame, Transform, TransformObject};
+//! use com_impl::media_foundation::IMFMediaType;
+//! use winapi::shared::winerror::HRESULT;
+//! use wio::com::ComPtr;
+//!
+//! struct Invert {
+//!     types: Vec<ComPtr<IMFMediaType>>,
+//! }
+//!
+//! // Media types are free-threaded
+//! unsafe impl Send for Invert {}
+//!
+//! impl Transform for Invert {
+//!     fn input_types(&self) -> Vec<ComPtr<IMFMediaType>> {
+//!         self.types.clone()
+//!     }
+//!
+//!     fn output_types(&self, _input: &IMFMediaType) -> Vec<ComPtr<IMFMediaType>> {
+//!         self.types.clone()
+//!     }
+//!
+//!     fn process(&mut self, input: &Frame, output: &mut Vec<u8>) -> Result<(), HRESULT> {
+//!         output.extend(input.data.iter().map(|b| !b));
+//!         Ok(())
+//!     }
+//! }
+//! # let types = vec![];
+//! let mft = TransformObject::new(Invert { types });
+//! ```
+
+use std::ptr;
+use std::sync::Mutex;
+
+use winapi::shared::basetsd::ULONG_PTR;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{
+    E_FAIL, E_INVALIDARG, E_NOTIMPL, E_POINTER, E_UNEXPECTED, HRESULT, S_OK,
+};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::winnt::LONGLONG;
+use wio::com::ComPtr;
+
+use super::{IMFAttributes, IMFMediaBuffer, IMFMediaType, IMFSample};
+use super::{MFCreateMemoryBuffer, MFCreateSample};
+use crate::{Refcount, VTable};
+
+pub use self::ffi::{
+    IMFTransform, IMFTransformVtbl, MFT_INPUT_STREAM_INFO, MFT_OUTPUT_DATA_BUFFER,
+    MFT_OUTPUT_STREAM_INFO,
+};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::basetsd::ULONG_PTR;
+    use winapi::shared::minwindef::DWORD;
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::LONGLONG;
+    use winapi::RIDL;
+
+    use super::super::{IMFAttributes, IMFMediaType, IMFSample};
+
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct MFT_INPUT_STREAM_INFO {
+        pub hnsMaxLatency: LONGLONG,
+        pub dwFlags: DWORD,
+        pub cbSize: DWORD,
+        pub cbMaxLookahead: DWORD,
+        pub cbAlignment: DWORD,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug, Default)]
+    pub struct MFT_OUTPUT_STREAM_INFO {
+        pub dwFlags: DWORD,
+        pub cbSize: DWORD,
+        pub cbAlignment: DWORD,
+    }
+
+    /// `pEvents` is an `IMFCollection`.
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug)]
+    pub struct MFT_OUTPUT_DATA_BUFFER {
+        pub dwStreamID: DWORD,
+        pub pSample: *mut IMFSample,
+        pub dwStatus: DWORD,
+        pub pEvents: *mut IUnknown,
+    }
+
+    // pEvent is an IMFMediaEvent, which nothing here looks at.
+    RIDL! {#[uuid(0xbf94c121, 0x5b05, 0x4e6f, 0x80, 0x00, 0xba, 0x59, 0x89, 0x61, 0x41, 0x4d)]
+    interface IMFTransform(IMFTransformVtbl): IUnknown(IUnknownVtbl) {
+        fn GetStreamLimits(
+            pdwInputMinimum: *mut DWORD,
+            pdwInputMaximum: *mut DWORD,
+            pdwOutputMinimum: *mut DWORD,
+            pdwOutputMaximum: *mut DWORD,
+        ) -> HRESULT,
+        fn GetStreamCount(
+            pcInputStreams: *mut DWORD,
+            pcOutputStreams: *mut DWORD,
+        ) -> HRESULT,
+        fn GetStreamIDs(
+            dwInputIDArraySize: DWORD,
+            pdwInputIDs: *mut DWORD,
+            dwOutputIDArraySize: DWORD,
+            pdwOutputIDs: *mut DWORD,
+        ) -> HRESULT,
+        fn GetInputStreamInfo(
+            dwInputStreamID: DWORD,
+            pStreamInfo: *mut MFT_INPUT_STREAM_INFO,
+        ) -> HRESULT,
+        fn GetOutputStreamInfo(
+            dwOutputStreamID: DWORD,
+            pStreamInfo: *mut MFT_OUTPUT_STREAM_INFO,
+        ) -> HRESULT,
+        fn GetAttributes(
+            pAttributes: *mut *mut IMFAttributes,
+        ) -> HRESULT,
+        fn GetInputStreamAttributes(
+            dwInputStreamID: DWORD,
+            pAttributes: *mut *mut IMFAttributes,
+        ) -> HRESULT,
+        fn GetOutputStreamAttributes(
+            dwOutputStreamID: DWORD,
+            pAttributes: *mut *mut IMFAttributes,
+        ) -> HRESULT,
+        fn DeleteInputStream(
+            dwStreamID: DWORD,
+        ) -> HRESULT,
+        fn AddInputStreams(
+            cStreams: DWORD,
+            adwStreamIDs: *mut DWORD,
+        ) -> HRESULT,
+        fn GetInputAvailableType(
+            dwInputStreamID: DWORD,
+            dwTypeIndex: DWORD,
+            ppType: *mut *mut IMFMediaType,
+        ) -> HRESULT,
+        fn GetOutputAvailableType(
+            dwOutputStreamID: DWORD,
+            dwTypeIndex: DWORD,
+            ppType: *mut *mut IMFMediaType,
+        ) -> HRESULT,
+        fn SetInputType(
+            dwInputStreamID: DWORD,
+            pType: *mut IMFMediaType,
+            dwFlags: DWORD,
+        ) -> HRESULT,
+        fn SetOutputType(
+            dwOutputStreamID: DWORD,
+            pType: *mut IMFMediaType,
+            dwFlags: DWORD,
+        ) -> HRESULT,
+        fn GetInputCurrentType(
+            dwInputStreamID: DWORD,
+            ppType: *mut *mut IMFMediaType,
+        ) -> HRESULT,
+        fn GetOutputCurrentType(
+            dwOutputStreamID: DWORD,
+            ppType: *mut *mut IMFMediaType,
+        ) -> HRESULT,
+        fn GetInputStatus(
+            dwInputStreamID: DWORD,
+            pdwFlags: *mut DWORD,
+        ) -> HRESULT,
+        fn GetOutputStatus(
+            pdwFlags: *mut DWORD,
+        ) -> HRESULT,
+        fn SetOutputBounds(
+            hnsLowerBound: LONGLONG,
+            hnsUpperBound: LONGLONG,
+        ) -> HRESULT,
+        fn ProcessEvent(
+            dwInputStreamID: DWORD,
+            pEvent: *mut IUnknown,
+        ) -> HRESULT,
+        fn ProcessMessage(
+            eMessage: DWORD,
+            ulParam: ULONG_PTR,
+        ) -> HRESULT,
+        fn ProcessInput(
+            dwInputStreamID: DWORD,
+            pSample: *mut IMFSample,
+            dwFlags: DWORD,
+        ) -> HRESULT,
+        fn ProcessOutput(
+            dwFlags: DWORD,
+            cOutputBufferCount: DWORD,
+            pOutputSamples: *mut MFT_OUTPUT_DATA_BUFFER,
+            pdwStatus: *mut DWORD,
+        ) -> HRESULT,
+    }}
+}
+
+pub const MF_E_INVALIDREQUEST: HRESULT = 0xC00D_36B2_u32 as HRESULT;
+pub const MF_E_INVALIDSTREAMNUMBER: HRESULT = 0xC00D_36B3_u32 as HRESULT;
+pub const MF_E_INVALIDMEDIATYPE: HRESULT = 0xC00D_36B4_u32 as HRESULT;
+pub const MF_E_NOTACCEPTING: HRESULT = 0xC00D_36B5_u32 as HRESULT;
+pub const MF_E_NO_MORE_TYPES: HRESULT = 0xC00D_36B9_u32 as HRESULT;
+pub const MF_E_TRANSFORM_TYPE_NOT_SET: HRESULT = 0xC00D_6D60_u32 as HRESULT;
+pub const MF_E_TRANSFORM_NEED_MORE_INPUT: HRESULT = 0xC00D_6D72_u32 as HRESULT;
+
+pub const MFT_SET_TYPE_TEST_ONLY: DWORD = 0x1;
+pub const MFT_INPUT_STATUS_ACCEPT_DATA: DWORD = 0x1;
+pub const MFT_OUTPUT_STATUS_SAMPLE_READY: DWORD = 0x1;
+
+pub const MFT_INPUT_STREAM_WHOLE_SAMPLES: DWORD = 0x1;
+pub const MFT_INPUT_STREAM_SINGLE_SAMPLE_PER_BUFFER: DWORD = 0x2;
+pub const MFT_OUTPUT_STREAM_WHOLE_SAMPLES: DWORD = 0x1;
+pub const MFT_OUTPUT_STREAM_SINGLE_SAMPLE_PER_BUFFER: DWORD = 0x2;
+pub const MFT_OUTPUT_STREAM_PROVIDES_SAMPLES: DWORD = 0x100;
+
+pub const MFT_MESSAGE_COMMAND_FLUSH: DWORD = 0x0000_0000;
+pub const MFT_MESSAGE_COMMAND_DRAIN: DWORD = 0x0000_0001;
+pub const MFT_MESSAGE_NOTIFY_BEGIN_STREAMING: DWORD = 0x1000_0000;
+pub const MFT_MESSAGE_NOTIFY_END_STREAMING: DWORD = 0x1000_0001;
+pub const MFT_MESSAGE_NOTIFY_END_OF_STREAM: DWORD = 0x1000_0002;
+pub const MFT_MESSAGE_NOTIFY_START_OF_STREAM: DWORD = 0x1000_0003;
+
+const MF_MEDIATYPE_EQUAL_MAJOR_TYPES: DWORD = 0x1;
+const MF_MEDIATYPE_EQUAL_FORMAT_TYPES: DWORD = 0x2;
+
+#[derive(Clone, Debug, Default)]
+/// The contents of one input sample.
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// Presentation time in 100ns units, if the sample has one.
+    pub time: Option<i64>,
+    pub duration: Option<i64>,
+}
+
+/// The data path of a [`TransformObject`].
+///
+/// Media Foundation calls transforms from its own worker threads, hence `Send`. Calls are
+/// serialized by the `TransformObject`.
+pub trait Transform: Send {
+    /// Supported input types, in order of preference.
+    fn input_types(&self) -> Vec<ComPtr<IMFMediaType>>;
+
+    /// Supported output types for the current input type, in order of preference.
+    fn output_types(&self, input: &IMFMediaType) -> Vec<ComPtr<IMFMediaType>>;
+
+    /// By default a type is accepted if its major type and subtype match one of
+    /// [`input_types`](Self::input_types).
+    fn accepts_input(&self, ty: &IMFMediaType) -> bool {
+        matches_any(ty, &self.input_types())
+    }
+
+    /// By default a type is accepted if its major type and subtype match one of
+    /// [`output_types`](Self::output_types).
+    fn accepts_output(&self, ty: &IMFMediaType, input: &IMFMediaType) -> bool {
+        matches_any(ty, &self.output_types(input))
+    }
+
+    /// Turns one input frame into the data of one output sample. `output` starts out empty.
+    fn process(&mut self, input: &Frame, output: &mut Vec<u8>) -> Result<(), HRESULT>;
+
+    /// Called when the pipeline discards pending data, e.g. when seeking.
+    fn flush(&mut self) {}
+}
+
+fn matches_any(ty: &IMFMediaType, types: &[ComPtr<IMFMediaType>]) -> bool {
+    const WANTED: DWORD = MF_MEDIATYPE_EQUAL_MAJOR_TYPES | MF_MEDIATYPE_EQUAL_FORMAT_TYPES;
+    types.iter().any(|offered| {
+        let mut flags = 0;
+        let hr = unsafe { ty.IsEqual(offered.as_raw(), &mut flags) };
+        hr >= 0 && flags & WANTED == WANTED
+    })
+}
+
+struct State<T> {
+    transform: T,
+    input_type: Option<ComPtr<IMFMediaType>>,
+    output_type: Option<ComPtr<IMFMediaType>>,
+    pending: Option<Frame>,
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IMFTransform` around a [`Transform`].
+pub struct TransformObject<T: Transform> {
+    vtbl: VTable<IMFTransformVtbl>,
+    refcount: Refcount,
+    state: Mutex<State<T>>,
+}
+
+impl<T: Transform> TransformObject<T> {
+    pub fn new(transform: T) -> ComPtr<IMFTransform> {
+        let state = State {
+            transform,
+            input_type: None,
+            output_type: None,
+            pending: None,
+        };
+        let ptr = TransformObject::create_raw(Mutex::new(state));
+        let ptr = ptr as *mut IMFTransform;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    fn with_state<R>(&self, f: impl FnOnce(&mut State<T>) -> R) -> Result<R, HRESULT> {
+        match self.state.lock() {
+            Ok(mut state) => Ok(f(&mut state)),
+            // A panic in the transform left the state unusable
+            Err(_) => Err(E_UNEXPECTED),
+        }
+    }
+}
+
+fn check_stream(id: DWORD) -> Result<(), HRESULT> {
+    if id == 0 {
+        Ok(())
+    } else {
+        Err(MF_E_INVALIDSTREAMNUMBER)
+    }
+}
+
+unsafe fn write_type(out: *mut *mut IMFMediaType, ty: Option<&ComPtr<IMFMediaType>>) -> HRESULT {
+    if out.is_null() {
+        return E_POINTER;
+    }
+    match ty {
+        Some(ty) => {
+            *out = ty.clone().into_raw();
+            S_OK
+        }
+        None => {
+            *out = ptr::null_mut();
+            MF_E_TRANSFORM_TYPE_NOT_SET
+        }
+    }
+}
+
+unsafe fn borrowed_type(ty: *mut IMFMediaType) -> Option<ComPtr<IMFMediaType>> {
+    if ty.is_null() {
+        return None;
+    }
+    (*ty).AddRef();
+    Some(ComPtr::from_raw(ty))
+}
+
+unsafe fn read_sample(sample: &IMFSample) -> Result<Frame, HRESULT> {
+    let mut buffer = ptr::null_mut();
+    let hr = sample.ConvertToContiguousBuffer(&mut buffer);
+    if hr < 0 {
+        return Err(hr);
+    }
+    let buffer = ComPtr::from_raw(buffer);
+
+    let mut data = ptr::null_mut();
+    let mut len = 0;
+    let hr = buffer.Lock(&mut data, ptr::null_mut(), &mut len);
+    if hr < 0 {
+        return Err(hr);
+    }
+    let bytes = std::slice::from_raw_parts(data, len as usize).to_vec();
+    buffer.Unlock();
+
+    let mut frame = Frame {
+        data: bytes,
+        time: None,
+        duration: None,
+    };
+    let mut value = 0;
+    if sample.GetSampleTime(&mut value) >= 0 {
+        frame.time = Some(value);
+    }
+    if sample.GetSampleDuration(&mut value) >= 0 {
+        frame.duration = Some(value);
+    }
+    Ok(frame)
+}
+
+unsafe fn create_sample(
+    data: &[u8],
+    time: Option<i64>,
+    duration: Option<i64>,
+) -> Result<ComPtr<IMFSample>, HRESULT> {
+    let mut buffer = ptr::null_mut();
+    let hr = MFCreateMemoryBuffer(data.len() as DWORD, &mut buffer);
+    if hr < 0 {
+        return Err(hr);
+    }
+    let buffer: ComPtr<IMFMediaBuffer> = ComPtr::from_raw(buffer);
+
+    let mut dst = ptr::null_mut();
+    let hr = buffer.Lock(&mut dst, ptr::null_mut(), ptr::null_mut());
+    if hr < 0 {
+        return Err(hr);
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len());
+    buffer.Unlock();
+    buffer.SetCurrentLength(data.len() as DWORD);
+
+    let mut sample = ptr::null_mut();
+    let hr = MFCreateSample(&mut sample);
+    if hr < 0 {
+        return Err(hr);
+    }
+    let sample: ComPtr<IMFSample> = ComPtr::from_raw(sample);
+    sample.AddBuffer(buffer.as_raw());
+    if let Some(time) = time {
+        sample.SetSampleTime(time);
+    }
+    if let Some(duration) = duration {
+        sample.SetSampleDuration(duration);
+    }
+    Ok(sample)
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: Transform> IMFTransform for TransformObject<T> {
+    unsafe fn get_stream_limits(
+        &self,
+        input_min: *mut DWORD,
+        input_max: *mut DWORD,
+        output_min: *mut DWORD,
+        output_max: *mut DWORD,
+    ) -> HRESULT {
+        if input_min.is_null()
+            || input_max.is_null()
+            || output_min.is_null()
+            || output_max.is_null()
+        {
+            return E_POINTER;
+        }
+        *input_min = 1;
+        *input_max = 1;
+        *output_min = 1;
+        *output_max = 1;
+        S_OK
+    }
+
+    unsafe fn get_stream_count(&self, inputs: *mut DWORD, outputs: *mut DWORD) -> HRESULT {
+        if inputs.is_null() || outputs.is_null() {
+            return E_POINTER;
+        }
+        *inputs = 1;
+        *outputs = 1;
+        S_OK
+    }
+
+    #[com_name = "GetStreamIDs"]
+    fn get_stream_ids(
+        &self,
+        _input_size: DWORD,
+        _inputs: *mut DWORD,
+        _output_size: DWORD,
+        _outputs: *mut DWORD,
+    ) -> HRESULT {
+        // Fixed streams numbered from 0
+        E_NOTIMPL
+    }
+
+    unsafe fn get_input_stream_info(&self, id: DWORD, info: *mut MFT_INPUT_STREAM_INFO) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if info.is_null() {
+            return E_POINTER;
+        }
+        *info = MFT_INPUT_STREAM_INFO {
+            dwFlags: MFT_INPUT_STREAM_WHOLE_SAMPLES | MFT_INPUT_STREAM_SINGLE_SAMPLE_PER_BUFFER,
+            ..Default::default()
+        };
+        S_OK
+    }
+
+    unsafe fn get_output_stream_info(
+        &self,
+        id: DWORD,
+        info: *mut MFT_OUTPUT_STREAM_INFO,
+    ) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if info.is_null() {
+            return E_POINTER;
+        }
+        *info = MFT_OUTPUT_STREAM_INFO {
+            dwFlags: MFT_OUTPUT_STREAM_WHOLE_SAMPLES
+                | MFT_OUTPUT_STREAM_SINGLE_SAMPLE_PER_BUFFER
+                | MFT_OUTPUT_STREAM_PROVIDES_SAMPLES,
+            ..Default::default()
+        };
+        S_OK
+    }
+
+    fn get_attributes(&self, _attributes: *mut *mut IMFAttributes) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn get_input_stream_attributes(
+        &self,
+        _id: DWORD,
+        _attributes: *mut *mut IMFAttributes,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn get_output_stream_attributes(
+        &self,
+        _id: DWORD,
+        _attributes: *mut *mut IMFAttributes,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn delete_input_stream(&self, _id: DWORD) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn add_input_streams(&self, _count: DWORD, _ids: *mut DWORD) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_input_available_type(
+        &self,
+        id: DWORD,
+        index: DWORD,
+        ty: *mut *mut IMFMediaType,
+    ) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if ty.is_null() {
+            return E_POINTER;
+        }
+        let types = match self.with_state(|s| s.transform.input_types()) {
+            Ok(types) => types,
+            Err(hr) => return hr,
+        };
+        match types.get(index as usize) {
+            Some(found) => write_type(ty, Some(found)),
+            None => {
+                *ty = ptr::null_mut();
+                MF_E_NO_MORE_TYPES
+            }
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_output_available_type(
+        &self,
+        id: DWORD,
+        index: DWORD,
+        ty: *mut *mut IMFMediaType,
+    ) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if ty.is_null() {
+            return E_POINTER;
+        }
+        *ty = ptr::null_mut();
+        let types = self.with_state(|s| {
+            let input = s.input_type.as_ref().ok_or(MF_E_TRANSFORM_TYPE_NOT_SET)?;
+            Ok(s.transform.output_types(input))
+        });
+        let types = match types {
+            Ok(Ok(types)) => types,
+            Ok(Err(hr)) | Err(hr) => return hr,
+        };
+        match types.get(index as usize) {
+            Some(found) => write_type(ty, Some(found)),
+            None => MF_E_NO_MORE_TYPES,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn set_input_type(&self, id: DWORD, ty: *mut IMFMediaType, flags: DWORD) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        let test_only = flags & MFT_SET_TYPE_TEST_ONLY != 0;
+        let ty = borrowed_type(ty);
+        let result = self.with_state(|s| {
+            if s.pending.is_some() {
+                return MF_E_INVALIDREQUEST;
+            }
+            if let Some(ty) = &ty {
+                if !s.transform.accepts_input(ty) {
+                    return MF_E_INVALIDMEDIATYPE;
+                }
+            }
+            if !test_only {
+                // The output type was negotiated against the old input type
+                s.input_type = ty;
+                s.output_type = None;
+            }
+            S_OK
+        });
+        result.unwrap_or_else(|hr| hr)
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn set_output_type(&self, id: DWORD, ty: *mut IMFMediaType, flags: DWORD) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        let test_only = flags & MFT_SET_TYPE_TEST_ONLY != 0;
+        let ty = borrowed_type(ty);
+        let result = self.with_state(|s| {
+            if s.pending.is_some() {
+                return MF_E_INVALIDREQUEST;
+            }
+            if let Some(ty) = &ty {
+                let input = match &s.input_type {
+                    Some(input) => input,
+                    None => return MF_E_TRANSFORM_TYPE_NOT_SET,
+                };
+                if !s.transform.accepts_output(ty, input) {
+                    return MF_E_INVALIDMEDIATYPE;
+                }
+            }
+            if !test_only {
+                s.output_type = ty;
+            }
+            S_OK
+        });
+        result.unwrap_or_else(|hr| hr)
+    }
+
+    unsafe fn get_input_current_type(&self, id: DWORD, ty: *mut *mut IMFMediaType) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        self.with_state(|s| write_type(ty, s.input_type.as_ref()))
+            .unwrap_or_else(|hr| hr)
+    }
+
+    unsafe fn get_output_current_type(&self, id: DWORD, ty: *mut *mut IMFMediaType) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        self.with_state(|s| write_type(ty, s.output_type.as_ref()))
+            .unwrap_or_else(|hr| hr)
+    }
+
+    unsafe fn get_input_status(&self, id: DWORD, status: *mut DWORD) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if status.is_null() {
+            return E_POINTER;
+        }
+        match self.with_state(|s| s.pending.is_none()) {
+            Ok(accepting) => {
+                *status = if accepting {
+                    MFT_INPUT_STATUS_ACCEPT_DATA
+                } else {
+                    0
+                };
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+
+    unsafe fn get_output_status(&self, status: *mut DWORD) -> HRESULT {
+        if status.is_null() {
+            return E_POINTER;
+        }
+        match self.with_state(|s| s.pending.is_some()) {
+            Ok(ready) => {
+                *status = if ready {
+                    MFT_OUTPUT_STATUS_SAMPLE_READY
+                } else {
+                    0
+                };
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+
+    fn set_output_bounds(&self, _lower: LONGLONG, _upper: LONGLONG) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    fn process_event(&self, _id: DWORD, _event: *mut IUnknown) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn process_message(&self, message: DWORD, _param: ULONG_PTR) -> HRESULT {
+        let result = self.with_state(|s| match message {
+            MFT_MESSAGE_COMMAND_FLUSH => {
+                s.pending = None;
+                s.transform.flush();
+                S_OK
+            }
+            // Every input sample is turned into output as soon as it is asked for, so there
+            // is never anything left to drain
+            _ => S_OK,
+        });
+        result.unwrap_or_else(|hr| hr)
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn process_input(&self, id: DWORD, sample: *mut IMFSample, _flags: DWORD) -> HRESULT {
+        if let Err(hr) = check_stream(id) {
+            return hr;
+        }
+        if sample.is_null() {
+            return E_POINTER;
+        }
+        let result = self.with_state(|s| {
+            if s.input_type.is_none() || s.output_type.is_none() {
+                return MF_E_TRANSFORM_TYPE_NOT_SET;
+            }
+            if s.pending.is_some() {
+                return MF_E_NOTACCEPTING;
+            }
+            match read_sample(&*sample) {
+                Ok(frame) => {
+                    s.pending = Some(frame);
+                    S_OK
+                }
+                Err(hr) => hr,
+            }
+        });
+        result.unwrap_or_else(|hr| hr)
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn process_output(
+        &self,
+        _flags: DWORD,
+        count: DWORD,
+        buffers: *mut MFT_OUTPUT_DATA_BUFFER,
+        status: *mut DWORD,
+    ) -> HRESULT {
+        if buffers.is_null() || status.is_null() {
+            return E_POINTER;
+        }
+        if count != 1 {
+            return E_INVALIDARG;
+        }
+        *status = 0;
+        let buffer = &mut *buffers;
+        if !buffer.pSample.is_null() {
+            // The caller was told this transform provides its own samples
+            return E_INVALIDARG;
+        }
+
+        let result = self.with_state(|s| {
+            if s.output_type.is_none() {
+                return MF_E_TRANSFORM_TYPE_NOT_SET;
+            }
+            let frame = match s.pending.take() {
+                Some(frame) => frame,
+                None => return MF_E_TRANSFORM_NEED_MORE_INPUT,
+            };
+
+            let mut output = Vec::new();
+            if let Err(hr) = s.transform.process(&frame, &mut output) {
+                return hr;
+            }
+            match create_sample(&output, frame.time, frame.duration) {
+                Ok(sample) => {
+                    buffer.pSample = sample.into_raw();
+                    buffer.dwStatus = 0;
+                    S_OK
+                }
+                Err(hr) => hr,
+            }
+        });
+        result.unwrap_or_else(|hr| hr)
+    }
+}
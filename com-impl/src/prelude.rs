@@ -0,0 +1,32 @@
+//! The items most implementation files need, in one import.
+//!
+//! ```no_run
+//! use com_impl::prelude::*;
+//!
+//! #[repr(C)]
+//! #[derive(ComImpl)]
+//! pub struct Cookie {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     id: u32,
+//! }
+//!
+//! fn bake(id: u32) -> ComPtr<IUnknown> {
+//!     ComBox::into_com_ptr(unsafe { ComBox::adopt(Cookie::create_raw(id)) })
+//! }
+//! ```
+
+#[cfg(feature = "dynamic")]
+pub use crate::dyn_object;
+pub use crate::{com_impl, ComBox, ComImpl, Refcount, VTable};
+
+pub use winapi::ctypes::c_void;
+pub use winapi::shared::guiddef::{IsEqualIID, GUID, IID, REFIID};
+pub use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE, ULONG};
+pub use winapi::shared::winerror::{
+    E_ABORT, E_ACCESSDENIED, E_FAIL, E_INVALIDARG, E_NOINTERFACE, E_NOTIMPL, E_OUTOFMEMORY,
+    E_POINTER, E_UNEXPECTED, FAILED, HRESULT, SUCCEEDED, S_FALSE, S_OK,
+};
+pub use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+pub use winapi::Interface;
+pub use wio::com::ComPtr;
@@ -0,0 +1,590 @@
+//! A safe `PROPVARIANT` wrapper and an in-memory `IPropertyStore`.
+//!
+//! ```no_run
+//! use com_impl::propsys::{PropVariant, PropertyStoreBuilder};
+//! use winapi::shared::wtypes::PROPERTYKEY;
+//! # let title_key: PROPERTYKEY = unsafe { std::mem::zeroed() };
+//!
+//! let store = PropertyStoreBuilder::new()
+//!     .value(title_key, "My Document")
+//!     .read_only_property(title_key)
+//!     .build();
+//!
+//! let value: PropVariant = 42u32.into();
+//! assert_eq!(value.to_u32(), Some(42));
+//! ```
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, GUID, IID};
+use winapi::shared::minwindef::{DWORD, FILETIME};
+use winapi::shared::winerror::{
+    E_FAIL, E_INVALIDARG, E_NOINTERFACE, E_POINTER, HRESULT, STG_E_ACCESSDENIED, S_FALSE, S_OK,
+};
+use winapi::shared::wtypes::{
+    PROPERTYKEY, VARIANT_FALSE, VARIANT_TRUE, VT_BOOL, VT_BSTR, VT_CLSID, VT_EMPTY, VT_FILETIME,
+    VT_I4, VT_I8, VT_LPWSTR, VT_R8, VT_UI4, VT_UI8,
+};
+use winapi::um::combaseapi::CoTaskMemAlloc;
+use winapi::um::oleauto::SysStringLen;
+use winapi::um::propidl::{PropVariantClear, PropVariantCopy, PROPVARIANT};
+use winapi::um::propkeydef::REFPROPERTYKEY;
+use winapi::um::propsys::{IPropertyStore, IPropertyStoreVtbl};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{BuildVTable, Refcount, VTable};
+
+pub use self::ffi::{IPropertyStoreCapabilities, IPropertyStoreCapabilitiesVtbl};
+
+// Not provided by winapi.
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::propkeydef::REFPROPERTYKEY;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0xc8e2d566, 0x186e, 0x4d49, 0xbf, 0x41, 0x69, 0x09, 0xea, 0xd5, 0x6a, 0xcc)]
+    interface IPropertyStoreCapabilities(IPropertyStoreCapabilitiesVtbl): IUnknown(IUnknownVtbl) {
+        fn IsPropertyWritable(
+            key: REFPROPERTYKEY,
+        ) -> HRESULT,
+    }}
+}
+
+/// An owned `PROPVARIANT`, cleared with `PropVariantClear` when dropped.
+pub struct PropVariant(PROPVARIANT);
+
+impl PropVariant {
+    /// An empty (`VT_EMPTY`) value.
+    pub fn new() -> PropVariant {
+        PropVariant(unsafe { mem::zeroed() })
+    }
+
+    /// Takes ownership of `raw`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid `PROPVARIANT` that nothing else will clear.
+    pub unsafe fn from_raw(raw: PROPVARIANT) -> PropVariant {
+        PropVariant(raw)
+    }
+
+    /// Releases ownership without clearing the value.
+    pub fn into_raw(self) -> PROPVARIANT {
+        let raw = unsafe { ptr::read(&self.0) };
+        mem::forget(self);
+        raw
+    }
+
+    pub fn as_raw(&self) -> &PROPVARIANT {
+        &self.0
+    }
+
+    /// For passing as an out parameter. Any previous value is cleared first.
+    pub fn as_mut_ptr(&mut self) -> *mut PROPVARIANT {
+        self.clear();
+        &mut self.0
+    }
+
+    pub fn vt(&self) -> u16 {
+        self.0.vt
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vt() == VT_EMPTY as u16
+    }
+
+    pub fn clear(&mut self) {
+        unsafe {
+            PropVariantClear(&mut self.0);
+        }
+    }
+
+    fn with(vt: u32, set: impl FnOnce(&mut PROPVARIANT)) -> PropVariant {
+        let mut value = PropVariant::new();
+        value.0.vt = vt as u16;
+        set(&mut value.0);
+        value
+    }
+
+    pub fn to_bool(&self) -> Option<bool> {
+        match self.vt() as u32 {
+            VT_BOOL => Some(unsafe { *self.0.data.boolVal() } != VARIANT_FALSE),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(&self) -> Option<i32> {
+        match self.vt() as u32 {
+            VT_I4 => Some(unsafe { *self.0.data.lVal() }),
+            _ => None,
+        }
+    }
+
+    pub fn to_u32(&self) -> Option<u32> {
+        match self.vt() as u32 {
+            VT_UI4 => Some(unsafe { *self.0.data.ulVal() }),
+            _ => None,
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match self.vt() as u32 {
+            VT_I8 => Some(unsafe { *self.0.data.hVal().QuadPart() }),
+            VT_I4 => self.to_i32().map(i64::from),
+            _ => None,
+        }
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        match self.vt() as u32 {
+            VT_UI8 => Some(unsafe { *self.0.data.uhVal().QuadPart() }),
+            VT_UI4 => self.to_u32().map(u64::from),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        match self.vt() as u32 {
+            VT_R8 => Some(unsafe { *self.0.data.dblVal() }),
+            _ => None,
+        }
+    }
+
+    /// Reads `VT_LPWSTR` and `VT_BSTR` values.
+    pub fn to_string(&self) -> Option<String> {
+        unsafe {
+            let (ptr, len) = match self.vt() as u32 {
+                VT_LPWSTR => {
+                    let ptr = *self.0.data.pwszVal();
+                    if ptr.is_null() {
+                        return None;
+                    }
+                    (
+                        ptr as *const u16,
+                        (0..).take_while(|&i| *ptr.add(i) != 0).count(),
+                    )
+                }
+                VT_BSTR => {
+                    let ptr = *self.0.data.bstrVal();
+                    (ptr as *const u16, SysStringLen(ptr) as usize)
+                }
+                _ => return None,
+            };
+            if ptr.is_null() {
+                return Some(String::new());
+            }
+            let s = std::slice::from_raw_parts(ptr, len);
+            Some(String::from_utf16_lossy(s))
+        }
+    }
+
+    pub fn to_guid(&self) -> Option<GUID> {
+        match self.vt() as u32 {
+            VT_CLSID => unsafe {
+                let ptr = *self.0.data.puuid();
+                if ptr.is_null() {
+                    None
+                } else {
+                    Some(*ptr)
+                }
+            },
+            _ => None,
+        }
+    }
+
+    pub fn to_filetime(&self) -> Option<FILETIME> {
+        match self.vt() as u32 {
+            VT_FILETIME => Some(unsafe { *self.0.data.filetime() }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for PropVariant {
+    fn default() -> Self {
+        PropVariant::new()
+    }
+}
+
+impl Drop for PropVariant {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl Clone for PropVariant {
+    fn clone(&self) -> Self {
+        let mut copy = PropVariant::new();
+        unsafe {
+            PropVariantCopy(&mut copy.0, &self.0);
+        }
+        copy
+    }
+}
+
+impl fmt::Debug for PropVariant {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("PropVariant").field(&self.vt()).finish()
+    }
+}
+
+impl From<bool> for PropVariant {
+    fn from(value: bool) -> PropVariant {
+        let value = if value { VARIANT_TRUE } else { VARIANT_FALSE };
+        PropVariant::with(VT_BOOL, |pv| unsafe { *pv.data.boolVal_mut() = value })
+    }
+}
+
+impl From<i32> for PropVariant {
+    fn from(value: i32) -> PropVariant {
+        PropVariant::with(VT_I4, |pv| unsafe { *pv.data.lVal_mut() = value })
+    }
+}
+
+impl From<u32> for PropVariant {
+    fn from(value: u32) -> PropVariant {
+        PropVariant::with(VT_UI4, |pv| unsafe { *pv.data.ulVal_mut() = value })
+    }
+}
+
+impl From<i64> for PropVariant {
+    fn from(value: i64) -> PropVariant {
+        PropVariant::with(VT_I8, |pv| unsafe {
+            *pv.data.hVal_mut().QuadPart_mut() = value
+        })
+    }
+}
+
+impl From<u64> for PropVariant {
+    fn from(value: u64) -> PropVariant {
+        PropVariant::with(VT_UI8, |pv| unsafe {
+            *pv.data.uhVal_mut().QuadPart_mut() = value
+        })
+    }
+}
+
+impl From<f64> for PropVariant {
+    fn from(value: f64) -> PropVariant {
+        PropVariant::with(VT_R8, |pv| unsafe { *pv.data.dblVal_mut() = value })
+    }
+}
+
+impl From<FILETIME> for PropVariant {
+    fn from(value: FILETIME) -> PropVariant {
+        PropVariant::with(VT_FILETIME, |pv| unsafe { *pv.data.filetime_mut() = value })
+    }
+}
+
+/// Stored as `VT_CLSID`.
+impl From<GUID> for PropVariant {
+    fn from(value: GUID) -> PropVariant {
+        unsafe {
+            let ptr = CoTaskMemAlloc(mem::size_of::<GUID>()) as *mut GUID;
+            if ptr.is_null() {
+                return PropVariant::new();
+            }
+            *ptr = value;
+            PropVariant::with(VT_CLSID, |pv| *pv.data.puuid_mut() = ptr)
+        }
+    }
+}
+
+/// Stored as `VT_LPWSTR`.
+impl<'a> From<&'a str> for PropVariant {
+    fn from(value: &'a str) -> PropVariant {
+        let wide: Vec<u16> = value.encode_utf16().chain(Some(0)).collect();
+        unsafe {
+            let ptr = CoTaskMemAlloc(wide.len() * 2) as *mut u16;
+            if ptr.is_null() {
+                return PropVariant::new();
+            }
+            ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+            PropVariant::with(VT_LPWSTR, |pv| *pv.data.pwszVal_mut() = ptr)
+        }
+    }
+}
+
+impl From<String> for PropVariant {
+    fn from(value: String) -> PropVariant {
+        PropVariant::from(value.as_str())
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct Key(u32, u16, u16, [u8; 8], u32);
+
+impl From<&PROPERTYKEY> for Key {
+    fn from(key: &PROPERTYKEY) -> Key {
+        let id = &key.fmtid;
+        Key(id.Data1, id.Data2, id.Data3, id.Data4, key.pid)
+    }
+}
+
+type CommitFn = Box<dyn Fn(&[(PROPERTYKEY, PropVariant)]) -> Result<(), HRESULT>>;
+
+#[derive(Default)]
+/// Builds an in-memory `IPropertyStore`, which also answers `IPropertyStoreCapabilities`.
+///
+/// Every property is writable unless the store is [`read_only`](Self::read_only) or the
+/// property was marked with [`read_only_property`](Self::read_only_property). `Commit`
+/// calls the [`on_commit`](Self::on_commit) handler with the current values.
+pub struct PropertyStoreBuilder {
+    entries: Vec<(PROPERTYKEY, PropVariant)>,
+    read_only: bool,
+    read_only_keys: HashSet<Key>,
+    on_commit: Option<CommitFn>,
+}
+
+impl PropertyStoreBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn value(mut self, key: PROPERTYKEY, value: impl Into<PropVariant>) -> Self {
+        let k = Key::from(&key);
+        let value = value.into();
+        match self.entries.iter_mut().find(|(e, _)| Key::from(e) == k) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key, value)),
+        }
+        self
+    }
+
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn read_only_property(mut self, key: PROPERTYKEY) -> Self {
+        self.read_only_keys.insert(Key::from(&key));
+        self
+    }
+
+    pub fn on_commit(
+        mut self,
+        f: impl Fn(&[(PROPERTYKEY, PropVariant)]) -> Result<(), HRESULT> + 'static,
+    ) -> Self {
+        self.on_commit = Some(Box::new(f));
+        self
+    }
+
+    pub fn build(self) -> ComPtr<IPropertyStore> {
+        let index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, (key, _))| (Key::from(key), i))
+            .collect();
+        let ptr = Box::into_raw(Box::new(PropertyStore {
+            vtbl: <PropertyStore as BuildVTable<_>>::STATIC_VTABLE,
+            caps_vtbl: VTable::new(&PropertyStore::CAPS_VTBL),
+            refcount: Default::default(),
+            entries: RefCell::new(self.entries),
+            index: RefCell::new(index),
+            read_only: self.read_only,
+            read_only_keys: self.read_only_keys,
+            on_commit: self.on_commit,
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IPropertyStore) }
+    }
+}
+
+/// Not derived, because `IPropertyStoreCapabilities` lives on a second vtable.
+#[repr(C)]
+struct PropertyStore {
+    vtbl: VTable<IPropertyStoreVtbl>,
+    caps_vtbl: VTable<IPropertyStoreCapabilitiesVtbl>,
+    refcount: Refcount,
+    entries: RefCell<Vec<(PROPERTYKEY, PropVariant)>>,
+    index: RefCell<HashMap<Key, usize>>,
+    read_only: bool,
+    read_only_keys: HashSet<Key>,
+    on_commit: Option<CommitFn>,
+}
+
+impl PropertyStore {
+    const CAPS_VTBL: IPropertyStoreCapabilitiesVtbl = IPropertyStoreCapabilitiesVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::caps_query_interface,
+            AddRef: Self::caps_add_ref,
+            Release: Self::caps_release,
+        },
+        IsPropertyWritable: Self::is_property_writable,
+    };
+
+    unsafe fn from_caps(this: *mut IUnknown) -> *mut IUnknown {
+        (this as *mut u8).sub(mem::size_of::<VTable<IPropertyStoreVtbl>>()) as *mut IUnknown
+    }
+
+    fn is_writable(&self, key: &PROPERTYKEY) -> bool {
+        !self.read_only && !self.read_only_keys.contains(&Key::from(key))
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof()) || IsEqualIID(riid, &IPropertyStore::uuidof()) {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            S_OK
+        } else if IsEqualIID(riid, &IPropertyStoreCapabilities::uuidof()) {
+            that.refcount.add_ref();
+            *ppv = &that.caps_vtbl as *const _ as *mut c_void;
+            S_OK
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn caps_query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        Self::query_interface(Self::from_caps(this), riid, ppv)
+    }
+
+    unsafe extern "system" fn caps_add_ref(this: *mut IUnknown) -> u32 {
+        Self::add_ref(Self::from_caps(this))
+    }
+
+    unsafe extern "system" fn caps_release(this: *mut IUnknown) -> u32 {
+        Self::release(Self::from_caps(this))
+    }
+
+    unsafe extern "system" fn is_property_writable(
+        this: *mut IPropertyStoreCapabilities,
+        key: REFPROPERTYKEY,
+    ) -> HRESULT {
+        let that = &*(Self::from_caps(this as *mut IUnknown) as *const Self);
+        if key.is_null() {
+            return E_POINTER;
+        }
+        if that.is_writable(&*key) {
+            S_OK
+        } else {
+            S_FALSE
+        }
+    }
+}
+
+unsafe impl BuildVTable<IUnknownVtbl> for PropertyStore {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: Self::query_interface,
+        AddRef: Self::add_ref,
+        Release: Self::release,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+#[com_impl::com_impl]
+unsafe impl IPropertyStore for PropertyStore {
+    unsafe fn get_count(&self, count: *mut DWORD) -> HRESULT {
+        if count.is_null() {
+            return E_POINTER;
+        }
+        *count = self.entries.borrow().len() as DWORD;
+        S_OK
+    }
+
+    unsafe fn get_at(&self, index: DWORD, key: *mut PROPERTYKEY) -> HRESULT {
+        if key.is_null() {
+            return E_POINTER;
+        }
+        match self.entries.borrow().get(index as usize) {
+            Some((k, _)) => {
+                *key = *k;
+                S_OK
+            }
+            None => E_INVALIDARG,
+        }
+    }
+
+    unsafe fn get_value(&self, key: REFPROPERTYKEY, value: *mut PROPVARIANT) -> HRESULT {
+        if key.is_null() || value.is_null() {
+            return E_POINTER;
+        }
+        // Missing properties are reported as VT_EMPTY rather than an error
+        *value = mem::zeroed();
+        let entries = self.entries.borrow();
+        match self.index.borrow().get(&Key::from(&*key)) {
+            Some(&i) => PropVariantCopy(value, entries[i].1.as_raw()),
+            None => S_OK,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn set_value(&self, key: REFPROPERTYKEY, value: *const PROPVARIANT) -> HRESULT {
+        if key.is_null() || value.is_null() {
+            return E_POINTER;
+        }
+        let key = &*key;
+        if !self.is_writable(key) {
+            return STG_E_ACCESSDENIED;
+        }
+
+        let mut copy = PropVariant::new();
+        let hr = PropVariantCopy(copy.as_mut_ptr(), value);
+        if hr < 0 {
+            return hr;
+        }
+
+        let mut entries = self.entries.borrow_mut();
+        let mut index = self.index.borrow_mut();
+        match index.get(&Key::from(key)) {
+            Some(&i) => entries[i].1 = copy,
+            None => {
+                index.insert(Key::from(key), entries.len());
+                entries.push((*key, copy));
+            }
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    fn commit(&self) -> HRESULT {
+        if self.read_only {
+            return STG_E_ACCESSDENIED;
+        }
+        match &self.on_commit {
+            Some(f) => match f(&self.entries.borrow()) {
+                Ok(()) => S_OK,
+                Err(hr) => hr,
+            },
+            None => S_OK,
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Describing the registry entries of COM classes, and writing them out for installers.
+//!
+//! [`Class`] lists what a class needs under `HKEY_CLASSES_ROOT`: its CLSID key, in-process
+//! server, threading model, ProgIDs and component categories. The entries can be written as a
+//! `.reg` script or a WiX fragment, for installer pipelines that don't run self-registration.
+//!
+//! ```no_run
+//! use com_impl::registration::{reg_script, Class, Scope, ThreadingModel};
+//! use winapi::shared::guiddef::GUID;
+//!
+//! const CLSID_FILE_STREAM: GUID = GUID {
+//!     Data1: 0x3b1d_53a4,
+//!     Data2: 0x6f0e,
+//!     Data3: 0x4c5b,
+//!     Data4: [0x92, 0x1f, 0x7a, 0x10, 0xd2, 0x4e, 0x8c, 0x31],
+//! };
+//!
+//! let entries = Class::new(CLSID_FILE_STREAM, "Font file stream")
+//!     .prog_id("Fonts.FileStream.1")
+//!     .version_independent_prog_id("Fonts.FileStream")
+//!     .threading_model(ThreadingModel::Both)
+//!     .entries(r"C:\Program Files\Fonts\fonts.dll");
+//!
+//! std::fs::write("fonts.reg", reg_script(&entries, Scope::Machine)).unwrap();
+//! ```
+//!
+//! A DLL registering itself writes the same entries with [`register`], and removes them with
+//! [`unregister`]. `com_impl::com_server!` generates `DllRegisterServer` and
+//! `DllUnregisterServer` doing so when its classes are given a `Class`.
+
+use std::fmt::Write as _;
+use std::ptr;
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{DWORD, HKEY, MAX_PATH};
+use winapi::shared::winerror::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, HRESULT, HRESULT_FROM_WIN32};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::libloaderapi::{
+    GetModuleFileNameW, GetModuleHandleExW, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+use winapi::um::winnt::{KEY_WRITE, LPCWSTR, REG_OPTION_NON_VOLATILE, REG_SZ};
+use winapi::um::winreg::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteTreeW, RegSetValueExW, HKEY_CLASSES_ROOT,
+    HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, LSTATUS,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegistryRoot {
+    /// `HKEY_CLASSES_ROOT`, or `HKEY_CURRENT_USER\Software\Classes` for a per-user install.
+    Classes,
+    /// `HKEY_LOCAL_MACHINE`, or `HKEY_CURRENT_USER` for a per-user install.
+    LocalMachine,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A single registry value. `name` is `None` for the key's default value.
+pub struct RegistryEntry {
+    pub root: RegistryRoot,
+    pub key: String,
+    pub name: Option<String>,
+    pub value: String,
+}
+
+impl RegistryEntry {
+    pub fn new(key: String, name: Option<&str>, value: String) -> RegistryEntry {
+        RegistryEntry {
+            root: RegistryRoot::Classes,
+            key,
+            name: name.map(String::from),
+            value,
+        }
+    }
+
+    pub fn local_machine(key: String, name: Option<&str>, value: String) -> RegistryEntry {
+        RegistryEntry {
+            root: RegistryRoot::LocalMachine,
+            ..RegistryEntry::new(key, name, value)
+        }
+    }
+}
+
+/// Formats a GUID the way the registry expects, e.g.
+/// `{00000000-0000-0000-C000-000000000046}`.
+pub fn guid_string(guid: &GUID) -> String {
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        guid.Data1,
+        guid.Data2,
+        guid.Data3,
+        guid.Data4[0],
+        guid.Data4[1],
+        guid.Data4[2],
+        guid.Data4[3],
+        guid.Data4[4],
+        guid.Data4[5],
+        guid.Data4[6],
+        guid.Data4[7],
+    )
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The `ThreadingModel` value of an in-process server.
+pub enum ThreadingModel {
+    Apartment,
+    Free,
+    Both,
+    Neutral,
+}
+
+impl ThreadingModel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThreadingModel::Apartment => "Apartment",
+            ThreadingModel::Free => "Free",
+            ThreadingModel::Both => "Both",
+            ThreadingModel::Neutral => "Neutral",
+        }
+    }
+}
+
+/// The registration of one in-process COM class.
+pub struct Class {
+    clsid: GUID,
+    description: String,
+    threading_model: ThreadingModel,
+    prog_id: Option<String>,
+    version_independent_prog_id: Option<String>,
+    categories: Vec<GUID>,
+}
+
+impl Class {
+    /// An apartment-threaded class without ProgIDs or categories.
+    pub fn new(clsid: GUID, description: &str) -> Class {
+        Class {
+            clsid,
+            description: description.into(),
+            threading_model: ThreadingModel::Apartment,
+            prog_id: None,
+            version_independent_prog_id: None,
+            categories: Vec::new(),
+        }
+    }
+
+    pub fn threading_model(mut self, model: ThreadingModel) -> Class {
+        self.threading_model = model;
+        self
+    }
+
+    /// The versioned ProgID, e.g. `Vendor.Component.1`.
+    pub fn prog_id(mut self, prog_id: &str) -> Class {
+        self.prog_id = Some(prog_id.into());
+        self
+    }
+
+    /// The ProgID without a version, e.g. `Vendor.Component`, pointing at the versioned one.
+    pub fn version_independent_prog_id(mut self, prog_id: &str) -> Class {
+        self.version_independent_prog_id = Some(prog_id.into());
+        self
+    }
+
+    /// Lists the class under `Implemented Categories`.
+    pub fn category(mut self, catid: GUID) -> Class {
+        self.categories.push(catid);
+        self
+    }
+
+    pub fn clsid(&self) -> &GUID {
+        &self.clsid
+    }
+
+    /// The entries for the class, served from `dll_path`.
+    pub fn entries(&self, dll_path: &str) -> Vec<RegistryEntry> {
+        let clsid = guid_string(&self.clsid);
+        let key = format!("CLSID\\{}", clsid);
+        let server = format!("{}\\InprocServer32", key);
+        let mut entries = vec![
+            RegistryEntry::new(key.clone(), None, self.description.clone()),
+            RegistryEntry::new(server.clone(), None, dll_path.into()),
+            RegistryEntry::new(
+                server,
+                Some("ThreadingModel"),
+                self.threading_model.as_str().into(),
+            ),
+        ];
+
+        if let Some(prog_id) = &self.prog_id {
+            entries.push(RegistryEntry::new(
+                format!("{}\\ProgID", key),
+                None,
+                prog_id.clone(),
+            ));
+            entries.push(RegistryEntry::new(
+                prog_id.clone(),
+                None,
+                self.description.clone(),
+            ));
+            entries.push(RegistryEntry::new(
+                format!("{}\\CLSID", prog_id),
+                None,
+                clsid.clone(),
+            ));
+        }
+
+        if let Some(independent) = &self.version_independent_prog_id {
+            entries.push(RegistryEntry::new(
+                format!("{}\\VersionIndependentProgID", key),
+                None,
+                independent.clone(),
+            ));
+            entries.push(RegistryEntry::new(
+                independent.clone(),
+                None,
+                self.description.clone(),
+            ));
+            entries.push(RegistryEntry::new(
+                format!("{}\\CLSID", independent),
+                None,
+                clsid.clone(),
+            ));
+            if let Some(prog_id) = &self.prog_id {
+                entries.push(RegistryEntry::new(
+                    format!("{}\\CurVer", independent),
+                    None,
+                    prog_id.clone(),
+                ));
+            }
+        }
+
+        // Category keys carry no values, only their existence matters
+        for catid in &self.categories {
+            entries.push(RegistryEntry::new(
+                format!("{}\\Implemented Categories\\{}", key, guid_string(catid)),
+                None,
+                String::new(),
+            ));
+        }
+
+        entries
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// Where entries are installed.
+pub enum Scope {
+    Machine,
+    /// Under `HKEY_CURRENT_USER`, for installs without administrator rights.
+    User,
+}
+
+/// The hive and full key an entry resolves to for `scope`.
+fn resolve(entry: &RegistryEntry, scope: Scope) -> (&'static str, String) {
+    match (entry.root, scope) {
+        (RegistryRoot::Classes, Scope::Machine) => ("HKEY_CLASSES_ROOT", entry.key.clone()),
+        (RegistryRoot::Classes, Scope::User) => (
+            "HKEY_CURRENT_USER",
+            format!("Software\\Classes\\{}", entry.key),
+        ),
+        (RegistryRoot::LocalMachine, Scope::Machine) => ("HKEY_LOCAL_MACHINE", entry.key.clone()),
+        (RegistryRoot::LocalMachine, Scope::User) => ("HKEY_CURRENT_USER", entry.key.clone()),
+    }
+}
+
+/// A `.reg` script adding `entries`, for `regedit /s` or review.
+///
+/// The script is returned as a string; regedit expects files containing non-ASCII text to
+/// be saved as UTF-16 with a byte order mark.
+pub fn reg_script(entries: &[RegistryEntry], scope: Scope) -> String {
+    let mut out = String::from("Windows Registry Editor Version 5.00\r\n");
+    let mut current = None;
+    for entry in entries {
+        let (hive, key) = resolve(entry, scope);
+        let path = format!("{}\\{}", hive, key);
+        if current.as_ref() != Some(&path) {
+            let _ = write!(out, "\r\n[{}]\r\n", path);
+            current = Some(path);
+        }
+        if entry.name.is_none() && entry.value.is_empty() {
+            continue;
+        }
+        match &entry.name {
+            Some(name) => {
+                let _ = write!(out, "\"{}\"", reg_escape(name));
+            }
+            None => out.push('@'),
+        }
+        let _ = write!(out, "=\"{}\"\r\n", reg_escape(&entry.value));
+    }
+    out
+}
+
+fn reg_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A WiX fragment with a component group named `id` adding `entries`.
+///
+/// `directory` is the `Id` of the directory the component is installed into. Server paths
+/// in the entries can use WiX formatting, e.g. `[#MyServer.dll]`, which is resolved by the
+/// installer.
+pub fn wix_fragment(entries: &[RegistryEntry], scope: Scope, id: &str, directory: &str) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<Wix xmlns=\"http://schemas.microsoft.com/wix/2006/wi\">\n");
+    out.push_str("  <Fragment>\n");
+    let _ = writeln!(out, "    <ComponentGroup Id=\"{}\">", xml_escape(id));
+    let _ = writeln!(
+        out,
+        "      <Component Id=\"{}\" Directory=\"{}\" Guid=\"*\">",
+        xml_escape(id),
+        xml_escape(directory)
+    );
+    let mut key_path = false;
+    for entry in entries {
+        let (hive, key) = resolve(entry, scope);
+        let root = match hive {
+            "HKEY_CLASSES_ROOT" => "HKCR",
+            "HKEY_LOCAL_MACHINE" => "HKLM",
+            _ => "HKCU",
+        };
+        if entry.name.is_none() && entry.value.is_empty() {
+            let _ = writeln!(
+                out,
+                "        <RegistryKey Root=\"{}\" Key=\"{}\" ForceCreateOnInstall=\"yes\" />",
+                root,
+                xml_escape(&key)
+            );
+            continue;
+        }
+
+        let _ = write!(
+            out,
+            "        <RegistryValue Root=\"{}\" Key=\"{}\"",
+            root,
+            xml_escape(&key)
+        );
+        if let Some(name) = &entry.name {
+            let _ = write!(out, " Name=\"{}\"", xml_escape(name));
+        }
+        let _ = write!(
+            out,
+            " Value=\"{}\" Type=\"string\"",
+            xml_escape(&entry.value)
+        );
+        // A component with a generated GUID needs one registry value as its key path
+        if !key_path {
+            out.push_str(" KeyPath=\"yes\"");
+            key_path = true;
+        }
+        out.push_str(" />\n");
+    }
+    out.push_str("      </Component>\n");
+    out.push_str("    </ComponentGroup>\n");
+    out.push_str("  </Fragment>\n");
+    out.push_str("</Wix>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes `entries` to the registry, for `DllRegisterServer` or an installer custom action.
+///
+/// Stops at the first entry that can't be written, usually with `E_ACCESSDENIED` when
+/// registering for the machine without administrator rights.
+pub fn register(entries: &[RegistryEntry], scope: Scope) -> Result<(), HRESULT> {
+    for entry in entries {
+        let (hive, key) = resolve(entry, scope);
+        let key = to_wide(&key);
+        let mut handle = ptr::null_mut();
+        let status = unsafe {
+            RegCreateKeyExW(
+                hive_key(hive),
+                key.as_ptr(),
+                0,
+                ptr::null_mut(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_WRITE,
+                ptr::null_mut(),
+                &mut handle,
+                ptr::null_mut(),
+            )
+        };
+        check(status)?;
+
+        // Category keys carry no values, only their existence matters
+        let status = if entry.name.is_none() && entry.value.is_empty() {
+            ERROR_SUCCESS as LSTATUS
+        } else {
+            let name = entry.name.as_ref().map(|name| to_wide(name));
+            let value = to_wide(&entry.value);
+            unsafe {
+                RegSetValueExW(
+                    handle,
+                    name.as_ref().map_or(ptr::null(), |name| name.as_ptr()),
+                    0,
+                    REG_SZ,
+                    value.as_ptr() as *const u8,
+                    (value.len() * 2) as DWORD,
+                )
+            }
+        };
+        unsafe { RegCloseKey(handle) };
+        check(status)?;
+    }
+    Ok(())
+}
+
+/// Deletes the keys `register` created for `entries`, along with anything below them, for
+/// `DllUnregisterServer`. Keys that are already gone are skipped.
+pub fn unregister(entries: &[RegistryEntry], scope: Scope) -> Result<(), HRESULT> {
+    let mut deleted: Vec<(&'static str, String)> = Vec::new();
+    for entry in entries {
+        let (hive, key) = resolve(entry, scope);
+        // Deleting the topmost key of each tree covers the keys below it
+        let covered = deleted.iter().any(|(other_hive, other)| {
+            *other_hive == hive && (key == *other || key.starts_with(&format!("{}\\", other)))
+        });
+        if covered {
+            continue;
+        }
+        let status = unsafe { RegDeleteTreeW(hive_key(hive), to_wide(&key).as_ptr()) };
+        if status != ERROR_FILE_NOT_FOUND as LSTATUS {
+            check(status)?;
+        }
+        deleted.push((hive, key));
+    }
+    Ok(())
+}
+
+/// The path of the module containing this code: the DLL when com-impl is linked into one,
+/// as `Class::entries` expects for the in-process server.
+pub fn module_path() -> Result<String, HRESULT> {
+    let mut module = ptr::null_mut();
+    let found = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            module_path as *const () as LPCWSTR,
+            &mut module,
+        )
+    };
+    if found == 0 {
+        return Err(last_error());
+    }
+
+    let mut buffer = vec![0u16; MAX_PATH];
+    loop {
+        let len = unsafe {
+            GetModuleFileNameW(module, buffer.as_mut_ptr(), buffer.len() as DWORD) as usize
+        };
+        if len == 0 {
+            return Err(last_error());
+        }
+        // A truncated path fills the whole buffer
+        if len < buffer.len() {
+            return Ok(String::from_utf16_lossy(&buffer[..len]));
+        }
+        let size = buffer.len() * 2;
+        buffer.resize(size, 0);
+    }
+}
+
+fn hive_key(hive: &str) -> HKEY {
+    match hive {
+        "HKEY_CLASSES_ROOT" => HKEY_CLASSES_ROOT,
+        "HKEY_LOCAL_MACHINE" => HKEY_LOCAL_MACHINE,
+        _ => HKEY_CURRENT_USER,
+    }
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+fn check(status: LSTATUS) -> Result<(), HRESULT> {
+    if status == ERROR_SUCCESS as LSTATUS {
+        Ok(())
+    } else {
+        Err(HRESULT_FROM_WIN32(status as u32))
+    }
+}
+
+fn last_error() -> HRESULT {
+    HRESULT_FROM_WIN32(unsafe { GetLastError() })
+}
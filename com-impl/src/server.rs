@@ -0,0 +1,182 @@
+//! The exports of an in-process COM server DLL.
+//!
+//! [`com_server!`] lists the classes the DLL serves, each by its CLSID and its class factory,
+//! and defines `DllGetClassObject` and `DllCanUnloadNow` for them. `DllGetClassObject` creates
+//! the factory of the requested class, and `DllCanUnloadNow` keeps the DLL loaded while the
+//! module lock of `com_impl::server_lock` is held: by a class factory's `LockServer`, or by
+//! an object with a `com_impl::server_lock::ServerLock` member.
+//!
+//! Giving each class its `com_impl::registration::Class` after a colon additionally defines
+//! `DllRegisterServer` and `DllUnregisterServer`, which write and remove the classes' registry
+//! entries for `regsvr32`, pointing them at the DLL.
+//!
+//! The crate must be built as a `cdylib`, with the exports listed in its `.def` file if they
+//! should keep their undecorated names on 32-bit targets.
+//!
+//! ```no_run
+//! use com_impl::class_factory::ClassFactory;
+//! use com_impl::registration::{Class, ThreadingModel};
+//! use com_impl::server_lock::ServerLock;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::shared::guiddef::GUID;
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! pub const CLSID_COUNTER: GUID = GUID {
+//!     Data1: 0x6c4f_0b2e,
+//!     Data2: 0x52a1,
+//!     Data3: 0x4d8e,
+//!     Data4: [0xb3, 0x07, 0x1e, 0x9a, 0x40, 0x5c, 0x77, 0xd2],
+//! };
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[class_factory]
+//! pub struct Counter {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     lock: ServerLock,
+//!     count: std::sync::atomic::AtomicU32,
+//! }
+//!
+//! com_impl::com_server! {
+//!     CLSID_COUNTER => ClassFactory<Counter>: Class::new(CLSID_COUNTER, "Counter")
+//!         .prog_id("Demo.Counter.1")
+//!         .threading_model(ThreadingModel::Both),
+//! }
+//! ```
+
+use std::ptr;
+
+use winapi::shared::guiddef::{IsEqualGUID, GUID};
+use winapi::shared::winerror::{CLASS_E_CLASSNOTAVAILABLE, E_POINTER, S_FALSE, S_OK};
+use winapi::um::olectl::SELFREG_E_CLASS;
+
+use crate::class_factory::NewFactory;
+use crate::registration::{self, Class, Scope};
+use crate::server_lock;
+
+// Named by the `com_server!` expansion
+#[doc(hidden)]
+pub use winapi::ctypes::c_void;
+#[doc(hidden)]
+pub use winapi::shared::guiddef::{REFCLSID, REFIID};
+#[doc(hidden)]
+pub use winapi::shared::winerror::HRESULT;
+
+/// Defines `DllGetClassObject` and `DllCanUnloadNow` for the classes listed.
+///
+/// Each entry is `CLSID => Factory`, where `CLSID` is a `GUID` expression and `Factory` a type
+/// with a `new()` returning `ComPtr<IClassFactory>`, usually
+/// `com_impl::class_factory::ClassFactory<Class>`.
+///
+/// With entries of the form `CLSID => Factory: Registration`, where `Registration` is the
+/// `com_impl::registration::Class` of that CLSID, `DllRegisterServer` and
+/// `DllUnregisterServer` are defined as well.
+#[macro_export]
+macro_rules! com_server {
+    ($($clsid:expr => $factory:ty : $class:expr),* $(,)*) => {
+        $crate::com_server! {
+            $($clsid => $factory),*
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "system" fn DllRegisterServer() -> $crate::server::HRESULT {
+            $crate::server::register_server(&[$((&$clsid, $class)),*])
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "system" fn DllUnregisterServer() -> $crate::server::HRESULT {
+            $crate::server::unregister_server(&[$((&$clsid, $class)),*])
+        }
+    };
+    ($($clsid:expr => $factory:ty),* $(,)*) => {
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub unsafe extern "system" fn DllGetClassObject(
+            rclsid: $crate::server::REFCLSID,
+            riid: $crate::server::REFIID,
+            ppv: *mut *mut $crate::server::c_void,
+        ) -> $crate::server::HRESULT {
+            $crate::server::get_class_object(
+                rclsid,
+                riid,
+                ppv,
+                &[$((&$clsid, <$factory>::new)),*],
+            )
+        }
+
+        #[no_mangle]
+        #[allow(non_snake_case)]
+        pub extern "system" fn DllCanUnloadNow() -> $crate::server::HRESULT {
+            $crate::server::can_unload_now()
+        }
+    };
+}
+
+#[doc(hidden)]
+/// `DllGetClassObject` for the classes listed in `com_server!`.
+pub unsafe fn get_class_object(
+    rclsid: REFCLSID,
+    riid: REFIID,
+    ppv: *mut *mut c_void,
+    classes: &[(&GUID, NewFactory)],
+) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    *ppv = ptr::null_mut();
+    if rclsid.is_null() {
+        return E_POINTER;
+    }
+
+    let class = classes
+        .iter()
+        .find(|(clsid, _)| IsEqualGUID(clsid, &*rclsid));
+    match class {
+        Some((_, factory)) => factory().QueryInterface(riid, ppv),
+        None => CLASS_E_CLASSNOTAVAILABLE,
+    }
+}
+
+/// What `DllCanUnloadNow` answers: `S_OK` once nothing holds the module lock, otherwise
+/// `S_FALSE`.
+pub fn can_unload_now() -> HRESULT {
+    if server_lock::count() == 0 {
+        S_OK
+    } else {
+        S_FALSE
+    }
+}
+
+#[doc(hidden)]
+/// `DllRegisterServer` for the classes listed in `com_server!`. Entries already written are
+/// removed again if a class fails to register.
+pub fn register_server(classes: &[(&GUID, Class)]) -> HRESULT {
+    let path = match registration::module_path() {
+        Ok(path) => path,
+        Err(hr) => return hr,
+    };
+    for (clsid, class) in classes {
+        debug_assert!(IsEqualGUID(clsid, class.clsid()));
+        if registration::register(&class.entries(&path), Scope::Machine).is_err() {
+            unregister_server(classes);
+            return SELFREG_E_CLASS;
+        }
+    }
+    S_OK
+}
+
+#[doc(hidden)]
+/// `DllUnregisterServer` for the classes listed in `com_server!`.
+pub fn unregister_server(classes: &[(&GUID, Class)]) -> HRESULT {
+    let mut result = S_OK;
+    for (_, class) in classes {
+        // Removing the keys doesn't depend on the server path
+        if registration::unregister(&class.entries(""), Scope::Machine).is_err() {
+            result = SELFREG_E_CLASS;
+        }
+    }
+    result
+}
@@ -0,0 +1,103 @@
+//! Counting what keeps a COM server loaded: live objects and `LockServer` calls.
+//!
+//! The module lock is a single count for the whole module. A [`ServerLock`] member makes
+//! `#[derive(ComImpl)]` objects hold one count from `create_raw` until their final `Release`,
+//! and `com_impl::class_factory::ClassFactory`'s `LockServer` adds and removes counts as well.
+//! `DllCanUnloadNow` may unload the module once the count is back at zero.
+//!
+//! ```no_run
+//! use com_impl::server_lock::{self, ServerLock};
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::unknwnbase::IUnknownVtbl;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Document {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     lock: ServerLock,
+//!     text: String,
+//! }
+//!
+//! let document = Document::create_raw("the lock is not a parameter".into());
+//! assert_eq!(server_lock::count(), 1);
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static RELEASES: Mutex<usize> = Mutex::new(0);
+static RELEASED: Condvar = Condvar::new();
+
+/// Adds one count to the module lock.
+pub fn lock() {
+    COUNT.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Removes one count from the module lock. An unlock without a matching [`lock`] is ignored.
+pub fn unlock() {
+    // An unbalanced unlock must not wrap around to locked forever
+    let previous = COUNT.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    if previous == Ok(1) {
+        let mut releases = RELEASES.lock().unwrap_or_else(|e| e.into_inner());
+        *releases += 1;
+        RELEASED.notify_all();
+    }
+}
+
+/// The live objects holding a [`ServerLock`], plus the `LockServer(TRUE)` calls not yet undone.
+pub fn count() -> usize {
+    COUNT.load(Ordering::Acquire)
+}
+
+/// How many times the count has dropped back to zero, for [`wait_for_release`].
+pub fn releases() -> usize {
+    *RELEASES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Blocks until the count drops back to zero for the first time after `seen`, a value of
+/// [`releases`] taken earlier. Taking it before the module can be locked means a release
+/// in between isn't missed.
+pub fn wait_for_release(seen: usize) {
+    let mut releases = RELEASES.lock().unwrap_or_else(|e| e.into_inner());
+    while *releases == seen {
+        releases = RELEASED.wait(releases).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// One count on the module lock, held until the value is dropped.
+///
+/// As a member of a `#[derive(ComImpl)]` struct, it is initialized with `Default` and is not a
+/// parameter of `create_raw`.
+pub struct ServerLock {
+    _private: (),
+}
+
+impl ServerLock {
+    pub fn new() -> ServerLock {
+        lock();
+        ServerLock { _private: () }
+    }
+}
+
+impl Default for ServerLock {
+    fn default() -> Self {
+        ServerLock::new()
+    }
+}
+
+impl Drop for ServerLock {
+    fn drop(&mut self) {
+        unlock();
+    }
+}
+
+impl fmt::Debug for ServerLock {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ServerLock")
+            .field("count", &count())
+            .finish()
+    }
+}
@@ -0,0 +1,818 @@
+//! Explorer context menu extensions built from a declarative list of [`MenuItem`]s.
+//!
+//! [`ContextMenu`] is a classic `IShellExtInit` + `IContextMenu3` handler, and
+//! [`ExplorerCommand`] exposes the same items through `IExplorerCommand` for the Windows 11
+//! menu. Both have to be handed out by a class factory registered for the CLSID used in
+//! [`context_menu_entries`] or [`explorer_command_entries`].
+//!
+//! ```no_run
+//! use com_impl::shell::context_menu::{ContextMenu, MenuItem};
+//!
+//! let menu = ContextMenu::new(vec![MenuItem::submenu(
+//!     "My Tool",
+//!     vec![
+//!         MenuItem::new("Count files", |paths| {
+//!             println!("{} selected", paths.len());
+//!             Ok(())
+//!         })
+//!         .verb("count"),
+//!         MenuItem::new("Open text files", |_paths| Ok(()))
+//!             .visible_when(|paths| paths.iter().all(|p| p.extension() == Some("txt".as_ref()))),
+//!     ],
+//! )]);
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::ffi::CStr;
+use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+use std::rc::Rc;
+
+use winapi::ctypes::c_void;
+use winapi::shared::basetsd::UINT_PTR;
+use winapi::shared::guiddef::{IsEqualIID, GUID, IID};
+use winapi::shared::minwindef::{
+    BOOL, DWORD, FALSE, HKEY, LPARAM, LRESULT, TRUE, UINT, ULONG, WPARAM,
+};
+use winapi::shared::windef::HMENU;
+use winapi::shared::winerror::{
+    E_FAIL, E_INVALIDARG, E_NOINTERFACE, E_NOTIMPL, E_POINTER, HRESULT, S_FALSE, S_OK,
+};
+use winapi::shared::wtypes::DVASPECT_CONTENT;
+use winapi::um::objidl::IBindCtx;
+use winapi::um::objidl::{IDataObject, FORMATETC, STGMEDIUM, TYMED_HGLOBAL};
+use winapi::um::shellapi::{DragQueryFileW, HDROP};
+use winapi::um::shobjidl_core::IShellItemArray;
+use winapi::um::shtypes::PCIDLIST_ABSOLUTE;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{LPSTR, LPWSTR};
+use winapi::um::winuser::{
+    CreatePopupMenu, DestroyMenu, InsertMenuItemW, CF_HDROP, MENUITEMINFOW, MIIM_ID, MIIM_STRING,
+    MIIM_SUBMENU,
+};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use super::{co_task_string, guid_string, shell_item_paths, RegistryEntry};
+use crate::{BuildVTable, Refcount, VTable};
+
+pub use self::ffi::{
+    IContextMenu, IContextMenu2, IContextMenu2Vtbl, IContextMenu3, IContextMenu3Vtbl,
+    IContextMenuVtbl, IEnumExplorerCommand, IEnumExplorerCommandVtbl, IExplorerCommand,
+    IExplorerCommandVtbl, IShellExtInit, IShellExtInitVtbl, CMINVOKECOMMANDINFO,
+};
+
+// None of these are provided by winapi.
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::basetsd::UINT_PTR;
+    use winapi::shared::guiddef::GUID;
+    use winapi::shared::minwindef::{BOOL, DWORD, HKEY, LPARAM, LRESULT, UINT, ULONG, WPARAM};
+    use winapi::shared::windef::{HMENU, HWND};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::objidl::IBindCtx;
+    use winapi::um::objidl::{IDataObject, STGMEDIUM};
+    use winapi::um::shobjidl_core::IShellItemArray;
+    use winapi::um::shtypes::PCIDLIST_ABSOLUTE;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::{HANDLE, INT, LPCSTR, LPSTR, LPWSTR};
+    use winapi::RIDL;
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    pub struct CMINVOKECOMMANDINFO {
+        pub cbSize: DWORD,
+        pub fMask: DWORD,
+        pub hwnd: HWND,
+        pub lpVerb: LPCSTR,
+        pub lpParameters: LPCSTR,
+        pub lpDirectory: LPCSTR,
+        pub nShow: INT,
+        pub dwHotKey: DWORD,
+        pub hIcon: HANDLE,
+    }
+
+    RIDL! {#[uuid(0x000214e8, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IShellExtInit(IShellExtInitVtbl): IUnknown(IUnknownVtbl) {
+        fn Initialize(
+            pidlFolder: PCIDLIST_ABSOLUTE,
+            pdtobj: *mut IDataObject,
+            hkeyProgID: HKEY,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x000214e4, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IContextMenu(IContextMenuVtbl): IUnknown(IUnknownVtbl) {
+        fn QueryContextMenu(
+            hmenu: HMENU,
+            indexMenu: UINT,
+            idCmdFirst: UINT,
+            idCmdLast: UINT,
+            uFlags: UINT,
+        ) -> HRESULT,
+        fn InvokeCommand(
+            pici: *mut CMINVOKECOMMANDINFO,
+        ) -> HRESULT,
+        fn GetCommandString(
+            idCmd: UINT_PTR,
+            uType: UINT,
+            pReserved: *mut UINT,
+            pszName: LPSTR,
+            cchMax: UINT,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x000214f4, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IContextMenu2(IContextMenu2Vtbl): IContextMenu(IContextMenuVtbl) {
+        fn HandleMenuMsg(
+            uMsg: UINT,
+            wParam: WPARAM,
+            lParam: LPARAM,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xbcfce0a0, 0xec17, 0x11d0, 0x8d, 0x10, 0x00, 0xa0, 0xc9, 0x0f, 0x27, 0x19)]
+    interface IContextMenu3(IContextMenu3Vtbl): IContextMenu2(IContextMenu2Vtbl) {
+        fn HandleMenuMsg2(
+            uMsg: UINT,
+            wParam: WPARAM,
+            lParam: LPARAM,
+            plResult: *mut LRESULT,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xa08ce4d0, 0xfa25, 0x44ab, 0xb5, 0x7c, 0xc7, 0xb1, 0xc3, 0x23, 0xe0, 0xb9)]
+    interface IExplorerCommand(IExplorerCommandVtbl): IUnknown(IUnknownVtbl) {
+        fn GetTitle(
+            psiItemArray: *mut IShellItemArray,
+            ppszName: *mut LPWSTR,
+        ) -> HRESULT,
+        fn GetIcon(
+            psiItemArray: *mut IShellItemArray,
+            ppszIcon: *mut LPWSTR,
+        ) -> HRESULT,
+        fn GetToolTip(
+            psiItemArray: *mut IShellItemArray,
+            ppszInfotip: *mut LPWSTR,
+        ) -> HRESULT,
+        fn GetCanonicalName(
+            pguidCommandName: *mut GUID,
+        ) -> HRESULT,
+        fn GetState(
+            psiItemArray: *mut IShellItemArray,
+            fOkToBeSlow: BOOL,
+            pCmdState: *mut DWORD,
+        ) -> HRESULT,
+        fn Invoke(
+            psiItemArray: *mut IShellItemArray,
+            pbc: *mut IBindCtx,
+        ) -> HRESULT,
+        fn GetFlags(
+            pFlags: *mut DWORD,
+        ) -> HRESULT,
+        fn EnumSubCommands(
+            ppEnum: *mut *mut IEnumExplorerCommand,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xa88826f8, 0x186f, 0x4987, 0xaa, 0xde, 0xea, 0x0c, 0xef, 0x8f, 0xbf, 0xe8)]
+    interface IEnumExplorerCommand(IEnumExplorerCommandVtbl): IUnknown(IUnknownVtbl) {
+        fn Next(
+            celt: ULONG,
+            pUICommand: *mut *mut IExplorerCommand,
+            pceltFetched: *mut ULONG,
+        ) -> HRESULT,
+        fn Skip(
+            celt: ULONG,
+        ) -> HRESULT,
+        fn Reset() -> HRESULT,
+        fn Clone(
+            ppenum: *mut *mut IEnumExplorerCommand,
+        ) -> HRESULT,
+    }}
+
+    #[link(name = "ole32")]
+    extern "system" {
+        pub fn ReleaseStgMedium(pmedium: *mut STGMEDIUM);
+    }
+}
+
+const CMF_DEFAULTONLY: UINT = 0x0000_0001;
+
+const GCS_VERBA: UINT = 0x0000_0000;
+const GCS_HELPTEXTA: UINT = 0x0000_0001;
+const GCS_VALIDATEA: UINT = 0x0000_0002;
+const GCS_UNICODE: UINT = 0x0000_0004;
+
+const ECS_ENABLED: DWORD = 0x0000_0000;
+const ECS_HIDDEN: DWORD = 0x0000_0002;
+const ECF_DEFAULT: DWORD = 0x0000_0000;
+const ECF_HASSUBCOMMANDS: DWORD = 0x0000_0001;
+
+type Handler = Box<dyn Fn(&[PathBuf]) -> Result<(), HRESULT>>;
+type Predicate = Box<dyn Fn(&[PathBuf]) -> bool>;
+
+/// A menu entry, either a command with a handler or a submenu.
+pub struct MenuItem {
+    title: String,
+    verb: Option<String>,
+    help: Option<String>,
+    icon: Option<String>,
+    handler: Option<Handler>,
+    visible: Option<Predicate>,
+    children: Vec<Rc<MenuItem>>,
+}
+
+impl MenuItem {
+    /// A command that calls `handler` with the selected paths when clicked.
+    pub fn new(
+        title: &str,
+        handler: impl Fn(&[PathBuf]) -> Result<(), HRESULT> + 'static,
+    ) -> MenuItem {
+        MenuItem {
+            handler: Some(Box::new(handler)),
+            ..MenuItem::empty(title)
+        }
+    }
+
+    pub fn submenu(title: &str, children: Vec<MenuItem>) -> MenuItem {
+        MenuItem {
+            children: children.into_iter().map(Rc::new).collect(),
+            ..MenuItem::empty(title)
+        }
+    }
+
+    fn empty(title: &str) -> MenuItem {
+        MenuItem {
+            title: title.into(),
+            verb: None,
+            help: None,
+            icon: None,
+            handler: None,
+            visible: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// The language-independent verb, used when the command is invoked by name.
+    pub fn verb(mut self, verb: &str) -> MenuItem {
+        self.verb = Some(verb.into());
+        self
+    }
+
+    /// Help text for the status bar or tooltip.
+    pub fn help(mut self, help: &str) -> MenuItem {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Icon resource in `path,index` form. Only shown through `IExplorerCommand`.
+    pub fn icon(mut self, icon: &str) -> MenuItem {
+        self.icon = Some(icon.into());
+        self
+    }
+
+    /// Only show the item when `f` returns true for the selection.
+    pub fn visible_when(mut self, f: impl Fn(&[PathBuf]) -> bool + 'static) -> MenuItem {
+        self.visible = Some(Box::new(f));
+        self
+    }
+
+    fn is_visible(&self, paths: &[PathBuf]) -> bool {
+        self.visible.as_ref().map(|f| f(paths)).unwrap_or(true)
+    }
+
+    fn invoke(&self, paths: &[PathBuf]) -> HRESULT {
+        match &self.handler {
+            Some(handler) => match handler(paths) {
+                Ok(()) => S_OK,
+                Err(hr) => hr,
+            },
+            None => E_FAIL,
+        }
+    }
+
+    fn find_verb(items: &[Rc<MenuItem>], verb: &str) -> Option<Rc<MenuItem>> {
+        items.iter().find_map(|item| {
+            if item.verb.as_deref() == Some(verb) {
+                Some(item.clone())
+            } else {
+                MenuItem::find_verb(&item.children, verb)
+            }
+        })
+    }
+}
+
+/// Entries registering `clsid` as a context menu handler named `name` for `file_type`, which
+/// can be an extension like `.txt`, a ProgID, `*` for all files, or `Directory`. Combine with
+/// [`class_entries`](super::class_entries).
+pub fn context_menu_entries(clsid: &GUID, name: &str, file_type: &str) -> Vec<RegistryEntry> {
+    let key = format!("{}\\shellex\\ContextMenuHandlers\\{}", file_type, name);
+    vec![RegistryEntry::new(key, None, guid_string(clsid))]
+}
+
+/// Entries adding `verb` to `file_type`, handled by the `IExplorerCommand` with `clsid`.
+pub fn explorer_command_entries(
+    clsid: &GUID,
+    verb: &str,
+    title: &str,
+    file_type: &str,
+) -> Vec<RegistryEntry> {
+    let key = format!("{}\\shell\\{}", file_type, verb);
+    vec![
+        RegistryEntry::new(key.clone(), Some("MUIVerb"), title.into()),
+        RegistryEntry::new(key, Some("ExplorerCommandHandler"), guid_string(clsid)),
+    ]
+}
+
+/// A classic context menu handler. It answers `IShellExtInit`, `IContextMenu`,
+/// `IContextMenu2` and `IContextMenu3`.
+///
+/// Not derived, because `IShellExtInit` lives on a second vtable.
+#[repr(C)]
+pub struct ContextMenu {
+    vtbl: VTable<IContextMenu3Vtbl>,
+    init_vtbl: VTable<IShellExtInitVtbl>,
+    refcount: Refcount,
+    items: Vec<Rc<MenuItem>>,
+    selection: RefCell<Rc<[PathBuf]>>,
+    commands: RefCell<Vec<Rc<MenuItem>>>,
+}
+
+impl ContextMenu {
+    pub fn new(items: Vec<MenuItem>) -> ComPtr<IContextMenu> {
+        let ptr = Box::into_raw(Box::new(ContextMenu {
+            vtbl: <ContextMenu as BuildVTable<_>>::STATIC_VTABLE,
+            init_vtbl: VTable::new(&Self::INIT_VTBL),
+            refcount: Default::default(),
+            items: items.into_iter().map(Rc::new).collect(),
+            selection: RefCell::new(Rc::new([])),
+            commands: RefCell::new(Vec::new()),
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IContextMenu) }
+    }
+
+    const INIT_VTBL: IShellExtInitVtbl = IShellExtInitVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::init_query_interface,
+            AddRef: Self::init_add_ref,
+            Release: Self::init_release,
+        },
+        Initialize: Self::initialize,
+    };
+
+    unsafe fn from_init(this: *mut IUnknown) -> *mut IUnknown {
+        (this as *mut u8).sub(mem::size_of::<VTable<IContextMenu3Vtbl>>()) as *mut IUnknown
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof())
+            || IsEqualIID(riid, &IContextMenu::uuidof())
+            || IsEqualIID(riid, &IContextMenu2::uuidof())
+            || IsEqualIID(riid, &IContextMenu3::uuidof())
+        {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            S_OK
+        } else if IsEqualIID(riid, &IShellExtInit::uuidof()) {
+            that.refcount.add_ref();
+            *ppv = &that.init_vtbl as *const _ as *mut c_void;
+            S_OK
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn init_query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        Self::query_interface(Self::from_init(this), riid, ppv)
+    }
+
+    unsafe extern "system" fn init_add_ref(this: *mut IUnknown) -> u32 {
+        Self::add_ref(Self::from_init(this))
+    }
+
+    unsafe extern "system" fn init_release(this: *mut IUnknown) -> u32 {
+        Self::release(Self::from_init(this))
+    }
+
+    unsafe extern "system" fn initialize(
+        this: *mut IShellExtInit,
+        _folder: PCIDLIST_ABSOLUTE,
+        data: *mut IDataObject,
+        _prog_id: HKEY,
+    ) -> HRESULT {
+        let that = &*(Self::from_init(this as *mut IUnknown) as *const Self);
+        // Background menus come without a data object, so the selection is empty
+        let paths = if data.is_null() {
+            Vec::new()
+        } else {
+            data_object_paths(&*data)
+        };
+        *that.selection.borrow_mut() = paths.into();
+        S_OK
+    }
+
+    unsafe fn insert_item(
+        &self,
+        menu: HMENU,
+        position: UINT,
+        item: &Rc<MenuItem>,
+        (first, last): (UINT, UINT),
+        commands: &mut Vec<Rc<MenuItem>>,
+    ) -> bool {
+        let id = first + commands.len() as UINT;
+        if id > last {
+            return false;
+        }
+
+        let mut title = super::to_wide(&item.title);
+        let mut info: MENUITEMINFOW = mem::zeroed();
+        info.cbSize = mem::size_of::<MENUITEMINFOW>() as UINT;
+        info.fMask = MIIM_STRING | MIIM_ID;
+        info.dwTypeData = title.as_mut_ptr();
+        info.wID = id;
+        commands.push(item.clone());
+
+        if !item.children.is_empty() {
+            let submenu = CreatePopupMenu();
+            let selection = self.selection.borrow().clone();
+            let mut sub_position = 0;
+            for child in item.children.iter().filter(|c| c.is_visible(&selection)) {
+                if self.insert_item(submenu, sub_position, child, (first, last), commands) {
+                    sub_position += 1;
+                }
+            }
+            info.fMask |= MIIM_SUBMENU;
+            info.hSubMenu = submenu;
+        }
+
+        if InsertMenuItemW(menu, position, TRUE, &info) == FALSE {
+            if !info.hSubMenu.is_null() {
+                DestroyMenu(info.hSubMenu);
+            }
+            return false;
+        }
+        true
+    }
+}
+
+unsafe impl BuildVTable<IUnknownVtbl> for ContextMenu {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: Self::query_interface,
+        AddRef: Self::add_ref,
+        Release: Self::release,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+#[com_impl::com_impl]
+unsafe impl IContextMenu for ContextMenu {
+    #[panic(result = "E_FAIL")]
+    unsafe fn query_context_menu(
+        &self,
+        menu: HMENU,
+        index: UINT,
+        first: UINT,
+        last: UINT,
+        flags: UINT,
+    ) -> HRESULT {
+        let mut commands = self.commands.borrow_mut();
+        commands.clear();
+        if flags & CMF_DEFAULTONLY != 0 {
+            return S_OK;
+        }
+
+        let selection = self.selection.borrow().clone();
+        let mut position = index;
+        for item in self.items.iter().filter(|i| i.is_visible(&selection)) {
+            if self.insert_item(menu, position, item, (first, last), &mut commands) {
+                position += 1;
+            }
+        }
+
+        // Success code holding the number of command IDs used
+        commands.len() as HRESULT
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn invoke_command(&self, info: *mut CMINVOKECOMMANDINFO) -> HRESULT {
+        if info.is_null() {
+            return E_INVALIDARG;
+        }
+
+        // The verb is either a command offset in the low word or a pointer to a verb string
+        let verb = (*info).lpVerb;
+        let item = if verb as usize >> 16 == 0 {
+            self.commands.borrow().get(verb as usize).cloned()
+        } else {
+            match CStr::from_ptr(verb).to_str() {
+                Ok(verb) => MenuItem::find_verb(&self.items, verb),
+                Err(_) => None,
+            }
+        };
+
+        match item {
+            Some(item) => {
+                let selection = self.selection.borrow().clone();
+                item.invoke(&selection)
+            }
+            None => E_FAIL,
+        }
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_command_string(
+        &self,
+        id: UINT_PTR,
+        kind: UINT,
+        _reserved: *mut UINT,
+        name: LPSTR,
+        max: UINT,
+    ) -> HRESULT {
+        let item = match self.commands.borrow().get(id).cloned() {
+            Some(item) => item,
+            None => return E_INVALIDARG,
+        };
+
+        let text = match kind & !GCS_UNICODE {
+            GCS_VERBA => &item.verb,
+            GCS_HELPTEXTA => &item.help,
+            GCS_VALIDATEA => return S_OK,
+            _ => return E_INVALIDARG,
+        };
+        let text = match text {
+            Some(text) => text,
+            None => return E_NOTIMPL,
+        };
+        if name.is_null() || max == 0 {
+            return E_INVALIDARG;
+        }
+
+        let max = max as usize - 1;
+        if kind & GCS_UNICODE != 0 {
+            let name = name as LPWSTR;
+            let len = text.encode_utf16().take(max).count();
+            for (i, c) in text.encode_utf16().take(len).enumerate() {
+                *name.add(i) = c;
+            }
+            *name.add(len) = 0;
+        } else {
+            let len = text.len().min(max);
+            ptr::copy_nonoverlapping(text.as_ptr(), name as *mut u8, len);
+            *name.add(len) = 0;
+        }
+        S_OK
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IContextMenu2 for ContextMenu {
+    fn handle_menu_msg(&self, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> HRESULT {
+        S_OK
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IContextMenu3 for ContextMenu {
+    unsafe fn handle_menu_msg2(
+        &self,
+        _msg: UINT,
+        _wparam: WPARAM,
+        _lparam: LPARAM,
+        result: *mut LRESULT,
+    ) -> HRESULT {
+        if !result.is_null() {
+            *result = 0;
+        }
+        S_OK
+    }
+}
+
+unsafe fn data_object_paths(data: &IDataObject) -> Vec<PathBuf> {
+    let format = FORMATETC {
+        cfFormat: CF_HDROP as u16,
+        ptd: ptr::null(),
+        dwAspect: DVASPECT_CONTENT,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL,
+    };
+    let mut medium: STGMEDIUM = mem::zeroed();
+    if data.GetData(&format, &mut medium) != S_OK {
+        return Vec::new();
+    }
+
+    // winapi declares the medium union as a pointer, which is where the HGLOBAL ends up
+    let drop = medium.u as HDROP;
+    let count = DragQueryFileW(drop, !0, ptr::null_mut(), 0);
+    let mut paths = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let len = DragQueryFileW(drop, i, ptr::null_mut(), 0);
+        let mut buf = vec![0u16; len as usize + 1];
+        let len = DragQueryFileW(drop, i, buf.as_mut_ptr(), buf.len() as UINT);
+        paths.push(String::from_utf16_lossy(&buf[..len as usize]).into());
+    }
+    ffi::ReleaseStgMedium(&mut medium);
+    paths
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// An `IExplorerCommand` for a [`MenuItem`]. Submenu items are enumerated as subcommands.
+pub struct ExplorerCommand {
+    vtbl: VTable<IExplorerCommandVtbl>,
+    refcount: Refcount,
+    item: Rc<MenuItem>,
+}
+
+impl ExplorerCommand {
+    pub fn new(item: MenuItem) -> ComPtr<IExplorerCommand> {
+        ExplorerCommand::from_item(Rc::new(item))
+    }
+
+    fn from_item(item: Rc<MenuItem>) -> ComPtr<IExplorerCommand> {
+        let ptr = ExplorerCommand::create_raw(item);
+        let ptr = ptr as *mut IExplorerCommand;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+}
+
+unsafe fn return_string(text: &Option<String>, out: *mut LPWSTR) -> HRESULT {
+    if out.is_null() {
+        return E_POINTER;
+    }
+    *out = ptr::null_mut();
+    match text {
+        Some(text) => match co_task_string(text) {
+            Ok(s) => {
+                *out = s;
+                S_OK
+            }
+            Err(hr) => hr,
+        },
+        None => E_NOTIMPL,
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IExplorerCommand for ExplorerCommand {
+    unsafe fn get_title(&self, _items: *mut IShellItemArray, name: *mut LPWSTR) -> HRESULT {
+        return_string(&Some(self.item.title.clone()), name)
+    }
+
+    unsafe fn get_icon(&self, _items: *mut IShellItemArray, icon: *mut LPWSTR) -> HRESULT {
+        return_string(&self.item.icon, icon)
+    }
+
+    unsafe fn get_tool_tip(&self, _items: *mut IShellItemArray, tip: *mut LPWSTR) -> HRESULT {
+        return_string(&self.item.help, tip)
+    }
+
+    fn get_canonical_name(&self, _name: *mut GUID) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_state(
+        &self,
+        items: *mut IShellItemArray,
+        _ok_to_be_slow: BOOL,
+        state: *mut DWORD,
+    ) -> HRESULT {
+        if state.is_null() {
+            return E_POINTER;
+        }
+        *state = if self.item.visible.is_none() || self.item.is_visible(&shell_item_paths(items)) {
+            ECS_ENABLED
+        } else {
+            ECS_HIDDEN
+        };
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn invoke(&self, items: *mut IShellItemArray, _bind: *mut IBindCtx) -> HRESULT {
+        self.item.invoke(&shell_item_paths(items))
+    }
+
+    unsafe fn get_flags(&self, flags: *mut DWORD) -> HRESULT {
+        if flags.is_null() {
+            return E_POINTER;
+        }
+        *flags = if self.item.children.is_empty() {
+            ECF_DEFAULT
+        } else {
+            ECF_HASSUBCOMMANDS
+        };
+        S_OK
+    }
+
+    unsafe fn enum_sub_commands(&self, commands: *mut *mut IEnumExplorerCommand) -> HRESULT {
+        if commands.is_null() {
+            return E_POINTER;
+        }
+        if self.item.children.is_empty() {
+            *commands = ptr::null_mut();
+            return E_NOTIMPL;
+        }
+        let ptr = CommandEnum::create_raw(self.item.clone(), Cell::new(0));
+        *commands = ptr as *mut IEnumExplorerCommand;
+        S_OK
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+struct CommandEnum {
+    vtbl: VTable<IEnumExplorerCommandVtbl>,
+    refcount: Refcount,
+    parent: Rc<MenuItem>,
+    position: Cell<usize>,
+}
+
+#[com_impl::com_impl]
+unsafe impl IEnumExplorerCommand for CommandEnum {
+    unsafe fn next(
+        &self,
+        count: ULONG,
+        commands: *mut *mut IExplorerCommand,
+        fetched: *mut ULONG,
+    ) -> HRESULT {
+        if commands.is_null() {
+            return E_POINTER;
+        }
+
+        let children = &self.parent.children;
+        let mut written = 0;
+        while written < count as usize && self.position.get() < children.len() {
+            let child = children[self.position.get()].clone();
+            *commands.add(written) = ExplorerCommand::from_item(child).into_raw();
+            self.position.set(self.position.get() + 1);
+            written += 1;
+        }
+
+        if !fetched.is_null() {
+            *fetched = written as ULONG;
+        }
+        if written == count as usize {
+            S_OK
+        } else {
+            S_FALSE
+        }
+    }
+
+    fn skip(&self, count: ULONG) -> HRESULT {
+        let len = self.parent.children.len();
+        let position = self.position.get() + count as usize;
+        self.position.set(position.min(len));
+        if position <= len {
+            S_OK
+        } else {
+            S_FALSE
+        }
+    }
+
+    fn reset(&self) -> HRESULT {
+        self.position.set(0);
+        S_OK
+    }
+
+    unsafe fn clone(&self, other: *mut *mut IEnumExplorerCommand) -> HRESULT {
+        if other.is_null() {
+            return E_POINTER;
+        }
+        let ptr = CommandEnum::create_raw(self.parent.clone(), self.position.clone());
+        *other = ptr as *mut IEnumExplorerCommand;
+        S_OK
+    }
+}
@@ -0,0 +1,80 @@
+//! Building blocks for Explorer shell extensions.
+//!
+//! The registry helpers here only describe the entries an extension needs. Most keys are
+//! relative to `HKEY_CLASSES_ROOT`, or `HKEY_CURRENT_USER\Software\Classes` for a per-user
+//! install, and writing them is left to the installer or `DllRegisterServer`.
+//! [`registration`](crate::registration) can turn them into a `.reg` script or WiX fragment.
+
+use std::path::PathBuf;
+use std::ptr;
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::winerror::{E_OUTOFMEMORY, HRESULT, SUCCEEDED};
+use winapi::um::combaseapi::{CoTaskMemAlloc, CoTaskMemFree};
+use winapi::um::shobjidl_core::{IShellItemArray, SIGDN_FILESYSPATH};
+use winapi::um::winnt::{LPCWSTR, LPWSTR};
+
+use crate::registration::Class;
+
+pub use crate::registration::{guid_string, RegistryEntry, RegistryRoot};
+
+pub mod context_menu;
+pub mod thumbnail;
+
+/// Entries registering `clsid` as an apartment-threaded in-process server in `dll_path`.
+pub fn class_entries(clsid: &GUID, description: &str, dll_path: &str) -> Vec<RegistryEntry> {
+    Class::new(*clsid, description).entries(dll_path)
+}
+
+pub(crate) fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+pub(crate) unsafe fn from_wide(s: LPCWSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *s.offset(i) != 0).count();
+    let s = std::slice::from_raw_parts(s, len);
+    Some(String::from_utf16_lossy(s))
+}
+
+/// Copies `s` into a string allocated with `CoTaskMemAlloc`, for returning to the shell.
+pub(crate) fn co_task_string(s: &str) -> Result<LPWSTR, HRESULT> {
+    let wide = to_wide(s);
+    unsafe {
+        let ptr = CoTaskMemAlloc(wide.len() * 2) as LPWSTR;
+        if ptr.is_null() {
+            return Err(E_OUTOFMEMORY);
+        }
+        ptr::copy_nonoverlapping(wide.as_ptr(), ptr, wide.len());
+        Ok(ptr)
+    }
+}
+
+/// File system paths of the items in `items`. Items without one are skipped.
+pub(crate) unsafe fn shell_item_paths(items: *mut IShellItemArray) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if items.is_null() {
+        return paths;
+    }
+
+    let items = &*items;
+    let mut count = 0;
+    if !SUCCEEDED(items.GetCount(&mut count)) {
+        return paths;
+    }
+    for i in 0..count {
+        let mut item = ptr::null_mut();
+        if !SUCCEEDED(items.GetItemAt(i, &mut item)) {
+            continue;
+        }
+        let mut name = ptr::null_mut();
+        if SUCCEEDED((*item).GetDisplayName(SIGDN_FILESYSPATH, &mut name)) {
+            paths.extend(from_wide(name).map(PathBuf::from));
+            CoTaskMemFree(name as _);
+        }
+        (*item).Release();
+    }
+    paths
+}
@@ -0,0 +1,682 @@
+//! Thumbnail and preview handlers driven by a single [`ThumbnailSource`] trait.
+//!
+//! Both [`ThumbnailProvider`] and [`PreviewHandler`] accept `IInitializeWithStream` and
+//! `IInitializeWithFile`, read the whole file into memory, and hand the bytes to the source.
+//! Preview handlers show the image returned for the size of the preview pane.
+//!
+//! ```no_run
+//! use com_impl::shell::thumbnail::{RgbaImage, ThumbnailProvider, ThumbnailSource};
+//! use winapi::shared::winerror::HRESULT;
+//!
+//! struct Solid;
+//!
+//! impl ThumbnailSource for Solid {
+//!     fn thumbnail(&self, data: &[u8], size: u32) -> Result<RgbaImage, HRESULT> {
+//!         let shade = data.first().cloned().unwrap_or(0);
+//!         let pixels = [shade, shade, shade, 255].repeat((size * size) as usize);
+//!         Ok(RgbaImage::new(size, size, pixels))
+//!     }
+//! }
+//!
+//! let provider = ThumbnailProvider::new(Solid);
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, GUID, IID};
+use winapi::shared::minwindef::{DWORD, UINT};
+use winapi::shared::windef::{HBITMAP, HWND, RECT};
+use winapi::shared::winerror::{
+    E_FAIL, E_INVALIDARG, E_NOINTERFACE, E_OUTOFMEMORY, E_POINTER, E_UNEXPECTED, HRESULT, S_FALSE,
+    S_OK,
+};
+use winapi::um::objidlbase::IStream;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::wingdi::{
+    CreateDIBSection, DeleteObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS,
+};
+use winapi::um::winnt::LPCWSTR;
+use winapi::um::winuser::{
+    CreateWindowExW, DestroyWindow, GetFocus, SendMessageW, SetFocus, SetWindowPos, IMAGE_BITMAP,
+    MSG, SS_BITMAP, SS_CENTERIMAGE, STM_SETIMAGE, SWP_NOACTIVATE, SWP_NOZORDER, WS_CHILD,
+    WS_VISIBLE,
+};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use super::{class_entries, from_wide, guid_string, to_wide, RegistryEntry};
+use crate::{BuildVTable, Refcount, VTable};
+
+pub use self::ffi::{
+    IInitializeWithFile, IInitializeWithFileVtbl, IInitializeWithStream, IInitializeWithStreamVtbl,
+    IPreviewHandler, IPreviewHandlerVtbl, IThumbnailProvider, IThumbnailProviderVtbl,
+};
+
+// None of these are provided by winapi.
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::minwindef::{DWORD, UINT};
+    use winapi::shared::windef::{HBITMAP, HWND, RECT};
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::objidlbase::IStream;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::LPCWSTR;
+    use winapi::um::winuser::MSG;
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0xb824b49d, 0x22ac, 0x4161, 0xac, 0x8a, 0x99, 0x16, 0xe8, 0xfa, 0x3f, 0x7f)]
+    interface IInitializeWithStream(IInitializeWithStreamVtbl): IUnknown(IUnknownVtbl) {
+        fn Initialize(
+            pstream: *mut IStream,
+            grfMode: DWORD,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xb7d14566, 0x0509, 0x4cce, 0xa7, 0x1f, 0x0a, 0x55, 0x42, 0x33, 0xbd, 0x9b)]
+    interface IInitializeWithFile(IInitializeWithFileVtbl): IUnknown(IUnknownVtbl) {
+        fn Initialize(
+            pszFilePath: LPCWSTR,
+            grfMode: DWORD,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xe357fccd, 0xa995, 0x4576, 0xb0, 0x1f, 0x23, 0x46, 0x30, 0x15, 0x4e, 0x96)]
+    interface IThumbnailProvider(IThumbnailProviderVtbl): IUnknown(IUnknownVtbl) {
+        fn GetThumbnail(
+            cx: UINT,
+            phbmp: *mut HBITMAP,
+            pdwAlpha: *mut DWORD,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x8895b1c6, 0xb41f, 0x4c1c, 0xa5, 0x62, 0x0d, 0x56, 0x42, 0x50, 0x83, 0x6f)]
+    interface IPreviewHandler(IPreviewHandlerVtbl): IUnknown(IUnknownVtbl) {
+        fn SetWindow(
+            hwnd: HWND,
+            prc: *const RECT,
+        ) -> HRESULT,
+        fn SetRect(
+            prc: *const RECT,
+        ) -> HRESULT,
+        fn DoPreview() -> HRESULT,
+        fn Unload() -> HRESULT,
+        fn SetFocus() -> HRESULT,
+        fn QueryFocus(
+            phwnd: *mut HWND,
+        ) -> HRESULT,
+        fn TranslateAccelerator(
+            pmsg: *mut MSG,
+        ) -> HRESULT,
+    }}
+}
+
+const WTSAT_ARGB: DWORD = 2;
+
+/// The surrogate process Explorer hosts preview handlers in.
+const PREVHOST_APPID: &str = "{6D2B5079-2F0B-48DD-AB7F-97CEC514D30B}";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// Straight (not premultiplied) RGBA pixels, 4 bytes per pixel, rows top to bottom.
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Copies the image into a top-down 32bpp BGRA DIB section.
+    fn to_bitmap(&self) -> Result<HBITMAP, HRESULT> {
+        let len = self.width as usize * self.height as usize * 4;
+        if self.width == 0 || self.height == 0 || self.pixels.len() < len {
+            return Err(E_INVALIDARG);
+        }
+
+        unsafe {
+            let mut info: BITMAPINFO = mem::zeroed();
+            info.bmiHeader = BITMAPINFOHEADER {
+                biSize: mem::size_of::<BITMAPINFOHEADER>() as DWORD,
+                biWidth: self.width as i32,
+                biHeight: -(self.height as i32),
+                biPlanes: 1,
+                biBitCount: 32,
+                biCompression: BI_RGB,
+                ..mem::zeroed()
+            };
+
+            let mut bits = ptr::null_mut();
+            let bitmap = CreateDIBSection(
+                ptr::null_mut(),
+                &info,
+                DIB_RGB_COLORS,
+                &mut bits,
+                ptr::null_mut(),
+                0,
+            );
+            if bitmap.is_null() || bits.is_null() {
+                return Err(E_OUTOFMEMORY);
+            }
+
+            let dst = std::slice::from_raw_parts_mut(bits as *mut u8, len);
+            for (dst, src) in dst.chunks_mut(4).zip(self.pixels.chunks(4)) {
+                dst.copy_from_slice(&[src[2], src[1], src[0], src[3]]);
+            }
+            Ok(bitmap)
+        }
+    }
+}
+
+/// Renders a file's contents as an image.
+pub trait ThumbnailSource {
+    /// `size` is the requested width and height. The image may be smaller in one dimension to
+    /// keep its aspect ratio, but should not be larger in either.
+    fn thumbnail(&self, data: &[u8], size: u32) -> Result<RgbaImage, HRESULT>;
+}
+
+/// Entries registering `clsid` as the thumbnail provider for `extension`, including the
+/// [`class_entries`].
+pub fn thumbnail_entries(
+    clsid: &GUID,
+    description: &str,
+    dll_path: &str,
+    extension: &str,
+) -> Vec<RegistryEntry> {
+    let mut entries = class_entries(clsid, description, dll_path);
+    let key = format!(
+        "{}\\ShellEx\\{}",
+        extension,
+        guid_string(&IThumbnailProvider::uuidof())
+    );
+    entries.push(RegistryEntry::new(key, None, guid_string(clsid)));
+    entries
+}
+
+/// Entries registering `clsid` as the preview handler for `extension`, including the
+/// [`class_entries`] and the surrogate host Explorer loads preview handlers in.
+pub fn preview_entries(
+    clsid: &GUID,
+    description: &str,
+    dll_path: &str,
+    extension: &str,
+) -> Vec<RegistryEntry> {
+    let clsid_string = guid_string(clsid);
+    let mut entries = class_entries(clsid, description, dll_path);
+    entries.push(RegistryEntry::new(
+        format!("CLSID\\{}", clsid_string),
+        Some("AppID"),
+        PREVHOST_APPID.into(),
+    ));
+    entries.push(RegistryEntry::new(
+        format!(
+            "{}\\ShellEx\\{}",
+            extension,
+            guid_string(&IPreviewHandler::uuidof())
+        ),
+        None,
+        clsid_string.clone(),
+    ));
+    entries.push(RegistryEntry::local_machine(
+        "Software\\Microsoft\\Windows\\CurrentVersion\\PreviewHandlers".into(),
+        Some(&clsid_string),
+        description.into(),
+    ));
+    entries
+}
+
+/// The two initialization vtables, placed directly after an object's primary vtable.
+#[repr(C)]
+struct InitVTables {
+    stream: VTable<IInitializeWithStreamVtbl>,
+    file: VTable<IInitializeWithFileVtbl>,
+}
+
+/// Implemented by the objects that embed [`InitVTables`] after their primary vtable.
+///
+/// # Safety
+///
+/// The implementing type must be `#[repr(C)]` with its primary vtable first and the
+/// [`InitVTables`] directly after it, and must be allocated with `Box`.
+unsafe trait Initialize: BuildVTable<IUnknownVtbl> + Sized {
+    fn primary() -> IID;
+
+    fn refcount(&self) -> &Refcount;
+    fn data(&self) -> &RefCell<Option<Vec<u8>>>;
+
+    const STREAM_VTBL: IInitializeWithStreamVtbl = IInitializeWithStreamVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: init_query_interface::<Self, 1>,
+            AddRef: init_add_ref::<Self, 1>,
+            Release: init_release::<Self, 1>,
+        },
+        Initialize: initialize_with_stream::<Self>,
+    };
+
+    const FILE_VTBL: IInitializeWithFileVtbl = IInitializeWithFileVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: init_query_interface::<Self, 2>,
+            AddRef: init_add_ref::<Self, 2>,
+            Release: init_release::<Self, 2>,
+        },
+        Initialize: initialize_with_file::<Self>,
+    };
+
+    fn init_vtables() -> InitVTables {
+        InitVTables {
+            stream: VTable::new(&Self::STREAM_VTBL),
+            file: VTable::new(&Self::FILE_VTBL),
+        }
+    }
+}
+
+/// Steps back `SLOT` vtable pointers to the start of the object.
+unsafe fn object<const SLOT: usize>(this: *mut IUnknown) -> *mut IUnknown {
+    (this as *mut *const c_void).sub(SLOT) as *mut IUnknown
+}
+
+unsafe extern "system" fn init_query_interface<T: Initialize, const SLOT: usize>(
+    this: *mut IUnknown,
+    riid: *const IID,
+    ppv: *mut *mut c_void,
+) -> HRESULT {
+    (T::VTBL.QueryInterface)(object::<SLOT>(this), riid, ppv)
+}
+
+unsafe extern "system" fn init_add_ref<T: Initialize, const SLOT: usize>(
+    this: *mut IUnknown,
+) -> u32 {
+    (T::VTBL.AddRef)(object::<SLOT>(this))
+}
+
+unsafe extern "system" fn init_release<T: Initialize, const SLOT: usize>(
+    this: *mut IUnknown,
+) -> u32 {
+    (T::VTBL.Release)(object::<SLOT>(this))
+}
+
+unsafe extern "system" fn initialize_with_stream<T: Initialize>(
+    this: *mut IInitializeWithStream,
+    stream: *mut IStream,
+    _mode: DWORD,
+) -> HRESULT {
+    let that = &*(object::<1>(this as *mut IUnknown) as *const T);
+    if stream.is_null() {
+        return E_POINTER;
+    }
+    if that.data().borrow().is_some() {
+        return E_UNEXPECTED;
+    }
+
+    let mut data = Vec::new();
+    let mut chunk = [0u8; 0x1000];
+    loop {
+        let mut read = 0;
+        let hr = (*stream).Read(
+            chunk.as_mut_ptr() as *mut c_void,
+            chunk.len() as u32,
+            &mut read,
+        );
+        if hr < 0 {
+            return hr;
+        }
+        data.extend_from_slice(&chunk[..read as usize]);
+        if hr != S_OK || read == 0 {
+            break;
+        }
+    }
+
+    *that.data().borrow_mut() = Some(data);
+    S_OK
+}
+
+unsafe extern "system" fn initialize_with_file<T: Initialize>(
+    this: *mut IInitializeWithFile,
+    path: LPCWSTR,
+    _mode: DWORD,
+) -> HRESULT {
+    let that = &*(object::<2>(this as *mut IUnknown) as *const T);
+    let path = match from_wide(path) {
+        Some(path) => path,
+        None => return E_POINTER,
+    };
+    if that.data().borrow().is_some() {
+        return E_UNEXPECTED;
+    }
+
+    match std::fs::read(path) {
+        Ok(data) => {
+            *that.data().borrow_mut() = Some(data);
+            S_OK
+        }
+        Err(_) => E_FAIL,
+    }
+}
+
+unsafe extern "system" fn query_interface<T: Initialize>(
+    this: *mut IUnknown,
+    riid: *const IID,
+    ppv: *mut *mut c_void,
+) -> HRESULT {
+    if ppv.is_null() {
+        return E_POINTER;
+    }
+    let that = &*(this as *const T);
+    let riid = &*riid;
+
+    let slot = if IsEqualIID(riid, &IUnknown::uuidof()) || IsEqualIID(riid, &T::primary()) {
+        0
+    } else if IsEqualIID(riid, &IInitializeWithStream::uuidof()) {
+        1
+    } else if IsEqualIID(riid, &IInitializeWithFile::uuidof()) {
+        2
+    } else {
+        *ppv = ptr::null_mut();
+        return E_NOINTERFACE;
+    };
+
+    that.refcount().add_ref();
+    *ppv = (this as *mut *const c_void).add(slot) as *mut c_void;
+    S_OK
+}
+
+unsafe extern "system" fn add_ref<T: Initialize>(this: *mut IUnknown) -> u32 {
+    let this = &*(this as *const T);
+    this.refcount().add_ref()
+}
+
+unsafe extern "system" fn release<T: Initialize>(this: *mut IUnknown) -> u32 {
+    let ptr = this as *mut T;
+    let count = (*ptr).refcount().release();
+    if count == 0 {
+        drop(Box::from_raw(ptr));
+    }
+    count
+}
+
+/// An `IThumbnailProvider` that also answers `IInitializeWithStream` and
+/// `IInitializeWithFile`.
+///
+/// Not derived, because the initialization interfaces live on their own vtables.
+#[repr(C)]
+pub struct ThumbnailProvider<T: ThumbnailSource> {
+    vtbl: VTable<IThumbnailProviderVtbl>,
+    init_vtbls: InitVTables,
+    refcount: Refcount,
+    data: RefCell<Option<Vec<u8>>>,
+    source: T,
+}
+
+impl<T: ThumbnailSource> ThumbnailProvider<T> {
+    pub fn new(source: T) -> ComPtr<IThumbnailProvider> {
+        let ptr = Box::into_raw(Box::new(ThumbnailProvider {
+            vtbl: <Self as BuildVTable<_>>::STATIC_VTABLE,
+            init_vtbls: Self::init_vtables(),
+            refcount: Default::default(),
+            data: RefCell::new(None),
+            source,
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IThumbnailProvider) }
+    }
+}
+
+unsafe impl<T: ThumbnailSource> Initialize for ThumbnailProvider<T> {
+    fn primary() -> IID {
+        IThumbnailProvider::uuidof()
+    }
+
+    fn refcount(&self) -> &Refcount {
+        &self.refcount
+    }
+
+    fn data(&self) -> &RefCell<Option<Vec<u8>>> {
+        &self.data
+    }
+}
+
+unsafe impl<T: ThumbnailSource> BuildVTable<IUnknownVtbl> for ThumbnailProvider<T> {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: query_interface::<Self>,
+        AddRef: add_ref::<Self>,
+        Release: release::<Self>,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: ThumbnailSource> IThumbnailProvider for ThumbnailProvider<T> {
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_thumbnail(&self, size: UINT, bitmap: *mut HBITMAP, alpha: *mut DWORD) -> HRESULT {
+        if bitmap.is_null() || alpha.is_null() {
+            return E_POINTER;
+        }
+        *bitmap = ptr::null_mut();
+
+        let data = self.data.borrow();
+        let data = match &*data {
+            Some(data) => data,
+            None => return E_UNEXPECTED,
+        };
+        let image = match self.source.thumbnail(data, size) {
+            Ok(image) => image,
+            Err(hr) => return hr,
+        };
+        match image.to_bitmap() {
+            Ok(handle) => {
+                *bitmap = handle;
+                *alpha = WTSAT_ARGB;
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+}
+
+/// An `IPreviewHandler` that shows the [`ThumbnailSource`] image for the size of the preview
+/// pane. Also answers `IInitializeWithStream` and `IInitializeWithFile`.
+///
+/// Not derived, because the initialization interfaces live on their own vtables.
+#[repr(C)]
+pub struct PreviewHandler<T: ThumbnailSource> {
+    vtbl: VTable<IPreviewHandlerVtbl>,
+    init_vtbls: InitVTables,
+    refcount: Refcount,
+    data: RefCell<Option<Vec<u8>>>,
+    source: T,
+    parent: Cell<HWND>,
+    rect: Cell<RECT>,
+    window: Cell<HWND>,
+    bitmap: Cell<HBITMAP>,
+}
+
+impl<T: ThumbnailSource> PreviewHandler<T> {
+    pub fn new(source: T) -> ComPtr<IPreviewHandler> {
+        let ptr = Box::into_raw(Box::new(PreviewHandler {
+            vtbl: <Self as BuildVTable<_>>::STATIC_VTABLE,
+            init_vtbls: Self::init_vtables(),
+            refcount: Default::default(),
+            data: RefCell::new(None),
+            source,
+            parent: Cell::new(ptr::null_mut()),
+            rect: Cell::new(RECT {
+                left: 0,
+                top: 0,
+                right: 0,
+                bottom: 0,
+            }),
+            window: Cell::new(ptr::null_mut()),
+            bitmap: Cell::new(ptr::null_mut()),
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IPreviewHandler) }
+    }
+
+    unsafe fn move_window(&self) {
+        let window = self.window.get();
+        if !window.is_null() {
+            let rect = self.rect.get();
+            SetWindowPos(
+                window,
+                ptr::null_mut(),
+                rect.left,
+                rect.top,
+                rect.right - rect.left,
+                rect.bottom - rect.top,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    unsafe fn destroy_window(&self) {
+        let window = self.window.replace(ptr::null_mut());
+        if !window.is_null() {
+            DestroyWindow(window);
+        }
+        let bitmap = self.bitmap.replace(ptr::null_mut());
+        if !bitmap.is_null() {
+            DeleteObject(bitmap as *mut _);
+        }
+    }
+}
+
+impl<T: ThumbnailSource> Drop for PreviewHandler<T> {
+    fn drop(&mut self) {
+        unsafe { self.destroy_window() }
+    }
+}
+
+unsafe impl<T: ThumbnailSource> Initialize for PreviewHandler<T> {
+    fn primary() -> IID {
+        IPreviewHandler::uuidof()
+    }
+
+    fn refcount(&self) -> &Refcount {
+        &self.refcount
+    }
+
+    fn data(&self) -> &RefCell<Option<Vec<u8>>> {
+        &self.data
+    }
+}
+
+unsafe impl<T: ThumbnailSource> BuildVTable<IUnknownVtbl> for PreviewHandler<T> {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: query_interface::<Self>,
+        AddRef: add_ref::<Self>,
+        Release: release::<Self>,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: ThumbnailSource> IPreviewHandler for PreviewHandler<T> {
+    unsafe fn set_window(&self, parent: HWND, rect: *const RECT) -> HRESULT {
+        if rect.is_null() {
+            return E_INVALIDARG;
+        }
+        self.parent.set(parent);
+        self.rect.set(*rect);
+        self.destroy_window();
+        S_OK
+    }
+
+    unsafe fn set_rect(&self, rect: *const RECT) -> HRESULT {
+        if rect.is_null() {
+            return E_INVALIDARG;
+        }
+        self.rect.set(*rect);
+        self.move_window();
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn do_preview(&self) -> HRESULT {
+        if self.parent.get().is_null() {
+            return E_UNEXPECTED;
+        }
+        self.destroy_window();
+
+        let rect = self.rect.get();
+        let size = (rect.right - rect.left).min(rect.bottom - rect.top).max(1) as u32;
+        let image = {
+            let data = self.data.borrow();
+            let data = match &*data {
+                Some(data) => data,
+                None => return E_UNEXPECTED,
+            };
+            match self.source.thumbnail(data, size) {
+                Ok(image) => image,
+                Err(hr) => return hr,
+            }
+        };
+        let bitmap = match image.to_bitmap() {
+            Ok(bitmap) => bitmap,
+            Err(hr) => return hr,
+        };
+        self.bitmap.set(bitmap);
+
+        let class = to_wide("STATIC");
+        let window = CreateWindowExW(
+            0,
+            class.as_ptr(),
+            ptr::null(),
+            WS_CHILD | WS_VISIBLE | SS_BITMAP | SS_CENTERIMAGE,
+            rect.left,
+            rect.top,
+            rect.right - rect.left,
+            rect.bottom - rect.top,
+            self.parent.get(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        );
+        if window.is_null() {
+            self.destroy_window();
+            return E_FAIL;
+        }
+        self.window.set(window);
+        SendMessageW(window, STM_SETIMAGE, IMAGE_BITMAP as usize, bitmap as isize);
+        self.move_window();
+        S_OK
+    }
+
+    unsafe fn unload(&self) -> HRESULT {
+        self.destroy_window();
+        *self.data.borrow_mut() = None;
+        S_OK
+    }
+
+    unsafe fn set_focus(&self) -> HRESULT {
+        let window = self.window.get();
+        if window.is_null() {
+            return S_FALSE;
+        }
+        SetFocus(window);
+        S_OK
+    }
+
+    unsafe fn query_focus(&self, focus: *mut HWND) -> HRESULT {
+        if focus.is_null() {
+            return E_INVALIDARG;
+        }
+        *focus = GetFocus();
+        if (*focus).is_null() {
+            E_FAIL
+        } else {
+            S_OK
+        }
+    }
+
+    fn translate_accelerator(&self, _msg: *mut MSG) -> HRESULT {
+        S_FALSE
+    }
+}
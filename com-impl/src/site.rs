@@ -0,0 +1,191 @@
+//! `IObjectWithSite` as a field of a derived COM object, for shell and browser extensions.
+//!
+//! Placing an [`ObjectWithSite`] member directly after the `VTable` is enough for
+//! `#[derive(ComImpl)]` to answer `QueryInterface` for `IObjectWithSite`. The member is not a
+//! parameter of `create_raw`.
+//!
+//! ```no_run
+//! use com_impl::site::ObjectWithSite;
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Extension {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     site: ObjectWithSite,
+//!     refcount: Refcount,
+//!     name: String,
+//! }
+//!
+//! impl Extension {
+//!     fn has_host(&self) -> bool {
+//!         // Usually asked for IServiceProvider or IWebBrowser2
+//!         self.site.get::<IUnknown>().is_some()
+//!     }
+//! }
+//!
+//! let extension = Extension::create_raw("only the other members are parameters".into());
+//! ```
+
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID, REFIID};
+use winapi::shared::winerror::{E_FAIL, E_POINTER, HRESULT, S_OK};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::VTable;
+
+pub use self::ffi::{IObjectWithSite, IObjectWithSiteVtbl};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::ctypes::c_void;
+    use winapi::shared::guiddef::REFIID;
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::RIDL;
+
+    // ocidl.h's IObjectWithSite is not covered by winapi.
+    RIDL! {#[uuid(0xfc4801a3, 0x2ba9, 0x11cf, 0xa2, 0x29, 0x00, 0xaa, 0x00, 0x3d, 0x73, 0x52)]
+    interface IObjectWithSite(IObjectWithSiteVtbl): IUnknown(IUnknownVtbl) {
+        fn SetSite(
+            pUnkSite: *mut IUnknown,
+        ) -> HRESULT,
+        fn GetSite(
+            riid: REFIID,
+            ppvSite: *mut *mut c_void,
+        ) -> HRESULT,
+    }}
+}
+
+#[repr(C)]
+/// The site handed to an object by its container, along with the `IObjectWithSite` vtable.
+///
+/// This must directly follow the object's primary `VTable` member, since the interface's
+/// `IUnknown` methods find the object by stepping back over that member.
+pub struct ObjectWithSite {
+    vtbl: VTable<IObjectWithSiteVtbl>,
+    site: RefCell<Option<ComPtr<IUnknown>>>,
+}
+
+impl ObjectWithSite {
+    const VTBL: IObjectWithSiteVtbl = IObjectWithSiteVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        SetSite: Self::set_site,
+        GetSite: Self::get_site,
+    };
+
+    pub fn new() -> ObjectWithSite {
+        ObjectWithSite {
+            vtbl: VTable::new(&Self::VTBL),
+            site: RefCell::new(None),
+        }
+    }
+
+    /// The site as `I`, if one is set and supports it.
+    pub fn get<I: Interface>(&self) -> Option<ComPtr<I>> {
+        self.site.borrow().as_ref()?.cast().ok()
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.site.borrow().is_some()
+    }
+
+    /// The pointer to hand out for `IObjectWithSite`.
+    pub fn as_ptr(&self) -> *mut IObjectWithSite {
+        &self.vtbl as *const _ as *mut IObjectWithSite
+    }
+
+    /// Releases the site, e.g. when the object is being torn down by its owner.
+    pub fn clear(&self) {
+        let old = self.site.borrow_mut().take();
+        drop(old);
+    }
+
+    unsafe fn outer(this: *mut IUnknown) -> *mut IUnknown {
+        (this as *mut u8).sub(mem::size_of::<VTable<IUnknownVtbl>>()) as *mut IUnknown
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        (*Self::outer(this)).QueryInterface(riid, ppv)
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        (*Self::outer(this)).AddRef()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        (*Self::outer(this)).Release()
+    }
+
+    unsafe extern "system" fn set_site(this: *mut IObjectWithSite, site: *mut IUnknown) -> HRESULT {
+        let this = &*(this as *const Self);
+        let site = if site.is_null() {
+            None
+        } else {
+            (*site).AddRef();
+            Some(ComPtr::from_raw(site))
+        };
+        // The old site may call back into this object while being released
+        let old = this.site.replace(site);
+        drop(old);
+        S_OK
+    }
+
+    unsafe extern "system" fn get_site(
+        this: *mut IObjectWithSite,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let this = &*(this as *const Self);
+        match &*this.site.borrow() {
+            Some(site) => site.QueryInterface(riid, ppv),
+            None => {
+                *ppv = ptr::null_mut();
+                E_FAIL
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]`'s `QueryInterface`.
+    pub fn query(&self, riid: &IID) -> Option<*mut c_void> {
+        if IsEqualIID(riid, &IObjectWithSite::uuidof()) {
+            Some(self.as_ptr() as *mut c_void)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ObjectWithSite {
+    fn default() -> Self {
+        ObjectWithSite::new()
+    }
+}
+
+impl fmt::Debug for ObjectWithSite {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ObjectWithSite")
+            .field("is_set", &self.is_set())
+            .finish()
+    }
+}
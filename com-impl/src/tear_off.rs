@@ -0,0 +1,133 @@
+//! Tear-off interfaces, implemented by a separate object created on first use.
+//!
+//! `#[tear_off(IExpensive = ExpensiveTearOff)]` on a `#[derive(ComImpl)]` struct with a
+//! [`TearOffs`] member makes `QueryInterface` answer `IExpensive` by creating an
+//! `ExpensiveTearOff` the first time it is asked for. The tear-off is aggregated by the object,
+//! so its interfaces share the object's identity and reference count, and it is released with
+//! the object. Rarely used interfaces, and their state, stay out of the object's allocation.
+//!
+//! The tear-off type needs an `com_impl::aggregation::Aggregation` member, and implements
+//! [`TearOff`] for the types it can be torn off from.
+//!
+//! ```no_run
+//! use com_impl::aggregation::Aggregation;
+//! use com_impl::tear_off::{TearOff, TearOffs};
+//! use com_impl::{Refcount, VTable};
+//! use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+//! use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[tear_off(IDWriteFontFileStream = StreamTearOff)]
+//! pub struct Font {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: Refcount,
+//!     tear_offs: TearOffs,
+//!     data: Vec<u8>,
+//! }
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! #[interfaces(IDWriteFontFileStream)]
+//! pub struct StreamTearOff {
+//!     vtbl: VTable<IDWriteFontFileStreamVtbl>,
+//!     refcount: Refcount,
+//!     aggregation: Aggregation,
+//!     // Outlived by the font, which owns the tear-off
+//!     font: *const Font,
+//! }
+//!
+//! unsafe impl TearOff<Font> for StreamTearOff {
+//!     fn create(owner: &Font, outer: *mut IUnknown) -> *mut IUnknown {
+//!         StreamTearOff::create_raw_aggregated(outer, owner)
+//!     }
+//! }
+//! # #[com_impl::com_impl]
+//! # unsafe impl IDWriteFontFileStream for StreamTearOff {
+//! #     fn get_file_size(&self, _: *mut u64) -> i32 { 0 }
+//! #     fn get_last_write_time(&self, _: *mut u64) -> i32 { 0 }
+//! #     fn read_file_fragment(
+//! #         &self, _: *mut *const winapi::ctypes::c_void, _: u64, _: u64,
+//! #         _: *mut *mut winapi::ctypes::c_void,
+//! #     ) -> i32 { 0 }
+//! #     fn release_file_fragment(&self, _: *mut winapi::ctypes::c_void) {}
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::Mutex;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::winerror::{E_NOINTERFACE, HRESULT};
+use winapi::um::unknwnbase::IUnknown;
+use wio::com::ComPtr;
+
+/// Implemented by a tear-off type for each type `Owner` it is torn off from. `create` must
+/// return a non-delegating `IUnknown` aggregated by `outer`, or null.
+///
+/// # Safety
+///
+/// The pointer `create` returns is queried and released as such an `IUnknown`, and owns one
+/// reference. The tear-off may keep a pointer to `owner`, but only use it until released.
+pub unsafe trait TearOff<Owner> {
+    /// Creates the tear-off for `owner`, aggregated by `outer`, and returns its
+    /// non-delegating `IUnknown`, usually through `create_raw_aggregated`.
+    ///
+    /// The tear-off is released before `owner` is dropped.
+    fn create(owner: &Owner, outer: *mut IUnknown) -> *mut IUnknown;
+}
+
+#[derive(Default)]
+/// The tear-offs of an object created so far.
+pub struct TearOffs {
+    created: Mutex<Vec<(usize, ComPtr<IUnknown>)>>,
+}
+
+impl TearOffs {
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]` to answer the `index`th `#[tear_off]`.
+    ///
+    /// # Safety
+    ///
+    /// `riid` and `ppv` must be valid as for `QueryInterface`. `create` must return null or
+    /// a non-delegating `IUnknown` with one reference, which the `TearOffs` takes over.
+    pub unsafe fn query(
+        &self,
+        index: usize,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+        create: impl FnOnce() -> *mut IUnknown,
+    ) -> HRESULT {
+        let inner = {
+            let mut created = self.created.lock().unwrap();
+            match created.iter().find(|(i, _)| *i == index) {
+                Some((_, inner)) => inner.as_raw(),
+                None => {
+                    let inner = create();
+                    if inner.is_null() {
+                        *ppv = std::ptr::null_mut();
+                        return E_NOINTERFACE;
+                    }
+                    created.push((index, ComPtr::from_raw(inner)));
+                    inner
+                }
+            }
+        };
+        // Not under the lock, as the tear-off may query its owner
+        (*inner).QueryInterface(riid, ppv)
+    }
+}
+
+impl fmt::Debug for TearOffs {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let count = self
+            .created
+            .lock()
+            .map(|created| created.len())
+            .unwrap_or(0);
+        fmt.debug_struct("TearOffs")
+            .field("created", &count)
+            .finish()
+    }
+}
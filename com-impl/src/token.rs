@@ -0,0 +1,79 @@
+//! Rust values carried through COM as opaque `IUnknown` pointers.
+//!
+//! APIs with context or cookie parameters typed as `IUnknown*` can be handed a [`ComToken`],
+//! which owns an arbitrary Rust value. Getting the value back checks both that the object is a
+//! token, through a private IID, and that it holds the expected type.
+//!
+//! ```no_run
+//! use com_impl::token::ComToken;
+//!
+//! struct Request {
+//!     id: u32,
+//! }
+//!
+//! let token = ComToken::new(Request { id: 7 });
+//!
+//! // Later, given the IUnknown back from the API
+//! let request = ComToken::<Request>::downcast_ref(&token).unwrap();
+//! assert_eq!(request.id, 7);
+//! assert!(ComToken::<String>::downcast_ref(&token).is_none());
+//! ```
+
+use std::any::TypeId;
+use std::ptr;
+
+use winapi::shared::winerror::SUCCEEDED;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+use self::ffi::IComToken;
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::RIDL;
+
+    // Only answered by ComToken, to recognize tokens among other objects.
+    RIDL! {#[uuid(0xeebbf333, 0x53cf, 0x46bc, 0x90, 0x6e, 0xa2, 0x16, 0x1a, 0x47, 0xc0, 0xf2)]
+    interface IComToken(IComTokenVtbl): IUnknown(IUnknownVtbl) {}}
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(marker(IComToken))]
+/// A COM object owning a `T`. The value is dropped with the last reference.
+pub struct ComToken<T: 'static> {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    // Shares its offset across all `T`, so it can be checked before the type is known
+    type_id: TypeId,
+    value: T,
+}
+
+impl<T: 'static> ComToken<T> {
+    pub fn new(value: T) -> ComPtr<IUnknown> {
+        let ptr = ComToken::create_raw(TypeId::of::<T>(), value);
+        let ptr = ptr as *mut IUnknown;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    /// The value, if `unknown` is a token holding a `T`.
+    pub fn downcast_ref(unknown: &IUnknown) -> Option<&T> {
+        unsafe {
+            let mut ptr = ptr::null_mut();
+            if !SUCCEEDED(unknown.QueryInterface(&IComToken::uuidof(), &mut ptr)) {
+                return None;
+            }
+            // `unknown` keeps the token alive for the lifetime of the result
+            (*(ptr as *mut IUnknown)).Release();
+
+            if (*(ptr as *const ComToken<()>)).type_id != TypeId::of::<T>() {
+                return None;
+            }
+            Some(&(*(ptr as *const ComToken<T>)).value)
+        }
+    }
+}
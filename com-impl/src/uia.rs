@@ -0,0 +1,650 @@
+//! UI Automation providers driven by a Rust trait, for exposing custom-drawn UI to screen
+//! readers and other accessibility clients.
+//!
+//! ```no_run
+//! use com_impl::uia::{Element, Provider, UIA_ButtonControlTypeId, UIA_ControlTypePropertyId,
+//!     UIA_NamePropertyId};
+//! use com_impl::variant::Variant;
+//! use winapi::shared::windef::HWND;
+//!
+//! struct Root {
+//!     hwnd: HWND,
+//! }
+//!
+//! impl Element for Root {
+//!     fn property(&self, property: i32) -> Option<Variant> {
+//!         match property {
+//!             UIA_NamePropertyId => Some("Play".into()),
+//!             UIA_ControlTypePropertyId => Some(UIA_ButtonControlTypeId.into()),
+//!             _ => None,
+//!         }
+//!     }
+//!
+//!     fn host(&self) -> Option<HWND> {
+//!         Some(self.hwnd)
+//!     }
+//! }
+//!
+//! # let hwnd = std::ptr::null_mut();
+//! let provider = Provider::new(Root { hwnd });
+//! // In the window procedure:
+//! // if let Some(result) = unsafe { return_provider(hwnd, wparam, lparam, &provider) } { ... }
+//! ```
+//!
+//! An element becomes a fragment, with parent/child navigation and a bounding rectangle, by
+//! returning itself from [`Element::fragment`]; a fragment root does the same through
+//! [`Fragment::root`]. Use `ComPtr::cast` to get the fragment interfaces of a [`Provider`].
+//!
+//! Providers are created with `ProviderOptions_UseComThreading` by default, so UI Automation
+//! calls them on the thread that owns the window rather than from its own worker threads.
+//!
+//! UI Automation is not covered by winapi, so the interfaces used here are defined in this
+//! module.
+
+#![allow(non_upper_case_globals)]
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID};
+use winapi::shared::minwindef::{LPARAM, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::shared::winerror::{E_FAIL, E_NOINTERFACE, E_POINTER, HRESULT, S_OK};
+use winapi::um::oaidl::{SAFEARRAY, VARIANT};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::LONG;
+use winapi::um::winuser::OBJID_CLIENT;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::variant::{SafeArray, Variant};
+use crate::{BuildVTable, Refcount, VTable};
+
+pub use self::ffi::{
+    IRawElementProviderFragment, IRawElementProviderFragmentRoot,
+    IRawElementProviderFragmentRootVtbl, IRawElementProviderFragmentVtbl,
+    IRawElementProviderSimple, IRawElementProviderSimpleVtbl, UiaRect,
+};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::minwindef::{LPARAM, WPARAM};
+    use winapi::shared::windef::HWND;
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::oaidl::{SAFEARRAY, VARIANT};
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::um::winnt::LONG;
+    use winapi::RIDL;
+
+    #[repr(C)]
+    #[derive(Copy, Clone, Debug, Default, PartialEq)]
+    pub struct UiaRect {
+        pub left: f64,
+        pub top: f64,
+        pub width: f64,
+        pub height: f64,
+    }
+
+    RIDL! {#[uuid(0xd6dd68d1, 0x86fd, 0x4332, 0x86, 0x66, 0x9a, 0xbe, 0xde, 0xa2, 0xd2, 0x4c)]
+    interface IRawElementProviderSimple(IRawElementProviderSimpleVtbl): IUnknown(IUnknownVtbl) {
+        fn get_ProviderOptions(
+            pRetVal: *mut LONG,
+        ) -> HRESULT,
+        fn GetPatternProvider(
+            patternId: LONG,
+            pRetVal: *mut *mut IUnknown,
+        ) -> HRESULT,
+        fn GetPropertyValue(
+            propertyId: LONG,
+            pRetVal: *mut VARIANT,
+        ) -> HRESULT,
+        fn get_HostRawElementProvider(
+            pRetVal: *mut *mut IRawElementProviderSimple,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0xf7063da8, 0x8359, 0x439c, 0x92, 0x97, 0xbb, 0xc5, 0x29, 0x9a, 0x7d, 0x87)]
+    interface IRawElementProviderFragment(IRawElementProviderFragmentVtbl):
+        IUnknown(IUnknownVtbl) {
+        fn Navigate(
+            direction: LONG,
+            pRetVal: *mut *mut IRawElementProviderFragment,
+        ) -> HRESULT,
+        fn GetRuntimeId(
+            pRetVal: *mut *mut SAFEARRAY,
+        ) -> HRESULT,
+        fn get_BoundingRectangle(
+            pRetVal: *mut UiaRect,
+        ) -> HRESULT,
+        fn GetEmbeddedFragmentRoots(
+            pRetVal: *mut *mut SAFEARRAY,
+        ) -> HRESULT,
+        fn SetFocus() -> HRESULT,
+        fn get_FragmentRoot(
+            pRetVal: *mut *mut IRawElementProviderFragmentRoot,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x620ce2a5, 0xab8f, 0x40a9, 0x86, 0xcb, 0xde, 0x3c, 0x75, 0x59, 0x9b, 0x58)]
+    interface IRawElementProviderFragmentRoot(IRawElementProviderFragmentRootVtbl):
+        IUnknown(IUnknownVtbl) {
+        fn ElementProviderFromPoint(
+            x: f64,
+            y: f64,
+            pRetVal: *mut *mut IRawElementProviderFragment,
+        ) -> HRESULT,
+        fn GetFocus(
+            pRetVal: *mut *mut IRawElementProviderFragment,
+        ) -> HRESULT,
+    }}
+
+    #[link(name = "uiautomationcore")]
+    extern "system" {
+        pub fn UiaHostProviderFromHwnd(
+            hwnd: HWND,
+            ppProvider: *mut *mut IRawElementProviderSimple,
+        ) -> HRESULT;
+        pub fn UiaReturnRawElementProvider(
+            hwnd: HWND,
+            wParam: WPARAM,
+            lParam: LPARAM,
+            el: *mut IRawElementProviderSimple,
+        ) -> LPARAM;
+    }
+}
+
+pub const ProviderOptions_ClientSideProvider: i32 = 0x1;
+pub const ProviderOptions_ServerSideProvider: i32 = 0x2;
+pub const ProviderOptions_NonClientAreaProvider: i32 = 0x4;
+pub const ProviderOptions_OverrideProvider: i32 = 0x8;
+pub const ProviderOptions_ProviderOwnsSetFocus: i32 = 0x10;
+pub const ProviderOptions_UseComThreading: i32 = 0x20;
+pub const ProviderOptions_UseClientCoordinates: i32 = 0x100;
+
+/// The first element of a fragment's runtime ID, asking UI Automation to prefix it with the
+/// host window's ID.
+pub const UiaAppendRuntimeId: i32 = 3;
+pub const UiaRootObjectId: i32 = -25;
+
+pub const UIA_InvokePatternId: i32 = 10000;
+pub const UIA_SelectionPatternId: i32 = 10001;
+pub const UIA_ValuePatternId: i32 = 10002;
+pub const UIA_RangeValuePatternId: i32 = 10003;
+pub const UIA_ScrollPatternId: i32 = 10004;
+pub const UIA_ExpandCollapsePatternId: i32 = 10005;
+pub const UIA_GridPatternId: i32 = 10006;
+pub const UIA_GridItemPatternId: i32 = 10007;
+pub const UIA_SelectionItemPatternId: i32 = 10010;
+pub const UIA_TextPatternId: i32 = 10014;
+pub const UIA_TogglePatternId: i32 = 10015;
+
+pub const UIA_BoundingRectanglePropertyId: i32 = 30001;
+pub const UIA_ProcessIdPropertyId: i32 = 30002;
+pub const UIA_ControlTypePropertyId: i32 = 30003;
+pub const UIA_LocalizedControlTypePropertyId: i32 = 30004;
+pub const UIA_NamePropertyId: i32 = 30005;
+pub const UIA_HasKeyboardFocusPropertyId: i32 = 30008;
+pub const UIA_IsKeyboardFocusablePropertyId: i32 = 30009;
+pub const UIA_IsEnabledPropertyId: i32 = 30010;
+pub const UIA_AutomationIdPropertyId: i32 = 30011;
+pub const UIA_ClassNamePropertyId: i32 = 30012;
+pub const UIA_HelpTextPropertyId: i32 = 30013;
+pub const UIA_IsControlElementPropertyId: i32 = 30016;
+pub const UIA_IsContentElementPropertyId: i32 = 30017;
+pub const UIA_IsOffscreenPropertyId: i32 = 30022;
+
+pub const UIA_ButtonControlTypeId: i32 = 50000;
+pub const UIA_CheckBoxControlTypeId: i32 = 50002;
+pub const UIA_ComboBoxControlTypeId: i32 = 50003;
+pub const UIA_EditControlTypeId: i32 = 50004;
+pub const UIA_HyperlinkControlTypeId: i32 = 50005;
+pub const UIA_ImageControlTypeId: i32 = 50006;
+pub const UIA_ListItemControlTypeId: i32 = 50007;
+pub const UIA_ListControlTypeId: i32 = 50008;
+pub const UIA_MenuItemControlTypeId: i32 = 50011;
+pub const UIA_ProgressBarControlTypeId: i32 = 50012;
+pub const UIA_RadioButtonControlTypeId: i32 = 50013;
+pub const UIA_SliderControlTypeId: i32 = 50015;
+pub const UIA_TabItemControlTypeId: i32 = 50019;
+pub const UIA_TextControlTypeId: i32 = 50020;
+pub const UIA_TreeItemControlTypeId: i32 = 50024;
+pub const UIA_CustomControlTypeId: i32 = 50025;
+pub const UIA_GroupControlTypeId: i32 = 50026;
+pub const UIA_DocumentControlTypeId: i32 = 50030;
+pub const UIA_WindowControlTypeId: i32 = 50032;
+pub const UIA_PaneControlTypeId: i32 = 50033;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NavigateDirection {
+    Parent,
+    NextSibling,
+    PreviousSibling,
+    FirstChild,
+    LastChild,
+}
+
+impl NavigateDirection {
+    fn from_raw(direction: LONG) -> Option<NavigateDirection> {
+        match direction {
+            0 => Some(NavigateDirection::Parent),
+            1 => Some(NavigateDirection::NextSibling),
+            2 => Some(NavigateDirection::PreviousSibling),
+            3 => Some(NavigateDirection::FirstChild),
+            4 => Some(NavigateDirection::LastChild),
+            _ => None,
+        }
+    }
+}
+
+/// The `IRawElementProviderSimple` side of an element.
+pub trait Element {
+    /// A combination of the `ProviderOptions_*` values.
+    fn options(&self) -> i32 {
+        ProviderOptions_ServerSideProvider | ProviderOptions_UseComThreading
+    }
+
+    /// The value of one of the `UIA_*PropertyId` properties, or `None` to let UI Automation
+    /// use its default.
+    fn property(&self, _property: i32) -> Option<Variant> {
+        None
+    }
+
+    /// The object implementing one of the `UIA_*PatternId` control patterns.
+    fn pattern(&self, _pattern: i32) -> Option<ComPtr<IUnknown>> {
+        None
+    }
+
+    /// The window this element is the root of. Only fragment roots and simple elements
+    /// hosted in their own window should return one.
+    fn host(&self) -> Option<HWND> {
+        None
+    }
+
+    /// Returning `Some` also exposes `IRawElementProviderFragment`.
+    fn fragment(&self) -> Option<&dyn Fragment> {
+        None
+    }
+}
+
+/// The `IRawElementProviderFragment` side of an element in a tree.
+pub trait Fragment {
+    fn navigate(&self, direction: NavigateDirection)
+        -> Option<ComPtr<IRawElementProviderFragment>>;
+
+    /// Unique among the element's siblings, usually `[UiaAppendRuntimeId, id]`. Fragment
+    /// roots hosted in a window may return an empty ID.
+    fn runtime_id(&self) -> Vec<i32>;
+
+    /// In screen coordinates.
+    fn bounding_rectangle(&self) -> UiaRect;
+
+    fn fragment_root(&self) -> Option<ComPtr<IRawElementProviderFragmentRoot>>;
+
+    fn embedded_fragment_roots(&self) -> Vec<ComPtr<IRawElementProviderSimple>> {
+        Vec::new()
+    }
+
+    fn set_focus(&self) -> Result<(), HRESULT> {
+        Ok(())
+    }
+
+    /// Returning `Some` also exposes `IRawElementProviderFragmentRoot`.
+    fn root(&self) -> Option<&dyn FragmentRoot> {
+        None
+    }
+}
+
+/// The `IRawElementProviderFragmentRoot` side of the top element of a tree.
+pub trait FragmentRoot {
+    /// The element at a point in screen coordinates.
+    fn element_from_point(&self, x: f64, y: f64) -> Option<ComPtr<IRawElementProviderFragment>>;
+
+    /// The element with keyboard focus, if it is not the root itself.
+    fn focus(&self) -> Option<ComPtr<IRawElementProviderFragment>> {
+        None
+    }
+}
+
+/// Answers `WM_GETOBJECT` with `provider`. Returns `None` for other object IDs, which should
+/// go to `DefWindowProc`.
+///
+/// # Safety
+///
+/// `hwnd`, `wparam` and `lparam` must come from a `WM_GETOBJECT` message.
+pub unsafe fn return_provider(
+    hwnd: HWND,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    provider: &ComPtr<IRawElementProviderSimple>,
+) -> Option<LPARAM> {
+    let id = lparam as i32;
+    if id != UiaRootObjectId && id != OBJID_CLIENT {
+        return None;
+    }
+    Some(ffi::UiaReturnRawElementProvider(
+        hwnd,
+        wparam,
+        lparam,
+        provider.as_raw(),
+    ))
+}
+
+fn write_out<I: Interface>(out: *mut *mut I, value: Option<ComPtr<I>>) -> HRESULT {
+    if out.is_null() {
+        return E_POINTER;
+    }
+    unsafe {
+        *out = value.map(ComPtr::into_raw).unwrap_or(ptr::null_mut());
+    }
+    S_OK
+}
+
+fn write_array(out: *mut *mut SAFEARRAY, array: Result<Option<SafeArray>, HRESULT>) -> HRESULT {
+    if out.is_null() {
+        return E_POINTER;
+    }
+    match array {
+        Ok(array) => {
+            unsafe {
+                *out = array.map(SafeArray::into_raw).unwrap_or(ptr::null_mut());
+            }
+            S_OK
+        }
+        Err(hr) => {
+            unsafe {
+                *out = ptr::null_mut();
+            }
+            hr
+        }
+    }
+}
+
+/// The fragment vtables are filled in by hand, so they need their own panic guard.
+fn guard(f: impl FnOnce() -> HRESULT) -> HRESULT {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(E_FAIL)
+}
+
+/// COM object exposing an [`Element`] as a UI Automation provider.
+///
+/// Not derived, because the fragment interfaces live on their own vtables.
+#[repr(C)]
+pub struct Provider<T: Element> {
+    vtbl: VTable<IRawElementProviderSimpleVtbl>,
+    fragment_vtbl: VTable<IRawElementProviderFragmentVtbl>,
+    root_vtbl: VTable<IRawElementProviderFragmentRootVtbl>,
+    refcount: Refcount,
+    element: T,
+}
+
+impl<T: Element> Provider<T> {
+    pub fn new(element: T) -> ComPtr<IRawElementProviderSimple> {
+        let ptr = Box::into_raw(Box::new(Provider {
+            vtbl: <Self as BuildVTable<_>>::STATIC_VTABLE,
+            fragment_vtbl: VTable::new(&Self::FRAGMENT_VTBL),
+            root_vtbl: VTable::new(&Self::ROOT_VTBL),
+            refcount: Default::default(),
+            element,
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IRawElementProviderSimple) }
+    }
+
+    const FRAGMENT_VTBL: IRawElementProviderFragmentVtbl = IRawElementProviderFragmentVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::slot_query_interface::<1>,
+            AddRef: Self::slot_add_ref::<1>,
+            Release: Self::slot_release::<1>,
+        },
+        Navigate: Self::navigate,
+        GetRuntimeId: Self::get_runtime_id,
+        get_BoundingRectangle: Self::get_bounding_rectangle,
+        GetEmbeddedFragmentRoots: Self::get_embedded_fragment_roots,
+        SetFocus: Self::set_focus,
+        get_FragmentRoot: Self::get_fragment_root,
+    };
+
+    const ROOT_VTBL: IRawElementProviderFragmentRootVtbl = IRawElementProviderFragmentRootVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::slot_query_interface::<2>,
+            AddRef: Self::slot_add_ref::<2>,
+            Release: Self::slot_release::<2>,
+        },
+        ElementProviderFromPoint: Self::element_provider_from_point,
+        GetFocus: Self::get_focus,
+    };
+
+    unsafe fn object<'a, const SLOT: usize>(this: *mut IUnknown) -> &'a Self {
+        &*((this as *mut *const c_void).sub(SLOT) as *const Self)
+    }
+
+    fn fragment(&self) -> Option<&dyn Fragment> {
+        self.element.fragment()
+    }
+
+    fn root(&self) -> Option<&dyn FragmentRoot> {
+        self.fragment().and_then(Fragment::root)
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        let slot = if IsEqualIID(riid, &IUnknown::uuidof())
+            || IsEqualIID(riid, &IRawElementProviderSimple::uuidof())
+        {
+            Some(0)
+        } else if IsEqualIID(riid, &IRawElementProviderFragment::uuidof()) {
+            that.fragment().map(|_| 1)
+        } else if IsEqualIID(riid, &IRawElementProviderFragmentRoot::uuidof()) {
+            that.root().map(|_| 2)
+        } else {
+            None
+        };
+
+        match slot {
+            Some(slot) => {
+                that.refcount.add_ref();
+                *ppv = (this as *mut *const c_void).add(slot) as *mut c_void;
+                S_OK
+            }
+            None => {
+                *ppv = ptr::null_mut();
+                E_NOINTERFACE
+            }
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn slot_query_interface<const SLOT: usize>(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        let that = Self::object::<SLOT>(this);
+        Self::query_interface(that as *const Self as *mut IUnknown, riid, ppv)
+    }
+
+    unsafe extern "system" fn slot_add_ref<const SLOT: usize>(this: *mut IUnknown) -> u32 {
+        Self::object::<SLOT>(this).refcount.add_ref()
+    }
+
+    unsafe extern "system" fn slot_release<const SLOT: usize>(this: *mut IUnknown) -> u32 {
+        let that = Self::object::<SLOT>(this);
+        Self::release(that as *const Self as *mut IUnknown)
+    }
+
+    unsafe extern "system" fn navigate(
+        this: *mut IRawElementProviderFragment,
+        direction: LONG,
+        result: *mut *mut IRawElementProviderFragment,
+    ) -> HRESULT {
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| {
+            let fragment = match (that.fragment(), NavigateDirection::from_raw(direction)) {
+                (Some(fragment), Some(direction)) => fragment.navigate(direction),
+                _ => None,
+            };
+            write_out(result, fragment)
+        })
+    }
+
+    unsafe extern "system" fn get_runtime_id(
+        this: *mut IRawElementProviderFragment,
+        result: *mut *mut SAFEARRAY,
+    ) -> HRESULT {
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| {
+            let id = that
+                .fragment()
+                .map(Fragment::runtime_id)
+                .unwrap_or_default();
+            if id.is_empty() {
+                return write_array(result, Ok(None));
+            }
+            write_array(result, SafeArray::from_i32s(&id).map(Some))
+        })
+    }
+
+    unsafe extern "system" fn get_bounding_rectangle(
+        this: *mut IRawElementProviderFragment,
+        result: *mut UiaRect,
+    ) -> HRESULT {
+        if result.is_null() {
+            return E_POINTER;
+        }
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| {
+            *result = that
+                .fragment()
+                .map(Fragment::bounding_rectangle)
+                .unwrap_or_default();
+            S_OK
+        })
+    }
+
+    unsafe extern "system" fn get_embedded_fragment_roots(
+        this: *mut IRawElementProviderFragment,
+        result: *mut *mut SAFEARRAY,
+    ) -> HRESULT {
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| {
+            let roots = that
+                .fragment()
+                .map(Fragment::embedded_fragment_roots)
+                .unwrap_or_default();
+            if roots.is_empty() {
+                return write_array(result, Ok(None));
+            }
+            write_array(result, SafeArray::from_unknowns(&roots).map(Some))
+        })
+    }
+
+    unsafe extern "system" fn set_focus(this: *mut IRawElementProviderFragment) -> HRESULT {
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| match that.fragment().map(Fragment::set_focus) {
+            Some(Ok(())) => S_OK,
+            Some(Err(hr)) => hr,
+            None => E_FAIL,
+        })
+    }
+
+    unsafe extern "system" fn get_fragment_root(
+        this: *mut IRawElementProviderFragment,
+        result: *mut *mut IRawElementProviderFragmentRoot,
+    ) -> HRESULT {
+        let that = Self::object::<1>(this as *mut IUnknown);
+        guard(|| write_out(result, that.fragment().and_then(Fragment::fragment_root)))
+    }
+
+    unsafe extern "system" fn element_provider_from_point(
+        this: *mut IRawElementProviderFragmentRoot,
+        x: f64,
+        y: f64,
+        result: *mut *mut IRawElementProviderFragment,
+    ) -> HRESULT {
+        let that = Self::object::<2>(this as *mut IUnknown);
+        guard(|| write_out(result, that.root().and_then(|r| r.element_from_point(x, y))))
+    }
+
+    unsafe extern "system" fn get_focus(
+        this: *mut IRawElementProviderFragmentRoot,
+        result: *mut *mut IRawElementProviderFragment,
+    ) -> HRESULT {
+        let that = Self::object::<2>(this as *mut IUnknown);
+        guard(|| write_out(result, that.root().and_then(FragmentRoot::focus)))
+    }
+}
+
+unsafe impl<T: Element> BuildVTable<IUnknownVtbl> for Provider<T> {
+    const VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: Self::query_interface,
+        AddRef: Self::add_ref,
+        Release: Self::release,
+    };
+
+    const STATIC_VTABLE: VTable<IUnknownVtbl> = VTable::new(&Self::VTBL);
+}
+
+#[com_impl::com_impl]
+unsafe impl<T: Element> IRawElementProviderSimple for Provider<T> {
+    #[com_name = "get_ProviderOptions"]
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_provider_options(&self, options: *mut LONG) -> HRESULT {
+        if options.is_null() {
+            return E_POINTER;
+        }
+        *options = self.element.options();
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_pattern_provider(&self, pattern: LONG, result: *mut *mut IUnknown) -> HRESULT {
+        write_out(result, self.element.pattern(pattern))
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_property_value(&self, property: LONG, result: *mut VARIANT) -> HRESULT {
+        if result.is_null() {
+            return E_POINTER;
+        }
+        let value = self.element.property(property).unwrap_or_default();
+        *result = value.into_raw();
+        S_OK
+    }
+
+    #[com_name = "get_HostRawElementProvider"]
+    #[panic(result = "E_FAIL")]
+    unsafe fn get_host_raw_element_provider(
+        &self,
+        result: *mut *mut IRawElementProviderSimple,
+    ) -> HRESULT {
+        if result.is_null() {
+            return E_POINTER;
+        }
+        *result = ptr::null_mut();
+        match self.element.host() {
+            Some(hwnd) => ffi::UiaHostProviderFromHwnd(hwnd, result),
+            None => S_OK,
+        }
+    }
+}
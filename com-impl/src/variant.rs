@@ -0,0 +1,315 @@
+//! Owned `VARIANT` and `SAFEARRAY` wrappers for automation-style interfaces.
+//!
+//! ```no_run
+//! use com_impl::variant::{SafeArray, Variant};
+//!
+//! let name = Variant::from("OK");
+//! assert_eq!(name.to_string().as_deref(), Some("OK"));
+//!
+//! let ids = SafeArray::from_i32s(&[3, 42]).unwrap();
+//! let value = Variant::from(ids);
+//! ```
+
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use winapi::shared::winerror::{E_OUTOFMEMORY, HRESULT};
+use winapi::shared::wtypes::{
+    BSTR, VARIANT_FALSE, VARIANT_TRUE, VARTYPE, VT_ARRAY, VT_BOOL, VT_BSTR, VT_EMPTY, VT_I4, VT_R8,
+    VT_UNKNOWN,
+};
+use winapi::um::oaidl::{SAFEARRAY, VARIANT};
+use winapi::um::oleauto::{
+    SafeArrayAccessData, SafeArrayCreateVector, SafeArrayDestroy, SafeArrayUnaccessData,
+    SysAllocStringLen, SysStringLen, VariantClear, VariantCopy,
+};
+use winapi::um::unknwnbase::IUnknown;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+/// An owned `VARIANT`, cleared with `VariantClear` when dropped.
+#[repr(transparent)]
+pub struct Variant(VARIANT);
+
+impl Variant {
+    /// An empty (`VT_EMPTY`) value.
+    pub fn new() -> Variant {
+        Variant(unsafe { mem::zeroed() })
+    }
+
+    /// Takes ownership of `raw`.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must be a valid `VARIANT` that nothing else will clear.
+    pub unsafe fn from_raw(raw: VARIANT) -> Variant {
+        Variant(raw)
+    }
+
+    /// Views a `VARIANT` owned by someone else, such as an `[in]` parameter.
+    pub fn from_raw_ref(raw: &VARIANT) -> &Variant {
+        unsafe { &*(raw as *const VARIANT as *const Variant) }
+    }
+
+    /// Releases ownership without clearing the value, e.g. to fill in an out parameter.
+    pub fn into_raw(self) -> VARIANT {
+        let raw = unsafe { ptr::read(&self.0) };
+        mem::forget(self);
+        raw
+    }
+
+    pub fn as_raw(&self) -> &VARIANT {
+        &self.0
+    }
+
+    /// For passing as an out parameter. Any previous value is cleared first.
+    pub fn as_mut_ptr(&mut self) -> *mut VARIANT {
+        self.clear();
+        &mut self.0
+    }
+
+    pub fn vt(&self) -> VARTYPE {
+        unsafe { self.0.n1.n2().vt }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vt() == VT_EMPTY as VARTYPE
+    }
+
+    pub fn clear(&mut self) {
+        unsafe {
+            VariantClear(&mut self.0);
+        }
+    }
+
+    fn with(vt: u32, set: impl FnOnce(&mut VARIANT)) -> Variant {
+        let mut value = Variant::new();
+        unsafe {
+            value.0.n1.n2_mut().vt = vt as VARTYPE;
+        }
+        set(&mut value.0);
+        value
+    }
+
+    pub fn to_bool(&self) -> Option<bool> {
+        match self.vt() as u32 {
+            VT_BOOL => Some(unsafe { *self.0.n1.n2().n3.boolVal() } != VARIANT_FALSE),
+            _ => None,
+        }
+    }
+
+    pub fn to_i32(&self) -> Option<i32> {
+        match self.vt() as u32 {
+            VT_I4 => Some(unsafe { *self.0.n1.n2().n3.lVal() }),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        match self.vt() as u32 {
+            VT_R8 => Some(unsafe { *self.0.n1.n2().n3.dblVal() }),
+            _ => None,
+        }
+    }
+
+    /// Reads `VT_BSTR` values.
+    pub fn to_string(&self) -> Option<String> {
+        match self.vt() as u32 {
+            VT_BSTR => unsafe {
+                let bstr = *self.0.n1.n2().n3.bstrVal() as BSTR;
+                if bstr.is_null() {
+                    return Some(String::new());
+                }
+                let s = std::slice::from_raw_parts(bstr, SysStringLen(bstr) as usize);
+                Some(String::from_utf16_lossy(s))
+            },
+            _ => None,
+        }
+    }
+
+    /// A new reference to a `VT_UNKNOWN` value.
+    pub fn to_unknown(&self) -> Option<ComPtr<IUnknown>> {
+        match self.vt() as u32 {
+            VT_UNKNOWN => unsafe {
+                let unk = *self.0.n1.n2().n3.punkVal();
+                if unk.is_null() {
+                    return None;
+                }
+                (*unk).AddRef();
+                Some(ComPtr::from_raw(unk))
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant::new()
+    }
+}
+
+impl Drop for Variant {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl Clone for Variant {
+    fn clone(&self) -> Self {
+        let mut copy = Variant::new();
+        unsafe {
+            VariantCopy(&mut copy.0, &self.0);
+        }
+        copy
+    }
+}
+
+impl fmt::Debug for Variant {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("Variant").field(&self.vt()).finish()
+    }
+}
+
+impl From<bool> for Variant {
+    fn from(value: bool) -> Variant {
+        let value = if value { VARIANT_TRUE } else { VARIANT_FALSE };
+        Variant::with(VT_BOOL, |v| unsafe {
+            *v.n1.n2_mut().n3.boolVal_mut() = value
+        })
+    }
+}
+
+impl From<i32> for Variant {
+    fn from(value: i32) -> Variant {
+        Variant::with(VT_I4, |v| unsafe { *v.n1.n2_mut().n3.lVal_mut() = value })
+    }
+}
+
+impl From<f64> for Variant {
+    fn from(value: f64) -> Variant {
+        Variant::with(VT_R8, |v| unsafe { *v.n1.n2_mut().n3.dblVal_mut() = value })
+    }
+}
+
+/// Stored as `VT_BSTR`.
+impl<'a> From<&'a str> for Variant {
+    fn from(value: &'a str) -> Variant {
+        let wide: Vec<u16> = value.encode_utf16().collect();
+        unsafe {
+            let bstr = SysAllocStringLen(wide.as_ptr(), wide.len() as u32);
+            if bstr.is_null() {
+                return Variant::new();
+            }
+            Variant::with(VT_BSTR, |v| *v.n1.n2_mut().n3.bstrVal_mut() = bstr as _)
+        }
+    }
+}
+
+impl From<String> for Variant {
+    fn from(value: String) -> Variant {
+        Variant::from(value.as_str())
+    }
+}
+
+/// Stored as `VT_UNKNOWN`, keeping the reference.
+impl<I: Interface> From<ComPtr<I>> for Variant {
+    fn from(value: ComPtr<I>) -> Variant {
+        let unk = value.into_raw() as *mut IUnknown;
+        Variant::with(VT_UNKNOWN, |v| unsafe {
+            *v.n1.n2_mut().n3.punkVal_mut() = unk
+        })
+    }
+}
+
+/// Stored as `VT_ARRAY` of the array's element type.
+impl From<SafeArray> for Variant {
+    fn from(value: SafeArray) -> Variant {
+        let vt = VT_ARRAY | value.vt as u32;
+        let array = value.into_raw();
+        Variant::with(vt, |v| unsafe {
+            *v.n1.n2_mut().n3.parray_mut() = array as _
+        })
+    }
+}
+
+/// An owned one-dimensional `SAFEARRAY`, destroyed when dropped.
+pub struct SafeArray {
+    ptr: *mut SAFEARRAY,
+    vt: VARTYPE,
+}
+
+impl SafeArray {
+    fn create<T: Copy>(vt: u32, items: &[T]) -> Result<SafeArray, HRESULT> {
+        unsafe {
+            let ptr = SafeArrayCreateVector(vt as VARTYPE, 0, items.len() as u32);
+            if ptr.is_null() {
+                return Err(E_OUTOFMEMORY);
+            }
+            let array = SafeArray {
+                ptr,
+                vt: vt as VARTYPE,
+            };
+
+            let mut data = ptr::null_mut();
+            let hr = SafeArrayAccessData(ptr, &mut data);
+            if hr < 0 {
+                return Err(hr);
+            }
+            ptr::copy_nonoverlapping(items.as_ptr(), data as *mut T, items.len());
+            SafeArrayUnaccessData(ptr);
+            Ok(array)
+        }
+    }
+
+    /// A `VT_I4` array, such as a UI Automation runtime ID.
+    pub fn from_i32s(items: &[i32]) -> Result<SafeArray, HRESULT> {
+        SafeArray::create(VT_I4, items)
+    }
+
+    /// A `VT_UNKNOWN` array holding a new reference to each item.
+    pub fn from_unknowns<I: Interface>(items: &[ComPtr<I>]) -> Result<SafeArray, HRESULT> {
+        let ptrs: Vec<*mut IUnknown> = items.iter().map(|i| i.as_raw() as *mut _).collect();
+        let array = SafeArray::create(VT_UNKNOWN, &ptrs)?;
+        for item in items {
+            unsafe {
+                (*(item.as_raw() as *mut IUnknown)).AddRef();
+            }
+        }
+        Ok(array)
+    }
+
+    pub fn vt(&self) -> VARTYPE {
+        self.vt
+    }
+
+    pub fn as_raw(&self) -> *mut SAFEARRAY {
+        self.ptr
+    }
+
+    /// Releases ownership, e.g. to fill in an out parameter.
+    pub fn into_raw(self) -> *mut SAFEARRAY {
+        let ptr = self.ptr;
+        mem::forget(self);
+        ptr
+    }
+}
+
+impl Drop for SafeArray {
+    fn drop(&mut self) {
+        // Releases the references held by VT_UNKNOWN arrays as well
+        unsafe {
+            SafeArrayDestroy(self.ptr);
+        }
+    }
+}
+
+impl fmt::Debug for SafeArray {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("SafeArray")
+            .field("ptr", &self.ptr)
+            .field("vt", &self.vt)
+            .finish()
+    }
+}
@@ -0,0 +1,304 @@
+//! Weak references, which observe an object without keeping it alive.
+//!
+//! A [`WeakRefcount`] member in place of the `Refcount` of a `#[derive(ComImpl)]` struct
+//! keeps the object's strong count in a separately allocated control block, which is also the
+//! object's `IWeakReference`. `QueryInterface` then answers `IWeakReferenceSource`, as WinRT
+//! consumers expect, and [`WeakRef`] holds such a reference from Rust.
+//!
+//! The control block outlives the object for as long as weak references to it remain. Once
+//! the last strong reference is released, `IWeakReference::Resolve` and [`WeakRef::upgrade`]
+//! find nothing.
+//!
+//! ```no_run
+//! use com_impl::weak::{WeakRef, WeakRefcount};
+//! use com_impl::VTable;
+//! use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+//! use wio::com::ComPtr;
+//!
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Document {
+//!     vtbl: VTable<IUnknownVtbl>,
+//!     refcount: WeakRefcount,
+//!     title: String,
+//! }
+//!
+//! let document = Document::create_raw("Untitled".into());
+//! let document = unsafe { ComPtr::from_raw(document as *mut IUnknown) };
+//! let weak = WeakRef::new(&document).unwrap();
+//! assert!(weak.upgrade::<IUnknown>().is_some());
+//!
+//! drop(document);
+//! assert!(weak.upgrade::<IUnknown>().is_none());
+//! ```
+
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{self, AtomicPtr, AtomicU32, Ordering};
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, REFIID};
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_POINTER, HRESULT, SUCCEEDED, S_OK};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::winrt::inspectable::IInspectable;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{ComRefcount, Refcount, VTable};
+
+pub use self::ffi::{
+    IWeakReference, IWeakReferenceSource, IWeakReferenceSourceVtbl, IWeakReferenceVtbl,
+};
+
+#[allow(non_snake_case)]
+mod ffi {
+    use winapi::shared::guiddef::REFIID;
+    use winapi::shared::winerror::HRESULT;
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::winrt::inspectable::IInspectable;
+    use winapi::RIDL;
+
+    // weakreference.h is not covered by winapi.
+    RIDL! {#[uuid(0x00000037, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IWeakReference(IWeakReferenceVtbl): IUnknown(IUnknownVtbl) {
+        fn Resolve(
+            riid: REFIID,
+            objectReference: *mut *mut IInspectable,
+        ) -> HRESULT,
+    }}
+
+    RIDL! {#[uuid(0x00000038, 0x0000, 0x0000, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46)]
+    interface IWeakReferenceSource(IWeakReferenceSourceVtbl): IUnknown(IUnknownVtbl) {
+        fn GetWeakReference(
+            weakReference: *mut *mut IWeakReference,
+        ) -> HRESULT,
+    }}
+}
+
+/// The reference count of an object that weak references can observe. Used like
+/// `com_impl::Refcount`, with the count kept in the object's `IWeakReference`.
+pub struct WeakRefcount {
+    control: ComPtr<IWeakReference>,
+}
+
+impl Default for WeakRefcount {
+    fn default() -> Self {
+        let control = Control::create_raw(AtomicU32::new(1), AtomicPtr::new(ptr::null_mut()));
+        WeakRefcount {
+            control: unsafe { ComPtr::from_raw(control as *mut IWeakReference) },
+        }
+    }
+}
+
+impl WeakRefcount {
+    fn control(&self) -> &Control {
+        unsafe { &*(self.control.as_raw() as *const Control) }
+    }
+
+    #[inline]
+    /// Adds a strong reference.
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `AddRef` may call it, for a reference it hands out.
+    pub unsafe fn add_ref(&self) -> u32 {
+        self.control()
+            .strong
+            .fetch_add(1, Ordering::Acquire)
+            .wrapping_add(1)
+    }
+
+    #[inline]
+    /// Releases a strong reference. Weak references resolve to nothing once this returns 0.
+    ///
+    /// # Safety
+    ///
+    /// Only the object's `Release` may call it, once for each reference, and drop the object
+    /// when it returns 0.
+    pub unsafe fn release(&self) -> u32 {
+        let count = self
+            .control()
+            .strong
+            .fetch_sub(1, Ordering::Release)
+            .wrapping_sub(1);
+        if count == 0 {
+            atomic::fence(Ordering::Acquire);
+        }
+        count
+    }
+
+    #[doc(hidden)]
+    /// Used by `#[derive(ComImpl)]` to answer `IWeakReferenceSource` for the object `this`.
+    /// `None` for any other IID.
+    pub unsafe fn query(
+        &self,
+        this: *mut IUnknown,
+        riid: REFIID,
+        ppv: *mut *mut c_void,
+    ) -> Option<HRESULT> {
+        if !IsEqualIID(&*riid, &IWeakReferenceSource::uuidof()) {
+            return None;
+        }
+        self.control().object.store(this, Ordering::Release);
+        (*this).AddRef();
+        let source = Source::create_raw(this, self.control.as_raw());
+        *ppv = source as *mut c_void;
+        Some(S_OK)
+    }
+}
+
+unsafe impl ComRefcount for WeakRefcount {
+    #[inline]
+    unsafe fn add_ref(&self) -> u32 {
+        WeakRefcount::add_ref(self)
+    }
+
+    #[inline]
+    unsafe fn release(&self) -> u32 {
+        WeakRefcount::release(self)
+    }
+}
+
+impl fmt::Debug for WeakRefcount {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let strong = self.control().strong.load(Ordering::Relaxed);
+        fmt.debug_struct("WeakRefcount")
+            .field("strong", &strong)
+            .finish()
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IWeakReference)]
+/// The control block of a [`WeakRefcount`]: the object's strong count, and the object once a
+/// weak reference to it has been handed out. Its own count is that of its weak references,
+/// plus the one of the object.
+struct Control {
+    vtbl: VTable<IWeakReferenceVtbl>,
+    refcount: Refcount,
+    strong: AtomicU32,
+    object: AtomicPtr<IUnknown>,
+}
+
+#[com_impl::com_impl]
+unsafe impl IWeakReference for Control {
+    unsafe fn resolve(&self, riid: REFIID, object: *mut *mut IInspectable) -> HRESULT {
+        if object.is_null() {
+            return E_POINTER;
+        }
+        *object = ptr::null_mut();
+
+        // Only an object that is still alive may gain a strong reference
+        let mut strong = self.strong.load(Ordering::Relaxed);
+        loop {
+            if strong == 0 {
+                return S_OK;
+            }
+            match self.strong.compare_exchange_weak(
+                strong,
+                strong + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => strong = current,
+            }
+        }
+
+        let unknown = self.object.load(Ordering::Acquire);
+        let hr = (*unknown).QueryInterface(riid, object as *mut *mut c_void);
+        (*unknown).Release();
+        hr
+    }
+}
+
+#[com_impl::com_impl(iunknown = manual)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IWeakReferenceSource)]
+/// The `IWeakReferenceSource` of an object, created for each query. Its IUnknown is the
+/// object's, and it holds a reference to the object.
+struct Source {
+    vtbl: VTable<IWeakReferenceSourceVtbl>,
+    refcount: Refcount,
+    owner: *mut IUnknown,
+    control: *mut IWeakReference,
+}
+
+#[com_impl::com_impl(no_parent)]
+unsafe impl IUnknown for Source {
+    unsafe fn query_interface(&self, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+        (*self.owner).QueryInterface(riid, ppv)
+    }
+
+    unsafe fn add_ref(&self) -> ULONG {
+        self.refcount.add_ref()
+    }
+
+    unsafe fn release(&self) -> ULONG {
+        let count = self.refcount.release();
+        if count == 0 {
+            (*self.owner).Release();
+            mem::drop(Box::from_raw(self as *const Self as *mut Self));
+        }
+        count
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IWeakReferenceSource for Source {
+    unsafe fn get_weak_reference(&self, reference: *mut *mut IWeakReference) -> HRESULT {
+        if reference.is_null() {
+            return E_POINTER;
+        }
+        (*self.control).AddRef();
+        *reference = self.control;
+        S_OK
+    }
+}
+
+#[derive(Clone)]
+/// A weak reference to a COM object, which doesn't keep it alive.
+pub struct WeakRef {
+    reference: ComPtr<IWeakReference>,
+}
+
+impl WeakRef {
+    /// A weak reference to `object`, if it is an `IWeakReferenceSource`.
+    pub fn new<I: Interface>(object: &ComPtr<I>) -> Option<WeakRef> {
+        let source = object.cast::<IWeakReferenceSource>().ok()?;
+        let mut reference = ptr::null_mut();
+        let hr = unsafe { source.GetWeakReference(&mut reference) };
+        if !SUCCEEDED(hr) || reference.is_null() {
+            return None;
+        }
+        Some(WeakRef {
+            reference: unsafe { ComPtr::from_raw(reference) },
+        })
+    }
+
+    /// The object as an `I`, if it is still alive and implements `I`.
+    pub fn upgrade<I: Interface>(&self) -> Option<ComPtr<I>> {
+        let mut object = ptr::null_mut();
+        let hr = unsafe { self.reference.Resolve(&I::uuidof(), &mut object) };
+        if !SUCCEEDED(hr) || object.is_null() {
+            return None;
+        }
+        Some(unsafe { ComPtr::from_raw(object as *mut I) })
+    }
+
+    pub fn as_raw(&self) -> *mut IWeakReference {
+        self.reference.as_raw()
+    }
+}
+
+impl fmt::Debug for WeakRef {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("WeakRef")
+            .field(&self.reference.as_raw())
+            .finish()
+    }
+}
@@ -0,0 +1,306 @@
+//! Closure-backed implementations of WebView2's one-method `...Handler` interfaces.
+//!
+//! Every WebView2 completed or event handler is `IUnknown` plus a single `Invoke` taking two
+//! arguments, so one generic object, [`Handler`], covers all of them given the handler's IID.
+//! The functions below instantiate it for the most common handlers.
+//!
+//! ```no_run
+//! use com_impl::webview2;
+//!
+//! let handler = webview2::create_environment_completed(|result| {
+//!     if let Ok(Some(environment)) = result {
+//!         // environment.as_raw() is the ICoreWebView2Environment pointer
+//!     }
+//! });
+//!
+//! // Pass handler.as_raw() to CreateCoreWebView2EnvironmentWithOptions
+//! ```
+//!
+//! WebView2 is not covered by winapi, and its interfaces are much larger than the handlers.
+//! Objects passed to the closures are therefore typed as `IUnknown`, but the pointer is the
+//! one WebView2 passed in (no `QueryInterface` is done), so `as_raw()` can be cast to the
+//! matching interface type of whichever bindings the host uses.
+//!
+//! WebView2 calls handlers on the UI thread that created the environment, so the closures
+//! don't need to be `Send`.
+
+#![allow(non_upper_case_globals, non_snake_case)]
+
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, GUID, IID};
+use winapi::shared::winerror::{E_FAIL, E_NOINTERFACE, E_POINTER, HRESULT, S_OK};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::LPCWSTR;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub const IID_ICoreWebView2CreateCoreWebView2EnvironmentCompletedHandler: IID = GUID {
+    Data1: 0x4e8a3389,
+    Data2: 0xc9d8,
+    Data3: 0x4bd2,
+    Data4: [0xb6, 0xb5, 0x12, 0x4f, 0xee, 0x6c, 0xc1, 0x4d],
+};
+pub const IID_ICoreWebView2CreateCoreWebView2ControllerCompletedHandler: IID = GUID {
+    Data1: 0x6c4819f3,
+    Data2: 0xc9b7,
+    Data3: 0x4260,
+    Data4: [0x81, 0x27, 0xc9, 0xf5, 0xbd, 0xe7, 0xf6, 0x8c],
+};
+pub const IID_ICoreWebView2ExecuteScriptCompletedHandler: IID = GUID {
+    Data1: 0x49511172,
+    Data2: 0xcc67,
+    Data3: 0x4bca,
+    Data4: [0x99, 0x23, 0x13, 0x71, 0x12, 0xf4, 0xc4, 0xcc],
+};
+pub const IID_ICoreWebView2AddScriptToExecuteOnDocumentCreatedCompletedHandler: IID = GUID {
+    Data1: 0xb99369f3,
+    Data2: 0x9b11,
+    Data3: 0x47b5,
+    Data4: [0xbc, 0x6f, 0x8e, 0x78, 0x95, 0xfc, 0xea, 0x17],
+};
+pub const IID_ICoreWebView2NavigationStartingEventHandler: IID = GUID {
+    Data1: 0x9adbe429,
+    Data2: 0xf36d,
+    Data3: 0x432b,
+    Data4: [0x9d, 0xdc, 0xf8, 0x88, 0x1f, 0xbd, 0x76, 0xe3],
+};
+pub const IID_ICoreWebView2NavigationCompletedEventHandler: IID = GUID {
+    Data1: 0xd33a35bf,
+    Data2: 0x1c49,
+    Data3: 0x4f98,
+    Data4: [0x93, 0xab, 0x00, 0x6e, 0x05, 0x33, 0xfe, 0x1c],
+};
+pub const IID_ICoreWebView2WebMessageReceivedEventHandler: IID = GUID {
+    Data1: 0x57213f19,
+    Data2: 0x00e6,
+    Data3: 0x49fa,
+    Data4: [0x8e, 0x07, 0x89, 0x8e, 0xa0, 0x1e, 0xcb, 0xd2],
+};
+pub const IID_ICoreWebView2DocumentTitleChangedEventHandler: IID = GUID {
+    Data1: 0xf5f2b923,
+    Data2: 0x953e,
+    Data3: 0x4042,
+    Data4: [0x9f, 0x95, 0xf3, 0xa1, 0x18, 0xe1, 0xaf, 0xd4],
+};
+
+#[repr(C)]
+/// The vtable layout shared by every WebView2 handler interface.
+pub struct HandlerVtbl<A, B> {
+    pub parent: IUnknownVtbl,
+    pub Invoke: unsafe extern "system" fn(this: *mut IUnknown, a: A, b: B) -> HRESULT,
+}
+
+type InvokeFn<A, B> = Box<dyn Fn(A, B) -> HRESULT>;
+
+/// COM object implementing the handler interface `iid` with a closure over the raw `Invoke`
+/// arguments.
+///
+/// Not derived, because the interface is only known at runtime.
+#[repr(C)]
+pub struct Handler<A: Copy + 'static, B: Copy + 'static> {
+    vtbl: VTable<HandlerVtbl<A, B>>,
+    refcount: Refcount,
+    iid: IID,
+    invoke: InvokeFn<A, B>,
+}
+
+impl<A: Copy + 'static, B: Copy + 'static> Handler<A, B> {
+    const VTBL: HandlerVtbl<A, B> = HandlerVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: Self::query_interface,
+            AddRef: Self::add_ref,
+            Release: Self::release,
+        },
+        Invoke: Self::invoke,
+    };
+
+    /// `f` receives the arguments exactly as WebView2 passed them; pointers are borrowed for
+    /// the duration of the call.
+    pub fn new(iid: IID, f: impl Fn(A, B) -> HRESULT + 'static) -> ComPtr<IUnknown> {
+        let ptr = Box::into_raw(Box::new(Handler {
+            vtbl: VTable::new(&Self::VTBL),
+            refcount: Default::default(),
+            iid,
+            invoke: Box::new(f),
+        }));
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof()) || IsEqualIID(riid, &that.iid) {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            S_OK
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn invoke(this: *mut IUnknown, a: A, b: B) -> HRESULT {
+        let that = &*(this as *const Self);
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (that.invoke)(a, b)))
+            .unwrap_or(E_FAIL)
+    }
+}
+
+unsafe fn borrowed(object: *mut IUnknown) -> Option<ComPtr<IUnknown>> {
+    if object.is_null() {
+        return None;
+    }
+    (*object).AddRef();
+    Some(ComPtr::from_raw(object))
+}
+
+unsafe fn from_wide(s: LPCWSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *s.offset(i) != 0).count();
+    let s = std::slice::from_raw_parts(s, len);
+    Some(String::from_utf16_lossy(s))
+}
+
+/// A `...CompletedHandler` whose result is an object, such as the environment or controller.
+pub fn completed_handler(
+    iid: IID,
+    f: impl Fn(Result<Option<ComPtr<IUnknown>>, HRESULT>) + 'static,
+) -> ComPtr<IUnknown> {
+    Handler::new(iid, move |error: HRESULT, result: *mut IUnknown| {
+        if error < 0 {
+            f(Err(error));
+        } else {
+            f(Ok(unsafe { borrowed(result) }));
+        }
+        S_OK
+    })
+}
+
+/// A `...CompletedHandler` whose result is a string.
+pub fn string_completed_handler(
+    iid: IID,
+    f: impl Fn(Result<String, HRESULT>) + 'static,
+) -> ComPtr<IUnknown> {
+    Handler::new(iid, move |error: HRESULT, result: LPCWSTR| {
+        if error < 0 {
+            f(Err(error));
+        } else {
+            f(Ok(unsafe { from_wide(result) }.unwrap_or_default()));
+        }
+        S_OK
+    })
+}
+
+/// An `...EventHandler`, called with the sender and the event arguments. Some events, such as
+/// `DocumentTitleChanged`, have no arguments.
+///
+/// An `Err` from `f` is returned to WebView2.
+pub fn event_handler(
+    iid: IID,
+    f: impl Fn(ComPtr<IUnknown>, Option<ComPtr<IUnknown>>) -> Result<(), HRESULT> + 'static,
+) -> ComPtr<IUnknown> {
+    Handler::new(iid, move |sender: *mut IUnknown, args: *mut IUnknown| {
+        let sender = match unsafe { borrowed(sender) } {
+            Some(sender) => sender,
+            None => return E_POINTER,
+        };
+        match f(sender, unsafe { borrowed(args) }) {
+            Ok(()) => S_OK,
+            Err(hr) => hr,
+        }
+    })
+}
+
+/// For `CreateCoreWebView2EnvironmentWithOptions`. The object is an
+/// `ICoreWebView2Environment`.
+pub fn create_environment_completed(
+    f: impl Fn(Result<Option<ComPtr<IUnknown>>, HRESULT>) + 'static,
+) -> ComPtr<IUnknown> {
+    completed_handler(
+        IID_ICoreWebView2CreateCoreWebView2EnvironmentCompletedHandler,
+        f,
+    )
+}
+
+/// For `ICoreWebView2Environment::CreateCoreWebView2Controller`. The object is an
+/// `ICoreWebView2Controller`.
+pub fn create_controller_completed(
+    f: impl Fn(Result<Option<ComPtr<IUnknown>>, HRESULT>) + 'static,
+) -> ComPtr<IUnknown> {
+    completed_handler(
+        IID_ICoreWebView2CreateCoreWebView2ControllerCompletedHandler,
+        f,
+    )
+}
+
+/// For `ICoreWebView2::ExecuteScript`. The string is the script's result as JSON.
+pub fn execute_script_completed(f: impl Fn(Result<String, HRESULT>) + 'static) -> ComPtr<IUnknown> {
+    string_completed_handler(IID_ICoreWebView2ExecuteScriptCompletedHandler, f)
+}
+
+/// For `ICoreWebView2::AddScriptToExecuteOnDocumentCreated`. The string is the script's ID.
+pub fn add_script_completed(f: impl Fn(Result<String, HRESULT>) + 'static) -> ComPtr<IUnknown> {
+    string_completed_handler(
+        IID_ICoreWebView2AddScriptToExecuteOnDocumentCreatedCompletedHandler,
+        f,
+    )
+}
+
+/// For `ICoreWebView2::add_NavigationStarting`.
+pub fn navigation_starting(
+    f: impl Fn(ComPtr<IUnknown>, Option<ComPtr<IUnknown>>) -> Result<(), HRESULT> + 'static,
+) -> ComPtr<IUnknown> {
+    event_handler(IID_ICoreWebView2NavigationStartingEventHandler, f)
+}
+
+/// For `ICoreWebView2::add_NavigationCompleted`.
+pub fn navigation_completed(
+    f: impl Fn(ComPtr<IUnknown>, Option<ComPtr<IUnknown>>) -> Result<(), HRESULT> + 'static,
+) -> ComPtr<IUnknown> {
+    event_handler(IID_ICoreWebView2NavigationCompletedEventHandler, f)
+}
+
+/// For `ICoreWebView2::add_WebMessageReceived`.
+pub fn web_message_received(
+    f: impl Fn(ComPtr<IUnknown>, Option<ComPtr<IUnknown>>) -> Result<(), HRESULT> + 'static,
+) -> ComPtr<IUnknown> {
+    event_handler(IID_ICoreWebView2WebMessageReceivedEventHandler, f)
+}
+
+/// For `ICoreWebView2::add_DocumentTitleChanged`.
+pub fn document_title_changed(
+    f: impl Fn(ComPtr<IUnknown>) -> Result<(), HRESULT> + 'static,
+) -> ComPtr<IUnknown> {
+    event_handler(
+        IID_ICoreWebView2DocumentTitleChangedEventHandler,
+        move |sender, _| f(sender),
+    )
+}
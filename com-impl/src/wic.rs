@@ -0,0 +1,206 @@
+//! Exposing Rust-owned pixel data to WIC.
+//!
+//! [`BitmapSource`] wraps any byte buffer as an `IWICBitmapSource` without copying it, so pixels
+//! generated in Rust can be handed straight to a WIC encoder, a format converter, or
+//! `ID2D1RenderTarget::CreateBitmapFromWicBitmap`.
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::winerror::{
+    E_INVALIDARG, E_POINTER, HRESULT, S_OK, WINCODEC_ERR_INSUFFICIENTBUFFER,
+    WINCODEC_ERR_PALETTEUNAVAILABLE,
+};
+use winapi::um::wincodec::{
+    GUID_WICPixelFormat16bppGray, GUID_WICPixelFormat24bppBGR, GUID_WICPixelFormat24bppRGB,
+    GUID_WICPixelFormat32bppBGR, GUID_WICPixelFormat32bppBGRA, GUID_WICPixelFormat32bppPBGRA,
+    GUID_WICPixelFormat32bppPRGBA, GUID_WICPixelFormat32bppRGBA, GUID_WICPixelFormat64bppRGBA,
+    GUID_WICPixelFormat8bppAlpha, GUID_WICPixelFormat8bppGray, IWICBitmapSource,
+    IWICBitmapSourceVtbl, IWICPalette, WICPixelFormatGUID, WICRect,
+};
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+#[derive(Copy, Clone)]
+/// A WIC pixel format GUID along with its size. Only byte-aligned formats are supported.
+pub struct PixelFormat {
+    pub guid: WICPixelFormatGUID,
+    pub bits_per_pixel: u32,
+}
+
+impl PixelFormat {
+    pub const GRAY8: PixelFormat = PixelFormat::new(GUID_WICPixelFormat8bppGray, 8);
+    pub const ALPHA8: PixelFormat = PixelFormat::new(GUID_WICPixelFormat8bppAlpha, 8);
+    pub const GRAY16: PixelFormat = PixelFormat::new(GUID_WICPixelFormat16bppGray, 16);
+    pub const BGR24: PixelFormat = PixelFormat::new(GUID_WICPixelFormat24bppBGR, 24);
+    pub const RGB24: PixelFormat = PixelFormat::new(GUID_WICPixelFormat24bppRGB, 24);
+    pub const BGR32: PixelFormat = PixelFormat::new(GUID_WICPixelFormat32bppBGR, 32);
+    pub const BGRA32: PixelFormat = PixelFormat::new(GUID_WICPixelFormat32bppBGRA, 32);
+    pub const PBGRA32: PixelFormat = PixelFormat::new(GUID_WICPixelFormat32bppPBGRA, 32);
+    pub const RGBA32: PixelFormat = PixelFormat::new(GUID_WICPixelFormat32bppRGBA, 32);
+    pub const PRGBA32: PixelFormat = PixelFormat::new(GUID_WICPixelFormat32bppPRGBA, 32);
+    pub const RGBA64: PixelFormat = PixelFormat::new(GUID_WICPixelFormat64bppRGBA, 64);
+
+    pub const fn new(guid: GUID, bits_per_pixel: u32) -> PixelFormat {
+        PixelFormat {
+            guid,
+            bits_per_pixel,
+        }
+    }
+
+    /// Bytes taken up by `width` pixels.
+    pub fn row_bytes(&self, width: u32) -> usize {
+        width as usize * (self.bits_per_pixel / 8) as usize
+    }
+}
+
+#[derive(Copy, Clone)]
+/// Dimensions and memory layout of a bitmap.
+pub struct BitmapLayout {
+    pub width: u32,
+    pub height: u32,
+    /// Bytes between the starts of consecutive rows.
+    pub stride: u32,
+    pub format: PixelFormat,
+    pub dpi_x: f64,
+    pub dpi_y: f64,
+}
+
+impl BitmapLayout {
+    /// A tightly packed layout at 96 DPI.
+    pub fn new(width: u32, height: u32, format: PixelFormat) -> BitmapLayout {
+        BitmapLayout {
+            width,
+            height,
+            stride: format.row_bytes(width) as u32,
+            format,
+            dpi_x: 96.0,
+            dpi_y: 96.0,
+        }
+    }
+
+    /// The smallest buffer this layout can describe.
+    pub fn min_buffer_len(&self) -> usize {
+        if self.height == 0 {
+            0
+        } else {
+            (self.height as usize - 1) * self.stride as usize + self.format.row_bytes(self.width)
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// An `IWICBitmapSource` reading from a Rust buffer.
+pub struct BitmapSource<B: AsRef<[u8]>> {
+    vtbl: VTable<IWICBitmapSourceVtbl>,
+    refcount: Refcount,
+    data: B,
+    layout: BitmapLayout,
+}
+
+impl<B: AsRef<[u8]>> BitmapSource<B> {
+    /// Wraps `data`, which must be large enough for `layout`. Fails with `E_INVALIDARG` if it is
+    /// too small, if the stride is shorter than a row, or if the format is not byte-aligned.
+    pub fn new(data: B, layout: BitmapLayout) -> Result<ComPtr<IWICBitmapSource>, HRESULT> {
+        let bpp = layout.format.bits_per_pixel;
+        if bpp == 0 || bpp & 7 != 0 {
+            return Err(E_INVALIDARG);
+        }
+        if (layout.stride as usize) < layout.format.row_bytes(layout.width) {
+            return Err(E_INVALIDARG);
+        }
+        if data.as_ref().len() < layout.min_buffer_len() {
+            return Err(E_INVALIDARG);
+        }
+
+        let ptr = BitmapSource::create_raw(data, layout);
+        let ptr = ptr as *mut IWICBitmapSource;
+        Ok(unsafe { ComPtr::from_raw(ptr) })
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl<B: AsRef<[u8]>> IWICBitmapSource for BitmapSource<B> {
+    unsafe fn get_size(&self, width: *mut u32, height: *mut u32) -> HRESULT {
+        if width.is_null() || height.is_null() {
+            return E_POINTER;
+        }
+        *width = self.layout.width;
+        *height = self.layout.height;
+        S_OK
+    }
+
+    unsafe fn get_pixel_format(&self, format: *mut WICPixelFormatGUID) -> HRESULT {
+        if format.is_null() {
+            return E_POINTER;
+        }
+        *format = self.layout.format.guid;
+        S_OK
+    }
+
+    unsafe fn get_resolution(&self, dpi_x: *mut f64, dpi_y: *mut f64) -> HRESULT {
+        if dpi_x.is_null() || dpi_y.is_null() {
+            return E_POINTER;
+        }
+        *dpi_x = self.layout.dpi_x;
+        *dpi_y = self.layout.dpi_y;
+        S_OK
+    }
+
+    fn copy_palette(&self, _palette: *mut IWICPalette) -> HRESULT {
+        WINCODEC_ERR_PALETTEUNAVAILABLE
+    }
+
+    unsafe fn copy_pixels(
+        &self,
+        rect: *const WICRect,
+        stride: u32,
+        buffer_size: u32,
+        buffer: *mut u8,
+    ) -> HRESULT {
+        if buffer.is_null() {
+            return E_POINTER;
+        }
+
+        let layout = &self.layout;
+        let (x, y, width, height) = if rect.is_null() {
+            (0, 0, layout.width, layout.height)
+        } else {
+            let rect = &*rect;
+            if rect.X < 0 || rect.Y < 0 || rect.Width < 0 || rect.Height < 0 {
+                return E_INVALIDARG;
+            }
+            let (x, y) = (rect.X as u32, rect.Y as u32);
+            let (width, height) = (rect.Width as u32, rect.Height as u32);
+            if x > layout.width || layout.width - x < width {
+                return E_INVALIDARG;
+            }
+            if y > layout.height || layout.height - y < height {
+                return E_INVALIDARG;
+            }
+            (x, y, width, height)
+        };
+
+        if width == 0 || height == 0 {
+            return S_OK;
+        }
+
+        let row_bytes = layout.format.row_bytes(width);
+        if (stride as usize) < row_bytes {
+            return E_INVALIDARG;
+        }
+        if (buffer_size as usize) < (height as usize - 1) * stride as usize + row_bytes {
+            return WINCODEC_ERR_INSUFFICIENTBUFFER;
+        }
+
+        let data = self.data.as_ref();
+        let offset = layout.format.row_bytes(x);
+        for row in 0..height as usize {
+            let src = (y as usize + row) * layout.stride as usize + offset;
+            let src = &data[src..src + row_bytes];
+            let dst = buffer.add(row * stride as usize);
+            std::ptr::copy_nonoverlapping(src.as_ptr(), dst, row_bytes);
+        }
+        S_OK
+    }
+}
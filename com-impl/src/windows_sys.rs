@@ -0,0 +1,61 @@
+//! IUnknown for interfaces bound with `windows-sys`, which has their IIDs but none of their
+//! vtables.
+//!
+//! With `#[com_impl(bindings = windows_sys)]`, vtables are declared by hand following the
+//! `windows` crate's naming: `IFoo_Vtbl`, starting with a `base__` member holding the parent's
+//! vtable, whose entries take `this` as a `*mut c_void`. An interface deriving directly from
+//! IUnknown starts with this module's [`IUnknown_Vtbl`]. Each interface's IID is a `GUID`
+//! constant named `IID_IFoo` in scope, or is given in `#[interfaces(IFoo = EXPR)]`.
+//!
+//! ```no_run
+//! use std::ffi::c_void;
+//!
+//! use com_impl::windows_sys::{IUnknown_Vtbl, GUID, HRESULT};
+//! use com_impl::{Refcount, VTable};
+//!
+//! #[repr(C)]
+//! #[allow(non_camel_case_types, non_snake_case)]
+//! pub struct ICounter_Vtbl {
+//!     pub base__: IUnknown_Vtbl,
+//!     pub Increment: unsafe extern "system" fn(this: *mut c_void, count: *mut u32) -> HRESULT,
+//! }
+//!
+//! pub const IID_ICounter: GUID = GUID::from_u128(0x3f2a_91c4_0d6e_4b5a_8c1f_72e0_5a9d_b316);
+//!
+//! #[com_impl::com_impl(bindings = windows_sys)]
+//! #[repr(C)]
+//! #[derive(com_impl::ComImpl)]
+//! pub struct Counter {
+//!     vtbl: VTable<ICounter_Vtbl>,
+//!     refcount: Refcount,
+//!     count: std::sync::atomic::AtomicU32,
+//! }
+//!
+//! #[com_impl::com_impl(bindings = windows_sys)]
+//! unsafe impl ICounter for Counter {
+//!     unsafe fn increment(&self, count: *mut u32) -> HRESULT {
+//!         *count = self.count.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+//!         0
+//!     }
+//! }
+//! ```
+
+use std::ffi::c_void;
+
+pub use ::windows_sys::core::{GUID, HRESULT};
+
+#[allow(non_upper_case_globals)]
+pub const IID_IUnknown: GUID = GUID::from_u128(0x0000_0000_0000_0000_c000_0000_0000_0046);
+
+/// The vtable of IUnknown, as the `windows` crate declares it.
+#[repr(C)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct IUnknown_Vtbl {
+    pub QueryInterface: unsafe extern "system" fn(
+        this: *mut c_void,
+        iid: *const GUID,
+        interface: *mut *mut c_void,
+    ) -> HRESULT,
+    pub AddRef: unsafe extern "system" fn(this: *mut c_void) -> u32,
+    pub Release: unsafe extern "system" fn(this: *mut c_void) -> u32,
+}
@@ -0,0 +1,951 @@
+//! WinRT `IAsyncAction` and `IAsyncOperation<T>` objects completed by a Rust `Future`.
+//!
+//! ```no_run
+//! use com_impl::winrt_async::AsyncOperation;
+//!
+//! let (operation, driver) = AsyncOperation::new(async { Ok(42i32) });
+//!
+//! // Run `driver` on any executor; it resolves once the future is done or the operation
+//! // was cancelled. Without one at hand, `AsyncOperation::spawn` runs it on a new thread.
+//! std::thread::spawn(move || futures_executor_of_choice(driver));
+//!
+//! // Return operation.as_raw() from the WinRT method.
+//! # fn futures_executor_of_choice(_: impl std::future::Future) {}
+//! ```
+//!
+//! The future's `Err` completes the operation with `AsyncStatus::Error` and that error code.
+//! `Cancel` stops polling the future and drops it.
+//!
+//! The objects are agile: the completion handler is called on whichever thread finishes the
+//! future, and every method may be called from any thread, so the future and its output
+//! have to be `Send`.
+//!
+//! `IAsyncOperation<T>` is a parameterized interface, so its IID depends on `T`. It is
+//! derived from the [`RuntimeType::SIGNATURE`] of `T` the same way the WinRT tooling does.
+
+#![allow(non_upper_case_globals, non_snake_case)]
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, GUID, IID};
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{
+    E_FAIL, E_ILLEGAL_DELEGATE_ASSIGNMENT, E_ILLEGAL_METHOD_CALL, E_ILLEGAL_STATE_CHANGE,
+    E_NOINTERFACE, E_OUTOFMEMORY, E_POINTER, HRESULT, S_OK,
+};
+use winapi::um::combaseapi::CoTaskMemAlloc;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::winrt::hstring::HSTRING;
+use winapi::winrt::inspectable::{IInspectable, IInspectableVtbl, TrustLevel};
+use winapi::winrt::winstring::WindowsCreateString;
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+pub const IID_IAsyncInfo: IID = GUID {
+    Data1: 0x0000_0036,
+    Data2: 0x0000,
+    Data3: 0x0000,
+    Data4: [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+};
+pub const IID_IAsyncAction: IID = GUID {
+    Data1: 0x5a64_8006,
+    Data2: 0x843a,
+    Data3: 0x4da9,
+    Data4: [0x86, 0x5b, 0x9d, 0x26, 0xe5, 0xdf, 0xad, 0x7b],
+};
+pub const IID_AsyncActionCompletedHandler: IID = GUID {
+    Data1: 0xa4ed_5c81,
+    Data2: 0x76c9,
+    Data3: 0x40bd,
+    Data4: [0x8b, 0xe6, 0xb1, 0xd9, 0x0f, 0xb2, 0x0a, 0xe7],
+};
+const IID_IAgileObject: IID = GUID {
+    Data1: 0x94ea_2b94,
+    Data2: 0xe9cc,
+    Data3: 0x49e0,
+    Data4: [0xc0, 0xff, 0xee, 0x64, 0xca, 0x8f, 0x5b, 0x90],
+};
+
+/// The `pinterface` GUIDs of `IAsyncOperation<T>` and `AsyncOperationCompletedHandler<T>`.
+const PIID_IAsyncOperation: &str = "9fc2b0bb-e446-44e2-aa61-9cab8f636af2";
+const PIID_AsyncOperationCompletedHandler: &str = "fcdcf02c-e5d8-4478-915a-4d90b74b83a5";
+
+#[repr(i32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AsyncStatus {
+    Started = 0,
+    Completed = 1,
+    Canceled = 2,
+    Error = 3,
+}
+
+/// A type that can be the result of an `IAsyncOperation<T>`.
+pub trait RuntimeType: Send + 'static {
+    /// The WinRT type signature, e.g. `i4` for `Int32` or `string` for `String`.
+    const SIGNATURE: &'static str;
+
+    /// How the value is passed across the ABI.
+    type Abi: Copy + 'static;
+
+    /// A value for the caller of `GetResults`, which then owns it.
+    fn to_abi(&self) -> Result<Self::Abi, HRESULT>;
+}
+
+macro_rules! primitive {
+    ($($ty:ty => $sig:expr),*) => {
+        $(impl RuntimeType for $ty {
+            const SIGNATURE: &'static str = $sig;
+            type Abi = $ty;
+
+            fn to_abi(&self) -> Result<$ty, HRESULT> {
+                Ok(*self)
+            }
+        })*
+    };
+}
+
+primitive! {
+    i32 => "i4",
+    u32 => "u4",
+    i64 => "i8",
+    u64 => "u8",
+    f32 => "f4",
+    f64 => "f8"
+}
+
+impl RuntimeType for bool {
+    const SIGNATURE: &'static str = "b1";
+    type Abi = u8;
+
+    fn to_abi(&self) -> Result<u8, HRESULT> {
+        Ok(*self as u8)
+    }
+}
+
+impl RuntimeType for String {
+    const SIGNATURE: &'static str = "string";
+    type Abi = HSTRING;
+
+    fn to_abi(&self) -> Result<HSTRING, HRESULT> {
+        let wide: Vec<u16> = self.encode_utf16().collect();
+        let mut hstring = ptr::null_mut();
+        let hr = unsafe { WindowsCreateString(wide.as_ptr(), wide.len() as u32, &mut hstring) };
+        if hr < 0 {
+            return Err(hr);
+        }
+        Ok(hstring)
+    }
+}
+
+/// The IID of a parameterized interface instance, per the WinRT type system: a name-based
+/// (version 5) UUID of `signature` in the WinRT namespace.
+///
+/// ```
+/// use com_impl::winrt_async::parameterized_iid;
+///
+/// // IAsyncOperation<Boolean>, {cdb5efb3-5788-509d-9be1-71ccb8a3362a}
+/// let iid = parameterized_iid("pinterface({9fc2b0bb-e446-44e2-aa61-9cab8f636af2};b1)");
+/// assert_eq!((iid.Data1, iid.Data2, iid.Data3), (0xcdb5_efb3, 0x5788, 0x509d));
+/// assert_eq!(iid.Data4, [0x9b, 0xe1, 0x71, 0xcc, 0xb8, 0xa3, 0x36, 0x2a]);
+///
+/// // IAsyncOperation<String>, {3e1fe603-f897-5263-b328-0806426b8a79}
+/// let iid = parameterized_iid("pinterface({9fc2b0bb-e446-44e2-aa61-9cab8f636af2};string)");
+/// assert_eq!((iid.Data1, iid.Data2, iid.Data3), (0x3e1f_e603, 0xf897, 0x5263));
+/// assert_eq!(iid.Data4, [0xb3, 0x28, 0x08, 0x06, 0x42, 0x6b, 0x8a, 0x79]);
+/// ```
+pub fn parameterized_iid(signature: &str) -> IID {
+    const NAMESPACE: [u8; 16] = [
+        0x11, 0xf4, 0x7a, 0xd5, 0x7b, 0x73, 0x42, 0xc0, 0xab, 0xae, 0x87, 0x8b, 0x1e, 0x16, 0xad,
+        0xee,
+    ];
+    let mut data = NAMESPACE.to_vec();
+    data.extend_from_slice(signature.as_bytes());
+    let hash = sha1(&data);
+
+    let mut b = [0; 16];
+    b.copy_from_slice(&hash[..16]);
+    b[6] = (b[6] & 0x0f) | 0x50;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    GUID {
+        Data1: u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        Data2: u16::from_be_bytes([b[4], b[5]]),
+        Data3: u16::from_be_bytes([b[6], b[7]]),
+        Data4: [b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]],
+    }
+}
+
+#[doc(hidden)]
+/// The SHA-1 of `data`, which `parameterized_iid` is built on. Public for its tests against
+/// the FIPS 180 vectors:
+///
+/// ```
+/// use com_impl::winrt_async::sha1;
+///
+/// let hex = |hash: [u8; 20]| hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+/// assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+/// assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+/// // Padded to two blocks
+/// let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+/// assert_eq!(hex(sha1(two_blocks)), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+/// ```
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+
+    let mut out = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// The layout shared by `IAsyncAction` and `IAsyncOperation<T>`, which only differ in the
+/// signature of `GetResults`.
+#[repr(C)]
+pub struct IAsyncVtbl<G> {
+    pub parent: IInspectableVtbl,
+    pub put_Completed:
+        unsafe extern "system" fn(this: *mut IInspectable, handler: *mut IUnknown) -> HRESULT,
+    pub get_Completed:
+        unsafe extern "system" fn(this: *mut IInspectable, handler: *mut *mut IUnknown) -> HRESULT,
+    pub GetResults: G,
+}
+
+#[repr(C)]
+pub struct IAsyncInfoVtbl {
+    pub parent: IInspectableVtbl,
+    pub get_Id: unsafe extern "system" fn(this: *mut IInspectable, id: *mut u32) -> HRESULT,
+    pub get_Status:
+        unsafe extern "system" fn(this: *mut IInspectable, status: *mut AsyncStatus) -> HRESULT,
+    pub get_ErrorCode:
+        unsafe extern "system" fn(this: *mut IInspectable, error: *mut HRESULT) -> HRESULT,
+    pub Cancel: unsafe extern "system" fn(this: *mut IInspectable) -> HRESULT,
+    pub Close: unsafe extern "system" fn(this: *mut IInspectable) -> HRESULT,
+}
+
+#[repr(C)]
+struct CompletedHandlerVtbl {
+    parent: IUnknownVtbl,
+    Invoke: unsafe extern "system" fn(
+        this: *mut IUnknown,
+        info: *mut IInspectable,
+        status: AsyncStatus,
+    ) -> HRESULT,
+}
+
+type ActionResults = unsafe extern "system" fn(this: *mut IInspectable) -> HRESULT;
+type OperationResults<A> = unsafe extern "system" fn(this: *mut IInspectable, A) -> HRESULT;
+
+/// What distinguishes an action from an operation of a particular `T`.
+trait Kind: Send + Sized + 'static {
+    type Output: Send + 'static;
+    type GetResults: Copy + 'static;
+
+    const VTBL: IAsyncVtbl<Self::GetResults>;
+    const STATIC_VTABLE: VTable<IAsyncVtbl<Self::GetResults>>;
+
+    /// The IIDs of the async interface and of its completed handler.
+    fn iids() -> (IID, IID);
+}
+
+enum Action {}
+
+impl Kind for Action {
+    type Output = ();
+    type GetResults = ActionResults;
+
+    const VTBL: IAsyncVtbl<ActionResults> = AsyncObject::<Action>::vtbl(Self::get_results);
+    const STATIC_VTABLE: VTable<IAsyncVtbl<ActionResults>> = VTable::new(&Self::VTBL);
+
+    fn iids() -> (IID, IID) {
+        (IID_IAsyncAction, IID_AsyncActionCompletedHandler)
+    }
+}
+
+impl Action {
+    unsafe extern "system" fn get_results(this: *mut IInspectable) -> HRESULT {
+        let that = &*(this as *const AsyncObject<Action>);
+        match that.state.lock() {
+            Ok(state) => state.check_results().map(|_| S_OK).unwrap_or_else(|hr| hr),
+            Err(_) => E_FAIL,
+        }
+    }
+}
+
+struct Operation<T>(PhantomData<T>);
+
+impl<T: RuntimeType> Kind for Operation<T> {
+    type Output = T;
+    type GetResults = OperationResults<*mut T::Abi>;
+
+    const VTBL: IAsyncVtbl<Self::GetResults> = AsyncObject::<Self>::vtbl(Self::get_results);
+    const STATIC_VTABLE: VTable<IAsyncVtbl<Self::GetResults>> = VTable::new(&Self::VTBL);
+
+    fn iids() -> (IID, IID) {
+        let operation = format!("pinterface({{{}}};{})", PIID_IAsyncOperation, T::SIGNATURE);
+        let handler = format!(
+            "pinterface({{{}}};{})",
+            PIID_AsyncOperationCompletedHandler,
+            T::SIGNATURE
+        );
+        (parameterized_iid(&operation), parameterized_iid(&handler))
+    }
+}
+
+impl<T: RuntimeType> Operation<T> {
+    unsafe extern "system" fn get_results(this: *mut IInspectable, result: *mut T::Abi) -> HRESULT {
+        if result.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const AsyncObject<Self>);
+        let state = match that.state.lock() {
+            Ok(state) => state,
+            Err(_) => return E_FAIL,
+        };
+        match state
+            .check_results()
+            .and_then(|value| value.unwrap().to_abi())
+        {
+            Ok(value) => {
+                *result = value;
+                S_OK
+            }
+            Err(hr) => hr,
+        }
+    }
+}
+
+/// A handler passed in through `put_Completed`.
+struct Handler(ComPtr<IUnknown>);
+
+// WinRT completion handlers are agile
+unsafe impl Send for Handler {}
+
+struct State<T> {
+    status: AsyncStatus,
+    result: Option<T>,
+    error: HRESULT,
+    handler: Option<Handler>,
+    handler_assigned: bool,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> State<T> {
+    fn check_results(&self) -> Result<Option<&T>, HRESULT> {
+        if self.closed {
+            return Err(E_ILLEGAL_METHOD_CALL);
+        }
+        match self.status {
+            AsyncStatus::Completed => Ok(self.result.as_ref()),
+            AsyncStatus::Error => Err(self.error),
+            AsyncStatus::Canceled => Err(E_ILLEGAL_METHOD_CALL),
+            AsyncStatus::Started => Err(E_ILLEGAL_METHOD_CALL),
+        }
+    }
+}
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Not derived, because `IAsyncInfo` lives on a second vtable and the primary interface's IID
+/// depends on `K`.
+#[repr(C)]
+struct AsyncObject<K: Kind> {
+    vtbl: VTable<IAsyncVtbl<K::GetResults>>,
+    info_vtbl: VTable<IAsyncInfoVtbl>,
+    refcount: Refcount,
+    iid: IID,
+    id: u32,
+    state: Mutex<State<K::Output>>,
+}
+
+impl<K: Kind> AsyncObject<K> {
+    const INFO_VTABLE: VTable<IAsyncInfoVtbl> = VTable::new(&Self::INFO_VTBL);
+    const INFO_VTBL: IAsyncInfoVtbl = IAsyncInfoVtbl {
+        parent: IInspectableVtbl {
+            parent: IUnknownVtbl {
+                QueryInterface: Self::info_query_interface,
+                AddRef: Self::info_add_ref,
+                Release: Self::info_release,
+            },
+            GetIids: Self::info_get_iids,
+            GetRuntimeClassName: Self::info_get_runtime_class_name,
+            GetTrustLevel: Self::info_get_trust_level,
+        },
+        get_Id: Self::get_id,
+        get_Status: Self::get_status,
+        get_ErrorCode: Self::get_error_code,
+        Cancel: Self::cancel,
+        Close: Self::close,
+    };
+
+    const fn vtbl(get_results: K::GetResults) -> IAsyncVtbl<K::GetResults> {
+        IAsyncVtbl {
+            parent: IInspectableVtbl {
+                parent: IUnknownVtbl {
+                    QueryInterface: Self::query_interface,
+                    AddRef: Self::add_ref,
+                    Release: Self::release,
+                },
+                GetIids: Self::get_iids,
+                GetRuntimeClassName: Self::get_runtime_class_name,
+                GetTrustLevel: Self::get_trust_level,
+            },
+            put_Completed: Self::put_completed,
+            get_Completed: Self::get_completed,
+            GetResults: get_results,
+        }
+    }
+
+    fn create<F>(future: F) -> (ComPtr<IInspectable>, Driver)
+    where
+        F: Future<Output = Result<K::Output, HRESULT>> + Send + 'static,
+    {
+        let (iid, _) = K::iids();
+        let ptr = Box::into_raw(Box::new(AsyncObject::<K> {
+            vtbl: K::STATIC_VTABLE,
+            info_vtbl: Self::INFO_VTABLE,
+            refcount: Default::default(),
+            iid,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            state: Mutex::new(State {
+                status: AsyncStatus::Started,
+                result: None,
+                error: S_OK,
+                handler: None,
+                handler_assigned: false,
+                closed: false,
+                waker: None,
+            }),
+        }));
+        let object = unsafe { ComPtr::from_raw(ptr as *mut IInspectable) };
+
+        let driver = Driver {
+            poll: Box::pin(Task::<K, F> {
+                kind: PhantomData,
+                object: object.clone(),
+                future: Some(Box::pin(future)),
+            }),
+        };
+        (object, driver)
+    }
+
+    unsafe fn from_info(this: *mut IInspectable) -> *mut IInspectable {
+        (this as *mut u8).sub(mem::size_of::<VTable<IAsyncVtbl<K::GetResults>>>()) as *mut _
+    }
+
+    /// Records the outcome unless the operation was cancelled first, and calls the handler.
+    fn complete(&self, outcome: Result<K::Output, HRESULT>) {
+        let handler = {
+            let mut state = match self.state.lock() {
+                Ok(state) => state,
+                Err(_) => return,
+            };
+            if state.status != AsyncStatus::Started {
+                return;
+            }
+            match outcome {
+                Ok(result) => {
+                    state.status = AsyncStatus::Completed;
+                    state.result = Some(result);
+                }
+                Err(hr) => {
+                    state.status = AsyncStatus::Error;
+                    state.error = hr;
+                }
+            }
+            state.handler.take()
+        };
+        if let Some(handler) = handler {
+            self.invoke(&handler);
+        }
+    }
+
+    fn invoke(&self, handler: &Handler) {
+        let status = match self.state.lock() {
+            Ok(state) => state.status,
+            Err(_) => return,
+        };
+        unsafe {
+            let vtbl = (*handler.0.as_raw()).lpVtbl as *const CompletedHandlerVtbl;
+            let this = self as *const Self as *mut IInspectable;
+            // Nothing can be done about a failing handler
+            ((*vtbl).Invoke)(handler.0.as_raw(), this, status);
+        }
+    }
+
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        if ppv.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let riid = &*riid;
+
+        if IsEqualIID(riid, &IUnknown::uuidof())
+            || IsEqualIID(riid, &IInspectable::uuidof())
+            || IsEqualIID(riid, &IID_IAgileObject)
+            || IsEqualIID(riid, &that.iid)
+        {
+            that.refcount.add_ref();
+            *ppv = this as *mut c_void;
+            S_OK
+        } else if IsEqualIID(riid, &IID_IAsyncInfo) {
+            that.refcount.add_ref();
+            *ppv = &that.info_vtbl as *const _ as *mut c_void;
+            S_OK
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn add_ref(this: *mut IUnknown) -> u32 {
+        let this = &*(this as *const Self);
+        this.refcount.add_ref()
+    }
+
+    unsafe extern "system" fn release(this: *mut IUnknown) -> u32 {
+        let ptr = this as *mut Self;
+        let count = (*ptr).refcount.release();
+        if count == 0 {
+            drop(Box::from_raw(ptr));
+        }
+        count
+    }
+
+    unsafe extern "system" fn get_iids(
+        this: *mut IInspectable,
+        count: *mut ULONG,
+        iids: *mut *mut IID,
+    ) -> HRESULT {
+        if count.is_null() || iids.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let array = CoTaskMemAlloc(2 * mem::size_of::<IID>()) as *mut IID;
+        if array.is_null() {
+            return E_OUTOFMEMORY;
+        }
+        *array = that.iid;
+        *array.add(1) = IID_IAsyncInfo;
+        *count = 2;
+        *iids = array;
+        S_OK
+    }
+
+    unsafe extern "system" fn get_runtime_class_name(
+        _this: *mut IInspectable,
+        name: *mut HSTRING,
+    ) -> HRESULT {
+        if name.is_null() {
+            return E_POINTER;
+        }
+        // Not a projected runtime class
+        *name = ptr::null_mut();
+        S_OK
+    }
+
+    unsafe extern "system" fn get_trust_level(
+        _this: *mut IInspectable,
+        level: *mut TrustLevel,
+    ) -> HRESULT {
+        if level.is_null() {
+            return E_POINTER;
+        }
+        *level = winapi::winrt::inspectable::BaseTrust;
+        S_OK
+    }
+
+    unsafe extern "system" fn put_completed(
+        this: *mut IInspectable,
+        handler: *mut IUnknown,
+    ) -> HRESULT {
+        if handler.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        (*handler).AddRef();
+        let handler = Handler(ComPtr::from_raw(handler));
+
+        let finished = {
+            let mut state = match that.state.lock() {
+                Ok(state) => state,
+                Err(_) => return E_FAIL,
+            };
+            if state.closed {
+                return E_ILLEGAL_METHOD_CALL;
+            }
+            if state.handler_assigned {
+                return E_ILLEGAL_DELEGATE_ASSIGNMENT;
+            }
+            state.handler_assigned = true;
+            if state.status == AsyncStatus::Started {
+                state.handler = Some(handler);
+                None
+            } else {
+                Some(handler)
+            }
+        };
+
+        // Already finished, so the handler is called straight away
+        if let Some(handler) = finished {
+            that.invoke(&handler);
+        }
+        S_OK
+    }
+
+    unsafe extern "system" fn get_completed(
+        this: *mut IInspectable,
+        handler: *mut *mut IUnknown,
+    ) -> HRESULT {
+        if handler.is_null() {
+            return E_POINTER;
+        }
+        let that = &*(this as *const Self);
+        let state = match that.state.lock() {
+            Ok(state) => state,
+            Err(_) => return E_FAIL,
+        };
+        *handler = match &state.handler {
+            Some(h) => {
+                h.0.AddRef();
+                h.0.as_raw()
+            }
+            None => ptr::null_mut(),
+        };
+        S_OK
+    }
+
+    unsafe extern "system" fn info_query_interface(
+        this: *mut IUnknown,
+        riid: *const IID,
+        ppv: *mut *mut c_void,
+    ) -> HRESULT {
+        Self::query_interface(Self::from_info(this as _) as _, riid, ppv)
+    }
+
+    unsafe extern "system" fn info_add_ref(this: *mut IUnknown) -> u32 {
+        Self::add_ref(Self::from_info(this as _) as _)
+    }
+
+    unsafe extern "system" fn info_release(this: *mut IUnknown) -> u32 {
+        Self::release(Self::from_info(this as _) as _)
+    }
+
+    unsafe extern "system" fn info_get_iids(
+        this: *mut IInspectable,
+        count: *mut ULONG,
+        iids: *mut *mut IID,
+    ) -> HRESULT {
+        Self::get_iids(Self::from_info(this), count, iids)
+    }
+
+    unsafe extern "system" fn info_get_runtime_class_name(
+        this: *mut IInspectable,
+        name: *mut HSTRING,
+    ) -> HRESULT {
+        Self::get_runtime_class_name(Self::from_info(this), name)
+    }
+
+    unsafe extern "system" fn info_get_trust_level(
+        this: *mut IInspectable,
+        level: *mut TrustLevel,
+    ) -> HRESULT {
+        Self::get_trust_level(Self::from_info(this), level)
+    }
+
+    unsafe fn info_state<'a>(this: *mut IInspectable) -> &'a Self {
+        &*(Self::from_info(this) as *const Self)
+    }
+
+    unsafe extern "system" fn get_id(this: *mut IInspectable, id: *mut u32) -> HRESULT {
+        if id.is_null() {
+            return E_POINTER;
+        }
+        *id = Self::info_state(this).id;
+        S_OK
+    }
+
+    unsafe extern "system" fn get_status(
+        this: *mut IInspectable,
+        status: *mut AsyncStatus,
+    ) -> HRESULT {
+        if status.is_null() {
+            return E_POINTER;
+        }
+        match Self::info_state(this).state.lock() {
+            Ok(state) if state.closed => E_ILLEGAL_METHOD_CALL,
+            Ok(state) => {
+                *status = state.status;
+                S_OK
+            }
+            Err(_) => E_FAIL,
+        }
+    }
+
+    unsafe extern "system" fn get_error_code(
+        this: *mut IInspectable,
+        error: *mut HRESULT,
+    ) -> HRESULT {
+        if error.is_null() {
+            return E_POINTER;
+        }
+        match Self::info_state(this).state.lock() {
+            Ok(state) if state.closed => E_ILLEGAL_METHOD_CALL,
+            Ok(state) => {
+                *error = state.error;
+                S_OK
+            }
+            Err(_) => E_FAIL,
+        }
+    }
+
+    unsafe extern "system" fn cancel(this: *mut IInspectable) -> HRESULT {
+        let that = Self::info_state(this);
+        let (handler, waker) = {
+            let mut state = match that.state.lock() {
+                Ok(state) => state,
+                Err(_) => return E_FAIL,
+            };
+            if state.closed {
+                return E_ILLEGAL_METHOD_CALL;
+            }
+            // Cancelling a finished operation does nothing
+            if state.status != AsyncStatus::Started {
+                return S_OK;
+            }
+            state.status = AsyncStatus::Canceled;
+            (state.handler.take(), state.waker.take())
+        };
+
+        // Lets the driver notice and drop the future
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        if let Some(handler) = handler {
+            that.invoke(&handler);
+        }
+        S_OK
+    }
+
+    unsafe extern "system" fn close(this: *mut IInspectable) -> HRESULT {
+        let that = Self::info_state(this);
+        let mut state = match that.state.lock() {
+            Ok(state) => state,
+            Err(_) => return E_FAIL,
+        };
+        if state.status == AsyncStatus::Started {
+            return E_ILLEGAL_STATE_CHANGE;
+        }
+        state.closed = true;
+        state.result = None;
+        state.handler = None;
+        S_OK
+    }
+}
+
+struct Task<K: Kind, F> {
+    kind: PhantomData<K>,
+    object: ComPtr<IInspectable>,
+    future: Option<Pin<Box<F>>>,
+}
+
+// The object is agile and only touched through its Mutex
+unsafe impl<K: Kind, F: Send> Send for Task<K, F> {}
+
+impl<K: Kind, F> Unpin for Task<K, F> {}
+
+impl<K: Kind, F> Future for Task<K, F>
+where
+    F: Future<Output = Result<K::Output, HRESULT>> + Send + 'static,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let object = unsafe { &*(self.object.as_raw() as *const AsyncObject<K>) };
+        {
+            let mut state = match object.state.lock() {
+                Ok(state) => state,
+                Err(_) => return Poll::Ready(()),
+            };
+            if state.status != AsyncStatus::Started {
+                // Cancelled
+                drop(state);
+                self.future = None;
+                return Poll::Ready(());
+            }
+            state.waker = Some(cx.waker().clone());
+        }
+
+        let future = match &mut self.future {
+            Some(future) => future,
+            None => return Poll::Ready(()),
+        };
+        let outcome = match future.as_mut().poll(cx) {
+            Poll::Ready(outcome) => outcome,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.future = None;
+        object.complete(outcome);
+        Poll::Ready(())
+    }
+}
+
+/// Polls the wrapped future to completion and completes the async object with its output.
+///
+/// Dropping the driver before it finishes leaves the operation `Started` forever.
+pub struct Driver {
+    poll: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl Future for Driver {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.poll.as_mut().poll(cx)
+    }
+}
+
+impl Driver {
+    /// Runs the driver to completion on a new thread.
+    pub fn spawn(self) {
+        thread::spawn(move || block_on(self));
+    }
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on(future: impl Future<Output = ()>) {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    while future.as_mut().poll(&mut cx).is_pending() {
+        thread::park();
+    }
+}
+
+/// An `IAsyncAction` completed by a future.
+pub struct AsyncAction;
+
+impl AsyncAction {
+    /// The action, and the driver that has to be polled for the future to make progress.
+    pub fn new<F>(future: F) -> (ComPtr<IInspectable>, Driver)
+    where
+        F: Future<Output = Result<(), HRESULT>> + Send + 'static,
+    {
+        AsyncObject::<Action>::create(future)
+    }
+
+    /// Like [`new`](AsyncAction::new), with the driver running on a new thread.
+    pub fn spawn<F>(future: F) -> ComPtr<IInspectable>
+    where
+        F: Future<Output = Result<(), HRESULT>> + Send + 'static,
+    {
+        let (action, driver) = AsyncAction::new(future);
+        driver.spawn();
+        action
+    }
+}
+
+/// An `IAsyncOperation<T>` completed by a future.
+pub struct AsyncOperation;
+
+impl AsyncOperation {
+    /// The operation, and the driver that has to be polled for the future to make progress.
+    pub fn new<T, F>(future: F) -> (ComPtr<IInspectable>, Driver)
+    where
+        T: RuntimeType,
+        F: Future<Output = Result<T, HRESULT>> + Send + 'static,
+    {
+        AsyncObject::<Operation<T>>::create(future)
+    }
+
+    /// Like [`new`](AsyncOperation::new), with the driver running on a new thread.
+    pub fn spawn<T, F>(future: F) -> ComPtr<IInspectable>
+    where
+        T: RuntimeType,
+        F: Future<Output = Result<T, HRESULT>> + Send + 'static,
+    {
+        let (operation, driver) = AsyncOperation::new(future);
+        driver.spawn();
+        operation
+    }
+
+    /// The IID of `IAsyncOperation<T>`.
+    pub fn iid<T: RuntimeType>() -> IID {
+        Operation::<T>::iids().0
+    }
+
+    /// The IID of `AsyncOperationCompletedHandler<T>`.
+    pub fn completed_handler_iid<T: RuntimeType>() -> IID {
+        Operation::<T>::iids().1
+    }
+}
@@ -0,0 +1,205 @@
+//! `IWbemObjectSink` for WMI asynchronous queries and event subscriptions, delivering results
+//! to a callback or a channel.
+//!
+//! ```no_run
+//! use com_impl::wmi::{ObjectSink, WbemEvent};
+//!
+//! let (sink, events) = ObjectSink::channel();
+//! let stub = unsafe { com_impl::wmi::unsecured_stub(&sink) }.unwrap();
+//!
+//! // services.ExecNotificationQueryAsync(..., stub.as_raw());
+//! for event in events {
+//!     match event {
+//!         WbemEvent::Object(object) => { /* inspect object.as_raw() */ }
+//!         WbemEvent::Complete { .. } => break,
+//!         WbemEvent::Progress { .. } => {}
+//!     }
+//! }
+//! // services.CancelAsyncCall(stub.as_raw());
+//! ```
+//!
+//! WMI calls the sink from its own RPC threads, not the thread that started the query, so the
+//! handler must be `Send + Sync` and should return quickly. Nothing is delivered after
+//! [`WbemEvent::Complete`].
+//!
+//! By default WMI has to authenticate back to the client process to deliver results, which
+//! fails for callers whose identity winmgmt cannot open, or when the process has not called
+//! `CoInitializeSecurity`. Passing the stub from [`unsecured_stub`] to the `...Async` call
+//! instead of the sink itself avoids the access check.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use winapi::ctypes::c_long;
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+use winapi::shared::wtypes::BSTR;
+use winapi::shared::wtypesbase::CLSCTX_LOCAL_SERVER;
+use winapi::um::combaseapi::CoCreateInstance;
+use winapi::um::oleauto::SysStringLen;
+use winapi::um::unknwnbase::IUnknown;
+use winapi::um::wbemcli::{
+    CLSID_UnsecuredApartment, IUnsecuredApartment, IWbemClassObject, IWbemObjectSink,
+    IWbemObjectSinkVtbl, WBEM_STATUS_COMPLETE, WBEM_STATUS_PROGRESS,
+};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+use crate::{Refcount, VTable};
+
+/// An `IWbemClassObject` delivered to a sink.
+///
+/// WMI's class objects are free-threaded, so unlike a plain `ComPtr` this can be sent to
+/// another thread.
+pub struct WbemObject(ComPtr<IWbemClassObject>);
+
+unsafe impl Send for WbemObject {}
+
+impl WbemObject {
+    unsafe fn from_borrowed(object: *mut IWbemClassObject) -> Option<WbemObject> {
+        if object.is_null() {
+            return None;
+        }
+        (*object).AddRef();
+        Some(WbemObject(ComPtr::from_raw(object)))
+    }
+
+    pub fn as_raw(&self) -> *mut IWbemClassObject {
+        self.0.as_raw()
+    }
+
+    pub fn into_inner(self) -> ComPtr<IWbemClassObject> {
+        self.0
+    }
+}
+
+pub enum WbemEvent {
+    /// A result of the query, or an event from a notification query.
+    Object(WbemObject),
+    /// Only sent for calls made with `WBEM_FLAG_SEND_STATUS`.
+    Progress {
+        status: HRESULT,
+        message: Option<String>,
+    },
+    /// The call finished, successfully or otherwise. `error` is the `__ExtendedStatus` object,
+    /// if WMI provided one.
+    Complete {
+        result: HRESULT,
+        message: Option<String>,
+        error: Option<WbemObject>,
+    },
+}
+
+unsafe fn from_bstr(s: BSTR) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    let s = std::slice::from_raw_parts(s, SysStringLen(s) as usize);
+    Some(String::from_utf16_lossy(s))
+}
+
+type EventFn = Box<dyn Fn(WbemEvent) + Send + Sync>;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+/// COM object implementing `IWbemObjectSink` by forwarding every call as a [`WbemEvent`].
+pub struct ObjectSink {
+    vtbl: VTable<IWbemObjectSinkVtbl>,
+    refcount: Refcount,
+    handler: EventFn,
+}
+
+impl ObjectSink {
+    pub fn new(f: impl Fn(WbemEvent) + Send + Sync + 'static) -> ComPtr<IWbemObjectSink> {
+        let ptr = ObjectSink::create_raw(Box::new(f));
+        let ptr = ptr as *mut IWbemObjectSink;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    /// A sink sending its events to the returned receiver. The receiver sees the channel
+    /// close once the sink has been released by everyone, including WMI.
+    pub fn channel() -> (ComPtr<IWbemObjectSink>, Receiver<WbemEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let tx: Mutex<Sender<WbemEvent>> = Mutex::new(tx);
+        let sink = ObjectSink::new(move |event| {
+            if let Ok(tx) = tx.lock() {
+                // The receiver going away just means nobody is listening any more
+                let _ = tx.send(event);
+            }
+        });
+        (sink, rx)
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IWbemObjectSink for ObjectSink {
+    #[panic(result = "E_FAIL")]
+    unsafe fn indicate(&self, count: c_long, objects: *mut *mut IWbemClassObject) -> HRESULT {
+        if objects.is_null() || count <= 0 {
+            return S_OK;
+        }
+        let objects = std::slice::from_raw_parts(objects, count as usize);
+        for &object in objects {
+            if let Some(object) = WbemObject::from_borrowed(object) {
+                (self.handler)(WbemEvent::Object(object));
+            }
+        }
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn set_status(
+        &self,
+        flags: c_long,
+        result: HRESULT,
+        message: BSTR,
+        object: *mut IWbemClassObject,
+    ) -> HRESULT {
+        let message = from_bstr(message);
+        let event = match flags as u32 {
+            WBEM_STATUS_COMPLETE => WbemEvent::Complete {
+                result,
+                message,
+                error: WbemObject::from_borrowed(object),
+            },
+            WBEM_STATUS_PROGRESS => WbemEvent::Progress {
+                status: result,
+                message,
+            },
+            _ => return S_OK,
+        };
+        (self.handler)(event);
+        S_OK
+    }
+}
+
+/// Wraps `sink` in a stub from the unsecured apartment (unsecapp.exe), which accepts calls
+/// from winmgmt without checking its identity.
+///
+/// Pass the stub to the `...Async` call, and to `CancelAsyncCall`.
+///
+/// # Safety
+///
+/// COM must be initialized on the calling thread.
+pub unsafe fn unsecured_stub(
+    sink: &ComPtr<IWbemObjectSink>,
+) -> Result<ComPtr<IWbemObjectSink>, HRESULT> {
+    let mut apartment = std::ptr::null_mut();
+    let hr = CoCreateInstance(
+        &CLSID_UnsecuredApartment,
+        std::ptr::null_mut(),
+        CLSCTX_LOCAL_SERVER,
+        &IUnsecuredApartment::uuidof(),
+        &mut apartment,
+    );
+    if hr < 0 {
+        return Err(hr);
+    }
+    let apartment = ComPtr::from_raw(apartment as *mut IUnsecuredApartment);
+
+    let mut stub = std::ptr::null_mut();
+    let hr = apartment.CreateObjectStub(sink.as_raw() as *mut IUnknown, &mut stub);
+    if hr < 0 {
+        return Err(hr);
+    }
+    ComPtr::from_raw(stub).cast()
+}
@@ -0,0 +1,837 @@
+//! Parsers for the attributes the macros accept.
+//!
+//! Values are Rust syntax, e.g. `#[panic(result = E_FAIL)]`. The older spelling wrapping them
+//! in a string literal, `#[panic(result = "E_FAIL")]`, is still accepted.
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream, Result};
+use syn::punctuated::Punctuated;
+use syn::{Attribute, Expr, Ident, LitStr, Path, Type, Visibility};
+
+use crate::bindings::Bindings;
+
+/// Parses the tokens after an attribute's path, e.g. `(result = E_FAIL)`. Errors name the
+/// attribute and keep the span of the token at fault.
+pub fn parse<T: Parse>(attr: &Attribute) -> Result<T> {
+    syn::parse2(attr.tts.clone()).map_err(|e| {
+        let path = attr.path.clone().into_token_stream();
+        syn::Error::new(e.span(), format!("Invalid syntax for #[{}]: {}", path, e))
+    })
+}
+
+/// Parses `T` either directly or from the contents of a string literal.
+fn parse_or_str<T: Parse>(input: ParseStream) -> Result<T> {
+    if input.peek(LitStr) {
+        let lit: LitStr = input.parse()?;
+        lit.parse()
+    } else {
+        input.parse()
+    }
+}
+
+/// The arguments of `#[com_impl(...)]`.
+pub struct ComImplArgs {
+    args: Vec<ComImplArg>,
+}
+
+#[derive(Clone)]
+enum ComImplArg {
+    Word(Ident),
+    Value(Ident, Expr),
+    List(Ident, TokenStream),
+}
+
+impl ComImplArgs {
+    pub fn has_word(&self, word: &str) -> bool {
+        self.args.iter().any(|arg| match arg {
+            ComImplArg::Word(ident) => ident == word,
+            _ => false,
+        })
+    }
+
+    /// The names of all arguments, in the order given.
+    pub fn names(&self) -> impl Iterator<Item = &Ident> {
+        self.args.iter().map(|arg| match arg {
+            ComImplArg::Word(ident) | ComImplArg::Value(ident, _) | ComImplArg::List(ident, _) => {
+                ident
+            }
+        })
+    }
+
+    /// The name of the argument `name`, for errors about it.
+    pub fn ident(&self, name: &str) -> Option<&Ident> {
+        self.names().find(|ident| *ident == name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Expr> {
+        self.args.iter().find_map(|arg| match arg {
+            ComImplArg::Value(ident, expr) if ident == name => Some(expr),
+            _ => None,
+        })
+    }
+
+    /// The contents of `name(...)`.
+    pub fn list(&self, name: &str) -> Option<&TokenStream> {
+        self.args.iter().find_map(|arg| match arg {
+            ComImplArg::List(ident, tokens) if ident == name => Some(tokens),
+            _ => None,
+        })
+    }
+
+    /// The same arguments, with `name(list)` added.
+    pub fn with_list(&self, name: &str, list: TokenStream) -> ComImplArgs {
+        let mut args = self.args.clone();
+        args.push(ComImplArg::List(Ident::new(name, Span::call_site()), list));
+        ComImplArgs { args }
+    }
+
+    /// The same arguments, leaving out those named in `names`.
+    pub fn without(&self, names: &[&str]) -> ComImplArgs {
+        let args = self.args.iter().zip(self.names());
+        ComImplArgs {
+            args: args
+                .filter(|(_, name)| !names.iter().any(|n| name == n))
+                .map(|(arg, _)| arg.clone())
+                .collect(),
+        }
+    }
+}
+
+impl Parse for ComImplArgs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let args = Punctuated::<ComImplArg, Token![,]>::parse_terminated(input)?;
+        Ok(ComImplArgs {
+            args: args.into_iter().collect(),
+        })
+    }
+}
+
+impl Parse for ComImplArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // `crate = ...` names its argument with a keyword
+        let ident = input.call(Ident::parse_any)?;
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            // A format string, rather than Rust syntax wrapped in one
+            if ident == "vtbl_name" {
+                let format: LitStr = input.parse()?;
+                return Ok(ComImplArg::Value(ident, parse_quote!(#format)));
+            }
+            Ok(ComImplArg::Value(ident, parse_or_str(input)?))
+        } else if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            Ok(ComImplArg::List(ident, content.parse()?))
+        } else {
+            Ok(ComImplArg::Word(ident))
+        }
+    }
+}
+
+/// `#[panic(abort)]` or `#[panic(result = EXPR)]`.
+pub enum PanicAttr {
+    Abort,
+    Result(Expr),
+}
+
+impl Parse for PanicAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let ident: Ident = content.parse()?;
+        if ident == "abort" && content.is_empty() {
+            return Ok(PanicAttr::Abort);
+        }
+        if ident != "result" {
+            return Err(content.error("expected `abort` or `result = ...`"));
+        }
+        content.parse::<Token![=]>()?;
+        Ok(PanicAttr::Result(parse_or_str(&content)?))
+    }
+}
+
+/// `#[refcount_check(abort)]`, `#[refcount_check(debug)]` or `#[refcount_check(none)]`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RefcountCheck {
+    Abort,
+    Debug,
+    None,
+}
+
+impl Parse for RefcountCheck {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let ident: Ident = content.parse()?;
+        let check = if ident == "abort" {
+            RefcountCheck::Abort
+        } else if ident == "debug" {
+            RefcountCheck::Debug
+        } else if ident == "none" {
+            RefcountCheck::None
+        } else {
+            return Err(syn::Error::new(
+                ident.span(),
+                "expected `abort`, `debug` or `none`",
+            ));
+        };
+        if !content.is_empty() {
+            return Err(content.error("expected `abort`, `debug` or `none`"));
+        }
+        Ok(check)
+    }
+}
+
+/// `#[com_inline(never)]` on a struct, or `inline = "never"` in `#[com_impl(...)]`: the
+/// inlining of the generated stubs, `#[inline(never)]` unless given.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Inline {
+    Default,
+    Never,
+    Always,
+}
+
+const EXPECTED_INLINE: &str = "expected `default`, `never` or `always`";
+
+impl Inline {
+    /// The `inline = ...` argument of `#[com_impl]`, if it is given.
+    pub fn from_arg(value: Option<&Expr>) -> Result<Option<Inline>> {
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let ident = syn::parse::Parser::parse2(parse_or_str::<Ident>, value.into_token_stream())
+            .map_err(|_| syn::Error::new_spanned(value, EXPECTED_INLINE))?;
+        Inline::from_ident(&ident).map(Some)
+    }
+
+    fn from_ident(ident: &Ident) -> Result<Inline> {
+        if ident == "default" {
+            Ok(Inline::Default)
+        } else if ident == "never" {
+            Ok(Inline::Never)
+        } else if ident == "always" {
+            Ok(Inline::Always)
+        } else {
+            Err(syn::Error::new(ident.span(), EXPECTED_INLINE))
+        }
+    }
+}
+
+impl Parse for Inline {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let inline = Inline::from_ident(&parse_or_str(&content)?)?;
+        if !content.is_empty() {
+            return Err(content.error(EXPECTED_INLINE));
+        }
+        Ok(inline)
+    }
+}
+
+/// The attribute on a stub, if any.
+impl ToTokens for Inline {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            Inline::Default => {}
+            Inline::Never => tokens.extend(quote! { #[inline(never)] }),
+            Inline::Always => tokens.extend(quote! { #[inline(always)] }),
+        }
+    }
+}
+
+/// `#[com_crate(PATH)]` on a struct, or `crate = PATH` in `#[com_impl(...)]`: the path the
+/// generated code names com-impl by, `::com_impl` unless given, for crates re-exporting it.
+pub struct ComCrate {
+    pub path: Path,
+}
+
+const EXPECTED_CRATE: &str = "expected the path of com-impl, e.g. `crate = \"my_crate::com\"`";
+
+impl ComCrate {
+    /// The `crate = ...` argument of `#[com_impl]`, or `::com_impl` if it isn't given.
+    pub fn from_arg(value: Option<&Expr>) -> Result<Path> {
+        match value {
+            Some(Expr::Path(expr)) if expr.qself.is_none() => Ok(expr.path.clone()),
+            Some(value) => Err(syn::Error::new_spanned(value, EXPECTED_CRATE)),
+            None => Ok(ComCrate::default_path()),
+        }
+    }
+
+    pub fn default_path() -> Path {
+        parse_quote!(::com_impl)
+    }
+}
+
+impl Parse for ComCrate {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let path = parse_or_str(&content)?;
+        if !content.is_empty() {
+            return Err(content.error(EXPECTED_CRATE));
+        }
+        Ok(ComCrate { path })
+    }
+}
+
+/// `#[com_panic(abort)]`, the only policy so far.
+pub struct ComPanicAttr;
+
+impl Parse for ComPanicAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let ident: Ident = content.parse()?;
+        if ident != "abort" {
+            return Err(syn::Error::new(ident.span(), "expected `abort`"));
+        }
+        if !content.is_empty() {
+            return Err(content.error("expected `abort`"));
+        }
+        Ok(ComPanicAttr)
+    }
+}
+
+/// `#[com_name = Name]`.
+pub struct ComNameAttr {
+    pub name: Ident,
+}
+
+impl Parse for ComNameAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        input.parse::<Token![=]>()?;
+        let name = if input.peek(LitStr) {
+            let lit: LitStr = input.parse()?;
+            let mut name: Ident = syn::parse_str(&lit.value()).map_err(|_| {
+                syn::Error::new(
+                    lit.span(),
+                    format!("`{}` is not a valid method name", lit.value()),
+                )
+            })?;
+            name.set_span(lit.span());
+            name
+        } else {
+            input.parse()?
+        };
+        Ok(ComNameAttr { name })
+    }
+}
+
+/// `#[out(size, ...)]`, which the `#[out]` of a method's parameters are moved to before the
+/// method is parsed.
+pub struct OutAttr {
+    pub params: Vec<Ident>,
+}
+
+impl Parse for OutAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let params = Punctuated::<Ident, Token![,]>::parse_terminated(&content)?;
+        Ok(OutAttr {
+            params: params.into_iter().collect(),
+        })
+    }
+}
+
+/// `#[iunknown(manual)]`, added to the struct by `#[com_impl(iunknown = manual)]`, or
+/// `#[iunknown(vtbl = PATH, iid = TYPE, is_equal_iid = PATH)]`, each of them optional.
+#[derive(Default)]
+pub struct IUnknownAttr {
+    pub manual: bool,
+    pub vtbl: Option<Path>,
+    pub iid: Option<Type>,
+    pub is_equal_iid: Option<Path>,
+}
+
+impl Parse for IUnknownAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let mut attr = IUnknownAttr::default();
+        while !content.is_empty() {
+            let ident: Ident = content.parse()?;
+            if ident == "manual" {
+                attr.manual = true;
+            } else if ident == "vtbl" {
+                content.parse::<Token![=]>()?;
+                attr.vtbl = Some(parse_or_str(&content)?);
+            } else if ident == "iid" {
+                content.parse::<Token![=]>()?;
+                attr.iid = Some(parse_or_str(&content)?);
+            } else if ident == "is_equal_iid" {
+                content.parse::<Token![=]>()?;
+                attr.is_equal_iid = Some(parse_or_str(&content)?);
+            } else {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "expected `manual`, `vtbl = ...`, `iid = ...` or `is_equal_iid = ...`",
+                ));
+            }
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![,]>()?;
+        }
+        Ok(attr)
+    }
+}
+
+/// `#[hot_reload(SLOT)]`, added to the struct by `#[com_impl(hot_reload = SLOT)]`.
+pub struct HotReloadAttr {
+    pub slot: Expr,
+}
+
+impl Parse for HotReloadAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(HotReloadAttr {
+            slot: content.parse()?,
+        })
+    }
+}
+
+/// `#[alloc(PATH)]`, added to the struct by `#[com_impl(alloc = ...)]`.
+pub struct AllocAttr {
+    pub alloc: Path,
+}
+
+impl Parse for AllocAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(AllocAttr {
+            alloc: content.parse()?,
+        })
+    }
+}
+
+/// `#[constructor(name = alloc_raw, vis = pub(crate))]`, from the `constructor(...)` argument of
+/// `#[com_impl]`.
+pub struct ConstructorAttr {
+    pub name: Option<Ident>,
+    pub vis: Option<Visibility>,
+}
+
+impl Parse for ConstructorAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let mut attr = ConstructorAttr {
+            name: None,
+            vis: None,
+        };
+        while !content.is_empty() {
+            let key: Ident = content.parse()?;
+            content.parse::<Token![=]>()?;
+            if key == "name" {
+                attr.name = Some(parse_or_str(&content)?);
+            } else if key == "vis" {
+                attr.vis = Some(parse_or_str(&content)?);
+            } else {
+                return Err(syn::Error::new(key.span(), "expected `name` or `vis`"));
+            }
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
+        Ok(attr)
+    }
+}
+
+/// `#[singleton(INSTANCE)]`.
+pub struct SingletonAttr {
+    pub instance: Path,
+}
+
+impl Parse for SingletonAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(SingletonAttr {
+            instance: content.parse()?,
+        })
+    }
+}
+
+/// `#[com_new]`, or `#[com_new(new = IFoo, new_bar = IBar)]` naming the constructors and the
+/// interfaces they return.
+pub struct ComNewAttr {
+    pub entries: Vec<ComNewEntry>,
+}
+
+pub struct ComNewEntry {
+    pub name: Ident,
+    pub interface: Type,
+}
+
+impl Parse for ComNewAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.is_empty() {
+            return Ok(ComNewAttr {
+                entries: Vec::new(),
+            });
+        }
+        let content;
+        parenthesized!(content in input);
+        let entries = Punctuated::<ComNewEntry, Token![,]>::parse_terminated(&content)?;
+        Ok(ComNewAttr {
+            entries: entries.into_iter().collect(),
+        })
+    }
+}
+
+impl Parse for ComNewEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let interface = parse_or_str(input)?;
+        Ok(ComNewEntry { name, interface })
+    }
+}
+
+/// `#[builder]`, `#[builder(IFoo)]`.
+pub struct BuilderAttr {
+    pub interface: Option<Type>,
+}
+
+impl Parse for BuilderAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.is_empty() {
+            return Ok(BuilderAttr { interface: None });
+        }
+        let content;
+        parenthesized!(content in input);
+        Ok(BuilderAttr {
+            interface: Some(parse_or_str(&content)?),
+        })
+    }
+}
+
+/// `#[lazy(File::open(&self.path).unwrap())]`, on a `Lazy` member.
+pub struct LazyAttr {
+    pub init: Expr,
+}
+
+impl Parse for LazyAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(LazyAttr {
+            init: content.parse()?,
+        })
+    }
+}
+
+/// `#[interface(IDWritePixelSnapping)]`, on a method of an ancestor of the `impl`'s interface.
+pub struct InterfaceAttr {
+    pub interface: Path,
+}
+
+impl Parse for InterfaceAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(InterfaceAttr {
+            interface: parse_or_str(&content)?,
+        })
+    }
+}
+
+/// An interface in `ancestors(...)`, with the methods its vtable is filled in with stubs for,
+/// as in `IDWritePixelSnapping(get_pixels_per_dip, ...)`.
+pub struct AncestorArg {
+    pub interface: Path,
+    pub stubs: TokenStream,
+}
+
+impl Parse for AncestorArg {
+    fn parse(input: ParseStream) -> Result<Self> {
+        // Not `Fn(...)` sugar
+        let interface = input.call(Path::parse_mod_style)?;
+        let stubs = if input.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            content.parse()?
+        } else {
+            TokenStream::new()
+        };
+        Ok(AncestorArg { interface, stubs })
+    }
+}
+
+/// `#[final_release(Self::recycle)]`.
+pub struct FinalReleaseAttr {
+    pub hook: Expr,
+}
+
+impl Parse for FinalReleaseAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(FinalReleaseAttr {
+            hook: content.parse()?,
+        })
+    }
+}
+
+/// `#[bindings(windows)]`, added to the struct by `#[com_impl(bindings = windows)]`.
+pub struct BindingsAttr {
+    pub bindings: Bindings,
+}
+
+impl Parse for BindingsAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let ident: Ident = content.parse()?;
+        match Bindings::from_ident(&ident) {
+            Some(bindings) if content.is_empty() => Ok(BindingsAttr { bindings }),
+            _ => Err(content.error("expected `winapi`, `windows`, `windows_sys` or `com`")),
+        }
+    }
+}
+
+/// `#[vtbl_name("{}Vtable")]`, added to the struct by `#[com_impl(vtbl_name = "{}Vtable")]`.
+pub struct VtblNameAttr {
+    pub format: LitStr,
+}
+
+impl Parse for VtblNameAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        Ok(VtblNameAttr {
+            format: content.parse()?,
+        })
+    }
+}
+
+/// `#[query_interface(hook = Self::query_first, fallback = Self::query_last)]`, each optional.
+#[derive(Default)]
+pub struct QueryInterfaceAttr {
+    pub hook: Option<Expr>,
+    pub fallback: Option<Expr>,
+}
+
+impl Parse for QueryInterfaceAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let mut attr = QueryInterfaceAttr::default();
+        let args = Punctuated::<ComImplArg, Token![,]>::parse_terminated(&content)?;
+        for arg in args {
+            match arg {
+                ComImplArg::Value(ident, expr) if ident == "hook" => attr.hook = Some(expr),
+                ComImplArg::Value(ident, expr) if ident == "fallback" => attr.fallback = Some(expr),
+                ComImplArg::Word(ident)
+                | ComImplArg::Value(ident, _)
+                | ComImplArg::List(ident, _) => {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "expected `hook = ...` or `fallback = ...`",
+                    ))
+                }
+            }
+        }
+        Ok(attr)
+    }
+}
+
+/// `#[delegate(IStream = stream, IPersist = persist)]`.
+pub struct DelegateAttr {
+    pub entries: Vec<DelegateEntry>,
+}
+
+pub struct DelegateEntry {
+    pub interface: Type,
+    pub member: Ident,
+}
+
+impl Parse for DelegateAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let entries = Punctuated::<DelegateEntry, Token![,]>::parse_terminated(&content)?;
+        Ok(DelegateAttr {
+            entries: entries.into_iter().collect(),
+        })
+    }
+}
+
+impl Parse for DelegateEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let interface = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let member = parse_or_str(input)?;
+        Ok(DelegateEntry { interface, member })
+    }
+}
+
+/// `#[tear_off(IExpensive = ExpensiveTearOff, IRare = RareTearOff)]`.
+pub struct TearOffAttr {
+    pub entries: Vec<TearOffEntry>,
+}
+
+pub struct TearOffEntry {
+    pub interface: Type,
+    pub ty: Type,
+}
+
+impl Parse for TearOffAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let entries = Punctuated::<TearOffEntry, Token![,]>::parse_terminated(&content)?;
+        Ok(TearOffAttr {
+            entries: entries.into_iter().collect(),
+        })
+    }
+}
+
+impl Parse for TearOffEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let interface = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let ty = parse_or_str(input)?;
+        Ok(TearOffEntry { interface, ty })
+    }
+}
+
+/// `#[interfaces(order(IHot, IWarm), family(IBase..=IBase3), ICold, IPlain = IID_IPlain,
+/// marker(IAgileObject))]`.
+pub struct InterfacesAttr {
+    pub priority: Vec<InterfaceEntry>,
+    pub families: Vec<Family>,
+    pub rest: Vec<InterfaceEntry>,
+    /// Interfaces without methods of their own, answered with the IUnknown pointer.
+    pub markers: Vec<InterfaceEntry>,
+}
+
+/// An interface listed in `#[interfaces]`, optionally with the expression giving its IID.
+pub struct InterfaceEntry {
+    pub ty: Type,
+    pub iid: Option<Expr>,
+}
+
+/// `family(IBase..=IBase3)`, the interfaces `IBase`, `IBase1`, `IBase2` and `IBase3`.
+pub struct Family {
+    pub base: Ident,
+    /// Oldest first, so a version's index is its number.
+    pub versions: Vec<Ident>,
+}
+
+impl Parse for Family {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let base: Ident = input.parse()?;
+        input.parse::<Token![..=]>()?;
+        let highest: Ident = input.parse()?;
+
+        let base_name = base.to_string();
+        let highest_name = highest.to_string();
+        let version = match highest_name.get(base_name.len()..) {
+            Some(digits) if highest_name.starts_with(&base_name) => digits.parse::<u32>().ok(),
+            _ => None,
+        };
+        let version = match version {
+            Some(version) if version > 0 => version,
+            _ => {
+                return Err(syn::Error::new(
+                    highest.span(),
+                    format!("expected `{}` followed by a version number", base_name),
+                ))
+            }
+        };
+
+        let mut versions = vec![base.clone()];
+        versions
+            .extend((1..=version).map(|v| Ident::new(&format!("{}{}", base_name, v), base.span())));
+        Ok(Family { base, versions })
+    }
+}
+
+impl Parse for InterfacesAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let mut priority = Vec::new();
+        let mut families = Vec::new();
+        let mut rest = Vec::new();
+        let mut markers = Vec::new();
+        while !content.is_empty() {
+            if content.peek(Ident) && content.peek2(syn::token::Paren) {
+                let ident: Ident = content.parse()?;
+                let group;
+                parenthesized!(group in content);
+                if ident == "order" {
+                    let list = Punctuated::<InterfaceEntry, Token![,]>::parse_terminated(&group)?;
+                    priority.extend(list);
+                } else if ident == "family" {
+                    families.push(group.parse()?);
+                } else if ident == "marker" {
+                    let list = Punctuated::<InterfaceEntry, Token![,]>::parse_terminated(&group)?;
+                    markers.extend(list);
+                } else {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "expected `order(...)`, `family(...)`, `marker(...)` or an interface",
+                    ));
+                }
+            } else {
+                rest.push(content.parse()?);
+            }
+            if content.is_empty() {
+                break;
+            }
+            content.parse::<Token![,]>()?;
+        }
+        Ok(InterfacesAttr {
+            priority,
+            families,
+            rest,
+            markers,
+        })
+    }
+}
+
+impl Parse for InterfaceEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let ty = parse_or_str(input)?;
+        let iid = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(parse_or_str(input)?)
+        } else {
+            None
+        };
+        Ok(InterfaceEntry { ty, iid })
+    }
+}
+
+/// `#[dispid(1)]` or `#[dispid(1, propput)]`.
+pub struct DispIdAttr {
+    pub dispid: Expr,
+    pub propput: bool,
+}
+
+impl Parse for DispIdAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let content;
+        parenthesized!(content in input);
+        let dispid = content.parse()?;
+        let mut propput = false;
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+            let ident: Ident = content.parse()?;
+            if ident != "propput" || !content.is_empty() {
+                return Err(syn::Error::new(ident.span(), "expected `propput`"));
+            }
+            propput = true;
+        }
+        Ok(DispIdAttr { dispid, propput })
+    }
+}
@@ -0,0 +1,364 @@
+//! The crate an interface's definition comes from, which decides how its vtable, its IID and
+//! IUnknown are spelled in the generated code.
+
+use proc_macro2::{Span, TokenStream};
+use syn::{Error, Expr, Ident, Path, Type};
+
+use crate::vtbl_name::VtblName;
+
+/// The error for a `bindings = ...` argument naming no known bindings.
+pub const EXPECTED: &str = "Expected `bindings = winapi`, `bindings = windows`, \
+                            `bindings = windows_sys` or `bindings = com`";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Bindings {
+    /// `winapi`: `IFooVtbl` with a `parent` member, IIDs from `winapi::Interface::uuidof()`.
+    Winapi,
+    /// The `windows` crate: `IFoo_Vtbl` with a `base__` member, IIDs from
+    /// `windows::core::Interface::IID`, and stubs taking `this` as a `*mut c_void`.
+    Windows,
+    /// `windows-sys`: vtables named like the `windows` crate's, IIDs from `IID_IFoo` constants,
+    /// and IUnknown from `com_impl::windows_sys`.
+    WindowsSys,
+    /// The `com` crate's `com::interfaces!`: `IFooVTable` with a `parent` member, IIDs from
+    /// `com::Interface::IID`, and stubs taking `this` as a `NonNull<IFooVPtr>`.
+    Com,
+}
+
+impl Bindings {
+    pub fn from_ident(ident: &Ident) -> Option<Bindings> {
+        if ident == "winapi" {
+            Some(Bindings::Winapi)
+        } else if ident == "windows" {
+            Some(Bindings::Windows)
+        } else if ident == "windows_sys" {
+            Some(Bindings::WindowsSys)
+        } else if ident == "com" {
+            Some(Bindings::Com)
+        } else {
+            None
+        }
+    }
+
+    /// The `bindings = ...` argument of `#[com_impl]`, winapi if it isn't given.
+    pub fn from_arg(value: Option<&Expr>) -> Result<Bindings, Error> {
+        let path = match value {
+            Some(Expr::Path(path)) if path.qself.is_none() => &path.path,
+            Some(value) => return Err(Error::new_spanned(value, EXPECTED)),
+            None => return Ok(Bindings::Winapi),
+        };
+        let ident = match path.segments.len() {
+            1 => &path.segments[0].ident,
+            _ => return Err(Error::new_spanned(path, EXPECTED)),
+        };
+        Bindings::from_ident(ident).ok_or_else(|| Error::new(ident.span(), EXPECTED))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Bindings::Winapi => "winapi",
+            Bindings::Windows => "windows",
+            Bindings::WindowsSys => "windows_sys",
+            Bindings::Com => "com",
+        }
+    }
+
+    /// Fails with an error naming `feature`, at `span`, if it can't be used with these bindings.
+    pub fn require_winapi(self, feature: &str, span: Span) -> Result<(), Error> {
+        match self {
+            Bindings::Winapi => Ok(()),
+            _ => Err(Error::new(
+                span,
+                format!(
+                    "{} is only available for winapi interfaces, not `bindings = {}`.",
+                    feature,
+                    self.name()
+                ),
+            )),
+        }
+    }
+
+    /// How these bindings name vtables, e.g. `IFooVtbl` or `IFoo_Vtbl`.
+    pub fn vtbl_name(self) -> VtblName {
+        let format = match self {
+            Bindings::Winapi => "{}Vtbl",
+            Bindings::Windows | Bindings::WindowsSys => "{}_Vtbl",
+            Bindings::Com => "{}VTable",
+        };
+        VtblName::parse(format, Span::call_site()).unwrap()
+    }
+
+    /// The interface of the vtable struct `vtbl`, or `None` if `vtbl_name` doesn't name it.
+    /// IUnknown's vtable keeps the name the bindings give it.
+    pub fn interface_of_vtbl(
+        self,
+        krate: &Path,
+        vtbl_name: &VtblName,
+        vtbl: &Type,
+    ) -> Option<Type> {
+        let is_iunknown = |interface: &Type| match interface {
+            Type::Path(path) => path.path.segments.last().unwrap().value().ident == "IUnknown",
+            _ => false,
+        };
+        match self.vtbl_name().interface_of(vtbl) {
+            Some(ref interface) if is_iunknown(interface) => return Some(self.iunknown(krate)),
+            _ => (),
+        }
+        vtbl_name.interface_of(vtbl)
+    }
+
+    /// The vtable member holding the entries of the parent interface.
+    pub fn parent_member(self) -> Ident {
+        match self {
+            Bindings::Winapi | Bindings::Com => parse_quote!(parent),
+            Bindings::Windows | Bindings::WindowsSys => parse_quote!(base__),
+        }
+    }
+
+    /// The type of `this` in the vtable entries of `com_ty`.
+    pub fn this_ty(self, com_ty: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { *mut #com_ty },
+            Bindings::Windows | Bindings::WindowsSys => quote! { *mut ::std::ffi::c_void },
+            Bindings::Com => {
+                let vptr = suffixed(com_ty, "VPtr");
+                quote! { ::std::ptr::NonNull<#vptr> }
+            }
+        }
+    }
+
+    /// `this` as a raw pointer, which the stubs cast from. `this_ty` unless that isn't one.
+    pub fn raw_this_ty(self, com_ty: &Path) -> TokenStream {
+        match self {
+            Bindings::Com => quote! { *mut ::std::ffi::c_void },
+            _ => self.this_ty(com_ty),
+        }
+    }
+
+    /// Shadows the stub parameter `this` with its raw pointer.
+    pub fn raw_this(self, this: &Ident) -> TokenStream {
+        match self {
+            Bindings::Com => quote! {
+                let #this = #this.as_ptr() as *mut ::std::ffi::c_void;
+            },
+            _ => quote! {},
+        }
+    }
+
+    /// IUnknown, as listed in `#[interfaces]`. For `windows-sys`, which has no interface types,
+    /// this only names its IID constant `com_impl::windows_sys::IID_IUnknown`. Paths into
+    /// com-impl start with `krate`.
+    pub fn iunknown(self, krate: &Path) -> Type {
+        match self {
+            Bindings::Winapi => parse_quote!(#krate::__private::IUnknown),
+            Bindings::Windows => parse_quote!(::windows::core::IUnknown),
+            Bindings::WindowsSys => parse_quote!(#krate::windows_sys::IUnknown),
+            Bindings::Com => parse_quote!(::com::interfaces::IUnknown),
+        }
+    }
+
+    pub fn iunknown_vtbl(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::IUnknownVtbl },
+            Bindings::Windows => quote! { ::windows::core::IUnknown_Vtbl },
+            Bindings::WindowsSys => quote! { #krate::windows_sys::IUnknown_Vtbl },
+            Bindings::Com => quote! { ::com::interfaces::iunknown::IUnknownVTable },
+        }
+    }
+
+    /// The type of `this` in IUnknown's vtable entries.
+    pub fn iunknown_this(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { *mut #krate::__private::IUnknown },
+            Bindings::Windows | Bindings::WindowsSys => quote! { *mut ::std::ffi::c_void },
+            Bindings::Com => {
+                quote! { ::std::ptr::NonNull<::com::interfaces::iunknown::IUnknownVPtr> }
+            }
+        }
+    }
+
+    pub fn c_void(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::c_void },
+            _ => quote! { ::std::ffi::c_void },
+        }
+    }
+
+    pub fn iid(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::IID },
+            Bindings::Windows => quote! { ::windows::core::GUID },
+            Bindings::WindowsSys => quote! { #krate::windows_sys::GUID },
+            Bindings::Com => quote! { ::com::sys::GUID },
+        }
+    }
+
+    pub fn hresult(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::HRESULT },
+            Bindings::Windows => quote! { ::windows::core::HRESULT },
+            Bindings::WindowsSys => quote! { #krate::windows_sys::HRESULT },
+            Bindings::Com => quote! { ::com::sys::HRESULT },
+        }
+    }
+
+    pub fn s_ok(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::S_OK },
+            Bindings::Windows => quote! { ::windows::core::HRESULT(0) },
+            Bindings::WindowsSys => quote! { 0 },
+            Bindings::Com => quote! { ::com::sys::S_OK },
+        }
+    }
+
+    pub fn e_nointerface(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::E_NOINTERFACE },
+            Bindings::Windows => quote! { ::windows::core::HRESULT(0x8000_4002_u32 as i32) },
+            Bindings::WindowsSys => quote! { (0x8000_4002_u32 as i32) },
+            Bindings::Com => quote! { ::com::sys::E_NOINTERFACE },
+        }
+    }
+
+    pub fn e_notimpl(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::E_NOTIMPL },
+            Bindings::Windows => quote! { ::windows::core::HRESULT(0x8000_4001_u32 as i32) },
+            Bindings::WindowsSys => quote! { (0x8000_4001_u32 as i32) },
+            Bindings::Com => quote! { (0x8000_4001_u32 as i32) },
+        }
+    }
+
+    pub fn e_pointer(self, krate: &Path) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::E_POINTER },
+            Bindings::Windows => quote! { ::windows::core::HRESULT(0x8000_4003_u32 as i32) },
+            Bindings::WindowsSys => quote! { (0x8000_4003_u32 as i32) },
+            Bindings::Com => quote! { ::com::sys::E_POINTER },
+        }
+    }
+
+    /// The IID of `interface`, unless it is given explicitly.
+    pub fn iid_of(self, krate: &Path, interface: &Type) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { <#interface as #krate::__private::Interface>::uuidof() },
+            Bindings::Windows => quote! { <#interface as ::windows::core::Interface>::IID },
+            Bindings::Com => quote! { <#interface as ::com::Interface>::IID },
+            Bindings::WindowsSys => {
+                // The SDK's name for the constant, next to where the interface would be
+                let mut path = match interface {
+                    Type::Path(path) if path.qself.is_none() => path.path.clone(),
+                    _ => {
+                        return quote! { ::std::compile_error!("Expected the name of an interface") }
+                    }
+                };
+                let mut last = path.segments.last_mut().unwrap();
+                let last = last.value_mut();
+                last.ident = Ident::new(&format!("IID_{}", last.ident), last.ident.span());
+                quote! { #path }
+            }
+        }
+    }
+
+    /// Whether the HRESULT `hr` is a failure code.
+    pub fn failed(self, hr: &Ident) -> TokenStream {
+        match self {
+            Bindings::Winapi | Bindings::WindowsSys | Bindings::Com => quote! { #hr < 0 },
+            Bindings::Windows => quote! { #hr.0 < 0 },
+        }
+    }
+
+    /// The first field of the GUID, which tells almost all interfaces apart on its own.
+    pub fn data1(self) -> Ident {
+        let name = match self {
+            Bindings::Winapi => "Data1",
+            Bindings::Windows | Bindings::WindowsSys | Bindings::Com => "data1",
+        };
+        Ident::new(name, Span::call_site())
+    }
+
+    /// The first field of the IID of `interface` as a constant, where the bindings give the
+    /// IID as one. winapi's only comes from calling `uuidof()`.
+    pub fn const_data1(self, krate: &Path, interface: &Type) -> Option<TokenStream> {
+        match self {
+            Bindings::Winapi => None,
+            Bindings::Windows | Bindings::WindowsSys | Bindings::Com => {
+                let iid = self.iid_of(krate, interface);
+                Some(quote! { #iid.data1 })
+            }
+        }
+    }
+
+    /// Whether the IID referenced by `riid` equals the local `iid`. Comparing the first field
+    /// first rejects almost every mismatch with a single integer compare, so the full GUID
+    /// comparison only runs for the likely hit.
+    pub fn compare_iid(self, krate: &Path, riid: &Ident) -> TokenStream {
+        let data1 = self.data1();
+        let equal = self.equal_iid(krate, riid);
+        quote! {
+            #riid.#data1 == iid.#data1 && #equal
+        }
+    }
+
+    /// Whether the IID referenced by `riid` equals the local `iid`, comparing all of it.
+    pub fn equal_iid(self, krate: &Path, riid: &Ident) -> TokenStream {
+        match self {
+            Bindings::Winapi => quote! { #krate::__private::IsEqualIID(#riid, &iid) },
+            Bindings::Windows | Bindings::Com => quote! { *#riid == iid },
+            // windows-sys' GUID doesn't implement PartialEq
+            Bindings::WindowsSys => quote! {
+                #riid.data1 == iid.data1
+                    && #riid.data2 == iid.data2
+                    && #riid.data3 == iid.data3
+                    && #riid.data4 == iid.data4
+            },
+        }
+    }
+
+    /// Calls the IUnknown method `method` of the object `this` points to, through its vtable.
+    pub fn call_iunknown(
+        self,
+        krate: &Path,
+        this: TokenStream,
+        method: &str,
+        args: TokenStream,
+    ) -> TokenStream {
+        let method = Ident::new(method, proc_macro2::Span::call_site());
+        match self {
+            Bindings::Winapi => quote! {
+                (*(#this as *mut #krate::__private::IUnknown)).#method(#args)
+            },
+            Bindings::Windows | Bindings::WindowsSys => {
+                let vtbl = self.iunknown_vtbl(krate);
+                quote! {
+                    {
+                        let this = #this as *mut ::std::ffi::c_void;
+                        ((**(this as *const *const #vtbl)).#method)(this, #args)
+                    }
+                }
+            }
+            Bindings::Com => {
+                let vtbl = self.iunknown_vtbl(krate);
+                quote! {
+                    {
+                        let this = #this as *mut ::std::ffi::c_void;
+                        ((**(this as *const *const #vtbl)).#method)(
+                            ::std::ptr::NonNull::new_unchecked(this as *mut _),
+                            #args
+                        )
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its last segment.
+fn suffixed(path: &Path, suffix: &str) -> Path {
+    let mut path = path.clone();
+    let mut last = path.segments.last_mut().unwrap();
+    let last = last.value_mut();
+    let name = format!("{}{}", last.ident, suffix);
+    last.ident = Ident::new(&name, last.ident.span());
+    path
+}
@@ -1,30 +1,470 @@
-use proc_macro2::Span;
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Group, Span, TokenStream, TokenTree};
 use quote::ToTokens;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
 use syn::{
-    AttributeArgs, Block, Expr, FnArg, Generics, Ident, ImplItem, ImplItemMethod, Item, ItemImpl,
-    Lit, Meta, MetaNameValue, NestedMeta, Pat, Path, ReturnType, Type,
+    Attribute, Block, Error, Expr, Fields, FieldsNamed, FnArg, Generics, Ident, ImplItem,
+    ImplItemMethod, Item, ItemImpl, ItemStruct, Meta, NestedMeta, Pat, Path, ReturnType, Type,
+    Visibility,
 };
 
-pub fn expand_com_impl(args: &AttributeArgs, item: &Item) -> Result<TokenStream, String> {
+use crate::attr::{
+    self, AncestorArg, ComCrate, ComImplArgs, ComNameAttr, Inline, InterfaceAttr, OutAttr,
+    PanicAttr,
+};
+use crate::bindings::Bindings;
+use crate::vtbl_name::{self, VtblName};
+
+pub fn expand_com_impl(args: &ComImplArgs, item: &Item) -> Result<TokenStream, Error> {
     let item = match item {
+        Item::Impl(item) if args.has_word("dispatch") && item.trait_.is_none() => {
+            let dispatch = args.ident("dispatch").unwrap().span();
+            Bindings::from_arg(args.value("bindings"))?.require_winapi("`dispatch`", dispatch)?;
+            return crate::dispatch::expand_dispatch(args, item);
+        }
+        Item::Impl(item)
+            if args.list("ancestors").is_some() || item.items.iter().any(is_ancestor_method) =>
+        {
+            return expand_hierarchy(args, item)
+        }
         Item::Impl(item) => item,
-        _ => return Err("#[com_impl] may only be used on an `impl` block".into()),
+        Item::Struct(item) => return expand_struct(args, item),
+        _ => {
+            return Err(Error::new_spanned(
+                item,
+                "#[com_impl] may only be used on an `impl` block or a struct",
+            ))
+        }
     };
 
     let info = ComImpl::parse(args, item)?;
-    let result = info.quote();
+    let mut result = info.quote();
+    if let Some(dispatch) = args.ident("dispatch") {
+        info.bindings
+            .require_winapi("`dispatch`", dispatch.span())?;
+        if !info.has_parent || info.member.is_some() {
+            return Err(Error::new(
+                dispatch.span(),
+                "A dual interface derives from IDispatch, in the primary vtable",
+            ));
+        }
+        let dual = crate::dispatch::expand_dual(&info.krate, item, info.com_ty, &info.com_vtbl)?;
+        result.extend(dual);
+    }
+
+    Ok(result)
+}
+
+/// An `unsafe impl` holding methods of the interface's ancestors as well, marked
+/// `#[interface(IParent)]`, expanded as one `impl` block per interface. Each vtable's `parent`
+/// member is filled in from the block of the interface it holds, as for separate blocks.
+/// The methods listed for an ancestor in `ancestors(...)` are stubbed in its block.
+fn expand_hierarchy(args: &ComImplArgs, item: &ItemImpl) -> Result<TokenStream, Error> {
+    if !ComImpl::has_parent(args) || args.value("member").is_some() {
+        let arg = ["no_parent", "no_iunknown", "member"]
+            .iter()
+            .find_map(|name| args.ident(name))
+            .unwrap();
+        return Err(Error::new(
+            arg.span(),
+            "Methods of ancestor interfaces, marked #[interface(...)], and `ancestors(...)` \
+             can't be combined with `no_parent`, `no_iunknown` or `member = ...`",
+        ));
+    }
+    let com_ty = ComImpl::com_ty(item)?;
+    let mut own = item.clone();
+    own.items.clear();
+    let mut ancestors: Vec<ItemImpl> = Vec::new();
+    for impl_item in &item.items {
+        let mut method = match impl_item {
+            _ if is_helper(impl_item) => {
+                own.items.push(impl_item.clone());
+                continue;
+            }
+            ImplItem::Method(method) => method.clone(),
+            _ => return Err(not_a_method(impl_item)),
+        };
+        let interface = match ancestor_of(&method)? {
+            Some(interface) if !same_path(&interface, com_ty) => interface,
+            _ => {
+                own.items.push(impl_item.clone());
+                continue;
+            }
+        };
+        method.attrs.retain(|attr| !attr.path.is_ident("interface"));
+
+        let existing = ancestors.iter().position(|ancestor| {
+            let (_, path, _) = ancestor.trait_.as_ref().unwrap();
+            same_path(path, &interface)
+        });
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let mut ancestor = own.clone();
+                // Without the methods and helpers of the block's own interface gathered so far
+                ancestor.items.clear();
+                ancestor.trait_.as_mut().unwrap().1 = interface;
+                ancestors.push(ancestor);
+                ancestors.len() - 1
+            }
+        };
+        ancestors[index].items.push(ImplItem::Method(method));
+    }
+
+    // The methods listed for an ancestor are stubbed in its block, one of its own if the
+    // block has none of its methods
+    let mut stubs = vec![TokenStream::new(); ancestors.len()];
+    if let Some(chain) = args.list("ancestors") {
+        let bindings = Bindings::from_arg(args.value("bindings"))?;
+        bindings.require_winapi("`ancestors(...)`", args.ident("ancestors").unwrap().span())?;
+        let chain = Punctuated::<AncestorArg, Token![,]>::parse_terminated
+            .parse2(chain.clone())
+            .map_err(|e| {
+                Error::new(
+                    e.span(),
+                    format!("Invalid syntax for `ancestors(...)`: {}", e),
+                )
+            })?;
+        for arg in chain {
+            let existing = ancestors.iter().position(|ancestor| {
+                let (_, path, _) = ancestor.trait_.as_ref().unwrap();
+                same_path(path, &arg.interface)
+            });
+            match existing {
+                Some(index) => stubs[index] = arg.stubs,
+                None => {
+                    let mut ancestor = item.clone();
+                    ancestor.items.clear();
+                    ancestor.trait_.as_mut().unwrap().1 = arg.interface;
+                    ancestors.push(ancestor);
+                    stubs.push(arg.stubs);
+                }
+            }
+        }
+    }
 
+    // The vtable given, dual interfaces, descriptions and stubs are the block's own interface's
+    let ancestor_args = args.without(&[
+        "vtbl",
+        "dispatch",
+        "describe",
+        "parent",
+        "ancestors",
+        "stub",
+    ]);
+    let mut result = expand_com_impl(&args.without(&["ancestors"]), &Item::Impl(own))?;
+    for (ancestor, stubs) in ancestors.iter().zip(stubs) {
+        let ancestor_args = ancestor_args.with_list("stub", stubs);
+        let ancestor = Item::Impl(ancestor.clone());
+        result.extend(expand_com_impl(&ancestor_args, &ancestor)?);
+    }
     Ok(result)
 }
 
+fn is_ancestor_method(item: &ImplItem) -> bool {
+    match item {
+        ImplItem::Method(method) => method.attrs.iter().any(|a| a.path.is_ident("interface")),
+        _ => false,
+    }
+}
+
+/// The interface named by a method's `#[interface(...)]`.
+fn ancestor_of(method: &ImplItemMethod) -> Result<Option<Path>, Error> {
+    for attr in &method.attrs {
+        if !attr.path.is_ident("interface") {
+            continue;
+        }
+
+        let InterfaceAttr { interface } = attr::parse(attr)?;
+        return Ok(Some(interface));
+    }
+    Ok(None)
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    a.into_token_stream().to_string() == b.into_token_stream().to_string()
+}
+
+/// Whether the item is a const or marked `#[com_skip]`, for the COM methods to use rather than
+/// a method of the interface.
+fn is_helper(item: &ImplItem) -> bool {
+    let attrs = match item {
+        ImplItem::Const(_) => return true,
+        ImplItem::Method(method) => &method.attrs,
+        ImplItem::Macro(mac) => &mac.attrs,
+        _ => return false,
+    };
+    attrs.iter().any(|attr| attr.path.is_ident("com_skip"))
+}
+
+fn not_a_method(item: &ImplItem) -> Error {
+    Error::new_spanned(
+        item,
+        "Only methods may be in a com_impl body, besides consts and items marked #[com_skip]",
+    )
+}
+
+/// Gives the methods marked `#[stub]` and declared without a body, as in
+/// `#[stub] fn do_verb();`, an empty one, so the `impl` block parses.
+pub fn fill_stub_bodies(item: TokenStream) -> TokenStream {
+    item.into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(ref group) if group.delimiter() == Delimiter::Brace => {
+                let mut body = Group::new(Delimiter::Brace, fill_stub_items(group.stream()));
+                body.set_span(group.span());
+                TokenTree::Group(body)
+            }
+            tt => tt,
+        })
+        .collect()
+}
+
+fn fill_stub_items(items: TokenStream) -> TokenStream {
+    let mut result = Vec::new();
+    let mut in_stub = false;
+    for tt in items {
+        match &tt {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                in_stub |= group.stream().to_string() == "stub";
+            }
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => in_stub = false,
+            TokenTree::Punct(punct) if punct.as_char() == ';' && in_stub => {
+                in_stub = false;
+                let mut body = Group::new(Delimiter::Brace, TokenStream::new());
+                body.set_span(punct.span());
+                result.push(TokenTree::Group(body));
+                continue;
+            }
+            _ => {}
+        }
+        result.push(tt);
+    }
+    result.into_iter().collect()
+}
+
+/// Moves the `#[out]` of a method's parameters, as in `fn get_file_size(&self, #[out] size:
+/// *mut u64)`, to an `#[out(size)]` on the method, as syn only parses attributes there.
+pub fn lift_out_params(item: TokenStream) -> TokenStream {
+    item.into_iter()
+        .map(|tt| match tt {
+            TokenTree::Group(ref group) if group.delimiter() == Delimiter::Brace => {
+                let mut body = Group::new(Delimiter::Brace, lift_out_items(group.stream()));
+                body.set_span(group.span());
+                TokenTree::Group(body)
+            }
+            tt => tt,
+        })
+        .collect()
+}
+
+fn lift_out_items(items: TokenStream) -> TokenStream {
+    let mut result = Vec::new();
+    // Where the current item, and its attributes, begin
+    let mut item_start = 0;
+    // How far into `fn name(` the tokens are
+    let mut after_fn = 0;
+    for tt in items {
+        match &tt {
+            TokenTree::Ident(ident) if ident == "fn" => after_fn = 1,
+            TokenTree::Ident(_) if after_fn == 1 => after_fn = 2,
+            TokenTree::Group(group)
+                if after_fn == 2 && group.delimiter() == Delimiter::Parenthesis =>
+            {
+                after_fn = 0;
+                let (params, outs) = strip_out_attrs(group.stream());
+                if !outs.is_empty() {
+                    let attr = quote! { #[out(#(#outs),*)] };
+                    result.splice(item_start..item_start, attr);
+                }
+                let mut params = Group::new(Delimiter::Parenthesis, params);
+                params.set_span(group.span());
+                result.push(TokenTree::Group(params));
+                continue;
+            }
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                after_fn = 0;
+                result.push(tt);
+                item_start = result.len();
+                continue;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ';' => {
+                after_fn = 0;
+                result.push(tt);
+                item_start = result.len();
+                continue;
+            }
+            _ => after_fn = 0,
+        }
+        result.push(tt);
+    }
+    result.into_iter().collect()
+}
+
+/// The parameters without their `#[out]`, and the names of the parameters it marked.
+fn strip_out_attrs(params: TokenStream) -> (TokenStream, Vec<Ident>) {
+    let mut result = Vec::new();
+    let mut outs = Vec::new();
+    let mut is_out = false;
+    let mut tokens = params.into_iter().peekable();
+    while let Some(tt) = tokens.next() {
+        match (&tt, tokens.peek()) {
+            (TokenTree::Punct(punct), Some(TokenTree::Group(group)))
+                if punct.as_char() == '#'
+                    && group.delimiter() == Delimiter::Bracket
+                    && group.stream().to_string() == "out" =>
+            {
+                tokens.next();
+                is_out = true;
+                continue;
+            }
+            (TokenTree::Ident(ident), _) if is_out && ident != "mut" => {
+                is_out = false;
+                outs.push(ident.clone());
+            }
+            _ => {}
+        }
+        result.push(tt);
+    }
+    (result.into_iter().collect(), outs)
+}
+
+/// The attributes of a method read by the macro, which aren't passed on.
+fn is_own_attr(attr: &Attribute) -> bool {
+    ["com_name", "panic", "dispid", "stub", "out"]
+        .iter()
+        .any(|name| attr.path.is_ident(name))
+}
+
+/// The attributes of a method that apply to every function generated for it, not only its
+/// body: `cfg`, and lint levels such as `#[allow(clippy::too_many_arguments)]`.
+fn is_shared_attr(attr: &Attribute) -> bool {
+    ["cfg", "allow", "warn", "deny", "forbid"]
+        .iter()
+        .any(|name| attr.path.is_ident(name))
+}
+
+/// `#[com_impl(iunknown = manual, hot_reload = SLOT, bindings = windows, vtbl_name = "...")]`
+/// on a struct, whose arguments are passed on to the derive as `#[iunknown(manual)]`,
+/// `#[hot_reload(SLOT)]`, `#[bindings(windows)]` and `#[vtbl_name("...")]`, and `alloc = ...`
+/// as `#[alloc(PATH)]`, `inline = ...` as `#[com_inline(...)]`, `crate = ...` as
+/// `#[com_crate(PATH)]`, and `constructor(...)` as `#[constructor(...)]`. `agile` adds an
+/// `#[ftm]` member. Being an attribute macro, it is expanded before the derive.
+fn expand_struct(args: &ComImplArgs, item: &ItemStruct) -> Result<TokenStream, Error> {
+    let mut item = item.clone();
+    let krate = ComCrate::from_arg(args.value("crate"))?;
+    for name in args.names() {
+        // Helper attributes must follow the derive that declares them
+        if name == "iunknown" {
+            let manual = match args.value("iunknown") {
+                Some(Expr::Path(path)) => path.qself.is_none() && path.path.is_ident("manual"),
+                _ => false,
+            };
+            if !manual {
+                return Err(Error::new(name.span(), "Expected `iunknown = manual`"));
+            }
+            item.attrs.push(parse_quote! { #[iunknown(manual)] });
+        } else if name == "hot_reload" {
+            let slot = match args.value("hot_reload") {
+                Some(slot) => slot,
+                None => return Err(Error::new(name.span(), "Expected `hot_reload = SLOT`")),
+            };
+            item.attrs.push(parse_quote! { #[hot_reload(#slot)] });
+        } else if name == "bindings" {
+            let bindings = match args.value("bindings") {
+                Some(bindings) => bindings,
+                None => return Err(Error::new(name.span(), crate::bindings::EXPECTED)),
+            };
+            Bindings::from_arg(Some(bindings))?;
+            item.attrs.push(parse_quote! { #[bindings(#bindings)] });
+        } else if name == "vtbl_name" {
+            let format = args.value("vtbl_name");
+            VtblName::from_arg(format)?
+                .ok_or_else(|| Error::new(name.span(), vtbl_name::EXPECTED))?;
+            item.attrs.push(parse_quote! { #[vtbl_name(#format)] });
+        } else if name == "inline" {
+            let inline = args.value("inline");
+            Inline::from_arg(inline)?
+                .ok_or_else(|| Error::new(name.span(), "Expected `inline = \"never\"`"))?;
+            item.attrs.push(parse_quote! { #[com_inline(#inline)] });
+        } else if name == "crate" {
+            if args.value("crate").is_none() {
+                return Err(Error::new(name.span(), "Expected `crate = PATH`"));
+            }
+            item.attrs.push(parse_quote! { #[com_crate(#krate)] });
+        } else if name == "alloc" {
+            let alloc = match args.value("alloc") {
+                Some(Expr::Path(path)) if path.qself.is_none() => &path.path,
+                _ => {
+                    return Err(Error::new(
+                        name.span(),
+                        "Expected `alloc = \"cotaskmem\"` or `alloc = PATH`",
+                    ))
+                }
+            };
+            let alloc: Path = if alloc.is_ident("cotaskmem") {
+                parse_quote! { #krate::alloc::CoTaskMem }
+            } else {
+                alloc.clone()
+            };
+            item.attrs.push(parse_quote! { #[alloc(#alloc)] });
+        } else if name == "constructor" {
+            let constructor = match args.list("constructor") {
+                Some(constructor) => constructor,
+                None => {
+                    return Err(Error::new(
+                        name.span(),
+                        "Expected `constructor(name = ..., vis = ...)`",
+                    ))
+                }
+            };
+            item.attrs
+                .push(parse_quote! { #[constructor(#constructor)] });
+        } else if name == "agile" && args.has_word("agile") {
+            let fields = match &mut item.fields {
+                Fields::Named(fields) => fields,
+                _ => {
+                    return Err(Error::new(
+                        name.span(),
+                        "`agile` needs a struct with named members.",
+                    ))
+                }
+            };
+            let ftm: FieldsNamed = parse_quote! {
+                { #[ftm] __com_impl_ftm: #krate::ftm::FreeThreadedMarshaler }
+            };
+            fields.named.extend(ftm.named);
+        } else {
+            return Err(Error::new(
+                name.span(),
+                "On a struct, #[com_impl] only accepts `iunknown = manual`, \
+                 `hot_reload = SLOT`, `bindings = ...`, `vtbl_name = \"...\"`, `alloc = ...`, \
+                 `inline = ...`, `crate = ...`, `constructor(...)` and `agile`",
+            ));
+        }
+    }
+    Ok(item.into_token_stream())
+}
+
 struct ComImpl<'a> {
     has_parent: bool,
+    apartment: bool,
+    intercept: bool,
+    unwind: bool,
+    inline: Inline,
+    /// The path of com-impl in the generated code, `::com_impl` unless `crate = ...` is given.
+    krate: Path,
+    describe: Option<Describe>,
+    member: Option<Ident>,
+    bindings: Bindings,
     self_ty: &'a Type,
     com_ty: &'a Path,
     com_vtbl: Path,
     com_ty_name: &'a Ident,
     functions: Vec<ComFunction<'a>>,
+    /// The names of the methods stubbed without a signature.
+    stubs: Vec<Ident>,
+    /// The consts and items marked `#[com_skip]`, moved to the inherent `impl`.
+    helpers: Vec<ImplItem>,
     generics: &'a Generics,
 }
 
@@ -32,33 +472,153 @@ impl<'a> ComImpl<'a> {
     fn quote(&self) -> TokenStream {
         let vtbl_impl = self.quote_vtbl_impl();
         let fn_impls = self.quote_fn_impls();
+        let describe_impl = self.quote_describe_impl();
+
+        let method_check = self.quote_method_check();
+        let name_checks = self.quote_name_checks();
 
         quote! {
             #vtbl_impl
+            #method_check
+            #name_checks
             #fn_impls
+            #describe_impl
+        }
+    }
+
+    /// Fails to compile, at the interface, when its vtable has more methods than the block
+    /// fills in. The compiler names the missing vtable field as well, but in the words of the
+    /// struct literal, and not at all once a method maps to a field the vtable lacks.
+    fn quote_method_check(&self) -> TokenStream {
+        let krate = &self.krate;
+        let com_vtbl = &self.com_vtbl;
+        // A free const can't name the impl's parameters
+        if mentions_generics(self.generics, com_vtbl.into_token_stream()) {
+            return quote! {};
+        }
+
+        let own_methods = if self.member.is_some() || self.has_parent {
+            let parent = self.bindings.parent_member();
+            quote! { #krate::own_methods(|vtbl: &#com_vtbl| &vtbl.#parent) }
+        } else {
+            quote! {
+                ::std::mem::size_of::<#com_vtbl>() / ::std::mem::size_of::<#krate::VtblEntry>()
+            }
+        };
+        let implemented = self.functions.len() + self.stubs.len();
+        let example = match self.functions.first() {
+            Some(f) => format!(" as `fn {}` is for `{}`,", f.name, f.com_name),
+            None => String::new(),
+        };
+        let message = format!(
+            "`{}` has methods the #[com_impl] block doesn't implement. Implement each as a \
+             method named after its vtable entry in snake_case,{} with the interface's \
+             arguments, or stub it with `stub(...)`.",
+            self.com_ty_name, example,
+        );
+
+        quote_spanned! { self.com_ty_name.span()=>
+            const _: () = {
+                if #own_methods > #implemented {
+                    ::std::panic!("{}", #message);
+                }
+            };
+        }
+    }
+
+    /// Fails to compile, at a method, when the interface has no method of the name it maps to,
+    /// pointing to `#[com_name = ...]` next to the compiler's error, which suggests the vtable
+    /// entry of the closest name. That error is about a struct literal the user never wrote.
+    /// Only winapi interfaces have a method for each vtable entry to look the names up by, and
+    /// a block none of whose names are found that way is left alone.
+    fn quote_name_checks(&self) -> TokenStream {
+        let com_ty = self.com_ty;
+        if self.bindings != Bindings::Winapi
+            || self.functions.is_empty()
+            || mentions_generics(self.generics, com_ty.into_token_stream())
+        {
+            return quote! {};
+        }
+
+        let names = self
+            .functions
+            .iter()
+            .map(|f| &f.com_name)
+            .chain(&self.stubs)
+            .collect::<Vec<_>>();
+        let mut probes = names.clone();
+        probes.sort();
+        probes.dedup();
+        let checks = self.functions.iter().map(|f| {
+            let com_name = &f.com_name;
+            let message = format!(
+                "`{}` has no method `{}` for `fn {}` to implement. Name the vtable entry it \
+                 implements with `#[com_name = ...]`, such as the field the compiler suggests \
+                 in place of `{}`.",
+                self.com_ty_name, com_name, f.name, com_name,
+            );
+
+            quote_spanned! { f.name.span()=>
+                const _: () = {
+                    if ANY_FOUND && !found(&<#com_ty>::#com_name) {
+                        ::std::panic!("{}", #message);
+                    }
+                };
+            }
+        });
+        let com_tys = std::iter::repeat(com_ty);
+
+        // A name the interface has no method of finds the trait's const instead, which unlike
+        // a function isn't zero-sized
+        quote! {
+            const _: () = {
+                struct Missing(u8);
+
+                #[allow(non_upper_case_globals)]
+                trait Probe {
+                    #(const #probes: Missing = Missing(0);)*
+                }
+
+                impl<T: ?::std::marker::Sized> Probe for T {}
+
+                const fn found<T>(_: &T) -> bool {
+                    ::std::mem::size_of::<T>() == 0
+                }
+
+                const ANY_FOUND: bool = #(found(&<#com_tys>::#names))||*;
+
+                #(#checks)*
+            };
         }
     }
 
     fn quote_vtbl_impl(&self) -> TokenStream {
+        let krate = &self.krate;
         let self_ty = self.self_ty;
         let (impgen, _, wherec) = self.generics.split_for_impl();
         let com_vtbl = &self.com_vtbl;
         let parent_entry = self.quote_parent_entry();
-        let com_entries = self
-            .functions
+        let com_entries = self.functions.iter().map(|f| f.quote_vtbl_entry(self));
+        let stub_entries = self
+            .stubs
             .iter()
-            .map(|f| f.quote_vtbl_entry(self.com_ty_name));
+            .map(|name| quote! { #name: #krate::stub() });
+        // Spanned at the interface, where the compiler reports missing entries, and which
+        // lets it suggest the closest field for an entry the vtable lacks
+        let literal = quote_spanned! { self.com_ty_name.span()=>
+            #com_vtbl {
+                #parent_entry
+                #(#com_entries,)*
+                #(#stub_entries,)*
+            }
+        };
 
         quote! {
-            unsafe impl #impgen com_impl::BuildVTable<#com_vtbl> for #self_ty #wherec {
-                const VTBL: #com_vtbl = #com_vtbl {
-                    #parent_entry
-                    #(#com_entries,)*
-                };
+            unsafe impl #impgen #krate::BuildVTable<#com_vtbl> for #self_ty #wherec {
+                const VTBL: #com_vtbl = #literal;
 
-                fn static_vtable() -> com_impl::VTable<#com_vtbl> {
-                    com_impl::VTable::new(&Self::VTBL)
-                }
+                const STATIC_VTABLE: #krate::VTable<#com_vtbl> =
+                    #krate::VTable::new(&Self::VTBL);
             }
         }
     }
@@ -66,21 +626,74 @@ impl<'a> ComImpl<'a> {
     fn quote_fn_impls(&self) -> TokenStream {
         let self_ty = self.self_ty;
         let (impgen, _, wherec) = self.generics.split_for_impl();
-        let fn_stubs = self.functions.iter().map(|f| f.quote_stub(self));
-        let fn_bodies = self.functions.iter().map(|f| f.quote_body(self));
+        let (stubs, functions): (Vec<_>, Vec<_>) = self.functions.iter().partition(|f| f.is_stub);
+
+        let fn_stubs = functions.iter().map(|f| {
+            if f.merges_body(self) {
+                f.quote_merged_stub(self)
+            } else {
+                f.quote_stub(self)
+            }
+        });
+        let fn_bodies = functions
+            .iter()
+            .filter(|f| !f.merges_body(self))
+            .map(|f| f.quote_body(self));
+        let fn_inherents = functions.iter().map(|f| f.quote_inherent(self));
+        let fn_fallbacks = self.functions.iter().map(|f| f.quote_cfg_fallback(self));
+        let fn_not_implemented = stubs.iter().map(|f| f.quote_not_implemented(self));
+        let helpers = &self.helpers;
 
         quote! {
             #[allow(non_snake_case)]
             impl #impgen #self_ty #wherec {
+                #(#helpers)*
+                #(#fn_not_implemented)*
                 #(#fn_stubs)*
+                #(#fn_fallbacks)*
                 #(#fn_bodies)*
+                #(#fn_inherents)*
+            }
+        }
+    }
+
+    fn quote_describe_impl(&self) -> TokenStream {
+        let describe = match &self.describe {
+            Some(describe) => describe,
+            None => return quote! {},
+        };
+
+        let krate = &self.krate;
+        let self_ty = self.self_ty;
+        let (impgen, _, wherec) = self.generics.split_for_impl();
+        let com_ty = self.com_ty;
+        let name = self.com_ty_name.to_string();
+        let parent = match &describe.parent {
+            Some(parent) => quote! { ::std::option::Option::Some(#parent) },
+            None => quote! { ::std::option::Option::None },
+        };
+        let methods = self.functions.iter().map(|f| f.quote_method_info(krate));
+
+        quote! {
+            impl #impgen #krate::header::Describe<#com_ty> for #self_ty #wherec {
+                const INTERFACE: #krate::header::InterfaceInfo =
+                    #krate::header::InterfaceInfo {
+                        name: #name,
+                        parent: #parent,
+                        methods: &[#(#methods),*],
+                    };
             }
         }
     }
 
     fn quote_parent_entry(&self) -> TokenStream {
-        if self.has_parent {
-            quote! { parent: <Self as com_impl::BuildVTable<_>>::VTBL, }
+        let krate = &self.krate;
+        let parent = self.bindings.parent_member();
+        if let Some(member) = &self.member {
+            let iunknown = member_iunknown(member);
+            quote! { #parent: Self::#iunknown, }
+        } else if self.has_parent {
+            quote! { #parent: <Self as #krate::BuildVTable<_>>::VTBL, }
         } else {
             quote!{}
         }
@@ -88,68 +701,208 @@ impl<'a> ComImpl<'a> {
 
     // ----------------------------------------------------------------
 
-    fn parse(args: &'a AttributeArgs, item: &'a ItemImpl) -> Result<Self, String> {
+    fn parse(args: &'a ComImplArgs, item: &'a ItemImpl) -> Result<Self, Error> {
         if item.unsafety.is_none() {
-            return Err(
+            return Err(Error::new(
+                item.impl_token.span,
                 "Implementing COM interfaces is inherently unsafe. Please use \
-                 `unsafe impl` to signify your understanding of this fact."
-                    .into(),
-            );
+                 `unsafe impl` to signify your understanding of this fact.",
+            ));
         }
 
         let has_parent = Self::has_parent(args);
+        let apartment = Self::is_apartment(args);
+        let intercept = Self::is_intercept(args);
+        let unwind = Self::is_unwind(args);
+        let inline = Inline::from_arg(args.value("inline"))?.unwrap_or(Inline::Never);
+        let krate = ComCrate::from_arg(args.value("crate"))?;
+        let describe = Describe::parse(args, has_parent)?;
+        let member = Self::member(args, has_parent)?;
+        let bindings = Bindings::from_arg(args.value("bindings"))?;
+        // Their helpers in com-impl speak winapi's types
+        let winapi_only = [
+            ("apartment", "`apartment`"),
+            ("intercept", "`intercept`"),
+            ("describe", "`describe`"),
+        ];
+        for &(name, feature) in &winapi_only {
+            if let Some(arg) = args.ident(name) {
+                bindings.require_winapi(feature, arg.span())?;
+            }
+        }
         let self_ty = &item.self_ty;
         let com_ty = Self::com_ty(item)?;
-        let com_vtbl = Self::com_vtbl(com_ty);
+        let com_vtbl = Self::com_vtbl(args, bindings, com_ty)?;
         let com_ty_name = Self::com_ty_name(com_ty);
-        let functions = ComFunction::parse_all(item)?;
+        let default_panic = Self::default_panic(args)?;
+        let (functions, mut stubs) = ComFunction::parse_all(item, &default_panic)?;
+        stubs.extend(Self::determine_stubs(args)?);
+        if let Some(stub) = stubs.first() {
+            let span = args.ident("stub").map_or(stub.span(), Ident::span);
+            bindings.require_winapi("Stubs without a signature", span)?;
+            if let Some(arg) = args.ident("describe") {
+                return Err(Error::new(
+                    arg.span(),
+                    "`describe` needs the signature of every method, which stubs without one \
+                     don't give",
+                ));
+            }
+        }
+        let helpers = Self::determine_helpers(item);
         let generics = &item.generics;
 
         Ok(ComImpl {
             has_parent,
+            apartment,
+            intercept,
+            unwind,
+            inline,
+            krate,
+            describe,
+            member,
+            bindings,
             self_ty,
             com_ty,
             com_vtbl,
             com_ty_name,
             functions,
+            stubs,
+            helpers,
             generics,
         })
     }
 
-    fn has_parent(args: &AttributeArgs) -> bool {
-        for arg in args {
-            match arg {
-                NestedMeta::Meta(Meta::Word(word)) if word == "no_parent" => return false,
-                _ => continue,
+    fn determine_helpers(item: &ItemImpl) -> Vec<ImplItem> {
+        let helpers = item.items.iter().filter(|item| is_helper(item));
+        helpers
+            .map(|helper| {
+                let mut helper = helper.clone();
+                let not_skip = |attr: &Attribute| !attr.path.is_ident("com_skip");
+                match &mut helper {
+                    ImplItem::Method(method) => method.attrs.retain(not_skip),
+                    ImplItem::Macro(mac) => mac.attrs.retain(not_skip),
+                    _ => {}
+                }
+                helper
+            })
+            .collect()
+    }
+
+    /// The panic behavior of the methods without a `#[panic(...)]`, given by `panic = abort` or
+    /// `panic_result = EXPR`.
+    fn default_panic(args: &ComImplArgs) -> Result<OnPanic, Error> {
+        match (args.value("panic"), args.value("panic_result")) {
+            (Some(_), Some(_)) => Err(Error::new(
+                args.ident("panic_result").unwrap().span(),
+                "`panic = ...` can't be combined with `panic_result = ...`",
+            )),
+            (Some(Expr::Path(path)), None) if path.path.is_ident("abort") => Ok(OnPanic::Abort),
+            (Some(value), None) => Err(Error::new_spanned(value, "Expected `panic = abort`")),
+            (None, Some(expr)) => Ok(OnPanic::Hresult(Box::new(quote! { { #expr } }))),
+            (None, None) => Ok(OnPanic::Nothing),
+        }
+    }
+
+    /// The vtable entries named in `stub(...)`, by their methods' names, or their own.
+    fn determine_stubs(args: &ComImplArgs) -> Result<Vec<Ident>, Error> {
+        let list = match args.list("stub") {
+            Some(list) => list,
+            None => return Ok(Vec::new()),
+        };
+        let names = Punctuated::<Ident, Token![,]>::parse_terminated
+            .parse2(list.clone())
+            .map_err(|e| Error::new(e.span(), format!("Invalid syntax for `stub(...)`: {}", e)))?;
+        names
+            .iter()
+            .map(|name| match pascal_case(&name.to_string()) {
+                _ if name.to_string().starts_with(char::is_uppercase) => Ok(name.clone()),
+                Some(com_name) => Ok(Ident::new(&com_name, name.span())),
+                None => Err(Error::new(
+                    name.span(),
+                    format!(
+                        "`{}` can't be mapped to a COM function name; stub it with \
+                         #[stub] and #[com_name] instead.",
+                        name
+                    ),
+                )),
+            })
+            .collect()
+    }
+
+    fn has_parent(args: &ComImplArgs) -> bool {
+        !args.has_word("no_parent") && !args.has_word("no_iunknown")
+    }
+
+    fn is_apartment(args: &ComImplArgs) -> bool {
+        args.has_word("apartment")
+    }
+
+    fn is_intercept(args: &ComImplArgs) -> bool {
+        args.has_word("intercept")
+    }
+
+    fn is_unwind(args: &ComImplArgs) -> bool {
+        args.has_word("unwind")
+    }
+
+    fn member(args: &ComImplArgs, has_parent: bool) -> Result<Option<Ident>, Error> {
+        let member = match args.value("member") {
+            Some(Expr::Path(path)) if path.qself.is_none() && path.path.segments.len() == 1 => {
+                path.path.segments[0].ident.clone()
+            }
+            Some(value) => {
+                return Err(Error::new_spanned(
+                    value,
+                    "`member` must name a VTable member, e.g. `member = vtbl2`",
+                ))
             }
+            None => return Ok(None),
+        };
+        // The derive only provides IUnknown for secondary vtables
+        if !has_parent {
+            return Err(Error::new(
+                member.span(),
+                "`member = ...` can't be combined with `no_parent` or `no_iunknown`",
+            ));
         }
-        true
+        Ok(Some(member))
     }
 
-    fn com_ty(item: &ItemImpl) -> Result<&Path, String> {
+    fn com_ty(item: &ItemImpl) -> Result<&Path, Error> {
         match &item.trait_ {
             Some((None, path, _)) => Ok(path),
 
-            Some((Some(_bang), _, _)) => Err("Cannot anti-impl a COM interface. (impl !T)".into()),
-            None => Err("You must specify an interface to implement. \
-                         (impl ISomething for MyTy)"
-                .into()),
+            Some((Some(bang), _, _)) => Err(Error::new(
+                bang.spans[0],
+                "Cannot anti-impl a COM interface. (impl !T)",
+            )),
+            None => Err(Error::new_spanned(
+                &item.self_ty,
+                "You must specify an interface to implement. \
+                 (impl ISomething for MyTy)",
+            )),
         }
     }
 
-    fn com_vtbl(com_ty: &Path) -> Path {
-        let mut path = com_ty.clone();
-
-        match path.segments.last_mut() {
-            Some(mut pair) => {
-                let last = pair.value_mut();
-                let new_id = Ident::new(&format!("{}Vtbl", last.ident), last.ident.span());
-                last.ident = new_id;
-            }
-            None => unreachable!(),
+    /// The vtable struct given by `vtbl = ...`, or the one named after `com_ty` by
+    /// `vtbl_name = "..."` or the bindings.
+    fn com_vtbl(args: &ComImplArgs, bindings: Bindings, com_ty: &Path) -> Result<Path, Error> {
+        let vtbl_name = VtblName::from_arg(args.value("vtbl_name"))?;
+        match args.value("vtbl") {
+            Some(vtbl) if vtbl_name.is_some() => Err(Error::new_spanned(
+                vtbl,
+                "`vtbl = ...` can't be combined with `vtbl_name = ...`",
+            )),
+            Some(Expr::Path(path)) if path.qself.is_none() => Ok(path.path.clone()),
+            Some(vtbl) => Err(Error::new_spanned(
+                vtbl,
+                "`vtbl` must name the vtable struct, e.g. `vtbl = IFooVtbl`",
+            )),
+            None => match vtbl_name {
+                Some(vtbl_name) => Ok(vtbl_name.vtbl_path(com_ty)),
+                None => Ok(bindings.vtbl_name().vtbl_path(com_ty)),
+            },
         }
-
-        path
     }
 
     fn com_ty_name(ty: &Path) -> &Ident {
@@ -158,17 +911,65 @@ impl<'a> ComImpl<'a> {
     }
 }
 
+struct Describe {
+    parent: Option<String>,
+}
+
+impl Describe {
+    fn parse(args: &ComImplArgs, has_parent: bool) -> Result<Option<Describe>, Error> {
+        let describe = args.has_word("describe");
+        let mut parent = match args.value("parent") {
+            Some(Expr::Path(path)) if path.qself.is_none() => path
+                .path
+                .segments
+                .last()
+                .map(|seg| seg.value().ident.to_string()),
+            Some(value) => {
+                return Err(Error::new_spanned(
+                    value,
+                    "`parent` must name an interface, e.g. `parent = IDispatch`",
+                ))
+            }
+            None => None,
+        };
+
+        if !describe {
+            if let Some(arg) = args.ident("parent") {
+                return Err(Error::new(
+                    arg.span(),
+                    "`parent = ...` only applies to #[com_impl(describe)]",
+                ));
+            }
+            return Ok(None);
+        }
+        if !has_parent {
+            parent = None;
+        } else if parent.is_none() {
+            parent = Some("IUnknown".into());
+        }
+        Ok(Some(Describe { parent }))
+    }
+}
+
 struct ComFunction<'a> {
     is_mut: bool,
+    /// The `self` of the receiver, with its span, for the body function to bind.
+    self_token: syn::token::SelfValue,
     is_unsafe: bool,
+    name: &'a Ident,
+    vis: &'a Visibility,
     com_name: Ident,
     panic_behavior: OnPanic,
     abi: String,
     args: Vec<Arg<'a>>,
     ret: &'a ReturnType,
     body: &'a Block,
+    attrs: Vec<&'a Attribute>,
+    cfg: Vec<NestedMeta>,
+    is_stub: bool,
 }
 
+#[derive(Clone)]
 enum OnPanic {
     Nothing,
     Abort,
@@ -183,7 +984,7 @@ impl<'a> ComFunction<'a> {
             (quote! { &* }, quote! { const })
         };
 
-        let abi = &self.abi;
+        let abi = self.stub_abi(context);
         let name = self.stub_name(context.com_ty_name);
         let body_name = self.body_name(context.com_ty_name);
         let args = self.quote_stub_args(context);
@@ -191,20 +992,102 @@ impl<'a> ComFunction<'a> {
         let ret = self.ret;
         let call_body = self.quote_stub_call(
             context,
+            &Ident::new("this", Span::call_site()),
             quote! {
                 let this = #refderef(this as *#ptrkind Self);
                 Self::#body_name(this, #pass)
             },
         );
 
+        let attrs = self.attrs.iter().filter(|attr| is_shared_attr(attr));
+
+        let inline = context.inline;
+
         quote! {
-            #[inline(never)]
+            #(#attrs)*
+            #inline
             unsafe extern #abi fn #name(#args) #ret {
                 #call_body
             }
         }
     }
 
+    /// The stub of a method `merges_body` allows, holding its body. A panic it is set to abort
+    /// on is seen by a guard, which the unwinding drops.
+    fn quote_merged_stub(&self, context: &ComImpl) -> TokenStream {
+        let krate = &context.krate;
+        let (unsafemod, _) = self.quote_modifiers();
+        let abi = self.stub_abi(context);
+        let name = self.stub_name(context.com_ty_name);
+        let args = self.quote_body_args();
+        let ret = self.ret;
+        let body = &self.body;
+        let attrs = &self.attrs;
+        let inline = if attrs.iter().any(|attr| attr.path.is_ident("inline")) {
+            quote! {}
+        } else {
+            context.inline.into_token_stream()
+        };
+        let message = self.abort_message(context);
+        let guard = match self.panic_behavior {
+            OnPanic::Nothing if self.is_unwind(context) => quote! {},
+            OnPanic::Nothing => quote! {
+                use #krate::PanicPolicy as _;
+                let __com_impl_guard =
+                    #krate::AbortGuard::new(Self::__COM_IMPL_PANIC_ABORT, #message);
+            },
+            OnPanic::Abort => quote! {
+                let __com_impl_guard = #krate::AbortGuard::new(true, #message);
+            },
+            OnPanic::Hresult(_) => unreachable!(),
+        };
+
+        quote! {
+            #(#attrs)*
+            #inline
+            #[allow(clippy::too_many_arguments)]
+            #unsafemod extern #abi fn #name(#args) #ret {
+                #guard
+                #body
+            }
+        }
+    }
+
+    /// The stub filling the vtable entry of a method its `#[cfg(...)]` leaves out.
+    fn quote_cfg_fallback(&self, context: &ComImpl) -> TokenStream {
+        if self.cfg.is_empty() || self.is_stub {
+            return quote! {};
+        }
+
+        let cfg = &self.cfg;
+        let stub = self.quote_not_implemented(context);
+        quote! {
+            #[cfg(not(all(#(#cfg),*)))]
+            #stub
+        }
+    }
+
+    /// A stub returning E_NOTIMPL, or nothing for a method without a return value.
+    fn quote_not_implemented(&self, context: &ComImpl) -> TokenStream {
+        let abi = self.stub_abi(context);
+        let name = self.stub_name(context.com_ty_name);
+        let args = self.quote_stub_args(context);
+        let ret = self.ret;
+        let result = match ret {
+            ReturnType::Default => quote! {},
+            ReturnType::Type(..) => context.bindings.e_notimpl(&context.krate),
+        };
+        let inline = context.inline;
+
+        quote! {
+            #[allow(unused_variables)]
+            #inline
+            unsafe extern #abi fn #name(#args) #ret {
+                #result
+            }
+        }
+    }
+
     fn quote_body(&self, context: &ComImpl) -> TokenStream {
         let unsafemod = if self.is_unsafe {
             quote! { unsafe }
@@ -212,25 +1095,150 @@ impl<'a> ComFunction<'a> {
             quote!{}
         };
 
-        let abi = &self.abi;
         let name = self.body_name(context.com_ty_name);
         let args = self.quote_body_args();
         let ret = self.ret;
         let body = &self.body;
+        let attrs = &self.attrs;
+        let inline = if attrs.iter().any(|attr| attr.path.is_ident("inline")) {
+            quote! {}
+        } else {
+            quote! { #[inline(always)] }
+        };
 
+        // A plain Rust fn, since a panic unwinding out of an extern fn aborts before the
+        // stub's catch_unwind can see it. The body takes the arguments the interface dictates,
+        // which can be many.
         quote! {
-            #[inline(always)]
-            #unsafemod extern #abi fn #name(#args) #ret
+            #(#attrs)*
+            #inline
+            #[allow(clippy::too_many_arguments)]
+            #unsafemod fn #name(#args) #ret
             #body
         }
     }
 
-    fn quote_body_args(&self) -> TokenStream {
+    fn has_inherent(&self) -> bool {
+        !matches!(self.vis, Visibility::Inherited)
+    }
+
+    /// The Rust signature of the method, calling the body directly. A method returning an
+    /// HRESULT returns a `Result` instead, with a failure HRESULT as `Err` and the parameters
+    /// marked `#[out]`, which the wrapper provides itself, as `Ok`. The wrapper is as unsafe
+    /// as the method.
+    fn quote_inherent(&self, context: &ComImpl) -> TokenStream {
+        if !self.has_inherent() {
+            return quote! {};
+        }
+
+        let (_, selfarg) = self.quote_modifiers();
+        let unsafemod = if self.is_unsafe {
+            quote! { unsafe }
+        } else {
+            quote! {}
+        };
+        let vis = self.vis;
+        let name = self.name;
+        let body_name = if self.merges_body(context) {
+            self.stub_name(context.com_ty_name)
+        } else {
+            self.body_name(context.com_ty_name)
+        };
+        let (outs, ins): (Vec<_>, Vec<_>) = self.args.iter().partition(|a| a.is_out);
+        let args = ins.iter().map(|a| a.quote_inherent_arg());
+        let pass = self.args.iter().map(|a| {
+            let id = a.inherent_ident();
+            if a.is_out {
+                quote! { #id.as_mut_ptr() }
+            } else {
+                quote! { #id }
+            }
+        });
+        let out_ids = outs.iter().map(|a| a.inherent_ident()).collect::<Vec<_>>();
+        let out_tys = outs.iter().map(|a| a.out_ty().unwrap()).collect::<Vec<_>>();
+        let attrs = self
+            .attrs
+            .iter()
+            .filter(|attr| is_shared_attr(attr) || attr.path.is_ident("doc"));
+
+        let (ret, body) = match self.hresult_ty() {
+            Some(hresult) => {
+                let failed = context
+                    .bindings
+                    .failed(&Ident::new("__com_impl_hr", Span::call_site()));
+                let (out_ty, out) = match (&out_tys[..], &out_ids[..]) {
+                    ([ty], [id]) => (quote! { #ty }, quote! { #id.assume_init() }),
+                    (tys, ids) => (quote! { (#(#tys),*) }, quote! { (#(#ids.assume_init()),*) }),
+                };
+                // `#[out]` promises the body writes the out-parameters whenever it succeeds
+                let ret = quote! { -> ::std::result::Result<#out_ty, #hresult> };
+                let (ids, tys) = (&out_ids, &out_tys);
+                let body = quote! {
+                    #(let mut #ids = ::std::mem::MaybeUninit::<#tys>::uninit();)*
+                    let __com_impl_hr = Self::#body_name(self, #(#pass),*);
+                    if #failed {
+                        ::std::result::Result::Err(__com_impl_hr)
+                    } else {
+                        ::std::result::Result::Ok(#out)
+                    }
+                };
+                (ret, body)
+            }
+            None => {
+                let ret = self.ret;
+                let body = quote! { Self::#body_name(self, #(#pass),*) };
+                (quote! { #ret }, body)
+            }
+        };
+        let body = if !self.is_unsafe && !outs.is_empty() {
+            quote! { unsafe { #body } }
+        } else {
+            body
+        };
+
+        quote! {
+            #(#attrs)*
+            #[inline(always)]
+            #vis #unsafemod fn #name(#selfarg, #(#args),*) #ret {
+                #body
+            }
+        }
+    }
+
+    /// The return type, if it is an HRESULT.
+    fn hresult_ty(&self) -> Option<&Type> {
+        let ty = match self.ret {
+            ReturnType::Type(_, ty) => &**ty,
+            ReturnType::Default => return None,
+        };
+        match ty {
+            Type::Path(path) if path.path.segments.last()?.value().ident == "HRESULT" => Some(ty),
+            _ => None,
+        }
+    }
+
+    fn quote_modifiers(&self) -> (TokenStream, TokenStream) {
+        let unsafemod = if self.is_unsafe {
+            quote! { unsafe }
+        } else {
+            quote! {}
+        };
         let selfarg = if self.is_mut {
             quote! { &mut self }
         } else {
             quote! { &self }
         };
+        (unsafemod, selfarg)
+    }
+
+    fn quote_body_args(&self) -> TokenStream {
+        // The body's own `self`, so it also resolves in a block a macro_rules macro wrote
+        let self_token = &self.self_token;
+        let selfarg = if self.is_mut {
+            quote! { &mut #self_token }
+        } else {
+            quote! { &#self_token }
+        };
 
         let args = self.args.iter().map(|a| a.quote_body_arg());
         quote! {
@@ -240,10 +1248,10 @@ impl<'a> ComFunction<'a> {
     }
 
     fn quote_stub_args(&self, context: &ComImpl) -> TokenStream {
-        let com_ty = context.com_ty;
+        let this_ty = context.bindings.this_ty(context.com_ty);
         let args = self.args.iter().map(|a| a.quote_stub_arg());
         quote! {
-            this: *mut #com_ty,
+            this: #this_ty,
             #(#args),*
         }
     }
@@ -255,42 +1263,132 @@ impl<'a> ComFunction<'a> {
         }
     }
 
-    fn quote_stub_call(&self, context: &ComImpl, inner: TokenStream) -> TokenStream {
-        match &self.panic_behavior {
-            OnPanic::Nothing => inner,
+    fn quote_stub_call(&self, context: &ComImpl, ptr: &Ident, inner: TokenStream) -> TokenStream {
+        let krate = &context.krate;
+        // Interceptors run on the apartment's thread, like the body they wrap
+        let inner = if context.intercept {
+            let interface = context.com_ty_name.to_string();
+            let method = self.com_name.to_string();
+            quote! {
+                let __com_impl_call = #krate::intercept::Call {
+                    interface: #interface,
+                    method: #method,
+                    this: #ptr as *const _,
+                };
+                #krate::intercept::invoke(&*(#ptr as *const Self), __com_impl_call, move || {
+                    #inner
+                })
+            }
+        } else {
+            inner
+        };
+
+        let inner = if context.apartment {
+            quote! {
+                let __com_impl_dispatcher =
+                    #krate::apartment::Affine::dispatcher(&*(#ptr as *const Self));
+                #krate::apartment::dispatch(__com_impl_dispatcher, move || {
+                    #inner
+                })
+            }
+        } else {
+            inner
+        };
+
+        let call = match &self.panic_behavior {
+            OnPanic::Nothing if self.is_unwind(context) => inner,
+            // Left to the type, which #[com_panic(abort)] on its derive sets
+            OnPanic::Nothing => {
+                let message = self.abort_message(context);
+                quote! {
+                    use #krate::PanicPolicy as _;
+                    let __com_impl_abort = Self::__COM_IMPL_PANIC_ABORT;
+                    #krate::call_with_panic_policy(__com_impl_abort, #message, move || {
+                        #inner
+                    })
+                }
+            }
             OnPanic::Abort => {
                 let message = self.abort_message(context);
                 quote! {
-                    let result = std::panic::catch_unwind(move || {
+                    let result = ::std::panic::catch_unwind(move || {
                         #inner
                     });
                     match result {
-                        Ok(result) => result,
-                        Err(_) => {
-                            let stderr = std::io::stderr();
-                            let _ = std::io::Write::write_all(&mut stderr.lock(), #message);
-                            std::process::abort();
-                        }
+                        ::std::result::Result::Ok(result) => result,
+                        ::std::result::Result::Err(_) => #krate::abort_on_panic(#message),
                     }
                 }
             }
-            OnPanic::Hresult(expr) => quote! {
-                let __com_impl_result = std::panic::catch_unwind(move || {
-                    #inner
-                });
-                match __com_impl_result {
-                    Ok(result) => result,
-                    Err(_) => #expr
+            // A payload of the return type, e.g. from panic_any(E_INVALIDARG), is returned as is
+            OnPanic::Hresult(expr) => {
+                let interface = context.com_ty_name.to_string();
+                let method = self.com_name.to_string();
+                quote! {
+                    let __com_impl_result = ::std::panic::catch_unwind(move || {
+                        #inner
+                    });
+                    match __com_impl_result {
+                        ::std::result::Result::Ok(result) => result,
+                        ::std::result::Result::Err(payload) => match payload.downcast() {
+                            ::std::result::Result::Ok(result) => *result,
+                            ::std::result::Result::Err(payload) => {
+                                #krate::report_panic(#interface, #method, &*payload);
+                                #expr
+                            }
+                        },
+                    }
                 }
-            },
+            }
+        };
+
+        // Stubs in a secondary vtable are called with a pointer to that VTable member
+        let call = match &context.member {
+            Some(member) => {
+                let this_ty = context.bindings.raw_this_ty(context.com_ty);
+                let offset = member_offset(member);
+                quote! {
+                    let #ptr = (#ptr as *mut u8).sub(Self::#offset) as #this_ty;
+                    #call
+                }
+            }
+            None => call,
+        };
+        let raw_this = context.bindings.raw_this(ptr);
+        quote! {
+            #raw_this
+            #call
+        }
+    }
+
+    fn quote_method_info(&self, krate: &Path) -> TokenStream {
+        let name = self.com_name.to_string();
+        let ret = match self.ret {
+            ReturnType::Default => "void".to_string(),
+            ReturnType::Type(_, ty) => c_type(ty),
+        };
+        let params = self.args.iter().enumerate().map(|(i, arg)| {
+            let ty = c_type(arg.ty);
+            let name = match arg.pat {
+                Some(Pat::Ident(pat)) => pat.ident.to_string(),
+                _ => format!("arg{}", i),
+            };
+            quote! { #krate::header::ParamInfo { name: #name, ty: #ty } }
+        });
+
+        quote! {
+            #krate::header::MethodInfo {
+                name: #name,
+                ret: #ret,
+                params: &[#(#params),*],
+            }
         }
     }
 
     // ----------------------------------------------------------------
 
     fn stub_name(&self, com_ty_name: &Ident) -> Ident {
-        let name = format!("__com_impl_stub__{}__{}", com_ty_name, self.com_name);
-        Ident::new(&name, com_ty_name.span())
+        stub_name(com_ty_name, &self.com_name)
     }
 
     fn body_name(&self, com_ty_name: &Ident) -> Ident {
@@ -298,15 +1396,66 @@ impl<'a> ComFunction<'a> {
         Ident::new(&name, com_ty_name.span())
     }
 
-    fn quote_vtbl_entry(&self, com_ty_name: &Ident) -> TokenStream {
+    fn quote_vtbl_entry(&self, context: &ComImpl) -> TokenStream {
         let com_name = &self.com_name;
-        let stub_name = self.stub_name(com_ty_name);
+        let stub_name = self.stub_name(context.com_ty_name);
+        if !self.merges_body(context) {
+            return quote! {
+                #com_name: Self::#stub_name
+            };
+        }
 
+        // The merged stub takes `self` where the entry passes `this`, the same pointer
+        let (unsafemod, _) = self.quote_modifiers();
+        let abi = self.stub_abi(context);
+        let self_ty = if self.is_mut {
+            quote! { &mut Self }
+        } else {
+            quote! { &Self }
+        };
+        let this_ty = context.bindings.this_ty(context.com_ty);
+        let tys = &self.args.iter().map(|a| a.ty).collect::<Vec<_>>();
+        let ret = self.ret;
         quote! {
-            #com_name: Self::#stub_name
+            #com_name: unsafe {
+                ::std::mem::transmute::<
+                    #unsafemod extern #abi fn(#self_ty, #(#tys),*) #ret,
+                    unsafe extern #abi fn(#this_ty, #(#tys),*) #ret,
+                >(Self::#stub_name)
+            }
+        }
+    }
+
+    /// Whether the stub is the method itself rather than a call to its body, which needs it to
+    /// take `this` as it is and not to catch a panic. A `#[panic(result = ...)]`, a `member`,
+    /// `intercept` or `apartment`, or a `#[cfg]` keeps the body separate.
+    fn merges_body(&self, context: &ComImpl) -> bool {
+        let catches = match self.panic_behavior {
+            OnPanic::Hresult(_) => true,
+            OnPanic::Nothing | OnPanic::Abort => false,
+        };
+        !self.is_stub
+            && !catches
+            && self.cfg.is_empty()
+            && context.member.is_none()
+            && !context.intercept
+            && !context.apartment
+    }
+
+    /// The ABI of the stub, which `#[com_impl(unwind)]` turns into its `-unwind` variant. The
+    /// vtable has to declare the entry with it as well, so callers expect the unwinding.
+    fn stub_abi(&self, context: &ComImpl) -> String {
+        if context.unwind && !self.abi.ends_with("-unwind") {
+            format!("{}-unwind", self.abi)
+        } else {
+            self.abi.clone()
         }
     }
 
+    fn is_unwind(&self, context: &ComImpl) -> bool {
+        self.stub_abi(context).ends_with("-unwind")
+    }
+
     fn abort_message(&self, context: &ComImpl) -> syn::LitByteStr {
         syn::LitByteStr::new(
             &format!(
@@ -320,153 +1469,200 @@ impl<'a> ComFunction<'a> {
 
     // ----------------------------------------------------------------
 
-    fn parse_all(item: &'a ItemImpl) -> Result<Vec<Self>, String> {
+    /// The methods of the block, and the names of those stubbed without a signature.
+    fn parse_all(
+        item: &'a ItemImpl,
+        default_panic: &OnPanic,
+    ) -> Result<(Vec<Self>, Vec<Ident>), Error> {
         let mut fns = Vec::new();
+        let mut stubs = Vec::new();
 
         for item in &item.items {
             let item = match item {
+                _ if is_helper(item) => continue,
                 ImplItem::Method(method) => method,
-                _ => return Err("Only methods may be in a com_impl body".into()),
+                _ => return Err(not_a_method(item)),
             };
 
-            fns.push(Self::parse(item)?);
+            let is_stub = Self::determine_stub(item)?;
+            if is_stub && item.sig.decl.inputs.is_empty() {
+                stubs.push(Self::determine_name(item)?);
+                continue;
+            }
+            fns.push(Self::parse(item, is_stub, default_panic)?);
         }
 
-        Ok(fns)
+        Ok((fns, stubs))
     }
 
-    fn parse(item: &'a ImplItemMethod) -> Result<Self, String> {
+    fn parse(
+        item: &'a ImplItemMethod,
+        is_stub: bool,
+        default_panic: &OnPanic,
+    ) -> Result<Self, Error> {
         Self::validate_sig(item)?;
 
-        let is_mut = Self::determine_mut(item)?;
+        let (is_mut, self_token) = Self::determine_receiver(item)?;
         let is_unsafe = Self::determine_unsafe(item);
+        let name = &item.sig.ident;
+        let vis = &item.vis;
         let com_name = Self::determine_name(item)?;
-        let panic_behavior = Self::determine_panic_behavior(item)?;
+        let panic_behavior = Self::determine_panic_behavior(item, default_panic)?;
         let abi = Self::determine_abi(item);
         let args = Self::parse_args(item)?;
         let ret = &item.sig.decl.output;
         let body = &item.block;
+        let attrs = item
+            .attrs
+            .iter()
+            .filter(|attr| !is_own_attr(attr))
+            .collect();
+        let cfg = Self::determine_cfg(item)?;
 
-        Ok(ComFunction {
+        let function = ComFunction {
             is_mut,
+            self_token,
             is_unsafe,
+            name,
+            vis,
             com_name,
             panic_behavior,
             abi,
             args,
             ret,
             body,
-        })
+            attrs,
+            cfg,
+            is_stub,
+        };
+        if function.args.iter().any(|a| a.is_out) && function.hresult_ty().is_none() {
+            return Err(Error::new(
+                name.span(),
+                "A method with #[out] parameters must return an HRESULT, which tells the \
+                 inherent wrapper whether they were written",
+            ));
+        }
+        Ok(function)
+    }
+
+    /// Whether the method is marked `#[stub]`, which only a declaration may be.
+    fn determine_stub(item: &ImplItemMethod) -> Result<bool, Error> {
+        let attr = match item.attrs.iter().find(|attr| attr.path.is_ident("stub")) {
+            Some(attr) => attr,
+            None => return Ok(false),
+        };
+        if !attr.tts.is_empty() {
+            return Err(Error::new_spanned(attr, "#[stub] takes no arguments."));
+        }
+        if !item.block.stmts.is_empty() {
+            return Err(Error::new_spanned(
+                &item.block,
+                "A #[stub] method is declared without a body, e.g. `#[stub] fn do_verb();`",
+            ));
+        }
+        if let Some(dispid) = item.attrs.iter().find(|attr| attr.path.is_ident("dispid")) {
+            return Err(Error::new_spanned(
+                dispid,
+                "A #[stub] method has no body for IDispatch to call.",
+            ));
+        }
+        Ok(true)
+    }
+
+    /// The predicates of the method's `#[cfg(...)]` attributes.
+    fn determine_cfg(item: &ImplItemMethod) -> Result<Vec<NestedMeta>, Error> {
+        let mut cfg = Vec::new();
+        for attr in &item.attrs {
+            if !attr.path.is_ident("cfg") {
+                continue;
+            }
+
+            match attr.parse_meta()? {
+                Meta::List(list) if list.nested.len() == 1 => cfg.extend(list.nested),
+                _ => {
+                    return Err(Error::new_spanned(
+                        attr,
+                        "Expected a single predicate, as in #[cfg(feature = \"...\")]",
+                    ))
+                }
+            }
+        }
+        Ok(cfg)
     }
 
-    fn determine_mut(item: &ImplItemMethod) -> Result<bool, String> {
+    /// Whether the method takes `&mut self`, and its `self`.
+    fn determine_receiver(item: &ImplItemMethod) -> Result<(bool, syn::token::SelfValue), Error> {
         let first_arg = item.sig.decl.inputs.first().map(|p| *p.value());
         let arg = match first_arg {
             Some(FnArg::SelfRef(arg)) => arg,
             _ => {
-                return Err(format!(
-                    "A COM method must take `self` by ref. (fn {})",
-                    item.sig.ident.to_string()
+                return Err(Error::new(
+                    item.sig.ident.span(),
+                    format!(
+                        "A COM method must take `self` by ref. (fn {})",
+                        item.sig.ident
+                    ),
                 ))
             }
         };
 
-        Ok(arg.mutability.is_some())
+        Ok((arg.mutability.is_some(), arg.self_token))
     }
 
     fn determine_unsafe(item: &ImplItemMethod) -> bool {
         item.sig.unsafety.is_some()
     }
 
-    fn determine_name(item: &ImplItemMethod) -> Result<Ident, String> {
-        // First check for a #[com_name = "..."] attribute
+    fn determine_name(item: &ImplItemMethod) -> Result<Ident, Error> {
+        // First check for a #[com_name = ...] attribute
         for attr in &item.attrs {
             if attr.path.segments.len() == 1 && attr.path.segments[0].ident == "com_name" {
-                let meta = attr.parse_meta().map_err(|e| e.to_string())?;
-                match &meta {
-                    Meta::NameValue(MetaNameValue {
-                        lit: Lit::Str(name),
-                        ..
-                    }) => return Ok(Ident::new(&name.value(), name.span())),
-                    _ => return Err("Invalid syntax for #[com_name] attribute".into()),
-                }
-            } else if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "panic" {
-                return Err(format!(
-                    "Invalid attribute `#[{}]` on COM method",
-                    attr.path.clone().into_token_stream()
-                ));
+                let attr: ComNameAttr = attr::parse(attr)?;
+                return Ok(attr.name);
             }
         }
 
         // Now try to convert the name from the method name
-        let orig_name = item.sig.ident.to_string();
-        let mut is_start = true;
-        let mut name = String::with_capacity(orig_name.len());
-        for c in orig_name.chars() {
-            match c {
-                '0'..='9' => name.push(c),
-                'A'..='Z' => name.push(c),
-                'a'..='z' if !is_start => name.push(c),
-                'a'..='z' if is_start => {
-                    name.push(c.to_ascii_uppercase());
-                    is_start = false;
-                }
-                '_' => is_start = true,
-                _ => {
-                    return Err(
-                        "Identifier ({}) that wouldn't be used in a COM function name found. \
-                         Please use #[com_name] to specify the function it maps to explicitly."
-                            .into(),
-                    )
-                }
-            }
-        }
+        let name = pascal_case(&item.sig.ident.to_string()).ok_or_else(|| {
+            Error::new(
+                item.sig.ident.span(),
+                format!(
+                    "Identifier ({}) that wouldn't be used in a COM function name found. \
+                     Please use #[com_name] to specify the function it maps to explicitly.",
+                    item.sig.ident
+                ),
+            )
+        })?;
 
         Ok(Ident::new(&name, item.sig.ident.span()))
     }
 
-    fn determine_panic_behavior(item: &ImplItemMethod) -> Result<OnPanic, String> {
+    fn determine_panic_behavior(
+        item: &ImplItemMethod,
+        default_panic: &OnPanic,
+    ) -> Result<OnPanic, Error> {
         for attr in &item.attrs {
             if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "panic" {
                 continue;
             }
 
-            let meta = attr.parse_meta().map_err(|e| e.to_string())?;
-            let attr = match &meta {
-                Meta::List(list) if list.nested.len() == 1 => &list.nested[0],
-                _ => {
-                    return Err("Incorrect syntax for #[panic]. \
-                                See documentation for #[com_impl]"
-                        .into())
-                }
-            };
-
-            match attr {
-                NestedMeta::Meta(Meta::Word(id)) if id == "abort" => {
-                    return Ok(OnPanic::Abort);
-                }
-                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
-                    ident,
-                    lit: Lit::Str(lit),
-                    ..
-                })) if ident == "result" => {
-                    let expr: Expr = match syn::parse_str(&lit.value()) {
-                        Ok(expr) => expr,
-                        Err(e) => return Err(format!("Error parsing #[panic] attribute: {}", e)),
-                    };
-
-                    let expr = quote_spanned!{lit.span()=> { #expr }};
-                    return Ok(OnPanic::Hresult(Box::new(expr)));
-                }
-                _ => {
-                    return Err("Incorrect syntax for #[panic]. \
-                                See documentation for #[com_impl]."
-                        .into())
-                }
-            }
+            let parsed: PanicAttr = attr::parse(attr).map_err(|e| {
+                Error::new(
+                    e.span(),
+                    format!("{}. See documentation for #[com_impl].", e),
+                )
+            })?;
+            return Ok(match parsed {
+                PanicAttr::Abort => OnPanic::Abort,
+                PanicAttr::Result(expr) => OnPanic::Hresult(Box::new(quote! { { #expr } })),
+            });
         }
 
-        Ok(OnPanic::Nothing)
+        // A method without a return value has nothing to return from the block's default
+        match (default_panic, &item.sig.decl.output) {
+            (OnPanic::Hresult(_), ReturnType::Default) => Ok(OnPanic::Abort),
+            (default_panic, _) => Ok(default_panic.clone()),
+        }
     }
 
     fn determine_abi(item: &ImplItemMethod) -> String {
@@ -481,34 +1677,70 @@ impl<'a> ComFunction<'a> {
         }
     }
 
-    fn parse_args(item: &ImplItemMethod) -> Result<Vec<Arg>, String> {
-        item.sig
+    fn parse_args(item: &ImplItemMethod) -> Result<Vec<Arg>, Error> {
+        let mut args = item
+            .sig
             .decl
             .inputs
             .iter()
             .skip(1)
             .enumerate()
             .map(|(i, arg)| Arg::parse(i, arg))
-            .collect()
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for attr in &item.attrs {
+            if !attr.path.is_ident("out") {
+                continue;
+            }
+            let attr: OutAttr = attr::parse(attr)?;
+            for param in attr.params {
+                let arg = args.iter_mut().find(|a| *a.inherent_ident() == param);
+                let arg = arg.ok_or_else(|| {
+                    Error::new(param.span(), format!("No parameter named `{}`", param))
+                })?;
+                if arg.out_ty().is_none() {
+                    return Err(Error::new_spanned(
+                        arg.ty,
+                        "An #[out] parameter must be a `*mut T` the method writes to",
+                    ));
+                }
+                arg.is_out = true;
+            }
+        }
+        Ok(args)
     }
 
-    fn validate_sig(item: &ImplItemMethod) -> Result<(), String> {
-        if item.sig.decl.variadic.is_some() {
-            return Err("Variadic methods are not allowed in COM".into());
+    fn validate_sig(item: &ImplItemMethod) -> Result<(), Error> {
+        let decl = &item.sig.decl;
+        if let Some(variadic) = &decl.variadic {
+            return Err(Error::new_spanned(
+                variadic,
+                "Variadic methods are not allowed in COM",
+            ));
         }
-        if item.sig.decl.generics.params.len() > 0 {
-            return Err(
-                "Generic types and lifetime parameters are not allowed on COM methods.".into(),
-            );
+        if decl.generics.params.len() > 0 {
+            return Err(Error::new_spanned(
+                &decl.generics.params,
+                "Generic types and lifetime parameters are not allowed on COM methods.",
+            ));
         }
-        if item.sig.decl.generics.where_clause.is_some() {
-            return Err("Where clauses are not allowed on COM methods.".into());
+        if let Some(where_clause) = &decl.generics.where_clause {
+            return Err(Error::new_spanned(
+                where_clause,
+                "Where clauses are not allowed on COM methods.",
+            ));
         }
-        if item.sig.constness.is_some() {
-            return Err("COM methods may not be const fns".into());
+        if let Some(constness) = &item.sig.constness {
+            return Err(Error::new(
+                constness.span,
+                "COM methods may not be const fns",
+            ));
         }
-        if item.sig.asyncness.is_some() {
-            return Err("COM methods may not be async fns".into());
+        if let Some(asyncness) = &item.sig.asyncness {
+            return Err(Error::new(
+                asyncness.span,
+                "COM methods may not be async fns",
+            ));
         }
 
         Ok(())
@@ -519,6 +1751,8 @@ struct Arg<'a> {
     ty: &'a Type,
     pat: Option<&'a Pat>,
     id: Ident,
+    /// Marked `#[out]`, so the inherent wrapper returns it rather than taking it.
+    is_out: bool,
 }
 
 impl<'a> Arg<'a> {
@@ -530,6 +1764,29 @@ impl<'a> Arg<'a> {
         }
     }
 
+    fn quote_inherent_arg(&self) -> TokenStream {
+        let ty = self.ty;
+        let id = self.inherent_ident();
+        quote! { #id : #ty }
+    }
+
+    /// Simple argument names are kept for the inherent wrapper so they show up nicely in docs,
+    /// anything else falls back to the generated name.
+    fn inherent_ident(&self) -> &Ident {
+        match self.pat {
+            Some(Pat::Ident(pat)) if pat.subpat.is_none() && pat.by_ref.is_none() => &pat.ident,
+            _ => &self.id,
+        }
+    }
+
+    /// What a `*mut T` parameter points to.
+    fn out_ty(&self) -> Option<&Type> {
+        match self.ty {
+            Type::Ptr(ptr) if ptr.mutability.is_some() => Some(&ptr.elem),
+            _ => None,
+        }
+    }
+
     fn quote_stub_arg(&self) -> TokenStream {
         let ty = self.ty;
         let id = &self.id;
@@ -538,19 +1795,132 @@ impl<'a> Arg<'a> {
 
     // ----------------------------------------------------------------
 
-    fn parse(i: usize, arg: &'a FnArg) -> Result<Self, String> {
+    fn parse(i: usize, arg: &'a FnArg) -> Result<Self, Error> {
         match arg {
             FnArg::Captured(cap) => Ok(Arg {
                 ty: &cap.ty,
                 pat: Some(&cap.pat),
                 id: Ident::new(&format!("__com_arg_{}", i), Span::call_site()),
+                is_out: false,
             }),
             FnArg::Ignored(ty) => Ok(Arg {
                 ty: ty,
                 pat: None,
                 id: Ident::new(&format!("__com_arg_{}", i), Span::call_site()),
+                is_out: false,
             }),
-            _ => return Err("Invalid argument syntax for COM function.".into()),
+            _ => {
+                return Err(Error::new_spanned(
+                    arg,
+                    "Invalid argument syntax for COM function.",
+                ))
+            }
         }
     }
 }
+
+/// The C spelling of a method parameter or return type, for generated headers. winapi names
+/// its types after the SDK, so paths map to their last segment.
+fn c_type(ty: &Type) -> String {
+    match ty {
+        Type::Ptr(ptr) => {
+            let pointee = c_type(&ptr.elem);
+            match (ptr.const_token.is_some(), pointee.ends_with('*')) {
+                (true, false) => format!("const {}*", pointee),
+                (true, true) => format!("{} const*", pointee),
+                (false, _) => format!("{}*", pointee),
+            }
+        }
+        Type::Reference(reference) => {
+            let pointee = c_type(&reference.elem);
+            if reference.mutability.is_some() {
+                format!("{}*", pointee)
+            } else {
+                format!("const {}*", pointee)
+            }
+        }
+        Type::Paren(paren) => c_type(&paren.elem),
+        Type::Group(group) => c_type(&group.elem),
+        Type::Path(path) if path.qself.is_none() => {
+            let ident = match path.path.segments.last() {
+                Some(last) => last.value().ident.to_string(),
+                None => unreachable!(),
+            };
+            let mapped = match &ident[..] {
+                "c_void" => "void",
+                "u8" => "UINT8",
+                "u16" => "UINT16",
+                "u32" => "UINT32",
+                "u64" => "UINT64",
+                "i8" => "INT8",
+                "i16" => "INT16",
+                "i32" => "INT32",
+                "i64" => "INT64",
+                "f32" => "FLOAT",
+                "f64" => "DOUBLE",
+                "usize" => "SIZE_T",
+                "isize" => "SSIZE_T",
+                other => other,
+            };
+            mapped.to_string()
+        }
+        other => other.into_token_stream().to_string(),
+    }
+}
+
+/// The name of the vtable entry a method implements.
+pub fn com_name(item: &ImplItemMethod) -> Result<Ident, Error> {
+    ComFunction::determine_name(item)
+}
+
+/// The `extern` function implementing the vtable entry `com_name` of `com_ty_name`.
+fn stub_name(com_ty_name: &Ident, com_name: &Ident) -> Ident {
+    let name = format!("__com_impl_stub__{}__{}", com_ty_name, com_name);
+    Ident::new(&name, com_ty_name.span())
+}
+
+/// Converts a snake_case method name to the PascalCase of its COM method, or `None` if it
+/// contains characters a COM name wouldn't.
+pub fn pascal_case(orig_name: &str) -> Option<String> {
+    let mut is_start = true;
+    let mut name = String::with_capacity(orig_name.len());
+    for c in orig_name.chars() {
+        match c {
+            '0'..='9' => name.push(c),
+            'A'..='Z' => name.push(c),
+            'a'..='z' if !is_start => name.push(c),
+            'a'..='z' if is_start => {
+                name.push(c.to_ascii_uppercase());
+                is_start = false;
+            }
+            '_' => is_start = true,
+            _ => return None,
+        }
+    }
+    Some(name)
+}
+
+/// Whether `tokens` name a type or const parameter of `generics`.
+pub fn mentions_generics(generics: &Generics, tokens: TokenStream) -> bool {
+    fn mentions(tokens: TokenStream, params: &[&Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => params.contains(&&ident),
+            TokenTree::Group(group) => mentions(group.stream(), params),
+            _ => false,
+        })
+    }
+    let params = generics.type_params().map(|param| &param.ident);
+    let consts = generics.const_params().map(|param| &param.ident);
+    let params = params.chain(consts).collect::<Vec<_>>();
+    mentions(tokens, &params)
+}
+
+/// The derive's constant holding the offset of a secondary VTable member.
+pub fn member_offset(member: &Ident) -> Ident {
+    Ident::new(&format!("__com_impl__{}__offset", member), member.span())
+}
+
+/// The derive's constant holding the IUnknown entries of a secondary VTable member.
+pub fn member_iunknown(member: &Ident) -> Ident {
+    Ident::new(&format!("__com_impl__{}__IUnknown", member), member.span())
+}
@@ -1,171 +1,2255 @@
-use proc_macro2::TokenStream;
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::spanned::Spanned;
 use syn::{
-    Attribute, Data, DeriveInput, Fields, FieldsNamed, GenericArgument, Generics, Ident, Lit, Meta,
-    NestedMeta, Path, PathArguments, Type, TypePath,
+    Attribute, Data, DeriveInput, Error, Expr, Fields, FieldsNamed, GenericArgument, Generics,
+    Ident, Meta, NestedMeta, Path, PathArguments, Type, Visibility,
 };
 
-pub fn expand_derive_com_impl(input: &DeriveInput) -> Result<TokenStream, String> {
+use crate::attr::{
+    self, AllocAttr, BindingsAttr, BuilderAttr, ComCrate, ComNewAttr, ComNewEntry, ComPanicAttr,
+    ConstructorAttr, DelegateAttr, DelegateEntry, Family, FinalReleaseAttr, HotReloadAttr,
+    IUnknownAttr, Inline, InterfaceEntry, InterfacesAttr, LazyAttr, QueryInterfaceAttr,
+    RefcountCheck, SingletonAttr, TearOffAttr, TearOffEntry, VtblNameAttr,
+};
+use crate::bindings::Bindings;
+use crate::vtbl_name::VtblName;
+use crate::com_impl::{member_iunknown, member_offset, mentions_generics};
+
+/// The interfaces answered with the primary vtable, the families among them, and the markers.
+type Interfaces = (Vec<InterfaceEntry>, Vec<Family>, Vec<InterfaceEntry>);
+
+pub fn expand_derive_com_impl(input: &DeriveInput) -> Result<TokenStream, Error> {
     let com_impl = ComImpl::parse(input)?;
     let result = com_impl.quote();
 
     Ok(result)
 }
 
-struct ComImpl<'a> {
-    name: &'a Ident,
-    vtbl_member: &'a Ident,
-    refc_member: &'a Ident,
-    other_members: Vec<Mem<'a>>,
-    interfaces: Vec<Type>,
-    generics: &'a Generics,
-}
+/// `#[derive(ComVtbl)]`, for interfaces that don't derive from IUnknown: only the constructor
+/// filling in the vtable, and the pointer to hand out.
+pub fn expand_derive_com_vtbl(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let name = &input.ident;
+    if !ComImpl::is_repr_c(input) {
+        return Err(Error::new(
+            name.span(),
+            "Your struct *must* be #[repr(C)] for ComVtbl.",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields,
+            _ => {
+                return Err(Error::new(
+                    name.span(),
+                    "ComVtbl will only work with structs with named members.",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "ComVtbl will only work with structs with named members.",
+            ))
+        }
+    };
+
+    let vtbl_member = ComImpl::determine_vtbl_member(fields)?;
+    let vtbl_ty = fields
+        .named
+        .iter()
+        .find(|field| field.ident.as_ref() == Some(vtbl_member))
+        .map(|field| ComImpl::vtbl_generic(&field.ty))
+        .unwrap()?;
+    let vtbl_name = ComImpl::determine_vtbl_name(&input.attrs, Bindings::Winapi)?;
+    let krate = ComImpl::determine_crate(&input.attrs)?;
+    let interface = vtbl_name.interface_of(vtbl_ty).ok_or_else(|| {
+        Error::new_spanned(
+            vtbl_ty,
+            "Could not determine the interface of the VTable member.",
+        )
+    })?;
+    let defaults = ComImpl::determine_default_members(fields)?;
+    let helpers = defaults
+        .iter()
+        .map(|&member| Some(member))
+        .collect::<Vec<_>>();
+    let members = ComImpl::parse_members(fields, vtbl_member, &helpers, &[]);
+    let params = members.iter().map(|m| m.quote_param());
+    let inits = members.iter().map(|m| m.quote_init());
+    let (impgen, tygen, wherec) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impgen #name #tygen #wherec {
+            fn create_callback(#(#params),*) -> Self {
+                #name {
+                    #vtbl_member: <Self as #krate::BuildVTable<_>>::STATIC_VTABLE,
+                    #(#defaults: ::std::default::Default::default(),)*
+                    #(#inits,)*
+                }
+            }
+
+            fn as_callback(&self) -> *mut #interface {
+                self as *const Self as *mut #interface
+            }
+        }
+    })
+}
+
+struct ComImpl<'a> {
+    name: &'a Ident,
+    vis: &'a Visibility,
+    vtbl_member: &'a Ident,
+    refc_member: Option<&'a Ident>,
+    refcount_check: RefcountCheck,
+    panic_abort: bool,
+    inline: Inline,
+    /// The path of com-impl in the generated code, from `#[com_crate]`.
+    krate: Path,
+    weak_refcount: bool,
+    arc: bool,
+    site_member: Option<&'a Ident>,
+    aggregation_member: Option<&'a Ident>,
+    aggregate_member: Option<&'a Ident>,
+    ftm_member: Option<&'a Ident>,
+    tear_offs_member: Option<&'a Ident>,
+    tear_offs: Vec<TearOffEntry>,
+    delegates: Vec<DelegateEntry>,
+    server_lock_member: Option<&'a Ident>,
+    default_members: Vec<&'a Ident>,
+    lazy_members: Vec<LazyMember<'a>>,
+    secondary_members: Vec<Secondary<'a>>,
+    other_members: Vec<Mem<'a>>,
+    interfaces: Vec<InterfaceEntry>,
+    vtbl_interfaces: Vec<Type>,
+    families: Vec<Family>,
+    generics: &'a Generics,
+    fields: &'a FieldsNamed,
+    manual_iunknown: bool,
+    hot_reload_slot: Option<Expr>,
+    class_factory: bool,
+    marshal_by_value: bool,
+    bindings: Bindings,
+    iunknown: IUnknownPaths,
+    query_hooks: QueryInterfaceAttr,
+    final_release: Option<Expr>,
+    alloc: Option<Path>,
+    singleton: Option<Path>,
+    stack: bool,
+    com_new: Vec<ComNewEntry>,
+    builder: Option<Type>,
+    constructor: Constructor,
+}
+
+/// A `#[lazy(...)]` member, holding a `Lazy<T>` created on first use.
+struct LazyMember<'a> {
+    name: &'a Ident,
+    ty: &'a Type,
+    init: Expr,
+}
+
+/// The name and visibility of `create_raw`.
+struct Constructor {
+    name: Ident,
+    vis: Visibility,
+}
+
+impl<'a> ComImpl<'a> {
+    fn quote(&self) -> TokenStream {
+        let create_raw = self.quote_create_raw();
+        let (iunknown_vtbl, iunknown_impl) = if self.manual_iunknown {
+            (quote! {}, quote! {})
+        } else {
+            (self.quote_iunknown_vtbl(), self.quote_iunknown_impl())
+        };
+        let implements = self.quote_implements();
+        let abi_check = self.quote_abi_check();
+        let families = self.quote_families();
+        let secondary = self.quote_secondary();
+        let aggregation = self.quote_aggregation();
+        let co_class = self.quote_co_class();
+        let query_interface = self.quote_query_interface();
+        let builder = self.quote_builder();
+        let lazy = self.quote_lazy();
+        let panic_policy = self.quote_panic_policy();
+
+        quote! {
+            #create_raw
+            #builder
+            #lazy
+            #query_interface
+            #iunknown_vtbl
+            #iunknown_impl
+            #implements
+            #abi_check
+            #families
+            #secondary
+            #aggregation
+            #co_class
+            #panic_policy
+        }
+    }
+
+    /// Shadows the `false` of `com_impl::PanicPolicy` for the `#[com_impl]` blocks of the type.
+    /// A private constant would be passed over for the trait's in blocks in other modules.
+    fn quote_panic_policy(&self) -> TokenStream {
+        if !self.panic_abort {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+
+        quote! {
+            impl #impgen #name #tygen #wherec {
+                #[doc(hidden)]
+                pub const __COM_IMPL_PANIC_ABORT: bool = true;
+            }
+        }
+    }
+
+    fn quote_create_raw(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let params = self.other_members.iter().map(|m| m.quote_param());
+        let params = &params.collect::<Vec<_>>();
+        let Constructor {
+            name: create_raw,
+            vis,
+        } = &self.constructor;
+
+        // Without an allocator, objects live in a Box
+        let alloc = |object: TokenStream| match &self.alloc {
+            Some(alloc) => quote! { <#alloc as #krate::alloc::ComAlloc>::alloc(#object) },
+            None => quote! { ::std::boxed::Box::into_raw(::std::boxed::Box::new(#object)) },
+        };
+
+        let create_aggregated = self.aggregation_member.map(|aggregation| {
+            let alloc = alloc(self.quote_new_object(quote! { outer }));
+            quote! {
+                fn create_raw_aggregated(
+                    outer: *mut #krate::__private::IUnknown,
+                    #(#params),*
+                ) -> *mut #krate::__private::IUnknown {
+                    let object = unsafe { &*#alloc };
+                    object.#aggregation.as_ptr()
+                }
+            }
+        });
+        let object = self.quote_new_object(quote! { ::std::ptr::null_mut() });
+
+        // The reference create_raw returns, as a ComBox, where ImplementsInterface<IUnknown> is
+        // implemented for it
+        let names = self.other_members.iter().map(|m| m.name);
+        let names = &names.collect::<Vec<_>>();
+        let create = if self.has_winapi_iunknown() {
+            quote! {
+                fn create(#(#params),*) -> #krate::ComBox<Self> {
+                    unsafe { #krate::ComBox::adopt(Self::#create_raw(#(#names),*)) }
+                }
+
+                /// Gives the reference to code that stores raw pointers, e.g. a C callback
+                /// registration, which calls `Release` when done or hands the pointer back
+                /// to [`from_raw`](Self::from_raw).
+                fn into_raw(this: #krate::ComBox<Self>) -> *mut #krate::__private::IUnknown {
+                    #krate::ComBox::leak_unknown(this)
+                }
+
+                /// Takes back the reference given away by [`into_raw`](Self::into_raw).
+                ///
+                /// # Safety
+                ///
+                /// `ptr` must come from `into_raw` of this type, and the reference it carried
+                /// must not have been released since. Panics if `ptr` is null.
+                unsafe fn from_raw(
+                    ptr: *mut #krate::__private::IUnknown,
+                ) -> #krate::ComBox<Self> {
+                    #krate::ComBox::adopt(ptr as *mut Self)
+                }
+            }
+        } else {
+            quote! {}
+        };
+        // The #[com_new] constructors, only for interfaces the object implements
+        let com_new = self.com_new.iter().map(|entry| {
+            let (new, interface) = (&entry.name, &entry.interface);
+            quote! {
+                pub fn #new(#(#params),*) -> #krate::ComPtr<#interface> {
+                    let ptr = Self::#create_raw(#(#names),*);
+                    unsafe { <Self as #krate::ImplementsInterface<#interface>>::into_com_ptr(ptr) }
+                }
+            }
+        });
+        let create = quote! {
+            #create
+            #(#com_new)*
+        };
+
+        // A singleton is built at compile time, into the static that holds it
+        if let Some(instance) = &self.singleton {
+            let sync = self.quote_singleton_sync();
+            return quote! {
+                impl #impgen #name #tygen #wherec {
+                    const fn create_static(#(#params),*) -> Self {
+                        #object
+                    }
+
+                    fn global_instance() -> *mut Self {
+                        &#instance as *const Self as *mut Self
+                    }
+                }
+
+                #sync
+            };
+        }
+
+        // A stack object lives in its guard, which checks that the callee let go of it
+        if self.stack {
+            return quote! {
+                impl #impgen #name #tygen #wherec {
+                    fn create_stack(#(#params),*) -> #krate::StackCom<Self> {
+                        unsafe { #krate::StackCom::new(#object) }
+                    }
+                }
+            };
+        }
+
+        // An Arc-backed object hands out the pointer to the Arc's contents, and may hand out
+        // a weak handle to them as well
+        if self.arc {
+            return quote! {
+                impl #impgen #name #tygen #wherec {
+                    #vis fn #create_raw(#(#params),*) -> *mut Self {
+                        ::std::sync::Arc::into_raw(::std::sync::Arc::new(#object)) as *mut Self
+                    }
+
+                    fn create_raw_weak(#(#params),*) -> (*mut Self, #krate::WeakCom<Self>) {
+                        let object = ::std::sync::Arc::new(#object);
+                        let weak = ::std::sync::Arc::downgrade(&object);
+                        let weak = #krate::WeakCom::from_weak(weak);
+                        (::std::sync::Arc::into_raw(object) as *mut Self, weak)
+                    }
+
+                    #create
+                }
+            };
+        }
+
+        let alloc = alloc(object);
+        let create_raw_with = self.quote_create_raw_with();
+        quote! {
+            impl #impgen #name #tygen #wherec {
+                #vis fn #create_raw(#(#params),*) -> *mut Self {
+                    #alloc
+                }
+
+                #create
+
+                #create_raw_with
+
+                #create_aggregated
+            }
+        }
+    }
+
+    /// `Sync` for a `#[singleton]`, so it can live in its static. Its vtables only point to
+    /// the `VTBL` constants, which are immutable and never freed, so sharing the object between
+    /// threads is up to its other members, which must all be `Sync`.
+    fn quote_singleton_sync(&self) -> TokenStream {
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let vtbls = self.secondary_members.iter().map(|s| s.member);
+        let vtbls = vtbls.chain(Some(self.vtbl_member)).collect::<Vec<_>>();
+        let members = self.fields.named.iter().filter(|field| match &field.ident {
+            Some(ident) => !vtbls.contains(&ident),
+            None => true,
+        });
+        let tys = members.map(|field| &field.ty);
+        let predicates = wherec
+            .into_iter()
+            .flat_map(|wherec| wherec.predicates.iter());
+
+        quote! {
+            unsafe impl #impgen ::std::marker::Sync for #name #tygen
+            where
+                #(#predicates,)*
+                #(#tys: ::std::marker::Sync,)*
+            {}
+        }
+    }
+
+    /// `FooBuilder`, taking the parameters of create_raw one at a time.
+    fn quote_builder(&self) -> TokenStream {
+        let krate = &self.krate;
+        let interface = match &self.builder {
+            Some(interface) => interface,
+            None => return quote! {},
+        };
+        let (name, vis, generics) = (self.name, self.vis, self.generics);
+        let (impgen, tygen, wherec) = generics.split_for_impl();
+        let builder = Ident::new(&format!("{}Builder", name), name.span());
+        let create_raw = &self.constructor.name;
+        let names = &self
+            .other_members
+            .iter()
+            .map(|m| m.name)
+            .collect::<Vec<_>>();
+        let tys = &self.other_members.iter().map(|m| m.ty).collect::<Vec<_>>();
+        let setters = self.other_members.iter().map(|m| {
+            let (member, ty) = (m.name, m.ty);
+            let doc = format!("Sets `{}`.", member);
+            quote! {
+                #[doc = #doc]
+                pub fn #member(mut self, #member: #ty) -> Self {
+                    self.#member = ::std::option::Option::Some(#member);
+                    self
+                }
+            }
+        });
+        let missing = names
+            .iter()
+            .map(|member| format!("{}::build: `{}` was not set", builder, member));
+        let doc = format!(
+            "Builds a `{}`, one member at a time. `build` panics if a member wasn't set.",
+            name
+        );
+
+        quote! {
+            #[doc = #doc]
+            #vis struct #builder #generics #wherec {
+                #(#names: ::std::option::Option<#tys>,)*
+                _marker: ::std::marker::PhantomData<fn() -> #name #tygen>,
+            }
+
+            impl #impgen #builder #tygen #wherec {
+                /// A builder with no members set.
+                pub fn new() -> Self {
+                    #builder {
+                        #(#names: ::std::option::Option::None,)*
+                        _marker: ::std::marker::PhantomData,
+                    }
+                }
+
+                #(#setters)*
+
+                /// Creates the object from the members set.
+                pub fn build(self) -> #krate::ComPtr<#interface> {
+                    let ptr = <#name #tygen>::#create_raw(#(self.#names.expect(#missing)),*);
+                    unsafe { #krate::ImplementsInterface::<#interface>::into_com_ptr(ptr) }
+                }
+            }
+
+            impl #impgen ::std::default::Default for #builder #tygen #wherec {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+        }
+    }
+
+    /// The accessors of the `#[lazy(...)]` members, creating the value on the first call.
+    fn quote_lazy(&self) -> TokenStream {
+        if self.lazy_members.is_empty() {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let accessors = self.lazy_members.iter().map(|lazy| {
+            let (member, ty, init) = (lazy.name, lazy.ty, &lazy.init);
+            quote! {
+                fn #member(&self) -> &#ty {
+                    self.#member.get_or_init(|| #init)
+                }
+            }
+        });
+
+        quote! {
+            impl #impgen #name #tygen #wherec {
+                #(#accessors)*
+            }
+        }
+    }
+
+    /// Whether the object's IUnknown is winapi's, which `ComBox` and `ComPtr` speak.
+    fn has_winapi_iunknown(&self) -> bool {
+        self.bindings == Bindings::Winapi && !self.iunknown.is_custom()
+    }
+
+    /// Whether QueryInterface answers the query `from_interface` makes, which compares the
+    /// `TypeId` of the type, and so needs it to be `'static` whatever its parameters.
+    fn answers_type_query(&self) -> bool {
+        self.has_winapi_iunknown() && self.generics.params.is_empty()
+    }
+
+    /// `query_interface::<I>()`, which hands out another interface of the object, and
+    /// `from_interface`, which finds the object behind one. A manual IUnknown's own
+    /// `query_interface` may have an inherent wrapper of the same name, and doesn't answer
+    /// the query `from_interface` makes.
+    fn quote_query_interface(&self) -> TokenStream {
+        let krate = &self.krate;
+        if !self.has_winapi_iunknown() || self.manual_iunknown {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let from_interface = if self.answers_type_query() {
+            quote! {
+                fn from_interface<I: #krate::__private::Interface>(
+                    ptr: &#krate::ComPtr<I>,
+                ) -> ::std::option::Option<&Self> {
+                    unsafe { #krate::from_interface(ptr) }
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        quote! {
+            impl #impgen #name #tygen #wherec {
+                fn query_interface<I: #krate::__private::Interface>(
+                    &self,
+                ) -> ::std::option::Option<#krate::ComPtr<I>> {
+                    let unknown = self as *const Self as *mut #krate::__private::IUnknown;
+                    unsafe { #krate::query_interface(unknown) }
+                }
+
+                #from_interface
+            }
+        }
+    }
+
+    /// The struct literal for a new object, aggregated by `outer` if it has an aggregation
+    /// member.
+    fn quote_new_object(&self, outer: TokenStream) -> TokenStream {
+        let name = self.name;
+        let helpers = self.helper_inits(outer).into_iter().map(|(member, init)| {
+            quote! { #member: #init, }
+        });
+        let inits = self.other_members.iter().map(|m| m.quote_init());
+
+        quote! {
+            #name {
+                #(#helpers)*
+                #(#inits,)*
+            }
+        }
+    }
+
+    /// The members the derive initializes itself, with their values: the vtables, the
+    /// refcount, the helper members and the `#[com_default]` members.
+    fn helper_inits(&self, outer: TokenStream) -> Vec<(&Ident, TokenStream)> {
+        let krate = &self.krate;
+        let vtbl_init = match &self.hot_reload_slot {
+            Some(slot) => quote! { #krate::hot_reload::VTableSlot::vtable(&#slot) },
+            None => quote! { <Self as #krate::BuildVTable<_>>::STATIC_VTABLE },
+        };
+        let mut inits = vec![(self.vtbl_member, vtbl_init)];
+        let defaults = [
+            self.refc_member,
+            self.site_member,
+            self.tear_offs_member,
+            self.server_lock_member,
+            self.ftm_member,
+        ];
+        let defaults = defaults.iter().filter_map(|&member| member);
+        let lazy = self.lazy_members.iter().map(|lazy| lazy.name);
+        for member in defaults
+            .chain(self.default_members.iter().cloned())
+            .chain(lazy)
+        {
+            inits.push((member, quote! { ::std::default::Default::default() }));
+        }
+        if let Some(aggregation) = self.aggregation_member {
+            let init = quote! {
+                #krate::aggregation::Aggregation::new(
+                    Self::__com_impl__NonDelegating__VTABLE,
+                    #outer,
+                )
+            };
+            inits.push((aggregation, init));
+        }
+        for secondary in &self.secondary_members {
+            let init = quote! { <Self as #krate::BuildVTable<_>>::STATIC_VTABLE };
+            inits.push((secondary.member, init));
+        }
+        inits
+    }
+
+    /// `create_raw_with`, which writes the object into its allocation in place.
+    fn quote_create_raw_with(&self) -> TokenStream {
+        let krate = &self.krate;
+        let alloc_uninit = match &self.alloc {
+            Some(alloc) => quote! {
+                let ptr = <#alloc as #krate::alloc::ComAlloc>::alloc_uninit::<Self>();
+            },
+            // The memory of a Box, which frees it on the final Release
+            None => quote! {
+                let layout = ::std::alloc::Layout::new::<Self>();
+                let ptr = ::std::alloc::alloc(layout) as *mut Self;
+                if ptr.is_null() {
+                    ::std::alloc::handle_alloc_error(layout);
+                }
+            },
+        };
+        let writes = self
+            .helper_inits(quote! { ::std::ptr::null_mut() })
+            .into_iter()
+            .map(|(member, init)| {
+                quote! { ::std::ptr::addr_of_mut!((*ptr).#member).write(#init); }
+            });
+
+        quote! {
+            unsafe fn create_raw_with<F: ::std::ops::FnOnce(*mut Self)>(init: F) -> *mut Self {
+                #alloc_uninit
+                #(#writes)*
+                init(ptr);
+                ptr
+            }
+        }
+    }
+
+    fn quote_iunknown_vtbl(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let iunknown_vtbl = self.iunknown_vtbl();
+        // An aggregable object's interfaces go through the controlling unknown
+        let (add_ref, release, query_interface) = if self.aggregation_member.is_some() {
+            (
+                quote! { __com_impl__Delegating__AddRef },
+                quote! { __com_impl__Delegating__Release },
+                quote! { __com_impl__Delegating__QueryInterface },
+            )
+        } else {
+            (
+                quote! { __com_impl__IUnknown__AddRef },
+                quote! { __com_impl__IUnknown__Release },
+                quote! { __com_impl__IUnknown__QueryInterface },
+            )
+        };
+
+        quote! {
+            unsafe impl #impgen #krate::BuildVTable<#iunknown_vtbl> for #name #tygen #wherec {
+                const VTBL: #iunknown_vtbl = #iunknown_vtbl {
+                    AddRef: Self::#add_ref,
+                    Release: Self::#release,
+                    QueryInterface: Self::#query_interface,
+                };
+
+                const STATIC_VTABLE: #krate::VTable<#iunknown_vtbl> =
+                    #krate::VTable::new(&Self::VTBL);
+            }
+        }
+    }
+
+    fn quote_iunknown_impl(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let bindings = self.bindings;
+        let (this_ty, c_void, iid, hresult) = (
+            self.iunknown_this(),
+            self.c_void(),
+            self.iid(),
+            bindings.hresult(krate),
+        );
+        let (s_ok, e_nointerface, e_pointer) = (
+            bindings.s_ok(krate),
+            bindings.e_nointerface(krate),
+            bindings.e_pointer(krate),
+        );
+        let riid = Ident::new("riid", proc_macro2::Span::call_site());
+        let raw_this = bindings.raw_this(&Ident::new("this", proc_macro2::Span::call_site()));
+        let inline = self.inline;
+
+        // A count of u32::MAX is never reached by AddRef, and marks a Release past zero
+        let message =
+            |text: String| syn::LitByteStr::new(text.as_bytes(), proc_macro2::Span::call_site());
+        let overflow = message(format!(
+            "AddRef overflowed the reference count of {}. Aborting!",
+            name
+        ));
+        let underflow = message(format!(
+            "{} was released more often than it was referenced. Aborting!",
+            name
+        ));
+        let (check_add_ref, check_release) = match self.refcount_check {
+            RefcountCheck::Abort => (
+                quote! {
+                    if count == 0 || count == u32::MAX {
+                        #krate::abort_on_panic(#overflow);
+                    }
+                },
+                quote! {
+                    if count == u32::MAX {
+                        #krate::abort_on_panic(#underflow);
+                    }
+                },
+            ),
+            RefcountCheck::Debug => (
+                quote! {
+                    if ::std::cfg!(debug_assertions) && (count == 0 || count == u32::MAX) {
+                        #krate::abort_on_panic(#overflow);
+                    }
+                },
+                quote! {
+                    if ::std::cfg!(debug_assertions) && count == u32::MAX {
+                        #krate::abort_on_panic(#underflow);
+                    }
+                },
+            ),
+            RefcountCheck::None => (quote! {}, quote! {}),
+        };
+
+        // The hook may keep the object, e.g. in a cache, which revives it with an AddRef
+        let final_release = self
+            .final_release
+            .as_ref()
+            .map(|hook| quote! { && #hook(&*ptr) });
+
+        // The guard of a stack object holds a reference until it drops the object itself
+        let free = match &self.alloc {
+            _ if self.stack => quote! { #krate::abort_on_panic(#underflow); },
+            Some(alloc) => quote! { <#alloc as #krate::alloc::ComAlloc>::free(ptr); },
+            None => quote! { ::std::mem::drop(::std::boxed::Box::from_raw(ptr)); },
+        };
+
+        // Without a refcount member, the count is the strong count of the object's Arc, or
+        // stands still for a singleton, which lives as long as the program
+        let (add_own_ref, add_ref_impl, release_impl) = match self.refc_member {
+            None if self.singleton.is_some() => (
+                quote! {},
+                quote! {
+                    let _ = this;
+                    2
+                },
+                quote! {
+                    let _ = this;
+                    1
+                },
+            ),
+            Some(refcount) => (
+                quote! {
+                    #krate::ComRefcount::add_ref(&(*(this as *const Self)).#refcount);
+                },
+                quote! {
+                    let this = &*(this as *const Self);
+                    let count = #krate::ComRefcount::add_ref(&this.#refcount);
+                    #check_add_ref
+                    count
+                },
+                quote! {
+                    let ptr = this as *mut Self;
+                    let count = #krate::ComRefcount::release(&(*ptr).#refcount);
+                    #check_release
+                    if count == 0 #final_release {
+                        // This was the last ref
+                        #free
+                    }
+                    count
+                },
+            ),
+            None => (
+                quote! {
+                    ::std::mem::forget(::std::sync::Arc::clone(&::std::mem::ManuallyDrop::new(
+                        ::std::sync::Arc::from_raw(this as *const Self),
+                    )));
+                },
+                quote! {
+                    let this = ::std::mem::ManuallyDrop::new(
+                        ::std::sync::Arc::from_raw(this as *const Self),
+                    );
+                    ::std::mem::forget(::std::sync::Arc::clone(&this));
+                    let count = ::std::sync::Arc::strong_count(&this) as u32;
+                    #check_add_ref
+                    count
+                },
+                quote! {
+                    let this = ::std::sync::Arc::from_raw(this as *const Self);
+                    let count = (::std::sync::Arc::strong_count(&this) as u32).wrapping_sub(1);
+                    #check_release
+                    // Drops the object if this was the last ref
+                    ::std::mem::drop(this);
+                    count
+                },
+            ),
+        };
+
+        // The pointers handed out release through the controlling unknown, so they have to
+        // be counted there as well
+        let add_ref = if self.aggregation_member.is_some() {
+            quote! { Self::__com_impl__Delegating__AddRef(this); }
+        } else {
+            add_own_ref.clone()
+        };
+
+        let is_equal_iid = self.quote_iid_match(&riid);
+        let type_query_riid = if self.answers_type_query() {
+            quote! { let __com_impl_riid = riid; }
+        } else {
+            quote! {}
+        };
+
+        // The hooks hand out their pointers as they are, with the reference they added
+        let call_hook = |hook: &Expr| {
+            quote! {
+                if let ::std::option::Option::Some(ptr) = #hook(&*(this as *const Self), riid) {
+                    *ppv = ptr;
+                    return #s_ok;
+                }
+            }
+        };
+        let query_hook = self.query_hooks.hook.as_ref().map(&call_hook);
+        let query_hook_fallback = self.query_hooks.fallback.as_ref().map(&call_hook);
+
+        // IObjectWithSite is answered by the helper member, which has its own vtable.
+        let query_site = self.site_member.map(|site| {
+            quote! {
+                else if let ::std::option::Option::Some(ptr) =
+                    (*(this as *const Self)).#site.query(riid)
+                {
+                    #add_ref
+                    *ppv = ptr;
+                    #krate::__private::S_OK
+                }
+            }
+        });
+
+        // Secondary vtables are answered with a pointer to their member
+        let query_secondary = self.secondary_members.iter().map(|secondary| {
+            let member = secondary.member;
+            let is_equal_iid = self.is_iid(&riid, &secondary.interface, None);
+            quote! {
+                else if #is_equal_iid {
+                    let that = &*(this as *const Self);
+                    #add_ref
+                    *ppv = &that.#member as *const _ as *mut #c_void;
+                    #s_ok
+                }
+            }
+        });
+
+        // Tear-offs are created on first use, and answer through their non-delegating IUnknown
+        let query_tear_offs = self.tear_offs.iter().enumerate().map(|(index, entry)| {
+            let tear_offs = self.tear_offs_member.unwrap();
+            let interface = &entry.interface;
+            let ty = &entry.ty;
+            quote! {
+                else if #krate::__private::IsEqualIID(
+                    riid,
+                    &<#interface as #krate::__private::Interface>::uuidof(),
+                ) {
+                    let that = &*(this as *const Self);
+                    that.#tear_offs.query(#index, riid, ppv, || {
+                        <#ty as #krate::tear_off::TearOff<Self>>::create(that, this)
+                    })
+                }
+            }
+        });
+
+        // IMarshal is answered by the free-threaded marshaler, aggregated on first use
+        let query_ftm = self.ftm_member.map(|ftm| {
+            quote! {
+                else if let ::std::option::Option::Some(hr) = (*(this as *const Self)).#ftm.query(
+                    this as *mut #krate::__private::IUnknown,
+                    riid,
+                    ppv,
+                ) {
+                    hr
+                }
+            }
+        });
+
+        // A copy of the object is marshaled by a separate IMarshal, created for each query
+        let query_marshal = if self.marshal_by_value {
+            Some(quote! {
+                else if let ::std::option::Option::Some(hr) = #krate::marshal::query::<Self>(
+                    this as *mut #krate::__private::IUnknown,
+                    riid,
+                    ppv,
+                ) {
+                    hr
+                }
+            })
+        } else {
+            None
+        };
+
+        // The control block of a weak refcount answers IWeakReferenceSource
+        let query_weak = match self.refc_member {
+            Some(refcount) if self.weak_refcount => Some(quote! {
+                else if let ::std::option::Option::Some(hr) =
+                    (*(this as *const Self)).#refcount.query(
+                        this as *mut #krate::__private::IUnknown,
+                        riid,
+                        ppv,
+                    )
+                {
+                    hr
+                }
+            }),
+            _ => None,
+        };
+
+        // Delegated interfaces are answered by the object in their member, counted there
+        let query_delegates = self.delegates.iter().map(|entry| {
+            let interface = &entry.interface;
+            let member = &entry.member;
+            quote! {
+                else if let ::std::option::Option::Some(ptr) =
+                    #krate::delegate::query::<#interface, _>(
+                        &(*(this as *const Self)).#member,
+                        riid,
+                    )
+                {
+                    *ppv = ptr;
+                    #krate::__private::S_OK
+                }
+            }
+        });
+
+        // Anything else is up to the aggregated inner object, if there is one
+        let query_fallback = match self.aggregate_member {
+            Some(aggregate) => quote! {
+                let inner = #krate::aggregation::InnerUnknown::inner_unknown(
+                    &(*(this as *const Self)).#aggregate,
+                );
+                if inner.is_null() {
+                    *ppv = ::std::ptr::null_mut();
+                    #krate::__private::E_NOINTERFACE
+                } else {
+                    (*inner).QueryInterface(riid, ppv)
+                }
+            },
+            None => quote! {
+                *ppv = ::std::ptr::null_mut();
+                #e_nointerface
+            },
+        };
+
+        // An inner object answers IUnknown with its non-delegating one
+        let query_aggregated = self.aggregation_member.map(|aggregation| {
+            quote! {
+                if (*(this as *const Self)).#aggregation.is_aggregated()
+                    && #krate::__private::IsEqualIID(
+                        riid,
+                        &<#krate::__private::IUnknown as #krate::__private::Interface>::uuidof(),
+                    )
+                {
+                    #add_own_ref
+                    let that = &*(this as *const Self);
+                    *ppv = that.#aggregation.as_ptr() as *mut #krate::__private::c_void;
+                    return #krate::__private::S_OK;
+                }
+            }
+        });
+
+        // Under #[com_panic(abort)], a panicking query hook or Drop mustn't unwind either
+        let guard = |method: &str, body: TokenStream| {
+            if !self.panic_abort {
+                return body;
+            }
+            let message = message(format!(
+                "IUnknown::{} of {} panicked. Aborting!",
+                method, name
+            ));
+            quote! {
+                #krate::call_with_panic_policy(true, #message, move || {
+                    #body
+                })
+            }
+        };
+        let add_ref_impl = guard("AddRef", add_ref_impl);
+        let release_impl = guard("Release", release_impl);
+        let query_interface_impl = guard(
+            "QueryInterface",
+            quote! {
+                if ppv.is_null() {
+                    return #e_pointer;
+                }
+                #type_query_riid
+                let riid = &*riid;
+                #query_aggregated
+                #query_hook
+                if #is_equal_iid {
+                    #add_ref
+                    *ppv = this as *mut #c_void;
+                    #s_ok
+                } #(#query_secondary)* #query_site #(#query_tear_offs)* #query_ftm
+                #query_marshal #query_weak #(#query_delegates)* else {
+                    #query_hook_fallback
+                    #query_fallback
+                }
+            },
+        );
+
+        quote! {
+            #[allow(non_snake_case)]
+            impl #impgen #name #tygen #wherec {
+                #inline
+                unsafe extern "system" fn __com_impl__IUnknown__AddRef(
+                    this: #this_ty,
+                ) -> u32 {
+                    #raw_this
+                    #add_ref_impl
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__IUnknown__Release(
+                    this: #this_ty,
+                ) -> u32 {
+                    #raw_this
+                    #release_impl
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__IUnknown__QueryInterface(
+                    this: #this_ty,
+                    riid: *const #iid,
+                    ppv: *mut *mut #c_void,
+                ) -> #hresult {
+                    #raw_this
+                    #query_interface_impl
+                }
+            }
+        }
+    }
+
+    fn quote_implements(&self) -> TokenStream {
+        let krate = &self.krate;
+        // ImplementsInterface is bounded by winapi's Interface trait
+        if self.bindings != Bindings::Winapi {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+
+        // An interface listed twice, e.g. IUnknown, must only be implemented once. Those
+        // given with their IID needn't implement winapi's Interface.
+        let mut seen = Vec::new();
+        let impls = self.interfaces.iter().filter_map(|entry| {
+            if entry.iid.is_some() {
+                return None;
+            }
+            let path = &entry.ty;
+            let key = path.into_token_stream().to_string();
+            if seen.contains(&key) {
+                return None;
+            }
+            seen.push(key);
+            Some(quote! {
+                unsafe impl #impgen #krate::ImplementsInterface<#path> for #name #tygen #wherec {}
+            })
+        });
+        let impls = impls.collect::<Vec<_>>();
+
+        quote! {
+            #(#impls)*
+        }
+    }
+
+    /// Checks that the vtable of each interface QueryInterface hands out the primary vtable
+    /// for is one the type fills in, i.e. the primary vtable or one of its parents, rather
+    /// than one with another layout. The vtable is the type of the interface's `lpVtbl`.
+    fn quote_abi_check(&self) -> TokenStream {
+        let krate = &self.krate;
+        if self.vtbl_interfaces.is_empty() {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let checks = self.vtbl_interfaces.iter().map(|interface| {
+            // Points the unsatisfied bound at the interface rather than at the struct
+            let name = Ident::new(&name.to_string(), interface.span());
+            quote_spanned! { interface.span()=>
+                #krate::check_vtable::<#name #tygen, #interface, _>(|i| i.lpVtbl);
+            }
+        });
+
+        quote! {
+            const _: () = {
+                #[allow(dead_code)]
+                fn __com_impl_check_interfaces #impgen () #wherec {
+                    #(#checks)*
+                }
+            };
+        }
+    }
+
+    fn quote_families(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+
+        let impls = self.families.iter().map(|family| {
+            let base = &family.base;
+            let highest = family.versions.len() as u32 - 1;
+            let versions = family.versions.iter().enumerate().map(|(v, iface)| {
+                let v = v as u32;
+                let iid = quote! { <#iface as #krate::__private::Interface>::uuidof() };
+                quote! { #v => ::std::option::Option::Some(#iid), }
+            });
+
+            quote! {
+                unsafe impl #impgen #krate::InterfaceFamily<#base> for #name #tygen #wherec {
+                    fn highest_supported() -> u32 {
+                        #highest
+                    }
+
+                    fn iid(version: u32) -> ::std::option::Option<#krate::__private::IID> {
+                        match version {
+                            #(#versions)*
+                            _ => ::std::option::Option::None,
+                        }
+                    }
+                }
+            }
+        });
+        let impls = impls.collect::<Vec<_>>();
+
+        quote! {
+            #(#impls)*
+        }
+    }
+
+    fn quote_aggregation(&self) -> TokenStream {
+        let krate = &self.krate;
+        let aggregation = match self.aggregation_member {
+            Some(aggregation) => aggregation,
+            None => return quote! {},
+        };
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let offset_expr = self.quote_offset_of(aggregation);
+        let inline = self.inline;
+
+        quote! {
+            #[allow(non_snake_case, non_upper_case_globals)]
+            impl #impgen #name #tygen #wherec {
+                #[doc(hidden)]
+                const __com_impl__NonDelegating__VTABLE:
+                    #krate::VTable<#krate::__private::IUnknownVtbl> =
+                    #krate::VTable::new(&#krate::__private::IUnknownVtbl {
+                        QueryInterface: Self::__com_impl__NonDelegating__QueryInterface,
+                        AddRef: Self::__com_impl__NonDelegating__AddRef,
+                        Release: Self::__com_impl__NonDelegating__Release,
+                    });
+
+                #[doc(hidden)]
+                const __com_impl__NonDelegating__offset: usize = #offset_expr;
+
+                #inline
+                unsafe extern "system" fn __com_impl__Delegating__AddRef(
+                    this: *mut #krate::__private::IUnknown,
+                ) -> u32 {
+                    match (*(this as *const Self)).#aggregation.outer() {
+                        ::std::option::Option::Some(outer) => (*outer.as_ptr()).AddRef(),
+                        ::std::option::Option::None => Self::__com_impl__IUnknown__AddRef(this),
+                    }
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__Delegating__Release(
+                    this: *mut #krate::__private::IUnknown,
+                ) -> u32 {
+                    match (*(this as *const Self)).#aggregation.outer() {
+                        ::std::option::Option::Some(outer) => (*outer.as_ptr()).Release(),
+                        ::std::option::Option::None => Self::__com_impl__IUnknown__Release(this),
+                    }
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__Delegating__QueryInterface(
+                    this: *mut #krate::__private::IUnknown,
+                    riid: *const #krate::__private::IID,
+                    ppv: *mut *mut #krate::__private::c_void,
+                ) -> #krate::__private::HRESULT {
+                    match (*(this as *const Self)).#aggregation.outer() {
+                        ::std::option::Option::Some(outer) => {
+                            (*outer.as_ptr()).QueryInterface(riid, ppv)
+                        }
+                        ::std::option::Option::None => {
+                            Self::__com_impl__IUnknown__QueryInterface(this, riid, ppv)
+                        }
+                    }
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__NonDelegating__AddRef(
+                    this: *mut #krate::__private::IUnknown,
+                ) -> u32 {
+                    let this = (this as *mut u8).sub(Self::__com_impl__NonDelegating__offset);
+                    Self::__com_impl__IUnknown__AddRef(this as *mut _)
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__NonDelegating__Release(
+                    this: *mut #krate::__private::IUnknown,
+                ) -> u32 {
+                    let this = (this as *mut u8).sub(Self::__com_impl__NonDelegating__offset);
+                    Self::__com_impl__IUnknown__Release(this as *mut _)
+                }
+
+                #inline
+                unsafe extern "system" fn __com_impl__NonDelegating__QueryInterface(
+                    this: *mut #krate::__private::IUnknown,
+                    riid: *const #krate::__private::IID,
+                    ppv: *mut *mut #krate::__private::c_void,
+                ) -> #krate::__private::HRESULT {
+                    let this = (this as *mut u8).sub(Self::__com_impl__NonDelegating__offset);
+                    Self::__com_impl__IUnknown__QueryInterface(this as *mut _, riid, ppv)
+                }
+            }
+        }
+    }
+
+    fn quote_co_class(&self) -> TokenStream {
+        let krate = &self.krate;
+        if !self.class_factory {
+            return quote! {};
+        }
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let create_raw = &self.constructor.name;
+        let defaults = self
+            .other_members
+            .iter()
+            .map(|_| quote! { ::std::default::Default::default() });
+        let defaults = &defaults.collect::<Vec<_>>();
+
+        let aggregated = match self.aggregation_member {
+            Some(_) => quote! {
+                ::std::result::Result::Ok(Self::create_raw_aggregated(outer, #(#defaults),*))
+            },
+            None => quote! {
+                ::std::result::Result::Err(#krate::__private::CLASS_E_NOAGGREGATION)
+            },
+        };
+
+        quote! {
+            unsafe impl #impgen #krate::class_factory::CoClass for #name #tygen #wherec {
+                unsafe fn create_instance(
+                    outer: *mut #krate::__private::IUnknown,
+                ) -> ::std::result::Result<
+                    *mut #krate::__private::IUnknown,
+                    #krate::__private::HRESULT,
+                > {
+                    if outer.is_null() {
+                        let object = Self::#create_raw(#(#defaults),*);
+                        ::std::result::Result::Ok(object as *mut #krate::__private::IUnknown)
+                    } else {
+                        #aggregated
+                    }
+                }
+            }
+        }
+    }
+
+    fn quote_secondary(&self) -> TokenStream {
+        let krate = &self.krate;
+        let name = self.name;
+        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+        let bindings = self.bindings;
+        let (iunknown_vtbl, this_ty, c_void, iid, hresult) = (
+            self.iunknown_vtbl(),
+            self.iunknown_this(),
+            self.c_void(),
+            self.iid(),
+            bindings.hresult(krate),
+        );
+        let raw_this = bindings.raw_this(&Ident::new("this", proc_macro2::Span::call_site()));
+        let inline = self.inline;
+
+        let items = self.secondary_members.iter().map(|secondary| {
+            let member = secondary.member;
+            let offset = member_offset(member);
+            let offset_expr = self.quote_offset_of(member);
+            let iunknown = member_iunknown(member);
+            let stub = |method: &str| {
+                Ident::new(&format!("__com_impl__{}__{}", member, method), member.span())
+            };
+            let (query_interface, add_ref, release) =
+                (stub("QueryInterface"), stub("AddRef"), stub("Release"));
+            let object = quote! { (this as *mut u8).sub(Self::#offset) };
+            let call_query_interface =
+                self.call_iunknown(object.clone(), "QueryInterface", quote! { riid, ppv });
+            let call_add_ref = self.call_iunknown(object.clone(), "AddRef", quote! {});
+            let call_release = self.call_iunknown(object, "Release", quote! {});
+
+            // These forward to the primary vtable, which also covers manual IUnknown
+            quote! {
+                #[doc(hidden)]
+                const #offset: usize = #offset_expr;
+
+                #[doc(hidden)]
+                const #iunknown: #iunknown_vtbl = #iunknown_vtbl {
+                    QueryInterface: Self::#query_interface,
+                    AddRef: Self::#add_ref,
+                    Release: Self::#release,
+                };
+
+                #inline
+                unsafe extern "system" fn #query_interface(
+                    this: #this_ty,
+                    riid: *const #iid,
+                    ppv: *mut *mut #c_void,
+                ) -> #hresult {
+                    #raw_this
+                    #call_query_interface
+                }
+
+                #inline
+                unsafe extern "system" fn #add_ref(this: #this_ty) -> u32 {
+                    #raw_this
+                    #call_add_ref
+                }
+
+                #inline
+                unsafe extern "system" fn #release(this: #this_ty) -> u32 {
+                    #raw_this
+                    #call_release
+                }
+            }
+        });
+        let items = items.collect::<Vec<_>>();
+        if items.is_empty() {
+            return quote! {};
+        }
+
+        quote! {
+            #[allow(non_snake_case, non_upper_case_globals)]
+            impl #impgen #name #tygen #wherec {
+                #(#items)*
+            }
+        }
+    }
+
+    fn iunknown_vtbl(&self) -> TokenStream {
+        match &self.iunknown.vtbl {
+            Some(vtbl) => quote! { #vtbl },
+            None => self.bindings.iunknown_vtbl(&self.krate),
+        }
+    }
+
+    fn iunknown_this(&self) -> TokenStream {
+        let interface = &self.iunknown.interface;
+        match &self.iunknown.vtbl {
+            Some(_) => quote! { *mut #interface },
+            None => self.bindings.iunknown_this(&self.krate),
+        }
+    }
+
+    /// The `c_void` of IUnknown's pointers. winapi's own is only the standard one with its
+    /// `std` feature, which other headers can't rely on.
+    fn c_void(&self) -> TokenStream {
+        match &self.iunknown.vtbl {
+            Some(_) => quote! { ::std::ffi::c_void },
+            None => self.bindings.c_void(&self.krate),
+        }
+    }
+
+    fn iid(&self) -> TokenStream {
+        match &self.iunknown.iid {
+            Some(iid) => quote! { #iid },
+            None => self.bindings.iid(&self.krate),
+        }
+    }
+
+    /// Whether the IID referenced by `riid` is the one of `interface`, or `iid` if given.
+    fn is_iid(&self, riid: &Ident, interface: &Type, iid: Option<&Expr>) -> TokenStream {
+        let iid_ty = self.iid();
+        let iid = match iid {
+            Some(iid) => quote! { #iid },
+            None => self.bindings.iid_of(&self.krate, interface),
+        };
+        let compare = match &self.iunknown.is_equal_iid {
+            Some(is_equal_iid) => quote! { #is_equal_iid(#riid, &iid) },
+            None => self.bindings.compare_iid(&self.krate, riid),
+        };
+
+        quote! {
+            {
+                let iid: #iid_ty = #iid;
+                #compare
+            }
+        }
+    }
+
+    /// Whether the IID referenced by `riid` is one of the interfaces answered with the primary
+    /// vtable. Switches on the first field of the IID, which tells almost all interfaces apart,
+    /// and compares the whole IID in the arm it lands on, with the arms in the order of the
+    /// interfaces. A first field the bindings give as a constant is matched as one, the others,
+    /// e.g. those of winapi's `uuidof()`, in the guard of their arm.
+    fn quote_iid_match(&self, riid: &Ident) -> TokenStream {
+        let krate = &self.krate;
+        let mut is_equal_iid = self
+            .interfaces
+            .iter()
+            .map(|entry| self.is_iid(riid, &entry.ty, entry.iid.as_ref()))
+            .collect::<Vec<_>>();
+        // The query from_interface makes, which reads past the IID it starts with
+        if self.answers_type_query() {
+            is_equal_iid.push(quote! { #krate::is_type_query::<Self>(__com_impl_riid) });
+        }
+
+        // Custom IIDs needn't have the fields of a GUID
+        if self.iunknown.iid.is_some() || self.iunknown.is_equal_iid.is_some() {
+            return quote! { #( #is_equal_iid )||* };
+        }
+
+        let iid_ty = self.iid();
+        let data1 = self.bindings.data1();
+        let equal = self.bindings.equal_iid(krate, riid);
+        let mut consts = Vec::new();
+        let arms = self.interfaces.iter().zip(&is_equal_iid).enumerate();
+        let mut arms = arms
+            .map(|(index, (entry, is_equal_iid))| {
+                // Constants in the function can't name the type's generic parameters
+                let value = match self.bindings.const_data1(krate, &entry.ty) {
+                    Some(value) if entry.iid.is_none() && !self.mentions_generics(&entry.ty) => {
+                        value
+                    }
+                    _ => return quote! { _ if #is_equal_iid => true, },
+                };
+                let name = Ident::new(&format!("__COM_IMPL_DATA1_{}", index), Span::call_site());
+                let iid = self.bindings.iid_of(krate, &entry.ty);
+                consts.push(quote! { const #name: u32 = #value; });
+                quote! {
+                    #name if {
+                        let iid: #iid_ty = #iid;
+                        #equal
+                    } => true,
+                }
+            })
+            .collect::<Vec<_>>();
+        if self.answers_type_query() {
+            let is_type_query = is_equal_iid.last().unwrap();
+            arms.push(quote! { _ if #is_type_query => true, });
+        }
+
+        quote! {
+            {
+                #(#consts)*
+                match #riid.#data1 {
+                    #(#arms)*
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Whether `ty` names one of the type's generic parameters.
+    fn mentions_generics(&self, ty: &Type) -> bool {
+        mentions_generics(self.generics, ty.into_token_stream())
+    }
+
+    /// Calls the IUnknown method `method` of the object `this` points to, through its vtable.
+    fn call_iunknown(&self, this: TokenStream, method: &str, args: TokenStream) -> TokenStream {
+        let vtbl = match &self.iunknown.vtbl {
+            Some(vtbl) => vtbl,
+            None => return self.bindings.call_iunknown(&self.krate, this, method, args),
+        };
+        let interface = &self.iunknown.interface;
+        let method = Ident::new(method, proc_macro2::Span::call_site());
+        quote! {
+            {
+                let this = #this as *mut #interface;
+                ((**(this as *const *const #vtbl)).#method)(this, #args)
+            }
+        }
+    }
+
+    /// The offset of `member` in the `#[repr(C)]` struct, from the sizes and alignments of
+    /// the members before it.
+    fn quote_offset_of(&self, member: &Ident) -> TokenStream {
+        let mut steps = Vec::new();
+        for field in self.fields.named.iter() {
+            let ty = &field.ty;
+            let align = quote! { ::std::mem::align_of::<#ty>() };
+            if field.ident.as_ref() == Some(member) {
+                steps.push(quote! { (offset + #align - 1) & !(#align - 1) });
+                break;
+            }
+            steps.push(quote! {
+                let offset = ((offset + #align - 1) & !(#align - 1)) + ::std::mem::size_of::<#ty>();
+            });
+        }
+
+        quote! {
+            {
+                let offset = 0usize;
+                #(#steps)*
+            }
+        }
+    }
+
+    // ----------------------------------------------------------------
+
+    fn parse(input: &'a DeriveInput) -> Result<Self, Error> {
+        let name = &input.ident;
+        if !Self::is_repr_c(input) {
+            return Err(Error::new(
+                name.span(),
+                "Your struct *must* be #[repr(C)] for ComImpl.",
+            ));
+        }
+
+        let data = match &input.data {
+            Data::Struct(data) => data,
+            _ => {
+                return Err(Error::new(
+                    name.span(),
+                    "ComImpl will only work with structs with named members.",
+                ))
+            }
+        };
+        let fields = match &data.fields {
+            Fields::Named(fields) => fields,
+            _ => {
+                return Err(Error::new(
+                    name.span(),
+                    "ComImpl will only work with structs with named members.",
+                ))
+            }
+        };
+        let attr_span = |name| Self::attr_span(input, name);
+
+        let iunknown_attr = Self::determine_iunknown(&input.attrs)?;
+        let manual_iunknown = iunknown_attr.manual;
+        let hot_reload_slot = Self::determine_hot_reload_slot(&input.attrs)?;
+        let class_factory = Self::is_class_factory(&input.attrs)?;
+        let marshal_by_value = Self::is_marshal_by_value(&input.attrs)?;
+        let bindings = Self::determine_bindings(&input.attrs)?;
+        let vtbl_member = Self::determine_vtbl_member(fields)?;
+        let arc = Self::is_arc(&input.attrs)?;
+        let refc_member = Self::determine_refcount_member(fields)?;
+        if arc && (refc_member.is_some() || manual_iunknown) {
+            return Err(Error::new(
+                attr_span("arc"),
+                "#[arc] counts references in the object's Arc, so it needs the derived IUnknown \
+                 and no Refcount member.",
+            ));
+        }
+        let singleton = Self::determine_singleton(&input.attrs)?;
+        if refc_member.is_none() && !manual_iunknown && !arc && singleton.is_none() {
+            return Err(Error::new(
+                name.span(),
+                "Could not find a com_impl::Refcount member, or one marked #[refcount]",
+            ));
+        }
+        let refcount_check = match Self::determine_refcount_check(&input.attrs)? {
+            Some(_) if manual_iunknown => {
+                return Err(Error::new(
+                    attr_span("refcount_check"),
+                    "#[refcount_check] needs the derived IUnknown.",
+                ));
+            }
+            Some(check) => check,
+            None => RefcountCheck::Debug,
+        };
+        let panic_abort = Self::is_panic_abort(&input.attrs)?;
+        let inline = Self::determine_inline(&input.attrs)?;
+        let krate = Self::determine_crate(&input.attrs)?;
+        let weak_refcount = !manual_iunknown && Self::is_weak_refcount(fields, refc_member);
+        let site_member = Self::determine_site_member(fields, vtbl_member)?;
+        let aggregation_member = Self::determine_aggregation_member(fields);
+        if let Some(member) = aggregation_member {
+            let conflict = if manual_iunknown {
+                Some("An Aggregation member needs the derived IUnknown.")
+            } else if weak_refcount {
+                Some("An aggregable object can't have a WeakRefcount member.")
+            } else if arc {
+                Some("An aggregable object can't be #[arc].")
+            } else {
+                None
+            };
+            if let Some(message) = conflict {
+                return Err(Error::new(member.span(), message));
+            }
+        }
+        let aggregate_member = Self::determine_aggregate_member(fields)?;
+        if let (Some(member), true) = (aggregate_member, manual_iunknown) {
+            return Err(Error::new(
+                member.span(),
+                "#[aggregate] needs the derived IUnknown.",
+            ));
+        }
+        let ftm_member = Self::determine_ftm_member(fields)?;
+        if let (Some(member), true) = (ftm_member, manual_iunknown) {
+            return Err(Error::new(
+                member.span(),
+                "#[ftm] needs the derived IUnknown.",
+            ));
+        }
+        if let Some(member) = ftm_member {
+            if Self::is_local_refcount(fields, refc_member) {
+                return Err(Error::new(
+                    member.span(),
+                    "#[ftm] lets any thread call the object, which a LocalRefcount can't count.",
+                ));
+            }
+        }
+        if marshal_by_value && (ftm_member.is_some() || manual_iunknown) {
+            return Err(Error::new(
+                attr_span("marshal_by_value"),
+                "#[marshal_by_value] needs the derived IUnknown, and answers IMarshal in place \
+                 of an #[ftm] member.",
+            ));
+        }
+        let alloc = Self::determine_alloc(&input.attrs)?;
+        if alloc.is_some() && arc {
+            return Err(Error::new(
+                attr_span("alloc"),
+                "#[arc] objects live in their Arc, not with an allocator.",
+            ));
+        }
+        let final_release = Self::determine_final_release(&input.attrs)?;
+        if final_release.is_some() && (manual_iunknown || arc) {
+            return Err(Error::new(
+                attr_span("final_release"),
+                "#[final_release] needs the derived IUnknown, and an object the Arc of #[arc] \
+                 can't keep.",
+            ));
+        }
+        let query_hooks = Self::determine_query_hooks(&input.attrs)?;
+        if (query_hooks.hook.is_some() || query_hooks.fallback.is_some()) && manual_iunknown {
+            return Err(Error::new(
+                attr_span("query_interface"),
+                "#[query_interface] needs the derived IUnknown.",
+            ));
+        }
+        let tear_offs = Self::determine_tear_offs(&input.attrs)?;
+        let tear_offs_member = Self::determine_tear_offs_member(fields);
+        if !tear_offs.is_empty() && (tear_offs_member.is_none() || manual_iunknown) {
+            return Err(Error::new(
+                attr_span("tear_off"),
+                "#[tear_off] needs a com_impl::tear_off::TearOffs member and the derived \
+                 IUnknown.",
+            ));
+        }
+        let delegates = Self::determine_delegates(&input.attrs, fields)?;
+        if !delegates.is_empty() && manual_iunknown {
+            return Err(Error::new(
+                attr_span("delegate"),
+                "#[delegate] needs the derived IUnknown.",
+            ));
+        }
+        let server_lock_member = Self::determine_server_lock_member(fields);
+        let singleton_conflicts = [
+            refc_member,
+            site_member,
+            aggregation_member,
+            tear_offs_member,
+            server_lock_member,
+            ftm_member,
+        ];
+        if singleton.is_some()
+            && (singleton_conflicts.iter().any(Option::is_some)
+                || manual_iunknown
+                || arc
+                || alloc.is_some()
+                || final_release.is_some()
+                || hot_reload_slot.is_some()
+                || class_factory)
+        {
+            return Err(Error::new(
+                attr_span("singleton"),
+                "A #[singleton] is built by a const fn and never freed, so it can't have a \
+                 refcount or helper members, nor #[arc], #[final_release], #[class_factory], \
+                 `alloc`, `hot_reload` or `iunknown = manual`.",
+            ));
+        }
+        let stack = Self::is_stack(&input.attrs)?;
+        if stack
+            && (aggregation_member.is_some()
+                || weak_refcount
+                || manual_iunknown
+                || arc
+                || singleton.is_some()
+                || alloc.is_some()
+                || final_release.is_some()
+                || class_factory)
+        {
+            return Err(Error::new(
+                attr_span("stack"),
+                "A #[stack] object lives in the guard returned by create_stack, so it can't be \
+                 aggregable or have a WeakRefcount, nor #[arc], #[singleton], #[final_release], \
+                 #[class_factory], `alloc` or `iunknown = manual`.",
+            ));
+        }
+        let vtbl_name = Self::determine_vtbl_name(&input.attrs, bindings)?;
+        let iunknown_span = attr_span("iunknown");
+        let iunknown =
+            Self::iunknown_paths(iunknown_attr, bindings, &krate, &vtbl_name, iunknown_span)?;
+        let constructor = Self::determine_constructor(&input.attrs)?;
+        let named = input
+            .attrs
+            .iter()
+            .any(|attr| attr.path.is_ident("constructor"));
+        if named && (singleton.is_some() || stack) {
+            return Err(Error::new(
+                attr_span("constructor"),
+                "#[singleton] and #[stack] objects have no create_raw to name with \
+                 `constructor(...)`.",
+            ));
+        }
+        let com_new =
+            Self::determine_com_new(&input.attrs, fields, vtbl_member, &krate, &vtbl_name)?;
+        if !com_new.is_empty()
+            && (bindings != Bindings::Winapi
+                || iunknown.is_custom()
+                || singleton.is_some()
+                || stack)
+        {
+            return Err(Error::new(
+                attr_span("com_new"),
+                "#[com_new] returns a ComPtr from create_raw, so it needs winapi's IUnknown, \
+                 and isn't available for #[singleton] or #[stack] objects.",
+            ));
+        }
+        let builder =
+            Self::determine_builder(&input.attrs, fields, vtbl_member, &krate, &vtbl_name)?;
+        if builder.is_some()
+            && (bindings != Bindings::Winapi
+                || iunknown.is_custom()
+                || singleton.is_some()
+                || stack)
+        {
+            return Err(Error::new(
+                attr_span("builder"),
+                "#[builder] returns a ComPtr from create_raw, so it needs winapi's IUnknown, \
+                 and isn't available for #[singleton] or #[stack] objects.",
+            ));
+        }
+        let secondary_members =
+            Self::determine_secondary_members(fields, vtbl_member, bindings, &krate, &vtbl_name)?;
+        let helpers = [
+            refc_member,
+            site_member,
+            aggregation_member,
+            ftm_member,
+            tear_offs_member,
+            server_lock_member,
+        ];
+        let default_members = Self::determine_default_members(fields)?;
+        let lazy_members = Self::determine_lazy_members(fields)?;
+        let lazy = lazy_members.iter().map(|lazy| lazy.name);
+        for member in default_members.iter().cloned().chain(lazy) {
+            let secondary = secondary_members.iter().any(|s| s.member == member);
+            if member == vtbl_member || helpers.contains(&Some(member)) || secondary {
+                return Err(Error::new(
+                    member.span(),
+                    format!(
+                        "`{}` is initialized by the derive already; #[com_default] and #[lazy] \
+                         are for the members passed to create_raw.",
+                        member
+                    ),
+                ));
+            }
+        }
+        if singleton.is_some() && !(default_members.is_empty() && lazy_members.is_empty()) {
+            return Err(Error::new(
+                attr_span("singleton"),
+                "A #[singleton] is built by a const fn, which can't call the \
+                 `Default::default()` of #[com_default] and #[lazy] members.",
+            ));
+        }
+        let mut params_skipped = helpers.to_vec();
+        params_skipped.extend(default_members.iter().map(|&member| Some(member)));
+        params_skipped.extend(lazy_members.iter().map(|lazy| Some(lazy.name)));
+        let other_members =
+            Self::parse_members(fields, vtbl_member, &params_skipped, &secondary_members);
+        let (mut interfaces, families, markers) = Self::determine_interfaces(
+            &input.attrs,
+            fields,
+            vtbl_member,
+            bindings,
+            &krate,
+            &vtbl_name,
+            &iunknown.interface,
+        )?;
+        // Markers share the vtable of whichever interface they're asked through, and those
+        // given with their IID needn't follow winapi's layout
+        let vtbl_interfaces = if bindings == Bindings::Winapi && !iunknown.is_custom() {
+            let listed = interfaces.iter().filter(|entry| entry.iid.is_none());
+            listed.map(|entry| entry.ty.clone()).collect()
+        } else {
+            Vec::new()
+        };
+        for interface in &vtbl_interfaces {
+            let key = interface.into_token_stream().to_string();
+            let secondary = secondary_members
+                .iter()
+                .find(|s| s.interface.clone().into_token_stream().to_string() == key);
+            if let Some(secondary) = secondary {
+                return Err(Error::new_spanned(
+                    interface,
+                    format!(
+                        "{} is answered from the `{}` VTable member, and can't be listed in \
+                         #[interfaces], which are answered from the primary vtable.",
+                        key, secondary.member
+                    ),
+                ));
+            }
+        }
+        interfaces.extend(markers);
+        let generics = &input.generics;
+        if builder.is_some() {
+            let reserved = |m: &&Mem| m.name == "new" || m.name == "build";
+            if let Some(m) = other_members.iter().find(reserved) {
+                return Err(Error::new(
+                    m.name.span(),
+                    format!(
+                        "A member named `{}` would collide with the method of {}Builder.",
+                        m.name, name
+                    ),
+                ));
+            }
+        }
+
+        // The helpers in com-impl speak winapi's types
+        let member_span = |member: Option<&Ident>| member.map(Ident::span);
+        let attr_used = |used: bool, name| if used { Some(attr_span(name)) } else { None };
+        let weak_refcount_member = if weak_refcount { refc_member } else { None };
+        let winapi_only = [
+            (member_span(site_member), "An ObjectWithSite member"),
+            (member_span(aggregation_member), "An Aggregation member"),
+            (member_span(aggregate_member), "#[aggregate]"),
+            (member_span(ftm_member), "#[ftm]"),
+            (
+                attr_used(marshal_by_value, "marshal_by_value"),
+                "#[marshal_by_value]",
+            ),
+            (member_span(weak_refcount_member), "A WeakRefcount member"),
+            (attr_used(!tear_offs.is_empty(), "tear_off"), "#[tear_off]"),
+            (attr_used(!delegates.is_empty(), "delegate"), "#[delegate]"),
+            (attr_used(!families.is_empty(), "interfaces"), "family(...)"),
+            (
+                attr_used(class_factory, "class_factory"),
+                "#[class_factory]",
+            ),
+        ];
+        for &(used, feature) in &winapi_only {
+            if let Some(span) = used {
+                bindings.require_winapi(feature, span)?;
+                if iunknown.is_custom() && feature != "family(...)" {
+                    return Err(Error::new(
+                        span,
+                        format!(
+                            "{} needs winapi's IUnknown, not the one given in #[iunknown(...)].",
+                            feature
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(ComImpl {
+            name,
+            vis: &input.vis,
+            vtbl_member,
+            refc_member,
+            refcount_check,
+            panic_abort,
+            inline,
+            krate,
+            weak_refcount,
+            arc,
+            site_member,
+            aggregation_member,
+            aggregate_member,
+            ftm_member,
+            tear_offs_member,
+            tear_offs,
+            delegates,
+            server_lock_member,
+            default_members,
+            lazy_members,
+            secondary_members,
+            other_members,
+            interfaces,
+            vtbl_interfaces,
+            families,
+            generics,
+            fields,
+            manual_iunknown,
+            hot_reload_slot,
+            class_factory,
+            marshal_by_value,
+            bindings,
+            iunknown,
+            query_hooks,
+            final_release,
+            alloc,
+            singleton,
+            stack,
+            com_new,
+            builder,
+            constructor,
+        })
+    }
+
+    /// The span of the struct's `#[name]` attribute, or of the struct's name if it has none,
+    /// for errors about the attribute.
+    fn attr_span(input: &DeriveInput, name: &str) -> Span {
+        match input.attrs.iter().find(|attr| attr.path.is_ident(name)) {
+            Some(attr) => attr.path.span(),
+            None => input.ident.span(),
+        }
+    }
+
+    fn determine_constructor(attrs: &[Attribute]) -> Result<Constructor, Error> {
+        let mut constructor = Constructor {
+            name: Ident::new("create_raw", proc_macro2::Span::call_site()),
+            vis: Visibility::Inherited,
+        };
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "constructor" {
+                continue;
+            }
+
+            let ConstructorAttr { name, vis } = attr::parse(attr)?;
+            constructor.name = name.unwrap_or(constructor.name);
+            constructor.vis = vis.unwrap_or(constructor.vis);
+            break;
+        }
+        Ok(constructor)
+    }
+
+    /// The `#[com_new]` constructors, where a bare `#[com_new]` is `new` returning the
+    /// interface of the vtable.
+    fn determine_com_new(
+        attrs: &[Attribute],
+        fields: &FieldsNamed,
+        vtbl: &Ident,
+        krate: &Path,
+        vtbl_name: &VtblName,
+    ) -> Result<Vec<ComNewEntry>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "com_new" {
+                continue;
+            }
+
+            let ComNewAttr { entries } = attr::parse(attr)?;
+            if !entries.is_empty() {
+                return Ok(entries);
+            }
+            let interface = Self::vtbl_interface(fields, vtbl, krate, vtbl_name);
+            let interface = interface.ok_or_else(|| {
+                Error::new(
+                    attr.path.span(),
+                    "Could not determine the interface of the VTable member for #[com_new]; \
+                     name it, e.g. #[com_new(new = IFoo)].",
+                )
+            })?;
+            let name = Ident::new("new", proc_macro2::Span::call_site());
+            return Ok(vec![ComNewEntry { name, interface }]);
+        }
+        Ok(Vec::new())
+    }
+
+    /// The interface `FooBuilder::build` returns, by default that of the vtable.
+    fn determine_builder(
+        attrs: &[Attribute],
+        fields: &FieldsNamed,
+        vtbl: &Ident,
+        krate: &Path,
+        vtbl_name: &VtblName,
+    ) -> Result<Option<Type>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "builder" {
+                continue;
+            }
+
+            let BuilderAttr { interface } = attr::parse(attr)?;
+            if interface.is_some() {
+                return Ok(interface);
+            }
+            let interface = Self::vtbl_interface(fields, vtbl, krate, vtbl_name);
+            let interface = interface.ok_or_else(|| {
+                Error::new(
+                    attr.path.span(),
+                    "Could not determine the interface of the VTable member for #[builder]; \
+                     name it, e.g. #[builder(IFoo)].",
+                )
+            })?;
+            return Ok(Some(interface));
+        }
+        Ok(None)
+    }
+
+    /// The winapi interface of the first vtable, from its name.
+    fn vtbl_interface(
+        fields: &FieldsNamed,
+        vtbl: &Ident,
+        krate: &Path,
+        vtbl_name: &VtblName,
+    ) -> Option<Type> {
+        let field = fields
+            .named
+            .iter()
+            .find(|f| f.ident.as_ref() == Some(vtbl))?;
+        let vtbl_ty = Self::vtbl_generic(&field.ty).ok()?;
+        Bindings::Winapi.interface_of_vtbl(krate, vtbl_name, vtbl_ty)
+    }
+
+    fn determine_singleton(attrs: &[Attribute]) -> Result<Option<Path>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "singleton" {
+                continue;
+            }
+
+            let SingletonAttr { instance } = attr::parse(attr)?;
+            return Ok(Some(instance));
+        }
+        Ok(None)
+    }
+
+    fn determine_alloc(attrs: &[Attribute]) -> Result<Option<Path>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "alloc" {
+                continue;
+            }
+
+            let AllocAttr { alloc } = attr::parse(attr)?;
+            return Ok(Some(alloc));
+        }
+        Ok(None)
+    }
+
+    fn determine_final_release(attrs: &[Attribute]) -> Result<Option<Expr>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "final_release" {
+                continue;
+            }
+
+            let FinalReleaseAttr { hook } = attr::parse(attr)?;
+            return Ok(Some(hook));
+        }
+        Ok(None)
+    }
+
+    fn determine_query_hooks(attrs: &[Attribute]) -> Result<QueryInterfaceAttr, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1
+                || attr.path.segments[0].ident != "query_interface"
+            {
+                continue;
+            }
+
+            return attr::parse(attr);
+        }
+        Ok(QueryInterfaceAttr::default())
+    }
+
+    /// Merges `#[iunknown(manual)]` from `#[com_impl(iunknown = manual)]` with the paths
+    /// given in `#[iunknown(...)]`.
+    fn determine_iunknown(attrs: &[Attribute]) -> Result<IUnknownAttr, Error> {
+        let mut merged = IUnknownAttr::default();
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "iunknown" {
+                continue;
+            }
+
+            let IUnknownAttr {
+                manual,
+                vtbl,
+                iid,
+                is_equal_iid,
+            } = attr::parse(attr)?;
+            merged.manual |= manual;
+            merged.vtbl = vtbl.or(merged.vtbl);
+            merged.iid = iid.or(merged.iid);
+            merged.is_equal_iid = is_equal_iid.or(merged.is_equal_iid);
+        }
+        Ok(merged)
+    }
 
-impl<'a> ComImpl<'a> {
-    fn quote(&self) -> TokenStream {
-        let create_raw = self.quote_create_raw();
-        let iunknown_vtbl = self.quote_iunknown_vtbl();
-        let iunknown_impl = self.quote_iunknown_impl();
+    /// The paths `#[iunknown(...)]` gives, errors about which point at `span`.
+    fn iunknown_paths(
+        attr: IUnknownAttr,
+        bindings: Bindings,
+        krate: &Path,
+        vtbl_name: &VtblName,
+        span: Span,
+    ) -> Result<IUnknownPaths, Error> {
+        let IUnknownAttr {
+            manual,
+            vtbl,
+            iid,
+            is_equal_iid,
+        } = attr;
+        let mut paths = IUnknownPaths {
+            interface: bindings.iunknown(krate),
+            vtbl,
+            iid,
+            is_equal_iid,
+        };
+        if !paths.is_custom() {
+            return Ok(paths);
+        }
+        if manual {
+            return Err(Error::new(
+                span,
+                "#[iunknown(...)] paths don't apply to `iunknown = manual`.",
+            ));
+        }
+        bindings.require_winapi("#[iunknown(...)]", span)?;
 
-        quote! {
-            #create_raw
-            #iunknown_vtbl
-            #iunknown_impl
+        // The vtable is named after the interface, like any other
+        if let Some(vtbl) = &paths.vtbl {
+            let vtbl: Type = parse_quote!(#vtbl);
+            paths.interface = match vtbl_name.interface_of(&vtbl) {
+                Some(interface) => interface,
+                None => match bindings.vtbl_name().interface_of(&vtbl) {
+                    Some(interface) => interface,
+                    None => {
+                        return Err(Error::new_spanned(
+                            &vtbl,
+                            format!(
+                                "Could not determine the interface of the IUnknown vtable `{}`.",
+                                (&vtbl).into_token_stream()
+                            ),
+                        ))
+                    }
+                },
+            };
         }
+        Ok(paths)
     }
 
-    fn quote_create_raw(&self) -> TokenStream {
-        let name = self.name;
-        let vtbl = self.vtbl_member;
-        let refcount = self.refc_member;
-        let (impgen, tygen, wherec) = self.generics.split_for_impl();
-        let params = self.other_members.iter().map(|m| m.quote_param());
-        let inits = self.other_members.iter().map(|m| m.quote_init());
+    fn determine_bindings(attrs: &[Attribute]) -> Result<Bindings, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "bindings" {
+                continue;
+            }
 
-        quote! {
-            impl #impgen #name #tygen #wherec {
-                fn create_raw(#(#params),*) -> *mut Self {
-                    Box::into_raw(Box::new(#name {
-                        #vtbl: <Self as com_impl::BuildVTable<_>>::static_vtable(),
-                        #refcount: Default::default(),
-                        #(#inits,)*
-                    }))
-                }
+            let BindingsAttr { bindings } = attr::parse(attr)?;
+            return Ok(bindings);
+        }
+        Ok(Bindings::Winapi)
+    }
+
+    fn determine_vtbl_name(attrs: &[Attribute], bindings: Bindings) -> Result<VtblName, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "vtbl_name" {
+                continue;
             }
+
+            let VtblNameAttr { format } = attr::parse(attr)?;
+            return VtblName::parse(&format.value(), format.span());
         }
+        Ok(bindings.vtbl_name())
     }
 
-    fn quote_iunknown_vtbl(&self) -> TokenStream {
-        let name = self.name;
-        let (impgen, tygen, wherec) = self.generics.split_for_impl();
-        let buildvtbl = quote! { com_impl::BuildVTable<winapi::um::unknwnbase::IUnknownVtbl> };
+    fn determine_refcount_check(attrs: &[Attribute]) -> Result<Option<RefcountCheck>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "refcount_check" {
+                continue;
+            }
 
-        quote! {
-            unsafe impl #impgen #buildvtbl for #name #tygen #wherec {
-                const VTBL: winapi::um::unknwnbase::IUnknownVtbl = winapi::um::unknwnbase::IUnknownVtbl {
-                    AddRef: Self::__com_impl__IUnknown__AddRef,
-                    Release: Self::__com_impl__IUnknown__Release,
-                    QueryInterface: Self::__com_impl__IUnknown__QueryInterface,
-                };
+            let check = attr::parse(attr)?;
+            return Ok(Some(check));
+        }
+        Ok(None)
+    }
 
-                fn static_vtable() -> com_impl::VTable<winapi::um::unknwnbase::IUnknownVtbl> {
-                    com_impl::VTable::new(&Self::VTBL)
-                }
+    fn determine_inline(attrs: &[Attribute]) -> Result<Inline, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "com_inline" {
+                continue;
             }
+
+            return attr::parse(attr);
         }
+        Ok(Inline::Never)
     }
 
-    fn quote_iunknown_impl(&self) -> TokenStream {
-        let name = self.name;
-        let refcount = self.refc_member;
-        let (impgen, tygen, wherec) = self.generics.split_for_impl();
+    fn determine_crate(attrs: &[Attribute]) -> Result<Path, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "com_crate" {
+                continue;
+            }
 
-        let is_equal_iid = self.interfaces.iter().map(|path| {
-            quote! {
-                winapi::shared::guiddef::IsEqualIID(
-                    &*riid,
-                    &<#path as winapi::Interface>::uuidof(),
-                )
+            let ComCrate { path } = attr::parse(attr)?;
+            return Ok(path);
+        }
+        Ok(ComCrate::default_path())
+    }
+
+    fn is_panic_abort(attrs: &[Attribute]) -> Result<bool, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "com_panic" {
+                continue;
             }
-        });
 
-        quote! {
-            #[allow(non_snake_case)]
-            impl #impgen #name #tygen #wherec {
-                #[inline(never)]
-                unsafe extern "system" fn __com_impl__IUnknown__AddRef(
-                    this: *mut winapi::um::unknwnbase::IUnknown,
-                ) -> u32 {
-                    let this = &*(this as *const Self);
-                    this.#refcount.add_ref()
-                }
+            let ComPanicAttr = attr::parse(attr)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
-                #[inline(never)]
-                unsafe extern "system" fn __com_impl__IUnknown__Release(
-                    this: *mut winapi::um::unknwnbase::IUnknown,
-                ) -> u32 {
-                    let ptr = this as *mut Self;
-                    let count = (*ptr).#refcount.release();
-                    if count == 0 {
-                        // This was the last ref
-                        ::std::mem::drop(Box::from_raw(ptr));
-                    }
-                    count
-                }
+    fn is_class_factory(attrs: &[Attribute]) -> Result<bool, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "class_factory" {
+                continue;
+            }
 
-                #[inline(never)]
-                unsafe extern "system" fn __com_impl__IUnknown__QueryInterface(
-                    this: *mut winapi::um::unknwnbase::IUnknown,
-                    riid: *const winapi::shared::guiddef::IID,
-                    ppv: *mut *mut winapi::ctypes::c_void,
-                ) -> winapi::shared::winerror::HRESULT {
-                    if ppv.is_null() {
-                        return winapi::shared::winerror::E_POINTER;
-                    }
-                    if #( #is_equal_iid )||* {
-                        let that = &*(this as *const Self);
-                        that.#refcount.add_ref();
-                        *ppv = this as *mut winapi::ctypes::c_void;
-                        winapi::shared::winerror::S_OK
-                    } else {
-                        *ppv = std::ptr::null_mut();
-                        winapi::shared::winerror::E_NOINTERFACE
-                    }
-                }
+            if !attr.tts.is_empty() {
+                return Err(Error::new_spanned(
+                    attr,
+                    "#[class_factory] takes no arguments.",
+                ));
             }
+            return Ok(true);
         }
+        Ok(false)
     }
 
-    // ----------------------------------------------------------------
+    fn is_arc(attrs: &[Attribute]) -> Result<bool, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "arc" {
+                continue;
+            }
 
-    fn parse(input: &'a DeriveInput) -> Result<Self, String> {
-        if !Self::is_repr_c(input) {
-            return Err("Your struct *must* be #[repr(C)] for ComImpl.".into());
+            if !attr.tts.is_empty() {
+                return Err(Error::new_spanned(attr, "#[arc] takes no arguments."));
+            }
+            return Ok(true);
         }
+        Ok(false)
+    }
 
-        let data = match &input.data {
-            Data::Struct(data) => data,
-            _ => return Err("ComImpl will only work with structs with named members.".into()),
-        };
-        let fields = match &data.fields {
-            Fields::Named(fields) => fields,
-            _ => return Err("ComImpl will only work with structs with named members.".into()),
-        };
+    fn is_stack(attrs: &[Attribute]) -> Result<bool, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "stack" {
+                continue;
+            }
 
-        let name = &input.ident;
-        let vtbl_member = Self::determine_vtbl_member(fields)?;
-        let refc_member = Self::determine_refcount_member(fields)?;
-        let other_members = Self::parse_members(fields, vtbl_member, refc_member);
-        let interfaces = Self::determine_interfaces(&input.attrs, fields, vtbl_member)?;
-        let generics = &input.generics;
+            if !attr.tts.is_empty() {
+                return Err(Error::new_spanned(attr, "#[stack] takes no arguments."));
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
 
-        Ok(ComImpl {
-            name,
-            vtbl_member,
-            refc_member,
-            other_members,
-            interfaces,
-            generics,
-        })
+    fn is_marshal_by_value(attrs: &[Attribute]) -> Result<bool, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "marshal_by_value"
+            {
+                continue;
+            }
+
+            if !attr.tts.is_empty() {
+                return Err(Error::new_spanned(
+                    attr,
+                    "#[marshal_by_value] takes no arguments.",
+                ));
+            }
+            return Ok(true);
+        }
+        Ok(false)
     }
 
     fn is_repr_c(input: &'a DeriveInput) -> bool {
@@ -192,7 +2276,19 @@ impl<'a> ComImpl<'a> {
         false
     }
 
-    fn determine_vtbl_member(fields: &FieldsNamed) -> Result<&Ident, String> {
+    fn determine_hot_reload_slot(attrs: &[Attribute]) -> Result<Option<Expr>, Error> {
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "hot_reload" {
+                continue;
+            }
+
+            let HotReloadAttr { slot } = attr::parse(attr)?;
+            return Ok(Some(slot));
+        }
+        Ok(None)
+    }
+
+    fn determine_vtbl_member(fields: &FieldsNamed) -> Result<&Ident, Error> {
         for field in fields.named.iter() {
             let ty = Self::ty_stem(&field.ty);
             let ty = match ty {
@@ -206,33 +2302,307 @@ impl<'a> ComImpl<'a> {
             return Ok(field.ident.as_ref().unwrap());
         }
 
-        Err("Could not find a com_impl::VTable member".into())
+        Err(Error::new(
+            fields.brace_token.span,
+            "Could not find a com_impl::VTable member",
+        ))
     }
 
-    fn determine_refcount_member(fields: &FieldsNamed) -> Result<&Ident, String> {
+    /// The member marked `#[refcount]`, or else the first of one of com-impl's refcount types.
+    fn determine_refcount_member(fields: &FieldsNamed) -> Result<Option<&Ident>, Error> {
+        let mut marked = None;
+        for field in fields.named.iter() {
+            let is_marked = field.attrs.iter().any(|attr| {
+                attr.path.segments.len() == 1 && attr.path.segments[0].ident == "refcount"
+            });
+            if !is_marked {
+                continue;
+            }
+            if marked.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "Only one member can be marked #[refcount].",
+                ));
+            }
+            marked = field.ident.as_ref();
+        }
+        if marked.is_some() {
+            return Ok(marked);
+        }
+
         for field in fields.named.iter() {
             let ty = Self::ty_stem(&field.ty);
             let ty = match ty {
                 Some(ty) => ty,
                 None => continue,
             };
-            if ty != "Refcount" {
+            if ty != "Refcount" && ty != "LocalRefcount" && ty != "WeakRefcount" {
                 continue;
             }
 
-            return Ok(field.ident.as_ref().unwrap());
+            return Ok(field.ident.as_ref());
+        }
+
+        Ok(None)
+    }
+
+    /// Whether the refcount member is a `com_impl::LocalRefcount`.
+    fn is_local_refcount(fields: &FieldsNamed, refcount: Option<&Ident>) -> bool {
+        fields.named.iter().any(|field| {
+            field.ident.as_ref() == refcount
+                && match Self::ty_stem(&field.ty) {
+                    Some(ty) => ty == "LocalRefcount",
+                    None => false,
+                }
+        })
+    }
+
+    /// Whether the refcount member is a `com_impl::weak::WeakRefcount`.
+    fn is_weak_refcount(fields: &FieldsNamed, refcount: Option<&Ident>) -> bool {
+        fields.named.iter().any(|field| {
+            field.ident.as_ref() == refcount
+                && match Self::ty_stem(&field.ty) {
+                    Some(ty) => ty == "WeakRefcount",
+                    None => false,
+                }
+        })
+    }
+
+    fn determine_site_member<'b>(
+        fields: &'b FieldsNamed,
+        vtbl: &Ident,
+    ) -> Result<Option<&'b Ident>, Error> {
+        let mut prev = None;
+        for field in fields.named.iter() {
+            let name = field.ident.as_ref().unwrap();
+            let is_site = match Self::ty_stem(&field.ty) {
+                Some(ty) => ty == "ObjectWithSite",
+                None => false,
+            };
+            if is_site {
+                // Its IUnknown methods step back over exactly one vtable pointer
+                if prev != Some(vtbl) {
+                    return Err(Error::new(
+                        name.span(),
+                        "The com_impl::site::ObjectWithSite member must directly follow the \
+                         VTable member.",
+                    ));
+                }
+                return Ok(Some(name));
+            }
+            prev = Some(name);
+        }
+        Ok(None)
+    }
+
+    fn determine_aggregation_member(fields: &FieldsNamed) -> Option<&Ident> {
+        fields.named.iter().find_map(|field| match Self::ty_stem(&field.ty) {
+            Some(ty) if ty == "Aggregation" => field.ident.as_ref(),
+            _ => None,
+        })
+    }
+
+    fn determine_tear_offs(attrs: &[Attribute]) -> Result<Vec<TearOffEntry>, Error> {
+        let mut tear_offs = Vec::new();
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "tear_off" {
+                continue;
+            }
+
+            let TearOffAttr { entries } = attr::parse(attr)?;
+            tear_offs.extend(entries);
+        }
+        Ok(tear_offs)
+    }
+
+    fn determine_delegates(
+        attrs: &[Attribute],
+        fields: &FieldsNamed,
+    ) -> Result<Vec<DelegateEntry>, Error> {
+        let mut delegates = Vec::new();
+        for attr in attrs {
+            if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "delegate" {
+                continue;
+            }
+
+            let DelegateAttr { entries } = attr::parse(attr)?;
+            for entry in &entries {
+                if !fields.named.iter().any(|f| f.ident.as_ref() == Some(&entry.member)) {
+                    return Err(Error::new(
+                        entry.member.span(),
+                        format!("#[delegate] names no member `{}`.", entry.member),
+                    ));
+                }
+            }
+            delegates.extend(entries);
+        }
+        Ok(delegates)
+    }
+
+    fn determine_tear_offs_member(fields: &FieldsNamed) -> Option<&Ident> {
+        fields.named.iter().find_map(|field| match Self::ty_stem(&field.ty) {
+            Some(ty) if ty == "TearOffs" => field.ident.as_ref(),
+            _ => None,
+        })
+    }
+
+    fn determine_server_lock_member(fields: &FieldsNamed) -> Option<&Ident> {
+        fields.named.iter().find_map(|field| match Self::ty_stem(&field.ty) {
+            Some(ty) if ty == "ServerLock" => field.ident.as_ref(),
+            _ => None,
+        })
+    }
+
+    fn determine_aggregate_member(fields: &FieldsNamed) -> Result<Option<&Ident>, Error> {
+        let mut aggregate = None;
+        for field in fields.named.iter() {
+            let marked = field.attrs.iter().any(|attr| {
+                attr.path.segments.len() == 1 && attr.path.segments[0].ident == "aggregate"
+            });
+            if !marked {
+                continue;
+            }
+            if aggregate.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "Only one member can be marked #[aggregate].",
+                ));
+            }
+            aggregate = field.ident.as_ref();
+        }
+        Ok(aggregate)
+    }
+
+    fn determine_ftm_member(fields: &FieldsNamed) -> Result<Option<&Ident>, Error> {
+        let mut ftm = None;
+        for field in fields.named.iter() {
+            let marked = field.attrs.iter().any(|attr| {
+                attr.path.segments.len() == 1 && attr.path.segments[0].ident == "ftm"
+            });
+            if !marked {
+                continue;
+            }
+            if ftm.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "Only one member can be marked #[ftm].",
+                ));
+            }
+            ftm = field.ident.as_ref();
+        }
+        Ok(ftm)
+    }
+
+    /// The members marked `#[com_default]`, which start out as `Default::default()`.
+    fn determine_default_members(fields: &FieldsNamed) -> Result<Vec<&Ident>, Error> {
+        let mut defaults = Vec::new();
+        for field in fields.named.iter() {
+            for attr in &field.attrs {
+                if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "com_default" {
+                    continue;
+                }
+
+                if !attr.tts.is_empty() {
+                    return Err(Error::new_spanned(
+                        attr,
+                        "#[com_default] takes no arguments.",
+                    ));
+                }
+                defaults.push(field.ident.as_ref().unwrap());
+            }
         }
+        Ok(defaults)
+    }
+
+    /// The members marked `#[lazy(...)]`, with the `T` of their `Lazy<T>`.
+    fn determine_lazy_members<'b>(fields: &'b FieldsNamed) -> Result<Vec<LazyMember<'b>>, Error> {
+        let mut lazy_members = Vec::new();
+        for field in fields.named.iter() {
+            for attr in &field.attrs {
+                if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "lazy" {
+                    continue;
+                }
+
+                let LazyAttr { init } = attr::parse(attr)?;
+                let name = field.ident.as_ref().unwrap();
+                let ty = Self::lazy_generic(&field.ty).ok_or_else(|| {
+                    Error::new_spanned(
+                        &field.ty,
+                        format!("#[lazy] member `{}` must be a Lazy<T>.", name),
+                    )
+                })?;
+                lazy_members.push(LazyMember { name, ty, init });
+            }
+        }
+        Ok(lazy_members)
+    }
+
+    fn lazy_generic(ty: &Type) -> Option<&Type> {
+        let final_seg = match ty {
+            Type::Path(typath) => *typath.path.segments.last()?.value(),
+            _ => return None,
+        };
+        if final_seg.ident != "Lazy" {
+            return None;
+        }
+        match &final_seg.arguments {
+            PathArguments::AngleBracketed(args) if args.args.len() == 1 => match &args.args[0] {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn determine_secondary_members<'b>(
+        fields: &'b FieldsNamed,
+        vtbl: &Ident,
+        bindings: Bindings,
+        krate: &Path,
+        vtbl_name: &VtblName,
+    ) -> Result<Vec<Secondary<'b>>, Error> {
+        let mut secondary = Vec::new();
+        for field in fields.named.iter() {
+            let member = field.ident.as_ref().unwrap();
+            if member == vtbl {
+                continue;
+            }
+            match Self::ty_stem(&field.ty) {
+                Some(ty) if ty == "VTable" => (),
+                _ => continue,
+            }
 
-        Err("Could not find a com_impl::Refcount member".into())
+            let vtbl_ty = Self::vtbl_generic(&field.ty)?;
+            if let Some(interface) = bindings.interface_of_vtbl(krate, vtbl_name, vtbl_ty) {
+                secondary.push(Secondary { member, interface });
+                continue;
+            }
+            return Err(Error::new_spanned(
+                vtbl_ty,
+                format!(
+                    "Could not determine the interface of the VTable member `{}`.",
+                    member
+                ),
+            ));
+        }
+        Ok(secondary)
     }
 
-    fn parse_members<'b>(fields: &'b FieldsNamed, vtbl: &Ident, refc: &Ident) -> Vec<Mem<'b>> {
+    fn parse_members<'b>(
+        fields: &'b FieldsNamed,
+        vtbl: &Ident,
+        helpers: &[Option<&Ident>],
+        secondary: &[Secondary],
+    ) -> Vec<Mem<'b>> {
         fields
             .named
             .iter()
             .filter_map(|f| {
                 let name = f.ident.as_ref().unwrap();
-                if name == vtbl || name == refc {
+                if name == vtbl || helpers.contains(&Some(name)) {
+                    return None;
+                }
+                if secondary.iter().any(|s| s.member == name) {
                     return None;
                 }
                 let ty = &f.ty;
@@ -245,96 +2615,113 @@ impl<'a> ComImpl<'a> {
         attrs: &[Attribute],
         fields: &FieldsNamed,
         vtbl: &Ident,
-    ) -> Result<Vec<Type>, String> {
+        bindings: Bindings,
+        krate: &Path,
+        vtbl_name: &VtblName,
+        iunknown: &Type,
+    ) -> Result<Interfaces, Error> {
+        let mut markers = Vec::new();
         for attr in attrs {
             if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "interfaces" {
                 continue;
             }
 
-            let meta = attr.parse_meta().map_err(|e| e.to_string())?;
-            let list = match &meta {
-                Meta::List(list) => list,
-                _ => return Err("Invalid syntax for #[interfaces]".into()),
-            };
+            let InterfacesAttr {
+                priority,
+                families,
+                rest,
+                markers: listed_markers,
+            } = attr::parse(attr)?;
 
-            let interfaces = Some(Ok(Self::iunknown_path()))
-                .into_iter()
-                .chain(list.nested.iter().map(|m| match m {
-                    NestedMeta::Meta(Meta::Word(word)) => Ok(Type::from(TypePath {
-                        qself: None,
-                        path: Path::from(word.clone()),
-                    })),
-                    NestedMeta::Literal(Lit::Str(lit)) => {
-                        syn::parse_str(&lit.value()).map_err(|e| e.to_string())
-                    }
-                    _ => Err("Bad syntax for #[interfaces]".into()),
-                }))
-                .collect();
+            // Markers alone still leave the other interfaces to the vtable
+            if priority.is_empty() && families.is_empty() && rest.is_empty() {
+                markers = listed_markers;
+                break;
+            }
+
+            // Hosts negotiating a version ask for the newest they know first
+            let mut interfaces = priority;
+            for family in &families {
+                let versions = family.versions.iter().rev();
+                interfaces.extend(versions.map(|v| Self::interface(parse_quote!(#v))));
+            }
+            // Unless it is listed, e.g. with its IID
+            let listed = interfaces.iter().chain(&rest).any(|entry| {
+                match Self::ty_stem(&entry.ty) {
+                    Some(ty) => ty == "IUnknown",
+                    None => false,
+                }
+            });
+            if !listed {
+                interfaces.push(Self::interface(iunknown.clone()));
+            }
+            interfaces.extend(rest);
 
-            return interfaces;
+            return Ok((interfaces, families, listed_markers));
         }
 
         for field in fields.named.iter() {
             if field.ident.as_ref() != Some(vtbl) {
                 continue;
             }
-            let mut vtbl_ty = Self::vtbl_generic(&field.ty)?.clone();
-            match &mut vtbl_ty {
-                Type::Path(path) => {
-                    let mut last = path.path.segments.last_mut().unwrap();
-                    let last = last.value_mut();
-                    let s = last.ident.to_string();
-                    if s.ends_with("Vtbl") {
-                        let nonv = &s[..s.len() - 4];
-                        if nonv == "IUnknown" {
-                            return Ok(vec![Self::iunknown_path()]);
-                        }
-                        let new_end = Ident::new(nonv, last.ident.span());
-                        last.ident = new_end;
-                    } else {
-                        break;
-                    }
-                }
-                _ => unreachable!(),
+            let iunknown = Self::interface(iunknown.clone());
+            let vtbl_ty = Self::vtbl_generic(&field.ty)?;
+            let interface = match bindings.interface_of_vtbl(krate, vtbl_name, vtbl_ty) {
+                Some(interface) => interface,
+                None => break,
+            };
+            let is_iunknown = match Self::ty_stem(&interface) {
+                Some(ty) => ty == "IUnknown",
+                None => false,
             };
+            let mut interfaces = vec![iunknown];
+            if !is_iunknown {
+                interfaces.push(Self::interface(interface));
+            }
 
-            return Ok(vec![Self::iunknown_path(), vtbl_ty]);
+            return Ok((interfaces, Vec::new(), markers));
         }
 
-        Err("Could not determine the COM interfaces you would like to implement.".into())
+        Err(Error::new(
+            vtbl.span(),
+            "Could not determine the COM interfaces you would like to implement.",
+        ))
     }
 
-    fn iunknown_path() -> Type {
-        syn::parse_str("winapi::um::unknwnbase::IUnknown").unwrap()
+    /// An interface whose IID comes from its bindings.
+    fn interface(ty: Type) -> InterfaceEntry {
+        InterfaceEntry { ty, iid: None }
     }
 
-    fn vtbl_generic(ty: &Type) -> Result<&Type, String> {
+    fn vtbl_generic(ty: &Type) -> Result<&Type, Error> {
+        let no_vtable = || Error::new_spanned(ty, "A ComImpl struct must have a VTable member.");
+        let invalid = || Error::new_spanned(ty, "Invalid generic arguments to VTable.");
         let segments = match ty {
             Type::Path(typath) => &typath.path.segments,
-            _ => return Err("A ComImpl struct must have a VTable member.".into()),
+            _ => return Err(no_vtable()),
         };
 
         let final_seg = match segments.last() {
             Some(seg) => *seg.value(),
-            None => return Err("A ComImpl struct must have a VTable member.".into()),
+            None => return Err(no_vtable()),
         };
 
         if final_seg.ident != "VTable" {
-            return Err("A ComImpl struct must have a VTable member.".into());
+            return Err(no_vtable());
         }
 
         let args = match &final_seg.arguments {
             PathArguments::AngleBracketed(args) => &args.args,
-            _ => return Err("Invalid generic arguments to VTable.".into()),
+            _ => return Err(invalid()),
         };
 
         if args.len() != 1 {
-            return Err("Invalid generic arguments to VTable.".into());
+            return Err(invalid());
         }
 
         let itype = match &args[0] {
             GenericArgument::Type(ty) => ty,
-            _ => return Err("Invalid generic arguments to VTable.".into()),
+            _ => return Err(invalid()),
         };
 
         Ok(itype)
@@ -351,6 +2738,27 @@ impl<'a> ComImpl<'a> {
     }
 }
 
+/// The IUnknown the derive implements: that of the bindings, or winapi-style definitions
+/// from elsewhere given with `#[iunknown(vtbl = ..., iid = ..., is_equal_iid = ...)]`.
+struct IUnknownPaths {
+    interface: Type,
+    vtbl: Option<Path>,
+    iid: Option<Type>,
+    is_equal_iid: Option<Path>,
+}
+
+impl IUnknownPaths {
+    fn is_custom(&self) -> bool {
+        self.vtbl.is_some() || self.iid.is_some() || self.is_equal_iid.is_some()
+    }
+}
+
+/// A `VTable` member after the first, answering its own interface.
+struct Secondary<'a> {
+    member: &'a Ident,
+    interface: Type,
+}
+
 struct Mem<'a> {
     name: &'a Ident,
     ty: &'a Type,
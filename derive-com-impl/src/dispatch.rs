@@ -0,0 +1,423 @@
+use proc_macro2::TokenStream;
+use syn::{
+    Attribute, Error, Expr, FnArg, Ident, ImplItem, ImplItemMethod, ItemImpl, Pat, Path,
+    ReturnType, Type,
+};
+
+use crate::attr::{self, ComCrate, ComImplArgs, ComNameAttr, DispIdAttr};
+use crate::com_impl::{com_name, pascal_case};
+
+/// `#[com_impl(dispatch)]` on an inherent impl block: implements `com_impl::dispatch::Dispatch`
+/// from the methods marked `#[dispid(n)]`, and the IDispatch vtable on top of it.
+pub fn expand_dispatch(args: &ComImplArgs, item: &ItemImpl) -> Result<TokenStream, Error> {
+    if let Some(name) = args
+        .names()
+        .find(|name| *name != "dispatch" && *name != "crate")
+    {
+        return Err(Error::new(
+            name.span(),
+            format!("`{}` can't be combined with `dispatch`", name),
+        ));
+    }
+
+    let krate = ComCrate::from_arg(args.value("crate"))?;
+    let mut item = item.clone();
+    let mut members = Vec::new();
+    for impl_item in &mut item.items {
+        if let ImplItem::Method(method) = impl_item {
+            if let Some(member) = DispatchMember::parse(&krate, method)? {
+                members.push(member);
+            }
+        }
+    }
+
+    let dispatch = quote_dispatch(&krate, &item, &members);
+    Ok(quote! {
+        #item
+        #dispatch
+    })
+}
+
+/// `#[com_impl(dispatch)]` on the implementation of a dual interface: routes `Invoke` for the
+/// methods marked `#[dispid(n)]` to their vtable entries, converting the arguments to the
+/// types they take.
+pub fn expand_dual(
+    krate: &Path,
+    item: &ItemImpl,
+    com_ty: &Path,
+    com_vtbl: &Path,
+) -> Result<TokenStream, Error> {
+    let mut members = Vec::new();
+    for impl_item in &item.items {
+        if let ImplItem::Method(method) = impl_item {
+            let member = DispatchMember::parse_dual(krate, method, com_ty, com_vtbl)?;
+            if let Some(member) = member {
+                members.push(member);
+            }
+        }
+    }
+
+    Ok(quote_dispatch(krate, item, &members))
+}
+
+fn quote_dispatch(krate: &Path, item: &ItemImpl, members: &[DispatchMember]) -> TokenStream {
+    let self_ty = &item.self_ty;
+    let (impgen, _, wherec) = item.generics.split_for_impl();
+    let member_infos = members.iter().map(|m| m.quote_info(krate));
+    let calls = members
+        .iter()
+        .enumerate()
+        .map(|(i, m)| m.quote_call(krate, i));
+
+    quote! {
+        unsafe impl #impgen #krate::dispatch::Dispatch for #self_ty #wherec {
+            const MEMBERS: &'static [#krate::dispatch::Member] = &[#(#member_infos),*];
+
+            #[allow(unused_variables)]
+            unsafe fn invoke(
+                &self,
+                index: usize,
+                args: &#krate::dispatch::Args,
+            ) -> ::std::result::Result<
+                #krate::variant::Variant,
+                #krate::__private::HRESULT,
+            > {
+                match index {
+                    #(#calls)*
+                    _ => ::std::result::Result::Err(
+                        #krate::__private::DISP_E_MEMBERNOTFOUND,
+                    ),
+                }
+            }
+        }
+
+        unsafe impl #impgen #krate::BuildVTable<#krate::__private::IDispatchVtbl>
+            for #self_ty #wherec
+        {
+            const VTBL: #krate::__private::IDispatchVtbl = #krate::__private::IDispatchVtbl {
+                parent: <Self as #krate::BuildVTable<_>>::VTBL,
+                GetTypeInfoCount: #krate::dispatch::get_type_info_count::<Self>,
+                GetTypeInfo: #krate::dispatch::get_type_info::<Self>,
+                GetIDsOfNames: #krate::dispatch::get_ids_of_names::<Self>,
+                Invoke: #krate::dispatch::invoke::<Self>,
+            };
+
+            const STATIC_VTABLE: #krate::VTable<#krate::__private::IDispatchVtbl> =
+                #krate::VTable::new(&Self::VTBL);
+        }
+    }
+}
+
+struct DispatchMember {
+    name: String,
+    dispid: Expr,
+    propput: bool,
+    params: Vec<String>,
+    /// The `VARTYPE` of each parameter, and of the result.
+    param_vts: Vec<TokenStream>,
+    ret_vt: TokenStream,
+    call: Call,
+}
+
+enum Call {
+    /// A method of the inherent impl block.
+    Method(Ident),
+    /// A dual interface's vtable entry, taking arguments of the types `params`, followed by a
+    /// pointer to the `[out, retval]` if it has one.
+    Entry {
+        com_ty: Box<Path>,
+        com_vtbl: Box<Path>,
+        com_name: Ident,
+        params: Vec<Type>,
+        retval: Option<Box<Type>>,
+    },
+}
+
+impl DispatchMember {
+    fn quote_info(&self, krate: &Path) -> TokenStream {
+        let name = &self.name;
+        let dispid = &self.dispid;
+        let kind = if self.propput {
+            quote! { #krate::dispatch::MemberKind::PropertyPut }
+        } else {
+            quote! { #krate::dispatch::MemberKind::Method }
+        };
+        let params = self.params.iter().zip(&self.param_vts).map(|(name, vt)| {
+            quote! { #krate::dispatch::Param { name: #name, vt: #vt } }
+        });
+        let ret_vt = &self.ret_vt;
+
+        quote! {
+            #krate::dispatch::Member {
+                name: #name,
+                dispid: #dispid,
+                kind: #kind,
+                params: &[#(#params),*],
+                ret: #ret_vt,
+            }
+        }
+    }
+
+    fn quote_call(&self, krate: &Path, index: usize) -> TokenStream {
+        let (com_ty, com_vtbl, com_name, params, retval) = match &self.call {
+            Call::Method(method) => {
+                let args = (0..self.params.len()).map(|i| quote! { args.get(#i)? });
+                return quote! {
+                    #index => #krate::dispatch::IntoVariant::into_variant(
+                        self.#method(#(#args),*)
+                    ),
+                };
+            }
+            Call::Entry {
+                com_ty,
+                com_vtbl,
+                com_name,
+                params,
+                retval,
+            } => (com_ty, com_vtbl, com_name, params, retval),
+        };
+
+        let args = params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| quote! { args.get_raw::<#ty>(#i)?, });
+        let (declare, pass, result) = match retval {
+            Some(ty) => (
+                quote! { let mut __com_impl_retval = ::std::mem::zeroed::<#ty>(); },
+                quote! { &mut __com_impl_retval },
+                quote! { #krate::dispatch::AutomationType::into_variant(__com_impl_retval) },
+            ),
+            None => (
+                quote! {},
+                quote! {},
+                quote! { #krate::variant::Variant::new() },
+            ),
+        };
+
+        quote! {
+            #index => {
+                #declare
+                let entry = <Self as #krate::BuildVTable<#com_vtbl>>::VTBL.#com_name;
+                let hr: #krate::__private::HRESULT = entry(
+                    self as *const Self as *mut #com_ty,
+                    #(#args)*
+                    #pass
+                );
+                if hr < 0 {
+                    return ::std::result::Result::Err(hr);
+                }
+                ::std::result::Result::Ok(#result)
+            }
+        }
+    }
+
+    // ----------------------------------------------------------------
+
+    /// Reads and removes the `#[dispid]` and `#[com_name]` attributes of a method, which is
+    /// only a member if it has the former.
+    fn parse(krate: &Path, method: &mut ImplItemMethod) -> Result<Option<Self>, Error> {
+        let dispid = match Self::take_attr(&mut method.attrs, "dispid") {
+            Some(attr) => attr::parse::<DispIdAttr>(&attr)?,
+            None => return Ok(None),
+        };
+        let com_name = match Self::take_attr(&mut method.attrs, "com_name") {
+            Some(attr) => Some(attr::parse::<ComNameAttr>(&attr)?.name.to_string()),
+            None => None,
+        };
+
+        let sig = &method.sig;
+        let ident = &sig.ident;
+        match sig.decl.inputs.first().map(|p| *p.value()) {
+            Some(FnArg::SelfRef(arg)) if arg.mutability.is_none() => (),
+            _ => {
+                return Err(Error::new(
+                    ident.span(),
+                    format!("A dispatch method must take `&self`. (fn {})", ident),
+                ))
+            }
+        }
+        if !sig.decl.generics.params.is_empty() || sig.decl.generics.where_clause.is_some() {
+            return Err(Error::new_spanned(
+                &sig.decl.generics,
+                format!("Dispatch methods can't be generic. (fn {})", ident),
+            ));
+        }
+
+        let mut params = Vec::new();
+        let mut param_vts = Vec::new();
+        for (i, arg) in sig.decl.inputs.iter().skip(1).enumerate() {
+            let (name, ty) = match arg {
+                FnArg::Captured(cap) => (param_name(i, &cap.pat), &cap.ty),
+                FnArg::Ignored(ty) => (format!("arg{}", i), ty),
+                _ => {
+                    return Err(Error::new_spanned(
+                        arg,
+                        "Invalid argument syntax for dispatch method.",
+                    ))
+                }
+            };
+            params.push(name);
+            param_vts.push(quote! { <#ty as #krate::dispatch::FromVariant>::VT });
+        }
+        let ret_vt = match &sig.decl.output {
+            ReturnType::Default => quote! { <() as #krate::dispatch::IntoVariant>::VT },
+            ReturnType::Type(_, ty) => quote! { <#ty as #krate::dispatch::IntoVariant>::VT },
+        };
+        if dispid.propput && params.is_empty() {
+            return Err(Error::new(
+                ident.span(),
+                format!(
+                    "A `propput` method must take the property's value. (fn {})",
+                    ident
+                ),
+            ));
+        }
+
+        let name = match com_name {
+            Some(name) => name,
+            None => {
+                let rust_name = ident.to_string();
+                let rust_name = match rust_name.get(..4) {
+                    Some("set_") if dispid.propput => &rust_name[4..],
+                    _ => &rust_name[..],
+                };
+                pascal_case(rust_name).ok_or_else(|| {
+                    Error::new(
+                        ident.span(),
+                        format!(
+                            "Please use #[com_name] to give `{}` a name automation clients \
+                             can use.",
+                            ident
+                        ),
+                    )
+                })?
+            }
+        };
+
+        Ok(Some(DispatchMember {
+            name,
+            dispid: dispid.dispid,
+            propput: dispid.propput,
+            params,
+            param_vts,
+            ret_vt,
+            call: Call::Method(ident.clone()),
+        }))
+    }
+
+    /// A method of a dual interface is a member if it has a `#[dispid]`. Its name is that of
+    /// its vtable entry, without the `get_`, `put_` or `putref_` MIDL gives property accessors.
+    fn parse_dual(
+        krate: &Path,
+        method: &ImplItemMethod,
+        com_ty: &Path,
+        com_vtbl: &Path,
+    ) -> Result<Option<Self>, Error> {
+        let dispid = match method.attrs.iter().find(|attr| is_attr(attr, "dispid")) {
+            Some(attr) => attr::parse::<DispIdAttr>(attr)?,
+            None => return Ok(None),
+        };
+        let com_name = com_name(method)?;
+        let entry = com_name.to_string();
+        let name = ["get_", "put_", "putref_"]
+            .iter()
+            .find(|prefix| entry.starts_with(*prefix))
+            .map_or(&entry[..], |prefix| &entry[prefix.len()..])
+            .to_string();
+
+        let mut names = Vec::new();
+        let mut params = Vec::new();
+        for (i, arg) in method.sig.decl.inputs.iter().skip(1).enumerate() {
+            let (name, ty) = match arg {
+                FnArg::Captured(cap) => (param_name(i, &cap.pat), &cap.ty),
+                FnArg::Ignored(ty) => (format!("arg{}", i), ty),
+                _ => {
+                    return Err(Error::new_spanned(
+                        arg,
+                        "Invalid argument syntax for COM function.",
+                    ))
+                }
+            };
+            names.push(name);
+            params.push(ty.clone());
+        }
+
+        // The method returns a value through a trailing out pointer, unless it's a setter or
+        // the pointer is an interface passed in
+        let retval = match params.last() {
+            Some(Type::Ptr(ptr))
+                if ptr.mutability.is_some() && !dispid.propput && !is_interface(&ptr.elem) =>
+            {
+                Some(Box::new((*ptr.elem).clone()))
+            }
+            _ => None,
+        };
+        if retval.is_some() {
+            names.pop();
+            params.pop();
+        }
+        let param_vts = params
+            .iter()
+            .map(|ty| quote! { <#ty as #krate::dispatch::AutomationType>::VT })
+            .collect();
+        let ret_vt = match &retval {
+            Some(ty) => quote! { <#ty as #krate::dispatch::AutomationType>::VT },
+            None => quote! { <() as #krate::dispatch::IntoVariant>::VT },
+        };
+        if dispid.propput && params.is_empty() {
+            let ident = &method.sig.ident;
+            return Err(Error::new(
+                ident.span(),
+                format!(
+                    "A `propput` method must take the property's value. (fn {})",
+                    ident
+                ),
+            ));
+        }
+
+        Ok(Some(DispatchMember {
+            name,
+            dispid: dispid.dispid,
+            propput: dispid.propput,
+            params: names,
+            param_vts,
+            ret_vt,
+            call: Call::Entry {
+                com_ty: Box::new(com_ty.clone()),
+                com_vtbl: Box::new(com_vtbl.clone()),
+                com_name,
+                params,
+                retval,
+            },
+        }))
+    }
+
+    fn take_attr(attrs: &mut Vec<Attribute>, name: &str) -> Option<Attribute> {
+        let position = attrs.iter().position(|attr| is_attr(attr, name))?;
+        Some(attrs.remove(position))
+    }
+}
+
+fn is_attr(attr: &Attribute, name: &str) -> bool {
+    attr.path.segments.len() == 1 && attr.path.segments[0].ident == name
+}
+
+/// Whether `ty` is one of the interfaces automation passes, which may be an `[in]` pointer.
+fn is_interface(ty: &Type) -> bool {
+    let path = match ty {
+        Type::Path(path) => &path.path,
+        _ => return false,
+    };
+    match path.segments.last() {
+        Some(last) => last.value().ident == "IUnknown" || last.value().ident == "IDispatch",
+        None => false,
+    }
+}
+
+/// The name callers may pass the `i`th argument by.
+fn param_name(i: usize, pat: &Pat) -> String {
+    match pat {
+        Pat::Ident(pat) => pat.ident.to_string().trim_start_matches('_').to_string(),
+        _ => format!("arg{}", i),
+    }
+}
@@ -94,18 +94,49 @@ extern crate proc_macro2;
 
 use proc_macro::TokenStream;
 use syn::DeriveInput;
-use syn::{AttributeArgs, Item};
+use syn::Item;
 
+mod attr;
+mod bindings;
 mod derive;
 mod com_impl;
+mod dispatch;
+mod vtbl_name;
 
-#[proc_macro_derive(ComImpl, attributes(interfaces))]
+#[proc_macro_derive(ComImpl, attributes(aggregate, alloc, arc, bindings, builder, class_factory, com_crate, com_default, com_inline, com_new, com_panic, constructor, delegate, final_release, ftm, hot_reload, interfaces, iunknown, lazy, marshal_by_value, query_interface, refcount, refcount_check, singleton, stack, tear_off, vtbl_name))]
 /// `#[derive(ComImpl)]`
 /// 
 /// Automatically implements reference counting for your COM object, creating a pointer via
 /// `Box::into_raw` and deallocating with `Box::from_raw`. A private inherent method named
 /// `create_raw` is added to your type that takes all of your struct members except the vtable
-/// and refcount as parameters in declaration order.
+/// and refcount as parameters in declaration order. `com_impl::ImplementsInterface<I>` is
+/// implemented for every interface `I` that QueryInterface answers.
+///
+/// Another private method, `create`, takes the same parameters and returns the reference as a
+/// `com_impl::ComBox<Self>`, which derefs to your struct and converts to a `ComPtr` of any of
+/// those interfaces with `ComBox::into_com_ptr`. It is not generated with other bindings or
+/// `#[iunknown(...)]`, nor for `#[singleton]` and `#[stack]` objects.
+///
+/// Alongside it, `into_raw(this: ComBox<Self>) -> *mut IUnknown` gives the reference to code
+/// that stores raw pointers, e.g. a C callback registration that releases it later, and
+/// `unsafe fn from_raw(ptr: *mut IUnknown) -> ComBox<Self>` takes it back from such a pointer.
+///
+/// A private method `query_interface::<I>()` asks the object's QueryInterface for another
+/// interface, returning it as a `com_impl::ComPtr<I>` with its own reference, or `None` if the
+/// object doesn't answer it. It is not generated with other bindings, `#[iunknown(...)]` or
+/// `iunknown = manual`.
+///
+/// Likewise, `from_interface(ptr: &ComPtr<I>) -> Option<&Self>` finds the object behind any
+/// of its interfaces, e.g. one handed back by a COM API. QueryInterface answers a private IID
+/// carrying the `TypeId` of the type with the object itself, so pointers to other objects,
+/// proxies included, give `None` instead of being cast blindly. Only for types without
+/// generic parameters.
+///
+/// A second private method, `unsafe fn create_raw_with(init: impl FnOnce(*mut Self))`, builds
+/// the object in its allocation instead of on the stack, for objects embedding large buffers.
+/// It writes the vtables, the refcount and the helper members described below, and `init`
+/// writes every other member through the uninitialized pointer, e.g. with
+/// `ptr::addr_of_mut!((*ptr).buffer).write_bytes(0, 1)`. It is not generated for `#[arc]`.
 /// 
 /// ### Additional attributes:
 /// 
@@ -114,11 +145,320 @@ mod com_impl;
 /// - Specifies the COM interfaces that this type should respond to in QueryInterface. IUnknown
 ///   is included implicitly. If this attribute is not specified it will be assumed that the only
 ///   types responded to are IUnknown and the type specified in the VTable.
+///
+/// - QueryInterface answers these with the primary vtable, so with winapi bindings it is a
+///   compile error to list an interface whose vtable (the type of its `lpVtbl`) the type
+///   doesn't fill in, i.e. anything but the VTable's interface and its ancestors. Interfaces of
+///   secondary `VTable` members are answered on their own and can't be listed. List interfaces
+///   that add no methods to IUnknown as markers, below.
+///
+/// `#[interfaces(order(IHot, IWarm), ICold)]`
+///
+/// - QueryInterface matches the first field of the requested IID against those of the
+///   interfaces, and compares the whole IID in the arms it matches, in turn. The first fields
+///   are matched as constants where the bindings give IIDs as constants, and compared one by
+///   one otherwise, as with winapi. Interfaces listed inside `order(...)` are compared first,
+///   in the order given, ahead of IUnknown and the remaining interfaces. Use this to put the
+///   IIDs your host queries most often at the front of the list.
+///
+/// - Interfaces may be given as paths, e.g. `winapi::um::dwrite::IDWriteFontFileStream`.
+///
+/// `#[interfaces(IThing = IID_IThing)]`
+///
+/// - Compares against the IID the expression gives, instead of the one of the interface's
+///   bindings. Interfaces given this way aren't covered by `ImplementsInterface`.
+///
+/// `#[interfaces(marker(IAgileObject, INoMarshal))]`
+///
+/// - For interfaces without methods of their own, such as `IAgileObject`, answered with the
+///   object's IUnknown pointer after the other interfaces. Given only markers, the other
+///   interfaces are still those of the VTable, as without `#[interfaces]`. Markers may be given
+///   with their IID like any other interface, e.g. `marker(IAgileObject = IID_IAgileObject)`.
+///
+/// `#[interfaces(family(IDWriteFactory..=IDWriteFactory3))]`
+///
+/// - For interfaces versioned by derivation. Answers every version from `IDWriteFactory` to
+///   `IDWriteFactory3`, named by appending the version number, so all of them must be in
+///   scope. The VTable member must be that of the newest version. The versions are compared
+///   newest first, after any `order(...)`, and `com_impl::InterfaceFamily<IDWriteFactory>` is
+///   implemented for the type, reporting the newest version through `highest_supported()`.
+///
+/// `#[query_interface(hook = Self::query_first, fallback = Self::query_last)]`
+///
+/// - Gives methods of the type, `fn(&self, riid: &IID) -> Option<*mut c_void>`, the chance to
+///   answer QueryInterface themselves: `hook` before the IIDs the derive knows, `fallback`
+///   after them and before any `#[aggregate]` member. A returned pointer is handed out as it
+///   is, so the method adds the reference it returns, e.g. with `ComPtr::clone(..).into_raw()`.
+///   Either may be given alone. The IID and `c_void` types are those of the bindings.
+///
+/// `#[tear_off(IExpensive = ExpensiveTearOff)]`
+///
+/// - Answers `IExpensive` with an `ExpensiveTearOff`, created the first time it is asked for
+///   and kept in the type's `com_impl::tear_off::TearOffs` member. The tear-off type is
+///   aggregable and implements `com_impl::tear_off::TearOff<Self>`. Requires the `tear_off`
+///   feature of `com-impl`.
+///
+/// `#[delegate(IStream = stream)]`
+///
+/// - Answers `IStream` with the object held in the member `stream`, adding a reference to it.
+///   The member implements `com_impl::delegate::Delegate<IStream>`, as `ComPtr<IStream>` and
+///   `Option<ComPtr<IStream>>` do, and the IID isn't answered while it holds nothing. The
+///   delegated object keeps its own identity. Requires the `delegate` feature of `com-impl`.
+///
+/// `#[com_new]`, `#[com_new(new = IFoo, new_bar = IBar)]`
+///
+/// - Adds public constructors taking the parameters of `create_raw` and returning the new
+///   object as a `com_impl::ComPtr`, instead of a `new` casting the pointer by hand. The bare
+///   form adds `new`, returning the interface of the vtable; otherwise each constructor is
+///   named with the interface it returns, which must be one the object implements. Not
+///   available with other bindings, `#[iunknown(...)]`, `#[singleton]` or `#[stack]`.
+///
+/// `#[builder]`, `#[builder(IFoo)]`
+///
+/// - Adds a `FooBuilder` next to the type, with the same visibility, taking the parameters of
+///   `create_raw` one at a time, e.g. `FooBuilder::new().write_time(t).file_data(v).build()`.
+///   `build` returns a `com_impl::ComPtr` of the vtable's interface, or of the one named, and
+///   panics if a member wasn't set. The same restrictions as `#[com_new]` apply, and no member
+///   may be named `new` or `build`.
+///
+/// `#[class_factory]`
+///
+/// - Implements `com_impl::class_factory::CoClass`, so `com_impl::class_factory::ClassFactory`
+///   can create objects of the type. The members passed to `create_raw` are given by
+///   `Default::default()`. With an `Aggregation` member, an outer object passed to
+///   `CreateInstance` aggregates the new object; otherwise it is refused with
+///   `CLASS_E_NOAGGREGATION`. Requires the `class_factory` feature of `com-impl`.
+///
+/// `#[arc]`
+///
+/// - Allocates the object in an `Arc` instead of a `Box`, whose strong count is the reference
+///   count, so the type has no `Refcount` member. An additional private inherent method
+///   `create_raw_weak` takes the same parameters as `create_raw` and also returns a
+///   `com_impl::WeakCom<Self>`, which observes the object from Rust without keeping it alive.
+///   Not available with `iunknown = manual` or an `Aggregation` member.
+///
+/// `#[refcount_check(abort)]`
+///
+/// - How the derived AddRef and Release guard against callers that break the counting rules.
+///   An AddRef taking the count past `u32::MAX - 1` or a Release taking it below zero, which
+///   would otherwise wrap around and free the object twice, report and abort the process:
+///   always with `abort`, only in builds with debug assertions with `debug`, the default, and
+///   never with `none`.
+///
+/// `#[com_panic(abort)]`
+///
+/// - Catches panics in every method of the type and aborts the process, so none unwinds into
+///   the caller: the derived IUnknown methods, including the `#[query_interface]` hooks,
+///   `#[final_release]` and the drop of the object, and the methods of its `#[com_impl]`
+///   blocks without a `#[panic(...)]` or block-level `panic` of their own, which take
+///   precedence. With `iunknown = manual`, the block implementing IUnknown follows it like any
+///   other.
+///
+/// `#[final_release(Self::recycle)]`
+///
+/// - Calls a method of the type, `fn(&self) -> bool`, when the final Release takes the count to
+///   zero, before the object is dropped. Returning `false` keeps the object, like ATL's
+///   `FinalRelease` and object caches do: whoever the method hands the pointer to, e.g. a
+///   pool, revives it later with an AddRef from zero. Not available with `#[arc]` or
+///   `iunknown = manual`.
+///
+/// `#[singleton(INSTANCE)]`
+///
+/// - For objects that live in a `static` rather than on the heap, like class factories. Instead
+///   of `create_raw`, a private `const fn create_static` takes the same parameters and builds
+///   the object for the static `INSTANCE`, e.g.
+///   `static INSTANCE: Factory = Factory::create_static(...);`, and a private method
+///   `global_instance` returns a pointer to it. The type is `Sync`, as the static requires,
+///   when its members other than the vtables are. AddRef and Release count nothing and never
+///   free, so the type has no `Refcount`. It can't have the helper members below either, nor
+///   `#[arc]`, `#[final_release]`, `#[class_factory]`, `alloc`, `hot_reload` or
+///   `iunknown = manual`.
+///
+/// `#[stack]`
+///
+/// - For callbacks that are only used during one call, e.g. enumeration sinks. Instead of
+///   `create_raw`, a private method `create_stack` takes the same parameters and returns a
+///   `com_impl::StackCom<Self>` holding the object by value, so it needs no heap allocation and
+///   may borrow local data through a lifetime parameter. Release never frees the object: the
+///   guard drops it, and aborts the process if references the callee added were not released
+///   by then. Not available with an `Aggregation` or `WeakRefcount` member, `#[arc]`,
+///   `#[singleton]`, `#[final_release]`, `#[class_factory]`, `alloc` or `iunknown = manual`.
+///
+/// `#[marshal_by_value]`
+///
+/// - Answers `IMarshal` for a type implementing `com_impl::marshal::MarshalByValue`, so
+///   marshaling the object copies its state to the destination instead of creating a proxy.
+///   Each query creates a separate `IMarshal`, sharing the object's IUnknown. Not available
+///   together with an `#[ftm]` member. Requires the `marshal` feature of `com-impl`.
+///
+/// `#[com_impl(iunknown = manual)]`
+///
+/// - Leaves IUnknown to you. The derive still adds `create_raw` and the `ImplementsInterface`
+///   impls, but not the IUnknown vtable or its methods; implement them with
+///   `#[com_impl(no_parent)] unsafe impl IUnknown for ...`, answering the interfaces listed in
+///   `#[interfaces]`. The `Refcount` member becomes optional. This attribute must be placed
+///   before `#[derive(ComImpl)]`.
+///
+/// `#[iunknown(vtbl = headers::IUnknownVtbl, iid = headers::GUID, is_equal_iid = IsEqualGUID)]`
+///
+/// - Implements IUnknown with winapi-style definitions from other headers instead of
+///   winapi's: the vtable struct, the type of IIDs, and the function comparing two of them by
+///   reference. Each is optional. The IUnknown interface, which the stubs take as `This`, is
+///   named after the vtable. IIDs still come from `winapi::Interface` unless `#[interfaces]`
+///   gives them, e.g. `#[interfaces(IFoo = IID_IFoo, IUnknown = IID_IUnknown)]`. Not available
+///   with `iunknown = manual`, other bindings, `#[tear_off]`, `#[class_factory]` or the
+///   `ObjectWithSite`, `Aggregation` and `#[aggregate]` members, which use winapi's IUnknown.
+///
+/// `#[com_impl(hot_reload = SLOT)]`
+///
+/// - `create_raw` points new objects at the `com_impl::hot_reload::VTableSlot` static `SLOT`
+///   instead of the type's own vtable, so they follow it when it is repointed to a reloaded
+///   module. Requires the `hot_reload` feature of `com-impl`. Like `iunknown`, this attribute
+///   must be placed before `#[derive(ComImpl)]`, and both may be given together.
+///
+/// `#[com_impl(bindings = windows)]`, `#[com_impl(bindings = windows_sys)]`
+///
+/// - For interfaces defined with the `windows` crate (0.52 or later) instead of winapi. The
+///   vtables are named `IFoo_Vtbl`, IIDs come from `windows::core::Interface`, and IUnknown is
+///   implemented with `windows::core::IUnknown_Vtbl`, so the crate must depend on `windows`.
+///   `ImplementsInterface` is not implemented, and the helper members and attributes above
+///   other than `ServerLock`, additional `VTable` members, `iunknown` and `hot_reload` are not
+///   available. The `#[com_impl]` blocks of the type need `bindings = windows` as well. Like
+///   `iunknown`, this attribute must be placed before `#[derive(ComImpl)]`.
+///
+/// - `windows_sys` works the same for vtables declared by hand next to `windows-sys`, which
+///   has none. IUnknown comes from `com_impl::windows_sys`, which requires the `windows_sys`
+///   feature of `com-impl`, and each interface's IID is the `GUID` constant `IID_IFoo` in scope
+///   unless `#[interfaces(IFoo = EXPR)]` gives it. Additional `VTable` members always use the
+///   `IID_IFoo` constant.
+///
+/// `#[com_impl(bindings = com)]`
+///
+/// - For interfaces defined with `com::interfaces!` from the `com` crate (0.6), whose vtables
+///   are named `IFooVTable`. IIDs come from `com::Interface`, and IUnknown is implemented with
+///   `com::interfaces::iunknown::IUnknownVTable`, so the crate must depend on `com`. The same
+///   restrictions as `bindings = windows` apply.
+///
+/// `#[com_impl(vtbl_name = "vtables::{}Table")]`
+///
+/// - Finds the interfaces of the `VTable` members from vtables named by the format string
+///   instead of the bindings' convention, `{}` standing for the interface's name, e.g.
+///   `"{}Vtable"` or `"{}_Vtbl"`. Modules before the name are those of the vtable relative to
+///   the interface, so `VTable<vtables::IFooTable>` implements `IFoo`. IUnknown's vtable keeps
+///   its usual name. The `#[com_impl]` blocks of the type take the same argument. Like
+///   `iunknown`, this attribute must be placed before `#[derive(ComImpl)]`.
+///
+/// `#[com_impl(agile)]`
+///
+/// - Adds a `#[ftm]` member, described below, making the object agile. Like `iunknown`, this
+///   attribute must be placed before `#[derive(ComImpl)]`.
+///
+/// `#[com_impl(alloc = "cotaskmem")]`, `#[com_impl(alloc = PATH)]`
+///
+/// - `create_raw` allocates the object with `CoTaskMemAlloc`, or with the type at `PATH`
+///   implementing `com_impl::alloc::ComAlloc`, instead of a `Box`, and the final Release frees
+///   it there. Requires the `alloc` feature of `com-impl`. Not available with `#[arc]`. Like
+///   `iunknown`, this attribute must be placed before `#[derive(ComImpl)]`.
+///
+/// `#[com_impl(inline = "default")]`, `#[com_inline(default)]`
+///
+/// - The inlining of the derived AddRef, Release and QueryInterface, as the same argument sets
+///   it for the stubs of a `#[com_impl]` block: `never`, the default, `default` or `always`.
+///   The blocks of the type take their own. Like `iunknown`, this attribute must be placed
+///   before `#[derive(ComImpl)]`, or given as `#[com_inline(...)]` after it.
+///
+/// `#[com_impl(crate = "my_crate::com")]`, `#[com_crate(my_crate::com)]`
+///
+/// - The path the derived code names com-impl by, `::com_impl` unless given, for crates that
+///   reach it through a re-export. The `#[com_impl]` blocks of the type take the same argument.
+///   Like `iunknown`, this attribute must be placed before `#[derive(ComImpl)]`, or given as
+///   `#[com_crate(...)]` after it.
+///
+/// `#[com_impl(constructor(name = alloc_raw, vis = pub(crate)))]`
+///
+/// - Renames `create_raw` and gives it a visibility, e.g. so other modules can create the
+///   object without a public wrapper around it. Either key may be left out. Not available for
+///   `#[singleton]` and `#[stack]` objects. Like `iunknown`, this attribute must be placed
+///   before `#[derive(ComImpl)]`.
+///
+/// ### Helper members
+///
+/// A member of type `com_impl::aggregation::Aggregation` makes the object aggregable: its
+/// IUnknown delegates to the outer object when it has one, and a private inherent method
+/// `create_raw_aggregated` takes the outer `IUnknown` before the other members and returns the
+/// non-delegating `IUnknown`. The member is not a parameter of either constructor.
+///
+/// A member marked `#[aggregate]`, implementing `com_impl::aggregation::InnerUnknown`, holds
+/// the non-delegating IUnknown of an aggregated inner object. QueryInterface forwards the IIDs
+/// it doesn't answer itself to the inner object, exposing all of its interfaces.
+///
+/// Each `VTable` member after the first answers the interface of its own vtable, e.g. a
+/// `VTable<IDWritePixelSnappingVtbl>` answers `IDWritePixelSnapping`. QueryInterface hands out
+/// a pointer to the member, and its IUnknown methods forward to the object. Implement the
+/// interface with `#[com_impl(member = ...)]`. These interfaces must derive directly from
+/// IUnknown, and are not covered by `ImplementsInterface`.
+///
+/// A member of type `com_impl::site::ObjectWithSite` placed directly after the vtable makes
+/// QueryInterface answer `IObjectWithSite` as well. It is initialized with `Default` and is not
+/// a parameter of `create_raw`.
+///
+/// A member of type `com_impl::server_lock::ServerLock` holds the module lock for the lifetime
+/// of the object, from `create_raw` until the final `Release`, so `DllCanUnloadNow` keeps the
+/// module loaded. It is initialized with `Default` and is not a parameter of `create_raw`.
+///
+/// A member of type `com_impl::weak::WeakRefcount` may take the place of the `Refcount`. It
+/// keeps the count in a control block that outlives the object, and QueryInterface answers
+/// `IWeakReferenceSource` with it, so weak references can observe the object without keeping
+/// it alive. Requires the `weak` feature of `com-impl`.
+///
+/// A member of type `com_impl::LocalRefcount` may take the place of the `Refcount` for objects
+/// that never leave their thread. It counts without atomic operations, and makes the type
+/// neither `Send` nor `Sync`. Not available with an `#[ftm]` member.
+///
+/// Any other type implementing `com_impl::ComRefcount` may count the references instead, when
+/// its member is marked `#[refcount]`, e.g. a count that is shared with another object or that
+/// logs for debugging. The derived AddRef and Release call its methods, and it is initialized
+/// with `Default`.
+///
+/// A member of type `com_impl::ftm::FreeThreadedMarshaler` marked `#[ftm]` makes QueryInterface
+/// answer `IMarshal` with the free-threaded marshaler, which the object aggregates on first use,
+/// so the object can be called from any apartment without a proxy. It is initialized with
+/// `Default` and is not a parameter of `create_raw`. Requires the `ftm` feature of `com-impl`.
+///
+/// Any other member marked `#[com_default]` is initialized with `Default` as well, and left out
+/// of the parameters of `create_raw` and the constructors built on it, e.g. for caches, flags
+/// and `Cell`s the caller has no say in. Not available for `#[singleton]` objects.
+///
+/// A member of type `com_impl::lazy::Lazy<T>` marked `#[lazy(EXPR)]` is created on first use
+/// instead: it starts out empty and is not a parameter of `create_raw`, and a private inherent
+/// method of the same name returns `&T`, evaluating `EXPR`, which may use `self`, on the first
+/// call. Not available for `#[singleton]` objects. Requires the `lazy` feature of `com-impl`.
 pub fn derive_com_impl(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     
     derive::expand_derive_com_impl(&input)
-        .unwrap_or_else(compile_error)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(ComVtbl, attributes(com_crate, com_default, vtbl_name))]
+/// `#[derive(ComVtbl)]`
+///
+/// For callback interfaces whose vtables don't start with IUnknown's methods, such as
+/// `ID3DInclude` or `IXAudio2VoiceCallback`. Nothing is reference counted, so the type has no
+/// `Refcount` member, and whoever creates the object keeps it alive and in place for as long
+/// as the API holds the pointer. A private inherent method `create_callback` takes all of the
+/// members except the vtable as parameters and returns the object by value, and `as_callback`
+/// returns the pointer to pass to the API, e.g. a `*mut ID3DInclude` for a
+/// `VTable<ID3DIncludeVtbl>`. Implement the interface with `#[com_impl(no_iunknown)]`.
+/// `#[com_impl(vtbl_name = "...")]` and `#[com_impl(crate = ...)]` apply as they do for
+/// `ComImpl`, and members marked `#[com_default]` are left out of `create_callback` in the same
+/// way.
+pub fn derive_com_vtbl(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    derive::expand_derive_com_vtbl(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
         .into()
 }
 
@@ -136,10 +476,150 @@ pub fn derive_com_impl(input: TokenStream) -> TokenStream {
 /// 
 /// Specifies that the vtable being implemented here does not have a `parent` member. These
 /// are very rare, but include IUnknown.
+///
+/// `#[com_impl(no_iunknown)]`
+///
+/// The same, for callback interfaces that don't derive from IUnknown, implemented by a
+/// `#[derive(ComVtbl)]` type.
+///
+/// `#[com_impl(inline = "default")]`, `#[com_impl(inline = "always")]`
+///
+/// The inlining of the stubs, `#[inline(never)]` unless given. `default` leaves it to the
+/// compiler, e.g. so LTO can inline small methods into callers that know the object's type;
+/// `always` asks for it. The quotes may be left out, as in `inline = always`.
+///
+/// `#[com_impl(crate = "my_crate::com")]`
+///
+/// The path the generated code names com-impl by, `::com_impl` unless given, for a dependency
+/// renamed in `Cargo.toml` or reached through another crate's re-export. The winapi items the
+/// code needs are reached through com-impl as well, so a crate implementing winapi interfaces
+/// needn't enable winapi's features for it, and `std` is named from the root, `::std`, so the
+/// code doesn't depend on the names in scope where it expands.
+///
+/// `#[com_impl(panic = abort)]`, `#[com_impl(panic_result = E_FAIL)]`
+///
+/// Gives every method of the block without a `#[panic(...)]` of its own the behavior of
+/// `#[panic(abort)]` or `#[panic(result = E_FAIL)]`, described below. Under `panic_result`,
+/// methods without a return value abort, having nothing to return. Methods with neither follow
+/// the `#[com_panic(abort)]` of the type, if its derive has one.
+///
+/// `#[com_impl(unwind)]`
+///
+/// Emits the stubs with the `-unwind` variant of their ABI, e.g. `extern "system-unwind"`, and
+/// lets panics in methods without a `#[panic(...)]` unwind into the caller instead of catching
+/// them, overriding `#[com_panic(abort)]`. Only for interfaces whose callers are Rust code you
+/// control: the vtable has to declare its entries with the same ABI, so that the callers expect
+/// the unwinding, which rules out the bindings of winapi and the other crates.
+///
+/// `#[com_impl(apartment)]`
+///
+/// For types implementing `com_impl::apartment::Affine`. Each stub checks which thread it was
+/// called on and, when that isn't the thread owning the type's `Dispatcher`, sends the call
+/// there and blocks until it returns. Requires the `apartment` feature of `com-impl`. Methods
+/// must return `HRESULT`, `ULONG` or nothing.
+///
+/// `#[com_impl(intercept)]`
+///
+/// For types implementing `com_impl::intercept::Interceptor`. Each stub reports the call to the
+/// global interceptor and the type's own before and after running the body, and returns their
+/// HRESULT instead if either answers the call. Requires the `intercept` feature of `com-impl`.
+/// Methods must return `HRESULT`, `ULONG` or nothing.
+///
+/// `#[com_impl(describe)]`, `#[com_impl(describe, parent = IDispatch)]`
+///
+/// Records the interface's methods and their C signatures for `com_impl::header`, which writes
+/// them out as a C++ declaration. The parent interface is assumed to be IUnknown unless given.
+/// Requires the `header` feature of `com-impl`.
+///
+/// `#[com_impl(member = snapping)]`
+///
+/// Implements the interface of the additional `VTable` member `snapping`, rather than an
+/// interface of the primary vtable. The stubs step back from the member to the start of the
+/// object before calling your methods.
+///
+/// `#[com_impl(ancestors(ID2D1Resource(get_factory), ID2D1DrawingStateBlock(...)))]`
+///
+/// Lists interfaces between IUnknown and the block's interface, each with methods of its own
+/// to stub as with `stub(...)`, for implementing only some of the methods of the hierarchy.
+/// An interface listed gets a vtable of its own, whose vtable struct must be in scope as for
+/// a block of its own, holding its methods marked `#[interface(...)]` in the block and the
+/// stubs. The compiler names any method it has neither of. Only for winapi interfaces.
+///
+/// `#[com_impl(stub(do_verb, get_moniker))]`
+///
+/// Fills the vtable entries of the methods named, as they would be in the block, with stubs,
+/// the same as `#[stub]` methods declared without a signature. Entries may be named as in the
+/// vtable as well, e.g. `stub(DoVerb)`.
+///
+/// `#[com_impl(vtbl = path::to::IFooVtable)]`
+///
+/// Names the vtable struct of the interface, for bindings whose vtables don't follow the
+/// `IFooVtbl` convention. A string literal, `vtbl = "IFooVtable"`, is accepted as well. The
+/// derive can't tell the interface from such a vtable either, so list it in `#[interfaces]`
+/// when it is the primary one.
+///
+/// `#[com_impl(vtbl_name = "{}Vtable")]`
+///
+/// Names the vtable struct after the interface with a format string, `{}` standing for the
+/// interface's name, instead of the bindings' convention. See the same argument of `ComImpl`.
+/// Can't be combined with `vtbl = ...`.
+///
+/// `#[com_impl(dispatch)]`
+///
+/// Applied to an inherent `impl` block, exposes the methods marked `#[dispid(n)]` to
+/// automation clients through `com_impl::dispatch::Dispatch` and an `IDispatchVtbl`, for a
+/// type whose primary vtable is IDispatch's. `#[dispid(n, propput)]` marks a property's
+/// setter. Other methods are left as they are. Requires the `dispatch` feature of `com-impl`.
+///
+/// Applied to the `unsafe impl` of a dual interface in the primary vtable, additionally
+/// implements IDispatch, with `Invoke` calling the stubs of the methods marked `#[dispid(n)]`.
+///
+/// `#[com_impl(bindings = windows)]`, `#[com_impl(bindings = windows_sys)]`
+///
+/// Implements an interface defined with the `windows` crate, or declared by hand the same way
+/// for `windows-sys`: the vtable is `IFoo_Vtbl`, its parent member `base__`, and the stubs take
+/// `this` as a `*mut c_void`. Methods are written with the bindings' types, e.g. returning
+/// `windows::core::HRESULT`. Can't be combined with `apartment`, `intercept`, `describe` or
+/// `dispatch`. The default, `bindings = winapi`, may be given explicitly.
+///
+/// `#[com_impl(bindings = com)]`
+///
+/// Implements an interface defined with `com::interfaces!`: the vtable is `IFooVTable`, its
+/// parent member `parent`, and the stubs take `this` as a `NonNull<IFooVPtr>`, as the macro
+/// declares them. Methods are written with the vtable's argument types and
+/// `com::sys::HRESULT`. The same restrictions as `bindings = windows` apply.
+///
+/// `#[com_impl(iunknown = manual)]`, `#[com_impl(hot_reload = SLOT)]`,
+/// `#[com_impl(bindings = ...)]`, `#[com_impl(vtbl_name = "...")]`, `#[com_impl(agile)]`,
+/// `#[com_impl(alloc = ...)]`, `#[com_impl(constructor(...))]`, `#[com_impl(inline = ...)]`,
+/// `#[com_impl(crate = ...)]`
+///
+/// Applied to a `#[derive(ComImpl)]` struct rather than an `impl` block. See `ComImpl`.
 /// 
+/// ### Inherent methods
+///
+/// A method declared with a visibility, e.g. `pub unsafe fn get_file_size(...)`, additionally
+/// gets an inherent method of the same name and visibility on your type. It calls the method
+/// body directly, so Rust code holding the concrete type can skip the vtable and the pointer
+/// casts.
+///
+/// A method returning an HRESULT gets a wrapper returning a `Result` instead, with failure
+/// codes as `Err`. Its `*mut T` parameters marked `#[out]` are provided by the wrapper and
+/// returned as `Ok`, so `pub unsafe fn get_file_size(&self, #[out] size: *mut u64) -> HRESULT`
+/// gets `pub unsafe fn get_file_size(&self) -> Result<u64, HRESULT>`. Several out-parameters
+/// are returned as a tuple. `#[out]` promises that the method writes a single `T` through the
+/// parameter whenever it succeeds, so it doesn't suit arrays, and the wrapper reads it only
+/// then. The wrapper is `unsafe` when the method is.
+///
+/// ### Consts and helpers
+///
+/// Consts in the block, and methods or macro invocations marked `#[com_skip]`, aren't part of
+/// the interface. They are moved to an inherent `impl` of your type as they are, so the COM
+/// methods can use them as `Self::NO_CONTEXT` or `self.in_bounds(...)`, next to them.
+///
 /// ### Attributes on methods
 /// 
-/// `#[com_name = "..."]`
+/// `#[com_name = GetFileSize]`
 /// 
 /// Overrides the method name this function corresponds to in the VTable. Method names by
 /// default are mapped from snake_case to PascalCase to determine their winapi names.
@@ -153,23 +633,119 @@ pub fn derive_com_impl(input: TokenStream) -> TokenStream {
 /// 
 /// <hb/>
 /// 
-/// `#[panic(result = "EXPRESSION")]`
+/// `#[panic(result = EXPRESSION)]`
 /// 
 /// Specifies that in the stub functions code should be generated to catch any unwinding from
 /// the user-provided bodies and return the specified expression. The expression should have
 /// the same type as the standard function body return. This is most useful with functions that
 /// return an HRESULT.
+///
+/// A panic whose payload has the return type is answered with the payload instead, so a body,
+/// or a helper deep inside it, can fail with a specific HRESULT through
+/// `std::panic::panic_any(E_INVALIDARG)`. This also applies to `panic_result` on the block.
+/// Other panics are passed to the function installed with `com_impl::set_panic_reporter`, if
+/// any, with the interface and method names and the panic message, before the expression is
+/// returned.
+///
+/// <hb/>
+///
+/// `#[interface(IDWritePixelSnapping)]`
+///
+/// Marks a method of one of the interface's ancestors, so a single `unsafe impl
+/// IDWriteTextRenderer` block can hold the methods of `IDWritePixelSnapping` as well. The
+/// methods are split into one block per interface, each filling in its own vtable, with the
+/// other arguments of `#[com_impl]` except `vtbl`, `describe` and `dispatch`, which apply to
+/// the block's interface. Every ancestor between IUnknown and the interface still needs its
+/// methods implemented somewhere, or listed in `ancestors(...)`. Can't be combined with
+/// `no_parent` or `member = ...`.
+///
+/// <hb/>
+///
+/// `#[stub]`
+///
+/// Marks a method declared without a body, e.g. `#[stub] fn do_verb();`, for the large
+/// interfaces of which only a few methods matter. Its vtable entry is filled with a stub of
+/// the entry's type, which returns `E_NOTIMPL` without looking at its arguments, or nothing,
+/// or zero or null for a method returning a count, a float or a pointer. A `BOOL` or `LONG`,
+/// which winapi declares as an HRESULT is, gets `E_NOTIMPL` as well. Only for winapi
+/// interfaces, and not with `describe`. Declared with its full signature, as in
+/// `#[stub] unsafe fn do_verb(&self, verb: LONG, ...) -> HRESULT;`, the stub is written from
+/// the signature instead, returning `E_NOTIMPL` or nothing, so it works with any bindings. A
+/// `#[stub]` method can't have a `#[dispid]`.
+///
+/// <hb/>
+///
+/// Any other attribute, e.g. a doc comment, `#[allow(clippy::too_many_arguments)]` or
+/// `#[inline]`, is passed on to the function holding the method's body, where `#[inline]`
+/// replaces the default `#[inline(always)]`. Unless the method has a `#[panic(result = ...)]`
+/// or a `#[cfg(...)]`, or the block a `member`, `intercept` or `apartment`, that function is
+/// the stub itself, whose inlining `#[inline]` replaces instead. `#[cfg(...)]` and lint levels
+/// apply to the stub and the inherent method as well, and doc comments to the inherent method.
+/// When its `#[cfg(...)]` leaves a method out, the vtable entry is still filled, by a stub
+/// returning `E_NOTIMPL`, or nothing for a method without a return value, so the interface is
+/// complete with any set of features. Such a method should return an HRESULT or nothing.
+///
+/// <hb/>
+///
+/// Attribute values are written as Rust code. Wrapping them in a string literal, as in
+/// `#[panic(result = "E_FAIL")]` or `#[com_name = "GetFileSize"]`, is still accepted.
+///
+/// ### Missing and misnamed methods
+///
+/// The macro doesn't see the vtable struct, so the methods are checked against it when the
+/// code is compiled. When the vtable has more methods than the block implements, compiling
+/// fails at the interface's name, after the compiler names the missing vtable field:
+///
+/// ```text
+/// error[E0063]: missing field `ReadFileFragment` in initializer of `IDWriteFontFileStreamVtbl`
+///   |
+/// 2 | unsafe impl IDWriteFontFileStream for FileStream {
+///   |             ^^^^^^^^^^^^^^^^^^^^^ missing `ReadFileFragment`
+///
+/// error[E0080]: evaluation panicked: `IDWriteFontFileStream` has methods the #[com_impl]
+///               block doesn't implement. Implement each as a method named after its vtable
+///               entry in snake_case, as `fn get_file_size` is for `GetFileSize`, with the
+///               interface's arguments, or stub it with `stub(...)`.
+///   |
+/// 2 | unsafe impl IDWriteFontFileStream for FileStream {
+///   |             ^^^^^^^^^^^^^^^^^^^^^ evaluation of `_` failed here
+/// ```
+///
+/// Implement it as `fn read_file_fragment`, or stub it, after the interface's name in
+/// `ancestors(...)` if it is an ancestor. The check isn't made for vtables named with the impl's generic
+/// parameters. A method whose name doesn't map to a field of the vtable is reported at the
+/// method's name as a field the vtable doesn't have, along with the field of the closest
+/// name:
+///
+/// ```text
+/// error[E0560]: struct `IDispatchVtbl` has no field named `GetIdsOfNames`
+///   |
+/// 4 |     fn get_ids_of_names(&self, ...) -> HRESULT {
+///   |        ^^^^^^^^^^^^^^^^ unknown field
+///   |
+/// help: a field with a similar name exists
+///
+/// error[E0080]: evaluation panicked: `IDispatch` has no method `GetIdsOfNames` for
+///               `fn get_ids_of_names` to implement. Name the vtable entry it implements with
+///               `#[com_name = ...]`, such as the field the compiler suggests in place of
+///               `GetIdsOfNames`.
+///   |
+/// 4 |     fn get_ids_of_names(&self, ...) -> HRESULT {
+///   |        ^^^^^^^^^^^^^^^^ evaluation of `_` failed here
+/// ```
+///
+/// The compiler picks the suggestion from the vtable's fields, preferring one that differs
+/// only in case. When the snake_case name can't be mapped to it, as with the acronym here,
+/// give the name with `#[com_name = ...]`. The second error only comes with winapi interfaces,
+/// among whose methods the name is looked up, and is left out when the interface has none of
+/// the block's names.
 pub fn com_impl(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let args = parse_macro_input!(attr as AttributeArgs);
+    let args = parse_macro_input!(attr as attr::ComImplArgs);
+    let item = com_impl::fill_stub_bodies(item.into());
+    let item: TokenStream = com_impl::lift_out_params(item).into();
     let item = parse_macro_input!(item as Item);
 
     com_impl::expand_com_impl(&args, &item)
-        .unwrap_or_else(compile_error)
+        .unwrap_or_else(|e| e.to_compile_error())
         .into()
 }
-
-fn compile_error(message: String) -> proc_macro2::TokenStream {
-    quote! {
-        compile_error!(#message);
-    }
-}
@@ -0,0 +1,106 @@
+//! How the name of an interface's vtable struct follows from the name of the interface.
+
+use proc_macro2::Span;
+use syn::punctuated::Punctuated;
+use syn::{Error, Expr, Ident, Lit, Path, PathSegment, Type};
+
+/// The error for a `vtbl_name = ...` argument that isn't a usable format string.
+pub const EXPECTED: &str = "`vtbl_name` must be a format string with `{}` in place of the \
+                            interface's name, e.g. `vtbl_name = \"{}Vtable\"`";
+
+/// A format string such as `"{}Vtbl"` or `"vtables::{}_Table"`. Modules before the name are
+/// looked up next to the interface: `IFoo`'s vtable is `vtables::IFoo_Table`, and
+/// `bindings::IFoo`'s is `bindings::vtables::IFoo_Table`.
+pub struct VtblName {
+    modules: Vec<String>,
+    prefix: String,
+    suffix: String,
+}
+
+impl VtblName {
+    /// Parses `format`, failing at `span` if it isn't usable.
+    pub fn parse(format: &str, span: Span) -> Result<VtblName, Error> {
+        let mut segments = format.split("::").map(str::to_owned).collect::<Vec<_>>();
+        let name = segments.pop().unwrap();
+        let mut parts = name.splitn(2, "{}");
+        let (prefix, suffix) = match (parts.next(), parts.next()) {
+            (Some(prefix), Some(suffix)) => (prefix.to_owned(), suffix.to_owned()),
+            _ => return Err(Error::new(span, EXPECTED)),
+        };
+
+        // The name must be an identifier for any interface name, and the modules as they are
+        let is_ident = |name: &str| syn::parse_str::<Ident>(name).is_ok();
+        if !is_ident(&format!("{}I{}", prefix, suffix)) || !segments.iter().all(|m| is_ident(m)) {
+            return Err(Error::new(span, EXPECTED));
+        }
+
+        Ok(VtblName {
+            modules: segments,
+            prefix,
+            suffix,
+        })
+    }
+
+    /// The `vtbl_name = "..."` argument of `#[com_impl]`, if it is given.
+    pub fn from_arg(value: Option<&Expr>) -> Result<Option<VtblName>, Error> {
+        match value {
+            Some(Expr::Lit(expr)) => match &expr.lit {
+                Lit::Str(format) => VtblName::parse(&format.value(), format.span()).map(Some),
+                _ => Err(Error::new_spanned(expr, EXPECTED)),
+            },
+            Some(value) => Err(Error::new_spanned(value, EXPECTED)),
+            None => Ok(None),
+        }
+    }
+
+    /// The vtable struct of `interface`.
+    pub fn vtbl_path(&self, interface: &Path) -> Path {
+        let mut path = interface.clone();
+        let mut last = path.segments.pop().unwrap().into_value();
+        for module in &self.modules {
+            path.segments
+                .push(Ident::new(module, last.ident.span()).into());
+        }
+        let name = format!("{}{}{}", self.prefix, last.ident, self.suffix);
+        last.ident = Ident::new(&name, last.ident.span());
+        path.segments.push(last);
+        path
+    }
+
+    /// The interface of the vtable struct `vtbl`, or `None` if it isn't named like one.
+    pub fn interface_of(&self, vtbl: &Type) -> Option<Type> {
+        let path = match vtbl {
+            Type::Path(path) if path.qself.is_none() => &path.path,
+            _ => return None,
+        };
+        let mut segments = path.segments.iter().cloned().collect::<Vec<_>>();
+        let mut last = segments.pop()?;
+        if segments.len() < self.modules.len() {
+            return None;
+        }
+        let modules = segments.split_off(segments.len() - self.modules.len());
+        let in_modules = modules
+            .iter()
+            .zip(&self.modules)
+            .all(|(segment, module)| segment.ident == module && segment.arguments.is_empty());
+        if !in_modules {
+            return None;
+        }
+
+        let name = last.ident.to_string();
+        let len = self.prefix.len() + self.suffix.len();
+        if name.len() <= len || !name.starts_with(&self.prefix) || !name.ends_with(&self.suffix) {
+            return None;
+        }
+        let name = &name[self.prefix.len()..name.len() - self.suffix.len()];
+        last.ident = Ident::new(name, last.ident.span());
+        segments.push(last);
+
+        let mut interface = path.clone();
+        interface.segments = segments.into_iter().collect::<Punctuated<PathSegment, _>>();
+        Some(Type::Path(syn::TypePath {
+            qself: None,
+            path: interface,
+        }))
+    }
+}
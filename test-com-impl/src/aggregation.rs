@@ -0,0 +1,112 @@
+use std::cell::Cell;
+use std::ptr;
+
+use com_impl::aggregation::{Aggregation, Inner};
+use com_impl::prelude::*;
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+/// Aggregable; answers `IDWriteFontFileStream` on behalf of its outer object.
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(IDWriteFontFileStream)]
+pub struct InnerStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    aggregation: Aggregation,
+    data: Vec<u8>,
+}
+
+#[com_impl]
+unsafe impl IDWriteFontFileStream for InnerStream {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.data.len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, _write_time: *mut u64) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        _start: *mut *const c_void,
+        _offset: u64,
+        _size: u64,
+        _ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
+
+/// Aggregates an `InnerStream`, exposing its stream as its own.
+#[com_impl(iunknown = manual)]
+#[repr(C)]
+#[derive(ComImpl)]
+pub struct Outer {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    inner: Cell<*mut IUnknown>,
+}
+
+impl Outer {
+    pub fn new(data: Vec<u8>) -> ComPtr<IUnknown> {
+        let outer = Outer::create_raw(Cell::new(ptr::null_mut()));
+        unsafe {
+            let inner = InnerStream::create_raw_aggregated(outer as *mut IUnknown, data);
+            (*outer).inner.set(inner);
+            ComPtr::from_raw(outer as *mut IUnknown)
+        }
+    }
+}
+
+#[com_impl(no_parent)]
+unsafe impl IUnknown for Outer {
+    unsafe fn query_interface(&self, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+        if IsEqualIID(&*riid, &IUnknown::uuidof()) {
+            self.refcount.add_ref();
+            *ppv = self as *const Self as *mut c_void;
+            S_OK
+        } else if IsEqualIID(&*riid, &IDWriteFontFileStream::uuidof()) {
+            (*self.inner.get()).QueryInterface(riid, ppv)
+        } else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe fn add_ref(&self) -> ULONG {
+        self.refcount.add_ref()
+    }
+
+    unsafe fn release(&self) -> ULONG {
+        let count = self.refcount.release();
+        if count == 0 {
+            (*self.inner.get()).Release();
+            drop(Box::from_raw(self as *const Self as *mut Self));
+        }
+        count
+    }
+}
+
+/// Exposes whatever its inner object answers, without knowing what that is.
+#[repr(C)]
+#[derive(ComImpl)]
+pub struct BlindOuter {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    #[aggregate]
+    inner: Inner,
+}
+
+impl BlindOuter {
+    pub fn new(data: Vec<u8>) -> ComPtr<IUnknown> {
+        let outer = BlindOuter::create_raw(Inner::default());
+        unsafe {
+            let inner = InnerStream::create_raw_aggregated(outer as *mut IUnknown, data);
+            (*outer).inner.set(inner);
+            ComPtr::from_raw(outer as *mut IUnknown)
+        }
+    }
+}
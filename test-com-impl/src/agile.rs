@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::ftm::FreeThreadedMarshaler;
+use com_impl::{Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+/// Answers IMarshal with the free-threaded marshaler in its `#[ftm]` member.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Counter {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    #[ftm]
+    marshaler: FreeThreadedMarshaler,
+    count: AtomicU32,
+}
+
+impl Counter {
+    pub fn new(start: u32) -> ComPtr<IUnknown> {
+        let ptr = Counter::create_raw(AtomicU32::new(start));
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+
+    pub fn increment(&self) -> u32 {
+        self.count.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// The same, with the member added by `agile`.
+#[repr(C)]
+#[com_impl::com_impl(agile)]
+#[derive(com_impl::ComImpl)]
+pub struct Gauge {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    level: AtomicU32,
+}
+
+impl Gauge {
+    pub fn new(level: u32) -> ComPtr<IUnknown> {
+        let ptr = Gauge::create_raw(AtomicU32::new(level));
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use com_impl::alloc::{BoxAlloc, ComAlloc};
+use com_impl::{Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+/// Lives on the COM task heap.
+#[repr(C)]
+#[com_impl::com_impl(alloc = "cotaskmem")]
+#[derive(com_impl::ComImpl)]
+pub struct TaskObject {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub cookie: u32,
+}
+
+impl TaskObject {
+    pub fn new(cookie: u32) -> ComPtr<IUnknown> {
+        let ptr = TaskObject::create_raw(cookie);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
+
+/// Objects allocated by `Counted` and not freed yet.
+pub static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// The global allocator, keeping count of the objects in it.
+pub enum Counted {}
+
+unsafe impl ComAlloc for Counted {
+    fn alloc_uninit<T>() -> *mut T {
+        LIVE.fetch_add(1, Ordering::Relaxed);
+        BoxAlloc::alloc_uninit()
+    }
+
+    unsafe fn free<T>(ptr: *mut T) {
+        BoxAlloc::free(ptr);
+        LIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Allocated by a custom allocator, given by its path.
+#[repr(C)]
+#[com_impl::com_impl(alloc = Counted)]
+#[derive(com_impl::ComImpl)]
+pub struct CountedObject {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub name: String,
+}
+
+impl CountedObject {
+    pub fn new(name: String) -> ComPtr<IUnknown> {
+        let ptr = CountedObject::create_raw(name);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
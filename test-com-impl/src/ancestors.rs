@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::BOOL;
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::um::dwrite::{
+    IDWriteInlineObject, IDWritePixelSnapping, IDWritePixelSnappingVtbl, IDWriteTextRenderer,
+    IDWriteTextRendererVtbl, DWRITE_GLYPH_RUN, DWRITE_GLYPH_RUN_DESCRIPTION, DWRITE_STRIKETHROUGH,
+    DWRITE_UNDERLINE,
+};
+use winapi::um::unknwnbase::IUnknown;
+
+/// Counts the glyphs drawn at one pixel per DIP, leaving the rest of `IDWritePixelSnapping` to
+/// the E_NOTIMPL stubs, so DirectWrite falls back to its defaults.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IDWritePixelSnapping, IDWriteTextRenderer)]
+#[com_new]
+pub struct GlyphCounter {
+    vtbl: VTable<IDWriteTextRendererVtbl>,
+    refcount: Refcount,
+    glyphs: AtomicU32,
+}
+
+#[com_impl::com_impl(ancestors(IDWritePixelSnapping(
+    is_pixel_snapping_disabled,
+    get_current_transform,
+)))]
+unsafe impl IDWriteTextRenderer for GlyphCounter {
+    unsafe fn draw_glyph_run(
+        &self,
+        _context: *mut c_void,
+        _baseline_origin_x: f32,
+        _baseline_origin_y: f32,
+        _measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: *const DWRITE_GLYPH_RUN,
+        _description: *const DWRITE_GLYPH_RUN_DESCRIPTION,
+        _effect: *mut IUnknown,
+    ) -> HRESULT {
+        let count = (*glyph_run).glyphCount;
+        self.glyphs.fetch_add(count, Ordering::Relaxed);
+        S_OK
+    }
+
+    fn draw_underline(
+        &self,
+        _: *mut c_void,
+        _: f32,
+        _: f32,
+        _: *const DWRITE_UNDERLINE,
+        _: *mut IUnknown,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    fn draw_strikethrough(
+        &self,
+        _: *mut c_void,
+        _: f32,
+        _: f32,
+        _: *const DWRITE_STRIKETHROUGH,
+        _: *mut IUnknown,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    fn draw_inline_object(
+        &self,
+        _: *mut c_void,
+        _: f32,
+        _: f32,
+        _: *mut IDWriteInlineObject,
+        _: BOOL,
+        _: BOOL,
+        _: *mut IUnknown,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    // After the methods of IDWriteTextRenderer, which stay in its own block
+    #[interface(IDWritePixelSnapping)]
+    unsafe fn get_pixels_per_dip(
+        &self,
+        _context: *mut c_void,
+        pixels_per_dip: *mut f32,
+    ) -> HRESULT {
+        *pixels_per_dip = 1.0;
+        S_OK
+    }
+}
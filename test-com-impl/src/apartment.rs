@@ -0,0 +1,61 @@
+use com_impl::apartment::{Affine, Dispatcher};
+use com_impl::{ComBox, Refcount, VTable};
+use std::cell::RefCell;
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+use crate::file_stream::fragment;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct UiStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    dispatcher: Dispatcher,
+    file_data: RefCell<Vec<u8>>,
+}
+
+impl UiStream {
+    pub fn new(dispatcher: Dispatcher, data: Vec<u8>) -> ComBox<UiStream> {
+        UiStream::create(dispatcher, RefCell::new(data))
+    }
+}
+
+impl Affine for UiStream {
+    fn dispatcher(&self) -> &Dispatcher {
+        &self.dispatcher
+    }
+}
+
+#[com_impl::com_impl(apartment)]
+unsafe impl IDWriteFontFileStream for UiStream {
+    pub unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.file_data.borrow().len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        *write_time = 0;
+        S_OK
+    }
+
+    #[panic(result = "E_FAIL")]
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        *start = match fragment(&self.file_data.borrow(), offset, size) {
+            Ok(fragment) => fragment,
+            Err(hr) => return hr,
+        };
+        *ctx = std::ptr::null_mut();
+        S_OK
+    }
+
+    #[panic(abort)]
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use com_impl::{ComBox, VTable, WeakCom};
+use winapi::um::unknwnbase::IUnknownVtbl;
+
+/// Counted by its Arc, so the cache below can observe it without keeping it alive.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[arc]
+pub struct Glyph {
+    vtbl: VTable<IUnknownVtbl>,
+    pub code_point: char,
+}
+
+/// Glyphs that are still alive, created again once they are gone.
+#[derive(Default)]
+pub struct GlyphCache {
+    live: Mutex<HashMap<char, WeakCom<Glyph>>>,
+}
+
+impl GlyphCache {
+    pub fn get(&self, code_point: char) -> ComBox<Glyph> {
+        let mut live = self.live.lock().unwrap();
+        if let Some(glyph) = live.get(&code_point).and_then(WeakCom::upgrade) {
+            return glyph;
+        }
+        let (glyph, weak) = Glyph::create_raw_weak(code_point);
+        live.insert(code_point, weak);
+        unsafe { ComBox::adopt(glyph) }
+    }
+}
@@ -0,0 +1,77 @@
+use com_impl::{ComBox, Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{BOOL, FALSE, FLOAT};
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+use winapi::um::dwrite::{IDWritePixelSnapping, IDWritePixelSnappingVtbl, DWRITE_MATRIX};
+
+/// Pixel snapping for a display whose DPI may not be known yet. Asking for the pixels per DIP
+/// before then panics inside the body, which the stub answers with E_FAIL.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IDWritePixelSnapping)]
+pub struct Display {
+    vtbl: VTable<IDWritePixelSnappingVtbl>,
+    refcount: Refcount,
+    dpi: Option<FLOAT>,
+}
+
+impl Display {
+    pub fn new(dpi: Option<FLOAT>) -> ComBox<Display> {
+        Display::create(dpi)
+    }
+
+    /// Calls GetPixelsPerDip through the vtable, as DirectWrite would.
+    pub fn pixels_per_dip(&self) -> Result<FLOAT, HRESULT> {
+        let snapping = self as *const Display as *mut IDWritePixelSnapping;
+        let mut pixels_per_dip = 0.0;
+        let hr = unsafe {
+            ((*(*snapping).lpVtbl).GetPixelsPerDip)(
+                snapping,
+                std::ptr::null_mut(),
+                &mut pixels_per_dip,
+            )
+        };
+        match hr {
+            S_OK => Ok(pixels_per_dip),
+            hr => Err(hr),
+        }
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWritePixelSnapping for Display {
+    unsafe fn is_pixel_snapping_disabled(
+        &self,
+        _context: *mut c_void,
+        is_disabled: *mut BOOL,
+    ) -> HRESULT {
+        *is_disabled = FALSE;
+        S_OK
+    }
+
+    unsafe fn get_current_transform(
+        &self,
+        _context: *mut c_void,
+        transform: *mut DWRITE_MATRIX,
+    ) -> HRESULT {
+        *transform = DWRITE_MATRIX {
+            m11: 1.0,
+            m12: 0.0,
+            m21: 0.0,
+            m22: 1.0,
+            dx: 0.0,
+            dy: 0.0,
+        };
+        S_OK
+    }
+
+    #[panic(result = E_FAIL)]
+    unsafe fn get_pixels_per_dip(
+        &self,
+        _context: *mut c_void,
+        pixels_per_dip: *mut FLOAT,
+    ) -> HRESULT {
+        *pixels_per_dip = self.dpi.expect("the DPI of the display is not known yet") / 96.0;
+        S_OK
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::aggregation::Aggregation;
+use com_impl::class_factory::ClassFactory;
+use com_impl::local_server::run_com_server;
+use com_impl::prelude::*;
+use com_impl::registration::{Class, ThreadingModel};
+use com_impl::server_lock::ServerLock;
+use winapi::um::unknwnbase::IClassFactory;
+
+pub const CLSID_COUNTER: GUID = GUID {
+    Data1: 0x6c4f_0b2e,
+    Data2: 0x52a1,
+    Data3: 0x4d8e,
+    Data4: [0xb3, 0x07, 0x1e, 0x9a, 0x40, 0x5c, 0x77, 0xd2],
+};
+
+pub const CLSID_SETTINGS: GUID = GUID {
+    Data1: 0x0f3d_8a61,
+    Data2: 0xc9b4,
+    Data3: 0x4e27,
+    Data4: [0x8d, 0x5a, 0x63, 0x21, 0xfe, 0x0b, 0x94, 0x1c],
+};
+
+/// Created by its factory with a zeroed count; can't be aggregated. Keeps its server loaded.
+#[repr(C)]
+#[derive(ComImpl)]
+#[class_factory]
+pub struct Counter {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    lock: ServerLock,
+    count: AtomicU32,
+}
+
+impl Counter {
+    pub fn increment(&self) -> u32 {
+        self.count.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// Aggregated when its factory is given an outer object.
+#[repr(C)]
+#[derive(ComImpl)]
+#[class_factory]
+pub struct Settings {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    aggregation: Aggregation,
+    values: Vec<(String, String)>,
+}
+
+pub fn factories() -> (ComPtr<IClassFactory>, ComPtr<IClassFactory>) {
+    (
+        ClassFactory::<Counter>::new(),
+        ClassFactory::<Settings>::new(),
+    )
+}
+
+com_impl::com_server! {
+    CLSID_COUNTER => ClassFactory<Counter>: Class::new(CLSID_COUNTER, "Counter")
+        .prog_id("Test.Counter.1")
+        .version_independent_prog_id("Test.Counter")
+        .threading_model(ThreadingModel::Both),
+    CLSID_SETTINGS => ClassFactory<Settings>: Class::new(CLSID_SETTINGS, "Settings"),
+}
+
+/// Runs the process as the local server of both classes.
+pub fn serve() -> Result<(), HRESULT> {
+    run_com_server(&[
+        (&CLSID_COUNTER, ClassFactory::<Counter>::new),
+        (&CLSID_SETTINGS, ClassFactory::<Settings>::new),
+    ])
+}
@@ -0,0 +1,64 @@
+#![allow(non_snake_case)]
+
+use std::mem;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use com::interfaces::IUnknown;
+use com::sys::{HRESULT, S_OK};
+use com_impl::{Refcount, VTable};
+
+com::interfaces! {
+    #[uuid("9c51e0d4-7a2b-4f63-8e15-3bd6a1c09f42")]
+    pub unsafe interface IValue: IUnknown {
+        pub fn GetValue(&self, value: *mut i32) -> HRESULT;
+        pub fn SetValue(&self, value: i32) -> HRESULT;
+    }
+
+    #[uuid("e4a7c310-5f2d-4b8e-96a1-0d3c7b5e2f84")]
+    pub unsafe interface ILabel: IUnknown {
+        pub fn GetLength(&self, length: *mut u32) -> HRESULT;
+    }
+}
+
+/// Implements interfaces declared with `com::interfaces!`.
+#[com_impl::com_impl(bindings = com)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IValue)]
+pub struct Cell {
+    vtbl: VTable<IValueVTable>,
+    refcount: Refcount,
+    label_vtbl: VTable<ILabelVTable>,
+    value: AtomicI32,
+    label: String,
+}
+
+impl Cell {
+    pub fn new(value: i32, label: String) -> IValue {
+        let ptr = Cell::create_raw(value.into(), label);
+        // An interface of the `com` crate is a transparent wrapper of its pointer
+        unsafe { mem::transmute(NonNull::new_unchecked(ptr)) }
+    }
+}
+
+#[com_impl::com_impl(bindings = com)]
+unsafe impl IValue for Cell {
+    unsafe fn get_value(&self, value: *mut i32) -> HRESULT {
+        *value = self.value.load(Ordering::Relaxed);
+        S_OK
+    }
+
+    pub fn set_value(&self, value: i32) -> HRESULT {
+        self.value.store(value, Ordering::Relaxed);
+        S_OK
+    }
+}
+
+#[com_impl::com_impl(bindings = com, member = label_vtbl)]
+unsafe impl ILabel for Cell {
+    unsafe fn get_length(&self, length: *mut u32) -> HRESULT {
+        *length = self.label.len() as u32;
+        S_OK
+    }
+}
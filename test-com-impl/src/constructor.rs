@@ -0,0 +1,24 @@
+use winapi::um::unknwnbase::IUnknown;
+use wio::com::ComPtr;
+
+mod token {
+    use com_impl::{Refcount, VTable};
+    use winapi::um::unknwnbase::IUnknownVtbl;
+
+    /// Created by the parent module through the renamed `create_raw`.
+    #[repr(C)]
+    #[com_impl::com_impl(constructor(name = alloc_raw, vis = pub(crate)))]
+    #[derive(com_impl::ComImpl)]
+    pub struct Token {
+        vtbl: VTable<IUnknownVtbl>,
+        refcount: Refcount,
+        pub id: u64,
+    }
+}
+
+pub use self::token::Token;
+
+pub fn issue(id: u64) -> ComPtr<IUnknown> {
+    let ptr = Token::alloc_raw(id);
+    unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+}
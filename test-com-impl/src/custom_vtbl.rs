@@ -0,0 +1,134 @@
+#![allow(non_snake_case)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::prelude::*;
+
+/// Hand-written bindings, whose vtable isn't named `ICounterVtbl`.
+#[repr(C)]
+pub struct ICounter {
+    pub lpVtbl: *const CounterFunctions,
+}
+
+#[repr(C)]
+pub struct CounterFunctions {
+    pub parent: IUnknownVtbl,
+    pub Increment: unsafe extern "system" fn(This: *mut ICounter, count: *mut ULONG) -> HRESULT,
+}
+
+impl Interface for ICounter {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0x2d8f_4c61,
+            Data2: 0x9b3e,
+            Data3: 0x4a07,
+            Data4: [0x85, 0xd2, 0x6e, 0x1c, 0x90, 0x3b, 0xa4, 0x5f],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(ICounter)]
+pub struct Counter {
+    vtbl: VTable<CounterFunctions>,
+    refcount: Refcount,
+    count: AtomicU32,
+}
+
+impl Counter {
+    pub fn new() -> *mut ICounter {
+        Counter::create_raw(0.into()) as *mut ICounter
+    }
+}
+
+#[com_impl(vtbl = CounterFunctions)]
+unsafe impl ICounter for Counter {
+    unsafe fn increment(&self, count: *mut ULONG) -> HRESULT {
+        *count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        S_OK
+    }
+}
+
+/// Hand-written bindings keeping their vtables in a module of their own.
+pub mod vtables {
+    use super::{IGauge, IReset};
+    use com_impl::prelude::*;
+
+    #[repr(C)]
+    pub struct IGaugeTable {
+        pub parent: IUnknownVtbl,
+        pub GetLevel: unsafe extern "system" fn(This: *mut IGauge, level: *mut ULONG) -> HRESULT,
+    }
+
+    #[repr(C)]
+    pub struct IResetTable {
+        pub parent: IUnknownVtbl,
+        pub Reset: unsafe extern "system" fn(This: *mut IReset) -> HRESULT,
+    }
+}
+
+#[repr(C)]
+pub struct IGauge {
+    pub lpVtbl: *const vtables::IGaugeTable,
+}
+
+impl Interface for IGauge {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0x7a3c_e0b9,
+            Data2: 0x14d5,
+            Data3: 0x4f28,
+            Data4: [0x9e, 0x61, 0x3b, 0x0a, 0xd7, 0x42, 0xc8, 0x15],
+        }
+    }
+}
+
+#[repr(C)]
+pub struct IReset {
+    pub lpVtbl: *const vtables::IResetTable,
+}
+
+impl Interface for IReset {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0xc5b1_08f2,
+            Data2: 0x6a4e,
+            Data3: 0x4d93,
+            Data4: [0xb2, 0x7f, 0x50, 0xe8, 0x1d, 0x6c, 0x39, 0xa4],
+        }
+    }
+}
+
+/// Finds its interfaces from vtables named by `vtbl_name` rather than `#[interfaces]`.
+#[com_impl(vtbl_name = "vtables::{}Table")]
+#[repr(C)]
+#[derive(ComImpl)]
+pub struct Gauge {
+    vtbl: VTable<vtables::IGaugeTable>,
+    refcount: Refcount,
+    reset_vtbl: VTable<vtables::IResetTable>,
+    level: AtomicU32,
+}
+
+impl Gauge {
+    pub fn new(level: u32) -> *mut IGauge {
+        Gauge::create_raw(level.into()) as *mut IGauge
+    }
+}
+
+#[com_impl(vtbl_name = "vtables::{}Table")]
+unsafe impl IGauge for Gauge {
+    unsafe fn get_level(&self, level: *mut ULONG) -> HRESULT {
+        *level = self.level.load(Ordering::Relaxed);
+        S_OK
+    }
+}
+
+#[com_impl(vtbl_name = "vtables::{}Table", member = reset_vtbl)]
+unsafe impl IReset for Gauge {
+    fn reset(&self) -> HRESULT {
+        self.level.store(0, Ordering::Relaxed);
+        S_OK
+    }
+}
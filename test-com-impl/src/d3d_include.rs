@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+
+use com_impl::VTable;
+use winapi::shared::minwindef::{LPCVOID, UINT};
+use winapi::shared::winerror::{E_FAIL, HRESULT, S_OK};
+use winapi::um::d3dcommon::{ID3DInclude, ID3DIncludeVtbl, D3D_INCLUDE_TYPE};
+use winapi::um::winnt::LPCSTR;
+
+/// Resolves the `#include`s of a shader from files held in memory. The compiler holds the
+/// pointer only for the duration of the compile call, so it needs no reference counting.
+#[repr(C)]
+#[derive(com_impl::ComVtbl)]
+pub struct ShaderIncludes {
+    vtbl: VTable<ID3DIncludeVtbl>,
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl ShaderIncludes {
+    pub fn new(files: HashMap<String, Vec<u8>>) -> Self {
+        ShaderIncludes::create_callback(files)
+    }
+
+    /// The `pInclude` argument of `D3DCompile`.
+    pub fn as_include(&self) -> *mut ID3DInclude {
+        self.as_callback()
+    }
+}
+
+#[com_impl::com_impl(no_iunknown)]
+unsafe impl ID3DInclude for ShaderIncludes {
+    unsafe fn open(
+        &self,
+        _include_type: D3D_INCLUDE_TYPE,
+        file_name: LPCSTR,
+        _parent_data: LPCVOID,
+        data: *mut LPCVOID,
+        bytes: *mut UINT,
+    ) -> HRESULT {
+        let file_name = CStr::from_ptr(file_name).to_string_lossy();
+        match self.files.get(&*file_name) {
+            Some(file) => {
+                *data = file.as_ptr() as LPCVOID;
+                *bytes = file.len() as UINT;
+                S_OK
+            }
+            None => E_FAIL,
+        }
+    }
+
+    fn close(&self, _data: LPCVOID) -> HRESULT {
+        // The files stay with the object
+        S_OK
+    }
+}
@@ -0,0 +1,21 @@
+use com_impl::{Refcount, VTable};
+use winapi::um::dwrite::IDWriteFontFileStream;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+/// Answers IDWriteFontFileStream with the stream it holds, once it has one.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[delegate(IDWriteFontFileStream = stream)]
+pub struct Font {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    stream: Option<ComPtr<IDWriteFontFileStream>>,
+}
+
+impl Font {
+    pub fn new(stream: Option<ComPtr<IDWriteFontFileStream>>) -> ComPtr<IUnknown> {
+        let ptr = Font::create_raw(stream);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
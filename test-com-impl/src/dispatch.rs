@@ -0,0 +1,54 @@
+use com_impl::prelude::*;
+use std::sync::Mutex;
+use winapi::um::oaidl::{IDispatch, IDispatchVtbl};
+
+/// Called by scripts as `greeter.Greet("world")` and `greeter.Greeting = "Hi"`.
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(IDispatch)]
+pub struct Greeter {
+    vtbl: VTable<IDispatchVtbl>,
+    refcount: Refcount,
+    greeting: Mutex<String>,
+}
+
+impl Greeter {
+    pub fn new() -> ComPtr<IDispatch> {
+        let ptr = Greeter::create_raw(Mutex::new("Hello".into()));
+        unsafe { ComPtr::from_raw(ptr as *mut IDispatch) }
+    }
+}
+
+#[com_impl(dispatch)]
+impl Greeter {
+    #[dispid(1)]
+    fn greet(&self, name: String, times: Option<i32>) -> String {
+        let greeting = format!("{}, {}!", self.greeting.lock().unwrap(), name);
+        greeting.repeat(times.unwrap_or(1).max(0) as usize)
+    }
+
+    #[dispid(2)]
+    fn greeting(&self) -> String {
+        self.greeting.lock().unwrap().clone()
+    }
+
+    #[dispid(2, propput)]
+    fn set_greeting(&self, value: String) -> Result<(), HRESULT> {
+        if value.is_empty() {
+            return Err(E_INVALIDARG);
+        }
+        *self.greeting.lock().unwrap() = value;
+        Ok(())
+    }
+
+    #[dispid(3)]
+    #[com_name = Reset]
+    fn reset_greeting(&self) {
+        *self.greeting.lock().unwrap() = "Hello".into();
+    }
+
+    /// Not exposed to scripts.
+    pub fn greeting_len(&self) -> usize {
+        self.greeting.lock().unwrap().len()
+    }
+}
@@ -0,0 +1,84 @@
+use com_impl::prelude::*;
+use std::sync::Mutex;
+use winapi::shared::wtypes::BSTR;
+use winapi::um::oaidl::IDispatch;
+use winapi::um::oleauto::{SysAllocStringLen, SysStringLen};
+
+use self::ffi::{ICalc, ICalcVtbl};
+
+#[allow(non_snake_case)]
+pub mod ffi {
+    use winapi::shared::winerror::HRESULT;
+    use winapi::shared::wtypes::BSTR;
+    use winapi::um::oaidl::{IDispatch, IDispatchVtbl};
+    use winapi::um::unknwnbase::IUnknown;
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0x3f8e61a4, 0x9c2d, 0x4b7e, 0xa1, 0x55, 0x0e, 0x7c, 0x92, 0x4d, 0x18, 0xb3)]
+    interface ICalc(ICalcVtbl): IDispatch(IDispatchVtbl) {
+        fn Add(a: i32, b: i32, sum: *mut i32,) -> HRESULT,
+        fn get_Name(name: *mut BSTR,) -> HRESULT,
+        fn put_Name(name: BSTR,) -> HRESULT,
+        fn Attach(object: *mut IUnknown,) -> HRESULT,
+        fn Clear() -> HRESULT,
+    }}
+}
+
+/// Callable through `ICalc` directly and by scripts through `IDispatch`.
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(ICalc, IDispatch)]
+pub struct Calculator {
+    vtbl: VTable<ICalcVtbl>,
+    refcount: Refcount,
+    name: Mutex<Vec<u16>>,
+    attached: Mutex<Option<ComPtr<IUnknown>>>,
+}
+
+impl Calculator {
+    pub fn new() -> ComPtr<ICalc> {
+        let ptr = Calculator::create_raw(Default::default(), Default::default());
+        unsafe { ComPtr::from_raw(ptr as *mut ICalc) }
+    }
+}
+
+#[com_impl(dispatch)]
+unsafe impl ICalc for Calculator {
+    #[dispid(1)]
+    unsafe fn add(&self, a: i32, b: i32, sum: *mut i32) -> HRESULT {
+        *sum = a.wrapping_add(b);
+        S_OK
+    }
+
+    #[dispid(2)]
+    #[com_name = get_Name]
+    unsafe fn get_name(&self, name: *mut BSTR) -> HRESULT {
+        let current = self.name.lock().unwrap();
+        *name = SysAllocStringLen(current.as_ptr(), current.len() as u32);
+        S_OK
+    }
+
+    #[dispid(2, propput)]
+    #[com_name = put_Name]
+    unsafe fn put_name(&self, name: BSTR) -> HRESULT {
+        let name = std::slice::from_raw_parts(name, SysStringLen(name) as usize);
+        *self.name.lock().unwrap() = name.to_vec();
+        S_OK
+    }
+
+    #[dispid(3)]
+    unsafe fn attach(&self, object: *mut IUnknown) -> HRESULT {
+        if object.is_null() {
+            return E_POINTER;
+        }
+        (*object).AddRef();
+        *self.attached.lock().unwrap() = Some(ComPtr::from_raw(object));
+        S_OK
+    }
+
+    // Only early-bound callers can clear
+    fn clear(&self) -> HRESULT {
+        *self.attached.lock().unwrap() = None;
+        S_OK
+    }
+}
@@ -0,0 +1,63 @@
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+use wio::com::ComPtr;
+
+use crate::file_stream::fragment;
+
+com_impl::dyn_object! {
+    /// A font file stream for any `FontStream`, sharing one vtable.
+    pub struct PluginStream: IDWriteFontFileStream(IDWriteFontFileStreamVtbl) => dyn FontStream {
+        unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT;
+        unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT;
+        unsafe fn read_file_fragment(
+            &self,
+            start: *mut *const c_void,
+            offset: u64,
+            size: u64,
+            ctx: *mut *mut c_void,
+        ) -> HRESULT;
+        unsafe fn release_file_fragment(&self, ctx: *mut c_void);
+    }
+}
+
+/// A font a plugin keeps in memory.
+pub struct MemoryFont {
+    data: Vec<u8>,
+    write_time: u64,
+}
+
+impl MemoryFont {
+    pub fn load(data: Vec<u8>, write_time: u64) -> ComPtr<IDWriteFontFileStream> {
+        PluginStream::new(Box::new(MemoryFont { data, write_time }))
+    }
+}
+
+impl FontStream for MemoryFont {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.data.len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        *write_time = self.write_time;
+        S_OK
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        *start = match fragment(&self.data, offset, size) {
+            Ok(fragment) => fragment,
+            Err(hr) => return hr,
+        };
+        *ctx = std::ptr::null_mut();
+        S_OK
+    }
+
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
@@ -0,0 +1,67 @@
+use com_impl::{ComBox, InterfaceFamily, Refcount, VTable};
+
+use self::ffi::{ICounter, ICounter1, ICounter1Vtbl, ICounter2, ICounter2Vtbl, ICounterVtbl};
+
+#[allow(non_snake_case)]
+pub mod ffi {
+    use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+    use winapi::RIDL;
+
+    RIDL! {#[uuid(0x6c2e6a55, 0x1b53, 0x4cf4, 0x8d, 0x0b, 0x3a, 0x6f, 0x1d, 0x92, 0x57, 0x20)]
+    interface ICounter(ICounterVtbl): IUnknown(IUnknownVtbl) {
+        fn Get() -> u32,
+    }}
+
+    RIDL! {#[uuid(0x6c2e6a55, 0x1b53, 0x4cf4, 0x8d, 0x0b, 0x3a, 0x6f, 0x1d, 0x92, 0x57, 0x21)]
+    interface ICounter1(ICounter1Vtbl): ICounter(ICounterVtbl) {
+        fn Increment() -> u32,
+    }}
+
+    RIDL! {#[uuid(0x6c2e6a55, 0x1b53, 0x4cf4, 0x8d, 0x0b, 0x3a, 0x6f, 0x1d, 0x92, 0x57, 0x22)]
+    interface ICounter2(ICounter2Vtbl): ICounter1(ICounter1Vtbl) {
+        fn Reset() -> (),
+    }}
+}
+
+/// Its methods are tiny, so the stubs are left for the compiler to inline, e.g. across crates
+/// under LTO.
+#[repr(C)]
+#[com_impl::com_impl(inline = "default")]
+#[derive(com_impl::ComImpl)]
+#[interfaces(family(ICounter..=ICounter2))]
+pub struct Counter {
+    vtbl: VTable<ICounter2Vtbl>,
+    refcount: Refcount,
+    value: std::sync::atomic::AtomicU32,
+}
+
+impl Counter {
+    pub fn new() -> ComBox<Counter> {
+        Counter::create(Default::default())
+    }
+
+    pub fn highest_supported() -> u32 {
+        <Counter as InterfaceFamily<ICounter>>::highest_supported()
+    }
+}
+
+#[com_impl::com_impl(inline = "default")]
+unsafe impl ICounter for Counter {
+    fn get(&self) -> u32 {
+        self.value.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[com_impl::com_impl(inline = "default")]
+unsafe impl ICounter1 for Counter {
+    fn increment(&self) -> u32 {
+        self.value.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1
+    }
+}
+
+#[com_impl::com_impl(inline = always)]
+unsafe impl ICounter2 for Counter {
+    fn reset(&self) {
+        self.value.store(0, std::sync::atomic::Ordering::SeqCst);
+    }
+}
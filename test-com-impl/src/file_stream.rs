@@ -6,6 +6,7 @@ use wio::com::ComPtr;
 
 #[repr(C)]
 #[derive(com_impl::ComImpl)]
+#[builder]
 pub struct FileStream {
     vtbl: VTable<IDWriteFontFileStreamVtbl>,
     refcount: Refcount,
@@ -20,21 +21,34 @@ impl FileStream {
         let ptr = ptr as *mut IDWriteFontFileStream;
         unsafe { ComPtr::from_raw(ptr) }
     }
+
+    /// The size of the data, through the inherent wrapper of GetFileSize rather than the
+    /// vtable.
+    pub fn size(&self) -> u64 {
+        // The wrapper provides the pointer GetFileSize writes to
+        unsafe { self.get_file_size() }.unwrap_or(0)
+    }
 }
 
 #[com_impl::com_impl]
 unsafe impl IDWriteFontFileStream for FileStream {
-    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+    /// Fragments point into `file_data`, so there is nothing to release them with.
+    const NO_CONTEXT: *mut c_void = std::ptr::null_mut();
+
+    /// The size of the file's data, in bytes.
+    pub unsafe fn get_file_size(&self, #[out] size: *mut u64) -> HRESULT {
         *size = self.file_data.len() as u64;
         S_OK
     }
 
+    #[inline]
     unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
         *write_time = self.write_time;
         S_OK
     }
 
     #[panic(result = "E_FAIL")]
+    #[allow(clippy::ptr_offset_with_cast)]
     unsafe fn read_file_fragment(
         &self,
         start: *mut *const c_void,
@@ -42,19 +56,14 @@ unsafe impl IDWriteFontFileStream for FileStream {
         size: u64,
         ctx: *mut *mut c_void,
     ) -> HRESULT {
-        if offset > std::isize::MAX as u64 || size > std::isize::MAX as u64 {
+        if !self.in_bounds(offset, size) {
             return HRESULT_FROM_WIN32(ERROR_INVALID_INDEX);
         }
 
         let offset = offset as usize;
-        let size = size as usize;
-
-        if offset + size > self.file_data.len() {
-            return HRESULT_FROM_WIN32(ERROR_INVALID_INDEX);
-        }
 
         *start = self.file_data.as_ptr().offset(offset as isize) as *const c_void;
-        *ctx = std::ptr::null_mut();
+        *ctx = Self::NO_CONTEXT;
 
         S_OK
     }
@@ -63,4 +72,23 @@ unsafe impl IDWriteFontFileStream for FileStream {
     unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {
         // Nothing to do
     }
+
+    #[com_skip]
+    fn in_bounds(&self, offset: u64, size: u64) -> bool {
+        match offset.checked_add(size) {
+            Some(end) => end <= self.file_data.len() as u64,
+            None => false,
+        }
+    }
+}
+
+/// The start of the `size` bytes at `offset` in `data`, or ERROR_INVALID_INDEX if they run
+/// past its end. For the streams of the other fixtures.
+pub fn fragment(data: &[u8], offset: u64, size: u64) -> Result<*const c_void, HRESULT> {
+    match offset.checked_add(size) {
+        Some(end) if end <= data.len() as u64 => {
+            Ok(data[offset as usize..].as_ptr() as *const c_void)
+        }
+        _ => Err(HRESULT_FROM_WIN32(ERROR_INVALID_INDEX)),
+    }
 }
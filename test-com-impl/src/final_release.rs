@@ -0,0 +1,48 @@
+use std::cell::RefCell;
+
+use com_impl::{Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+const POOL_SIZE: usize = 4;
+
+thread_local! {
+    static POOL: RefCell<Vec<*mut Buffer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Returns itself to a pool on its final Release, rather than freeing its allocation.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[final_release(Self::recycle)]
+pub struct Buffer {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub data: RefCell<Vec<u8>>,
+}
+
+impl Buffer {
+    pub fn get() -> ComPtr<IUnknown> {
+        let ptr = match POOL.with(|pool| pool.borrow_mut().pop()) {
+            // Pooled buffers are revived from a count of zero
+            Some(ptr) => unsafe {
+                let ptr = ptr as *mut IUnknown;
+                (*ptr).AddRef();
+                ptr
+            },
+            None => Buffer::create_raw(RefCell::new(Vec::with_capacity(4096))) as *mut IUnknown,
+        };
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    fn recycle(&self) -> bool {
+        POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() == POOL_SIZE {
+                return true;
+            }
+            self.data.borrow_mut().clear();
+            pool.push(self as *const Buffer as *mut Buffer);
+            false
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{E_INVALIDARG, HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileLoader, IDWriteFontFileLoaderVtbl, IDWriteFontFileStream};
+use wio::com::ComPtr;
+
+use crate::file_stream::FileStream;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct FontLoader {
+    vtbl: VTable<IDWriteFontFileLoaderVtbl>,
+    refcount: Refcount,
+    write_time: u64,
+    fonts: Vec<Vec<u8>>,
+    #[com_default]
+    streams_created: AtomicU32,
+}
+
+impl FontLoader {
+    pub fn new(write_time: u64, fonts: Vec<Vec<u8>>) -> ComPtr<IDWriteFontFileLoader> {
+        let ptr = FontLoader::create_raw(write_time, fonts);
+        let ptr = ptr as *mut IDWriteFontFileLoader;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    pub fn streams_created(&self) -> u32 {
+        self.streams_created.load(Ordering::Relaxed)
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileLoader for FontLoader {
+    #[panic(result = "E_INVALIDARG")]
+    unsafe fn create_stream_from_key(
+        &self,
+        key: *const c_void,
+        key_size: u32,
+        stream: *mut *mut IDWriteFontFileStream,
+    ) -> HRESULT {
+        if key_size as usize != std::mem::size_of::<usize>() {
+            return E_INVALIDARG;
+        }
+
+        // An item of the body with a `self` of its own
+        struct Key(usize);
+        impl Key {
+            fn index(&self) -> usize {
+                self.0
+            }
+        }
+
+        let index = Key(*(key as *const usize)).index();
+        let data = match self.fonts.get(index) {
+            Some(data) => data.clone(),
+            None => return E_INVALIDARG,
+        };
+
+        *stream = FileStream::new(self.write_time, data).into_raw();
+        self.streams_created.fetch_add(1, Ordering::Relaxed);
+        S_OK
+    }
+}
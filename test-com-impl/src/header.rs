@@ -0,0 +1,52 @@
+use com_impl::header::HeaderBuilder;
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+use crate::file_stream::fragment;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(order(winapi::um::dwrite::IDWriteFontFileStream))]
+pub struct DescribedStream<T: AsRef<[u8]>> {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    data: T,
+}
+
+#[com_impl::com_impl(describe, parent = IUnknown)]
+unsafe impl<T: AsRef<[u8]>> IDWriteFontFileStream for DescribedStream<T> {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.data.as_ref().len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        *write_time = 0;
+        S_OK
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        *start = match fragment(self.data.as_ref(), offset, size) {
+            Ok(fragment) => fragment,
+            Err(hr) => return hr,
+        };
+        *ctx = std::ptr::null_mut();
+        S_OK
+    }
+
+    unsafe fn release_file_fragment(&self, _: *mut c_void) {}
+}
+
+pub fn header() -> String {
+    HeaderBuilder::new()
+        .interface::<IDWriteFontFileStream, DescribedStream<Vec<u8>>>()
+        .build()
+}
@@ -0,0 +1,60 @@
+use com_impl::hot_reload::{Generation, VTableSlot};
+use com_impl::{BuildVTable, Refcount, VTable};
+use std::sync::Mutex;
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{E_NOTIMPL, HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+use wio::com::ComPtr;
+
+pub static SLOT: VTableSlot<IDWriteFontFileStreamVtbl> =
+    VTableSlot::new(<ReloadableStream as BuildVTable<_>>::VTBL);
+
+#[com_impl::com_impl(hot_reload = SLOT)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct ReloadableStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    generation: Generation,
+    data: Mutex<Vec<u8>>,
+}
+
+impl ReloadableStream {
+    pub fn new(data: Vec<u8>) -> ComPtr<IDWriteFontFileStream> {
+        let ptr = ReloadableStream::create_raw(Generation::current(&SLOT), Mutex::new(data));
+        unsafe { ComPtr::from_raw(ptr as *mut IDWriteFontFileStream) }
+    }
+
+    fn len(&self) -> u64 {
+        let mut data = self.data.lock().unwrap();
+        // A reload drops everything past the first kilobyte
+        self.generation.migrate(&SLOT, |_| data.truncate(1024));
+        data.len() as u64
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileStream for ReloadableStream {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.len();
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, _write_time: *mut u64) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    // Fragments would point into `data`, which the next reload may truncate while DirectWrite
+    // still holds them, so this stream doesn't hand any out
+    unsafe fn read_file_fragment(
+        &self,
+        _start: *mut *const c_void,
+        _offset: u64,
+        _size: u64,
+        _ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
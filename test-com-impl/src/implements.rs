@@ -0,0 +1,31 @@
+use com_impl::{ComBox, ImplementsInterface};
+use winapi::um::dwrite::IDWriteFontFileStream;
+use winapi::um::objidlbase::{IAgileObject, INoMarshal};
+use winapi::um::unknwnbase::IUnknown;
+use wio::com::ComPtr;
+
+use crate::file_stream::FileStream;
+use crate::generic::ComAny;
+use crate::header::DescribedStream;
+use crate::marker::AgileStream;
+use crate::site::Sited;
+
+fn assert_implements<T: ImplementsInterface<I>, I: winapi::Interface>() {}
+
+pub fn check() {
+    assert_implements::<FileStream, IDWriteFontFileStream>();
+    assert_implements::<FileStream, IUnknown>();
+    assert_implements::<ComAny<u32>, IUnknown>();
+    assert_implements::<DescribedStream<Vec<u8>>, IDWriteFontFileStream>();
+    assert_implements::<AgileStream, IDWriteFontFileStream>();
+    assert_implements::<AgileStream, IAgileObject>();
+    assert_implements::<AgileStream, INoMarshal>();
+}
+
+pub fn new_unknown(data: u32) -> ComPtr<IUnknown> {
+    unsafe { into_com_ptr(ComBox::leak(Sited::new(data))) }
+}
+
+unsafe fn into_com_ptr<T: ImplementsInterface<IUnknown>>(raw: *mut T) -> ComPtr<IUnknown> {
+    T::into_com_ptr(raw)
+}
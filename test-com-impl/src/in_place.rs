@@ -0,0 +1,29 @@
+use std::ptr;
+
+use com_impl::{Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+const SIZE: usize = 1024;
+
+/// Too large for the stack, so it is written straight into its allocation.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Atlas {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub pixels: [[u32; SIZE]; SIZE],
+    pub generation: u64,
+}
+
+impl Atlas {
+    pub fn new(generation: u64) -> ComPtr<IUnknown> {
+        let ptr = unsafe {
+            Atlas::create_raw_with(|atlas| {
+                ptr::addr_of_mut!((*atlas).pixels).write_bytes(0, 1);
+                ptr::addr_of_mut!((*atlas).generation).write(generation);
+            })
+        };
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
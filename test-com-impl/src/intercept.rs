@@ -0,0 +1,79 @@
+use com_impl::intercept::{Call, Interceptor};
+use com_impl::{ComBox, Refcount, VTable};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{E_ACCESSDENIED, HRESULT, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+use crate::file_stream::fragment;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct GuardedStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    locked: AtomicBool,
+    calls: AtomicUsize,
+    file_data: Vec<u8>,
+}
+
+impl GuardedStream {
+    pub fn new(data: Vec<u8>) -> ComBox<GuardedStream> {
+        GuardedStream::create(AtomicBool::new(false), AtomicUsize::new(0), data)
+    }
+
+    pub fn set_locked(&self, locked: bool) {
+        self.locked.store(locked, Ordering::SeqCst);
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Interceptor for GuardedStream {
+    fn before(&self, _call: &Call) -> Option<HRESULT> {
+        if self.locked.load(Ordering::SeqCst) {
+            Some(E_ACCESSDENIED)
+        } else {
+            None
+        }
+    }
+
+    fn after(&self, _call: &Call, _result: Option<HRESULT>) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[com_impl::com_impl(intercept)]
+unsafe impl IDWriteFontFileStream for GuardedStream {
+    pub unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.file_data.len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        *write_time = 0;
+        S_OK
+    }
+
+    #[panic(result = E_ACCESSDENIED)]
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        *start = match fragment(&self.file_data, offset, size) {
+            Ok(fragment) => fragment,
+            Err(hr) => return hr,
+        };
+        *ctx = std::ptr::null_mut();
+        S_OK
+    }
+
+    #[com_name = ReleaseFileFragment]
+    #[panic(abort)]
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use com_impl::lazy::Lazy;
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{ERROR_INVALID_INDEX, HRESULT, HRESULT_FROM_WIN32, S_OK};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+/// Reads its file on the first call that needs the contents, not when it's created.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[com_new]
+pub struct DiskStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    path: PathBuf,
+    #[lazy(std::fs::read(&self.path).unwrap_or_default())]
+    data: Lazy<Vec<u8>>,
+}
+
+impl DiskStream {
+    pub fn is_loaded(&self) -> bool {
+        self.data.get().is_some()
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileStream for DiskStream {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = self.data().len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, write_time: *mut u64) -> HRESULT {
+        *write_time = 0;
+        S_OK
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        let data = self.data();
+        match offset.checked_add(size) {
+            Some(end) if end <= data.len() as u64 => {
+                *start = data[offset as usize..].as_ptr() as *const c_void;
+                *ctx = std::ptr::null_mut();
+                S_OK
+            }
+            _ => HRESULT_FROM_WIN32(ERROR_INVALID_INDEX),
+        }
+    }
+
+    fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
@@ -1,2 +1,47 @@
+pub mod aggregation;
+pub mod agile;
+pub mod alloc;
+pub mod ancestors;
+pub mod apartment;
+pub mod arc;
+pub mod caught_panic;
+pub mod class_factory;
+pub mod com_interop;
+pub mod constructor;
+pub mod custom_vtbl;
+pub mod d3d_include;
+pub mod delegate;
+pub mod dispatch;
+pub mod dual;
+pub mod dynamic;
+pub mod family;
 pub mod file_stream;
+pub mod final_release;
+pub mod font_loader;
 pub mod generic;
+pub mod header;
+pub mod hot_reload;
+pub mod implements;
+pub mod in_place;
+pub mod intercept;
+pub mod lazy;
+pub mod local_refcount;
+pub mod manual;
+pub mod marker;
+pub mod marshal;
+pub mod query_hook;
+pub mod reexport;
+pub mod refcount;
+pub mod registration;
+pub mod singleton;
+pub mod site;
+pub mod snapping_loader;
+pub mod stack;
+pub mod stub;
+pub mod tear_off;
+pub mod thrown;
+pub mod unwind;
+pub mod vendored;
+pub mod weak;
+pub mod windows_interop;
+pub mod windows_sys_interop;
@@ -0,0 +1,96 @@
+use std::ptr;
+
+use com_impl::{LocalRefcount, VTable};
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dwrite::{
+    IDWriteNumberSubstitution, IDWriteTextAnalysisSource, IDWriteTextAnalysisSourceVtbl,
+    DWRITE_READING_DIRECTION, DWRITE_READING_DIRECTION_LEFT_TO_RIGHT,
+};
+use winapi::um::winnt::WCHAR;
+use wio::com::ComPtr;
+
+/// Only lives for the duration of an `IDWriteTextAnalyzer` call on one thread, so it counts
+/// without atomics.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IDWriteTextAnalysisSource)]
+pub struct AnalysisSource {
+    vtbl: VTable<IDWriteTextAnalysisSourceVtbl>,
+    refcount: LocalRefcount,
+    text: Vec<WCHAR>,
+    locale: Vec<WCHAR>,
+}
+
+impl AnalysisSource {
+    pub fn new(text: &str, locale: &str) -> ComPtr<IDWriteTextAnalysisSource> {
+        let text = text.encode_utf16().collect();
+        let locale = locale.encode_utf16().chain(Some(0)).collect();
+        let ptr = AnalysisSource::create_raw(text, locale);
+        let ptr = ptr as *mut IDWriteTextAnalysisSource;
+        unsafe { ComPtr::from_raw(ptr) }
+    }
+
+    fn remaining(&self, position: u32) -> u32 {
+        (self.text.len() as u32).saturating_sub(position)
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteTextAnalysisSource for AnalysisSource {
+    unsafe fn get_text_at_position(
+        &self,
+        position: u32,
+        text: *mut *const WCHAR,
+        length: *mut u32,
+    ) -> HRESULT {
+        *length = self.remaining(position);
+        *text = if *length == 0 {
+            ptr::null()
+        } else {
+            self.text.as_ptr().add(position as usize)
+        };
+        S_OK
+    }
+
+    unsafe fn get_text_before_position(
+        &self,
+        position: u32,
+        text: *mut *const WCHAR,
+        length: *mut u32,
+    ) -> HRESULT {
+        if position == 0 || position as usize > self.text.len() {
+            *text = ptr::null();
+            *length = 0;
+        } else {
+            *text = self.text.as_ptr();
+            *length = position;
+        }
+        S_OK
+    }
+
+    fn get_paragraph_reading_direction(&self) -> DWRITE_READING_DIRECTION {
+        DWRITE_READING_DIRECTION_LEFT_TO_RIGHT
+    }
+
+    unsafe fn get_locale_name(
+        &self,
+        position: u32,
+        length: *mut u32,
+        locale: *mut *const WCHAR,
+    ) -> HRESULT {
+        *length = self.remaining(position);
+        *locale = self.locale.as_ptr();
+        S_OK
+    }
+
+    unsafe fn get_number_substitution(
+        &self,
+        position: u32,
+        length: *mut u32,
+        substitution: *mut *mut IDWriteNumberSubstitution,
+    ) -> HRESULT {
+        *length = self.remaining(position);
+        *substitution = ptr::null_mut();
+        S_OK
+    }
+}
@@ -0,0 +1,37 @@
+use com_impl::prelude::*;
+
+/// Lives for the whole program, so references are not counted.
+#[com_impl(iunknown = manual)]
+#[repr(C)]
+#[derive(ComImpl)]
+pub struct Singleton {
+    vtbl: VTable<IUnknownVtbl>,
+    pub name: &'static str,
+}
+
+impl Singleton {
+    pub fn leak(name: &'static str) -> &'static Singleton {
+        unsafe { &*Singleton::create_raw(name) }
+    }
+}
+
+#[com_impl(no_parent)]
+unsafe impl IUnknown for Singleton {
+    unsafe fn query_interface(&self, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+        if IsEqualIID(&*riid, &IUnknown::uuidof()) {
+            *ppv = self as *const Self as *mut c_void;
+            S_OK
+        } else {
+            *ppv = std::ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    fn add_ref(&self) -> ULONG {
+        1
+    }
+
+    fn release(&self) -> ULONG {
+        1
+    }
+}
@@ -0,0 +1,42 @@
+use com_impl::{Refcount, VTable};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+use winapi::um::objidlbase::{IAgileObject, INoMarshal};
+use winapi::um::unknwnbase::IUnknown;
+use wio::com::ComPtr;
+
+/// Answers the marker interfaces next to the interface of its vtable.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(marker(IAgileObject, INoMarshal))]
+#[com_new(new = IUnknown)]
+pub struct AgileStream {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+}
+
+impl AgileStream {
+    /// The stream as the marker interface, which has no vtable of its own.
+    pub fn as_agile(&self) -> Option<ComPtr<IAgileObject>> {
+        self.query_interface()
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWriteFontFileStream for AgileStream {
+    fn get_file_size(&self, _: *mut u64) -> i32 {
+        0
+    }
+    fn get_last_write_time(&self, _: *mut u64) -> i32 {
+        0
+    }
+    fn read_file_fragment(
+        &self,
+        _: *mut *const winapi::ctypes::c_void,
+        _: u64,
+        _: u64,
+        _: *mut *mut winapi::ctypes::c_void,
+    ) -> i32 {
+        0
+    }
+    fn release_file_fragment(&self, _: *mut winapi::ctypes::c_void) {}
+}
@@ -0,0 +1,47 @@
+use com_impl::marshal::MarshalByValue;
+use com_impl::prelude::*;
+
+pub const CLSID_ERROR_INFO: GUID = GUID {
+    Data1: 0x9b2e_4f17,
+    Data2: 0x6a03,
+    Data3: 0x4c59,
+    Data4: [0xa1, 0x8d, 0x37, 0xe0, 0x5f, 0x2c, 0x91, 0x46],
+};
+
+/// Copied to wherever it is marshaled; created empty by its factory to unmarshal a copy.
+#[repr(C)]
+#[derive(ComImpl)]
+#[class_factory]
+#[marshal_by_value]
+pub struct ErrorInfo {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub code: HRESULT,
+    pub message: String,
+}
+
+impl ErrorInfo {
+    pub fn new(code: HRESULT, message: String) -> ComPtr<IUnknown> {
+        let ptr = ErrorInfo::create_raw(code, message);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
+
+impl MarshalByValue for ErrorInfo {
+    const CLSID: GUID = CLSID_ERROR_INFO;
+
+    fn marshal(&self, data: &mut Vec<u8>) -> Result<(), HRESULT> {
+        data.extend_from_slice(&self.code.to_le_bytes());
+        data.extend_from_slice(self.message.as_bytes());
+        Ok(())
+    }
+
+    fn unmarshal(data: &[u8]) -> Result<ComPtr<IUnknown>, HRESULT> {
+        if data.len() < 4 {
+            return Err(E_INVALIDARG);
+        }
+        let code = HRESULT::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        let message = String::from_utf8(data[4..].to_vec()).map_err(|_| E_INVALIDARG)?;
+        Ok(ErrorInfo::new(code, message))
+    }
+}
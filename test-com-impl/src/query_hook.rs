@@ -0,0 +1,44 @@
+use std::cell::Cell;
+
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{IsEqualIID, IID};
+use winapi::um::dwrite::IDWriteFontFileStream;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::Interface;
+use wio::com::ComPtr;
+
+/// Answers IDWriteFontFileStream with the stream it wraps, and counts the IIDs nobody answers.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[query_interface(hook = Self::query_stream, fallback = Self::count_miss)]
+pub struct StreamWrapper {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    stream: ComPtr<IDWriteFontFileStream>,
+    misses: Cell<u32>,
+}
+
+impl StreamWrapper {
+    pub fn new(stream: ComPtr<IDWriteFontFileStream>) -> ComPtr<IUnknown> {
+        let ptr = StreamWrapper::create_raw(stream, Cell::new(0));
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+
+    pub fn misses(&self) -> u32 {
+        self.misses.get()
+    }
+
+    fn query_stream(&self, riid: &IID) -> Option<*mut c_void> {
+        if IsEqualIID(riid, &IDWriteFontFileStream::uuidof()) {
+            Some(self.stream.clone().into_raw() as *mut c_void)
+        } else {
+            None
+        }
+    }
+
+    fn count_miss(&self, _riid: &IID) -> Option<*mut c_void> {
+        self.misses.set(self.misses.get() + 1);
+        None
+    }
+}
@@ -0,0 +1,35 @@
+//! An object naming com-impl only through a re-export, as a crate wrapping it would, from a
+//! module without the prelude.
+#![no_implicit_prelude]
+
+use crate::family::ffi::{ICounter, ICounterVtbl};
+
+/// com-impl, as the crate wrapping it re-exports it.
+pub mod com {
+    pub use ::com_impl::*;
+}
+
+use self::com::{ComBox, Refcount, VTable};
+
+#[repr(C)]
+#[com::com_impl(crate = "crate::reexport::com")]
+#[derive(com::ComImpl)]
+#[interfaces(ICounter)]
+pub struct Constant {
+    vtbl: VTable<ICounterVtbl>,
+    refcount: Refcount,
+    value: u32,
+}
+
+impl Constant {
+    pub fn new(value: u32) -> ComBox<Constant> {
+        Constant::create(value)
+    }
+}
+
+#[com::com_impl(crate = "crate::reexport::com")]
+unsafe impl ICounter for Constant {
+    fn get(&self) -> u32 {
+        self.value
+    }
+}
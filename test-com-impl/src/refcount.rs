@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use com_impl::{ComRefcount, Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+/// The number of `Tracked` objects alive, to find leaks in tests.
+pub static LIVE_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts the objects whose references it counts.
+pub struct LiveRefcount {
+    inner: Refcount,
+}
+
+impl Default for LiveRefcount {
+    fn default() -> Self {
+        LIVE_OBJECTS.fetch_add(1, Ordering::Relaxed);
+        LiveRefcount {
+            inner: Refcount::default(),
+        }
+    }
+}
+
+unsafe impl ComRefcount for LiveRefcount {
+    unsafe fn add_ref(&self) -> u32 {
+        self.inner.add_ref()
+    }
+
+    unsafe fn release(&self) -> u32 {
+        let count = self.inner.release();
+        if count == 0 {
+            LIVE_OBJECTS.fetch_sub(1, Ordering::Relaxed);
+        }
+        count
+    }
+}
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Tracked {
+    vtbl: VTable<IUnknownVtbl>,
+    #[refcount]
+    refcount: LiveRefcount,
+    pub name: String,
+}
+
+impl Tracked {
+    pub fn new(name: String) -> ComPtr<IUnknown> {
+        let ptr = Tracked::create_raw(name);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
+
+/// Checks its reference count in release builds too.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[refcount_check(abort)]
+pub struct Strict {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+}
+
+impl Strict {
+    pub fn new() -> ComPtr<IUnknown> {
+        let ptr = Strict::create_raw();
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::{ComBox, Refcount, VTable};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+
+/// A listener registered with an API that keeps it as an `IUnknown` pointer until it is
+/// unregistered, when it hands the pointer back.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Listener {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    pub events: AtomicU32,
+}
+
+impl Listener {
+    pub fn new() -> ComBox<Listener> {
+        Listener::create(AtomicU32::new(0))
+    }
+
+    /// The pointer to register, carrying its own reference to the listener.
+    pub fn register(this: &ComBox<Listener>) -> *mut IUnknown {
+        Listener::into_raw(this.clone())
+    }
+
+    /// Takes the reference back from a pointer handed out by `register`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `register`, and the API must have given it up.
+    pub unsafe fn unregister(ptr: *mut IUnknown) -> ComBox<Listener> {
+        Listener::from_raw(ptr)
+    }
+
+    pub fn notify(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::VTable;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+static REGISTRY: Registry = Registry::create_static(AtomicU32::new(0));
+
+/// Lives in a static, for the whole program.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[singleton(REGISTRY)]
+pub struct Registry {
+    vtbl: VTable<IUnknownVtbl>,
+    registrations: AtomicU32,
+}
+
+impl Registry {
+    pub fn get() -> ComPtr<IUnknown> {
+        unsafe { ComPtr::from_raw(Registry::global_instance() as *mut IUnknown) }
+    }
+
+    pub fn register(&self) -> u32 {
+        self.registrations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
@@ -0,0 +1,22 @@
+use com_impl::site::ObjectWithSite;
+use com_impl::{ComBox, Refcount, VTable};
+use winapi::um::unknwnbase::IUnknownVtbl;
+
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Sited<T: Sized> {
+    vtbl: VTable<IUnknownVtbl>,
+    site: ObjectWithSite,
+    refcount: Refcount,
+    pub data: T,
+}
+
+impl<T> Sited<T> {
+    pub fn new(data: T) -> ComBox<Sited<T>> {
+        Sited::create(data)
+    }
+
+    pub fn has_site(&self) -> bool {
+        self.site.is_set()
+    }
+}
@@ -0,0 +1,71 @@
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{BOOL, FALSE, FLOAT};
+use winapi::shared::winerror::{E_FAIL, E_NOTIMPL, HRESULT, S_OK};
+use winapi::um::dwrite::{
+    IDWriteFontFileLoader, IDWriteFontFileLoaderVtbl, IDWriteFontFileStream, IDWritePixelSnapping,
+    IDWritePixelSnappingVtbl, DWRITE_MATRIX,
+};
+use wio::com::ComPtr;
+
+/// Answers `IDWritePixelSnapping` from a second vtable.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[com_new]
+pub struct SnappingLoader {
+    vtbl: VTable<IDWriteFontFileLoaderVtbl>,
+    refcount: Refcount,
+    snapping: VTable<IDWritePixelSnappingVtbl>,
+    pixels_per_dip: f32,
+}
+
+impl SnappingLoader {
+    /// The scale of the loader behind a snapping interface it handed out, which comes back
+    /// from DirectWrite as any other object's would.
+    pub fn scale_of(snapping: &ComPtr<IDWritePixelSnapping>) -> Option<f32> {
+        SnappingLoader::from_interface(snapping).map(|loader| loader.pixels_per_dip)
+    }
+}
+
+#[com_impl::com_impl(panic = abort)]
+unsafe impl IDWriteFontFileLoader for SnappingLoader {
+    unsafe fn create_stream_from_key(
+        &self,
+        _key: *const c_void,
+        _key_size: u32,
+        _stream: *mut *mut IDWriteFontFileStream,
+    ) -> HRESULT {
+        E_NOTIMPL
+    }
+}
+
+#[com_impl::com_impl(member = snapping, panic_result = E_FAIL)]
+unsafe impl IDWritePixelSnapping for SnappingLoader {
+    unsafe fn is_pixel_snapping_disabled(&self, _ctx: *mut c_void, disabled: *mut BOOL) -> HRESULT {
+        *disabled = FALSE;
+        S_OK
+    }
+
+    #[cfg(feature = "transform")]
+    unsafe fn get_current_transform(
+        &self,
+        _ctx: *mut c_void,
+        transform: *mut DWRITE_MATRIX,
+    ) -> HRESULT {
+        *transform = DWRITE_MATRIX {
+            m11: 1.0,
+            m12: 0.0,
+            m21: 0.0,
+            m22: 1.0,
+            dx: 0.0,
+            dy: 0.0,
+        };
+        S_OK
+    }
+
+    #[panic(abort)]
+    unsafe fn get_pixels_per_dip(&self, _ctx: *mut c_void, pixels_per_dip: *mut FLOAT) -> HRESULT {
+        *pixels_per_dip = self.pixels_per_dip;
+        S_OK
+    }
+}
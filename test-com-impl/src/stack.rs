@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+
+use com_impl::{LocalRefcount, StackCom, VTable};
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dwrite::{
+    IDWriteNumberSubstitution, IDWriteTextAnalysisSink, IDWriteTextAnalysisSinkVtbl,
+    IDWriteTextAnalyzer, DWRITE_LINE_BREAKPOINT, DWRITE_SCRIPT_ANALYSIS,
+};
+
+use crate::local_refcount::AnalysisSource;
+
+/// A run of text in one script: its position, length and script.
+pub type ScriptRun = (u32, u32, u16);
+
+/// Collects the script runs of one `AnalyzeScript` call into the caller's vector.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IDWriteTextAnalysisSink)]
+#[stack]
+pub struct ScriptSink<'a> {
+    vtbl: VTable<IDWriteTextAnalysisSinkVtbl>,
+    refcount: LocalRefcount,
+    runs: &'a RefCell<Vec<ScriptRun>>,
+}
+
+pub fn analyze_script(
+    analyzer: &IDWriteTextAnalyzer,
+    text: &str,
+    locale: &str,
+) -> Result<Vec<ScriptRun>, HRESULT> {
+    let length = text.encode_utf16().count() as u32;
+    let source = AnalysisSource::new(text, locale);
+    let runs = RefCell::new(Vec::new());
+    let sink = ScriptSink::create_stack(&runs);
+    let hr = unsafe {
+        analyzer.AnalyzeScript(source.as_raw(), 0, length, StackCom::as_interface(&sink))
+    };
+    drop(sink);
+    if hr < 0 {
+        return Err(hr);
+    }
+    Ok(runs.into_inner())
+}
+
+#[com_impl::com_impl]
+unsafe impl<'a> IDWriteTextAnalysisSink for ScriptSink<'a> {
+    unsafe fn set_script_analysis(
+        &self,
+        position: u32,
+        length: u32,
+        analysis: *const DWRITE_SCRIPT_ANALYSIS,
+    ) -> HRESULT {
+        self.runs
+            .borrow_mut()
+            .push((position, length, (*analysis).script));
+        S_OK
+    }
+
+    unsafe fn set_line_breakpoints(
+        &self,
+        _position: u32,
+        _length: u32,
+        _breakpoints: *const DWRITE_LINE_BREAKPOINT,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    fn set_bidi_level(
+        &self,
+        _position: u32,
+        _length: u32,
+        _explicit: u8,
+        _resolved: u8,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    unsafe fn set_number_substitution(
+        &self,
+        _position: u32,
+        _length: u32,
+        _substitution: *mut IDWriteNumberSubstitution,
+    ) -> HRESULT {
+        S_OK
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Mutex;
+
+use com_impl::{Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::{HRESULT, S_OK};
+use winapi::um::dcommon::DWRITE_MEASURING_MODE;
+use winapi::um::dwrite::{
+    IDWritePixelSnapping, IDWritePixelSnappingVtbl, IDWriteTextRenderer, IDWriteTextRendererVtbl,
+    DWRITE_GLYPH_RUN, DWRITE_GLYPH_RUN_DESCRIPTION, DWRITE_STRIKETHROUGH,
+};
+use winapi::um::unknwnbase::IUnknown;
+
+/// Collects the font sizes of the glyph runs drawn, stubbing every other method. A poisoned
+/// lock aborts rather than unwinding into DirectWrite.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[com_panic(abort)]
+#[interfaces(IDWritePixelSnapping, IDWriteTextRenderer)]
+pub struct SizeCollector {
+    vtbl: VTable<IDWriteTextRendererVtbl>,
+    refcount: Refcount,
+    sizes: Mutex<Vec<f32>>,
+}
+
+#[com_impl::com_impl(stub(draw_inline_object))]
+unsafe impl IDWriteTextRenderer for SizeCollector {
+    unsafe fn draw_glyph_run(
+        &self,
+        _context: *mut c_void,
+        _baseline_origin_x: f32,
+        _baseline_origin_y: f32,
+        _measuring_mode: DWRITE_MEASURING_MODE,
+        glyph_run: *const DWRITE_GLYPH_RUN,
+        _description: *const DWRITE_GLYPH_RUN_DESCRIPTION,
+        _effect: *mut IUnknown,
+    ) -> HRESULT {
+        self.sizes.lock().unwrap().push((*glyph_run).fontEmSize);
+        S_OK
+    }
+
+    #[stub]
+    fn draw_underline();
+
+    #[stub]
+    unsafe fn draw_strikethrough(
+        &self,
+        context: *mut c_void,
+        baseline_origin_x: f32,
+        baseline_origin_y: f32,
+        strikethrough: *const DWRITE_STRIKETHROUGH,
+        effect: *mut IUnknown,
+    ) -> HRESULT;
+}
+
+#[com_impl::com_impl]
+unsafe impl IDWritePixelSnapping for SizeCollector {
+    #[stub]
+    fn is_pixel_snapping_disabled();
+
+    #[stub]
+    fn get_current_transform();
+
+    #[stub]
+    fn get_pixels_per_dip();
+}
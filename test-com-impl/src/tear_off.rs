@@ -0,0 +1,70 @@
+use com_impl::aggregation::Aggregation;
+use com_impl::prelude::*;
+use com_impl::tear_off::{TearOff, TearOffs};
+use winapi::um::dwrite::{IDWriteFontFileStream, IDWriteFontFileStreamVtbl};
+
+use crate::file_stream::fragment;
+
+/// Only allocates its stream when asked for one.
+#[repr(C)]
+#[derive(ComImpl)]
+#[tear_off(IDWriteFontFileStream = StreamTearOff)]
+pub struct Font {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: Refcount,
+    tear_offs: TearOffs,
+    data: Vec<u8>,
+}
+
+impl Font {
+    pub fn new(data: Vec<u8>) -> ComPtr<IUnknown> {
+        let ptr = Font::create_raw(data);
+        unsafe { ComPtr::from_raw(ptr as *mut IUnknown) }
+    }
+}
+
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(IDWriteFontFileStream)]
+pub struct StreamTearOff {
+    vtbl: VTable<IDWriteFontFileStreamVtbl>,
+    refcount: Refcount,
+    aggregation: Aggregation,
+    font: *const Font,
+}
+
+unsafe impl TearOff<Font> for StreamTearOff {
+    fn create(owner: &Font, outer: *mut IUnknown) -> *mut IUnknown {
+        StreamTearOff::create_raw_aggregated(outer, owner)
+    }
+}
+
+#[com_impl]
+unsafe impl IDWriteFontFileStream for StreamTearOff {
+    unsafe fn get_file_size(&self, size: *mut u64) -> HRESULT {
+        *size = (*self.font).data.len() as u64;
+        S_OK
+    }
+
+    unsafe fn get_last_write_time(&self, _write_time: *mut u64) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    unsafe fn read_file_fragment(
+        &self,
+        start: *mut *const c_void,
+        offset: u64,
+        size: u64,
+        ctx: *mut *mut c_void,
+    ) -> HRESULT {
+        // The tear-off is released before the font, so its data outlives the fragment
+        *start = match fragment(&(*self.font).data, offset, size) {
+            Ok(fragment) => fragment,
+            Err(hr) => return hr,
+        };
+        *ctx = std::ptr::null_mut();
+        S_OK
+    }
+
+    unsafe fn release_file_fragment(&self, _ctx: *mut c_void) {}
+}
@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+
+use com_impl::{ComBox, Refcount, VTable};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_FAIL, E_INVALIDARG, E_NOTIMPL, HRESULT, S_OK};
+use winapi::um::objidlbase::{ISequentialStream, ISequentialStreamVtbl};
+
+/// A stream collecting the text written to it, which has to be ASCII. The check sits in a
+/// helper, which fails the method calling it with E_INVALIDARG by panicking with the HRESULT
+/// rather than handing an error back up.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(ISequentialStream)]
+pub struct TextSink {
+    vtbl: VTable<ISequentialStreamVtbl>,
+    refcount: Refcount,
+    text: RefCell<String>,
+}
+
+impl TextSink {
+    pub fn new() -> ComBox<TextSink> {
+        TextSink::create(Default::default())
+    }
+
+    /// Calls Write through the vtable, as a caller of the stream would.
+    pub fn write(&self, bytes: &[u8]) -> HRESULT {
+        let stream = self as *const TextSink as *mut ISequentialStream;
+        unsafe {
+            ((*(*stream).lpVtbl).Write)(
+                stream,
+                bytes.as_ptr() as *const c_void,
+                bytes.len() as ULONG,
+                std::ptr::null_mut(),
+            )
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.text.borrow().clone()
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl ISequentialStream for TextSink {
+    unsafe fn read(&self, _pv: *mut c_void, _cb: ULONG, _read: *mut ULONG) -> HRESULT {
+        E_NOTIMPL
+    }
+
+    #[panic(result = E_FAIL)]
+    unsafe fn write(&self, pv: *const c_void, cb: ULONG, written: *mut ULONG) -> HRESULT {
+        let bytes = std::slice::from_raw_parts(pv as *const u8, cb as usize);
+        self.text.borrow_mut().push_str(ascii(bytes));
+        if !written.is_null() {
+            *written = cb;
+        }
+        S_OK
+    }
+}
+
+/// The bytes as text. Fails the COM method it is called from with E_INVALIDARG unless they
+/// are all ASCII.
+fn ascii(bytes: &[u8]) -> &str {
+    if !bytes.is_ascii() {
+        std::panic::panic_any(E_INVALIDARG);
+    }
+    std::str::from_utf8(bytes).unwrap()
+}
@@ -0,0 +1,72 @@
+#![allow(non_snake_case)]
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+use com_impl::prelude::*;
+
+/// Hand-written bindings for an interface only Rust code calls, whose vtable lets panics
+/// unwind back to the caller.
+#[repr(C)]
+pub struct IVisitor {
+    pub lpVtbl: *const IVisitorVtbl,
+}
+
+#[repr(C)]
+pub struct IVisitorVtbl {
+    pub parent: IUnknownVtbl,
+    pub Visit: unsafe extern "system-unwind" fn(This: *mut IVisitor, value: ULONG) -> HRESULT,
+    pub Finish: unsafe extern "system-unwind" fn(This: *mut IVisitor) -> HRESULT,
+}
+
+impl Interface for IVisitor {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0x1f0c_5a21,
+            Data2: 0x7d3e,
+            Data3: 0x4b8a,
+            Data4: [0x9c, 0x61, 0x2e, 0x54, 0x0a, 0xd7, 0x3b, 0x90],
+        }
+    }
+}
+
+/// Sums the values it visits and panics on zero, unwinding through the stub back to `sum`.
+#[repr(C)]
+#[derive(ComImpl)]
+#[interfaces(IVisitor)]
+pub struct Summer {
+    vtbl: VTable<IVisitorVtbl>,
+    refcount: Refcount,
+    sum: Cell<ULONG>,
+}
+
+impl Summer {
+    pub fn sum(values: &[ULONG]) -> Option<ULONG> {
+        let summer = Summer::create(Cell::new(0));
+        let visitor = &*summer as *const Summer as *mut IVisitor;
+        let visited = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            for &value in values {
+                ((*(*visitor).lpVtbl).Visit)(visitor, value);
+            }
+            ((*(*visitor).lpVtbl).Finish)(visitor)
+        }));
+        match visited {
+            Ok(S_OK) => Some(summer.sum.get()),
+            _ => None,
+        }
+    }
+}
+
+#[com_impl(unwind)]
+unsafe impl IVisitor for Summer {
+    fn visit(&self, value: ULONG) -> HRESULT {
+        assert_ne!(value, 0, "visited a zero");
+        self.sum.set(self.sum.get() + value);
+        S_OK
+    }
+
+    #[panic(result = E_FAIL)]
+    fn finish(&self) -> HRESULT {
+        S_OK
+    }
+}
@@ -0,0 +1,98 @@
+#![allow(non_snake_case, non_upper_case_globals)]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use com_impl::{Refcount, VTable};
+
+use self::headers::{IID_IUnknown, IMeter, IMeterVtbl, IsEqualGUID, HRESULT, S_OK};
+
+/// Headers declared by hand in winapi's style, sharing none of its types.
+pub mod headers {
+    use std::ffi::c_void;
+
+    pub type HRESULT = i32;
+    pub const S_OK: HRESULT = 0;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct GUID {
+        pub Data1: u32,
+        pub Data2: u16,
+        pub Data3: u16,
+        pub Data4: [u8; 8],
+    }
+
+    pub fn IsEqualGUID(a: &GUID, b: &GUID) -> bool {
+        a.Data1 == b.Data1 && a.Data2 == b.Data2 && a.Data3 == b.Data3 && a.Data4 == b.Data4
+    }
+
+    pub const IID_IUnknown: GUID = GUID {
+        Data1: 0x0000_0000,
+        Data2: 0x0000,
+        Data3: 0x0000,
+        Data4: [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    };
+
+    #[repr(C)]
+    pub struct IUnknown {
+        pub lpVtbl: *const IUnknownVtbl,
+    }
+
+    #[repr(C)]
+    pub struct IUnknownVtbl {
+        pub QueryInterface: unsafe extern "system" fn(
+            This: *mut IUnknown,
+            riid: *const GUID,
+            ppvObject: *mut *mut c_void,
+        ) -> HRESULT,
+        pub AddRef: unsafe extern "system" fn(This: *mut IUnknown) -> u32,
+        pub Release: unsafe extern "system" fn(This: *mut IUnknown) -> u32,
+    }
+
+    pub const IID_IMeter: GUID = GUID {
+        Data1: 0x58e2_a7d1,
+        Data2: 0x3c0f,
+        Data3: 0x4b96,
+        Data4: [0xa4, 0x1e, 0x7d, 0x25, 0xc9, 0x80, 0x6b, 0x13],
+    };
+
+    #[repr(C)]
+    pub struct IMeter {
+        pub lpVtbl: *const IMeterVtbl,
+    }
+
+    #[repr(C)]
+    pub struct IMeterVtbl {
+        pub parent: IUnknownVtbl,
+        pub GetReading: unsafe extern "system" fn(This: *mut IMeter, reading: *mut u32) -> HRESULT,
+    }
+}
+
+/// Implements IUnknown with the vendored headers rather than winapi's.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[iunknown(
+    vtbl = headers::IUnknownVtbl,
+    iid = headers::GUID,
+    is_equal_iid = IsEqualGUID
+)]
+#[interfaces(IMeter = headers::IID_IMeter, headers::IUnknown = IID_IUnknown)]
+pub struct Meter {
+    vtbl: VTable<IMeterVtbl>,
+    refcount: Refcount,
+    reading: AtomicU32,
+}
+
+impl Meter {
+    pub fn new(reading: u32) -> *mut IMeter {
+        Meter::create_raw(reading.into()) as *mut IMeter
+    }
+}
+
+#[com_impl::com_impl]
+unsafe impl IMeter for Meter {
+    unsafe fn get_reading(&self, reading: *mut u32) -> HRESULT {
+        *reading = self.reading.fetch_add(1, Ordering::Relaxed);
+        S_OK
+    }
+}
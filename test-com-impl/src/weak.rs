@@ -0,0 +1,22 @@
+use com_impl::weak::{WeakRef, WeakRefcount};
+use com_impl::VTable;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use wio::com::ComPtr;
+
+/// Counted by a control block, so weak references outlive it.
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+pub struct Document {
+    vtbl: VTable<IUnknownVtbl>,
+    refcount: WeakRefcount,
+    pub title: String,
+}
+
+impl Document {
+    pub fn new(title: String) -> (ComPtr<IUnknown>, WeakRef) {
+        let ptr = Document::create_raw(title);
+        let document = unsafe { ComPtr::from_raw(ptr as *mut IUnknown) };
+        let weak = WeakRef::new(&document).unwrap();
+        (document, weak)
+    }
+}
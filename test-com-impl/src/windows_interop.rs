@@ -0,0 +1,59 @@
+#![allow(non_snake_case)]
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use com_impl::{Refcount, VTable};
+use windows::core::{IUnknown, IUnknown_Vtbl, Interface, HRESULT};
+use windows_core::interface;
+
+#[interface("5a0ee9a0-41e5-4c1f-9a4b-6f4f7d0c2c11")]
+pub unsafe trait IValue: IUnknown {
+    fn GetValue(&self, value: *mut i32) -> HRESULT;
+    fn SetValue(&self, value: i32) -> HRESULT;
+}
+
+#[interface("0b7e5d52-93c4-4d8a-8f61-2ad4c0f3e6a7")]
+pub unsafe trait ILabel: IUnknown {
+    fn GetLength(&self, length: *mut u32) -> HRESULT;
+}
+
+/// Implements interfaces declared with the `windows` crate.
+#[com_impl::com_impl(bindings = windows)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IValue)]
+pub struct Cell {
+    vtbl: VTable<IValue_Vtbl>,
+    refcount: Refcount,
+    label_vtbl: VTable<ILabel_Vtbl>,
+    value: AtomicI32,
+    label: String,
+}
+
+impl Cell {
+    pub fn new(value: i32, label: String) -> IValue {
+        let ptr = Cell::create_raw(value.into(), label);
+        unsafe { IValue::from_raw(ptr as *mut _) }
+    }
+}
+
+#[com_impl::com_impl(bindings = windows)]
+unsafe impl IValue for Cell {
+    unsafe fn get_value(&self, value: *mut i32) -> HRESULT {
+        *value = self.value.load(Ordering::Relaxed);
+        HRESULT(0)
+    }
+
+    pub fn set_value(&self, value: i32) -> HRESULT {
+        self.value.store(value, Ordering::Relaxed);
+        HRESULT(0)
+    }
+}
+
+#[com_impl::com_impl(bindings = windows, member = label_vtbl)]
+unsafe impl ILabel for Cell {
+    unsafe fn get_length(&self, length: *mut u32) -> HRESULT {
+        *length = self.label.len() as u32;
+        HRESULT(0)
+    }
+}
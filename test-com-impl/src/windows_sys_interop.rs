@@ -0,0 +1,58 @@
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals)]
+
+use std::ffi::c_void;
+
+use com_impl::windows_sys::{IUnknown_Vtbl, GUID, HRESULT};
+use com_impl::{Refcount, VTable};
+
+#[repr(C)]
+pub struct IValue_Vtbl {
+    pub base__: IUnknown_Vtbl,
+    pub GetValue: unsafe extern "system" fn(this: *mut c_void, value: *mut i32) -> HRESULT,
+}
+
+#[repr(C)]
+pub struct ILabel_Vtbl {
+    pub base__: IUnknown_Vtbl,
+    pub GetLength: unsafe extern "system" fn(this: *mut c_void, length: *mut u32) -> HRESULT,
+}
+
+pub const IID_IValue: GUID = GUID::from_u128(0x5a0e_e9a0_41e5_4c1f_9a4b_6f4f_7d0c_2c11);
+pub const IID_ILabel: GUID = GUID::from_u128(0x0b7e_5d52_93c4_4d8a_8f61_2ad4_c0f3_e6a7);
+pub const IID_IReadOnlyValue: GUID = GUID::from_u128(0xc41d_07e3_5b28_4f96_a0d3_19b7_e25c_8f40);
+
+/// Implements vtables declared by hand for `windows-sys`, answering `IValue` under a second
+/// IID as well.
+#[com_impl::com_impl(bindings = windows_sys)]
+#[repr(C)]
+#[derive(com_impl::ComImpl)]
+#[interfaces(IValue, IReadOnlyValue = IID_IReadOnlyValue)]
+pub struct Cell {
+    vtbl: VTable<IValue_Vtbl>,
+    refcount: Refcount,
+    label_vtbl: VTable<ILabel_Vtbl>,
+    value: i32,
+    label: String,
+}
+
+impl Cell {
+    pub fn new(value: i32, label: String) -> *mut c_void {
+        Cell::create_raw(value, label) as *mut c_void
+    }
+}
+
+#[com_impl::com_impl(bindings = windows_sys)]
+unsafe impl IValue for Cell {
+    unsafe fn get_value(&self, value: *mut i32) -> HRESULT {
+        *value = self.value;
+        0
+    }
+}
+
+#[com_impl::com_impl(bindings = windows_sys, member = label_vtbl)]
+unsafe impl ILabel for Cell {
+    unsafe fn get_length(&self, length: *mut u32) -> HRESULT {
+        *length = self.label.len() as u32;
+        0
+    }
+}